@@ -26,6 +26,8 @@
           # Core build tools
           pkg-config
           stdenv.cc # Provides the 'cc' linker
+          # protoc for the gRPC definitions
+          protobuf
           # OpenSSL for reqwest
           openssl
           libxcrypt
@@ -49,6 +51,9 @@
           OPENSSL_LIB_DIR = "${pkgs.openssl}/lib";
           OPENSSL_NO_VENDOR = "1";
 
+          # The protoc bundled with the build dependencies doesn't run on NixOS
+          PROTOC = "${pkgs.protobuf}/bin/protoc";
+
           # This is crucial for runtime linking. It tells the application where to find
           # shared libraries like libwayland-client.so when you run `cargo run`.
           LD_LIBRARY_PATH = pkgs.lib.makeLibraryPath iced-deps;
@@ -67,7 +72,8 @@
           version = "0.1.0";
           src = self;
           cargoLock.lockFile = ./Cargo.lock;
-          nativeBuildInputs = [ pkgs.pkg-config ];
+          nativeBuildInputs = [ pkgs.pkg-config pkgs.protobuf ];
+          PROTOC = "${pkgs.protobuf}/bin/protoc";
           buildInputs = with pkgs; [
             openssl
             libx11
@@ -0,0 +1,639 @@
+mod output;
+mod value;
+
+use std::{io::Read, path::PathBuf, time::Duration};
+
+use chrono::{DateTime, Utc};
+use clap::{Parser, Subcommand};
+use rcada_core::{
+    tag::{AlarmLevel, Tag, TagMeta, TagValue},
+    unit::Unit,
+    value::{DataType, Value},
+};
+use rcada_sdk::{
+    Client, ErrorCode, ValueWrite,
+    model::{
+        AuditAction, AuditQuery, ConfigFormat, ConflictPolicy, ImportChanges, ImportOptions,
+        ImportReport, WriteResult,
+    },
+};
+use serde::Serialize;
+
+use crate::{
+    output::{Format, Stream, Table},
+    value::{format_name, format_timestamp, format_value, parse_name, parse_value},
+};
+
+/// Command-line client of an RCADA server.
+#[derive(Debug, Parser)]
+#[command(
+    name = "rcada_cli",
+    version,
+    about = "Command-line client of an RCADA server"
+)]
+struct Args {
+    /// Server to connect to, `https://` for a server with TLS
+    #[arg(
+        long,
+        env = "RCADA_SERVER_URL",
+        default_value = "http://127.0.0.1:8080"
+    )]
+    server: String,
+    /// API key sent with every request
+    #[arg(long, env = "RCADA_API_KEY", hide_env_values = true)]
+    api_key: Option<String>,
+    /// Token from `rcada_cli login`, used if no API key is given
+    #[arg(long, env = "RCADA_TOKEN", hide_env_values = true)]
+    token: Option<String>,
+    /// CA certificate (PEM) trusted in addition to the system ones
+    #[arg(long, env = "RCADA_CA_CERT")]
+    ca_cert: Option<PathBuf>,
+    /// Output format
+    #[arg(short, long, value_enum, default_value_t = Format::Table, global = true)]
+    output: Format,
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Debug, Subcommand)]
+enum Command {
+    /// Create, inspect and delete tags
+    #[command(subcommand)]
+    Tags(TagsCommand),
+    /// Write tag values, e.g. `write temperature=21.5 pump/on=true`
+    ///
+    /// Values are parsed as the data type of their tag. If the tag can't be
+    /// read the type is guessed from the literal.
+    Write {
+        #[arg(required = true, value_name = "TAG=VALUE", value_parser = parse_assignment)]
+        values: Vec<(String, String)>,
+    },
+    /// Print values as they change until interrupted
+    Watch {
+        /// Tags to watch, every readable tag if empty
+        tags: Vec<String>,
+        /// Poll interval in milliseconds
+        #[arg(long, default_value_t = 1000)]
+        interval_ms: u64,
+    },
+    /// Export and import the tag configuration
+    #[command(subcommand)]
+    Config(ConfigCommand),
+    /// Accepted value writes of a tag, rebuilt from the audit log
+    ///
+    /// Reading the audit log needs the `admin` permission. Writes of drivers
+    /// are only listed if the server runs with `audit.system_value_writes`.
+    History {
+        tag: String,
+        /// Only writes at or after this time (RFC 3339)
+        #[arg(long)]
+        from: Option<DateTime<Utc>>,
+        /// Only writes before this time (RFC 3339)
+        #[arg(long)]
+        to: Option<DateTime<Utc>>,
+        /// Number of newest writes
+        #[arg(long, default_value_t = 100)]
+        limit: usize,
+    },
+    /// Tags whose value is beyond one of their alarm limits
+    ///
+    /// The limits are checked against the current values. The server keeps
+    /// no alarm state, so alarms can't be acknowledged.
+    Alarms,
+    /// Print a token for `--token` or `RCADA_TOKEN`, reading the password
+    /// from `RCADA_PASSWORD` or stdin
+    Login {
+        username: String,
+        #[arg(long, env = "RCADA_PASSWORD", hide_env_values = true)]
+        password: Option<String>,
+    },
+}
+
+#[derive(Debug, Subcommand)]
+enum TagsCommand {
+    /// Tags the caller may read
+    List,
+    /// Value and metadata of a tag
+    Get {
+        name: String,
+    },
+    Create {
+        name: String,
+        /// Data type: integer, float, boolean or string
+        #[arg(long = "type", value_parser = parse_name::<DataType>)]
+        data_type: DataType,
+        /// Unit, e.g. celsius or percent
+        #[arg(long, value_parser = parse_name::<Unit>, default_value = "none")]
+        unit: Unit,
+        #[arg(long, default_value = "")]
+        description: String,
+        /// Fractional digits shown for numeric values
+        #[arg(long)]
+        precision: Option<u8>,
+        #[arg(long)]
+        read_only: bool,
+        /// Label as `KEY=VALUE`, can be repeated
+        #[arg(long = "label", value_name = "KEY=VALUE", value_parser = parse_assignment)]
+        labels: Vec<(String, String)>,
+    },
+    Delete {
+        name: String,
+    },
+}
+
+#[derive(Debug, Subcommand)]
+enum ConfigCommand {
+    /// Print the configuration of every tag
+    Export {
+        #[arg(long, value_parser = parse_name::<ConfigFormat>, default_value = "json")]
+        format: ConfigFormat,
+        /// Write to a file instead of stdout
+        #[arg(long, short = 'f')]
+        file: Option<PathBuf>,
+    },
+    /// Apply a configuration file, `-` for stdin
+    Import {
+        file: PathBuf,
+        /// Format of the file, guessed from its extension if omitted
+        #[arg(long, value_parser = parse_name::<ConfigFormat>)]
+        format: Option<ConfigFormat>,
+        /// Report the changes without applying them
+        #[arg(long)]
+        dry_run: bool,
+        /// What to do with existing tags that differ: fail, skip or overwrite
+        #[arg(long, value_parser = parse_name::<ConflictPolicy>, default_value = "fail")]
+        policy: ConflictPolicy,
+        /// Delete tags missing from the file
+        #[arg(long)]
+        delete_missing: bool,
+    },
+}
+
+#[derive(Debug, thiserror::Error)]
+enum Error {
+    #[error(transparent)]
+    Api(#[from] rcada_sdk::Error),
+    #[error(transparent)]
+    Io(#[from] std::io::Error),
+    #[error(transparent)]
+    Json(#[from] serde_json::Error),
+    #[error(transparent)]
+    Csv(#[from] csv::Error),
+    #[error("{0}")]
+    Invalid(String),
+}
+
+fn parse_assignment(raw: &str) -> Result<(String, String), String> {
+    raw.split_once('=')
+        .map(|(key, value)| (key.to_string(), value.to_string()))
+        .ok_or_else(|| format!("expected NAME=VALUE, got {raw:?}"))
+}
+
+#[tokio::main]
+async fn main() {
+    let args = Args::parse();
+    match run(args).await {
+        Ok(true) => {},
+        Ok(false) => std::process::exit(1),
+        Err(e) => {
+            eprintln!("error: {e}");
+            std::process::exit(1);
+        },
+    }
+}
+
+/// Runs the command, returns whether every part of it succeeded.
+async fn run(args: Args) -> Result<bool, Error> {
+    let mut builder = Client::builder(&args.server);
+    if let Some(path) = &args.ca_cert {
+        builder = builder.ca_certificate_file(path)?;
+    }
+    if let Some(key) = args.api_key {
+        builder = builder.api_key(key);
+    } else if let Some(token) = args.token {
+        builder = builder.bearer_token(token);
+    }
+    let client = builder.build()?;
+    let format = args.output;
+
+    match args.command {
+        Command::Tags(TagsCommand::List) => {
+            let tags = client.list_tags().await?;
+            output::print(format, &tags, tag_table(&tags))?;
+        },
+        Command::Tags(TagsCommand::Get {
+            name,
+        }) => {
+            let tag = client.get_tag(&name).await?;
+            output::print(format, &tag, tag_details(&tag))?;
+        },
+        Command::Tags(TagsCommand::Create {
+            name,
+            data_type,
+            unit,
+            description,
+            precision,
+            read_only,
+            labels,
+        }) => {
+            let meta = TagMeta {
+                description,
+                precision,
+                read_only,
+                labels: labels.into_iter().collect(),
+                ..TagMeta::new(unit, data_type)
+            };
+            client.create_tag(&name, &meta).await?;
+            let tag = client.get_tag(&name).await?;
+            output::print(format, &tag, tag_details(&tag))?;
+        },
+        Command::Tags(TagsCommand::Delete {
+            name,
+        }) => client.delete_tag(&name).await?,
+        Command::Write {
+            values,
+        } => return write(&client, format, values).await,
+        Command::Watch {
+            tags,
+            interval_ms,
+        } => watch(&client, format, tags, interval_ms).await?,
+        Command::Config(ConfigCommand::Export {
+            format: config_format,
+            file,
+        }) => {
+            let document = client.export_configuration_as(config_format).await?;
+            match file {
+                Some(path) => std::fs::write(path, document)?,
+                None => print!("{document}"),
+            }
+        },
+        Command::Config(ConfigCommand::Import {
+            file,
+            format: config_format,
+            dry_run,
+            policy,
+            delete_missing,
+        }) => {
+            let config_format = config_format
+                .or_else(|| {
+                    let extension = file.extension()?.to_str()?;
+                    parse_name(if extension == "yml" {
+                        "yaml"
+                    } else {
+                        extension
+                    })
+                    .ok()
+                })
+                .unwrap_or_default();
+            let mut document = Vec::new();
+            if file.as_os_str() == "-" {
+                std::io::stdin().read_to_end(&mut document)?;
+            } else {
+                document = std::fs::read(&file)?;
+            }
+            let options = ImportOptions {
+                dry_run,
+                policy,
+                delete_missing,
+            };
+            let report = client
+                .import_configuration_as(document, config_format, options)
+                .await?;
+            output::print(format, &report, import_table(&report))?;
+        },
+        Command::History {
+            tag,
+            from,
+            to,
+            limit,
+        } => history(&client, format, tag, from, to, limit).await?,
+        Command::Alarms => alarms(&client, format).await?,
+        Command::Login {
+            username,
+            password,
+        } => {
+            let password = match password {
+                Some(password) => password,
+                None => {
+                    let mut password = String::new();
+                    std::io::stdin().read_line(&mut password)?;
+                    password.trim_end_matches(['\r', '\n']).to_string()
+                },
+            };
+            let token = client.login(&username, &password).await?;
+            match format {
+                Format::Table => println!("{}", token.token),
+                _ => {
+                    let mut table = Table::new(&["TOKEN", "EXPIRES AT"]);
+                    table.row(vec![token.token.clone(), token.expires_at.to_rfc3339()]);
+                    output::print(format, &token, table)?;
+                },
+            }
+        },
+    }
+    Ok(true)
+}
+
+fn tag_table(tags: &[Tag]) -> Table {
+    let mut table = Table::new(&["NAME", "VALUE", "UNIT", "TYPE", "TIMESTAMP"]);
+    for tag in tags {
+        table.row(vec![
+            tag.name.to_string(),
+            format_value(&tag.value.value, tag.meta.precision),
+            format_name(&tag.meta.unit),
+            format_name(&tag.meta.data_type),
+            format_timestamp(tag.value.timestamp),
+        ]);
+    }
+    table
+}
+
+fn tag_details(tag: &Tag) -> Table {
+    let optional = |value: Option<String>| value.unwrap_or_default();
+    let alarms = &tag.meta.alarms;
+    let limits = [
+        ("low_low", alarms.low_low),
+        ("low", alarms.low),
+        ("high", alarms.high),
+        ("high_high", alarms.high_high),
+    ];
+
+    let mut table = Table::new(&["FIELD", "VALUE"]);
+    for (field, value) in [
+        ("name", tag.name.to_string()),
+        ("value", format_value(&tag.value.value, tag.meta.precision)),
+        ("timestamp", format_timestamp(tag.value.timestamp)),
+        ("unit", format_name(&tag.meta.unit)),
+        ("data_type", format_name(&tag.meta.data_type)),
+        ("description", tag.meta.description.clone()),
+        (
+            "range",
+            optional(
+                tag.meta
+                    .range
+                    .map(|range| format!("{}..{}", range.low, range.high)),
+            ),
+        ),
+        (
+            "precision",
+            optional(tag.meta.precision.map(|p| p.to_string())),
+        ),
+        ("read_only", tag.meta.read_only.to_string()),
+        (
+            "labels",
+            join(tag.meta.labels.iter().map(|(k, v)| format!("{k}={v}"))),
+        ),
+        (
+            "alarms",
+            join(
+                limits
+                    .iter()
+                    .filter_map(|(name, limit)| limit.map(|limit| format!("{name}={limit}"))),
+            ),
+        ),
+        ("expression", optional(tag.meta.expression.clone())),
+    ] {
+        table.row(vec![field.to_string(), value]);
+    }
+    table
+}
+
+fn join(items: impl Iterator<Item = String>) -> String {
+    items.collect::<Vec<_>>().join(",")
+}
+
+#[derive(Debug, Serialize)]
+struct WriteOutcome {
+    tag: String,
+    value: Value,
+    result: Option<WriteResult>,
+    error: Option<String>,
+}
+
+async fn write(
+    client: &Client,
+    format: Format,
+    values: Vec<(String, String)>,
+) -> Result<bool, Error> {
+    let mut writes = Vec::new();
+    for (tag, raw) in values {
+        let data_type = client
+            .get_tag(&tag)
+            .await
+            .ok()
+            .map(|tag| tag.meta.data_type);
+        let value =
+            parse_value(&raw, data_type).map_err(|e| Error::Invalid(format!("{tag}: {e}")))?;
+        writes.push(ValueWrite::new(tag, value));
+    }
+
+    let outcomes: Vec<WriteOutcome> = client
+        .write_values(writes)
+        .await
+        .into_iter()
+        .map(|(write, result)| WriteOutcome {
+            tag: write.tag,
+            value: write.value,
+            error: result.as_ref().err().map(|e| e.to_string()),
+            result: result.ok(),
+        })
+        .collect();
+
+    let mut table = Table::new(&["TAG", "VALUE", "RESULT"]);
+    for outcome in &outcomes {
+        let result = match (&outcome.result, &outcome.error) {
+            (Some(result), _) => format_name(result).to_lowercase(),
+            (None, Some(error)) => error.clone(),
+            (None, None) => String::new(),
+        };
+        table.row(vec![
+            outcome.tag.clone(),
+            format_value(&outcome.value, None),
+            result,
+        ]);
+    }
+    output::print(format, &outcomes, table)?;
+    Ok(outcomes.iter().all(|outcome| outcome.error.is_none()))
+}
+
+async fn watch(
+    client: &Client,
+    format: Format,
+    tags: Vec<String>,
+    interval_ms: u64,
+) -> Result<(), Error> {
+    let filter = (!tags.is_empty()).then_some(tags);
+    let mut watch = client.watch(Duration::from_millis(interval_ms.max(1)), filter);
+    let mut stream = Stream::new(format, &["TIMESTAMP", "NAME", "VALUE", "UNIT"]);
+    while let Some(changes) = watch.next().await {
+        let changes = match changes {
+            Ok(changes) => changes,
+            Err(e) if e.is_unauthorized() => return Err(e.into()),
+            Err(e) => {
+                eprintln!("warning: {e}");
+                continue;
+            },
+        };
+        for tag in changes {
+            let cells = [
+                format_timestamp(tag.value.timestamp),
+                tag.name.to_string(),
+                format_value(&tag.value.value, tag.meta.precision),
+                format_name(&tag.meta.unit),
+            ];
+            stream.row(&tag, &cells)?;
+        }
+    }
+    Ok(())
+}
+
+fn import_table(report: &ImportReport) -> Table {
+    let mut table = Table::new(&["CHANGE", "KIND", "NAME"]);
+    let sections = [
+        (
+            "tag",
+            [
+                &report.create,
+                &report.update,
+                &report.delete,
+                &report.conflicts,
+            ],
+        ),
+        ("alias", changes(&report.aliases)),
+        ("template", changes(&report.templates)),
+    ];
+    for (kind, lists) in sections {
+        for (change, names) in ["create", "update", "delete", "conflict"].iter().zip(lists) {
+            for name in names {
+                table.row(vec![change.to_string(), kind.to_string(), name.clone()]);
+            }
+        }
+    }
+    for driver in &report.drivers {
+        table.row(vec![
+            "differs".to_string(),
+            "driver".to_string(),
+            driver.clone(),
+        ]);
+    }
+    table
+}
+
+fn changes(changes: &ImportChanges) -> [&Vec<String>; 4] {
+    [
+        &changes.create,
+        &changes.update,
+        &changes.delete,
+        &changes.conflicts,
+    ]
+}
+
+#[derive(Debug, Serialize)]
+struct HistoryEntry {
+    timestamp: Option<DateTime<Utc>>,
+    value: Value,
+    user: String,
+    recorded_at: DateTime<Utc>,
+}
+
+async fn history(
+    client: &Client,
+    format: Format,
+    tag: String,
+    from: Option<DateTime<Utc>>,
+    to: Option<DateTime<Utc>>,
+    limit: usize,
+) -> Result<(), Error> {
+    let precision = client
+        .get_tag(&tag)
+        .await
+        .ok()
+        .and_then(|tag| tag.meta.precision);
+    let records = client
+        .query_audit_log(&AuditQuery {
+            tag: Some(tag),
+            action: Some(AuditAction::UpdateValue),
+            from,
+            to,
+            limit: Some(limit),
+            ..Default::default()
+        })
+        .await
+        .map_err(|e| match e.code() {
+            Some(ErrorCode::PermissionDenied) => Error::Invalid(
+                "history needs the admin permission to read the audit log".to_string(),
+            ),
+            _ => e.into(),
+        })?;
+
+    let entries: Vec<HistoryEntry> = records
+        .into_iter()
+        .filter(|record| record.result == "ok")
+        .filter_map(|record| {
+            let value: TagValue = serde_json::from_value(record.new?).ok()?;
+            Some(HistoryEntry {
+                timestamp: value.timestamp,
+                value: value.value,
+                user: record.user,
+                recorded_at: record.timestamp,
+            })
+        })
+        .collect();
+
+    let mut table = Table::new(&["TIMESTAMP", "VALUE", "USER"]);
+    for entry in &entries {
+        table.row(vec![
+            format_timestamp(entry.timestamp.or(Some(entry.recorded_at))),
+            format_value(&entry.value, precision),
+            entry.user.clone(),
+        ]);
+    }
+    output::print(format, &entries, table)
+}
+
+#[derive(Debug, Serialize)]
+struct Alarm {
+    tag: String,
+    level: AlarmLevel,
+    value: Value,
+    limit: f64,
+    timestamp: Option<DateTime<Utc>>,
+    #[serde(skip)]
+    precision: Option<u8>,
+}
+
+async fn alarms(client: &Client, format: Format) -> Result<(), Error> {
+    let mut alarms = Vec::new();
+    for tag in client.list_tags().await? {
+        let limits = tag.meta.alarms;
+        let Some(level) = tag.value.value.as_f64().and_then(|v| limits.level(v)) else {
+            continue;
+        };
+        let limit = match level {
+            AlarmLevel::LowLow => limits.low_low,
+            AlarmLevel::Low => limits.low,
+            AlarmLevel::High => limits.high,
+            AlarmLevel::HighHigh => limits.high_high,
+        };
+        alarms.push(Alarm {
+            tag: tag.name.to_string(),
+            level,
+            value: tag.value.value,
+            limit: limit.unwrap_or_default(),
+            timestamp: tag.value.timestamp,
+            precision: tag.meta.precision,
+        });
+    }
+
+    let mut table = Table::new(&["TAG", "LEVEL", "VALUE", "LIMIT", "TIMESTAMP"]);
+    for alarm in &alarms {
+        table.row(vec![
+            alarm.tag.clone(),
+            format_name(&alarm.level),
+            format_value(&alarm.value, alarm.precision),
+            alarm.limit.to_string(),
+            format_timestamp(alarm.timestamp),
+        ]);
+    }
+    output::print(format, &alarms, table)
+}
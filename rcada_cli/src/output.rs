@@ -0,0 +1,119 @@
+use std::io::{self, Write};
+
+use serde::Serialize;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, clap::ValueEnum)]
+pub enum Format {
+    /// Aligned columns for people
+    #[default]
+    Table,
+    Json,
+    Csv,
+}
+
+/// Rows of a result, printed as a table or CSV. JSON output serializes the
+/// result itself instead, so it keeps the structure of the API.
+pub struct Table {
+    headers: Vec<&'static str>,
+    rows: Vec<Vec<String>>,
+}
+
+impl Table {
+    pub fn new(headers: &[&'static str]) -> Self {
+        Self {
+            headers: headers.to_vec(),
+            rows: Vec::new(),
+        }
+    }
+
+    pub fn row(&mut self, cells: Vec<String>) {
+        self.rows.push(cells);
+    }
+}
+
+pub fn print(format: Format, data: &impl Serialize, table: Table) -> Result<(), crate::Error> {
+    let mut out = io::stdout().lock();
+    match format {
+        Format::Table => {
+            let widths: Vec<usize> = (0..table.headers.len())
+                .map(|i| {
+                    table
+                        .rows
+                        .iter()
+                        .filter_map(|row| row.get(i))
+                        .map(|cell| cell.chars().count())
+                        .chain([table.headers[i].len()])
+                        .max()
+                        .unwrap_or_default()
+                })
+                .collect();
+            let headers: Vec<String> = table.headers.iter().map(|h| h.to_string()).collect();
+            for row in [&headers].into_iter().chain(&table.rows) {
+                let line: Vec<String> = row
+                    .iter()
+                    .zip(&widths)
+                    .map(|(cell, width)| format!("{cell:<width$}"))
+                    .collect();
+                writeln!(out, "{}", line.join("  ").trim_end())?;
+            }
+        },
+        Format::Json => {
+            serde_json::to_writer_pretty(&mut out, data)?;
+            writeln!(out)?;
+        },
+        Format::Csv => {
+            let mut writer = csv::Writer::from_writer(out);
+            writer.write_record(&table.headers)?;
+            for row in &table.rows {
+                writer.write_record(row)?;
+            }
+            writer.flush()?;
+        },
+    }
+    Ok(())
+}
+
+/// Rows printed as they come, for `watch`. Tables are tab-separated since
+/// the column widths aren't known up front, JSON is one object per line.
+pub struct Stream {
+    format: Format,
+    headers: &'static [&'static str],
+    started: bool,
+}
+
+impl Stream {
+    pub fn new(format: Format, headers: &'static [&'static str]) -> Self {
+        Self {
+            format,
+            headers,
+            started: false,
+        }
+    }
+
+    pub fn row(&mut self, data: &impl Serialize, cells: &[String]) -> Result<(), crate::Error> {
+        let mut out = io::stdout().lock();
+        match self.format {
+            Format::Table => {
+                if !self.started {
+                    writeln!(out, "{}", self.headers.join("\t"))?;
+                }
+                writeln!(out, "{}", cells.join("\t"))?;
+            },
+            Format::Json => {
+                serde_json::to_writer(&mut out, data)?;
+                writeln!(out)?;
+            },
+            Format::Csv => {
+                let mut writer = csv::Writer::from_writer(&mut out);
+                if !self.started {
+                    writer.write_record(self.headers)?;
+                }
+                writer.write_record(cells)?;
+                writer.flush()?;
+            },
+        }
+        self.started = true;
+        out.flush()?;
+        Ok(())
+    }
+}
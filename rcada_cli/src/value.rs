@@ -0,0 +1,63 @@
+use chrono::{DateTime, Utc};
+use rcada_core::value::{DataType, Value};
+use serde::de::DeserializeOwned;
+
+/// Parses a value as the tag's `data_type`, or guesses the type from the
+/// literal if the tag can't be read: `true`/`false`, then integer, float and
+/// string.
+pub fn parse_value(raw: &str, data_type: Option<DataType>) -> Result<Value, String> {
+    let literal = Value::String(raw.to_string());
+    match data_type {
+        Some(data_type) => literal
+            .convert(data_type)
+            .ok_or_else(|| format!("{raw:?} is not a valid {data_type:?}")),
+        None => Ok([DataType::Boolean, DataType::Integer, DataType::Float]
+            .into_iter()
+            .find_map(|data_type| literal.convert(data_type))
+            .unwrap_or(literal)),
+    }
+}
+
+/// Parses the name of an API enum case-insensitively, e.g. `celsius` for
+/// `Unit::Celsius` or `yaml` for `ConfigFormat::Yaml`.
+pub fn parse_name<T: DeserializeOwned>(raw: &str) -> Result<T, String> {
+    let mut chars = raw.chars();
+    let capitalized = chars
+        .next()
+        .map(|first| {
+            first
+                .to_uppercase()
+                .chain(chars.flat_map(char::to_lowercase))
+                .collect()
+        })
+        .unwrap_or_default();
+    [raw.to_string(), raw.to_lowercase(), capitalized]
+        .into_iter()
+        .find_map(|name| serde_json::from_value(serde_json::Value::String(name)).ok())
+        .ok_or_else(|| format!("unknown name {raw:?}"))
+}
+
+/// Value as shown in tables, floats with the tag precision.
+pub fn format_value(value: &Value, precision: Option<u8>) -> String {
+    match value {
+        Value::Float(v) => match precision {
+            Some(precision) => format!("{:.*}", precision as usize, v),
+            None => v.to_string(),
+        },
+        Value::Integer(v) => v.to_string(),
+        Value::Boolean(v) => v.to_string(),
+        Value::String(v) => v.clone(),
+    }
+}
+
+pub fn format_timestamp(timestamp: Option<DateTime<Utc>>) -> String {
+    timestamp.map(|t| t.to_rfc3339()).unwrap_or_default()
+}
+
+/// Name of a unit or data type as in the API, e.g. `Celsius`.
+pub fn format_name(value: &impl serde::Serialize) -> String {
+    match serde_json::to_value(value) {
+        Ok(serde_json::Value::String(name)) => name,
+        _ => String::new(),
+    }
+}
@@ -3,13 +3,20 @@
     windows_subsystem = "windows"
 )]
 
-use iced::widget::{Column, Container, Row, Space, Text};
+use iced::widget::{Column, Container, Row, Space, Text, button, text_input};
 use iced::{Element, Length, Subscription, Task};
 use rcada_core::{tag::Tag, unit::Unit};
-use serde::Deserialize;
+use rcada_sdk::{Auth, RetryPolicy};
 use std::time::Duration;
 
 const SERVER_URL: &str = "http://127.0.0.1:8080";
+/// Server to connect to, `https://` for a server with TLS.
+const SERVER_URL_ENV: &str = "RCADA_SERVER_URL";
+/// CA certificate (PEM) trusted in addition to the system ones, for servers
+/// with a private or self-signed certificate.
+const CA_CERT_ENV: &str = "RCADA_CA_CERT";
+/// API key used instead of logging in, for unattended displays.
+const API_KEY_ENV: &str = "RCADA_API_KEY";
 const POLLING_RATE: u64 = 200;
 const HEALTHCHECK_RATE: u64 = 1000;
 
@@ -25,7 +32,9 @@ pub struct TagDisplay {
 impl From<Tag> for TagDisplay {
     fn from(tag: Tag) -> Self {
         let value_str = match tag.value.value {
-            rcada_core::value::Value::Float(v) => format!("{:.2}", v),
+            rcada_core::value::Value::Float(v) => {
+                format!("{:.*}", tag.meta.precision.unwrap_or(2) as usize, v)
+            },
             rcada_core::value::Value::Integer(v) => v.to_string(),
             rcada_core::value::Value::Boolean(v) => v.to_string(),
             rcada_core::value::Value::String(v) => v,
@@ -61,9 +70,10 @@ impl From<Tag> for TagDisplay {
     }
 }
 
-#[derive(Debug, Deserialize)]
-struct TagsResponse {
-    tags: Vec<Tag>,
+#[derive(Debug, Clone)]
+pub enum FetchError {
+    Unauthorized,
+    Failed,
 }
 
 #[derive(Debug, Clone)]
@@ -71,23 +81,35 @@ pub enum Message {
     Refresh,
     HealthCheckServer,
     HealthCheckServerResult(bool),
-    Refreshed(Vec<TagDisplay>),
+    Refreshed(Result<Vec<TagDisplay>, FetchError>),
+    UsernameChanged(String),
+    PasswordChanged(String),
+    Login,
+    LoggedIn(Result<String, String>),
 }
 
-#[derive(Debug, Clone, Default)]
+#[derive(Debug, Clone)]
 struct RcadaClient {
     tags: Vec<TagDisplay>,
-    server_url: String,
+    api: rcada_sdk::Client,
     server_online: bool,
+    login_required: bool,
+    username: String,
+    password: String,
+    login_error: Option<String>,
 }
 
 impl RcadaClient {
-    fn new() -> (Self, Task<Message>) {
+    fn new(api: rcada_sdk::Client) -> (Self, Task<Message>) {
         (
             Self {
                 tags: Vec::new(),
-                server_url: SERVER_URL.to_string(),
+                api,
                 server_online: false,
+                login_required: false,
+                username: String::new(),
+                password: String::new(),
+                login_error: None,
             },
             Task::none(),
         )
@@ -124,8 +146,21 @@ impl RcadaClient {
 
         let lines = Column::with_children(rows);
 
+        let body: Element<'_, Message> = if self.login_required {
+            self.login_view()
+        } else {
+            Column::new().spacing(20).push(header).push(lines).into()
+        };
+
         let status_bar = Column::with_children([
-            Text::new(self.server_url.clone()).into(),
+            Text::new(
+                self.api
+                    .base_url()
+                    .as_str()
+                    .trim_end_matches('/')
+                    .to_string(),
+            )
+            .into(),
             Text::new(if self.server_online {
                 "Online"
             } else {
@@ -138,8 +173,7 @@ impl RcadaClient {
             .spacing(20)
             .padding(20)
             .push(title)
-            .push(header)
-            .push(lines)
+            .push(body)
             .push(Space::new().height(Length::Fill))
             .push(status_bar);
 
@@ -149,21 +183,83 @@ impl RcadaClient {
             .into()
     }
 
+    fn login_view(&self) -> Element<'_, Message> {
+        let mut form = Column::new()
+            .spacing(10)
+            .max_width(320)
+            .push(Text::new("Log in").size(20))
+            .push(
+                text_input("User name", &self.username)
+                    .on_input(Message::UsernameChanged)
+                    .on_submit(Message::Login),
+            )
+            .push(
+                text_input("Password", &self.password)
+                    .secure(true)
+                    .on_input(Message::PasswordChanged)
+                    .on_submit(Message::Login),
+            )
+            .push(button(Text::new("Log in")).on_press(Message::Login));
+        if let Some(error) = &self.login_error {
+            form = form.push(Text::new(error.clone()).size(14));
+        }
+        form.into()
+    }
+
     fn update(&mut self, message: Message) -> Task<Message> {
         match message {
             Message::Refresh => {
-                if self.server_online {
-                    Task::perform(RcadaClient::fetch_tags(), Message::Refreshed)
+                if self.server_online && !self.login_required {
+                    Task::perform(
+                        RcadaClient::fetch_tags(self.api.clone()),
+                        Message::Refreshed,
+                    )
                 } else {
                     Task::none()
                 }
             },
-            Message::Refreshed(tags) => {
+            Message::Refreshed(Ok(tags)) => {
                 self.tags = tags;
                 Task::none()
             },
+            Message::Refreshed(Err(FetchError::Unauthorized)) => {
+                self.tags.clear();
+                self.login_required = true;
+                Task::none()
+            },
+            Message::Refreshed(Err(FetchError::Failed)) => {
+                self.tags.clear();
+                Task::none()
+            },
+            Message::UsernameChanged(username) => {
+                self.username = username;
+                Task::none()
+            },
+            Message::PasswordChanged(password) => {
+                self.password = password;
+                Task::none()
+            },
+            Message::Login => Task::perform(
+                RcadaClient::login(
+                    self.api.clone(),
+                    self.username.clone(),
+                    self.password.clone(),
+                ),
+                Message::LoggedIn,
+            ),
+            Message::LoggedIn(Ok(token)) => {
+                self.api.set_auth(Auth::Bearer(token));
+                self.login_required = false;
+                self.login_error = None;
+                self.password.clear();
+                Task::none()
+            },
+            Message::LoggedIn(Err(e)) => {
+                self.login_error = Some(e);
+                Task::none()
+            },
             Message::HealthCheckServer => Task::perform(
-                RcadaClient::health_check(),
+                RcadaClient::health_check(self.api.clone()),
                 Message::HealthCheckServerResult,
             ),
             Message::HealthCheckServerResult(status) => {
@@ -182,46 +278,64 @@ impl RcadaClient {
         Subscription::batch([poll_tags, health_check])
     }
 
-    async fn health_check() -> bool {
-        #[derive(Deserialize)]
-        struct HealthCheckResponse {
-            status: String,
+    async fn health_check(api: rcada_sdk::Client) -> bool {
+        match api.health().await {
+            Ok(health) => health.status == "healthy",
+            // The server is up but the health check isn't public
+            Err(e) => e.is_unauthorized(),
         }
+    }
 
-        let resp = reqwest::get(format!("{}/api/v1/health", SERVER_URL)).await;
-
-        match resp {
-            Ok(response) => match response.json::<HealthCheckResponse>().await {
-                Ok(HealthCheckResponse {
-                    status,
-                }) => true,
-                Err(_) => false,
+    async fn fetch_tags(api: rcada_sdk::Client) -> Result<Vec<TagDisplay>, FetchError> {
+        match api.list_tags().await {
+            Ok(tags) => Ok(tags.into_iter().map(Into::into).collect()),
+            Err(e) if e.is_unauthorized() => Err(FetchError::Unauthorized),
+            Err(e) => {
+                eprintln!("{e}");
+                Err(FetchError::Failed)
             },
-            Err(_) => false,
         }
     }
 
-    async fn fetch_tags() -> Vec<TagDisplay> {
-        let resp = reqwest::get(format!("{}/api/v1/tags", SERVER_URL)).await;
-
-        match resp {
-            Ok(response) => match response.json::<TagsResponse>().await {
-                Ok(tags) => tags.tags.into_iter().map(Into::into).collect(),
-                Err(e) => {
-                    eprintln!("{e:?}");
-                    Vec::new()
-                },
-            },
-            Err(e) => {
-                eprintln!("{e:?}");
-                Vec::new()
-            },
+    async fn login(
+        api: rcada_sdk::Client,
+        username: String,
+        password: String,
+    ) -> Result<String, String> {
+        match api.login(&username, &password).await {
+            Ok(token) => Ok(token.token),
+            Err(rcada_sdk::Error::Api(e)) => Err(e.message),
+            Err(e) => Err(e.to_string()),
         }
     }
 }
 
+/// API client of `RCADA_SERVER_URL`, trusting the CA in `RCADA_CA_CERT` and
+/// authenticated with `RCADA_API_KEY`, if set.
+fn api_client() -> rcada_sdk::Result<rcada_sdk::Client> {
+    let server_url = std::env::var(SERVER_URL_ENV).unwrap_or_else(|_| SERVER_URL.to_string());
+    // Tags and health are polled anyway, a retry would only delay the next poll
+    let mut builder = rcada_sdk::Client::builder(server_url).retry(RetryPolicy::none());
+    if let Ok(path) = std::env::var(CA_CERT_ENV) {
+        builder = builder.ca_certificate_file(path)?;
+    }
+    if let Ok(key) = std::env::var(API_KEY_ENV) {
+        builder = builder.api_key(key);
+    }
+    builder.build()
+}
+
 fn main() -> iced::Result {
-    iced::application(RcadaClient::new, RcadaClient::update, RcadaClient::view)
+    let api = match api_client() {
+        Ok(api) => api,
+        Err(e) => {
+            eprintln!("error: {e}");
+            std::process::exit(2);
+        },
+    };
+
+    let boot = move || RcadaClient::new(api.clone());
+    iced::application(boot, RcadaClient::update, RcadaClient::view)
         .subscription(RcadaClient::subscription)
         .run()
 }
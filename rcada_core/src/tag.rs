@@ -1,3 +1,5 @@
+use std::collections::BTreeMap;
+
 use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
 use smol_str::SmolStr;
@@ -10,19 +12,146 @@ use crate::{
 pub type TagName = SmolStr;
 
 #[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
 pub struct TagValue {
+    #[cfg_attr(feature = "openapi", schema(value_type = crate::value::ValueSchema))]
     pub value: Value,
     pub timestamp: Option<DateTime<Utc>>,
+    #[serde(default)]
+    pub quality: Quality,
+}
+
+/// How far a value can be trusted, set by whoever writes it. Drivers mark
+/// values `bad` when the source is unreachable and keep the last value.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+#[serde(rename_all = "snake_case")]
+pub enum Quality {
+    #[default]
+    Good,
+    Uncertain,
+    Bad,
+}
+
+/// Engineering range of a tag value, used for scaling and display.
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize, Serialize)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+pub struct EngineeringRange {
+    pub low: f64,
+    pub high: f64,
+}
+
+impl EngineeringRange {
+    pub fn is_valid(&self) -> bool {
+        self.low.is_finite() && self.high.is_finite() && self.low < self.high
+    }
+}
+
+/// Alarm thresholds in engineering units. Unset limits are not checked.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Deserialize, Serialize)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+pub struct AlarmLimits {
+    #[serde(default)]
+    pub low_low: Option<f64>,
+    #[serde(default)]
+    pub low: Option<f64>,
+    #[serde(default)]
+    pub high: Option<f64>,
+    #[serde(default)]
+    pub high_high: Option<f64>,
+}
+
+impl AlarmLimits {
+    /// Checks that the set limits are finite and ordered `low_low <= low <= high <= high_high`.
+    pub fn is_valid(&self) -> bool {
+        let limits: Vec<f64> = [self.low_low, self.low, self.high, self.high_high]
+            .into_iter()
+            .flatten()
+            .collect();
+        limits.iter().all(|limit| limit.is_finite()) && limits.is_sorted()
+    }
+
+    /// Most severe limit `value` is beyond, limits are inclusive.
+    pub fn level(&self, value: f64) -> Option<AlarmLevel> {
+        let beyond = |limit: Option<f64>, above: bool| {
+            limit.is_some_and(|limit| {
+                if above {
+                    value >= limit
+                } else {
+                    value <= limit
+                }
+            })
+        };
+        if beyond(self.high_high, true) {
+            Some(AlarmLevel::HighHigh)
+        } else if beyond(self.low_low, false) {
+            Some(AlarmLevel::LowLow)
+        } else if beyond(self.high, true) {
+            Some(AlarmLevel::High)
+        } else if beyond(self.low, false) {
+            Some(AlarmLevel::Low)
+        } else {
+            None
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+#[serde(rename_all = "snake_case")]
+pub enum AlarmLevel {
+    LowLow,
+    Low,
+    High,
+    HighHigh,
 }
 
 #[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
 pub struct TagMeta {
     pub unit: Unit,
     pub data_type: DataType,
+    #[serde(default)]
+    pub description: String,
+    #[serde(default)]
+    pub range: Option<EngineeringRange>,
+    /// Number of fractional digits shown for numeric values.
+    #[serde(default)]
+    pub precision: Option<u8>,
+    /// Read-only tags can't be written through the API, only by the server itself.
+    #[serde(default)]
+    pub read_only: bool,
+    #[serde(default)]
+    pub labels: BTreeMap<String, String>,
+    /// Stored for clients, the server doesn't raise alarms from the limits.
+    #[serde(default)]
+    pub alarms: AlarmLimits,
+    /// Expression the value of a calculated tag is derived from. The server
+    /// stores it for an external calculation engine but doesn't evaluate it.
+    #[serde(default)]
+    pub expression: Option<String>,
+}
+
+impl TagMeta {
+    pub fn new(unit: Unit, data_type: DataType) -> Self {
+        Self {
+            unit,
+            data_type,
+            description: String::new(),
+            range: None,
+            precision: None,
+            read_only: false,
+            labels: BTreeMap::new(),
+            alarms: AlarmLimits::default(),
+            expression: None,
+        }
+    }
 }
 
 #[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
 pub struct Tag {
+    #[cfg_attr(feature = "openapi", schema(value_type = String))]
     pub name: TagName,
     pub value: TagValue,
     pub meta: TagMeta,
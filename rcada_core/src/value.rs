@@ -1,6 +1,10 @@
 use serde::{Deserialize, Serialize};
 
+/// 2^63, the first float past the range of `i64`.
+const I64_END: f32 = 9_223_372_036_854_775_808.0;
+
 #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
 pub enum Value {
     Integer(i64),
     Float(f32),
@@ -8,7 +12,13 @@ pub enum Value {
     String(String),
 }
 
+/// [`Value`] under another name for `#[schema(value_type = ...)]`, utoipa
+/// takes every type named `Value` for `serde_json::Value`.
+#[cfg(feature = "openapi")]
+pub type ValueSchema = Value;
+
 #[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
 pub enum DataType {
     Integer,
     Float,
@@ -26,6 +36,16 @@ impl Value {
         }
     }
 
+    /// Numeric value, `None` for strings.
+    pub fn as_f64(&self) -> Option<f64> {
+        match self {
+            Value::Integer(v) => Some(*v as f64),
+            Value::Float(v) => Some(*v as f64),
+            Value::Boolean(v) => Some(*v as u8 as f64),
+            Value::String(_) => None,
+        }
+    }
+
     pub fn default_with_data_type(data_type: DataType) -> Self {
         match data_type {
             DataType::Integer => Self::Integer(Default::default()),
@@ -34,4 +54,113 @@ impl Value {
             DataType::String => Self::String(Default::default()),
         }
     }
+
+    /// Converts the value into `data_type` without losing information.
+    ///
+    /// Returns `None` if the value can't be represented in the target type,
+    /// e.g. `Float(2.5)` as `Integer` or `String("abc")` as `Float`.
+    pub fn convert(&self, data_type: DataType) -> Option<Self> {
+        match (self, data_type) {
+            (value, target) if value.get_data_type() == target => Some(value.clone()),
+            (Value::Integer(v), DataType::Float) => {
+                // Casts saturate, so integers rounding up to 2^63 come back as
+                // `i64::MAX` and have to be rejected by range
+                let f = *v as f32;
+                (f < I64_END && f as i64 == *v).then_some(Value::Float(f))
+            },
+            (Value::Integer(v), DataType::Boolean) => match v {
+                0 => Some(Value::Boolean(false)),
+                1 => Some(Value::Boolean(true)),
+                _ => None,
+            },
+            (Value::Float(v), DataType::Integer) => {
+                let in_range = v.fract() == 0.0 && (-I64_END..I64_END).contains(v);
+                in_range.then_some(Value::Integer(*v as i64))
+            },
+            (Value::Float(v), DataType::Boolean) => {
+                if *v == 0.0 {
+                    Some(Value::Boolean(false))
+                } else if *v == 1.0 {
+                    Some(Value::Boolean(true))
+                } else {
+                    None
+                }
+            },
+            (Value::Boolean(v), DataType::Integer) => Some(Value::Integer(*v as i64)),
+            (Value::Boolean(v), DataType::Float) => Some(Value::Float(*v as u8 as f32)),
+            (Value::Integer(v), DataType::String) => Some(Value::String(v.to_string())),
+            (Value::Float(v), DataType::String) => Some(Value::String(v.to_string())),
+            (Value::Boolean(v), DataType::String) => Some(Value::String(v.to_string())),
+            (Value::String(s), DataType::Integer) => s.trim().parse().ok().map(Value::Integer),
+            (Value::String(s), DataType::Float) => {
+                // Like integers, numbers a float only holds rounded are rejected,
+                // and so are infinity and NaN
+                let v: f64 = s.trim().parse().ok()?;
+                let f = v as f32;
+                (f.is_finite() && f.to_string().parse::<f64>() == Ok(v)).then_some(Value::Float(f))
+            },
+            (Value::String(s), DataType::Boolean) => s.trim().parse().ok().map(Value::Boolean),
+            _ => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn string(s: &str) -> Value {
+        Value::String(s.to_string())
+    }
+
+    #[test]
+    fn converts_without_losing_information() {
+        use DataType::*;
+
+        let cases = [
+            (Value::Integer(7), Float, Some(Value::Float(7.0))),
+            (Value::Integer(16_777_217), Float, None),
+            (Value::Integer(i64::MAX), Float, None),
+            (Value::Integer(1), Boolean, Some(Value::Boolean(true))),
+            (Value::Integer(2), Boolean, None),
+            (Value::Integer(-3), String, Some(string("-3"))),
+            (Value::Float(-4.0), Integer, Some(Value::Integer(-4))),
+            (Value::Float(2.5), Integer, None),
+            (Value::Float(I64_END), Integer, None),
+            (Value::Float(f32::NAN), Integer, None),
+            (Value::Float(0.0), Boolean, Some(Value::Boolean(false))),
+            (Value::Float(0.5), Boolean, None),
+            (Value::Float(0.1), String, Some(string("0.1"))),
+            (Value::Boolean(true), Integer, Some(Value::Integer(1))),
+            (Value::Boolean(true), Float, Some(Value::Float(1.0))),
+            (Value::Boolean(false), String, Some(string("false"))),
+            (string(" 12 "), Integer, Some(Value::Integer(12))),
+            (string("1.5"), Integer, None),
+            (string("0.1"), Float, Some(Value::Float(0.1))),
+            (string("-2e3"), Float, Some(Value::Float(-2000.0))),
+            (string("16777217"), Float, None),
+            (string("1e39"), Float, None),
+            (string("inf"), Float, None),
+            (string("NaN"), Float, None),
+            (string("abc"), Float, None),
+            (string("true"), Boolean, Some(Value::Boolean(true))),
+            (string("1"), Boolean, None),
+            (string("same"), String, Some(string("same"))),
+        ];
+        for (value, data_type, expected) in cases {
+            assert_eq!(
+                value.convert(data_type),
+                expected,
+                "{value:?} as {data_type:?}"
+            );
+        }
+    }
+
+    #[test]
+    fn floats_survive_a_round_trip_through_strings() {
+        for v in [0.1, -1.0 / 3.0, f32::MAX, f32::MIN_POSITIVE, 16_777_216.0] {
+            let s = Value::Float(v).convert(DataType::String).unwrap();
+            assert_eq!(s.convert(DataType::Float), Some(Value::Float(v)), "{s:?}");
+        }
+    }
 }
@@ -0,0 +1,172 @@
+use std::collections::BTreeSet;
+
+use reqwest::{Method, StatusCode, header::CONTENT_TYPE};
+
+use crate::{
+    client::Client,
+    error::{ApiError, Error, Result},
+    model::{
+        AccessModel, AuditQuery, AuditRecord, ConfigFormat, Configuration, Grant, Health, Identity,
+        ImportOptions, ImportReport, ListAuditRecordsResponse, LoginRequest, MyAccess,
+        PutBindingRequest, PutRoleRequest, Readiness, ReloadReport, Role, Token, VerifyReport,
+    },
+};
+
+/// Configuration, administration, access control and health.
+impl Client {
+    /// Configuration of every tag, alias, template and driver.
+    pub async fn export_configuration(&self) -> Result<Configuration> {
+        self.send_json(self.request(Method::GET, &["configuration"]))
+            .await
+    }
+
+    /// Configuration as a JSON, YAML or CSV document, CSV only holds the tags.
+    pub async fn export_configuration_as(&self, format: ConfigFormat) -> Result<String> {
+        let response = self
+            .send(
+                self.request(Method::GET, &["configuration"])
+                    .query(&[("format", format)]),
+            )
+            .await?;
+        Ok(response.text().await?)
+    }
+
+    /// Applies a configuration. An import rejected because of conflicts
+    /// fails with [`crate::ErrorCode::Conflict`] and the report in the
+    /// error details.
+    pub async fn import_configuration(
+        &self,
+        configuration: &Configuration,
+        options: ImportOptions,
+    ) -> Result<ImportReport> {
+        let body = serde_json::to_vec(configuration)?;
+        self.import_configuration_as(body, ConfigFormat::Json, options)
+            .await
+    }
+
+    /// Applies a configuration document in the given format.
+    pub async fn import_configuration_as(
+        &self,
+        document: impl Into<Vec<u8>>,
+        format: ConfigFormat,
+        options: ImportOptions,
+    ) -> Result<ImportReport> {
+        self.send_json(
+            self.request(Method::POST, &["configuration", "import"])
+                .query(&options)
+                .header(CONTENT_TYPE, format.content_type())
+                .body(document.into()),
+        )
+        .await
+    }
+
+    /// Reloads the configuration file of the server.
+    pub async fn reload(&self) -> Result<ReloadReport> {
+        self.send_json(self.request(Method::POST, &["admin", "reload"]))
+            .await
+    }
+
+    /// Exchanges a user name and password for a token. The client keeps its
+    /// credentials, pass the token to [`Client::set_auth`] to use it.
+    pub async fn login(&self, username: &str, password: &str) -> Result<Token> {
+        self.send_json(
+            self.request(Method::POST, &["auth", "login"])
+                .json(&LoginRequest {
+                    username,
+                    password,
+                }),
+        )
+        .await
+    }
+
+    pub async fn whoami(&self) -> Result<Identity> {
+        self.send_json(self.request(Method::GET, &["auth", "whoami"]))
+            .await
+    }
+
+    pub async fn get_access(&self) -> Result<AccessModel> {
+        self.send_json(self.request(Method::GET, &["access"])).await
+    }
+
+    pub async fn my_access(&self) -> Result<MyAccess> {
+        self.send_json(self.request(Method::GET, &["access", "me"]))
+            .await
+    }
+
+    /// Creates or replaces a role.
+    pub async fn put_role(&self, name: &str, description: &str, grants: &[Grant]) -> Result<Role> {
+        self.send_json(self.request(Method::PUT, &["access", "roles", name]).json(
+            &PutRoleRequest {
+                description,
+                grants,
+            },
+        ))
+        .await
+    }
+
+    pub async fn delete_role(&self, name: &str) -> Result<()> {
+        self.send_empty(self.request(Method::DELETE, &["access", "roles", name]))
+            .await
+    }
+
+    /// Binds roles to a subject, replacing the bound ones. Subjects are written
+    /// `user:<name>`, `api_key:<name>` or `cert:<common name>`.
+    pub async fn put_binding(&self, subject: &str, roles: &BTreeSet<String>) -> Result<()> {
+        self.send_empty(
+            self.request(Method::PUT, &["access", "bindings", subject])
+                .json(&PutBindingRequest {
+                    roles,
+                }),
+        )
+        .await
+    }
+
+    pub async fn delete_binding(&self, subject: &str) -> Result<()> {
+        self.send_empty(self.request(Method::DELETE, &["access", "bindings", subject]))
+            .await
+    }
+
+    /// Newest audit records matching `query`.
+    pub async fn query_audit_log(&self, query: &AuditQuery) -> Result<Vec<AuditRecord>> {
+        let response: ListAuditRecordsResponse = self
+            .send_json(self.request(Method::GET, &["audit"]).query(query))
+            .await?;
+        Ok(response.records)
+    }
+
+    pub async fn verify_audit_log(&self) -> Result<VerifyReport> {
+        self.send_json(self.request(Method::GET, &["audit", "verify"]))
+            .await
+    }
+
+    pub async fn health(&self) -> Result<Health> {
+        self.send_json(self.request(Method::GET, &["health"])).await
+    }
+
+    pub async fn live(&self) -> Result<Health> {
+        self.send_json(self.request(Method::GET, &["health", "live"]))
+            .await
+    }
+
+    /// Report of every component. A server that isn't ready answers with
+    /// `503`, which is returned as a report rather than an error.
+    pub async fn ready(&self) -> Result<Readiness> {
+        let request = self.request(Method::GET, &["health", "ready"]).build()?;
+        let response = self.http.execute(request).await?;
+        let status = response.status();
+        let body = response.bytes().await?;
+        if status.is_success() || status == StatusCode::SERVICE_UNAVAILABLE {
+            Ok(serde_json::from_slice(&body)?)
+        } else {
+            Err(Error::Api(ApiError::from_body(status, &body)))
+        }
+    }
+
+    /// Metrics in the Prometheus text format.
+    pub async fn metrics(&self) -> Result<String> {
+        let mut url = self.base_url.clone();
+        url.set_path(&format!("{}/metrics", url.path().trim_end_matches('/')));
+        let response = self.send(self.auth.apply(self.http.get(url))).await?;
+        Ok(response.text().await?)
+    }
+}
@@ -0,0 +1,494 @@
+use std::{path::Path, time::Duration};
+
+use reqwest::{Method, Request, RequestBuilder, Response, StatusCode, Url};
+use serde::de::DeserializeOwned;
+
+use crate::error::{ApiError, Error, Result};
+
+/// Credentials sent with every request.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub enum Auth {
+    #[default]
+    None,
+    ApiKey(String),
+    Bearer(String),
+}
+
+impl Auth {
+    pub(crate) fn apply(&self, request: RequestBuilder) -> RequestBuilder {
+        match self {
+            Auth::None => request,
+            Auth::ApiKey(key) => request.header("X-API-Key", key),
+            Auth::Bearer(token) => request.bearer_auth(token),
+        }
+    }
+}
+
+/// When failed requests are sent again.
+///
+/// Requests that didn't reach the server are always retried. Requests that
+/// got a `502`, `503` or `504` are retried only if sending them twice has
+/// the same effect as sending them once, i.e. not for `POST`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RetryPolicy {
+    /// Attempts after the first one.
+    pub max_retries: u32,
+    /// Delay before the first retry, doubled after each one.
+    pub initial_backoff: Duration,
+    pub max_backoff: Duration,
+}
+
+impl RetryPolicy {
+    pub fn none() -> Self {
+        Self {
+            max_retries: 0,
+            ..Self::default()
+        }
+    }
+
+    fn backoff(&self, retry: u32) -> Duration {
+        self.initial_backoff
+            .saturating_mul(2u32.saturating_pow(retry))
+            .min(self.max_backoff)
+    }
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self {
+            max_retries: 2,
+            initial_backoff: Duration::from_millis(100),
+            max_backoff: Duration::from_secs(2),
+        }
+    }
+}
+
+/// Async client of the RCADA API. Cloning is cheap and shares connections.
+#[derive(Debug, Clone)]
+pub struct Client {
+    pub(crate) http: reqwest::Client,
+    pub(crate) base_url: Url,
+    pub(crate) auth: Auth,
+    retry: RetryPolicy,
+}
+
+pub struct ClientBuilder {
+    base_url: String,
+    auth: Auth,
+    retry: RetryPolicy,
+    timeout: Option<Duration>,
+    ca_certificates: Vec<Vec<u8>>,
+}
+
+impl ClientBuilder {
+    pub fn auth(mut self, auth: Auth) -> Self {
+        self.auth = auth;
+        self
+    }
+
+    pub fn api_key(self, key: impl Into<String>) -> Self {
+        self.auth(Auth::ApiKey(key.into()))
+    }
+
+    pub fn bearer_token(self, token: impl Into<String>) -> Self {
+        self.auth(Auth::Bearer(token.into()))
+    }
+
+    pub fn retry(mut self, retry: RetryPolicy) -> Self {
+        self.retry = retry;
+        self
+    }
+
+    /// Timeout of a single attempt.
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = Some(timeout);
+        self
+    }
+
+    /// Trusts a CA certificate (PEM) in addition to the system ones, for
+    /// servers with a private or self-signed certificate.
+    pub fn ca_certificate_pem(mut self, pem: impl Into<Vec<u8>>) -> Self {
+        self.ca_certificates.push(pem.into());
+        self
+    }
+
+    pub fn ca_certificate_file(self, path: impl AsRef<Path>) -> Result<Self> {
+        let path = path.as_ref();
+        let pem = std::fs::read(path)
+            .map_err(|e| Error::Config(format!("cannot read {}: {e}", path.display())))?;
+        Ok(self.ca_certificate_pem(pem))
+    }
+
+    pub fn build(self) -> Result<Client> {
+        let base_url = Url::parse(self.base_url.trim_end_matches('/'))
+            .map_err(|e| Error::Config(format!("invalid server URL {}: {e}", self.base_url)))?;
+        if base_url.cannot_be_a_base() {
+            return Err(Error::Config(format!(
+                "invalid server URL {}",
+                self.base_url
+            )));
+        }
+
+        let mut http = reqwest::Client::builder();
+        for pem in &self.ca_certificates {
+            let cert = reqwest::Certificate::from_pem(pem)
+                .map_err(|e| Error::Config(format!("invalid CA certificate: {e}")))?;
+            http = http.add_root_certificate(cert);
+        }
+        if let Some(timeout) = self.timeout {
+            http = http.timeout(timeout);
+        }
+
+        Ok(Client {
+            http: http.build()?,
+            base_url,
+            auth: self.auth,
+            retry: self.retry,
+        })
+    }
+}
+
+impl Client {
+    /// Builder of a client of the server at `base_url`, e.g.
+    /// `https://scada.local:8443`.
+    pub fn builder(base_url: impl Into<String>) -> ClientBuilder {
+        ClientBuilder {
+            base_url: base_url.into(),
+            auth: Auth::None,
+            retry: RetryPolicy::default(),
+            timeout: None,
+            ca_certificates: Vec::new(),
+        }
+    }
+
+    pub fn new(base_url: impl Into<String>) -> Result<Self> {
+        Self::builder(base_url).build()
+    }
+
+    pub fn base_url(&self) -> &Url {
+        &self.base_url
+    }
+
+    pub fn auth(&self) -> &Auth {
+        &self.auth
+    }
+
+    /// Replaces the credentials, e.g. with the token returned by
+    /// [`Client::login`].
+    pub fn set_auth(&mut self, auth: Auth) {
+        self.auth = auth;
+    }
+
+    /// URL of `/api/v1/<segments>`, each segment percent-encoded.
+    pub(crate) fn url(&self, segments: &[&str]) -> Url {
+        let mut url = self.base_url.clone();
+        if let Ok(mut path) = url.path_segments_mut() {
+            path.pop_if_empty().extend(["api", "v1"]).extend(segments);
+        }
+        url
+    }
+
+    pub(crate) fn request(&self, method: Method, segments: &[&str]) -> RequestBuilder {
+        self.auth
+            .apply(self.http.request(method, self.url(segments)))
+    }
+
+    /// Sends `request` and returns the response if it has a success status.
+    pub(crate) async fn send(&self, request: RequestBuilder) -> Result<Response> {
+        let response = self.execute(request.build()?).await?;
+        if response.status().is_success() {
+            return Ok(response);
+        }
+        let status = response.status();
+        let body = response.bytes().await?;
+        Err(Error::Api(ApiError::from_body(status, &body)))
+    }
+
+    pub(crate) async fn send_json<T: DeserializeOwned>(
+        &self,
+        request: RequestBuilder,
+    ) -> Result<T> {
+        let body = self.send(request).await?.bytes().await?;
+        Ok(serde_json::from_slice(&body)?)
+    }
+
+    pub(crate) async fn send_empty(&self, request: RequestBuilder) -> Result<()> {
+        self.send(request).await.map(|_| ())
+    }
+
+    /// Sends `request`, retrying it according to the [`RetryPolicy`].
+    pub(crate) async fn execute(&self, request: Request) -> Result<Response> {
+        let idempotent = request.method() != Method::POST;
+        let mut retry = 0;
+        loop {
+            // Streamed bodies can't be sent twice
+            let Some(attempt) = request.try_clone() else {
+                return Ok(self.http.execute(request).await?);
+            };
+            let result = self.http.execute(attempt).await;
+            let retryable = match &result {
+                Ok(response) => idempotent && is_transient(response.status()),
+                Err(e) => e.is_connect() || (idempotent && e.is_timeout()),
+            };
+            if !retryable || retry >= self.retry.max_retries {
+                return Ok(result?);
+            }
+
+            let backoff = self.retry.backoff(retry);
+            match &result {
+                Ok(response) => tracing::debug!(
+                    "{} {}: {}, retrying in {:?}",
+                    request.method(),
+                    request.url(),
+                    response.status(),
+                    backoff
+                ),
+                Err(e) => tracing::debug!(
+                    "{} {}: {}, retrying in {:?}",
+                    request.method(),
+                    request.url(),
+                    e,
+                    backoff
+                ),
+            }
+            tokio::time::sleep(backoff).await;
+            retry += 1;
+        }
+    }
+}
+
+fn is_transient(status: StatusCode) -> bool {
+    matches!(
+        status,
+        StatusCode::BAD_GATEWAY | StatusCode::SERVICE_UNAVAILABLE | StatusCode::GATEWAY_TIMEOUT
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        collections::{HashMap, VecDeque},
+        sync::{Arc, Mutex},
+    };
+
+    use rcada_core::{tag::TagMeta, unit::Unit, value::DataType};
+    use tokio::{
+        io::{AsyncBufReadExt, AsyncReadExt, AsyncWriteExt, BufReader},
+        net::TcpListener,
+    };
+
+    use super::*;
+    use crate::{error::ErrorCode, model::ComponentStatus};
+
+    #[derive(Debug)]
+    struct Received {
+        method: String,
+        path: String,
+        headers: HashMap<String, String>,
+        body: String,
+    }
+
+    type Log = Arc<Mutex<Vec<Received>>>;
+
+    /// Stand-in server answering the requests with `responses` in turn, and
+    /// with `500` once they're used up. Returns its URL and the requests.
+    async fn run_server(responses: Vec<(u16, &'static str)>) -> (String, Log) {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        let responses = Arc::new(Mutex::new(VecDeque::from(responses)));
+        let log = Log::default();
+        let received = log.clone();
+        tokio::spawn(async move {
+            while let Ok((stream, _)) = listener.accept().await {
+                let responses = responses.clone();
+                let received = received.clone();
+                tokio::spawn(async move {
+                    let mut stream = BufReader::new(stream);
+                    while let Some(request) = read_request(&mut stream).await {
+                        received.lock().unwrap().push(request);
+                        let (status, body) = responses
+                            .lock()
+                            .unwrap()
+                            .pop_front()
+                            .unwrap_or((500, "no more responses"));
+                        let response = format!(
+                            "HTTP/1.1 {status} Stand-in\r\ncontent-type: application/json\r\ncontent-length: {}\r\n\r\n{body}",
+                            body.len()
+                        );
+                        if stream.write_all(response.as_bytes()).await.is_err() {
+                            break;
+                        }
+                    }
+                });
+            }
+        });
+        (url, log)
+    }
+
+    async fn read_request(stream: &mut BufReader<tokio::net::TcpStream>) -> Option<Received> {
+        let mut line = String::new();
+        stream.read_line(&mut line).await.ok()?;
+        let mut parts = line.split_whitespace();
+        let method = parts.next()?.to_string();
+        let path = parts.next()?.to_string();
+        let mut headers = HashMap::new();
+        loop {
+            let mut line = String::new();
+            stream.read_line(&mut line).await.ok()?;
+            let Some((name, value)) = line.trim_end().split_once(':') else {
+                break;
+            };
+            headers.insert(name.to_ascii_lowercase(), value.trim().to_string());
+        }
+        let len = headers
+            .get("content-length")
+            .map_or(0, |len| len.parse().unwrap());
+        let mut body = vec![0; len];
+        stream.read_exact(&mut body).await.ok()?;
+        Some(Received {
+            method,
+            path,
+            headers,
+            body: String::from_utf8(body).unwrap(),
+        })
+    }
+
+    const TAG: &str = r#"{
+        "name": "line1/level",
+        "value": {"value": {"Float": 42.5}, "timestamp": null, "quality": "good", "data_type": "Float"},
+        "meta": {"unit": "Percent", "data_type": "Float"}
+    }"#;
+
+    fn fast_retries() -> RetryPolicy {
+        RetryPolicy {
+            max_retries: 2,
+            initial_backoff: Duration::from_millis(1),
+            max_backoff: Duration::from_millis(1),
+        }
+    }
+
+    #[tokio::test]
+    async fn sends_credentials_and_encodes_tag_names() {
+        let (url, log) = run_server(vec![(200, TAG), (200, r#"{"result": "Updated"}"#)]).await;
+        let client = Client::builder(format!("{url}/scada/"))
+            .api_key("secret")
+            .build()
+            .unwrap();
+
+        let tag = client.get_tag("line1/level").await.unwrap();
+        assert_eq!(tag.name, "line1/level");
+        let mut client = client;
+        client.set_auth(Auth::Bearer("token".to_string()));
+        let result = client
+            .write_value("line1/level", &rcada_core::value::Value::Float(1.5))
+            .await
+            .unwrap();
+        assert_eq!(result, crate::model::WriteResult::Updated);
+
+        let log = log.lock().unwrap();
+        assert_eq!(log[0].method, "GET");
+        assert_eq!(log[0].path, "/scada/api/v1/tags/line1%2Flevel");
+        assert_eq!(log[0].headers["x-api-key"], "secret");
+        assert_eq!(log[1].method, "PUT");
+        assert_eq!(log[1].path, "/scada/api/v1/tags/line1%2Flevel/value");
+        assert_eq!(log[1].headers["authorization"], "Bearer token");
+        assert!(!log[1].headers.contains_key("x-api-key"));
+        let body: serde_json::Value = serde_json::from_str(&log[1].body).unwrap();
+        assert_eq!(
+            body,
+            serde_json::json!({"value": {"Float": 1.5}, "timestamp": null})
+        );
+    }
+
+    #[tokio::test]
+    async fn returns_the_error_body_of_the_api() {
+        let envelope = r#"{
+            "code": "tag_not_found",
+            "message": "tag missing not found",
+            "request_id": "5d0c1d3e-8a4b-4f3e-9b7a-2c1e6f0d9a41"
+        }"#;
+        let (url, _) = run_server(vec![
+            (404, envelope),
+            (418, "I'm a teapot"),
+            (200, r#"{"code": "not json of a tag"}"#),
+        ])
+        .await;
+        let client = Client::new(url).unwrap();
+
+        let error = client.get_tag("missing").await.unwrap_err();
+        assert!(error.is_not_found());
+        assert_eq!(error.code(), Some(ErrorCode::TagNotFound));
+        let Error::Api(api) = error else {
+            panic!("not an API error");
+        };
+        assert_eq!(
+            api.request_id.map(|id| id.to_string()).as_deref(),
+            Some("5d0c1d3e-8a4b-4f3e-9b7a-2c1e6f0d9a41")
+        );
+        assert_eq!(api.message, "tag missing not found");
+
+        // Bodies of proxies are kept as the message
+        let error = client.get_tag("missing").await.unwrap_err();
+        assert_eq!(error.code(), Some(ErrorCode::Unknown));
+        assert_eq!(error.status(), Some(StatusCode::IM_A_TEAPOT));
+        assert!(error.to_string().contains("I'm a teapot"), "{error}");
+
+        let error = client.get_tag("missing").await.unwrap_err();
+        assert!(matches!(error, Error::Decode(_)), "{error}");
+    }
+
+    #[tokio::test]
+    async fn retries_transient_errors_unless_posting() {
+        let (url, log) = run_server(vec![
+            (503, "restarting"),
+            (502, "restarting"),
+            (200, TAG),
+            (503, "restarting"),
+        ])
+        .await;
+        let client = Client::builder(url).retry(fast_retries()).build().unwrap();
+
+        client.get_tag("line1/level").await.unwrap();
+        assert_eq!(log.lock().unwrap().len(), 3);
+
+        // Creating twice isn't the same as creating once
+        let error = client
+            .create_tag("line1/flow", &TagMeta::new(Unit::None, DataType::Float))
+            .await
+            .unwrap_err();
+        assert_eq!(error.status(), Some(StatusCode::SERVICE_UNAVAILABLE));
+        assert_eq!(log.lock().unwrap().len(), 4);
+        assert_eq!(log.lock().unwrap()[3].method, "POST");
+    }
+
+    #[tokio::test]
+    async fn reports_unreachable_servers_after_retrying() {
+        // Nothing listens on the port of a closed listener
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap();
+        drop(listener);
+        let client = Client::builder(format!("http://{address}"))
+            .retry(fast_retries())
+            .build()
+            .unwrap();
+        let error = client.health().await.unwrap_err();
+        assert!(
+            matches!(&error, Error::Http(e) if e.is_connect()),
+            "{error}"
+        );
+    }
+
+    #[tokio::test]
+    async fn reports_the_readiness_of_a_server_that_isnt_ready() {
+        let (url, _) = run_server(vec![(
+            503,
+            r#"{"status": "not_ready", "components": [
+                {"name": "driver/plc", "status": "down", "latency_ms": 2, "details": "refused"}
+            ]}"#,
+        )])
+        .await;
+        let readiness = Client::new(url).unwrap().ready().await.unwrap();
+        assert!(!readiness.is_ready());
+        assert_eq!(readiness.components[0].status, ComponentStatus::Down);
+    }
+}
@@ -0,0 +1,129 @@
+use std::fmt;
+
+use reqwest::StatusCode;
+use serde::Deserialize;
+use uuid::Uuid;
+
+/// Machine-readable reason of a failed request, see the "Errors" section of
+/// the server README.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ErrorCode {
+    InvalidRequest,
+    InvalidRange,
+    InvalidAlarmLimits,
+    InvalidDataType,
+    TimestampRequired,
+    TimestampOutOfOrder,
+    Unauthorized,
+    InvalidCredentials,
+    PermissionDenied,
+    ReadOnly,
+    NotFound,
+    TagNotFound,
+    AliasNotFound,
+    TemplateNotFound,
+    InstanceNotFound,
+    RoleNotFound,
+    BindingNotFound,
+    LoginDisabled,
+    AlreadyExists,
+    Conflict,
+    IncompatibleDataType,
+    InvalidConfiguration,
+    Internal,
+    /// Code added by a newer server, or a response that isn't an error body
+    /// of the API (e.g. from a proxy).
+    #[serde(other)]
+    Unknown,
+}
+
+/// Error body returned by the server.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct ApiError {
+    #[serde(skip, default = "default_status")]
+    pub status: StatusCode,
+    pub code: ErrorCode,
+    pub message: String,
+    #[serde(default)]
+    pub details: Option<serde_json::Value>,
+    #[serde(default)]
+    pub request_id: Option<Uuid>,
+}
+
+fn default_status() -> StatusCode {
+    StatusCode::INTERNAL_SERVER_ERROR
+}
+
+impl ApiError {
+    /// Parses an error body, falling back to the raw body for responses that
+    /// don't come from the API.
+    pub(crate) fn from_body(status: StatusCode, body: &[u8]) -> Self {
+        match serde_json::from_slice::<ApiError>(body) {
+            Ok(error) => Self {
+                status,
+                ..error
+            },
+            Err(_) => Self {
+                status,
+                code: ErrorCode::Unknown,
+                message: String::from_utf8_lossy(body).trim().to_string(),
+                details: None,
+                request_id: None,
+            },
+        }
+    }
+}
+
+impl fmt::Display for ApiError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} ({:?}): {}", self.status, self.code, self.message)?;
+        if let Some(request_id) = self.request_id {
+            write!(f, " [request {request_id}]")?;
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+    /// The server rejected the request.
+    #[error("{0}")]
+    Api(ApiError),
+    /// The request didn't reach the server or the response was cut off.
+    #[error("request failed: {0}")]
+    Http(#[from] reqwest::Error),
+    #[error("cannot decode response: {0}")]
+    Decode(#[from] serde_json::Error),
+    #[error("invalid client configuration: {0}")]
+    Config(String),
+}
+
+impl Error {
+    /// Code of the error body, if the server answered.
+    pub fn code(&self) -> Option<ErrorCode> {
+        match self {
+            Error::Api(error) => Some(error.code),
+            _ => None,
+        }
+    }
+
+    pub fn status(&self) -> Option<StatusCode> {
+        match self {
+            Error::Api(error) => Some(error.status),
+            Error::Http(error) => error.status(),
+            _ => None,
+        }
+    }
+
+    /// The credentials are missing, invalid or expired.
+    pub fn is_unauthorized(&self) -> bool {
+        self.status() == Some(StatusCode::UNAUTHORIZED)
+    }
+
+    pub fn is_not_found(&self) -> bool {
+        self.status() == Some(StatusCode::NOT_FOUND)
+    }
+}
+
+pub type Result<T, E = Error> = std::result::Result<T, E>;
@@ -0,0 +1,30 @@
+//! Async client of the RCADA HTTP API.
+//!
+//! ```no_run
+//! # async fn run() -> rcada_sdk::Result<()> {
+//! use rcada_sdk::{Client, rcada_core::value::Value};
+//!
+//! let client = Client::builder("http://127.0.0.1:8080")
+//!     .api_key("secret")
+//!     .build()?;
+//! for tag in client.list_tags().await? {
+//!     println!("{} = {:?}", tag.name, tag.value.value);
+//! }
+//! client.write_value("setpoint", &Value::Float(21.5)).await?;
+//! # Ok(())
+//! # }
+//! ```
+
+mod admin;
+mod client;
+mod error;
+pub mod model;
+mod tags;
+mod templates;
+mod watch;
+
+pub use client::{Auth, Client, ClientBuilder, RetryPolicy};
+pub use error::{ApiError, Error, ErrorCode, Result};
+pub use rcada_core;
+pub use tags::ValueWrite;
+pub use watch::Watch;
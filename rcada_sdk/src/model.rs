@@ -0,0 +1,451 @@
+//! Request and response bodies of the API that aren't [`rcada_core`] types.
+
+use std::collections::{BTreeMap, BTreeSet};
+
+use chrono::{DateTime, Utc};
+use rcada_core::{
+    tag::{AlarmLimits, EngineeringRange, TagMeta},
+    unit::Unit,
+    value::{DataType, Value},
+};
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+/// Outcome of a value write.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum WriteResult {
+    Updated,
+    /// The value or timestamp equals the current one.
+    Ignored,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub(crate) struct CreateTagRequest<'a> {
+    pub name: &'a str,
+    #[serde(flatten)]
+    pub meta: &'a TagMeta,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub(crate) struct UpdateValueRequest<'a> {
+    pub value: &'a Value,
+    pub timestamp: Option<DateTime<Utc>>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub(crate) struct UpdateValueResponse {
+    pub result: WriteResult,
+}
+
+/// Partial update of the metadata of a tag.
+///
+/// `None` fields are left untouched, `Some(None)` clears `range`, `precision`
+/// and `expression`, and a `None` label removes it.
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct TagMetaPatch {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub unit: Option<Unit>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub data_type: Option<DataType>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub range: Option<Option<EngineeringRange>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub precision: Option<Option<u8>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub read_only: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub labels: Option<BTreeMap<String, Option<String>>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub alarms: Option<AlarmLimits>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub expression: Option<Option<String>>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub(crate) struct RenameTagRequest<'a> {
+    pub new_name: &'a str,
+    pub keep_alias: bool,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub(crate) struct ListTagsResponse<T> {
+    pub tags: Vec<T>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub(crate) struct CreateAliasRequest<'a> {
+    pub target: &'a str,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Alias {
+    pub alias: String,
+    pub target: String,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub(crate) struct ListAliasesResponse {
+    pub aliases: Vec<Alias>,
+}
+
+/// Set of tags shared by every instance of an equipment type.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct TagTemplate {
+    pub name: String,
+    #[serde(default)]
+    pub description: String,
+    pub points: Vec<TemplatePoint>,
+}
+
+/// Tag of a template, named relative to the instance prefix.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct TemplatePoint {
+    pub name: String,
+    pub meta: TagMeta,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub(crate) struct ListTemplatesResponse {
+    pub templates: Vec<TagTemplate>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub(crate) struct UpdateTemplateRequest<'a> {
+    pub description: &'a str,
+    pub points: &'a [TemplatePoint],
+}
+
+/// Template after an update and the changes applied to its instances.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct TemplateUpdate {
+    pub template: TagTemplate,
+    pub instances: Vec<InstanceUpdate>,
+}
+
+/// Changes applied to the tags of one instance after a template update.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct InstanceUpdate {
+    pub prefix: String,
+    pub created: Vec<String>,
+    pub updated: Vec<String>,
+    pub deleted: Vec<String>,
+    pub failed: Vec<(String, String)>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub(crate) struct InstantiateRequest<'a> {
+    pub prefix: &'a str,
+}
+
+/// Tags of a template instance.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Instance {
+    pub prefix: String,
+    pub tags: Vec<String>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub(crate) struct ListInstancesResponse {
+    pub instances: Vec<String>,
+}
+
+/// Version-controllable configuration of all tags, aliases, templates and
+/// drivers. A section left out of an import is left as it is on the server.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct Configuration {
+    pub tags: Vec<TagConfig>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub aliases: Option<Vec<Alias>>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub templates: Option<Vec<TemplateConfig>>,
+    /// Drivers as in the server configuration file, with the passwords
+    /// redacted. An import only reports the ones that differ.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub drivers: Option<Vec<serde_json::Value>>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct TemplateConfig {
+    #[serde(flatten)]
+    pub template: TagTemplate,
+    /// Prefixes of the instances.
+    #[serde(default)]
+    pub instances: Vec<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct TagConfig {
+    pub name: String,
+    #[serde(flatten)]
+    pub meta: TagMeta,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ConfigFormat {
+    #[default]
+    Json,
+    Yaml,
+    Csv,
+}
+
+impl ConfigFormat {
+    pub fn content_type(self) -> &'static str {
+        match self {
+            ConfigFormat::Json => "application/json",
+            ConfigFormat::Yaml => "application/yaml",
+            ConfigFormat::Csv => "text/csv",
+        }
+    }
+}
+
+/// What to do with tags that exist on the server but differ from the import.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ConflictPolicy {
+    /// Reject the whole import.
+    #[default]
+    Fail,
+    /// Keep the existing tags.
+    Skip,
+    /// Replace the metadata of the existing tags.
+    Overwrite,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize)]
+pub struct ImportOptions {
+    pub dry_run: bool,
+    pub policy: ConflictPolicy,
+    /// Delete tags, aliases, templates and instances that are missing from
+    /// the sections of the import.
+    pub delete_missing: bool,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct ImportReport {
+    pub dry_run: bool,
+    pub applied: bool,
+    pub create: Vec<String>,
+    pub update: Vec<String>,
+    pub delete: Vec<String>,
+    /// Tags that differ from the import but were kept or caused a rejection.
+    pub conflicts: Vec<String>,
+    pub unchanged: usize,
+    #[serde(default)]
+    pub aliases: ImportChanges,
+    /// Templates whose definition or instances change.
+    #[serde(default)]
+    pub templates: ImportChanges,
+    /// Drivers that differ from the server configuration file.
+    #[serde(default)]
+    pub drivers: Vec<String>,
+}
+
+/// Changes of the aliases or templates in an import.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct ImportChanges {
+    pub create: Vec<String>,
+    pub update: Vec<String>,
+    pub delete: Vec<String>,
+    pub conflicts: Vec<String>,
+    pub unchanged: usize,
+}
+
+/// Changes applied by a reload of the server configuration file.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct ReloadReport {
+    pub tags_created: Vec<String>,
+    pub tags_updated: Vec<String>,
+    pub tags_deleted: Vec<String>,
+    pub drivers_started: Vec<String>,
+    pub drivers_restarted: Vec<String>,
+    pub drivers_stopped: Vec<String>,
+    pub log_filter_changed: bool,
+    pub certificate_reloaded: bool,
+    /// Changed settings that only take effect after a restart.
+    pub restart_required: Vec<String>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub(crate) struct LoginRequest<'a> {
+    pub username: &'a str,
+    pub password: &'a str,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Token {
+    pub token: String,
+    pub expires_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum AuthMethod {
+    Anonymous,
+    ApiKey,
+    Jwt,
+    ClientCertificate,
+    /// User name and password sent by a protocol without tokens, e.g. OPC UA.
+    Password,
+}
+
+/// Who the server thinks sent a request.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Identity {
+    pub name: String,
+    pub method: AuthMethod,
+    pub roles: Vec<String>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Permission {
+    Read,
+    Write,
+    Configure,
+    /// Implies every other permission.
+    Admin,
+}
+
+/// Permissions on the tags whose names match `tags`, where `*` matches any
+/// sequence of characters and `?` a single one.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Grant {
+    pub permissions: Vec<Permission>,
+    pub tags: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Role {
+    pub name: String,
+    #[serde(default)]
+    pub description: String,
+    pub grants: Vec<Grant>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub(crate) struct PutRoleRequest<'a> {
+    pub description: &'a str,
+    pub grants: &'a [Grant],
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub(crate) struct PutBindingRequest<'a> {
+    pub roles: &'a BTreeSet<String>,
+}
+
+/// Roles and the roles bound to users and API keys.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct AccessModel {
+    #[serde(default)]
+    pub roles: Vec<Role>,
+    /// Roles by `user:<name>`, `api_key:<name>` or `cert:<common name>`.
+    #[serde(default)]
+    pub bindings: BTreeMap<String, BTreeSet<String>>,
+}
+
+/// Roles of the caller, given by the configuration, its token and bindings.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct MyAccess {
+    pub name: String,
+    pub roles: BTreeSet<String>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AuditAction {
+    CreateTag,
+    UpdateValue,
+    UpdateMeta,
+    DeleteTag,
+    RenameTag,
+    CreateAlias,
+    DeleteAlias,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct AuditRecord {
+    pub seq: u64,
+    pub timestamp: DateTime<Utc>,
+    pub user: String,
+    pub source: Option<String>,
+    pub request_id: Option<Uuid>,
+    pub action: AuditAction,
+    pub tag: String,
+    pub old: Option<serde_json::Value>,
+    pub new: Option<serde_json::Value>,
+    /// `ok`, or the error that rejected the change.
+    pub result: String,
+    pub prev_hash: String,
+    pub hash: String,
+}
+
+/// Filter of the audit log, unset fields match every record.
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct AuditQuery {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub tag: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub user: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub action: Option<AuditAction>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub request_id: Option<Uuid>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub from: Option<DateTime<Utc>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub to: Option<DateTime<Utc>>,
+    /// Only the newest matching records are returned.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub limit: Option<usize>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub(crate) struct ListAuditRecordsResponse {
+    pub records: Vec<AuditRecord>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct VerifyReport {
+    pub valid: bool,
+    pub records: u64,
+    /// Sequence number (or line for unreadable lines) where the chain breaks.
+    pub broken_at: Option<u64>,
+    pub reason: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Health {
+    pub status: String,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ComponentStatus {
+    Up,
+    Down,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ComponentHealth {
+    pub name: String,
+    pub status: ComponentStatus,
+    pub latency_ms: u64,
+    pub details: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Readiness {
+    /// `ready` if every component is up, `not_ready` otherwise.
+    pub status: String,
+    pub components: Vec<ComponentHealth>,
+}
+
+impl Readiness {
+    pub fn is_ready(&self) -> bool {
+        self.components
+            .iter()
+            .all(|component| component.status == ComponentStatus::Up)
+    }
+}
@@ -0,0 +1,170 @@
+use std::collections::HashMap;
+
+use chrono::{DateTime, Utc};
+use rcada_core::{
+    tag::{Tag, TagMeta, TagValue},
+    value::Value,
+};
+use reqwest::Method;
+use tokio::task::JoinSet;
+
+use crate::{
+    client::Client,
+    error::Result,
+    model::{
+        Alias, CreateAliasRequest, CreateTagRequest, ListAliasesResponse, ListTagsResponse,
+        RenameTagRequest, TagMetaPatch, UpdateValueRequest, UpdateValueResponse, WriteResult,
+    },
+};
+
+/// Value write of [`Client::write_values`].
+#[derive(Debug, Clone, PartialEq)]
+pub struct ValueWrite {
+    pub tag: String,
+    pub value: Value,
+    /// Time of the value, the server uses the time of the request if unset.
+    pub timestamp: Option<DateTime<Utc>>,
+}
+
+impl ValueWrite {
+    pub fn new(tag: impl Into<String>, value: Value) -> Self {
+        Self {
+            tag: tag.into(),
+            value,
+            timestamp: None,
+        }
+    }
+}
+
+/// Tags, their values and aliases.
+impl Client {
+    /// Tags the caller may read.
+    pub async fn list_tags(&self) -> Result<Vec<Tag>> {
+        let response: ListTagsResponse<Tag> =
+            self.send_json(self.request(Method::GET, &["tags"])).await?;
+        Ok(response.tags)
+    }
+
+    /// Tag by its name or an alias of it.
+    pub async fn get_tag(&self, name: &str) -> Result<Tag> {
+        self.send_json(self.request(Method::GET, &["tags", name]))
+            .await
+    }
+
+    pub async fn create_tag(&self, name: &str, meta: &TagMeta) -> Result<()> {
+        self.send_empty(
+            self.request(Method::POST, &["tags"])
+                .json(&CreateTagRequest {
+                    name,
+                    meta,
+                }),
+        )
+        .await
+    }
+
+    /// Changes the metadata of a tag and returns the updated tag.
+    pub async fn update_tag_meta(&self, name: &str, patch: &TagMetaPatch) -> Result<Tag> {
+        self.send_json(self.request(Method::PATCH, &["tags", name]).json(patch))
+            .await
+    }
+
+    pub async fn delete_tag(&self, name: &str) -> Result<()> {
+        self.send_empty(self.request(Method::DELETE, &["tags", name]))
+            .await
+    }
+
+    /// Renames a tag, keeping the old name as an alias if `keep_alias` is set.
+    pub async fn rename_tag(&self, name: &str, new_name: &str, keep_alias: bool) -> Result<Tag> {
+        self.send_json(self.request(Method::POST, &["tags", name, "rename"]).json(
+            &RenameTagRequest {
+                new_name,
+                keep_alias,
+            },
+        ))
+        .await
+    }
+
+    /// Current value of a tag.
+    pub async fn read_value(&self, name: &str) -> Result<TagValue> {
+        self.get_tag(name).await.map(|tag| tag.value)
+    }
+
+    /// Writes a value, stamped with the time of the request.
+    pub async fn write_value(&self, name: &str, value: &Value) -> Result<WriteResult> {
+        self.write_value_at(name, value, None).await
+    }
+
+    pub async fn write_value_at(
+        &self,
+        name: &str,
+        value: &Value,
+        timestamp: Option<DateTime<Utc>>,
+    ) -> Result<WriteResult> {
+        let response: UpdateValueResponse = self
+            .send_json(self.request(Method::PUT, &["tags", name, "value"]).json(
+                &UpdateValueRequest {
+                    value,
+                    timestamp,
+                },
+            ))
+            .await?;
+        Ok(response.result)
+    }
+
+    /// Writes several values concurrently. Writes are independent, so some
+    /// may fail while others succeed; results are in the order of `writes`.
+    pub async fn write_values(
+        &self,
+        writes: impl IntoIterator<Item = ValueWrite>,
+    ) -> Vec<(ValueWrite, Result<WriteResult>)> {
+        let mut tasks = JoinSet::new();
+        for (i, write) in writes.into_iter().enumerate() {
+            let client = self.clone();
+            tasks.spawn(async move {
+                let result = client
+                    .write_value_at(&write.tag, &write.value, write.timestamp)
+                    .await;
+                (i, write, result)
+            });
+        }
+
+        let mut results: Vec<_> = tasks.join_all().await;
+        results.sort_by_key(|(i, _, _)| *i);
+        results
+            .into_iter()
+            .map(|(_, write, result)| (write, result))
+            .collect()
+    }
+
+    /// Current values of the tags the caller may read, by tag name.
+    pub async fn read_values(&self) -> Result<HashMap<String, TagValue>> {
+        Ok(self
+            .list_tags()
+            .await?
+            .into_iter()
+            .map(|tag| (tag.name.to_string(), tag.value))
+            .collect())
+    }
+
+    pub async fn list_aliases(&self) -> Result<Vec<Alias>> {
+        let response: ListAliasesResponse = self
+            .send_json(self.request(Method::GET, &["aliases"]))
+            .await?;
+        Ok(response.aliases)
+    }
+
+    pub async fn create_alias(&self, alias: &str, target: &str) -> Result<Alias> {
+        self.send_json(
+            self.request(Method::PUT, &["aliases", alias])
+                .json(&CreateAliasRequest {
+                    target,
+                }),
+        )
+        .await
+    }
+
+    pub async fn delete_alias(&self, alias: &str) -> Result<()> {
+        self.send_empty(self.request(Method::DELETE, &["aliases", alias]))
+            .await
+    }
+}
@@ -0,0 +1,79 @@
+use reqwest::Method;
+
+use crate::{
+    client::Client,
+    error::Result,
+    model::{
+        Instance, InstantiateRequest, ListInstancesResponse, ListTemplatesResponse, TagTemplate,
+        TemplatePoint, TemplateUpdate, UpdateTemplateRequest,
+    },
+};
+
+/// Tag templates and their instances.
+impl Client {
+    pub async fn list_templates(&self) -> Result<Vec<TagTemplate>> {
+        let response: ListTemplatesResponse = self
+            .send_json(self.request(Method::GET, &["templates"]))
+            .await?;
+        Ok(response.templates)
+    }
+
+    pub async fn get_template(&self, name: &str) -> Result<TagTemplate> {
+        self.send_json(self.request(Method::GET, &["templates", name]))
+            .await
+    }
+
+    pub async fn create_template(&self, template: &TagTemplate) -> Result<TagTemplate> {
+        self.send_json(self.request(Method::POST, &["templates"]).json(template))
+            .await
+    }
+
+    /// Replaces the points of a template and applies them to its instances.
+    pub async fn update_template(
+        &self,
+        name: &str,
+        description: &str,
+        points: &[TemplatePoint],
+    ) -> Result<TemplateUpdate> {
+        self.send_json(self.request(Method::PUT, &["templates", name]).json(
+            &UpdateTemplateRequest {
+                description,
+                points,
+            },
+        ))
+        .await
+    }
+
+    pub async fn delete_template(&self, name: &str) -> Result<()> {
+        self.send_empty(self.request(Method::DELETE, &["templates", name]))
+            .await
+    }
+
+    /// Prefixes of the instances of a template.
+    pub async fn list_instances(&self, template: &str) -> Result<Vec<String>> {
+        let response: ListInstancesResponse = self
+            .send_json(self.request(Method::GET, &["templates", template, "instances"]))
+            .await?;
+        Ok(response.instances)
+    }
+
+    /// Creates the tags of a template under `prefix`.
+    pub async fn instantiate(&self, template: &str, prefix: &str) -> Result<Instance> {
+        self.send_json(
+            self.request(Method::POST, &["templates", template, "instances"])
+                .json(&InstantiateRequest {
+                    prefix,
+                }),
+        )
+        .await
+    }
+
+    /// Deletes an instance and its tags.
+    pub async fn remove_instance(&self, template: &str, prefix: &str) -> Result<Instance> {
+        self.send_json(self.request(
+            Method::DELETE,
+            &["templates", template, "instances", prefix],
+        ))
+        .await
+    }
+}
@@ -0,0 +1,72 @@
+use std::{collections::HashMap, time::Duration};
+
+use rcada_core::tag::{Tag, TagValue};
+use tokio::{sync::mpsc, task::JoinHandle};
+
+use crate::{client::Client, error::Result};
+
+/// Changes of tag values, see [`Client::watch`].
+///
+/// The server has no push channel yet, so the tags are polled. Dropping the
+/// watch stops the polling.
+pub struct Watch {
+    changes: mpsc::Receiver<Result<Vec<Tag>>>,
+    task: JoinHandle<()>,
+}
+
+impl Watch {
+    /// Tags whose value or timestamp changed since the previous poll. The
+    /// first poll returns every tag. A failed poll is returned as an error
+    /// and polling continues.
+    pub async fn next(&mut self) -> Option<Result<Vec<Tag>>> {
+        self.changes.recv().await
+    }
+}
+
+impl Drop for Watch {
+    fn drop(&mut self) {
+        self.task.abort();
+    }
+}
+
+impl Client {
+    /// Polls the tags the caller may read every `interval` and reports the
+    /// ones that changed. `filter` limits the watch to the given tag names.
+    pub fn watch(&self, interval: Duration, filter: Option<Vec<String>>) -> Watch {
+        let (sender, changes) = mpsc::channel(16);
+        let client = self.clone();
+        let task = tokio::spawn(async move {
+            let mut last: HashMap<String, TagValue> = HashMap::new();
+            let mut ticker = tokio::time::interval(interval);
+            ticker.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+            loop {
+                ticker.tick().await;
+                let update = client.list_tags().await.map(|tags| {
+                    tags.into_iter()
+                        .filter(|tag| {
+                            filter
+                                .as_ref()
+                                .is_none_or(|names| names.iter().any(|name| tag.name == name))
+                        })
+                        .filter(|tag| last.get(tag.name.as_str()) != Some(&tag.value))
+                        .collect::<Vec<Tag>>()
+                });
+                if let Ok(changed) = &update {
+                    if changed.is_empty() {
+                        continue;
+                    }
+                    for tag in changed {
+                        last.insert(tag.name.to_string(), tag.value.clone());
+                    }
+                }
+                if sender.send(update).await.is_err() {
+                    break;
+                }
+            }
+        });
+        Watch {
+            changes,
+            task,
+        }
+    }
+}
@@ -0,0 +1,11 @@
+fn main() -> Result<(), Box<dyn std::error::Error>> {
+    // Use the bundled protoc unless one is given, so building needs no system package
+    if std::env::var_os("PROTOC").is_none() {
+        // SAFETY: build scripts are single-threaded
+        unsafe { std::env::set_var("PROTOC", protoc_bin_vendored::protoc_bin_path()?) };
+    }
+    // The client is only used by the tests
+    tonic_prost_build::configure()
+        .compile_protos(&["proto/tags.proto", "proto/sparkplug_b.proto"], &["proto"])?;
+    Ok(())
+}
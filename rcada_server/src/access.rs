@@ -0,0 +1,591 @@
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    fmt, fs, io,
+    path::{Path, PathBuf},
+    str::FromStr,
+    sync::RwLock,
+};
+
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+
+use crate::auth::{AuthMethod, CERT_PREFIX, Identity};
+
+pub const ACCESS_FILE: &str = "access.json";
+
+/// Pattern of a grant that covers every tag.
+pub const ALL_TAGS: &str = "*";
+
+#[derive(
+    Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize, Deserialize, ToSchema,
+)]
+#[serde(rename_all = "snake_case")]
+pub enum Permission {
+    Read,
+    Write,
+    Configure,
+    /// Implies every other permission.
+    Admin,
+}
+
+/// Permissions on the tags whose names match `tags`, where `*` matches any
+/// sequence of characters and `?` a single one.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, ToSchema)]
+pub struct Grant {
+    pub permissions: Vec<Permission>,
+    #[serde(default = "all_tags")]
+    pub tags: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, ToSchema)]
+pub struct Role {
+    pub name: String,
+    #[serde(default)]
+    pub description: String,
+    pub grants: Vec<Grant>,
+}
+
+/// How the subject of a binding authenticates.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum SubjectKind {
+    /// Configured user or subject of a token.
+    User,
+    ApiKey,
+    ClientCertificate,
+}
+
+/// User, API key or client certificate that roles are bound to, written as
+/// `user:<name>`, `api_key:<name>` or `cert:<common name>` so that a
+/// certificate can't get the roles of a user with the same name.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub struct Subject {
+    pub kind: SubjectKind,
+    pub name: String,
+}
+
+/// Roles and the roles bound to users and API keys, as stored in `access.json`.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, ToSchema)]
+pub struct AccessModel {
+    #[serde(default)]
+    pub roles: Vec<Role>,
+    /// Roles by `user:<name>`, `api_key:<name>` or `cert:<common name>`.
+    #[serde(default)]
+    #[schema(value_type = BTreeMap<String, BTreeSet<String>>)]
+    pub bindings: BTreeMap<Subject, BTreeSet<String>>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum AccessError {
+    RoleNotFound,
+    BindingNotFound,
+    UnknownRoles(Vec<String>),
+    /// The role is still bound to these subjects.
+    RoleInUse(Vec<String>),
+    InvalidRole(String),
+    InvalidSubject(String),
+    Storage(String),
+}
+
+fn all_tags() -> String {
+    ALL_TAGS.to_string()
+}
+
+impl Default for AccessModel {
+    /// Roles for the usual split of responsibilities, without any bindings.
+    fn default() -> Self {
+        let role = |name: &str, description: &str, permissions: &[Permission]| Role {
+            name: name.to_string(),
+            description: description.to_string(),
+            grants: vec![Grant {
+                permissions: permissions.to_vec(),
+                tags: all_tags(),
+            }],
+        };
+        Self {
+            roles: vec![
+                role("viewer", "Read all tags", &[Permission::Read]),
+                role(
+                    "operator",
+                    "Read all tags and write values",
+                    &[Permission::Read, Permission::Write],
+                ),
+                role(
+                    "engineer",
+                    "Operator, and change the configuration",
+                    &[Permission::Read, Permission::Write, Permission::Configure],
+                ),
+                role("admin", "Everything", &[Permission::Admin]),
+            ],
+            bindings: BTreeMap::new(),
+        }
+    }
+}
+
+impl SubjectKind {
+    fn as_str(&self) -> &'static str {
+        match self {
+            SubjectKind::User => "user",
+            SubjectKind::ApiKey => "api_key",
+            SubjectKind::ClientCertificate => "cert",
+        }
+    }
+}
+
+impl Subject {
+    pub fn new(kind: SubjectKind, name: impl Into<String>) -> Self {
+        Self {
+            kind,
+            name: name.into(),
+        }
+    }
+
+    /// Subject of the bindings of `identity`, anonymous identities have none.
+    pub fn of(identity: &Identity) -> Option<Self> {
+        let name = identity.name.as_str();
+        let (kind, name) = match identity.method {
+            AuthMethod::Anonymous => return None,
+            AuthMethod::ApiKey => (SubjectKind::ApiKey, name),
+            AuthMethod::Jwt | AuthMethod::Password => (SubjectKind::User, name),
+            AuthMethod::ClientCertificate => (
+                SubjectKind::ClientCertificate,
+                name.strip_prefix(CERT_PREFIX).unwrap_or(name),
+            ),
+        };
+        Some(Self::new(kind, name))
+    }
+}
+
+impl fmt::Display for Subject {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}:{}", self.kind.as_str(), self.name)
+    }
+}
+
+impl FromStr for Subject {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || format!("{s}: expected user:<name>, api_key:<name> or cert:<name>");
+        let (kind, name) = s.split_once(':').ok_or_else(invalid)?;
+        let kind = match kind {
+            "user" => SubjectKind::User,
+            "api_key" => SubjectKind::ApiKey,
+            "cert" => SubjectKind::ClientCertificate,
+            _ => return Err(invalid()),
+        };
+        if name.is_empty() {
+            return Err(invalid());
+        }
+        Ok(Self::new(kind, name))
+    }
+}
+
+impl TryFrom<String> for Subject {
+    type Error = String;
+
+    fn try_from(s: String) -> Result<Self, Self::Error> {
+        s.parse()
+    }
+}
+
+impl From<Subject> for String {
+    fn from(subject: Subject) -> Self {
+        subject.to_string()
+    }
+}
+
+impl Grant {
+    fn allows(&self, permission: Permission, tag: Option<&str>) -> bool {
+        let permitted = self
+            .permissions
+            .iter()
+            .any(|p| *p == permission || *p == Permission::Admin);
+        permitted
+            && match tag {
+                Some(tag) => matches(&self.tags, tag),
+                None => self.tags == ALL_TAGS,
+            }
+    }
+}
+
+impl Role {
+    pub fn validate(&self) -> Result<(), String> {
+        if self.name.is_empty() {
+            return Err("role name is empty".to_string());
+        }
+        for grant in &self.grants {
+            if grant.permissions.is_empty() {
+                return Err("grant without permissions".to_string());
+            }
+            if grant.tags.is_empty() {
+                return Err("grant with an empty tag pattern".to_string());
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Decides what an [`Identity`] may do. The model can be changed at runtime and
+/// is saved after every change if the store has a file.
+#[derive(Debug, Default)]
+pub struct AccessControl {
+    model: RwLock<AccessModel>,
+    path: Option<PathBuf>,
+}
+
+impl AccessControl {
+    pub fn new(model: AccessModel) -> Self {
+        Self {
+            model: RwLock::new(model),
+            path: None,
+        }
+    }
+
+    /// Loads the model from `path`, or starts with the default roles.
+    pub fn open(path: impl AsRef<Path>) -> io::Result<Self> {
+        let path = path.as_ref().to_path_buf();
+        let model = match fs::read(&path) {
+            Ok(bytes) => serde_json::from_slice(&bytes)
+                .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => AccessModel::default(),
+            Err(e) => return Err(e),
+        };
+        Ok(Self {
+            model: RwLock::new(model),
+            path: Some(path),
+        })
+    }
+
+    /// Anonymous identities only exist while authentication is disabled and
+    /// may do everything.
+    pub fn is_allowed(
+        &self,
+        identity: &Identity,
+        permission: Permission,
+        tag: Option<&str>,
+    ) -> bool {
+        if identity.method == AuthMethod::Anonymous {
+            return true;
+        }
+        let model = self.read();
+        let bound = Subject::of(identity).and_then(|subject| model.bindings.get(&subject));
+        model
+            .roles
+            .iter()
+            .filter(|role| {
+                identity.roles.contains(&role.name) || bound.is_some_and(|b| b.contains(&role.name))
+            })
+            .flat_map(|role| &role.grants)
+            .any(|grant| grant.allows(permission, tag))
+    }
+
+    /// Roles of `identity`, from its credentials and from the bindings.
+    pub fn roles_of(&self, identity: &Identity) -> BTreeSet<String> {
+        let mut roles: BTreeSet<String> = identity.roles.iter().cloned().collect();
+        if let Some(subject) = Subject::of(identity)
+            && let Some(bound) = self.read().bindings.get(&subject)
+        {
+            roles.extend(bound.iter().cloned());
+        }
+        roles
+    }
+
+    pub fn get_model(&self) -> AccessModel {
+        self.read().clone()
+    }
+
+    pub fn get_role(&self, name: &str) -> Option<Role> {
+        self.read()
+            .roles
+            .iter()
+            .find(|role| role.name == name)
+            .cloned()
+    }
+
+    /// Creates or replaces a role, returns the previous one.
+    pub fn put_role(&self, role: Role) -> Result<Option<Role>, AccessError> {
+        role.validate().map_err(AccessError::InvalidRole)?;
+        self.update(|model| {
+            let previous = match model.roles.iter_mut().find(|r| r.name == role.name) {
+                Some(existing) => Some(std::mem::replace(existing, role)),
+                None => {
+                    model.roles.push(role);
+                    None
+                },
+            };
+            Ok(previous)
+        })
+    }
+
+    pub fn delete_role(&self, name: &str) -> Result<(), AccessError> {
+        self.update(|model| {
+            let subjects: Vec<String> = model
+                .bindings
+                .iter()
+                .filter(|(_, roles)| roles.contains(name))
+                .map(|(subject, _)| subject.to_string())
+                .collect();
+            if !subjects.is_empty() {
+                return Err(AccessError::RoleInUse(subjects));
+            }
+            let len = model.roles.len();
+            model.roles.retain(|role| role.name != name);
+            if model.roles.len() == len {
+                return Err(AccessError::RoleNotFound);
+            }
+            Ok(())
+        })
+    }
+
+    /// Binds exactly `roles` to a user, API key or client certificate.
+    pub fn set_binding(
+        &self,
+        subject: Subject,
+        roles: BTreeSet<String>,
+    ) -> Result<(), AccessError> {
+        self.update(|model| {
+            let unknown: Vec<String> = roles
+                .iter()
+                .filter(|name| !model.roles.iter().any(|role| &role.name == *name))
+                .cloned()
+                .collect();
+            if !unknown.is_empty() {
+                return Err(AccessError::UnknownRoles(unknown));
+            }
+            model.bindings.insert(subject, roles);
+            Ok(())
+        })
+    }
+
+    pub fn delete_binding(&self, subject: &Subject) -> Result<(), AccessError> {
+        self.update(|model| {
+            model
+                .bindings
+                .remove(subject)
+                .map(|_| ())
+                .ok_or(AccessError::BindingNotFound)
+        })
+    }
+
+    fn read(&self) -> std::sync::RwLockReadGuard<'_, AccessModel> {
+        self.model.read().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// Applies `change` to a copy of the model and keeps it only if it's saved.
+    fn update<T>(
+        &self,
+        change: impl FnOnce(&mut AccessModel) -> Result<T, AccessError>,
+    ) -> Result<T, AccessError> {
+        let mut model = self.model.write().unwrap_or_else(|e| e.into_inner());
+        let mut updated = model.clone();
+        let result = change(&mut updated)?;
+        self.save(&updated)
+            .map_err(|e| AccessError::Storage(e.to_string()))?;
+        *model = updated;
+        Ok(result)
+    }
+
+    fn save(&self, model: &AccessModel) -> io::Result<()> {
+        let Some(path) = &self.path else {
+            return Ok(());
+        };
+        let json = serde_json::to_vec_pretty(model).map_err(io::Error::other)?;
+        let tmp = path.with_extension("tmp");
+        fs::write(&tmp, json)?;
+        fs::rename(tmp, path)
+    }
+}
+
+/// Glob match where `*` matches any sequence of characters and `?` one.
+pub fn matches(pattern: &str, name: &str) -> bool {
+    let pattern: Vec<char> = pattern.chars().collect();
+    let name: Vec<char> = name.chars().collect();
+    let (mut p, mut n) = (0, 0);
+    let mut backtrack = None;
+    while n < name.len() {
+        match pattern.get(p) {
+            Some('*') => {
+                backtrack = Some((p, n));
+                p += 1;
+            },
+            Some(c) if *c == '?' || *c == name[n] => {
+                p += 1;
+                n += 1;
+            },
+            _ => match backtrack {
+                Some((star, matched)) => {
+                    p = star + 1;
+                    n = matched + 1;
+                    backtrack = Some((star, matched + 1));
+                },
+                None => return false,
+            },
+        }
+    }
+    pattern[p..].iter().all(|c| *c == '*')
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn identity(name: &str, method: AuthMethod, roles: &[&str]) -> Identity {
+        Identity {
+            name: name.to_string(),
+            method,
+            roles: roles.iter().map(|role| role.to_string()).collect(),
+        }
+    }
+
+    fn roles(names: &[&str]) -> BTreeSet<String> {
+        names.iter().map(|name| name.to_string()).collect()
+    }
+
+    fn area_operator() -> Role {
+        Role {
+            name: "area1-operator".to_string(),
+            description: String::new(),
+            grants: vec![Grant {
+                permissions: vec![Permission::Read, Permission::Write],
+                tags: "area1.*".to_string(),
+            }],
+        }
+    }
+
+    #[test]
+    fn glob_matching() {
+        assert!(matches("*", ""));
+        assert!(matches("*", "area1.pump.speed"));
+        assert!(matches("area1.*", "area1.pump.speed"));
+        assert!(matches("area1.*", "area1."));
+        assert!(!matches("area1.*", "area1"));
+        assert!(!matches("area1.*", "area10.pump"));
+        assert!(matches("*.speed", "area1.pump.speed"));
+        assert!(!matches("*.speed", "area1.pump.speed2"));
+        assert!(matches("area?.pump", "area2.pump"));
+        assert!(!matches("area?.pump", "area.pump"));
+        assert!(!matches("area?.pump", "area12.pump"));
+        assert!(matches("a*b*c", "axxbyybzzc"));
+        assert!(!matches("a*b*c", "axxbyyb"));
+        assert!(matches("**", "x"));
+        assert!(matches("pump", "pump"));
+        assert!(!matches("pump", "pumps"));
+        assert!(matches("pümpe?", "pümpe1"));
+    }
+
+    #[test]
+    fn subjects_parse_and_print() {
+        let subject: Subject = "cert:historian".parse().unwrap();
+        assert_eq!(
+            subject,
+            Subject::new(SubjectKind::ClientCertificate, "historian")
+        );
+        assert_eq!(subject.to_string(), "cert:historian");
+        assert_eq!(
+            "api_key:a:b".parse::<Subject>(),
+            Ok(Subject::new(SubjectKind::ApiKey, "a:b"))
+        );
+        for invalid in ["alice", "user:", "group:alice", ":alice"] {
+            assert!(invalid.parse::<Subject>().is_err(), "{invalid}");
+        }
+
+        let mut model = AccessModel::default();
+        model
+            .bindings
+            .insert(Subject::new(SubjectKind::User, "alice"), roles(&["viewer"]));
+        let json = serde_json::to_value(&model).unwrap();
+        assert_eq!(
+            json["bindings"]["user:alice"],
+            serde_json::json!(["viewer"])
+        );
+        assert_eq!(serde_json::from_value::<AccessModel>(json).unwrap(), model);
+    }
+
+    #[test]
+    fn roles_come_from_credentials_and_bindings() {
+        let access = AccessControl::new(AccessModel::default());
+        access.put_role(area_operator()).unwrap();
+        access
+            .set_binding(
+                Subject::new(SubjectKind::User, "alice"),
+                roles(&["area1-operator"]),
+            )
+            .unwrap();
+
+        let alice = identity("alice", AuthMethod::Jwt, &["viewer"]);
+        assert_eq!(
+            access.roles_of(&alice),
+            roles(&["area1-operator", "viewer"])
+        );
+        assert!(access.is_allowed(&alice, Permission::Read, Some("area2.pump")));
+        assert!(access.is_allowed(&alice, Permission::Write, Some("area1.pump")));
+        assert!(!access.is_allowed(&alice, Permission::Write, Some("area2.pump")));
+        // A grant on some tags doesn't allow operations on all tags
+        assert!(!access.is_allowed(&alice, Permission::Write, None));
+        assert!(access.is_allowed(&alice, Permission::Read, None));
+        assert!(!access.is_allowed(&alice, Permission::Configure, Some("area1.pump")));
+
+        let admin = identity("root", AuthMethod::ApiKey, &["admin"]);
+        assert!(access.is_allowed(&admin, Permission::Configure, None));
+        assert!(access.is_allowed(&admin, Permission::Write, Some("x")));
+
+        let anonymous = Identity::anonymous();
+        assert!(access.is_allowed(&anonymous, Permission::Admin, None));
+    }
+
+    #[test]
+    fn bindings_are_separate_per_kind_of_credentials() {
+        let access = AccessControl::new(AccessModel::default());
+        access
+            .set_binding(Subject::new(SubjectKind::User, "alice"), roles(&["admin"]))
+            .unwrap();
+
+        let user = identity("alice", AuthMethod::Jwt, &[]);
+        let password = identity("alice", AuthMethod::Password, &[]);
+        let api_key = identity("alice", AuthMethod::ApiKey, &[]);
+        let cert = identity("cert:alice", AuthMethod::ClientCertificate, &[]);
+        assert!(access.is_allowed(&user, Permission::Admin, None));
+        assert!(access.is_allowed(&password, Permission::Admin, None));
+        assert!(!access.is_allowed(&api_key, Permission::Read, None));
+        assert!(!access.is_allowed(&cert, Permission::Read, None));
+        assert!(access.roles_of(&cert).is_empty());
+
+        access
+            .set_binding(
+                Subject::new(SubjectKind::ClientCertificate, "alice"),
+                roles(&["viewer"]),
+            )
+            .unwrap();
+        assert!(access.is_allowed(&cert, Permission::Read, None));
+        assert!(!access.is_allowed(&api_key, Permission::Read, None));
+    }
+
+    #[test]
+    fn binding_changes_are_checked() {
+        let access = AccessControl::new(AccessModel::default());
+        let subject = Subject::new(SubjectKind::ApiKey, "historian");
+
+        assert_eq!(
+            access.set_binding(subject.clone(), roles(&["viewer", "nobody"])),
+            Err(AccessError::UnknownRoles(vec!["nobody".to_string()]))
+        );
+        access
+            .set_binding(subject.clone(), roles(&["viewer"]))
+            .unwrap();
+        assert_eq!(
+            access.delete_role("viewer"),
+            Err(AccessError::RoleInUse(vec![
+                "api_key:historian".to_string()
+            ]))
+        );
+
+        access.delete_binding(&subject).unwrap();
+        assert_eq!(
+            access.delete_binding(&subject),
+            Err(AccessError::BindingNotFound)
+        );
+        access.delete_role("viewer").unwrap();
+        assert_eq!(access.get_role("viewer"), None);
+    }
+}
@@ -0,0 +1,737 @@
+use std::{
+    collections::{BTreeMap, HashMap},
+    path::PathBuf,
+    sync::Arc,
+    time::{Duration, SystemTime},
+};
+
+use ractor::{Actor, ActorProcessingErr, ActorRef};
+use serde::{Deserialize, Serialize};
+use tokio::sync::mpsc;
+use utoipa::ToSchema;
+
+use rcada_core::tag::{Tag, TagMeta, TagName};
+
+use crate::{
+    actor::{CountedMessage, Mailbox, tag},
+    audit::Origin,
+    config::{Args, DEFAULT_CONFIG_FILE, ServerConfig},
+    driver::{self, DriverConfig, DriverStatus, RunningDriver},
+    metrics::metrics,
+    repository::tag::{CreateTagResult, TagChange},
+    tls::CertificateResolver,
+};
+
+const REPLY_CHANNEL_SIZE: usize = 1;
+
+/// How often the configuration file and the TLS certificate are checked for changes.
+const WATCH_INTERVAL: Duration = Duration::from_secs(2);
+
+/// Name of this actor in the audit log.
+const AUDIT_USER: &str = "config";
+
+/// Name of this actor in the metrics.
+const METRICS_ACTOR: &str = "config";
+
+/// How long a driver may take to shut down before it's replaced.
+const DRIVER_STOP_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Applies a new log filter, e.g. through a `tracing_subscriber` reload handle.
+pub type LogFilterReloader = Box<dyn Fn(&str) -> Result<(), String> + Send + Sync>;
+
+/// Owns the running configuration: creates the configured tags, runs the
+/// drivers and applies configuration changes without a restart.
+pub struct ConfigActor;
+
+pub struct ConfigArguments {
+    pub args: Args,
+    pub config: ServerConfig,
+    pub tag_repo: ActorRef<tag::Message>,
+    pub set_log_filter: LogFilterReloader,
+    /// Certificate of the HTTPS server, reloaded when its files change.
+    pub certificate: Option<Arc<CertificateResolver>>,
+}
+
+pub struct ConfigState {
+    args: Args,
+    config: ServerConfig,
+    tag_repo: ActorRef<tag::Message>,
+    set_log_filter: LogFilterReloader,
+    certificate: Option<Arc<CertificateResolver>>,
+    drivers: HashMap<String, RunningDriver>,
+    modified: Option<SystemTime>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize, ToSchema)]
+pub struct ReloadReport {
+    #[schema(value_type = Vec<String>)]
+    pub tags_created: Vec<TagName>,
+    #[schema(value_type = Vec<String>)]
+    pub tags_updated: Vec<TagName>,
+    #[schema(value_type = Vec<String>)]
+    pub tags_deleted: Vec<TagName>,
+    pub drivers_started: Vec<String>,
+    pub drivers_restarted: Vec<String>,
+    pub drivers_stopped: Vec<String>,
+    pub log_filter_changed: bool,
+    pub certificate_reloaded: bool,
+    /// Changed settings that only take effect after a restart.
+    pub restart_required: Vec<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum ReloadError {
+    /// The new configuration can't be loaded, the running one is kept.
+    InvalidConfig(String),
+    /// The tag changes were rejected, nothing was changed.
+    Tags(String),
+    /// Drivers failed to start, the running configuration was restored.
+    Drivers(Vec<(String, String)>),
+}
+
+/// Tag changes of a reload.
+#[derive(Default)]
+struct TagPlan {
+    /// New and changed tags.
+    upserts: Vec<TagChange>,
+    /// Changes that undo `upserts`.
+    undo: Vec<TagChange>,
+    deletes: Vec<TagChange>,
+}
+
+#[derive(Debug)]
+pub enum Message {
+    Reload {
+        result: mpsc::Sender<Result<ReloadReport, ReloadError>>,
+    },
+    /// Reloads the configuration if its file was modified.
+    CheckFile,
+    GetDriverStatus {
+        result: mpsc::Sender<BTreeMap<String, DriverStatus>>,
+    },
+    /// Configuration of the drivers, running or not.
+    GetDrivers {
+        result: mpsc::Sender<Vec<DriverConfig>>,
+    },
+}
+
+#[cfg(feature = "cluster")]
+impl ractor::Message for Message {}
+
+impl CountedMessage for Message {
+    const ACTOR: &'static str = METRICS_ACTOR;
+}
+
+impl Message {
+    /// Name of the variant, for metrics.
+    pub fn kind(&self) -> &'static str {
+        match self {
+            Self::Reload {
+                ..
+            } => "Reload",
+            Self::CheckFile => "CheckFile",
+            Self::GetDriverStatus {
+                ..
+            } => "GetDriverStatus",
+            Self::GetDrivers {
+                ..
+            } => "GetDrivers",
+        }
+    }
+
+    pub fn get_driver_status() -> (Self, mpsc::Receiver<BTreeMap<String, DriverStatus>>) {
+        let (sender, receiver) = mpsc::channel(REPLY_CHANNEL_SIZE);
+        (
+            Self::GetDriverStatus {
+                result: sender,
+            },
+            receiver,
+        )
+    }
+
+    pub fn get_drivers() -> (Self, mpsc::Receiver<Vec<DriverConfig>>) {
+        let (sender, receiver) = mpsc::channel(REPLY_CHANNEL_SIZE);
+        (
+            Self::GetDrivers {
+                result: sender,
+            },
+            receiver,
+        )
+    }
+
+    pub fn reload() -> (Self, mpsc::Receiver<Result<ReloadReport, ReloadError>>) {
+        let (sender, receiver) = mpsc::channel(REPLY_CHANNEL_SIZE);
+        (
+            Self::Reload {
+                result: sender,
+            },
+            receiver,
+        )
+    }
+}
+
+#[cfg_attr(feature = "async-trait", ractor::async_trait)]
+impl Actor for ConfigActor {
+    type Msg = Message;
+    type State = ConfigState;
+    type Arguments = ConfigArguments;
+
+    async fn pre_start(
+        &self,
+        myself: ActorRef<Self::Msg>,
+        args: Self::Arguments,
+    ) -> Result<Self::State, ActorProcessingErr> {
+        tracing::info!("actor: Config started");
+        let mut state = ConfigState {
+            args: args.args,
+            config: ServerConfig::default(),
+            tag_repo: args.tag_repo,
+            set_log_filter: args.set_log_filter,
+            certificate: args.certificate,
+            drivers: HashMap::new(),
+            modified: None,
+        };
+        state.modified = state.file_modified();
+
+        for tag in &args.config.tags {
+            let (command, mut reply) =
+                tag::Message::create_tag(tag.name.as_str(), tag.meta.clone());
+            state
+                .tag_repo
+                .enqueue(command.with_origin(Origin::system(AUDIT_USER)))?;
+            match reply.recv().await {
+                Some(CreateTagResult::SuccessfullyCreated) => {
+                    tracing::info!("Created tag {}", tag.name)
+                },
+                Some(CreateTagResult::AlreadyExists) => {},
+                Some(CreateTagResult::Storage(e)) => {
+                    tracing::error!("Cannot save tag {}: {}", tag.name, e)
+                },
+                None => tracing::error!("Cannot create tag {}", tag.name),
+            }
+        }
+
+        // A driver that can't start doesn't keep the server from starting
+        state
+            .start_drivers(&args.config.drivers, &mut ReloadReport::default())
+            .await;
+        state.config = args.config;
+
+        if state.config_path().is_some() || state.certificate.is_some() {
+            myself.send_interval(WATCH_INTERVAL, || Message::CheckFile);
+        }
+        Ok(state)
+    }
+
+    async fn post_stop(
+        &self,
+        _myself: ActorRef<Self::Msg>,
+        state: &mut Self::State,
+    ) -> Result<(), ActorProcessingErr> {
+        tracing::info!("Stopping drivers");
+        for (_, driver) in state.drivers.drain() {
+            driver.cell.stop(None);
+        }
+        Ok(())
+    }
+
+    async fn handle(
+        &self,
+        _myself: ActorRef<Self::Msg>,
+        message: Self::Msg,
+        state: &mut Self::State,
+    ) -> Result<(), ActorProcessingErr> {
+        // CheckFile is sent by the actor itself
+        if !matches!(message, Message::CheckFile) {
+            metrics()
+                .mailbox_depth
+                .with_label_values(&[METRICS_ACTOR])
+                .dec();
+        }
+        let _timer = metrics()
+            .message_duration
+            .with_label_values(&[METRICS_ACTOR, message.kind()])
+            .start_timer();
+        match message {
+            Message::Reload {
+                result,
+            } => {
+                let report = state.reload().await;
+                if result.send(report).await.is_err() {
+                    tracing::error!("failed to send result to channel (receiver dropped)");
+                }
+            },
+            Message::CheckFile => {
+                state.reload_certificate();
+                let modified = state.file_modified();
+                if modified != state.modified {
+                    state.modified = modified;
+                    tracing::info!("Configuration file changed, reloading");
+                    if let Err(e) = state.reload().await {
+                        tracing::error!("Configuration reload failed: {:?}", e);
+                    }
+                }
+            },
+            Message::GetDriverStatus {
+                result,
+            } => {
+                let status = state
+                    .drivers
+                    .iter()
+                    .map(|(name, driver)| (name.clone(), driver.status()))
+                    .collect();
+                if result.send(status).await.is_err() {
+                    tracing::error!("failed to send result to channel (receiver dropped)");
+                }
+            },
+            Message::GetDrivers {
+                result,
+            } => {
+                if result.send(state.config.drivers.clone()).await.is_err() {
+                    tracing::error!("failed to send result to channel (receiver dropped)");
+                }
+            },
+        }
+        Ok(())
+    }
+}
+
+impl ConfigState {
+    fn config_path(&self) -> Option<PathBuf> {
+        match &self.args.config {
+            Some(path) => Some(path.clone()),
+            None => {
+                let path = PathBuf::from(DEFAULT_CONFIG_FILE);
+                path.exists().then_some(path)
+            },
+        }
+    }
+
+    fn file_modified(&self) -> Option<SystemTime> {
+        let path = self.config_path()?;
+        std::fs::metadata(path).and_then(|m| m.modified()).ok()
+    }
+
+    /// Replaces the TLS certificate if its files changed, returns whether it did.
+    fn reload_certificate(&self) -> bool {
+        let Some(certificate) = &self.certificate else {
+            return false;
+        };
+        match certificate.reload() {
+            Ok(reloaded) => {
+                if reloaded {
+                    tracing::info!("TLS certificate reloaded");
+                }
+                reloaded
+            },
+            Err(e) => {
+                tracing::error!("Keeping the current TLS certificate: {}", e);
+                false
+            },
+        }
+    }
+
+    /// Loads the configuration again and applies the difference to the running one.
+    ///
+    /// New and changed tags are applied first, since the drivers need them.
+    /// If a driver fails to start, these tag changes are undone and the
+    /// previous drivers restored. Tags and drivers are only removed once
+    /// everything else succeeded.
+    async fn reload(&mut self) -> Result<ReloadReport, ReloadError> {
+        let certificate_reloaded = self.reload_certificate();
+        let config = ServerConfig::load(&self.args).map_err(|e| {
+            tracing::error!("Keeping the running configuration: {}", e);
+            ReloadError::InvalidConfig(e.to_string())
+        })?;
+        let mut report = ReloadReport {
+            certificate_reloaded,
+            ..ReloadReport::default()
+        };
+
+        let plan = self.plan_tags(&config).await?;
+        self.apply_tag_changes(plan.upserts.clone()).await?;
+
+        let failed = self.start_drivers(&config.drivers, &mut report).await;
+        if !failed.is_empty() {
+            tracing::error!(
+                "Keeping the running configuration, drivers failed: {:?}",
+                failed
+            );
+            self.restore_drivers(&report).await;
+            if let Err(e) = self.apply_tag_changes(plan.undo).await {
+                tracing::error!("Failed to undo the tag changes: {:?}", e);
+            }
+            return Err(ReloadError::Drivers(failed));
+        }
+        self.stop_removed_drivers(&config.drivers, &mut report)
+            .await;
+        // Only tags deleted in the meantime can make this fail
+        if let Err(e) = self.apply_tag_changes(plan.deletes.clone()).await {
+            tracing::error!("Failed to delete tags: {:?}", e);
+        }
+
+        for change in plan.upserts.into_iter().chain(plan.deletes) {
+            match change {
+                TagChange::Create {
+                    name,
+                    ..
+                } => report.tags_created.push(name),
+                TagChange::Update {
+                    name,
+                    ..
+                } => report.tags_updated.push(name),
+                TagChange::Delete {
+                    name,
+                } => report.tags_deleted.push(name),
+            }
+        }
+
+        if config.log.filter != self.config.log.filter {
+            match (self.set_log_filter)(&config.log.filter) {
+                Ok(()) => report.log_filter_changed = true,
+                Err(e) => tracing::error!("Failed to change log filter: {}", e),
+            }
+        }
+        if config.http != self.config.http {
+            report.restart_required.push("http".to_string());
+        }
+        if config.grpc != self.config.grpc {
+            report.restart_required.push("grpc".to_string());
+        }
+        if config.opcua != self.config.opcua {
+            report.restart_required.push("opcua".to_string());
+        }
+        if config.iec104 != self.config.iec104 {
+            report.restart_required.push("iec104".to_string());
+        }
+        if config.dnp3 != self.config.dnp3 {
+            report.restart_required.push("dnp3".to_string());
+        }
+        if config.storage != self.config.storage {
+            report.restart_required.push("storage".to_string());
+        }
+        if config.auth != self.config.auth {
+            report.restart_required.push("auth".to_string());
+        }
+        if config.audit != self.config.audit {
+            report.restart_required.push("audit".to_string());
+        }
+
+        self.config = config;
+        tracing::info!("Configuration reloaded: {:?}", report);
+        Ok(report)
+    }
+
+    /// Splits the changes of the tags into the new and changed tags, the
+    /// changes undoing them and the deleted tags.
+    async fn plan_tags(&self, config: &ServerConfig) -> Result<TagPlan, ReloadError> {
+        let previous: BTreeMap<&str, &TagMeta> = self
+            .config
+            .tags
+            .iter()
+            .map(|tag| (tag.name.as_str(), &tag.meta))
+            .collect();
+
+        let mut plan = TagPlan::default();
+        for tag in &config.tags {
+            let name = TagName::from(tag.name.as_str());
+            match self.get_tag(&name).await? {
+                None => {
+                    plan.upserts.push(TagChange::Create {
+                        name: name.clone(),
+                        meta: tag.meta.clone(),
+                    });
+                    plan.undo.push(TagChange::Delete {
+                        name,
+                    });
+                },
+                Some(current)
+                    if previous
+                        .get(tag.name.as_str())
+                        .is_some_and(|meta| **meta != tag.meta) =>
+                {
+                    plan.upserts.push(TagChange::Update {
+                        name: name.clone(),
+                        meta: tag.meta.clone(),
+                    });
+                    plan.undo.push(TagChange::Update {
+                        name,
+                        meta: current.meta,
+                    });
+                },
+                Some(_) => {},
+            }
+        }
+        for name in previous.keys() {
+            let name = TagName::from(*name);
+            if !config.tags.iter().any(|tag| tag.name == name)
+                && self.get_tag(&name).await?.is_some()
+            {
+                plan.deletes.push(TagChange::Delete {
+                    name,
+                });
+            }
+        }
+        Ok(plan)
+    }
+
+    /// Applies all changes at once.
+    async fn apply_tag_changes(&self, changes: Vec<TagChange>) -> Result<(), ReloadError> {
+        if changes.is_empty() {
+            return Ok(());
+        }
+        let (command, mut reply) = tag::Message::apply_changes(changes);
+        self.tag_repo
+            .enqueue(command.with_origin(Origin::system(AUDIT_USER)))
+            .map_err(|e| ReloadError::Tags(e.to_string()))?;
+        reply
+            .recv()
+            .await
+            .ok_or_else(|| ReloadError::Tags("no response from tag actor".to_string()))?
+            .map_err(|e| ReloadError::Tags(format!("{e:?}")))
+    }
+
+    async fn get_tag(&self, name: &TagName) -> Result<Option<Tag>, ReloadError> {
+        let (command, mut reply) = tag::Message::get_tag(name.clone());
+        self.tag_repo
+            .enqueue(command)
+            .map_err(|e| ReloadError::Tags(e.to_string()))?;
+        reply
+            .recv()
+            .await
+            .map(Result::ok)
+            .ok_or_else(|| ReloadError::Tags("no response from tag actor".to_string()))
+    }
+
+    /// Starts new drivers and restarts changed ones, returning the drivers
+    /// that failed to start. The previous version of a changed driver that
+    /// fails keeps running.
+    async fn start_drivers(
+        &mut self,
+        drivers: &[DriverConfig],
+        report: &mut ReloadReport,
+    ) -> Vec<(String, String)> {
+        let mut failed = Vec::new();
+        for driver_config in drivers {
+            let name = driver_config.name().to_string();
+            let previous = self.previous_driver(&name);
+            let restart = match &previous {
+                Some(previous) if self.drivers.contains_key(&name) => {
+                    if previous == driver_config {
+                        continue;
+                    }
+                    self.stop_driver(&name).await;
+                    true
+                },
+                _ => false,
+            };
+
+            let data_dir = &self.config.storage.data_dir;
+            match driver::spawn(driver_config.clone(), self.tag_repo.clone(), data_dir).await {
+                Ok(driver) => {
+                    self.drivers.insert(name.clone(), driver);
+                    if restart {
+                        report.drivers_restarted.push(name);
+                    } else {
+                        report.drivers_started.push(name);
+                    }
+                },
+                Err(e) => {
+                    tracing::error!("Failed to start driver {}: {}", name, e);
+                    failed.push((name.clone(), e.to_string()));
+                    if let Some(previous) = previous.filter(|_| restart) {
+                        self.start_driver(previous).await;
+                    }
+                },
+            }
+        }
+        failed
+    }
+
+    /// Undoes [`Self::start_drivers`]: stops the started drivers and brings
+    /// back the previous version of the restarted ones.
+    async fn restore_drivers(&mut self, report: &ReloadReport) {
+        for name in &report.drivers_started {
+            self.stop_driver(name).await;
+        }
+        for name in &report.drivers_restarted {
+            self.stop_driver(name).await;
+            if let Some(previous) = self.previous_driver(name) {
+                self.start_driver(previous).await;
+            }
+        }
+    }
+
+    /// Stops the drivers that aren't in `drivers` any more.
+    async fn stop_removed_drivers(&mut self, drivers: &[DriverConfig], report: &mut ReloadReport) {
+        let removed: Vec<String> = self
+            .drivers
+            .keys()
+            .filter(|name| !drivers.iter().any(|driver| driver.name() == name.as_str()))
+            .cloned()
+            .collect();
+        for name in removed {
+            self.stop_driver(&name).await;
+            report.drivers_stopped.push(name);
+        }
+    }
+
+    /// Configuration of a driver in the running configuration.
+    fn previous_driver(&self, name: &str) -> Option<DriverConfig> {
+        self.config
+            .drivers
+            .iter()
+            .find(|driver| driver.name() == name)
+            .cloned()
+    }
+
+    async fn start_driver(&mut self, config: DriverConfig) {
+        let name = config.name().to_string();
+        let data_dir = &self.config.storage.data_dir;
+        match driver::spawn(config, self.tag_repo.clone(), data_dir).await {
+            Ok(driver) => {
+                self.drivers.insert(name, driver);
+            },
+            Err(e) => tracing::error!("Failed to start driver {} again: {}", name, e),
+        }
+    }
+
+    async fn stop_driver(&mut self, name: &str) {
+        if let Some(driver) = self.drivers.remove(name)
+            && let Err(e) = driver
+                .cell
+                .stop_and_wait(None, Some(DRIVER_STOP_TIMEOUT))
+                .await
+        {
+            tracing::warn!("Driver {} didn't stop cleanly: {}", name, e);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+
+    use super::*;
+    use crate::driver::testing::{self, wait_for};
+
+    /// Holds the name of a driver actor, so that the driver can't start.
+    struct Squatter;
+
+    #[cfg_attr(feature = "async-trait", ractor::async_trait)]
+    impl Actor for Squatter {
+        type Msg = ();
+        type State = ();
+        type Arguments = ();
+
+        async fn pre_start(
+            &self,
+            _myself: ActorRef<Self::Msg>,
+            _args: Self::Arguments,
+        ) -> Result<Self::State, ActorProcessingErr> {
+            Ok(())
+        }
+    }
+
+    fn s7(name: &str, poll_interval_ms: u64) -> String {
+        format!(
+            "[[drivers]]\nkind = \"s7\"\nname = \"{name}\"\naddress = \"127.0.0.1:1\"\n\
+             poll_interval_ms = {poll_interval_ms}\n"
+        )
+    }
+
+    fn tag(name: &str, description: &str) -> String {
+        format!(
+            "[[tags]]\nname = \"{name}\"\nunit = \"None\"\ndata_type = \"Float\"\n\
+             description = \"{description}\"\n"
+        )
+    }
+
+    async fn start(path: &Path) -> (ActorRef<Message>, ActorRef<tag::Message>) {
+        let args = Args {
+            config: Some(path.to_path_buf()),
+            ..Default::default()
+        };
+        let tag_repo = testing::tag_repo().await;
+        let (config, _) = Actor::spawn(
+            None,
+            ConfigActor,
+            ConfigArguments {
+                config: ServerConfig::load(&args).unwrap(),
+                args,
+                tag_repo: tag_repo.clone(),
+                set_log_filter: Box::new(|_| Ok(())),
+                certificate: None,
+            },
+        )
+        .await
+        .unwrap();
+        (config, tag_repo)
+    }
+
+    async fn ask<T>(
+        config: &ActorRef<Message>,
+        (command, mut reply): (Message, mpsc::Receiver<T>),
+    ) -> T {
+        config.enqueue(command).unwrap();
+        reply.recv().await.unwrap()
+    }
+
+    #[tokio::test]
+    async fn reloads_are_undone_if_a_driver_fails_to_start() {
+        let id = uuid::Uuid::new_v4();
+        let path = std::env::temp_dir().join(format!("rcada-config-{id}.toml"));
+        let (plc, blocked) = (format!("plc-{id}"), format!("blocked-{id}"));
+        std::fs::write(&path, tag("a", "before") + &s7(&plc, 1000)).unwrap();
+        let (config, tag_repo) = start(&path).await;
+        let (_squatter, _) = Actor::spawn(Some(format!("driver/{blocked}")), Squatter, ())
+            .await
+            .unwrap();
+
+        let changed = [
+            tag("a", "after"),
+            tag("b", ""),
+            s7(&plc, 500),
+            s7(&blocked, 1000),
+        ];
+        std::fs::write(&path, changed.concat()).unwrap();
+        match ask(&config, Message::reload()).await {
+            Err(ReloadError::Drivers(failed)) => {
+                assert_eq!(failed.len(), 1);
+                assert_eq!(failed[0].0, blocked);
+            },
+            result => panic!("{result:?}"),
+        }
+
+        let (command, mut reply) = tag::Message::get_tag("b");
+        tag_repo.enqueue(command).unwrap();
+        assert!(reply.recv().await.unwrap().is_err());
+        let a = wait_for(&tag_repo, "a", |_| true).await;
+        assert_eq!(a.meta.description, "before");
+        let status = ask(&config, Message::get_driver_status()).await;
+        assert_eq!(status.into_keys().collect::<Vec<_>>(), [plc]);
+        let drivers = ask(&config, Message::get_drivers()).await;
+        match drivers.as_slice() {
+            [DriverConfig::S7(driver)] => assert_eq!(driver.poll_interval_ms, 1000),
+            drivers => panic!("{drivers:?}"),
+        }
+
+        config.stop(None);
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[tokio::test]
+    async fn changes_of_the_file_are_applied() {
+        let path = std::env::temp_dir().join(format!("rcada-config-{}.toml", uuid::Uuid::new_v4()));
+        std::fs::write(&path, tag("a", "")).unwrap();
+        let (config, tag_repo) = start(&path).await;
+
+        // Without a reload, after the next check of the file
+        std::fs::write(&path, tag("a", "") + &tag("b", "added")).unwrap();
+        let b = wait_for(&tag_repo, "b", |_| true).await;
+        assert_eq!(b.meta.description, "added");
+
+        config.stop(None);
+        std::fs::remove_file(path).unwrap();
+    }
+}
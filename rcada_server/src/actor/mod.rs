@@ -1 +1,30 @@
+use ractor::{ActorRef, MessagingErr};
+
+use crate::metrics::metrics;
+
+pub mod config;
 pub mod tag;
+pub mod template;
+
+/// Message of an actor that counts the messages waiting for it in the
+/// `actor_mailbox_depth` metric.
+pub trait CountedMessage: ractor::Message {
+    /// Name of the actor in the metrics.
+    const ACTOR: &'static str;
+}
+
+/// Sends messages that the receiving actor counts until it handles them.
+pub trait Mailbox<M> {
+    /// [`ActorRef::send_message`] that counts `message` if it's delivered.
+    fn enqueue(&self, message: M) -> Result<(), MessagingErr<M>>;
+}
+
+impl<M: CountedMessage> Mailbox<M> for ActorRef<M> {
+    fn enqueue(&self, message: M) -> Result<(), MessagingErr<M>> {
+        let depth = metrics().mailbox_depth.with_label_values(&[M::ACTOR]);
+        // Counted before sending, so the actor can't take it out of the count
+        // before it's in
+        depth.inc();
+        self.send_message(message).inspect_err(|_| depth.dec())
+    }
+}
@@ -1,21 +1,37 @@
 use std::marker::PhantomData;
 use std::sync::Arc;
+use std::time::Duration;
 
 use ractor::ActorProcessingErr;
 use ractor::{Actor, ActorRef};
-use tokio::sync::mpsc;
+use tokio::sync::{broadcast, mpsc};
 
 use rcada_core::{
-    tag::{Tag, TagName, TagValue},
-    unit::Unit,
+    tag::{Tag, TagMeta, TagName, TagValue},
     value::DataType,
 };
 
 const REPLY_CHANNEL_SIZE: usize = 1;
 
-use crate::repository::tag::{
-    CreateTagResult, DeleteTagError, ReadTagError, TagRepository, UpdateValueError,
-    UpdateValueResult,
+/// Value updates kept for subscribers that fall behind, older ones are lost.
+const VALUE_UPDATES_CHANNEL_SIZE: usize = 1024;
+
+/// Name of this actor in the metrics.
+const METRICS_ACTOR: &str = "tag_repository";
+
+/// Value changes the repository held back are flushed this long after the
+/// first of them.
+const FLUSH_DELAY: Duration = Duration::from_secs(1);
+
+use crate::{
+    actor::CountedMessage,
+    audit::{AuditAction, AuditLog, Origin, outcome},
+    metrics::metrics,
+    repository::tag::{
+        AliasError, ApplyChangesError, CreateTagResult, DeleteTagError, ReadTagError,
+        RenameTagError, TagAlias, TagChange, TagMetaPatch, TagRepository, UpdateMetaError,
+        UpdateValueError, UpdateValueResult,
+    },
 };
 
 #[derive(Default)]
@@ -29,93 +45,367 @@ where
     R: TagRepository + Default + 'static,
 {
     type Msg = Message;
-    type State = Arc<R>;
-    type Arguments = R;
+    type State = TagActorState<R>;
+    type Arguments = (R, AuditLog);
 
     async fn pre_start(
         &self,
         _myself: ActorRef<Self::Msg>,
-        repo: Self::Arguments,
+        (repo, audit): Self::Arguments,
     ) -> Result<Self::State, ActorProcessingErr> {
         tracing::info!("actor: TagRepository started");
-        Ok(Arc::new(repo))
+        metrics().tags.set(repo.tag_count() as i64);
+        Ok(TagActorState {
+            repo: Arc::new(repo),
+            audit,
+            value_updates: broadcast::channel(VALUE_UPDATES_CHANNEL_SIZE).0,
+            flush_scheduled: false,
+        })
     }
 
-    async fn handle(
+    async fn post_stop(
         &self,
         _myself: ActorRef<Self::Msg>,
+        state: &mut Self::State,
+    ) -> Result<(), ActorProcessingErr> {
+        state.repo.flush();
+        Ok(())
+    }
+
+    async fn handle(
+        &self,
+        myself: ActorRef<Self::Msg>,
         message: Self::Msg,
         state: &mut Self::State,
     ) -> Result<(), ActorProcessingErr> {
         tracing::info!("handling message {message:?}");
-        let ok = match message {
+        // Flush is sent by the actor itself and isn't counted
+        if !matches!(message, Message::Flush) {
+            metrics()
+                .mailbox_depth
+                .with_label_values(&[METRICS_ACTOR])
+                .dec();
+        }
+        let _timer = metrics()
+            .message_duration
+            .with_label_values(&[METRICS_ACTOR, message.kind()])
+            .start_timer();
+        let kind = message.kind();
+        let delivered = match message {
             Message::CreateTag {
                 name,
-                unit,
-                data_type,
+                meta,
+                origin,
                 result,
-            } => result
-                .send(state.create_tag(name, unit, data_type))
-                .await
-                .is_ok(),
+            } => {
+                let new = json(&meta);
+                let created = state.repo.create_tag(name.clone(), meta);
+                let outcome = match &created {
+                    CreateTagResult::SuccessfullyCreated => "ok".to_string(),
+                    CreateTagResult::AlreadyExists => "AlreadyExists".to_string(),
+                    CreateTagResult::Storage(e) => e.clone(),
+                };
+                state
+                    .audit
+                    .record(&origin, AuditAction::CreateTag, &name, None, new, outcome);
+                result.send(created).await.is_ok()
+            },
             Message::UpdateTagValue {
                 name,
                 value,
+                origin,
                 result,
-            } => result
-                .send(state.update_tag_value(name, value))
-                .await
-                .is_ok(),
+            } => {
+                let audited = state.audit.records_value_write(&origin);
+                let old = audited.then(|| state.repo.get_tag(&name).ok()).flatten();
+                let new = audited.then(|| json(&value)).flatten();
+                // Checked here rather than by the callers, so that a metadata
+                // change can't slip in between the check and the write
+                let read_only = !origin.system
+                    && state
+                        .repo
+                        .get_tag(&name)
+                        .is_ok_and(|tag| tag.meta.read_only);
+                let updated = if read_only {
+                    Err(UpdateValueError::ReadOnly)
+                } else {
+                    state.repo.update_tag_value(name.clone(), value)
+                };
+                match &updated {
+                    Ok(_) => metrics().tag_updates.inc(),
+                    Err(e) => metrics()
+                        .rejected_updates
+                        .with_label_values(&[e.kind()])
+                        .inc(),
+                }
+                if audited {
+                    let tag = old.as_ref().map_or(&name, |tag| &tag.name);
+                    state.audit.record(
+                        &origin,
+                        AuditAction::UpdateValue,
+                        tag,
+                        old.as_ref().and_then(|tag| json(&tag.value)),
+                        new,
+                        outcome(&updated),
+                    );
+                }
+                if matches!(updated, Ok(UpdateValueResult::Updated))
+                    && state.value_updates.receiver_count() > 0
+                    && let Ok(tag) = state.repo.get_tag(&name)
+                {
+                    // Nobody listening is not an error
+                    let _ = state.value_updates.send(tag);
+                }
+                if matches!(updated, Ok(UpdateValueResult::Updated)) && !state.flush_scheduled {
+                    state.flush_scheduled = true;
+                    myself.send_after(FLUSH_DELAY, || Message::Flush);
+                }
+                result.send(updated).await.is_ok()
+            },
+            Message::UpdateTagMeta {
+                name,
+                patch,
+                origin,
+                result,
+            } => {
+                let old = state.repo.get_tag(&name).ok();
+                let updated = state.repo.update_tag_meta(&name, patch);
+                state.audit.record(
+                    &origin,
+                    AuditAction::UpdateMeta,
+                    old.as_ref().map_or(&name, |tag| &tag.name),
+                    old.as_ref().and_then(|tag| json(&tag.meta)),
+                    updated.as_ref().ok().and_then(|tag| json(&tag.meta)),
+                    outcome(&updated),
+                );
+                result.send(updated).await.is_ok()
+            },
             Message::DeleteTag {
                 name,
+                origin,
                 result,
-            } => result.send(state.delete_tag(&name)).await.is_ok(),
+            } => {
+                let old = state.repo.get_tag(&name).ok();
+                let deleted = state.repo.delete_tag(&name);
+                state.audit.record(
+                    &origin,
+                    AuditAction::DeleteTag,
+                    old.as_ref().map_or(&name, |tag| &tag.name),
+                    old.as_ref().and_then(json),
+                    None,
+                    outcome(&deleted),
+                );
+                result.send(deleted).await.is_ok()
+            },
+            Message::RenameTag {
+                name,
+                new_name,
+                keep_alias,
+                origin,
+                result,
+            } => {
+                let new = json(&new_name);
+                let renamed = state.repo.rename_tag(&name, new_name, keep_alias);
+                state.audit.record(
+                    &origin,
+                    AuditAction::RenameTag,
+                    &name,
+                    json(&name),
+                    new,
+                    outcome(&renamed),
+                );
+                result.send(renamed).await.is_ok()
+            },
+            Message::CreateAlias {
+                alias,
+                target,
+                origin,
+                result,
+            } => {
+                let created = state.repo.create_alias(alias.clone(), &target);
+                state.audit.record(
+                    &origin,
+                    AuditAction::CreateAlias,
+                    &target,
+                    None,
+                    json(&alias),
+                    outcome(&created),
+                );
+                result.send(created).await.is_ok()
+            },
+            Message::DeleteAlias {
+                alias,
+                origin,
+                result,
+            } => {
+                let target = state
+                    .repo
+                    .get_all_aliases()
+                    .into_iter()
+                    .find(|a| a.alias == alias)
+                    .map_or_else(|| alias.clone(), |a| a.target);
+                let deleted = state.repo.delete_alias(&alias);
+                state.audit.record(
+                    &origin,
+                    AuditAction::DeleteAlias,
+                    &target,
+                    json(&alias),
+                    None,
+                    outcome(&deleted),
+                );
+                result.send(deleted).await.is_ok()
+            },
+            Message::GetAllAliases {
+                result,
+            } => result.send(state.repo.get_all_aliases()).await.is_ok(),
+            Message::ApplyChanges {
+                changes,
+                origin,
+                result,
+            } => {
+                let old: Vec<Option<Tag>> = changes
+                    .iter()
+                    .map(|change| state.repo.get_tag(change.name()).ok())
+                    .collect();
+                let applied = state.repo.apply_changes(changes.clone());
+                let outcome = outcome(&applied);
+                for (change, old) in changes.into_iter().zip(old) {
+                    let old_meta = old.as_ref().and_then(|tag| json(&tag.meta));
+                    let (action, name, new) = match change {
+                        TagChange::Create {
+                            name,
+                            meta,
+                        } => (AuditAction::CreateTag, name, json(&meta)),
+                        TagChange::Update {
+                            name,
+                            meta,
+                        } => (AuditAction::UpdateMeta, name, json(&meta)),
+                        TagChange::Delete {
+                            name,
+                        } => (AuditAction::DeleteTag, name, None),
+                    };
+                    state
+                        .audit
+                        .record(&origin, action, &name, old_meta, new, outcome.clone());
+                }
+                result.send(applied).await.is_ok()
+            },
             Message::TagExists {
                 name,
                 result,
-            } => result.send(state.is_tag_exists(&name)).await.is_ok(),
+            } => result.send(state.repo.is_tag_exists(&name)).await.is_ok(),
             Message::GetTag {
                 name,
                 result,
-            } => result.send(state.get_tag(&name)).await.is_ok(),
+            } => result.send(state.repo.get_tag(&name)).await.is_ok(),
             Message::GetAllTags {
                 result,
-            } => result.send(state.get_all_tags()).await.is_ok(),
+            } => result.send(state.repo.get_all_tags()).await.is_ok(),
             Message::GetTagDataType {
                 name,
                 result,
-            } => result.send(state.get_tag_data_type(&name)).await.is_ok(),
+            } => result
+                .send(state.repo.get_tag_data_type(&name))
+                .await
+                .is_ok(),
             Message::GetTagValue {
                 name,
                 result,
-            } => result.send(state.get_tag_value(&name)).await.is_ok(),
+            } => result.send(state.repo.get_tag_value(&name)).await.is_ok(),
+            Message::Subscribe {
+                result,
+            } => result.send(state.value_updates.subscribe()).await.is_ok(),
+            Message::Flush => {
+                state.flush_scheduled = false;
+                state.repo.flush();
+                true
+            },
+            Message::Ping {
+                result,
+            } => {
+                // The readiness check may have stopped waiting, that's not an error
+                let _ = result.send(()).await;
+                true
+            },
         };
-        if ok {
-            Ok(())
-        } else {
-            tracing::error!("failed to send result to channel (receiver dropped)");
-            Err("Cannot send result to channel".into())
+        metrics().tags.set(state.repo.tag_count() as i64);
+        metrics()
+            .value_subscribers
+            .set(state.value_updates.receiver_count() as i64);
+        // The change is made either way, the caller just stopped waiting for
+        // it, e.g. after a timeout. That's no reason to stop the actor.
+        if !delivered {
+            tracing::warn!("{kind}: failed to send result to channel (receiver dropped)");
         }
+        Ok(())
     }
 }
 
+pub struct TagActorState<R> {
+    repo: Arc<R>,
+    audit: AuditLog,
+    value_updates: broadcast::Sender<Tag>,
+    /// A [`Message::Flush`] is on its way.
+    flush_scheduled: bool,
+}
+
+fn json(value: &impl serde::Serialize) -> Option<serde_json::Value> {
+    serde_json::to_value(value).ok()
+}
+
+/// Changes carry the [`Origin`] they're audited with, which is the server
+/// itself unless set with [`Message::with_origin`].
 #[derive(Debug)]
 pub enum Message {
     CreateTag {
         name: TagName,
-        unit: Unit,
-        data_type: DataType,
+        meta: TagMeta,
+        origin: Origin,
         result: mpsc::Sender<CreateTagResult>,
     },
     UpdateTagValue {
         name: TagName,
         value: TagValue,
+        origin: Origin,
         result: mpsc::Sender<Result<UpdateValueResult, UpdateValueError>>,
     },
+    UpdateTagMeta {
+        name: TagName,
+        patch: TagMetaPatch,
+        origin: Origin,
+        result: mpsc::Sender<Result<Tag, UpdateMetaError>>,
+    },
     DeleteTag {
         name: TagName,
+        origin: Origin,
         result: mpsc::Sender<Result<(), DeleteTagError>>,
     },
+    RenameTag {
+        name: TagName,
+        new_name: TagName,
+        keep_alias: bool,
+        origin: Origin,
+        result: mpsc::Sender<Result<Tag, RenameTagError>>,
+    },
+    CreateAlias {
+        alias: TagName,
+        target: TagName,
+        origin: Origin,
+        result: mpsc::Sender<Result<TagAlias, AliasError>>,
+    },
+    DeleteAlias {
+        alias: TagName,
+        origin: Origin,
+        result: mpsc::Sender<Result<(), AliasError>>,
+    },
+    GetAllAliases {
+        result: mpsc::Sender<Vec<TagAlias>>,
+    },
+    ApplyChanges {
+        changes: Vec<TagChange>,
+        origin: Origin,
+        result: mpsc::Sender<Result<(), ApplyChangesError>>,
+    },
     TagExists {
         name: TagName,
         result: mpsc::Sender<bool>,
@@ -135,23 +425,132 @@ pub enum Message {
         name: TagName,
         result: mpsc::Sender<Option<TagValue>>,
     },
+    /// Receives every tag whose value is updated from now on, with the new
+    /// value. Ignored writes and metadata changes are not sent.
+    Subscribe {
+        result: mpsc::Sender<broadcast::Receiver<Tag>>,
+    },
+    /// Answers as soon as it's handled, to check that the actor is responsive.
+    Ping {
+        result: mpsc::Sender<()>,
+    },
+    /// Writes the value changes the repository held back.
+    Flush,
 }
 
 #[cfg(feature = "cluster")]
 impl ractor::Message for Message {}
 
+impl CountedMessage for Message {
+    const ACTOR: &'static str = METRICS_ACTOR;
+}
+
 impl Message {
+    /// Name of the variant, for metrics.
+    pub fn kind(&self) -> &'static str {
+        match self {
+            Self::CreateTag {
+                ..
+            } => "CreateTag",
+            Self::UpdateTagValue {
+                ..
+            } => "UpdateTagValue",
+            Self::UpdateTagMeta {
+                ..
+            } => "UpdateTagMeta",
+            Self::DeleteTag {
+                ..
+            } => "DeleteTag",
+            Self::RenameTag {
+                ..
+            } => "RenameTag",
+            Self::CreateAlias {
+                ..
+            } => "CreateAlias",
+            Self::DeleteAlias {
+                ..
+            } => "DeleteAlias",
+            Self::GetAllAliases {
+                ..
+            } => "GetAllAliases",
+            Self::ApplyChanges {
+                ..
+            } => "ApplyChanges",
+            Self::TagExists {
+                ..
+            } => "TagExists",
+            Self::GetTag {
+                ..
+            } => "GetTag",
+            Self::GetAllTags {
+                ..
+            } => "GetAllTags",
+            Self::GetTagDataType {
+                ..
+            } => "GetTagDataType",
+            Self::GetTagValue {
+                ..
+            } => "GetTagValue",
+            Self::Subscribe {
+                ..
+            } => "Subscribe",
+            Self::Ping {
+                ..
+            } => "Ping",
+            Self::Flush => "Flush",
+        }
+    }
+
+    /// Sets who asked for a change, other messages are returned unchanged.
+    pub fn with_origin(mut self, new_origin: Origin) -> Self {
+        match &mut self {
+            Self::CreateTag {
+                origin,
+                ..
+            }
+            | Self::UpdateTagValue {
+                origin,
+                ..
+            }
+            | Self::UpdateTagMeta {
+                origin,
+                ..
+            }
+            | Self::DeleteTag {
+                origin,
+                ..
+            }
+            | Self::RenameTag {
+                origin,
+                ..
+            }
+            | Self::CreateAlias {
+                origin,
+                ..
+            }
+            | Self::DeleteAlias {
+                origin,
+                ..
+            }
+            | Self::ApplyChanges {
+                origin,
+                ..
+            } => *origin = new_origin,
+            _ => {},
+        }
+        self
+    }
+
     pub fn create_tag(
         name: impl Into<TagName>,
-        unit: Unit,
-        data_type: DataType,
+        meta: TagMeta,
     ) -> (Self, mpsc::Receiver<CreateTagResult>) {
         let (sender, receiver) = mpsc::channel(REPLY_CHANNEL_SIZE);
         (
             Self::CreateTag {
                 name: name.into(),
-                unit,
-                data_type,
+                meta,
+                origin: Origin::default(),
                 result: sender,
             },
             receiver,
@@ -170,6 +569,23 @@ impl Message {
             Self::UpdateTagValue {
                 name: name.into(),
                 value,
+                origin: Origin::default(),
+                result: sender,
+            },
+            receiver,
+        )
+    }
+
+    pub fn update_tag_meta(
+        name: impl Into<TagName>,
+        patch: TagMetaPatch,
+    ) -> (Self, mpsc::Receiver<Result<Tag, UpdateMetaError>>) {
+        let (sender, receiver) = mpsc::channel(REPLY_CHANNEL_SIZE);
+        (
+            Self::UpdateTagMeta {
+                name: name.into(),
+                patch,
+                origin: Origin::default(),
                 result: sender,
             },
             receiver,
@@ -183,6 +599,79 @@ impl Message {
         (
             Self::DeleteTag {
                 name: name.into(),
+                origin: Origin::default(),
+                result: sender,
+            },
+            receiver,
+        )
+    }
+
+    pub fn rename_tag(
+        name: impl Into<TagName>,
+        new_name: impl Into<TagName>,
+        keep_alias: bool,
+    ) -> (Self, mpsc::Receiver<Result<Tag, RenameTagError>>) {
+        let (sender, receiver) = mpsc::channel(REPLY_CHANNEL_SIZE);
+        (
+            Self::RenameTag {
+                name: name.into(),
+                new_name: new_name.into(),
+                keep_alias,
+                origin: Origin::default(),
+                result: sender,
+            },
+            receiver,
+        )
+    }
+
+    pub fn create_alias(
+        alias: impl Into<TagName>,
+        target: impl Into<TagName>,
+    ) -> (Self, mpsc::Receiver<Result<TagAlias, AliasError>>) {
+        let (sender, receiver) = mpsc::channel(REPLY_CHANNEL_SIZE);
+        (
+            Self::CreateAlias {
+                alias: alias.into(),
+                target: target.into(),
+                origin: Origin::default(),
+                result: sender,
+            },
+            receiver,
+        )
+    }
+
+    pub fn delete_alias(
+        alias: impl Into<TagName>,
+    ) -> (Self, mpsc::Receiver<Result<(), AliasError>>) {
+        let (sender, receiver) = mpsc::channel(REPLY_CHANNEL_SIZE);
+        (
+            Self::DeleteAlias {
+                alias: alias.into(),
+                origin: Origin::default(),
+                result: sender,
+            },
+            receiver,
+        )
+    }
+
+    pub fn get_all_aliases() -> (Self, mpsc::Receiver<Vec<TagAlias>>) {
+        let (sender, receiver) = mpsc::channel(REPLY_CHANNEL_SIZE);
+        (
+            Self::GetAllAliases {
+                result: sender,
+            },
+            receiver,
+        )
+    }
+
+    pub fn apply_changes(
+        changes: Vec<TagChange>,
+    ) -> (Self, mpsc::Receiver<Result<(), ApplyChangesError>>) {
+        let (sender, receiver) = mpsc::channel(REPLY_CHANNEL_SIZE);
+        (
+            Self::ApplyChanges {
+                changes,
+                origin: Origin::default(),
                 result: sender,
             },
             receiver,
@@ -242,4 +731,24 @@ impl Message {
             receiver,
         )
     }
+
+    pub fn subscribe() -> (Self, mpsc::Receiver<broadcast::Receiver<Tag>>) {
+        let (sender, receiver) = mpsc::channel(REPLY_CHANNEL_SIZE);
+        (
+            Self::Subscribe {
+                result: sender,
+            },
+            receiver,
+        )
+    }
+
+    pub fn ping() -> (Self, mpsc::Receiver<()>) {
+        let (sender, receiver) = mpsc::channel(REPLY_CHANNEL_SIZE);
+        (
+            Self::Ping {
+                result: sender,
+            },
+            receiver,
+        )
+    }
 }
@@ -0,0 +1,792 @@
+use std::marker::PhantomData;
+use std::sync::Arc;
+
+use ractor::ActorProcessingErr;
+use ractor::{Actor, ActorRef};
+use serde::{Deserialize, Serialize};
+use tokio::sync::mpsc;
+use utoipa::ToSchema;
+
+use rcada_core::tag::TagName;
+
+const REPLY_CHANNEL_SIZE: usize = 1;
+
+/// Name of this actor in the metrics.
+const METRICS_ACTOR: &str = "template_repository";
+
+use crate::{
+    actor::{CountedMessage, Mailbox, tag},
+    audit::Origin,
+    metrics::metrics,
+    repository::{
+        tag::{CreateTagResult, DeleteTagError, TagMetaPatch},
+        template::{TagTemplate, TemplateError, TemplateName, TemplateRepository},
+    },
+};
+
+/// Manages tag templates and keeps the tags of their instances in sync
+/// through the tag repository actor.
+#[derive(Default)]
+pub struct TemplateRepositoryActor<R: TemplateRepository + Default> {
+    _repo: PhantomData<R>,
+}
+
+pub struct TemplateActorState<R> {
+    repo: Arc<R>,
+    tags: ActorRef<tag::Message>,
+    /// Origin of the message being handled, passed on to the tag actor.
+    origin: Origin,
+}
+
+/// Changes applied to the tags of one instance after a template update.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize, ToSchema)]
+pub struct InstanceUpdate {
+    #[schema(value_type = String)]
+    pub prefix: TagName,
+    #[schema(value_type = Vec<String>)]
+    pub created: Vec<TagName>,
+    #[schema(value_type = Vec<String>)]
+    pub updated: Vec<TagName>,
+    #[schema(value_type = Vec<String>)]
+    pub deleted: Vec<TagName>,
+    #[schema(value_type = Vec<(String, String)>)]
+    pub failed: Vec<(TagName, String)>,
+}
+
+#[cfg_attr(feature = "async-trait", ractor::async_trait)]
+impl<R> Actor for TemplateRepositoryActor<R>
+where
+    R: TemplateRepository + Default + 'static,
+{
+    type Msg = Message;
+    type State = TemplateActorState<R>;
+    type Arguments = (R, ActorRef<tag::Message>);
+
+    async fn pre_start(
+        &self,
+        _myself: ActorRef<Self::Msg>,
+        (repo, tags): Self::Arguments,
+    ) -> Result<Self::State, ActorProcessingErr> {
+        tracing::info!("actor: TemplateRepository started");
+        Ok(TemplateActorState {
+            repo: Arc::new(repo),
+            tags,
+            origin: Origin::default(),
+        })
+    }
+
+    async fn handle(
+        &self,
+        _myself: ActorRef<Self::Msg>,
+        message: Self::Msg,
+        state: &mut Self::State,
+    ) -> Result<(), ActorProcessingErr> {
+        tracing::info!("handling message {message:?}");
+        metrics()
+            .mailbox_depth
+            .with_label_values(&[METRICS_ACTOR])
+            .dec();
+        let _timer = metrics()
+            .message_duration
+            .with_label_values(&[METRICS_ACTOR, message.kind()])
+            .start_timer();
+        let kind = message.kind();
+        let delivered = match message {
+            Message::CreateTemplate {
+                template,
+                result,
+            } => result
+                .send(state.repo.create_template(template))
+                .await
+                .is_ok(),
+            Message::UpdateTemplate {
+                template,
+                origin,
+                result,
+            } => {
+                state.origin = origin;
+                result
+                    .send(state.update_template(template).await)
+                    .await
+                    .is_ok()
+            },
+            Message::GetTemplate {
+                name,
+                result,
+            } => result.send(state.repo.get_template(&name)).await.is_ok(),
+            Message::GetAllTemplates {
+                result,
+            } => result.send(state.repo.get_all_templates()).await.is_ok(),
+            Message::DeleteTemplate {
+                name,
+                result,
+            } => result.send(state.repo.delete_template(&name)).await.is_ok(),
+            Message::Instantiate {
+                template,
+                prefix,
+                origin,
+                result,
+            } => {
+                state.origin = origin;
+                result
+                    .send(state.instantiate(&template, prefix).await)
+                    .await
+                    .is_ok()
+            },
+            Message::AdoptInstance {
+                template,
+                prefix,
+                origin,
+                result,
+            } => {
+                state.origin = origin;
+                result
+                    .send(state.adopt_instance(&template, prefix).await)
+                    .await
+                    .is_ok()
+            },
+            Message::RemoveInstance {
+                template,
+                prefix,
+                origin,
+                result,
+            } => {
+                state.origin = origin;
+                result
+                    .send(state.remove_instance(&template, &prefix).await)
+                    .await
+                    .is_ok()
+            },
+            Message::GetInstances {
+                template,
+                result,
+            } => result
+                .send(state.repo.get_instances(&template))
+                .await
+                .is_ok(),
+        };
+        // The change is made either way, the caller just stopped waiting for
+        // it, e.g. after a timeout. That's no reason to stop the actor.
+        if !delivered {
+            tracing::warn!("{kind}: failed to send result to channel (receiver dropped)");
+        }
+        Ok(())
+    }
+}
+
+impl<R: TemplateRepository> TemplateActorState<R> {
+    async fn call<T>(
+        &self,
+        (command, mut reply): (tag::Message, mpsc::Receiver<T>),
+    ) -> Result<T, TemplateError> {
+        self.tags
+            .enqueue(command.with_origin(self.origin.clone()))
+            .map_err(|e| TemplateError::TagActor(e.to_string()))?;
+        reply
+            .recv()
+            .await
+            .ok_or_else(|| TemplateError::TagActor("no response from tag actor".into()))
+    }
+
+    /// Creates every tag of the template under `prefix`. Nothing is created
+    /// if any of the tags already exists.
+    async fn instantiate(
+        &self,
+        template: &TemplateName,
+        prefix: TagName,
+    ) -> Result<Vec<TagName>, TemplateError> {
+        let template = self.repo.get_template(template)?;
+        if self.repo.get_instances(&template.name)?.contains(&prefix) {
+            return Err(TemplateError::InstanceAlreadyExists);
+        }
+
+        let mut existing = Vec::new();
+        for point in &template.points {
+            let name = point.tag_name(&prefix);
+            if self.call(tag::Message::tag_exists(name.clone())).await? {
+                existing.push(name);
+            }
+        }
+        if !existing.is_empty() {
+            return Err(TemplateError::TagsAlreadyExist(existing));
+        }
+
+        let mut created = Vec::new();
+        for point in &template.points {
+            let name = point.tag_name(&prefix);
+            let meta = point.instance_meta(&prefix);
+            match self
+                .call(tag::Message::create_tag(name.clone(), meta))
+                .await
+            {
+                Ok(CreateTagResult::SuccessfullyCreated) => created.push(name),
+                result => {
+                    self.delete_tags(&created).await;
+                    return match result {
+                        Err(e) => Err(e),
+                        Ok(CreateTagResult::Storage(e)) => Err(TemplateError::Storage(e)),
+                        Ok(_) => Err(TemplateError::TagsAlreadyExist(vec![name])),
+                    };
+                },
+            }
+        }
+
+        self.repo.add_instance(&template.name, prefix)?;
+        Ok(created)
+    }
+
+    /// Registers an instance whose tags may already exist, e.g. when a
+    /// configuration is imported. Only the missing tags are created, existing
+    /// tags keep their metadata.
+    async fn adopt_instance(
+        &self,
+        template: &TemplateName,
+        prefix: TagName,
+    ) -> Result<Vec<TagName>, TemplateError> {
+        let template = self.repo.get_template(template)?;
+        if self.repo.get_instances(&template.name)?.contains(&prefix) {
+            return Err(TemplateError::InstanceAlreadyExists);
+        }
+
+        let mut created = Vec::new();
+        for point in &template.points {
+            let name = point.tag_name(&prefix);
+            if self.call(tag::Message::tag_exists(name.clone())).await? {
+                continue;
+            }
+            let meta = point.instance_meta(&prefix);
+            match self
+                .call(tag::Message::create_tag(name.clone(), meta))
+                .await
+            {
+                Ok(CreateTagResult::SuccessfullyCreated) => created.push(name),
+                result => {
+                    self.delete_tags(&created).await;
+                    return match result {
+                        Err(e) => Err(e),
+                        Ok(CreateTagResult::Storage(e)) => Err(TemplateError::Storage(e)),
+                        Ok(_) => Err(TemplateError::TagsAlreadyExist(vec![name])),
+                    };
+                },
+            }
+        }
+
+        if let Err(e) = self.repo.add_instance(&template.name, prefix) {
+            self.delete_tags(&created).await;
+            return Err(e);
+        }
+        Ok(created)
+    }
+
+    /// Deletes the tags of an instance and forgets it.
+    async fn remove_instance(
+        &self,
+        template: &TemplateName,
+        prefix: &TagName,
+    ) -> Result<Vec<TagName>, TemplateError> {
+        let template = self.repo.get_template(template)?;
+        if !self.repo.get_instances(&template.name)?.contains(prefix) {
+            return Err(TemplateError::InstanceNotFound);
+        }
+
+        let names: Vec<TagName> = template
+            .points
+            .iter()
+            .map(|point| point.tag_name(prefix))
+            .collect();
+        let deleted = self.delete_tags(&names).await;
+        self.repo.remove_instance(&template.name, prefix)?;
+        Ok(deleted)
+    }
+
+    /// Replaces the template and applies the difference to every instance.
+    async fn update_template(
+        &self,
+        template: TagTemplate,
+    ) -> Result<Vec<InstanceUpdate>, TemplateError> {
+        let previous = self.repo.update_template(template.clone())?;
+        let instances = self.repo.get_instances(&template.name)?;
+
+        let mut updates = Vec::new();
+        for prefix in instances {
+            let mut update = InstanceUpdate {
+                prefix: prefix.clone(),
+                ..Default::default()
+            };
+
+            for old in &previous.points {
+                if !template.points.iter().any(|point| point.name == old.name) {
+                    let name = old.tag_name(&prefix);
+                    match self.call(tag::Message::delete_tag(name.clone())).await {
+                        Ok(Ok(())) | Ok(Err(DeleteTagError::TagNameNotFound)) => {
+                            update.deleted.push(name)
+                        },
+                        Ok(Err(DeleteTagError::Storage(e))) => update.failed.push((name, e)),
+                        Err(e) => update.failed.push((name, format!("{e:?}"))),
+                    }
+                }
+            }
+
+            for point in &template.points {
+                let name = point.tag_name(&prefix);
+                let meta = point.instance_meta(&prefix);
+                match previous.points.iter().find(|old| old.name == point.name) {
+                    Some(old) if old.meta == point.meta => {},
+                    Some(old) => {
+                        let patch = TagMetaPatch::replacing(&old.instance_meta(&prefix), meta);
+                        let command = tag::Message::update_tag_meta(name.clone(), patch);
+                        match self.call(command).await {
+                            Ok(Ok(_)) => update.updated.push(name),
+                            Ok(Err(e)) => update.failed.push((name, format!("{e:?}"))),
+                            Err(e) => update.failed.push((name, format!("{e:?}"))),
+                        }
+                    },
+                    None => match self
+                        .call(tag::Message::create_tag(name.clone(), meta))
+                        .await
+                    {
+                        Ok(CreateTagResult::SuccessfullyCreated) => update.created.push(name),
+                        Ok(result) => update.failed.push((name, format!("{result:?}"))),
+                        Err(e) => update.failed.push((name, format!("{e:?}"))),
+                    },
+                }
+            }
+
+            if !update.failed.is_empty() {
+                tracing::warn!(
+                    "template {}: failed to update instance {}: {:?}",
+                    template.name,
+                    prefix,
+                    update.failed
+                );
+            }
+            updates.push(update);
+        }
+
+        Ok(updates)
+    }
+
+    async fn delete_tags(&self, names: &[TagName]) -> Vec<TagName> {
+        let mut deleted = Vec::new();
+        for name in names {
+            match self.call(tag::Message::delete_tag(name.clone())).await {
+                Ok(Ok(())) => deleted.push(name.clone()),
+                Ok(Err(DeleteTagError::TagNameNotFound)) => {},
+                Ok(Err(DeleteTagError::Storage(e))) => {
+                    tracing::error!("failed to delete tag {name}: {e}")
+                },
+                Err(e) => tracing::error!("failed to delete tag {name}: {e:?}"),
+            }
+        }
+        deleted
+    }
+}
+
+#[derive(Debug)]
+pub enum Message {
+    CreateTemplate {
+        template: TagTemplate,
+        result: mpsc::Sender<Result<(), TemplateError>>,
+    },
+    UpdateTemplate {
+        template: TagTemplate,
+        origin: Origin,
+        result: mpsc::Sender<Result<Vec<InstanceUpdate>, TemplateError>>,
+    },
+    GetTemplate {
+        name: TemplateName,
+        result: mpsc::Sender<Result<TagTemplate, TemplateError>>,
+    },
+    GetAllTemplates {
+        result: mpsc::Sender<Vec<TagTemplate>>,
+    },
+    DeleteTemplate {
+        name: TemplateName,
+        result: mpsc::Sender<Result<(), TemplateError>>,
+    },
+    Instantiate {
+        template: TemplateName,
+        prefix: TagName,
+        origin: Origin,
+        result: mpsc::Sender<Result<Vec<TagName>, TemplateError>>,
+    },
+    AdoptInstance {
+        template: TemplateName,
+        prefix: TagName,
+        origin: Origin,
+        result: mpsc::Sender<Result<Vec<TagName>, TemplateError>>,
+    },
+    RemoveInstance {
+        template: TemplateName,
+        prefix: TagName,
+        origin: Origin,
+        result: mpsc::Sender<Result<Vec<TagName>, TemplateError>>,
+    },
+    GetInstances {
+        template: TemplateName,
+        result: mpsc::Sender<Result<Vec<TagName>, TemplateError>>,
+    },
+}
+
+#[cfg(feature = "cluster")]
+impl ractor::Message for Message {}
+
+impl CountedMessage for Message {
+    const ACTOR: &'static str = METRICS_ACTOR;
+}
+
+impl Message {
+    /// Name of the variant, for metrics.
+    pub fn kind(&self) -> &'static str {
+        match self {
+            Self::CreateTemplate {
+                ..
+            } => "CreateTemplate",
+            Self::UpdateTemplate {
+                ..
+            } => "UpdateTemplate",
+            Self::GetTemplate {
+                ..
+            } => "GetTemplate",
+            Self::GetAllTemplates {
+                ..
+            } => "GetAllTemplates",
+            Self::DeleteTemplate {
+                ..
+            } => "DeleteTemplate",
+            Self::Instantiate {
+                ..
+            } => "Instantiate",
+            Self::AdoptInstance {
+                ..
+            } => "AdoptInstance",
+            Self::RemoveInstance {
+                ..
+            } => "RemoveInstance",
+            Self::GetInstances {
+                ..
+            } => "GetInstances",
+        }
+    }
+
+    /// Sets who asked for a change of tags, other messages are returned unchanged.
+    pub fn with_origin(mut self, new_origin: Origin) -> Self {
+        match &mut self {
+            Self::UpdateTemplate {
+                origin,
+                ..
+            }
+            | Self::Instantiate {
+                origin,
+                ..
+            }
+            | Self::AdoptInstance {
+                origin,
+                ..
+            }
+            | Self::RemoveInstance {
+                origin,
+                ..
+            } => *origin = new_origin,
+            _ => {},
+        }
+        self
+    }
+
+    pub fn create_template(
+        template: TagTemplate,
+    ) -> (Self, mpsc::Receiver<Result<(), TemplateError>>) {
+        let (sender, receiver) = mpsc::channel(REPLY_CHANNEL_SIZE);
+        (
+            Self::CreateTemplate {
+                template,
+                result: sender,
+            },
+            receiver,
+        )
+    }
+
+    pub fn update_template(
+        template: TagTemplate,
+    ) -> (
+        Self,
+        mpsc::Receiver<Result<Vec<InstanceUpdate>, TemplateError>>,
+    ) {
+        let (sender, receiver) = mpsc::channel(REPLY_CHANNEL_SIZE);
+        (
+            Self::UpdateTemplate {
+                template,
+                origin: Origin::default(),
+                result: sender,
+            },
+            receiver,
+        )
+    }
+
+    pub fn get_template(
+        name: impl Into<TemplateName>,
+    ) -> (Self, mpsc::Receiver<Result<TagTemplate, TemplateError>>) {
+        let (sender, receiver) = mpsc::channel(REPLY_CHANNEL_SIZE);
+        (
+            Self::GetTemplate {
+                name: name.into(),
+                result: sender,
+            },
+            receiver,
+        )
+    }
+
+    pub fn get_all_templates() -> (Self, mpsc::Receiver<Vec<TagTemplate>>) {
+        let (sender, receiver) = mpsc::channel(REPLY_CHANNEL_SIZE);
+        (
+            Self::GetAllTemplates {
+                result: sender,
+            },
+            receiver,
+        )
+    }
+
+    pub fn delete_template(
+        name: impl Into<TemplateName>,
+    ) -> (Self, mpsc::Receiver<Result<(), TemplateError>>) {
+        let (sender, receiver) = mpsc::channel(REPLY_CHANNEL_SIZE);
+        (
+            Self::DeleteTemplate {
+                name: name.into(),
+                result: sender,
+            },
+            receiver,
+        )
+    }
+
+    pub fn instantiate(
+        template: impl Into<TemplateName>,
+        prefix: impl Into<TagName>,
+    ) -> (Self, mpsc::Receiver<Result<Vec<TagName>, TemplateError>>) {
+        let (sender, receiver) = mpsc::channel(REPLY_CHANNEL_SIZE);
+        (
+            Self::Instantiate {
+                template: template.into(),
+                prefix: prefix.into(),
+                origin: Origin::default(),
+                result: sender,
+            },
+            receiver,
+        )
+    }
+
+    pub fn adopt_instance(
+        template: impl Into<TemplateName>,
+        prefix: impl Into<TagName>,
+    ) -> (Self, mpsc::Receiver<Result<Vec<TagName>, TemplateError>>) {
+        let (sender, receiver) = mpsc::channel(REPLY_CHANNEL_SIZE);
+        (
+            Self::AdoptInstance {
+                template: template.into(),
+                prefix: prefix.into(),
+                origin: Origin::default(),
+                result: sender,
+            },
+            receiver,
+        )
+    }
+
+    pub fn remove_instance(
+        template: impl Into<TemplateName>,
+        prefix: impl Into<TagName>,
+    ) -> (Self, mpsc::Receiver<Result<Vec<TagName>, TemplateError>>) {
+        let (sender, receiver) = mpsc::channel(REPLY_CHANNEL_SIZE);
+        (
+            Self::RemoveInstance {
+                template: template.into(),
+                prefix: prefix.into(),
+                origin: Origin::default(),
+                result: sender,
+            },
+            receiver,
+        )
+    }
+
+    pub fn get_instances(
+        template: impl Into<TemplateName>,
+    ) -> (Self, mpsc::Receiver<Result<Vec<TagName>, TemplateError>>) {
+        let (sender, receiver) = mpsc::channel(REPLY_CHANNEL_SIZE);
+        (
+            Self::GetInstances {
+                template: template.into(),
+                result: sender,
+            },
+            receiver,
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+
+    use rcada_core::{
+        tag::{Tag, TagMeta},
+        unit::Unit,
+        value::DataType,
+    };
+
+    use super::*;
+    use crate::{
+        actor::tag::TagRepositoryActor,
+        audit::AuditLog,
+        repository::{
+            tag::inmemory::TagStorage,
+            template::{TemplatePoint, inmemory::TemplateStorage},
+        },
+    };
+
+    async fn ask<M: CountedMessage, T>(
+        actor: &ActorRef<M>,
+        (command, mut reply): (M, mpsc::Receiver<T>),
+    ) -> T {
+        actor.enqueue(command).unwrap();
+        reply.recv().await.unwrap()
+    }
+
+    async fn actors() -> (ActorRef<Message>, ActorRef<tag::Message>) {
+        let (tags, _) = Actor::spawn(
+            None,
+            TagRepositoryActor::default(),
+            (TagStorage::default(), AuditLog::disabled()),
+        )
+        .await
+        .unwrap();
+        let (templates, _) = Actor::spawn(
+            None,
+            TemplateRepositoryActor::default(),
+            (TemplateStorage::new(), tags.clone()),
+        )
+        .await
+        .unwrap();
+        (templates, tags)
+    }
+
+    fn point(name: &str, data_type: DataType) -> TemplatePoint {
+        TemplatePoint {
+            name: name.to_string(),
+            meta: TagMeta::new(Unit::None, data_type),
+        }
+    }
+
+    fn pump(points: Vec<TemplatePoint>) -> TagTemplate {
+        TagTemplate {
+            name: "pump".into(),
+            description: String::new(),
+            points,
+        }
+    }
+
+    async fn get_tag(tags: &ActorRef<tag::Message>, name: &str) -> Option<Tag> {
+        ask(tags, tag::Message::get_tag(name)).await.ok()
+    }
+
+    fn names(names: &[&str]) -> Vec<TagName> {
+        names.iter().map(|name| TagName::from(*name)).collect()
+    }
+
+    #[tokio::test]
+    async fn instances_get_the_tags_of_their_template() {
+        let (templates, tags) = actors().await;
+        let mut running = point("running", DataType::Boolean);
+        running.meta.expression = Some("{instance}.speed > 0".to_string());
+        let template = pump(vec![point("speed", DataType::Float), running]);
+        ask(&templates, Message::create_template(template))
+            .await
+            .unwrap();
+
+        assert_eq!(
+            ask(&templates, Message::instantiate("pump", "p1")).await,
+            Ok(names(&["p1.speed", "p1.running"]))
+        );
+        let running = get_tag(&tags, "p1.running").await.unwrap();
+        assert_eq!(running.meta.expression.as_deref(), Some("p1.speed > 0"));
+        assert_eq!(
+            ask(&templates, Message::instantiate("pump", "p1")).await,
+            Err(TemplateError::InstanceAlreadyExists)
+        );
+
+        // Nothing is created if one of the tags is taken
+        let meta = TagMeta::new(Unit::None, DataType::Float);
+        ask(&tags, tag::Message::create_tag("p2.speed", meta)).await;
+        assert_eq!(
+            ask(&templates, Message::instantiate("pump", "p2")).await,
+            Err(TemplateError::TagsAlreadyExist(names(&["p2.speed"])))
+        );
+        assert!(get_tag(&tags, "p2.running").await.is_none());
+        // Unless the existing tags are adopted
+        assert_eq!(
+            ask(&templates, Message::adopt_instance("pump", "p2")).await,
+            Ok(names(&["p2.running"]))
+        );
+
+        assert_eq!(
+            ask(&templates, Message::remove_instance("pump", "p1")).await,
+            Ok(names(&["p1.speed", "p1.running"]))
+        );
+        assert!(get_tag(&tags, "p1.speed").await.is_none());
+        assert_eq!(
+            ask(&templates, Message::get_instances("pump")).await,
+            Ok(names(&["p2"]))
+        );
+    }
+
+    #[tokio::test]
+    async fn template_changes_reach_every_instance() {
+        let (templates, tags) = actors().await;
+        let template = pump(vec![
+            point("speed", DataType::Float),
+            point("running", DataType::Boolean),
+        ]);
+        ask(&templates, Message::create_template(template))
+            .await
+            .unwrap();
+        for prefix in ["p1", "p2"] {
+            ask(&templates, Message::instantiate("pump", prefix))
+                .await
+                .unwrap();
+        }
+        // A label set on one instance only
+        let patch = TagMetaPatch {
+            labels: Some(BTreeMap::from([(
+                "area".to_string(),
+                Some("north".to_string()),
+            )])),
+            ..Default::default()
+        };
+        ask(&tags, tag::Message::update_tag_meta("p1.speed", patch))
+            .await
+            .unwrap();
+
+        let mut speed = point("speed", DataType::Float);
+        speed.meta.description = "Shaft speed".to_string();
+        let template = pump(vec![speed, point("current", DataType::Float)]);
+        let updates = ask(&templates, Message::update_template(template))
+            .await
+            .unwrap();
+
+        let expected: Vec<_> = ["p1", "p2"]
+            .into_iter()
+            .map(|prefix| InstanceUpdate {
+                prefix: prefix.into(),
+                created: names(&[&format!("{prefix}.current")]),
+                updated: names(&[&format!("{prefix}.speed")]),
+                deleted: names(&[&format!("{prefix}.running")]),
+                failed: Vec::new(),
+            })
+            .collect();
+        assert_eq!(updates, expected);
+        assert!(get_tag(&tags, "p2.running").await.is_none());
+        let speed = get_tag(&tags, "p1.speed").await.unwrap();
+        assert_eq!(speed.meta.description, "Shaft speed");
+        assert_eq!(
+            speed.meta.labels.get("area").map(String::as_str),
+            Some("north")
+        );
+    }
+}
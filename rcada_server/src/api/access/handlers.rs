@@ -0,0 +1,289 @@
+use actix_web::{
+    HttpRequest, HttpResponse, delete, get, put,
+    web::{Data, Json, Path, ReqData},
+};
+use tracing::instrument;
+use uuid::Uuid;
+
+use crate::{
+    access::{AccessControl, AccessError, AccessModel, Permission, Role, Subject},
+    api::error::{ApiError, ErrorCode, request_id_of},
+    auth::Identity,
+};
+
+use super::{
+    authorize,
+    model::{MyAccessResponse, PutBindingRequest, PutRoleRequest},
+};
+
+fn error_response(request_id: Uuid, error: AccessError) -> ApiError {
+    tracing::warn!(%request_id, "access request failed: {:?}", error);
+    let api_error = |code: ErrorCode, message: &str| ApiError::new(request_id, code, message);
+    match error {
+        AccessError::RoleNotFound => api_error(ErrorCode::RoleNotFound, "Role not found"),
+        AccessError::BindingNotFound => api_error(ErrorCode::BindingNotFound, "Binding not found"),
+        AccessError::UnknownRoles(roles) => api_error(ErrorCode::InvalidRequest, "Unknown roles")
+            .with_details(serde_json::json!({ "roles": roles })),
+        AccessError::RoleInUse(subjects) => {
+            api_error(ErrorCode::Conflict, "Role is bound to subjects")
+                .with_details(serde_json::json!({ "subjects": subjects }))
+        },
+        AccessError::InvalidRole(reason) => api_error(ErrorCode::InvalidRequest, "Invalid role")
+            .with_details(serde_json::json!({ "reason": reason })),
+        AccessError::InvalidSubject(reason) => {
+            api_error(ErrorCode::InvalidRequest, "Invalid subject")
+                .with_details(serde_json::json!({ "reason": reason }))
+        },
+        AccessError::Storage(e) => {
+            ApiError::internal(request_id, format!("failed to save access model: {e}"))
+        },
+    }
+}
+
+fn parse_subject(request_id: Uuid, subject: &str) -> Result<Subject, ApiError> {
+    subject
+        .parse()
+        .map_err(|e| error_response(request_id, AccessError::InvalidSubject(e)))
+}
+
+#[utoipa::path(
+    tag = "access",
+    responses(
+        (status = 200, description = "Roles and bindings", body = AccessModel),
+        (status = 403, description = "Permission denied", body = ApiError),
+    )
+)]
+#[get("")]
+#[instrument(skip(access, identity, http_req))]
+pub async fn get_access(
+    access: Data<AccessControl>,
+    identity: ReqData<Identity>,
+    http_req: HttpRequest,
+) -> Result<HttpResponse, ApiError> {
+    let request_id = request_id_of(&http_req);
+    tracing::info!(%request_id, "request (get_access)");
+
+    authorize(request_id, &access, &identity, Permission::Admin, None)?;
+    Ok(HttpResponse::Ok().json(access.get_model()))
+}
+
+#[utoipa::path(
+    tag = "access",
+    responses(
+        (status = 200, description = "Roles of the caller", body = MyAccessResponse),
+    )
+)]
+#[get("/me")]
+#[instrument(skip(access, identity))]
+pub async fn get_my_access(
+    access: Data<AccessControl>,
+    identity: ReqData<Identity>,
+) -> Result<HttpResponse, ApiError> {
+    Ok(HttpResponse::Ok().json(MyAccessResponse {
+        name: identity.name.clone(),
+        roles: access.roles_of(&identity),
+    }))
+}
+
+#[utoipa::path(
+    tag = "access",
+    params(("name" = String, Path, description = "Role name")),
+    request_body = PutRoleRequest,
+    responses(
+        (status = 200, description = "Role replaced", body = Role),
+        (status = 201, description = "Role created", body = Role),
+        (status = 400, description = "Invalid role", body = ApiError),
+        (status = 403, description = "Permission denied", body = ApiError),
+    )
+)]
+#[put("/roles/{name}")]
+#[instrument(skip(access, identity, req, http_req))]
+pub async fn put_role(
+    access: Data<AccessControl>,
+    identity: ReqData<Identity>,
+    http_req: HttpRequest,
+    name: Path<String>,
+    req: Json<PutRoleRequest>,
+) -> Result<HttpResponse, ApiError> {
+    let request_id = request_id_of(&http_req);
+    tracing::info!(%request_id, "request: {} (put_role)", name);
+
+    authorize(request_id, &access, &identity, Permission::Admin, None)?;
+    let role = Role {
+        name: name.into_inner(),
+        description: req.0.description,
+        grants: req.0.grants,
+    };
+    match access.put_role(role.clone()) {
+        Ok(None) => Ok(HttpResponse::Created().json(role)),
+        Ok(Some(_)) => Ok(HttpResponse::Ok().json(role)),
+        Err(e) => Err(error_response(request_id, e)),
+    }
+}
+
+#[utoipa::path(
+    tag = "access",
+    params(("name" = String, Path, description = "Role name")),
+    responses(
+        (status = 204, description = "Role deleted"),
+        (status = 403, description = "Permission denied", body = ApiError),
+        (status = 404, description = "Role not found", body = ApiError),
+        (status = 409, description = "Role is bound to subjects", body = ApiError),
+    )
+)]
+#[delete("/roles/{name}")]
+#[instrument(skip(access, identity, http_req))]
+pub async fn delete_role(
+    access: Data<AccessControl>,
+    identity: ReqData<Identity>,
+    http_req: HttpRequest,
+    name: Path<String>,
+) -> Result<HttpResponse, ApiError> {
+    let request_id = request_id_of(&http_req);
+    tracing::info!(%request_id, "request: {} (delete_role)", name);
+
+    authorize(request_id, &access, &identity, Permission::Admin, None)?;
+    match access.delete_role(&name) {
+        Ok(()) => Ok(HttpResponse::NoContent().finish()),
+        Err(e) => Err(error_response(request_id, e)),
+    }
+}
+
+#[utoipa::path(
+    tag = "access",
+    params(("subject" = String, Path, description = "`user:<name>`, `api_key:<name>` or `cert:<common name>`")),
+    request_body = PutBindingRequest,
+    responses(
+        (status = 200, description = "Roles bound", body = PutBindingRequest),
+        (status = 400, description = "Invalid subject or unknown roles", body = ApiError),
+        (status = 403, description = "Permission denied", body = ApiError),
+    )
+)]
+#[put("/bindings/{subject}")]
+#[instrument(skip(access, identity, req, http_req))]
+pub async fn put_binding(
+    access: Data<AccessControl>,
+    identity: ReqData<Identity>,
+    http_req: HttpRequest,
+    subject: Path<String>,
+    req: Json<PutBindingRequest>,
+) -> Result<HttpResponse, ApiError> {
+    let request_id = request_id_of(&http_req);
+    tracing::info!(%request_id, "request: {} (put_binding)", subject);
+
+    authorize(request_id, &access, &identity, Permission::Admin, None)?;
+    let subject = parse_subject(request_id, &subject)?;
+    match access.set_binding(subject, req.0.roles.clone()) {
+        Ok(()) => Ok(HttpResponse::Ok().json(req.0)),
+        Err(e) => Err(error_response(request_id, e)),
+    }
+}
+
+#[utoipa::path(
+    tag = "access",
+    params(("subject" = String, Path, description = "`user:<name>`, `api_key:<name>` or `cert:<common name>`")),
+    responses(
+        (status = 204, description = "Binding removed"),
+        (status = 400, description = "Invalid subject", body = ApiError),
+        (status = 403, description = "Permission denied", body = ApiError),
+        (status = 404, description = "Binding not found", body = ApiError),
+    )
+)]
+#[delete("/bindings/{subject}")]
+#[instrument(skip(access, identity, http_req))]
+pub async fn delete_binding(
+    access: Data<AccessControl>,
+    identity: ReqData<Identity>,
+    http_req: HttpRequest,
+    subject: Path<String>,
+) -> Result<HttpResponse, ApiError> {
+    let request_id = request_id_of(&http_req);
+    tracing::info!(%request_id, "request: {} (delete_binding)", subject);
+
+    authorize(request_id, &access, &identity, Permission::Admin, None)?;
+    let subject = parse_subject(request_id, &subject)?;
+    match access.delete_binding(&subject) {
+        Ok(()) => Ok(HttpResponse::NoContent().finish()),
+        Err(e) => Err(error_response(request_id, e)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use actix_web::{
+        App, HttpMessage,
+        body::MessageBody,
+        dev::{ServiceRequest, ServiceResponse},
+        http::{StatusCode, header::HeaderValue},
+        middleware::{Next, from_fn},
+        test,
+    };
+    use tracing_actix_web::{RequestId, TracingLogger};
+
+    use super::*;
+    use crate::auth::{API_KEY_HEADER, ApiKeyConfig, AuthConfig, Authenticator};
+
+    const ID_HEADER: &str = "x-test-request-id";
+
+    /// Shows the id the tracing middleware gave the request.
+    async fn echo_request_id(
+        req: ServiceRequest,
+        next: Next<impl MessageBody>,
+    ) -> Result<ServiceResponse<impl MessageBody>, actix_web::Error> {
+        let id = req.extensions().get::<RequestId>().map(|id| id.to_string());
+        let mut response = next.call(req).await?;
+        if let Some(id) = id {
+            response.headers_mut().insert(
+                ID_HEADER.try_into().unwrap(),
+                HeaderValue::from_str(&id).unwrap(),
+            );
+        }
+        Ok(response)
+    }
+
+    #[actix_web::test]
+    async fn errors_carry_the_id_of_the_tracing_middleware() {
+        let config = AuthConfig {
+            enabled: true,
+            api_keys: vec![ApiKeyConfig {
+                name: "historian".to_string(),
+                key: "secret-key".to_string(),
+                roles: vec!["viewer".to_string()],
+            }],
+            ..AuthConfig::default()
+        };
+        let app = test::init_service(
+            App::new()
+                .app_data(Data::new(Authenticator::new(&config).unwrap()))
+                .app_data(Data::new(AccessControl::new(AccessModel::default())))
+                .service(crate::api::scope())
+                .wrap(from_fn(echo_request_id))
+                .wrap(TracingLogger::default()),
+        )
+        .await;
+
+        // Rejected by the handler, and by the authentication middleware
+        for api_key in ["secret-key", "wrong"] {
+            let req = test::TestRequest::get()
+                .uri("/api/v1/access")
+                .insert_header((API_KEY_HEADER, api_key))
+                .to_request();
+            let response = test::call_service(&app, req).await;
+            let expected = if api_key == "wrong" {
+                StatusCode::UNAUTHORIZED
+            } else {
+                StatusCode::FORBIDDEN
+            };
+            assert_eq!(response.status(), expected);
+            let id = response
+                .headers()
+                .get(ID_HEADER)
+                .unwrap()
+                .to_str()
+                .unwrap()
+                .to_string();
+            let body: serde_json::Value = test::read_body_json(response).await;
+            assert_eq!(body["request_id"], serde_json::json!(id), "{api_key}");
+        }
+    }
+}
@@ -0,0 +1,51 @@
+use actix_web::dev::HttpServiceFactory;
+use utoipa_actix_web::OpenApiFactory;
+use uuid::Uuid;
+
+use crate::{
+    access::{AccessControl, Permission},
+    api::error::{ApiError, ErrorCode},
+    auth::Identity,
+};
+
+pub mod handlers;
+pub mod model;
+
+pub fn scope() -> impl HttpServiceFactory + OpenApiFactory {
+    utoipa_actix_web::scope("/access")
+        .service(handlers::get_access)
+        .service(handlers::get_my_access)
+        .service(handlers::put_role)
+        .service(handlers::delete_role)
+        .service(handlers::put_binding)
+        .service(handlers::delete_binding)
+}
+
+/// Checks `permission` on `tag`, or on all tags if it's `None`, and returns
+/// the 403 error if it's missing.
+pub fn authorize(
+    request_id: Uuid,
+    access: &AccessControl,
+    identity: &Identity,
+    permission: Permission,
+    tag: Option<&str>,
+) -> Result<(), ApiError> {
+    if access.is_allowed(identity, permission, tag) {
+        return Ok(());
+    }
+    tracing::warn!(
+        %request_id,
+        "{} is missing permission {:?} on {}",
+        identity.name,
+        permission,
+        tag.unwrap_or("all tags")
+    );
+    Err(
+        ApiError::new(request_id, ErrorCode::PermissionDenied, "Permission denied").with_details(
+            serde_json::json!({
+                "permission": permission,
+                "tag": tag
+            }),
+        ),
+    )
+}
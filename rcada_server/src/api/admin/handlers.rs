@@ -0,0 +1,73 @@
+use actix_web::{
+    HttpRequest, HttpResponse, post,
+    web::{Data, ReqData},
+};
+use ractor::ActorRef;
+use tracing::instrument;
+
+use crate::{
+    access::{AccessControl, Permission},
+    actor::{
+        self, Mailbox,
+        config::{ReloadError, ReloadReport},
+    },
+    api::{
+        access::authorize,
+        error::{ApiError, ErrorCode, request_id_of},
+    },
+    auth::Identity,
+};
+
+#[utoipa::path(
+    tag = "admin",
+    responses(
+        (status = 200, description = "Applied changes", body = ReloadReport),
+        (status = 403, description = "Permission denied", body = ApiError),
+        (status = 409, description = "Tag changes rejected", body = ApiError),
+        (status = 422, description = "Invalid configuration", body = ApiError),
+    )
+)]
+#[post("/reload")]
+#[instrument(skip(config_actor, access, identity, http_req))]
+pub async fn reload_config(
+    config_actor: Data<ActorRef<actor::config::Message>>,
+    access: Data<AccessControl>,
+    identity: ReqData<Identity>,
+    http_req: HttpRequest,
+) -> Result<HttpResponse, ApiError> {
+    let request_id = request_id_of(&http_req);
+    tracing::info!(%request_id, "request: (reload_config)");
+
+    authorize(request_id, &access, &identity, Permission::Admin, None)?;
+
+    let (command, mut reply) = actor::config::Message::reload();
+    config_actor
+        .enqueue(command)
+        .map_err(|e| ApiError::internal(request_id, e))?;
+    let result = reply
+        .recv()
+        .await
+        .ok_or_else(|| ApiError::internal(request_id, "actor response channel closed"))?;
+
+    match result {
+        Ok(report) => Ok(HttpResponse::Ok().json(report)),
+        Err(ReloadError::InvalidConfig(e)) => Err(ApiError::new(
+            request_id,
+            ErrorCode::InvalidConfiguration,
+            "Invalid configuration",
+        )
+        .with_details(serde_json::json!({ "reason": e }))),
+        Err(ReloadError::Tags(e)) => {
+            Err(
+                ApiError::new(request_id, ErrorCode::Conflict, "Tag changes rejected")
+                    .with_details(serde_json::json!({ "reason": e })),
+            )
+        },
+        Err(ReloadError::Drivers(failed)) => Err(ApiError::new(
+            request_id,
+            ErrorCode::InvalidConfiguration,
+            "Drivers failed to start",
+        )
+        .with_details(serde_json::json!({ "drivers": failed }))),
+    }
+}
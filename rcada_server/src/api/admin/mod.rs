@@ -0,0 +1,8 @@
+pub mod handlers;
+
+use actix_web::dev::HttpServiceFactory;
+use utoipa_actix_web::OpenApiFactory;
+
+pub fn scope() -> impl HttpServiceFactory + OpenApiFactory {
+    utoipa_actix_web::scope("/admin").service(handlers::reload_config)
+}
@@ -0,0 +1,165 @@
+use actix_web::{
+    HttpRequest, HttpResponse, delete, get, put,
+    web::{Data, Json, Path, ReqData},
+};
+use ractor::ActorRef;
+use tracing::instrument;
+
+use crate::{
+    access::{AccessControl, Permission},
+    actor::{self, Mailbox},
+    api::{
+        access::authorize,
+        error::{ApiError, ErrorCode, request_id_of},
+    },
+    audit::Origin,
+    auth::Identity,
+    repository::tag::AliasError,
+};
+
+use super::model::{AliasResponse, CreateAliasRequest, ListAliasesResponse};
+
+#[utoipa::path(
+    tag = "aliases",
+    responses(
+        (status = 200, description = "Aliases", body = ListAliasesResponse),
+        (status = 403, description = "Permission denied", body = ApiError),
+    )
+)]
+#[get("")]
+#[instrument(skip(tag_repo_actor, access, identity, http_req))]
+pub async fn list_aliases(
+    tag_repo_actor: Data<ActorRef<actor::tag::Message>>,
+    access: Data<AccessControl>,
+    identity: ReqData<Identity>,
+    http_req: HttpRequest,
+) -> Result<HttpResponse, ApiError> {
+    let request_id = request_id_of(&http_req);
+    tracing::info!(%request_id, "request (list_aliases)");
+
+    authorize(request_id, &access, &identity, Permission::Read, None)?;
+
+    let (command, mut reply) = actor::tag::Message::get_all_aliases();
+    tag_repo_actor
+        .enqueue(command)
+        .map_err(|e| ApiError::internal(request_id, e))?;
+    let aliases = reply
+        .recv()
+        .await
+        .ok_or_else(|| ApiError::internal(request_id, "actor response channel closed"))?;
+
+    Ok(HttpResponse::Ok().json(ListAliasesResponse {
+        aliases: aliases.into_iter().map(Into::into).collect(),
+    }))
+}
+
+#[utoipa::path(
+    tag = "aliases",
+    params(("alias" = String, Path, description = "Alias name")),
+    request_body = CreateAliasRequest,
+    responses(
+        (status = 201, description = "Alias created", body = AliasResponse),
+        (status = 403, description = "Permission denied", body = ApiError),
+        (status = 404, description = "Target tag not found", body = ApiError),
+        (status = 409, description = "Name already in use", body = ApiError),
+    )
+)]
+#[put("/{alias}")]
+#[instrument(skip(tag_repo_actor, access, identity, req, http_req))]
+pub async fn create_alias(
+    tag_repo_actor: Data<ActorRef<actor::tag::Message>>,
+    access: Data<AccessControl>,
+    identity: ReqData<Identity>,
+    http_req: HttpRequest,
+    alias: Path<String>,
+    req: Json<CreateAliasRequest>,
+) -> Result<HttpResponse, ApiError> {
+    let request_id = request_id_of(&http_req);
+    let alias_ref = alias.as_str();
+    tracing::info!(%request_id, "request: {} (create_alias) target={}", alias_ref, req.target);
+
+    authorize(request_id, &access, &identity, Permission::Configure, None)?;
+
+    let (command, mut reply) = actor::tag::Message::create_alias(alias_ref, req.target.as_str());
+    tag_repo_actor
+        .enqueue(command.with_origin(Origin::request(request_id, &identity, http_req.peer_addr())))
+        .map_err(|e| ApiError::internal(request_id, e))?;
+    let result = reply
+        .recv()
+        .await
+        .ok_or_else(|| ApiError::internal(request_id, "actor response channel closed"))?;
+
+    match result {
+        Ok(alias) => Ok(HttpResponse::Created().json(AliasResponse::from(alias))),
+        Err(AliasError::TagNameNotFound | AliasError::AliasNotFound) => {
+            tracing::warn!(%request_id, "Alias target not found: {}", req.target);
+            Err(ApiError::new(
+                request_id,
+                ErrorCode::TagNotFound,
+                "Tag not found",
+            ))
+        },
+        Err(AliasError::AlreadyExists) => {
+            tracing::warn!(%request_id, "Alias name already in use: {}", alias_ref);
+            Err(ApiError::new(
+                request_id,
+                ErrorCode::AlreadyExists,
+                "Tag name already in use",
+            ))
+        },
+        Err(AliasError::Storage(e)) => Err(ApiError::internal(
+            request_id,
+            format!("failed to save aliases: {e}"),
+        )),
+    }
+}
+
+#[utoipa::path(
+    tag = "aliases",
+    params(("alias" = String, Path, description = "Alias name")),
+    responses(
+        (status = 204, description = "Alias deleted"),
+        (status = 403, description = "Permission denied", body = ApiError),
+        (status = 404, description = "Alias not found", body = ApiError),
+    )
+)]
+#[delete("/{alias}")]
+#[instrument(skip(tag_repo_actor, access, identity, http_req))]
+pub async fn delete_alias(
+    tag_repo_actor: Data<ActorRef<actor::tag::Message>>,
+    access: Data<AccessControl>,
+    identity: ReqData<Identity>,
+    http_req: HttpRequest,
+    alias: Path<String>,
+) -> Result<HttpResponse, ApiError> {
+    let request_id = request_id_of(&http_req);
+    let alias_ref = alias.as_str();
+    tracing::info!(%request_id, "request: {} (delete_alias)", alias_ref);
+
+    authorize(request_id, &access, &identity, Permission::Configure, None)?;
+
+    let (command, mut reply) = actor::tag::Message::delete_alias(alias_ref);
+    tag_repo_actor
+        .enqueue(command.with_origin(Origin::request(request_id, &identity, http_req.peer_addr())))
+        .map_err(|e| ApiError::internal(request_id, e))?;
+    let result = reply
+        .recv()
+        .await
+        .ok_or_else(|| ApiError::internal(request_id, "actor response channel closed"))?;
+
+    match result {
+        Ok(()) => Ok(HttpResponse::NoContent().finish()),
+        Err(AliasError::Storage(e)) => Err(ApiError::internal(
+            request_id,
+            format!("failed to save aliases: {e}"),
+        )),
+        Err(_) => {
+            tracing::warn!(%request_id, "Alias not found for deletion: {}", alias_ref);
+            Err(ApiError::new(
+                request_id,
+                ErrorCode::AliasNotFound,
+                "Alias not found",
+            ))
+        },
+    }
+}
@@ -0,0 +1,12 @@
+pub mod handlers;
+pub mod model;
+
+use actix_web::dev::HttpServiceFactory;
+use utoipa_actix_web::OpenApiFactory;
+
+pub fn scope() -> impl HttpServiceFactory + OpenApiFactory {
+    utoipa_actix_web::scope("/aliases")
+        .service(handlers::list_aliases)
+        .service(handlers::create_alias)
+        .service(handlers::delete_alias)
+}
@@ -0,0 +1,29 @@
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+
+use crate::repository::tag::TagAlias;
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, ToSchema)]
+pub struct CreateAliasRequest {
+    pub target: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, ToSchema)]
+pub struct AliasResponse {
+    pub alias: String,
+    pub target: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, ToSchema)]
+pub struct ListAliasesResponse {
+    pub aliases: Vec<AliasResponse>,
+}
+
+impl From<TagAlias> for AliasResponse {
+    fn from(alias: TagAlias) -> Self {
+        AliasResponse {
+            alias: alias.alias.to_string(),
+            target: alias.target.to_string(),
+        }
+    }
+}
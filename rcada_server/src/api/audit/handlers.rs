@@ -0,0 +1,84 @@
+use actix_web::{
+    HttpRequest, HttpResponse, get,
+    web::{self, Data, Query, ReqData},
+};
+use tracing::instrument;
+
+use crate::{
+    access::{AccessControl, Permission},
+    api::{
+        access::authorize,
+        error::{ApiError, request_id_of},
+    },
+    audit::{AuditLog, AuditQuery, VerifyReport},
+    auth::Identity,
+};
+
+use super::model::ListAuditRecordsResponse;
+
+/// Records returned when the query has no `limit`.
+const DEFAULT_LIMIT: usize = 100;
+
+#[utoipa::path(
+    tag = "audit",
+    params(AuditQuery),
+    responses(
+        (status = 200, description = "Newest matching records", body = ListAuditRecordsResponse),
+        (status = 403, description = "Permission denied", body = ApiError),
+    )
+)]
+#[get("")]
+#[instrument(skip(audit, access, identity, http_req))]
+pub async fn query_audit_log(
+    audit: Data<AuditLog>,
+    access: Data<AccessControl>,
+    identity: ReqData<Identity>,
+    http_req: HttpRequest,
+    query: Query<AuditQuery>,
+) -> Result<HttpResponse, ApiError> {
+    let request_id = request_id_of(&http_req);
+    tracing::info!(%request_id, "request: (query_audit_log)");
+
+    authorize(request_id, &access, &identity, Permission::Admin, None)?;
+
+    let mut query = query.into_inner();
+    query.limit.get_or_insert(DEFAULT_LIMIT);
+    let audit = audit.into_inner();
+    let records = web::block(move || audit.query(&query))
+        .await
+        .map_err(|e| ApiError::internal(request_id, e))?
+        .map_err(|e| ApiError::internal(request_id, format!("failed to read audit log: {e}")))?;
+
+    Ok(HttpResponse::Ok().json(ListAuditRecordsResponse {
+        records,
+    }))
+}
+
+#[utoipa::path(
+    tag = "audit",
+    responses(
+        (status = 200, description = "Result of the check", body = VerifyReport),
+        (status = 403, description = "Permission denied", body = ApiError),
+    )
+)]
+#[get("/verify")]
+#[instrument(skip(audit, access, identity, http_req))]
+pub async fn verify_audit_log(
+    audit: Data<AuditLog>,
+    access: Data<AccessControl>,
+    identity: ReqData<Identity>,
+    http_req: HttpRequest,
+) -> Result<HttpResponse, ApiError> {
+    let request_id = request_id_of(&http_req);
+    tracing::info!(%request_id, "request: (verify_audit_log)");
+
+    authorize(request_id, &access, &identity, Permission::Admin, None)?;
+
+    let audit = audit.into_inner();
+    let report = web::block(move || audit.verify())
+        .await
+        .map_err(|e| ApiError::internal(request_id, e))?
+        .map_err(|e| ApiError::internal(request_id, format!("failed to read audit log: {e}")))?;
+
+    Ok(HttpResponse::Ok().json(report))
+}
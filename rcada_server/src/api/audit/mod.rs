@@ -0,0 +1,11 @@
+pub mod handlers;
+pub mod model;
+
+use actix_web::dev::HttpServiceFactory;
+use utoipa_actix_web::OpenApiFactory;
+
+pub fn scope() -> impl HttpServiceFactory + OpenApiFactory {
+    utoipa_actix_web::scope("/audit")
+        .service(handlers::verify_audit_log)
+        .service(handlers::query_audit_log)
+}
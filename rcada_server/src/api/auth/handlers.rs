@@ -0,0 +1,62 @@
+use actix_web::{
+    HttpRequest, HttpResponse, get, post,
+    web::{Data, Json, ReqData},
+};
+use tracing::instrument;
+
+use crate::{
+    api::error::{ApiError, ErrorCode, request_id_of},
+    auth::{AuthError, Authenticator, Identity, Token},
+};
+
+use super::model::LoginRequest;
+
+#[utoipa::path(
+    tag = "auth",
+    security(()),
+    request_body = LoginRequest,
+    responses(
+        (status = 200, description = "Token", body = Token),
+        (status = 401, description = "Invalid user name or password", body = ApiError),
+        (status = 404, description = "Login is not configured", body = ApiError),
+    )
+)]
+#[post("/login")]
+#[instrument(skip(authenticator, req, http_req), fields(username = %req.username))]
+pub async fn login(
+    authenticator: Data<Authenticator>,
+    http_req: HttpRequest,
+    req: Json<LoginRequest>,
+) -> Result<HttpResponse, ApiError> {
+    let request_id = request_id_of(&http_req);
+    tracing::info!(%request_id, "request: (login) username={}", req.username);
+
+    match authenticator.login(&req.username, &req.password) {
+        Ok(token) => Ok(HttpResponse::Ok().json(token)),
+        Err(AuthError::LoginDisabled) => Err(ApiError::new(
+            request_id,
+            ErrorCode::LoginDisabled,
+            "Login is not configured",
+        )),
+        Err(e) => {
+            tracing::warn!(%request_id, "login failed: {}", e);
+            Err(ApiError::new(
+                request_id,
+                ErrorCode::InvalidCredentials,
+                "Invalid user name or password",
+            ))
+        },
+    }
+}
+
+#[utoipa::path(
+    tag = "auth",
+    responses(
+        (status = 200, description = "Identity of the caller", body = Identity),
+    )
+)]
+#[get("/whoami")]
+#[instrument(skip(identity))]
+pub async fn whoami(identity: ReqData<Identity>) -> Result<HttpResponse, ApiError> {
+    Ok(HttpResponse::Ok().json(identity.into_inner()))
+}
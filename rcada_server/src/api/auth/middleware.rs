@@ -0,0 +1,145 @@
+use actix_web::{
+    Error, HttpMessage, HttpResponse,
+    body::{EitherBody, MessageBody},
+    dev::{ServiceRequest, ServiceResponse},
+    http::header::WWW_AUTHENTICATE,
+    middleware::Next,
+    web::Data,
+};
+
+use crate::{
+    api::error::{ApiError, ErrorCode, request_id_of},
+    auth::{Authenticator, Identity},
+    tls::ClientCertificate,
+};
+
+/// Paths reachable without credentials.
+const LOGIN_PATH: &str = "/api/v1/auth/login";
+const HEALTH_PATH: &str = "/api/v1/health";
+const METRICS_PATH: &str = "/metrics";
+
+/// Rejects requests without valid credentials and stores the [`Identity`]
+/// of the others in the request extensions.
+pub async fn authenticate(
+    req: ServiceRequest,
+    next: Next<impl MessageBody>,
+) -> Result<ServiceResponse<EitherBody<impl MessageBody>>, Error> {
+    let Some(authenticator) = req.app_data::<Data<Authenticator>>().cloned() else {
+        req.extensions_mut().insert(Identity::anonymous());
+        return next
+            .call(req)
+            .await
+            .map(ServiceResponse::map_into_left_body);
+    };
+
+    let public = req.path() == LOGIN_PATH
+        || (authenticator.is_health_public()
+            && (req.path().starts_with(HEALTH_PATH) || req.path() == METRICS_PATH));
+    let client_cert = req.conn_data::<ClientCertificate>();
+    let identity = match authenticator.authenticate(req.headers(), client_cert) {
+        Ok(identity) => identity,
+        Err(_) if public => Identity::anonymous(),
+        Err(e) => {
+            tracing::warn!(path = req.path(), "unauthorized request: {}", e);
+            let error = ApiError::new(request_id_of(&req), ErrorCode::Unauthorized, e.to_string());
+            let response = HttpResponse::Unauthorized()
+                .insert_header((WWW_AUTHENTICATE, "Bearer"))
+                .json(error);
+            return Ok(req.into_response(response).map_into_right_body());
+        },
+    };
+
+    req.extensions_mut().insert(identity);
+    next.call(req)
+        .await
+        .map(ServiceResponse::map_into_left_body)
+}
+
+#[cfg(test)]
+mod tests {
+    use actix_web::{App, HttpRequest, http::StatusCode, middleware::from_fn, test, web};
+
+    use super::*;
+    use crate::auth::{API_KEY_HEADER, ApiKeyConfig, AuthConfig};
+
+    async fn whoami(req: HttpRequest) -> String {
+        req.extensions()
+            .get::<Identity>()
+            .map(|identity| identity.name.clone())
+            .unwrap_or_default()
+    }
+
+    async fn get(public_health: bool, path: &str, api_key: Option<&str>) -> (StatusCode, String) {
+        let config = AuthConfig {
+            enabled: true,
+            public_health,
+            api_keys: vec![ApiKeyConfig {
+                name: "scada".to_string(),
+                key: "secret-key".to_string(),
+                roles: Vec::new(),
+            }],
+            ..AuthConfig::default()
+        };
+        let app = test::init_service(
+            App::new()
+                .app_data(Data::new(Authenticator::new(&config).unwrap()))
+                .wrap(from_fn(authenticate))
+                .default_service(web::to(whoami)),
+        )
+        .await;
+
+        let mut req = test::TestRequest::get().uri(path);
+        if let Some(key) = api_key {
+            req = req.insert_header((API_KEY_HEADER, key));
+        }
+        let response = test::call_service(&app, req.to_request()).await;
+        let status = response.status();
+        let body = test::read_body(response).await;
+        (status, String::from_utf8_lossy(&body).into_owned())
+    }
+
+    #[actix_web::test]
+    async fn requests_without_credentials_are_rejected() {
+        let (status, _) = get(true, "/api/v1/tags", None).await;
+        assert_eq!(status, StatusCode::UNAUTHORIZED);
+
+        let (status, _) = get(true, "/api/v1/tags", Some("wrong")).await;
+        assert_eq!(status, StatusCode::UNAUTHORIZED);
+
+        let (status, body) = get(true, "/api/v1/tags", Some("secret-key")).await;
+        assert_eq!((status, body.as_str()), (StatusCode::OK, "scada"));
+    }
+
+    #[actix_web::test]
+    async fn public_paths_are_exempt() {
+        for path in [
+            LOGIN_PATH,
+            HEALTH_PATH,
+            "/api/v1/health/ready",
+            METRICS_PATH,
+        ] {
+            let (status, body) = get(true, path, None).await;
+            assert_eq!(
+                (status, body.as_str()),
+                (StatusCode::OK, "anonymous"),
+                "{path}"
+            );
+        }
+        // Wrong credentials on a public path fall back to anonymous
+        let (status, body) = get(true, HEALTH_PATH, Some("wrong")).await;
+        assert_eq!((status, body.as_str()), (StatusCode::OK, "anonymous"));
+        // Valid credentials are still used
+        let (status, body) = get(true, METRICS_PATH, Some("secret-key")).await;
+        assert_eq!((status, body.as_str()), (StatusCode::OK, "scada"));
+    }
+
+    #[actix_web::test]
+    async fn health_and_metrics_need_credentials_unless_public() {
+        for path in [HEALTH_PATH, METRICS_PATH, "/metrics/extra"] {
+            let (status, _) = get(false, path, None).await;
+            assert_eq!(status, StatusCode::UNAUTHORIZED, "{path}");
+        }
+        let (status, _) = get(false, LOGIN_PATH, None).await;
+        assert_eq!(status, StatusCode::OK);
+    }
+}
@@ -0,0 +1,12 @@
+pub mod handlers;
+pub mod middleware;
+pub mod model;
+
+use actix_web::dev::HttpServiceFactory;
+use utoipa_actix_web::OpenApiFactory;
+
+pub fn scope() -> impl HttpServiceFactory + OpenApiFactory {
+    utoipa_actix_web::scope("/auth")
+        .service(handlers::login)
+        .service(handlers::whoami)
+}
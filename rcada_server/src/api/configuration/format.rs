@@ -0,0 +1,173 @@
+use rcada_core::{
+    tag::{AlarmLimits, EngineeringRange, TagMeta},
+    unit::Unit,
+    value::DataType,
+};
+use serde::{Deserialize, Serialize};
+
+use super::model::{ConfigFormat, Configuration, TagConfig};
+
+#[derive(Debug, thiserror::Error)]
+pub enum FormatError {
+    #[error("invalid JSON: {0}")]
+    Json(#[from] serde_json::Error),
+    #[error("invalid YAML: {0}")]
+    Yaml(#[from] serde_yaml::Error),
+    #[error("invalid CSV: {0}")]
+    Csv(#[from] csv::Error),
+    #[error("invalid CSV: {0}")]
+    CsvRow(String),
+}
+
+impl ConfigFormat {
+    pub fn content_type(&self) -> &'static str {
+        match self {
+            ConfigFormat::Json => "application/json",
+            ConfigFormat::Yaml => "application/yaml",
+            ConfigFormat::Csv => "text/csv",
+        }
+    }
+
+    pub fn from_content_type(content_type: &str) -> Option<Self> {
+        match content_type.split(';').next()?.trim() {
+            "application/json" => Some(ConfigFormat::Json),
+            "application/yaml" | "application/x-yaml" | "text/yaml" => Some(ConfigFormat::Yaml),
+            "text/csv" => Some(ConfigFormat::Csv),
+            _ => None,
+        }
+    }
+}
+
+/// One tag per line. Labels are written as `key=value` pairs separated by `;`.
+#[derive(Debug, Serialize, Deserialize)]
+struct CsvTagRow {
+    name: String,
+    unit: Unit,
+    data_type: DataType,
+    description: String,
+    range_low: Option<f64>,
+    range_high: Option<f64>,
+    precision: Option<u8>,
+    read_only: bool,
+    labels: String,
+    alarm_low_low: Option<f64>,
+    alarm_low: Option<f64>,
+    alarm_high: Option<f64>,
+    alarm_high_high: Option<f64>,
+    expression: Option<String>,
+}
+
+impl From<TagConfig> for CsvTagRow {
+    fn from(tag: TagConfig) -> Self {
+        let meta = tag.meta;
+        CsvTagRow {
+            name: tag.name,
+            unit: meta.unit,
+            data_type: meta.data_type,
+            description: meta.description,
+            range_low: meta.range.map(|range| range.low),
+            range_high: meta.range.map(|range| range.high),
+            precision: meta.precision,
+            read_only: meta.read_only,
+            labels: meta
+                .labels
+                .into_iter()
+                .map(|(key, value)| format!("{key}={value}"))
+                .collect::<Vec<_>>()
+                .join(";"),
+            alarm_low_low: meta.alarms.low_low,
+            alarm_low: meta.alarms.low,
+            alarm_high: meta.alarms.high,
+            alarm_high_high: meta.alarms.high_high,
+            expression: meta.expression,
+        }
+    }
+}
+
+impl TryFrom<CsvTagRow> for TagConfig {
+    type Error = FormatError;
+
+    fn try_from(row: CsvTagRow) -> Result<Self, Self::Error> {
+        let range = match (row.range_low, row.range_high) {
+            (Some(low), Some(high)) => Some(EngineeringRange {
+                low,
+                high,
+            }),
+            (None, None) => None,
+            _ => {
+                return Err(FormatError::CsvRow(format!(
+                    "{}: range_low and range_high must be set together",
+                    row.name
+                )));
+            },
+        };
+
+        let labels = row
+            .labels
+            .split(';')
+            .filter(|pair| !pair.is_empty())
+            .map(|pair| {
+                pair.split_once('=')
+                    .map(|(key, value)| (key.to_string(), value.to_string()))
+                    .ok_or_else(|| {
+                        FormatError::CsvRow(format!("{}: label without '=': {pair}", row.name))
+                    })
+            })
+            .collect::<Result<_, _>>()?;
+
+        Ok(TagConfig {
+            name: row.name,
+            meta: TagMeta {
+                unit: row.unit,
+                data_type: row.data_type,
+                description: row.description,
+                range,
+                precision: row.precision,
+                read_only: row.read_only,
+                labels,
+                alarms: AlarmLimits {
+                    low_low: row.alarm_low_low,
+                    low: row.alarm_low,
+                    high: row.alarm_high,
+                    high_high: row.alarm_high_high,
+                },
+                expression: row.expression.filter(|expression| !expression.is_empty()),
+            },
+        })
+    }
+}
+
+/// CSV only holds the tags, the other sections are left out.
+pub fn encode(config: Configuration, format: ConfigFormat) -> Result<Vec<u8>, FormatError> {
+    match format {
+        ConfigFormat::Json => Ok(serde_json::to_vec_pretty(&config)?),
+        ConfigFormat::Yaml => Ok(serde_yaml::to_string(&config)?.into_bytes()),
+        ConfigFormat::Csv => {
+            let mut writer = csv::Writer::from_writer(Vec::new());
+            for tag in config.tags {
+                writer.serialize(CsvTagRow::from(tag))?;
+            }
+            writer
+                .into_inner()
+                .map_err(|e| FormatError::CsvRow(e.to_string()))
+        },
+    }
+}
+
+pub fn decode(bytes: &[u8], format: ConfigFormat) -> Result<Configuration, FormatError> {
+    match format {
+        ConfigFormat::Json => Ok(serde_json::from_slice(bytes)?),
+        ConfigFormat::Yaml => Ok(serde_yaml::from_slice(bytes)?),
+        ConfigFormat::Csv => {
+            let mut reader = csv::Reader::from_reader(bytes);
+            let tags = reader
+                .deserialize::<CsvTagRow>()
+                .map(|row| row.map_err(FormatError::from).and_then(TagConfig::try_from))
+                .collect::<Result<_, _>>()?;
+            Ok(Configuration {
+                tags,
+                ..Default::default()
+            })
+        },
+    }
+}
@@ -0,0 +1,354 @@
+use actix_web::{
+    HttpRequest, HttpResponse, get, post,
+    web::{Bytes, Data, Query, ReqData},
+};
+use ractor::ActorRef;
+use tokio::sync::mpsc;
+use tracing::instrument;
+use uuid::Uuid;
+
+use crate::{
+    access::{AccessControl, Permission},
+    actor::{self, CountedMessage, Mailbox},
+    api::{
+        access::authorize,
+        error::{ApiError, ErrorCode, request_id_of},
+    },
+    audit::Origin,
+    auth::Identity,
+    repository::{
+        tag::{AliasError, ApplyChangesError},
+        template::TemplateError,
+    },
+};
+
+use super::{
+    format,
+    model::{
+        AliasChange, ConfigFormat, Configuration, ConflictPolicy, Existing, ExportQuery,
+        ImportQuery, ImportResponse, TemplateChange,
+    },
+};
+
+/// Sends a command to an actor and waits for the reply.
+async fn ask<M: CountedMessage, T>(
+    request_id: Uuid,
+    actor: &ActorRef<M>,
+    (command, mut reply): (M, mpsc::Receiver<T>),
+) -> Result<T, ApiError> {
+    actor
+        .enqueue(command)
+        .map_err(|e| ApiError::internal(request_id, e))?;
+    reply
+        .recv()
+        .await
+        .ok_or_else(|| ApiError::internal(request_id, "actor response channel closed"))
+}
+
+/// Collects the configuration the server is running with.
+async fn existing(
+    request_id: Uuid,
+    tag_repo_actor: &ActorRef<actor::tag::Message>,
+    template_actor: &ActorRef<actor::template::Message>,
+    config_actor: &ActorRef<actor::config::Message>,
+) -> Result<Existing, ApiError> {
+    let mut existing = Existing {
+        tags: ask(
+            request_id,
+            tag_repo_actor,
+            actor::tag::Message::get_all_tags(),
+        )
+        .await?,
+        aliases: ask(
+            request_id,
+            tag_repo_actor,
+            actor::tag::Message::get_all_aliases(),
+        )
+        .await?,
+        templates: Vec::new(),
+        drivers: ask(
+            request_id,
+            config_actor,
+            actor::config::Message::get_drivers(),
+        )
+        .await?,
+    };
+    let templates = actor::template::Message::get_all_templates();
+    for template in ask(request_id, template_actor, templates).await? {
+        let instances = actor::template::Message::get_instances(template.name.clone());
+        // Skip templates deleted in the meantime
+        if let Ok(instances) = ask(request_id, template_actor, instances).await? {
+            existing.templates.push((template, instances));
+        }
+    }
+    Ok(existing)
+}
+
+#[utoipa::path(
+    tag = "configuration",
+    params(ExportQuery),
+    responses(
+        (status = 200, description = "Configuration in the requested format", body = Configuration),
+        (status = 403, description = "Permission denied", body = ApiError),
+    )
+)]
+#[get("")]
+#[instrument(skip(
+    tag_repo_actor,
+    template_actor,
+    config_actor,
+    access,
+    identity,
+    http_req
+))]
+pub async fn export_configuration(
+    tag_repo_actor: Data<ActorRef<actor::tag::Message>>,
+    template_actor: Data<ActorRef<actor::template::Message>>,
+    config_actor: Data<ActorRef<actor::config::Message>>,
+    access: Data<AccessControl>,
+    identity: ReqData<Identity>,
+    http_req: HttpRequest,
+    query: Query<ExportQuery>,
+) -> Result<HttpResponse, ApiError> {
+    let request_id = request_id_of(&http_req);
+    tracing::info!(%request_id, "request (export_configuration) format={:?}", query.format);
+
+    authorize(request_id, &access, &identity, Permission::Read, None)?;
+
+    let existing = existing(request_id, &tag_repo_actor, &template_actor, &config_actor).await?;
+    let body = format::encode(Configuration::from(existing), query.format).map_err(|e| {
+        ApiError::internal(request_id, format!("failed to encode configuration: {e}"))
+    })?;
+
+    Ok(HttpResponse::Ok()
+        .content_type(query.format.content_type())
+        .body(body))
+}
+
+#[utoipa::path(
+    tag = "configuration",
+    params(ImportQuery),
+    request_body = Configuration,
+    responses(
+        (status = 200, description = "Import report", body = ImportResponse),
+        (status = 400, description = "Invalid configuration", body = ApiError),
+        (status = 403, description = "Permission denied", body = ApiError),
+        (status = 409, description = "Conflicts with existing tags", body = ApiError),
+    )
+)]
+#[post("/import")]
+#[allow(clippy::too_many_arguments)]
+#[instrument(skip(
+    tag_repo_actor,
+    template_actor,
+    config_actor,
+    access,
+    identity,
+    http_req,
+    body
+))]
+pub async fn import_configuration(
+    tag_repo_actor: Data<ActorRef<actor::tag::Message>>,
+    template_actor: Data<ActorRef<actor::template::Message>>,
+    config_actor: Data<ActorRef<actor::config::Message>>,
+    access: Data<AccessControl>,
+    identity: ReqData<Identity>,
+    http_req: HttpRequest,
+    query: Query<ImportQuery>,
+    body: Bytes,
+) -> Result<HttpResponse, ApiError> {
+    let request_id = request_id_of(&http_req);
+    let format = query
+        .format
+        .or_else(|| {
+            http_req
+                .headers()
+                .get(actix_web::http::header::CONTENT_TYPE)
+                .and_then(|value| value.to_str().ok())
+                .and_then(ConfigFormat::from_content_type)
+        })
+        .unwrap_or_default();
+    tracing::info!(%request_id, "request (import_configuration) format={:?} {:?}", format, query);
+
+    authorize(request_id, &access, &identity, Permission::Configure, None)?;
+
+    let config = match format::decode(&body, format) {
+        Ok(config) => config,
+        Err(e) => {
+            tracing::warn!(%request_id, "Invalid configuration: {}", e);
+            return Err(ApiError::new(
+                request_id,
+                ErrorCode::InvalidRequest,
+                "Invalid configuration",
+            )
+            .with_details(serde_json::json!({ "reason": e.to_string() })));
+        },
+    };
+    if let Some((kind, name)) = config.find_duplicate() {
+        tracing::warn!(%request_id, "Duplicate {} in configuration: {}", kind, name);
+        return Err(ApiError::new(
+            request_id,
+            ErrorCode::InvalidRequest,
+            format!("Duplicate {kind}"),
+        )
+        .with_details(serde_json::json!({ kind: name })));
+    }
+
+    let existing = existing(request_id, &tag_repo_actor, &template_actor, &config_actor).await?;
+    let (changes, mut response) = config.diff(existing, &query);
+
+    if query.policy == ConflictPolicy::Fail && response.conflicts() {
+        tracing::warn!(%request_id, "Import conflicts with the configuration: {:?}", response);
+        return Err(ApiError::new(
+            request_id,
+            ErrorCode::Conflict,
+            "Import conflicts with existing tags",
+        )
+        .with_details(serde_json::json!(response)));
+    }
+    if query.dry_run || changes.is_empty() {
+        return Ok(HttpResponse::Ok().json(response));
+    }
+
+    let origin = Origin::request(request_id, &identity, http_req.peer_addr());
+    if !changes.tags.is_empty() {
+        let (command, reply) = actor::tag::Message::apply_changes(changes.tags);
+        let command = (command.with_origin(origin.clone()), reply);
+        if let Err(e) = ask(request_id, &tag_repo_actor, command).await? {
+            return Err(tags_error(request_id, e));
+        }
+    }
+
+    // Aliases and templates are changed one at a time after the tags, so a
+    // failure leaves the import partly applied.
+    for change in changes.aliases {
+        let result = match change {
+            AliasChange::Create {
+                alias,
+                target,
+            } => {
+                let (command, reply) = actor::tag::Message::create_alias(alias, target);
+                let command = (command.with_origin(origin.clone()), reply);
+                ask(request_id, &tag_repo_actor, command).await?.map(|_| ())
+            },
+            AliasChange::Delete {
+                alias,
+            } => {
+                let (command, reply) = actor::tag::Message::delete_alias(alias);
+                let command = (command.with_origin(origin.clone()), reply);
+                // Aliases of deleted tags are already gone
+                match ask(request_id, &tag_repo_actor, command).await? {
+                    Err(AliasError::AliasNotFound) => Ok(()),
+                    result => result,
+                }
+            },
+        };
+        if let Err(e) = result {
+            return Err(partly_applied(request_id, format!("{e:?}")));
+        }
+    }
+
+    for change in changes.templates {
+        let result = match change {
+            TemplateChange::RemoveInstance {
+                template,
+                prefix,
+            } => {
+                let (command, reply) = actor::template::Message::remove_instance(template, prefix);
+                let command = (command.with_origin(origin.clone()), reply);
+                ask(request_id, &template_actor, command).await?.map(|_| ())
+            },
+            TemplateChange::Delete(name) => {
+                let command = actor::template::Message::delete_template(name);
+                ask(request_id, &template_actor, command).await?
+            },
+            TemplateChange::Create(template) => {
+                let command = actor::template::Message::create_template(template);
+                ask(request_id, &template_actor, command).await?
+            },
+            TemplateChange::Update(template) => {
+                let (command, reply) = actor::template::Message::update_template(template);
+                let command = (command.with_origin(origin.clone()), reply);
+                ask(request_id, &template_actor, command).await?.map(|_| ())
+            },
+            TemplateChange::AddInstance {
+                template,
+                prefix,
+            } => {
+                let (command, reply) = actor::template::Message::adopt_instance(template, prefix);
+                let command = (command.with_origin(origin.clone()), reply);
+                ask(request_id, &template_actor, command).await?.map(|_| ())
+            },
+        };
+        if let Err(e) = result {
+            return Err(partly_applied(request_id, template_reason(e)));
+        }
+    }
+
+    response.applied = true;
+    Ok(HttpResponse::Ok().json(response))
+}
+
+fn template_reason(error: TemplateError) -> String {
+    match error {
+        TemplateError::InvalidPoint {
+            point,
+            reason,
+        } => format!("invalid template point {point}: {reason}"),
+        e => format!("{e:?}"),
+    }
+}
+
+fn partly_applied(request_id: Uuid, reason: String) -> ApiError {
+    tracing::warn!(%request_id, "Import failed after applying the tags: {}", reason);
+    ApiError::new(
+        request_id,
+        ErrorCode::Conflict,
+        "Import failed after applying the tags",
+    )
+    .with_details(serde_json::json!({ "reason": reason }))
+}
+
+fn tags_error(request_id: Uuid, error: ApplyChangesError) -> ApiError {
+    match error {
+        ApplyChangesError::IncompatibleDataType {
+            name,
+            from,
+            to,
+        } => {
+            tracing::warn!(%request_id, "Incompatible data type change for tag: {}", name);
+            ApiError::new(
+                request_id,
+                ErrorCode::IncompatibleDataType,
+                "Current value can't be converted to the new data type",
+            )
+            .with_details(serde_json::json!({
+                "tag": name,
+                "from": format!("{:?}", from),
+                "to": format!("{:?}", to)
+            }))
+        },
+        ApplyChangesError::InvalidMeta(name) => {
+            tracing::warn!(%request_id, "Invalid metadata for tag: {}", name);
+            ApiError::new(
+                request_id,
+                ErrorCode::InvalidRequest,
+                "Invalid engineering range or alarm limits",
+            )
+            .with_details(serde_json::json!({ "tag": name }))
+        },
+        ApplyChangesError::Storage(e) => {
+            ApiError::internal(request_id, format!("failed to save tags: {e}"))
+        },
+        e => {
+            // The tags changed between computing the diff and applying it
+            tracing::warn!(%request_id, "Import failed: {:?}", e);
+            ApiError::new(
+                request_id,
+                ErrorCode::Conflict,
+                "Tags changed during import, retry",
+            )
+            .with_details(serde_json::json!({ "reason": format!("{:?}", e) }))
+        },
+    }
+}
@@ -0,0 +1,16 @@
+pub mod format;
+pub mod handlers;
+pub mod model;
+
+use actix_web::dev::HttpServiceFactory;
+use utoipa_actix_web::OpenApiFactory;
+
+/// Maximum size of an imported configuration.
+const IMPORT_LIMIT: usize = 16 * 1024 * 1024;
+
+pub fn scope() -> impl HttpServiceFactory + OpenApiFactory {
+    utoipa_actix_web::scope("/configuration")
+        .app_data(actix_web::web::PayloadConfig::new(IMPORT_LIMIT))
+        .service(handlers::export_configuration)
+        .service(handlers::import_configuration)
+}
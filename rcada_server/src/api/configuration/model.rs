@@ -0,0 +1,666 @@
+use std::collections::{BTreeMap, BTreeSet, HashSet};
+
+use rcada_core::tag::{Tag, TagMeta, TagName};
+use serde::{Deserialize, Serialize};
+use utoipa::{IntoParams, ToSchema};
+
+use crate::{
+    driver::DriverConfig,
+    repository::{
+        tag::{TagAlias, TagChange},
+        template::{TagTemplate, TemplateName},
+    },
+};
+
+/// Version-controllable configuration of all tags, aliases, templates and drivers.
+///
+/// A section missing from an import is left as it is, CSV only holds the tags.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize, ToSchema)]
+pub struct Configuration {
+    pub tags: Vec<TagConfig>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub aliases: Option<Vec<AliasConfig>>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub templates: Option<Vec<TemplateConfig>>,
+    /// Drivers and the tags bound to their points, with the passwords
+    /// redacted. They are set in the server configuration file, so an import
+    /// only reports the drivers that differ.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    #[schema(value_type = Option<Vec<Object>>)]
+    pub drivers: Option<Vec<DriverConfig>>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, ToSchema)]
+pub struct AliasConfig {
+    pub alias: String,
+    pub target: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, ToSchema)]
+pub struct TemplateConfig {
+    #[serde(flatten)]
+    pub template: TagTemplate,
+    /// Prefixes of the instances.
+    #[serde(default)]
+    pub instances: Vec<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, ToSchema)]
+pub struct TagConfig {
+    pub name: String,
+    #[serde(flatten)]
+    pub meta: TagMeta,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum ConfigFormat {
+    #[default]
+    Json,
+    Yaml,
+    Csv,
+}
+
+/// What to do with tags, aliases and templates that exist on the server but
+/// differ from the import.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum ConflictPolicy {
+    /// Reject the whole import.
+    #[default]
+    Fail,
+    /// Keep the existing tags.
+    Skip,
+    /// Replace the metadata of the existing tags.
+    Overwrite,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct ExportQuery {
+    #[serde(default)]
+    pub format: ConfigFormat,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct ImportQuery {
+    #[serde(default)]
+    pub format: Option<ConfigFormat>,
+    #[serde(default)]
+    pub dry_run: bool,
+    #[serde(default)]
+    pub policy: ConflictPolicy,
+    /// Delete tags, aliases, templates and instances that are missing from
+    /// the sections of the import.
+    #[serde(default)]
+    pub delete_missing: bool,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize, ToSchema)]
+pub struct ImportResponse {
+    pub dry_run: bool,
+    pub applied: bool,
+    pub create: Vec<String>,
+    pub update: Vec<String>,
+    pub delete: Vec<String>,
+    /// Tags that differ from the import but were kept or caused a rejection.
+    pub conflicts: Vec<String>,
+    pub unchanged: usize,
+    pub aliases: ImportChanges,
+    /// Templates whose definition or instances change.
+    pub templates: ImportChanges,
+    /// Drivers that differ from the running configuration, they have to be
+    /// changed in the configuration file.
+    pub drivers: Vec<String>,
+}
+
+/// Changes of one section of the configuration.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize, ToSchema)]
+pub struct ImportChanges {
+    pub create: Vec<String>,
+    pub update: Vec<String>,
+    pub delete: Vec<String>,
+    pub conflicts: Vec<String>,
+    pub unchanged: usize,
+}
+
+impl ImportResponse {
+    pub fn conflicts(&self) -> bool {
+        !self.conflicts.is_empty()
+            || !self.aliases.conflicts.is_empty()
+            || !self.templates.conflicts.is_empty()
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum AliasChange {
+    Create {
+        alias: TagName,
+        target: TagName,
+    },
+    Delete {
+        alias: TagName,
+    },
+}
+
+/// Change of the templates. The variants are in the order they are applied in.
+#[derive(Debug, Clone, PartialEq)]
+pub enum TemplateChange {
+    RemoveInstance {
+        template: TemplateName,
+        prefix: TagName,
+    },
+    Delete(TemplateName),
+    Create(TagTemplate),
+    Update(TagTemplate),
+    AddInstance {
+        template: TemplateName,
+        prefix: TagName,
+    },
+}
+
+/// Changes that turn the server's configuration into the imported one.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ConfigurationChanges {
+    pub tags: Vec<TagChange>,
+    pub aliases: Vec<AliasChange>,
+    pub templates: Vec<TemplateChange>,
+}
+
+impl TemplateChange {
+    fn order(&self) -> u8 {
+        match self {
+            TemplateChange::RemoveInstance {
+                ..
+            } => 0,
+            TemplateChange::Delete(_) => 1,
+            TemplateChange::Create(_) => 2,
+            TemplateChange::Update(_) => 3,
+            TemplateChange::AddInstance {
+                ..
+            } => 4,
+        }
+    }
+}
+
+impl ConfigurationChanges {
+    pub fn is_empty(&self) -> bool {
+        self.tags.is_empty() && self.aliases.is_empty() && self.templates.is_empty()
+    }
+}
+
+/// Configuration the server is running with, compared against an import.
+#[derive(Debug, Clone, Default)]
+pub struct Existing {
+    pub tags: Vec<Tag>,
+    pub aliases: Vec<TagAlias>,
+    pub templates: Vec<(TagTemplate, Vec<TagName>)>,
+    pub drivers: Vec<DriverConfig>,
+}
+
+impl From<TagAlias> for AliasConfig {
+    fn from(alias: TagAlias) -> Self {
+        AliasConfig {
+            alias: alias.alias.to_string(),
+            target: alias.target.to_string(),
+        }
+    }
+}
+
+impl From<Existing> for Configuration {
+    /// Sorted export of the configuration, with the driver passwords redacted.
+    fn from(existing: Existing) -> Self {
+        let mut tags: Vec<TagConfig> = existing.tags.into_iter().map(Into::into).collect();
+        tags.sort_by(|a, b| a.name.cmp(&b.name));
+        let mut templates: Vec<TemplateConfig> = existing
+            .templates
+            .into_iter()
+            .map(|(template, instances)| TemplateConfig {
+                template,
+                instances: instances.iter().map(ToString::to_string).collect(),
+            })
+            .collect();
+        templates.sort_by(|a, b| a.template.name.cmp(&b.template.name));
+
+        Configuration {
+            tags,
+            aliases: Some(existing.aliases.into_iter().map(Into::into).collect()),
+            templates: Some(templates),
+            drivers: Some(
+                existing
+                    .drivers
+                    .iter()
+                    .map(DriverConfig::redacted)
+                    .collect(),
+            ),
+        }
+    }
+}
+
+impl From<Tag> for TagConfig {
+    fn from(tag: Tag) -> Self {
+        TagConfig {
+            name: tag.name.to_string(),
+            meta: tag.meta,
+        }
+    }
+}
+
+fn find_duplicate<'a>(names: impl IntoIterator<Item = &'a str>) -> Option<&'a str> {
+    let mut seen = HashSet::new();
+    names.into_iter().find(|name| !seen.insert(*name))
+}
+
+impl Configuration {
+    /// Returns the kind and name of the first tag, alias or template that
+    /// occurs more than once.
+    pub fn find_duplicate(&self) -> Option<(&'static str, &str)> {
+        let tags = find_duplicate(self.tags.iter().map(|tag| tag.name.as_str()));
+        let aliases = self.aliases.iter().flatten().map(|a| a.alias.as_str());
+        let templates = self
+            .templates
+            .iter()
+            .flatten()
+            .map(|t| t.template.name.as_str());
+        tags.map(|name| ("tag", name))
+            .or_else(|| find_duplicate(aliases).map(|name| ("alias", name)))
+            .or_else(|| find_duplicate(templates).map(|name| ("template", name)))
+    }
+
+    /// Computes the changes that turn the `existing` configuration into this one.
+    pub fn diff(
+        self,
+        existing: Existing,
+        query: &ImportQuery,
+    ) -> (ConfigurationChanges, ImportResponse) {
+        let mut response = ImportResponse {
+            dry_run: query.dry_run,
+            ..Default::default()
+        };
+        let mut changes = ConfigurationChanges {
+            tags: diff_tags(self.tags, existing.tags, query, &mut response),
+            ..Default::default()
+        };
+        if let Some(aliases) = self.aliases {
+            changes.aliases = diff_aliases(aliases, existing.aliases, query, &mut response.aliases);
+        }
+        if let Some(templates) = self.templates {
+            changes.templates = diff_templates(
+                templates,
+                existing.templates,
+                query,
+                &mut response.templates,
+            );
+        }
+        if let Some(drivers) = self.drivers {
+            let running: Vec<DriverConfig> = existing
+                .drivers
+                .iter()
+                .map(DriverConfig::redacted)
+                .collect();
+            let names: BTreeSet<&str> = drivers
+                .iter()
+                .chain(&running)
+                .map(DriverConfig::name)
+                .collect();
+            for name in names {
+                let driver = |drivers: &[DriverConfig]| {
+                    drivers
+                        .iter()
+                        .find(|driver| driver.name() == name)
+                        .map(DriverConfig::redacted)
+                };
+                if driver(&drivers) != driver(&running) {
+                    response.drivers.push(name.to_string());
+                }
+            }
+        }
+        (changes, response)
+    }
+}
+
+fn diff_tags(
+    tags: Vec<TagConfig>,
+    existing: Vec<Tag>,
+    query: &ImportQuery,
+    response: &mut ImportResponse,
+) -> Vec<TagChange> {
+    let mut existing: BTreeMap<TagName, TagMeta> = existing
+        .into_iter()
+        .map(|tag| (tag.name, tag.meta))
+        .collect();
+    let mut changes = Vec::new();
+
+    for tag in tags {
+        let name = TagName::from(tag.name);
+        match existing.remove(&name) {
+            None => {
+                response.create.push(name.to_string());
+                changes.push(TagChange::Create {
+                    name,
+                    meta: tag.meta,
+                });
+            },
+            Some(meta) if meta == tag.meta => response.unchanged += 1,
+            Some(_) if query.policy != ConflictPolicy::Overwrite => {
+                response.conflicts.push(name.to_string());
+            },
+            Some(_) => {
+                response.update.push(name.to_string());
+                changes.push(TagChange::Update {
+                    name,
+                    meta: tag.meta,
+                });
+            },
+        }
+    }
+
+    if query.delete_missing {
+        for name in existing.into_keys() {
+            response.delete.push(name.to_string());
+            changes.push(TagChange::Delete {
+                name,
+            });
+        }
+    }
+
+    changes
+}
+
+/// A changed alias is deleted and created again.
+fn diff_aliases(
+    aliases: Vec<AliasConfig>,
+    existing: Vec<TagAlias>,
+    query: &ImportQuery,
+    report: &mut ImportChanges,
+) -> Vec<AliasChange> {
+    let mut existing: BTreeMap<TagName, TagName> = existing
+        .into_iter()
+        .map(|alias| (alias.alias, alias.target))
+        .collect();
+    let mut deleted = Vec::new();
+    let mut created = Vec::new();
+
+    for entry in aliases {
+        let alias = TagName::from(entry.alias);
+        let target = TagName::from(entry.target);
+        match existing.remove(&alias) {
+            None => report.create.push(alias.to_string()),
+            Some(current) if current == target => {
+                report.unchanged += 1;
+                continue;
+            },
+            Some(_) if query.policy != ConflictPolicy::Overwrite => {
+                report.conflicts.push(alias.to_string());
+                continue;
+            },
+            Some(_) => {
+                report.update.push(alias.to_string());
+                deleted.push(AliasChange::Delete {
+                    alias: alias.clone(),
+                });
+            },
+        }
+        created.push(AliasChange::Create {
+            alias,
+            target,
+        });
+    }
+
+    if query.delete_missing {
+        for alias in existing.into_keys() {
+            report.delete.push(alias.to_string());
+            deleted.push(AliasChange::Delete {
+                alias,
+            });
+        }
+    }
+
+    deleted.extend(created);
+    deleted
+}
+
+/// Instances are added to existing templates without a conflict, their tags
+/// are expected in the tags of the import.
+fn diff_templates(
+    templates: Vec<TemplateConfig>,
+    existing: Vec<(TagTemplate, Vec<TagName>)>,
+    query: &ImportQuery,
+    report: &mut ImportChanges,
+) -> Vec<TemplateChange> {
+    let mut existing: BTreeMap<TemplateName, (TagTemplate, Vec<TagName>)> = existing
+        .into_iter()
+        .map(|(template, instances)| (template.name.clone(), (template, instances)))
+        .collect();
+    let mut changes = Vec::new();
+
+    for entry in templates {
+        let name = entry.template.name.clone();
+        let instances: Vec<TagName> = entry.instances.into_iter().map(TagName::from).collect();
+        let (current, listed) = match existing.remove(&name) {
+            None => {
+                report.create.push(name.to_string());
+                changes.push(TemplateChange::Create(entry.template));
+                (Vec::new(), true)
+            },
+            Some((template, current)) if template == entry.template => (current, false),
+            Some(_) if query.policy != ConflictPolicy::Overwrite => {
+                report.conflicts.push(name.to_string());
+                continue;
+            },
+            Some((_, current)) => {
+                report.update.push(name.to_string());
+                changes.push(TemplateChange::Update(entry.template));
+                (current, true)
+            },
+        };
+
+        let mut changed = false;
+        for prefix in &instances {
+            if !current.contains(prefix) {
+                changed = true;
+                changes.push(TemplateChange::AddInstance {
+                    template: name.clone(),
+                    prefix: prefix.clone(),
+                });
+            }
+        }
+        if query.delete_missing {
+            for prefix in current.iter().filter(|prefix| !instances.contains(prefix)) {
+                changed = true;
+                changes.push(TemplateChange::RemoveInstance {
+                    template: name.clone(),
+                    prefix: prefix.clone(),
+                });
+            }
+        }
+        if !listed {
+            if changed {
+                report.update.push(name.to_string());
+            } else {
+                report.unchanged += 1;
+            }
+        }
+    }
+
+    if query.delete_missing {
+        for (name, (_, instances)) in existing {
+            report.delete.push(name.to_string());
+            for prefix in instances {
+                changes.push(TemplateChange::RemoveInstance {
+                    template: name.clone(),
+                    prefix,
+                });
+            }
+            changes.push(TemplateChange::Delete(name));
+        }
+    }
+
+    changes.sort_by_key(TemplateChange::order);
+    changes
+}
+
+#[cfg(test)]
+mod tests {
+    use rcada_core::{
+        tag::TagValue,
+        unit::Unit,
+        value::{DataType, Value},
+    };
+
+    use super::*;
+    use crate::{
+        api::configuration::format,
+        driver::{
+            REDACTED,
+            opcua::OpcUaClientConfig,
+            s7::{S7Config, S7Point},
+        },
+        repository::template::TemplatePoint,
+    };
+
+    fn tag(name: &str) -> Tag {
+        let meta = TagMeta::new(Unit::Percent, DataType::Float);
+        Tag {
+            name: name.into(),
+            value: TagValue {
+                value: Value::Float(0.0),
+                timestamp: None,
+                quality: Default::default(),
+            },
+            meta,
+        }
+    }
+
+    fn server() -> Existing {
+        let template = TagTemplate {
+            name: "pump".into(),
+            description: String::new(),
+            points: vec![TemplatePoint {
+                name: "speed".into(),
+                meta: TagMeta::new(Unit::Percent, DataType::Float),
+            }],
+        };
+        let opcua: OpcUaClientConfig = toml::from_str(
+            r#"
+            name = "historian"
+            endpoint = "opc.tcp://127.0.0.1:4840/"
+            username = "rcada"
+            password = "secret"
+            "#,
+        )
+        .unwrap();
+        Existing {
+            tags: vec![tag("p1.speed"), tag("level")],
+            aliases: vec![TagAlias {
+                alias: "tank_level".into(),
+                target: "level".into(),
+            }],
+            templates: vec![(template, vec!["p1".into()])],
+            drivers: vec![
+                DriverConfig::S7(S7Config {
+                    name: "plc".into(),
+                    address: "127.0.0.1:102".into(),
+                    rack: 0,
+                    slot: 1,
+                    poll_interval_ms: 1000,
+                    timeout_ms: 1000,
+                    points: vec![S7Point {
+                        tag: "level".into(),
+                        address: "DB1.DBD0:REAL".into(),
+                        writable: false,
+                    }],
+                }),
+                DriverConfig::OpcUa(Box::new(opcua)),
+            ],
+        }
+    }
+
+    fn import(delete_missing: bool) -> ImportQuery {
+        ImportQuery {
+            delete_missing,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn exported_configuration_imports_without_changes() {
+        let exported = Configuration::from(server());
+        let Some(DriverConfig::OpcUa(opcua)) = &exported.drivers.as_ref().unwrap().get(1) else {
+            panic!("drivers not exported");
+        };
+        assert_eq!(opcua.password.as_deref(), Some(REDACTED));
+
+        for config_format in [ConfigFormat::Json, ConfigFormat::Yaml] {
+            let bytes = format::encode(exported.clone(), config_format).unwrap();
+            let decoded = format::decode(&bytes, config_format).unwrap();
+            assert_eq!(decoded, exported);
+
+            let (changes, response) = decoded.diff(server(), &import(true));
+            assert!(changes.is_empty(), "{changes:?}");
+            assert!(!response.conflicts());
+            assert!(response.drivers.is_empty());
+            assert_eq!(response.unchanged, 2);
+            assert_eq!(response.aliases.unchanged, 1);
+            assert_eq!(response.templates.unchanged, 1);
+        }
+    }
+
+    #[test]
+    fn exported_configuration_recreates_the_server() {
+        let exported = Configuration::from(server());
+        let (changes, response) = exported.diff(Existing::default(), &import(false));
+
+        assert_eq!(response.create, vec!["level", "p1.speed"]);
+        assert_eq!(
+            changes.aliases,
+            vec![AliasChange::Create {
+                alias: "tank_level".into(),
+                target: "level".into(),
+            }]
+        );
+        assert!(matches!(
+            changes.templates.as_slice(),
+            [
+                TemplateChange::Create(_),
+                TemplateChange::AddInstance { .. }
+            ]
+        ));
+        assert_eq!(response.drivers, vec!["historian", "plc"]);
+    }
+
+    #[test]
+    fn missing_sections_are_left_alone() {
+        let bytes = format::encode(Configuration::from(server()), ConfigFormat::Csv).unwrap();
+        let decoded = format::decode(&bytes, ConfigFormat::Csv).unwrap();
+        assert_eq!(decoded.aliases, None);
+
+        let (changes, response) = decoded.diff(server(), &import(true));
+        assert!(changes.is_empty(), "{changes:?}");
+        assert_eq!(response.aliases, ImportChanges::default());
+        assert_eq!(response.templates, ImportChanges::default());
+    }
+
+    #[test]
+    fn changed_template_instances_are_added_and_removed() {
+        let mut config = Configuration::from(server());
+        config.templates.as_mut().unwrap()[0].instances = vec!["p2".into()];
+
+        let (changes, response) = config.diff(server(), &import(true));
+        assert_eq!(
+            changes.templates,
+            vec![
+                TemplateChange::RemoveInstance {
+                    template: "pump".into(),
+                    prefix: "p1".into(),
+                },
+                TemplateChange::AddInstance {
+                    template: "pump".into(),
+                    prefix: "p2".into(),
+                },
+            ]
+        );
+        assert_eq!(response.templates.update, vec!["pump"]);
+    }
+}
@@ -0,0 +1,168 @@
+use std::fmt;
+
+use actix_web::{
+    HttpMessage, HttpRequest, HttpResponse, ResponseError,
+    error::{JsonPayloadError, PathError, QueryPayloadError},
+    http::StatusCode,
+    web::{JsonConfig, PathConfig, QueryConfig},
+};
+use serde::Serialize;
+use tracing_actix_web::RequestId;
+use utoipa::ToSchema;
+use uuid::Uuid;
+
+/// Machine-readable reason of a failed request.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum ErrorCode {
+    InvalidRequest,
+    InvalidRange,
+    InvalidAlarmLimits,
+    InvalidDataType,
+    TimestampRequired,
+    TimestampOutOfOrder,
+    Unauthorized,
+    InvalidCredentials,
+    PermissionDenied,
+    ReadOnly,
+    NotFound,
+    TagNotFound,
+    AliasNotFound,
+    TemplateNotFound,
+    InstanceNotFound,
+    RoleNotFound,
+    BindingNotFound,
+    LoginDisabled,
+    AlreadyExists,
+    Conflict,
+    IncompatibleDataType,
+    InvalidConfiguration,
+    Internal,
+}
+
+impl ErrorCode {
+    pub fn status(self) -> StatusCode {
+        match self {
+            ErrorCode::InvalidRequest
+            | ErrorCode::InvalidRange
+            | ErrorCode::InvalidAlarmLimits
+            | ErrorCode::InvalidDataType
+            | ErrorCode::TimestampRequired
+            | ErrorCode::TimestampOutOfOrder => StatusCode::BAD_REQUEST,
+            ErrorCode::Unauthorized | ErrorCode::InvalidCredentials => StatusCode::UNAUTHORIZED,
+            ErrorCode::PermissionDenied | ErrorCode::ReadOnly => StatusCode::FORBIDDEN,
+            ErrorCode::NotFound
+            | ErrorCode::TagNotFound
+            | ErrorCode::AliasNotFound
+            | ErrorCode::TemplateNotFound
+            | ErrorCode::InstanceNotFound
+            | ErrorCode::RoleNotFound
+            | ErrorCode::BindingNotFound
+            | ErrorCode::LoginDisabled => StatusCode::NOT_FOUND,
+            ErrorCode::AlreadyExists | ErrorCode::Conflict | ErrorCode::IncompatibleDataType => {
+                StatusCode::CONFLICT
+            },
+            ErrorCode::InvalidConfiguration => StatusCode::UNPROCESSABLE_ENTITY,
+            ErrorCode::Internal => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+}
+
+/// Body of every error response of the API.
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct ApiError {
+    pub code: ErrorCode,
+    pub message: String,
+    pub details: Option<serde_json::Value>,
+    pub request_id: Uuid,
+}
+
+impl ApiError {
+    pub fn new(request_id: Uuid, code: ErrorCode, message: impl Into<String>) -> Self {
+        Self {
+            code,
+            message: message.into(),
+            details: None,
+            request_id,
+        }
+    }
+
+    pub fn with_details(mut self, details: serde_json::Value) -> Self {
+        self.details = Some(details);
+        self
+    }
+
+    /// Logs `error` and hides it from the client.
+    pub fn internal(request_id: Uuid, error: impl fmt::Display) -> Self {
+        tracing::error!(%request_id, "internal error: {}", error);
+        Self::new(request_id, ErrorCode::Internal, "Internal server error")
+    }
+
+    /// Error raised outside of a handler, identified by the request id of
+    /// the tracing middleware.
+    pub fn from_request(req: &HttpRequest, code: ErrorCode, message: impl Into<String>) -> Self {
+        Self::new(request_id_of(req), code, message)
+    }
+}
+
+/// Request id assigned by the tracing middleware, or a new one if the
+/// request didn't pass through it.
+pub fn request_id_of(req: &impl HttpMessage) -> Uuid {
+    req.extensions()
+        .get::<RequestId>()
+        .map(|id| **id)
+        .unwrap_or_else(Uuid::new_v4)
+}
+
+impl fmt::Display for ApiError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:?}: {}", self.code, self.message)
+    }
+}
+
+impl ResponseError for ApiError {
+    fn status_code(&self) -> StatusCode {
+        self.code.status()
+    }
+
+    fn error_response(&self) -> HttpResponse {
+        HttpResponse::build(self.status_code()).json(self)
+    }
+}
+
+/// Extractor configs reporting malformed requests with an [`ApiError`].
+pub fn json_config() -> JsonConfig {
+    JsonConfig::default().error_handler(|e: JsonPayloadError, req| {
+        tracing::warn!(path = req.path(), "invalid JSON body: {}", e);
+        ApiError::from_request(req, ErrorCode::InvalidRequest, "Invalid JSON body")
+            .with_details(serde_json::json!({ "reason": e.to_string() }))
+            .into()
+    })
+}
+
+pub fn query_config() -> QueryConfig {
+    QueryConfig::default().error_handler(|e: QueryPayloadError, req| {
+        tracing::warn!(path = req.path(), "invalid query string: {}", e);
+        ApiError::from_request(req, ErrorCode::InvalidRequest, "Invalid query string")
+            .with_details(serde_json::json!({ "reason": e.to_string() }))
+            .into()
+    })
+}
+
+pub fn path_config() -> PathConfig {
+    PathConfig::default().error_handler(|e: PathError, req| {
+        tracing::warn!(path = req.path(), "invalid path: {}", e);
+        ApiError::from_request(req, ErrorCode::InvalidRequest, "Invalid path")
+            .with_details(serde_json::json!({ "reason": e.to_string() }))
+            .into()
+    })
+}
+
+/// Default service for paths that don't match any route.
+pub async fn not_found(req: HttpRequest) -> Result<HttpResponse, ApiError> {
+    Err(ApiError::from_request(
+        &req,
+        ErrorCode::NotFound,
+        "Resource not found",
+    ))
+}
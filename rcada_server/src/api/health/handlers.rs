@@ -1,8 +1,34 @@
-use actix_web::{HttpResponse, get};
+use std::time::{Duration, Instant};
+
+use actix_web::{
+    HttpRequest, HttpResponse, get,
+    web::{self, Data},
+};
+use ractor::ActorRef;
+use tokio::time::timeout;
 use tracing::instrument;
 
-use super::model::HealthResponse;
+use super::{
+    DataDir,
+    model::{ComponentHealth, ComponentStatus, HealthResponse, ReadinessResponse},
+};
+use crate::{
+    actor::{self, Mailbox},
+    api::error::request_id_of,
+};
+
+/// How long each component may take to answer the readiness check.
+const READINESS_DEADLINE: Duration = Duration::from_secs(1);
 
+/// File written and removed in the data directory to check it's writable.
+const PROBE_FILE: &str = ".readiness";
+
+#[utoipa::path(
+    tag = "health",
+    responses(
+        (status = 200, description = "Server is healthy", body = HealthResponse),
+    )
+)]
 #[get("")]
 #[instrument]
 pub async fn health_check() -> actix_web::Result<HttpResponse> {
@@ -10,3 +36,239 @@ pub async fn health_check() -> actix_web::Result<HttpResponse> {
         status: "healthy".to_string(),
     }))
 }
+
+/// The process is running and serving requests.
+#[utoipa::path(
+    tag = "health",
+    responses(
+        (status = 200, description = "Server is alive", body = HealthResponse),
+    )
+)]
+#[get("/live")]
+#[instrument]
+pub async fn liveness() -> actix_web::Result<HttpResponse> {
+    Ok(HttpResponse::Ok().json(HealthResponse {
+        status: "alive".to_string(),
+    }))
+}
+
+/// The server can do its work: the tag actor answers, the storage is
+/// writable and the drivers are connected.
+#[utoipa::path(
+    tag = "health",
+    responses(
+        (status = 200, description = "Every component is up", body = ReadinessResponse),
+        (status = 503, description = "A component is down", body = ReadinessResponse),
+    )
+)]
+#[get("/ready")]
+#[instrument(skip(tag_repo_actor, config_actor, data_dir, http_req))]
+pub async fn readiness(
+    tag_repo_actor: Data<ActorRef<actor::tag::Message>>,
+    config_actor: Data<ActorRef<actor::config::Message>>,
+    data_dir: Data<DataDir>,
+    http_req: HttpRequest,
+) -> actix_web::Result<HttpResponse> {
+    let request_id = request_id_of(&http_req);
+    tracing::info!(%request_id, "request: (readiness)");
+
+    let mut components = vec![
+        check_tag_repository(&tag_repo_actor).await,
+        check_storage(data_dir.0.clone()).await,
+    ];
+    components.extend(check_drivers(&config_actor).await);
+
+    let ready = components
+        .iter()
+        .all(|component| component.status == ComponentStatus::Up);
+    let response = ReadinessResponse {
+        status: if ready { "ready" } else { "not_ready" }.to_string(),
+        components,
+    };
+    if ready {
+        Ok(HttpResponse::Ok().json(response))
+    } else {
+        tracing::warn!(%request_id, "not ready: {:?}", response.components);
+        Ok(HttpResponse::ServiceUnavailable().json(response))
+    }
+}
+
+fn component(
+    name: &str,
+    start: Instant,
+    result: Result<Option<String>, String>,
+) -> ComponentHealth {
+    let (status, details) = match result {
+        Ok(details) => (ComponentStatus::Up, details),
+        Err(e) => (ComponentStatus::Down, Some(e)),
+    };
+    ComponentHealth {
+        name: name.to_string(),
+        status,
+        latency_ms: start.elapsed().as_millis() as u64,
+        details,
+    }
+}
+
+async fn check_tag_repository(tag_repo_actor: &ActorRef<actor::tag::Message>) -> ComponentHealth {
+    let start = Instant::now();
+    let (command, mut reply) = actor::tag::Message::ping();
+    let result = match tag_repo_actor.enqueue(command) {
+        Ok(()) => match timeout(READINESS_DEADLINE, reply.recv()).await {
+            Ok(Some(())) => Ok(None),
+            Ok(None) => Err("actor stopped".to_string()),
+            Err(_) => Err("no answer within the deadline".to_string()),
+        },
+        Err(e) => Err(e.to_string()),
+    };
+    component("tag_repository", start, result)
+}
+
+async fn check_storage(data_dir: std::path::PathBuf) -> ComponentHealth {
+    let start = Instant::now();
+    let probe = web::block(move || {
+        let path = data_dir.join(PROBE_FILE);
+        std::fs::write(&path, b"ok")?;
+        std::fs::remove_file(&path)
+    });
+    let result = match timeout(READINESS_DEADLINE, probe).await {
+        Ok(Ok(Ok(()))) => Ok(None),
+        Ok(Ok(Err(e))) => Err(e.to_string()),
+        Ok(Err(e)) => Err(e.to_string()),
+        Err(_) => Err("no answer within the deadline".to_string()),
+    };
+    component("storage", start, result)
+}
+
+/// One component per driver, named `driver/<name>`.
+async fn check_drivers(config_actor: &ActorRef<actor::config::Message>) -> Vec<ComponentHealth> {
+    let start = Instant::now();
+    let (command, mut reply) = actor::config::Message::get_driver_status();
+    let status = match config_actor.enqueue(command) {
+        Ok(()) => match timeout(READINESS_DEADLINE, reply.recv()).await {
+            Ok(Some(status)) => Ok(status),
+            Ok(None) => Err("config actor stopped".to_string()),
+            Err(_) => Err("no answer within the deadline".to_string()),
+        },
+        Err(e) => Err(e.to_string()),
+    };
+    let status = match status {
+        Ok(status) => status,
+        Err(e) => return vec![component("drivers", start, Err(e))],
+    };
+
+    status
+        .into_iter()
+        .map(|(name, status)| {
+            let result = match (status.connected, status.last_error) {
+                (true, _) => Ok(status
+                    .last_poll
+                    .map(|at| format!("last poll {}", at.to_rfc3339()))),
+                (false, Some(e)) => Err(e),
+                (false, None) => Err("not connected yet".to_string()),
+            };
+            component(&format!("driver/{name}"), start, result)
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use actix_web::{App, http::StatusCode, test};
+    use ractor::Actor;
+
+    use super::*;
+    use crate::{
+        actor::config::{ConfigActor, ConfigArguments},
+        config::{Args, ServerConfig},
+        driver::testing,
+    };
+
+    async fn ready(
+        tag_repo: ActorRef<actor::tag::Message>,
+        config: &str,
+        data_dir: PathBuf,
+    ) -> (StatusCode, ReadinessResponse) {
+        let (config_actor, _) = Actor::spawn(
+            None,
+            ConfigActor,
+            ConfigArguments {
+                args: Args::default(),
+                config: toml::from_str::<ServerConfig>(config).unwrap(),
+                tag_repo: tag_repo.clone(),
+                set_log_filter: Box::new(|_| Ok(())),
+                certificate: None,
+            },
+        )
+        .await
+        .unwrap();
+        let app = test::init_service(
+            App::new()
+                .app_data(Data::new(tag_repo))
+                .app_data(Data::new(config_actor.clone()))
+                .app_data(Data::new(DataDir(data_dir)))
+                .service(readiness),
+        )
+        .await;
+
+        let response =
+            test::call_service(&app, test::TestRequest::get().uri("/ready").to_request()).await;
+        let status = response.status();
+        let body = test::read_body_json(response).await;
+        config_actor.stop(None);
+        (status, body)
+    }
+
+    fn statuses(response: &ReadinessResponse) -> Vec<(&str, ComponentStatus)> {
+        response
+            .components
+            .iter()
+            .map(|component| (component.name.as_str(), component.status))
+            .collect()
+    }
+
+    #[actix_web::test]
+    async fn ready_when_every_component_is_up() {
+        let (status, response) = ready(testing::tag_repo().await, "", std::env::temp_dir()).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(response.status, "ready");
+        assert_eq!(
+            statuses(&response),
+            [
+                ("tag_repository", ComponentStatus::Up),
+                ("storage", ComponentStatus::Up)
+            ]
+        );
+    }
+
+    #[actix_web::test]
+    async fn not_ready_while_a_component_is_down() {
+        let tag_repo = testing::tag_repo().await;
+        let missing = std::env::temp_dir().join(format!("rcada-missing-{}", uuid::Uuid::new_v4()));
+        // Nothing listens on port 1
+        let driver = format!("plc-{}", uuid::Uuid::new_v4());
+        let config =
+            format!("[[drivers]]\nkind = \"s7\"\nname = \"{driver}\"\naddress = \"127.0.0.1:1\"\n");
+        let (status, response) = ready(tag_repo.clone(), &config, missing).await;
+        assert_eq!(status, StatusCode::SERVICE_UNAVAILABLE);
+        assert_eq!(response.status, "not_ready");
+        assert_eq!(
+            statuses(&response),
+            [
+                ("tag_repository", ComponentStatus::Up),
+                ("storage", ComponentStatus::Down),
+                (format!("driver/{driver}").as_str(), ComponentStatus::Down)
+            ]
+        );
+
+        tag_repo.stop(None);
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        let (_, response) = ready(tag_repo, "", std::env::temp_dir()).await;
+        assert_eq!(
+            statuses(&response)[0],
+            ("tag_repository", ComponentStatus::Down)
+        );
+    }
+}
@@ -1,7 +1,18 @@
 pub mod handlers;
 pub mod model;
 
-pub fn scope() -> actix_web::Scope {
-    actix_web::web::scope("/health")
+use std::path::PathBuf;
+
+use actix_web::dev::HttpServiceFactory;
+use utoipa_actix_web::OpenApiFactory;
+
+/// Directory the readiness check writes to, to test the storage.
+#[derive(Debug, Clone)]
+pub struct DataDir(pub PathBuf);
+
+pub fn scope() -> impl HttpServiceFactory + OpenApiFactory {
+    utoipa_actix_web::scope("/health")
         .service(handlers::health_check)
+        .service(handlers::liveness)
+        .service(handlers::readiness)
 }
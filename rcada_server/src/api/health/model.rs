@@ -1,6 +1,31 @@
 use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
 
-#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, ToSchema)]
 pub struct HealthResponse {
     pub status: String,
 }
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum ComponentStatus {
+    Up,
+    Down,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, ToSchema)]
+pub struct ComponentHealth {
+    pub name: String,
+    pub status: ComponentStatus,
+    /// Time the check took.
+    pub latency_ms: u64,
+    /// Why the component is down, or what it reported.
+    pub details: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, ToSchema)]
+pub struct ReadinessResponse {
+    /// `ready` if every component is up, `not_ready` otherwise.
+    pub status: String,
+    pub components: Vec<ComponentHealth>,
+}
@@ -0,0 +1,78 @@
+use actix_web::{HttpRequest, HttpResponse, get};
+use tracing::instrument;
+
+use crate::{
+    api::error::{ApiError, request_id_of},
+    metrics::metrics,
+};
+
+/// Content type of the Prometheus text format.
+const TEXT_FORMAT: &str = "text/plain; version=0.0.4; charset=utf-8";
+
+#[utoipa::path(
+    tag = "metrics",
+    responses(
+        (status = 200, description = "Metrics in the Prometheus text format", body = String, content_type = "text/plain"),
+    )
+)]
+#[get("")]
+#[instrument(skip(req))]
+pub async fn get_metrics(req: HttpRequest) -> Result<HttpResponse, ApiError> {
+    let body = metrics().encode().map_err(|e| {
+        ApiError::internal(
+            request_id_of(&req),
+            format!("failed to encode metrics: {e}"),
+        )
+    })?;
+    Ok(HttpResponse::Ok().content_type(TEXT_FORMAT).body(body))
+}
+
+#[cfg(test)]
+mod tests {
+    use actix_web::{App, http::header::CONTENT_TYPE, middleware::from_fn, test, web};
+
+    use crate::{
+        api::metrics::{middleware::record_duration, scope},
+        auth::{AuthConfig, Authenticator},
+        metrics::metrics,
+    };
+
+    #[actix_web::test]
+    async fn metrics_are_served_in_the_text_format() {
+        let app = test::init_service(
+            App::new()
+                .wrap(from_fn(record_duration))
+                .app_data(web::Data::new(
+                    Authenticator::new(&AuthConfig::default()).unwrap(),
+                ))
+                .service(scope())
+                .route("/metrics-test/{name}", web::get().to(|| async { "ok" })),
+        )
+        .await;
+        metrics()
+            .driver_polls
+            .with_label_values(&["metrics-test", "timeout"])
+            .inc();
+        for uri in ["/metrics-test/a", "/metrics-test/b", "/metrics-test"] {
+            test::call_service(&app, test::TestRequest::get().uri(uri).to_request()).await;
+        }
+
+        let response =
+            test::call_service(&app, test::TestRequest::get().uri("/metrics").to_request()).await;
+        assert!(response.status().is_success());
+        assert_eq!(
+            response.headers().get(CONTENT_TYPE).unwrap(),
+            super::TEXT_FORMAT
+        );
+        let body = String::from_utf8(test::read_body(response).await.to_vec()).unwrap();
+        for line in [
+            "# TYPE rcada_driver_polls_total counter",
+            r#"rcada_driver_polls_total{driver="metrics-test",result="timeout"} 1"#,
+            // By route pattern rather than path
+            r#"rcada_http_request_duration_seconds_count{method="GET",route="/metrics-test/{name}",status="200"} 2"#,
+            r#"rcada_http_request_duration_seconds_count{method="GET",route="unmatched",status="404"}"#,
+        ] {
+            assert!(body.contains(line), "{line} missing in\n{body}");
+        }
+    }
+}
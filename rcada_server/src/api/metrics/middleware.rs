@@ -0,0 +1,33 @@
+use actix_web::{
+    Error,
+    body::MessageBody,
+    dev::{ServiceRequest, ServiceResponse},
+    middleware::Next,
+};
+
+use crate::metrics::metrics;
+
+/// Route of requests that didn't match any endpoint, so that unknown paths
+/// don't create new series.
+const UNMATCHED_ROUTE: &str = "unmatched";
+
+/// Observes the time taken to answer each request by its route pattern.
+pub async fn record_duration(
+    req: ServiceRequest,
+    next: Next<impl MessageBody>,
+) -> Result<ServiceResponse<impl MessageBody>, Error> {
+    let method = req.method().to_string();
+    let start = std::time::Instant::now();
+    let response = next.call(req).await?;
+
+    let route = response.request().match_pattern();
+    metrics()
+        .http_duration
+        .with_label_values(&[
+            method.as_str(),
+            route.as_deref().unwrap_or(UNMATCHED_ROUTE),
+            response.status().as_str(),
+        ])
+        .observe(start.elapsed().as_secs_f64());
+    Ok(response)
+}
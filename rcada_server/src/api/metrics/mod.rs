@@ -0,0 +1,16 @@
+pub mod handlers;
+pub mod middleware;
+
+use actix_web::dev::HttpServiceFactory;
+use utoipa_actix_web::OpenApiFactory;
+
+use crate::api::auth;
+
+/// `/metrics`, outside of `/api/v1` where Prometheus expects it.
+pub fn scope() -> impl HttpServiceFactory + OpenApiFactory {
+    utoipa_actix_web::scope("/metrics")
+        .wrap(actix_web::middleware::from_fn(
+            auth::middleware::authenticate,
+        ))
+        .service(handlers::get_metrics)
+}
@@ -1,8 +1,383 @@
+pub mod access;
+pub mod admin;
+pub mod aliases;
+pub mod audit;
+pub mod auth;
+pub mod configuration;
+pub mod error;
 pub mod health;
+pub mod metrics;
+pub mod openapi;
 pub mod tags;
+pub mod templates;
 
-pub fn scope() -> actix_web::Scope {
-    actix_web::web::scope("/api/v1")
+use actix_web::dev::HttpServiceFactory;
+use utoipa_actix_web::OpenApiFactory;
+
+/// All endpoints, behind the authentication middleware.
+pub fn scope() -> impl HttpServiceFactory + OpenApiFactory {
+    utoipa_actix_web::scope("/api/v1")
+        .wrap(actix_web::middleware::from_fn(
+            auth::middleware::authenticate,
+        ))
         .service(health::scope())
+        .service(auth::scope())
         .service(tags::scope())
+        .service(aliases::scope())
+        .service(templates::scope())
+        .service(configuration::scope())
+        .service(admin::scope())
+        .service(access::scope())
+        .service(audit::scope())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        any::type_name,
+        collections::{BTreeMap, BTreeSet},
+    };
+
+    use actix_web::web::Query;
+    use chrono::Utc;
+    use rcada_core::{
+        tag::{AlarmLimits, EngineeringRange, Quality, Tag, TagMeta, TagValue},
+        unit::Unit,
+        value::{DataType, Value},
+    };
+    use rcada_sdk::model as sdk;
+    use serde::{Serialize, de::DeserializeOwned};
+    use uuid::Uuid;
+
+    use super::*;
+    use crate::{
+        access::{AccessModel, Grant, Permission, Role, Subject},
+        actor::{config::ReloadReport, template::InstanceUpdate},
+        audit::{AuditAction, AuditQuery, AuditRecord, VerifyReport},
+        auth::{AuthMethod, Identity, Token},
+        repository::{
+            tag::UpdateValueResult,
+            template::{TagTemplate, TemplatePoint},
+        },
+    };
+
+    /// Decodes what the server sends with the SDK type `T` and checks that
+    /// nothing was lost or renamed on the way.
+    fn round_trip<T: Serialize + DeserializeOwned>(server: &impl Serialize) -> T {
+        let json = serde_json::to_value(server).unwrap();
+        let decoded: T = serde_json::from_value(json.clone())
+            .unwrap_or_else(|e| panic!("{}: {e}: {json}", type_name::<T>()));
+        assert_eq!(
+            serde_json::to_value(&decoded).unwrap(),
+            json,
+            "{}",
+            type_name::<T>()
+        );
+        decoded
+    }
+
+    fn meta() -> TagMeta {
+        TagMeta {
+            description: "Level of tank 1".to_string(),
+            range: Some(EngineeringRange {
+                low: 0.0,
+                high: 100.0,
+            }),
+            precision: Some(1),
+            read_only: true,
+            labels: BTreeMap::from([("area".to_string(), "north".to_string())]),
+            alarms: AlarmLimits {
+                high: Some(90.0),
+                ..AlarmLimits::default()
+            },
+            expression: Some("level * 2".to_string()),
+            ..TagMeta::new(Unit::Percent, DataType::Float)
+        }
+    }
+
+    fn template() -> TagTemplate {
+        TagTemplate {
+            name: "pump".into(),
+            description: "Feed pump".to_string(),
+            points: vec![TemplatePoint {
+                name: "speed".to_string(),
+                meta: meta(),
+            }],
+        }
+    }
+
+    #[test]
+    fn sdk_reads_tags_as_the_server_writes_them() {
+        let tag = Tag {
+            name: "tank1/level".into(),
+            value: TagValue {
+                value: Value::Float(42.5),
+                timestamp: Some(Utc::now()),
+                quality: Quality::Uncertain,
+            },
+            meta: meta(),
+        };
+        // The SDK ignores the data type the server repeats next to the value
+        let response = tags::model::TagResponse::from(tag.clone());
+        let decoded: Tag = serde_json::from_value(serde_json::to_value(response).unwrap()).unwrap();
+        assert_eq!(decoded, tag);
+
+        for (server, sdk) in [
+            (UpdateValueResult::Updated, sdk::WriteResult::Updated),
+            (UpdateValueResult::Ignored, sdk::WriteResult::Ignored),
+        ] {
+            assert_eq!(round_trip::<sdk::WriteResult>(&server), sdk);
+        }
+        round_trip::<sdk::Alias>(&aliases::model::AliasResponse {
+            alias: "level".to_string(),
+            target: "tank1/level".to_string(),
+        });
+    }
+
+    #[test]
+    fn sdk_reads_templates_as_the_server_writes_them() {
+        round_trip::<sdk::TagTemplate>(&template());
+        round_trip::<sdk::TemplateUpdate>(&templates::model::UpdateTemplateResponse {
+            template: template(),
+            instances: vec![InstanceUpdate {
+                prefix: "p1".into(),
+                created: vec!["p1/speed".into()],
+                updated: vec!["p1/level".into()],
+                deleted: vec!["p1/flow".into()],
+                failed: vec![("p1/power".into(), "read only".to_string())],
+            }],
+        });
+        round_trip::<sdk::Instance>(&templates::model::InstanceResponse {
+            prefix: "p1".to_string(),
+            tags: vec!["p1/speed".to_string()],
+        });
+    }
+
+    #[test]
+    fn sdk_reads_configurations_as_the_server_writes_them() {
+        let driver: crate::driver::DriverConfig = serde_json::from_value(serde_json::json!({
+            "kind": "s7",
+            "name": "plc",
+            "address": "127.0.0.1:102",
+            "points": [{"tag": "tank1/level", "address": "DB1.DBD0:REAL"}],
+        }))
+        .unwrap();
+        let configuration = configuration::model::Configuration {
+            tags: vec![configuration::model::TagConfig {
+                name: "tank1/level".to_string(),
+                meta: meta(),
+            }],
+            aliases: Some(vec![configuration::model::AliasConfig {
+                alias: "level".to_string(),
+                target: "tank1/level".to_string(),
+            }]),
+            templates: Some(vec![configuration::model::TemplateConfig {
+                template: template(),
+                instances: vec!["p1".to_string()],
+            }]),
+            drivers: Some(vec![driver]),
+        };
+        round_trip::<sdk::Configuration>(&configuration);
+
+        let changes = |name: &str| configuration::model::ImportChanges {
+            create: vec![format!("{name}1")],
+            update: vec![format!("{name}2")],
+            delete: vec![format!("{name}3")],
+            conflicts: vec![format!("{name}4")],
+            unchanged: 5,
+        };
+        round_trip::<sdk::ImportReport>(&configuration::model::ImportResponse {
+            dry_run: true,
+            applied: false,
+            create: vec!["a".to_string()],
+            update: vec!["b".to_string()],
+            delete: vec!["c".to_string()],
+            conflicts: vec!["d".to_string()],
+            unchanged: 3,
+            aliases: changes("alias"),
+            templates: changes("template"),
+            drivers: vec!["plc".to_string()],
+        });
+        round_trip::<sdk::ReloadReport>(&ReloadReport {
+            tags_created: vec!["a".into()],
+            tags_updated: vec!["b".into()],
+            tags_deleted: vec!["c".into()],
+            drivers_started: vec!["plc".to_string()],
+            drivers_restarted: vec!["rtu".to_string()],
+            drivers_stopped: vec!["broker".to_string()],
+            log_filter_changed: true,
+            certificate_reloaded: true,
+            restart_required: vec!["http.bind".to_string()],
+        });
+        for (format, policy) in [
+            (
+                configuration::model::ConfigFormat::Yaml,
+                configuration::model::ConflictPolicy::Skip,
+            ),
+            (
+                configuration::model::ConfigFormat::Csv,
+                configuration::model::ConflictPolicy::Overwrite,
+            ),
+        ] {
+            round_trip::<sdk::ConfigFormat>(&format);
+            round_trip::<sdk::ConflictPolicy>(&policy);
+        }
+    }
+
+    #[test]
+    fn sdk_reads_access_and_audit_as_the_server_writes_them() {
+        for method in [
+            AuthMethod::Anonymous,
+            AuthMethod::ApiKey,
+            AuthMethod::Jwt,
+            AuthMethod::ClientCertificate,
+            AuthMethod::Password,
+        ] {
+            round_trip::<sdk::Identity>(&Identity {
+                name: "alice".to_string(),
+                method,
+                roles: vec!["operator".to_string()],
+            });
+        }
+        round_trip::<sdk::Token>(&Token {
+            token: "header.claims.signature".to_string(),
+            expires_at: Utc::now(),
+        });
+        round_trip::<sdk::AccessModel>(&AccessModel {
+            roles: vec![Role {
+                name: "operator".to_string(),
+                description: "Runs the plant".to_string(),
+                grants: vec![Grant {
+                    permissions: vec![
+                        Permission::Read,
+                        Permission::Write,
+                        Permission::Configure,
+                        Permission::Admin,
+                    ],
+                    tags: "line1/*".to_string(),
+                }],
+            }],
+            bindings: BTreeMap::from([(
+                Subject::try_from("cert:historian".to_string()).unwrap(),
+                BTreeSet::from(["operator".to_string()]),
+            )]),
+        });
+        round_trip::<sdk::MyAccess>(&access::model::MyAccessResponse {
+            name: "alice".to_string(),
+            roles: BTreeSet::from(["operator".to_string()]),
+        });
+
+        for action in [
+            AuditAction::CreateTag,
+            AuditAction::UpdateValue,
+            AuditAction::UpdateMeta,
+            AuditAction::DeleteTag,
+            AuditAction::RenameTag,
+            AuditAction::CreateAlias,
+            AuditAction::DeleteAlias,
+        ] {
+            round_trip::<sdk::AuditRecord>(&AuditRecord {
+                seq: 7,
+                timestamp: Utc::now(),
+                user: "alice".to_string(),
+                source: Some("127.0.0.1:50000".to_string()),
+                request_id: Some(Uuid::new_v4()),
+                action,
+                tag: "tank1/level".into(),
+                old: Some(serde_json::json!(1.0)),
+                new: Some(serde_json::json!(2.0)),
+                result: "ok".to_string(),
+                prev_hash: "00".to_string(),
+                hash: "ff".to_string(),
+            });
+        }
+        round_trip::<sdk::VerifyReport>(&VerifyReport {
+            valid: false,
+            records: 7,
+            broken_at: Some(3),
+            reason: Some("hash mismatch".to_string()),
+        });
+    }
+
+    #[test]
+    fn sdk_reads_health_as_the_server_writes_it() {
+        round_trip::<sdk::Health>(&health::model::HealthResponse {
+            status: "ok".to_string(),
+        });
+        let readiness = round_trip::<sdk::Readiness>(&health::model::ReadinessResponse {
+            status: "not_ready".to_string(),
+            components: vec![health::model::ComponentHealth {
+                name: "driver/plc".to_string(),
+                status: health::model::ComponentStatus::Down,
+                latency_ms: 3,
+                details: Some("connection refused".to_string()),
+            }],
+        });
+        assert!(!readiness.is_ready());
+    }
+
+    #[test]
+    fn server_reads_requests_as_the_sdk_writes_them() {
+        let patch = sdk::TagMetaPatch {
+            unit: Some(Unit::Percent),
+            description: Some("Level".to_string()),
+            range: Some(None),
+            precision: Some(Some(2)),
+            labels: Some(BTreeMap::from([("area".to_string(), None)])),
+            expression: Some(None),
+            ..sdk::TagMetaPatch::default()
+        };
+        let request: tags::model::UpdateTagMetaRequest =
+            serde_json::from_value(serde_json::to_value(&patch).unwrap()).unwrap();
+        assert_eq!(
+            request,
+            tags::model::UpdateTagMetaRequest {
+                unit: Some(Unit::Percent),
+                description: Some("Level".to_string()),
+                range: Some(None),
+                precision: Some(Some(2)),
+                labels: Some(BTreeMap::from([("area".to_string(), None)])),
+                expression: Some(None),
+                ..tags::model::UpdateTagMetaRequest::default()
+            }
+        );
+
+        let options = sdk::ImportOptions {
+            dry_run: true,
+            policy: sdk::ConflictPolicy::Overwrite,
+            delete_missing: true,
+        };
+        let query = serde_urlencoded::to_string(options).unwrap();
+        let Query(query) = Query::<configuration::model::ImportQuery>::from_query(&query).unwrap();
+        assert!(query.dry_run && query.delete_missing);
+        assert_eq!(
+            query.policy,
+            configuration::model::ConflictPolicy::Overwrite
+        );
+
+        let request_id = Uuid::new_v4();
+        let filter = sdk::AuditQuery {
+            tag: Some("tank1/level".to_string()),
+            user: Some("alice".to_string()),
+            action: Some(sdk::AuditAction::RenameTag),
+            request_id: Some(request_id),
+            from: Some(Utc::now()),
+            limit: Some(10),
+            ..sdk::AuditQuery::default()
+        };
+        let query = serde_urlencoded::to_string(&filter).unwrap();
+        let Query(query) = Query::<AuditQuery>::from_query(&query).unwrap();
+        assert_eq!(
+            query,
+            AuditQuery {
+                tag: filter.tag.clone(),
+                user: filter.user.clone(),
+                action: Some(AuditAction::RenameTag),
+                request_id: Some(request_id),
+                from: filter.from,
+                to: None,
+                limit: Some(10),
+            }
+        );
+    }
 }
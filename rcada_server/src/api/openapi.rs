@@ -0,0 +1,142 @@
+use actix_web::{
+    App, HttpResponse,
+    dev::HttpServiceFactory,
+    web::{self, Data},
+};
+use utoipa::{
+    Modify, OpenApi,
+    openapi::{
+        self,
+        security::{ApiKey, ApiKeyValue, HttpAuthScheme, HttpBuilder, SecurityScheme},
+    },
+};
+use utoipa_actix_web::AppExt;
+use utoipa_scalar::{Scalar, Servable};
+
+const OPENAPI_PATH: &str = "/api/v1/openapi.json";
+const DOCS_PATH: &str = "/api/v1/docs";
+
+#[derive(OpenApi)]
+#[openapi(
+    info(title = "RCADA", description = "Tags, templates and administration of an RCADA server."),
+    modifiers(&SecuritySchemes),
+    security(("api_key" = []), ("bearer" = [])),
+    tags(
+        (name = "tags", description = "Tags and their values"),
+        (name = "aliases", description = "Alternative names of tags"),
+        (name = "templates", description = "Tag templates and their instances"),
+        (name = "configuration", description = "Import and export of the tag configuration"),
+        (name = "admin", description = "Server administration"),
+        (name = "auth", description = "Login and identity of the caller"),
+        (name = "access", description = "Roles and role bindings"),
+        (name = "audit", description = "Audit log of tag changes"),
+        (name = "health", description = "Health checks"),
+        (name = "metrics", description = "Prometheus metrics"),
+    )
+)]
+struct ApiDoc;
+
+struct SecuritySchemes;
+
+impl Modify for SecuritySchemes {
+    fn modify(&self, openapi: &mut openapi::OpenApi) {
+        let components = openapi.components.get_or_insert_with(Default::default);
+        components.add_security_scheme(
+            "api_key",
+            SecurityScheme::ApiKey(ApiKey::Header(ApiKeyValue::new("X-API-Key"))),
+        );
+        components.add_security_scheme(
+            "bearer",
+            SecurityScheme::Http(
+                HttpBuilder::new()
+                    .scheme(HttpAuthScheme::Bearer)
+                    .bearer_format("JWT")
+                    .build(),
+            ),
+        );
+    }
+}
+
+/// Document of every route of [`super::scope`] and [`super::metrics::scope`],
+/// collected from the `#[utoipa::path]` attributes of their handlers.
+pub fn openapi() -> openapi::OpenApi {
+    let (_, openapi) = App::new()
+        .into_utoipa_app()
+        .openapi(ApiDoc::openapi())
+        .service(super::metrics::scope())
+        .service(super::scope())
+        .split_for_parts();
+    openapi
+}
+
+/// `/api/v1/openapi.json` and the interactive docs at `/api/v1/docs`. Both are
+/// public and must be registered before [`super::scope`].
+pub fn services(openapi: openapi::OpenApi) -> impl HttpServiceFactory {
+    (
+        web::resource(OPENAPI_PATH)
+            .app_data(Data::new(openapi.clone()))
+            .get(get_openapi),
+        Scalar::with_url(DOCS_PATH, openapi).title("RCADA API"),
+    )
+}
+
+async fn get_openapi(openapi: Data<openapi::OpenApi>) -> HttpResponse {
+    HttpResponse::Ok().json(openapi.as_ref())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{collections::BTreeSet, fs, path::Path};
+
+    use super::openapi;
+
+    const ROUTE_ATTRIBUTES: [&str; 5] = ["#[get(", "#[post(", "#[put(", "#[patch(", "#[delete("];
+
+    /// Names of the functions with an actix route attribute below `dir`.
+    fn route_handlers(dir: &Path, handlers: &mut BTreeSet<String>) {
+        for entry in fs::read_dir(dir).unwrap() {
+            let path = entry.unwrap().path();
+            if path.is_dir() {
+                route_handlers(&path, handlers);
+                continue;
+            }
+            let mut routed = false;
+            for line in fs::read_to_string(&path).unwrap().lines().map(str::trim) {
+                if ROUTE_ATTRIBUTES.iter().any(|attr| line.starts_with(attr)) {
+                    routed = true;
+                } else if routed && let Some(signature) = line.strip_prefix("pub async fn ") {
+                    let name = signature.split('(').next().unwrap_or_default();
+                    handlers.insert(name.to_string());
+                    routed = false;
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn every_route_is_documented() {
+        let doc = serde_json::to_value(openapi()).unwrap();
+        let documented: BTreeSet<String> = doc["paths"]
+            .as_object()
+            .unwrap()
+            .values()
+            .filter_map(|item| item.as_object())
+            .flat_map(|item| item.values())
+            .filter_map(|operation| operation["operationId"].as_str())
+            .map(str::to_string)
+            .collect();
+
+        let mut handlers = BTreeSet::new();
+        route_handlers(
+            &Path::new(env!("CARGO_MANIFEST_DIR")).join("src/api"),
+            &mut handlers,
+        );
+
+        assert!(!handlers.is_empty());
+        let undocumented: Vec<&String> = handlers.difference(&documented).collect();
+        assert!(
+            undocumented.is_empty(),
+            "routes missing from the OpenAPI document: {undocumented:?}"
+        );
+    }
+}
@@ -1,190 +1,261 @@
 use actix_web::{
-    HttpResponse, delete, get, post, put,
-    web::{Data, Json, Path},
+    HttpRequest, HttpResponse, delete, get, patch, post, put,
+    web::{Data, Json, Path, ReqData},
 };
 use ractor::ActorRef;
 use tracing::instrument;
-use uuid::Uuid;
 
 use crate::{
+    access::AccessControl,
     actor,
-    repository::tag::{CreateTagResult, DeleteTagError, UpdateValueError, UpdateValueResult},
+    api::error::{ApiError, request_id_of},
+    auth::Identity,
+    repository::tag::CreateTagResult,
 };
 
-use super::model::{
-    CreateTagRequest, CreateTagResponse, ListTagsResponse, TagResponse, UpdateValueRequest,
-    UpdateValueResponse,
+use super::{
+    model::{
+        CreateTagRequest, CreateTagResponse, ListTagsResponse, RenameTagRequest, TagResponse,
+        UpdateTagMetaRequest, UpdateValueRequest, UpdateValueResponse,
+    },
+    service::TagService,
 };
 
+#[utoipa::path(
+    tag = "tags",
+    request_body = CreateTagRequest,
+    responses(
+        (status = 201, description = "Tag created", body = CreateTagResponse),
+        (status = 400, description = "Invalid range or alarm limits", body = ApiError),
+        (status = 403, description = "Permission denied", body = ApiError),
+        (status = 409, description = "Tag already exists", body = ApiError),
+    )
+)]
 #[post("")]
-#[instrument(skip(tag_repo_actor, req))]
+#[instrument(skip(tag_repo_actor, access, identity, req, http_req))]
 pub async fn create_tag(
     tag_repo_actor: Data<ActorRef<actor::tag::Message>>,
+    access: Data<AccessControl>,
+    identity: ReqData<Identity>,
+    http_req: HttpRequest,
     req: Json<CreateTagRequest>,
-) -> actix_web::Result<HttpResponse> {
-    let request_id = Uuid::new_v4();
-    tracing::info!(%request_id, "request: (create_tag) name={}", req.name);
-
-    let (command, mut reply) =
-        actor::tag::Message::create_tag(req.name.clone(), req.unit, req.data_type);
-
-    tag_repo_actor.send_message(command).map_err(|e| {
-        tracing::error!(error = %e, "failed to send message to tag actor");
-        actix_web::error::ErrorInternalServerError("Failed to send message to actor")
-    })?;
-
-    let result = reply.recv().await.ok_or_else(|| {
-        tracing::error!("actor response channel closed before reply received");
-        actix_web::error::ErrorInternalServerError("No response from actor")
-    })?;
-
-    match result {
-        CreateTagResult::SuccessfullyCreated => {
-            Ok(HttpResponse::Created().json(CreateTagResponse {
-                name: req.name.clone(),
-                result: CreateTagResult::SuccessfullyCreated,
-            }))
-        },
-        CreateTagResult::AlreadyExists => Ok(HttpResponse::Conflict().json(CreateTagResponse {
-            name: req.name.clone(),
-            result: CreateTagResult::AlreadyExists,
-        })),
-    }
+) -> Result<HttpResponse, ApiError> {
+    let service = TagService::new(
+        &tag_repo_actor,
+        &access,
+        &identity,
+        http_req.peer_addr(),
+        request_id_of(&http_req),
+    );
+    tracing::info!(request_id = %service.request_id(), "request: (create_tag) name={}", req.name);
+
+    service.create_tag(&req.name, (&req.0).into()).await?;
+    Ok(HttpResponse::Created().json(CreateTagResponse {
+        name: req.name.clone(),
+        result: CreateTagResult::SuccessfullyCreated,
+    }))
 }
 
+/// Lists the tags the caller may read.
+#[utoipa::path(
+    tag = "tags",
+    responses(
+        (status = 200, description = "Tags the caller may read", body = ListTagsResponse),
+    )
+)]
 #[get("")]
-#[instrument(skip(tag_repo_actor))]
+#[instrument(skip(tag_repo_actor, access, identity, http_req))]
 pub async fn list_tags(
     tag_repo_actor: Data<ActorRef<actor::tag::Message>>,
-) -> actix_web::Result<HttpResponse> {
-    let request_id = Uuid::new_v4();
-    tracing::info!(%request_id, "request (list_tags)");
-    let (command, mut reply) = actor::tag::Message::get_all_tags();
-    tag_repo_actor
-        .send_message(command)
-        .map_err(actix_web::error::ErrorInternalServerError)?;
-    let tags = reply.recv().await.ok_or_else(|| {
-        tracing::error!("actor response channel closed before reply received");
-        actix_web::error::ErrorInternalServerError("Channel closed")
-    })?;
-
-    let responses: Vec<TagResponse> = tags.into_iter().map(|t| t.into()).collect();
+    access: Data<AccessControl>,
+    identity: ReqData<Identity>,
+    http_req: HttpRequest,
+) -> Result<HttpResponse, ApiError> {
+    let service = TagService::new(
+        &tag_repo_actor,
+        &access,
+        &identity,
+        http_req.peer_addr(),
+        request_id_of(&http_req),
+    );
+    tracing::info!(request_id = %service.request_id(), "request (list_tags)");
+
+    let tags = service.list_tags().await?;
     Ok(HttpResponse::Ok().json(ListTagsResponse {
-        tags: responses,
+        tags: tags.into_iter().map(TagResponse::from).collect(),
     }))
 }
 
+#[utoipa::path(
+    tag = "tags",
+    params(("name" = String, Path, description = "Tag name or alias")),
+    responses(
+        (status = 200, description = "Tag", body = TagResponse),
+        (status = 403, description = "Permission denied", body = ApiError),
+        (status = 404, description = "Tag not found", body = ApiError),
+    )
+)]
 #[get("/{name}")]
-#[instrument(skip(tag_repo_actor))]
+#[instrument(skip(tag_repo_actor, access, identity, http_req))]
 pub async fn get_tag(
     tag_repo_actor: Data<ActorRef<actor::tag::Message>>,
+    access: Data<AccessControl>,
+    identity: ReqData<Identity>,
+    http_req: HttpRequest,
     name: Path<String>,
-) -> actix_web::Result<HttpResponse> {
-    let request_id = Uuid::new_v4();
-    let name_ref = name.as_str();
-    tracing::info!(%request_id, "request: {} (get_tag)", name_ref);
-
-    let (command, mut reply) = actor::tag::Message::get_tag(name_ref);
-    tag_repo_actor
-        .send_message(command)
-        .map_err(actix_web::error::ErrorInternalServerError)?;
-    let result = reply.recv().await.ok_or_else(|| {
-        tracing::error!("actor response channel closed before reply received");
-        actix_web::error::ErrorInternalServerError("Channel closed")
-    })?;
-
-    match result {
-        Ok(tag) => Ok(HttpResponse::Ok().json(TagResponse::from(tag))),
-        Err(_) => {
-            tracing::warn!(%request_id, "Tag not found: {}", name_ref);
-            Ok(HttpResponse::NotFound().body("Tag not found"))
-        },
-    }
+) -> Result<HttpResponse, ApiError> {
+    let service = TagService::new(
+        &tag_repo_actor,
+        &access,
+        &identity,
+        http_req.peer_addr(),
+        request_id_of(&http_req),
+    );
+    tracing::info!(request_id = %service.request_id(), "request: {} (get_tag)", name);
+
+    let tag = service.get_tag(&name).await?;
+    Ok(HttpResponse::Ok().json(TagResponse::from(tag)))
 }
 
+#[utoipa::path(
+    tag = "tags",
+    params(("name" = String, Path, description = "Tag name or alias")),
+    request_body = UpdateValueRequest,
+    responses(
+        (status = 200, description = "Value written or ignored", body = UpdateValueResponse),
+        (status = 400, description = "Invalid data type or timestamp", body = ApiError),
+        (status = 403, description = "Permission denied or tag is read-only", body = ApiError),
+        (status = 404, description = "Tag not found", body = ApiError),
+    )
+)]
 #[put("/{name}/value")]
-#[instrument(skip(tag_repo_actor, req))]
+#[instrument(skip(tag_repo_actor, access, identity, req, http_req))]
 pub async fn update_tag_value(
     tag_repo_actor: Data<ActorRef<actor::tag::Message>>,
+    access: Data<AccessControl>,
+    identity: ReqData<Identity>,
+    http_req: HttpRequest,
     name: Path<String>,
     req: Json<UpdateValueRequest>,
-) -> actix_web::Result<HttpResponse> {
-    let request_id = Uuid::new_v4();
-    let name_ref = name.as_str();
-    tracing::info!(%request_id, "request: {} (update_tag_value)", name_ref);
-
-    let tag_value = req.0.into();
-    let (command, mut reply) = actor::tag::Message::update_tag_value(name_ref, tag_value);
-    tag_repo_actor
-        .send_message(command)
-        .map_err(actix_web::error::ErrorInternalServerError)?;
-    let result = reply.recv().await.ok_or_else(|| {
-        tracing::error!("actor response channel closed before reply received");
-        actix_web::error::ErrorInternalServerError("Channel closed")
-    })?;
-
-    match result {
-        Ok(UpdateValueResult::Updated) => Ok(HttpResponse::Ok().json(UpdateValueResponse {
-            result: UpdateValueResult::Updated,
-        })),
-        Ok(UpdateValueResult::Ignored) => Ok(HttpResponse::Ok().json(UpdateValueResponse {
-            result: UpdateValueResult::Ignored,
-        })),
-        Err(UpdateValueError::TagNameNotFound) => {
-            tracing::warn!(%request_id, "Tag not found for update: {}", name_ref);
-            Ok(HttpResponse::NotFound().body("Tag not found"))
-        },
-        Err(UpdateValueError::NoneTimestampProvided) => {
-            tracing::warn!(%request_id, "Timestamp required for update: {}", name_ref);
-            Ok(HttpResponse::BadRequest().body("Timestamp is required after first update"))
-        },
-        Err(UpdateValueError::TimestamoOutOfOrder {
-            previous,
-        }) => {
-            tracing::warn!(%request_id, "Timestamp out of order for tag: {}", name_ref);
-            Ok(HttpResponse::BadRequest().json(serde_json::json!({
-                "error": "Timestamp out of order",
-                "previous_timestamp": previous
-            })))
-        },
-        Err(UpdateValueError::InvalidDataType {
-            expected,
-            actual,
-        }) => {
-            tracing::warn!(%request_id, "Invalid data type for tag: {}", name_ref);
-            Ok(HttpResponse::BadRequest().json(serde_json::json!({
-                "error": "Invalid data type",
-                "expected": format!("{:?}", expected),
-                "actual": format!("{:?}", actual)
-            })))
-        },
-    }
+) -> Result<HttpResponse, ApiError> {
+    let service = TagService::new(
+        &tag_repo_actor,
+        &access,
+        &identity,
+        http_req.peer_addr(),
+        request_id_of(&http_req),
+    );
+    tracing::info!(request_id = %service.request_id(), "request: {} (update_tag_value)", name);
+
+    let result = service.update_tag_value(&name, req.0.into()).await?;
+    Ok(HttpResponse::Ok().json(UpdateValueResponse {
+        result,
+    }))
 }
 
+#[utoipa::path(
+    tag = "tags",
+    params(("name" = String, Path, description = "Tag name or alias")),
+    request_body = UpdateTagMetaRequest,
+    responses(
+        (status = 200, description = "Updated tag", body = TagResponse),
+        (status = 400, description = "Invalid range or alarm limits", body = ApiError),
+        (status = 403, description = "Permission denied", body = ApiError),
+        (status = 404, description = "Tag not found", body = ApiError),
+        (status = 409, description = "Value can't be converted to the new data type", body = ApiError),
+    )
+)]
+#[patch("/{name}")]
+#[instrument(skip(tag_repo_actor, access, identity, req, http_req))]
+pub async fn update_tag_meta(
+    tag_repo_actor: Data<ActorRef<actor::tag::Message>>,
+    access: Data<AccessControl>,
+    identity: ReqData<Identity>,
+    http_req: HttpRequest,
+    name: Path<String>,
+    req: Json<UpdateTagMetaRequest>,
+) -> Result<HttpResponse, ApiError> {
+    let service = TagService::new(
+        &tag_repo_actor,
+        &access,
+        &identity,
+        http_req.peer_addr(),
+        request_id_of(&http_req),
+    );
+    tracing::info!(request_id = %service.request_id(), "request: {} (update_tag_meta)", name);
+
+    let tag = service.update_tag_meta(&name, req.0.into()).await?;
+    Ok(HttpResponse::Ok().json(TagResponse::from(tag)))
+}
+
+#[utoipa::path(
+    tag = "tags",
+    params(("name" = String, Path, description = "Tag name or alias")),
+    responses(
+        (status = 204, description = "Tag deleted"),
+        (status = 403, description = "Permission denied", body = ApiError),
+        (status = 404, description = "Tag not found", body = ApiError),
+    )
+)]
 #[delete("/{name}")]
-#[instrument(skip(tag_repo_actor))]
+#[instrument(skip(tag_repo_actor, access, identity, http_req))]
 pub async fn delete_tag(
     tag_repo_actor: Data<ActorRef<actor::tag::Message>>,
+    access: Data<AccessControl>,
+    identity: ReqData<Identity>,
+    http_req: HttpRequest,
+    name: Path<String>,
+) -> Result<HttpResponse, ApiError> {
+    let service = TagService::new(
+        &tag_repo_actor,
+        &access,
+        &identity,
+        http_req.peer_addr(),
+        request_id_of(&http_req),
+    );
+    tracing::info!(request_id = %service.request_id(), "request: {} (delete_tag)", name);
+
+    service.delete_tag(&name).await?;
+    Ok(HttpResponse::NoContent().finish())
+}
+
+#[utoipa::path(
+    tag = "tags",
+    params(("name" = String, Path, description = "Tag name or alias")),
+    request_body = RenameTagRequest,
+    responses(
+        (status = 200, description = "Renamed tag", body = TagResponse),
+        (status = 403, description = "Permission denied", body = ApiError),
+        (status = 404, description = "Tag not found", body = ApiError),
+        (status = 409, description = "New name already in use", body = ApiError),
+    )
+)]
+#[post("/{name}/rename")]
+#[instrument(skip(tag_repo_actor, access, identity, req, http_req))]
+pub async fn rename_tag(
+    tag_repo_actor: Data<ActorRef<actor::tag::Message>>,
+    access: Data<AccessControl>,
+    identity: ReqData<Identity>,
+    http_req: HttpRequest,
     name: Path<String>,
-) -> actix_web::Result<HttpResponse> {
-    let request_id = Uuid::new_v4();
-    let name_ref = name.as_str();
-    tracing::info!(%request_id, "request: {} (delete_tag)", name_ref);
-
-    let (command, mut reply) = actor::tag::Message::delete_tag(name_ref);
-    tag_repo_actor
-        .send_message(command)
-        .map_err(actix_web::error::ErrorInternalServerError)?;
-    let result = reply.recv().await.ok_or_else(|| {
-        tracing::error!("actor response channel closed before reply received");
-        actix_web::error::ErrorInternalServerError("Channel closed")
-    })?;
-
-    match result {
-        Ok(_) => Ok(HttpResponse::NoContent().finish()),
-        Err(DeleteTagError::TagNameNotFound) => {
-            tracing::warn!(%request_id, "Tag not found for deletion: {}", name_ref);
-            Ok(HttpResponse::NotFound().body("Tag not found"))
-        },
-    }
+    req: Json<RenameTagRequest>,
+) -> Result<HttpResponse, ApiError> {
+    let service = TagService::new(
+        &tag_repo_actor,
+        &access,
+        &identity,
+        http_req.peer_addr(),
+        request_id_of(&http_req),
+    );
+    tracing::info!(
+        request_id = %service.request_id(),
+        "request: {} (rename_tag) new_name={}",
+        name,
+        req.new_name
+    );
+
+    let tag = service
+        .rename_tag(&name, &req.new_name, req.keep_alias)
+        .await?;
+    Ok(HttpResponse::Ok().json(TagResponse::from(tag)))
 }
@@ -1,11 +1,17 @@
 pub mod handlers;
 pub mod model;
+pub mod service;
 
-pub fn scope() -> actix_web::Scope {
-    actix_web::web::scope("/tags")
+use actix_web::dev::HttpServiceFactory;
+use utoipa_actix_web::OpenApiFactory;
+
+pub fn scope() -> impl HttpServiceFactory + OpenApiFactory {
+    utoipa_actix_web::scope("/tags")
         .service(handlers::create_tag)
         .service(handlers::list_tags)
         .service(handlers::get_tag)
         .service(handlers::update_tag_value)
+        .service(handlers::update_tag_meta)
         .service(handlers::delete_tag)
+        .service(handlers::rename_tag)
 }
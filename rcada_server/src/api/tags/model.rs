@@ -1,58 +1,134 @@
+use std::collections::BTreeMap;
+
 use chrono::{DateTime, Utc};
 use rcada_core::{
-    tag::{Tag, TagValue},
+    tag::{AlarmLimits, EngineeringRange, Quality, Tag, TagMeta, TagValue},
     unit::Unit,
-    value::{DataType, Value},
+    value::{DataType, Value, ValueSchema},
 };
-use serde::{Deserialize, Serialize};
+use serde::{Deserialize, Deserializer, Serialize};
+use utoipa::ToSchema;
 
-use crate::repository::tag::{CreateTagResult, UpdateValueResult};
+use crate::repository::tag::{CreateTagResult, TagMetaPatch, UpdateValueResult};
 
-#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, ToSchema)]
 pub struct CreateTagRequest {
     pub name: String,
     pub unit: Unit,
     pub data_type: DataType,
+    #[serde(default)]
+    pub description: String,
+    #[serde(default)]
+    pub range: Option<EngineeringRange>,
+    #[serde(default)]
+    pub precision: Option<u8>,
+    #[serde(default)]
+    pub read_only: bool,
+    #[serde(default)]
+    pub labels: BTreeMap<String, String>,
+    #[serde(default)]
+    pub alarms: AlarmLimits,
+    #[serde(default)]
+    pub expression: Option<String>,
 }
 
-#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, ToSchema)]
 pub struct CreateTagResponse {
     pub name: String,
     pub result: CreateTagResult,
 }
 
-#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, ToSchema)]
 pub struct UpdateValueRequest {
+    #[schema(value_type = ValueSchema)]
     pub value: Value,
     pub timestamp: Option<DateTime<Utc>>,
+    #[serde(default)]
+    pub quality: Quality,
 }
 
-#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, ToSchema)]
 pub struct UpdateValueResponse {
     pub result: UpdateValueResult,
 }
 
-#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+/// Body of `PATCH /tags/{name}`.
+///
+/// Missing fields are left untouched, `null` clears `range`, `precision` and
+/// `expression` and removes a label.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize, ToSchema)]
+pub struct UpdateTagMetaRequest {
+    #[serde(default)]
+    pub unit: Option<Unit>,
+    #[serde(default)]
+    pub data_type: Option<DataType>,
+    #[serde(default)]
+    pub description: Option<String>,
+    #[serde(default, deserialize_with = "double_option")]
+    pub range: Option<Option<EngineeringRange>>,
+    #[serde(default, deserialize_with = "double_option")]
+    pub precision: Option<Option<u8>>,
+    #[serde(default)]
+    pub read_only: Option<bool>,
+    #[serde(default)]
+    pub labels: Option<BTreeMap<String, Option<String>>>,
+    #[serde(default)]
+    pub alarms: Option<AlarmLimits>,
+    #[serde(default, deserialize_with = "double_option")]
+    pub expression: Option<Option<String>>,
+}
+
+/// Distinguishes an explicit `null` (`Some(None)`) from a missing field (`None`).
+fn double_option<'de, T, D>(deserializer: D) -> Result<Option<Option<T>>, D::Error>
+where
+    T: Deserialize<'de>,
+    D: Deserializer<'de>,
+{
+    Option::<T>::deserialize(deserializer).map(Some)
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, ToSchema)]
+pub struct RenameTagRequest {
+    pub new_name: String,
+    /// Keep the old name as an alias of the renamed tag.
+    #[serde(default = "default_keep_alias")]
+    pub keep_alias: bool,
+}
+
+fn default_keep_alias() -> bool {
+    true
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, ToSchema)]
 pub struct TagResponse {
     pub name: String,
     pub value: ValueResponse,
     pub meta: TagMetaResponse,
 }
 
-#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, ToSchema)]
 pub struct ValueResponse {
+    #[schema(value_type = ValueSchema)]
     pub value: Value,
     pub timestamp: Option<DateTime<Utc>>,
+    pub quality: Quality,
     pub data_type: DataType,
 }
 
-#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, ToSchema)]
 pub struct TagMetaResponse {
     pub unit: Unit,
     pub data_type: DataType,
+    pub description: String,
+    pub range: Option<EngineeringRange>,
+    pub precision: Option<u8>,
+    pub read_only: bool,
+    pub labels: BTreeMap<String, String>,
+    pub alarms: AlarmLimits,
+    pub expression: Option<String>,
 }
 
-#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, ToSchema)]
 pub struct ListTagsResponse {
     pub tags: Vec<TagResponse>,
 }
@@ -65,12 +141,58 @@ impl From<Tag> for TagResponse {
             value: ValueResponse {
                 value: tag.value.value,
                 timestamp: tag.value.timestamp,
+                quality: tag.value.quality,
                 data_type,
             },
-            meta: TagMetaResponse {
-                unit: tag.meta.unit,
-                data_type: tag.meta.data_type,
-            },
+            meta: tag.meta.into(),
+        }
+    }
+}
+
+impl From<TagMeta> for TagMetaResponse {
+    fn from(meta: TagMeta) -> Self {
+        TagMetaResponse {
+            unit: meta.unit,
+            data_type: meta.data_type,
+            description: meta.description,
+            range: meta.range,
+            precision: meta.precision,
+            read_only: meta.read_only,
+            labels: meta.labels,
+            alarms: meta.alarms,
+            expression: meta.expression,
+        }
+    }
+}
+
+impl From<&CreateTagRequest> for TagMeta {
+    fn from(req: &CreateTagRequest) -> Self {
+        TagMeta {
+            unit: req.unit,
+            data_type: req.data_type,
+            description: req.description.clone(),
+            range: req.range,
+            precision: req.precision,
+            read_only: req.read_only,
+            labels: req.labels.clone(),
+            alarms: req.alarms,
+            expression: req.expression.clone(),
+        }
+    }
+}
+
+impl From<UpdateTagMetaRequest> for TagMetaPatch {
+    fn from(req: UpdateTagMetaRequest) -> Self {
+        TagMetaPatch {
+            unit: req.unit,
+            data_type: req.data_type,
+            description: req.description,
+            range: req.range,
+            precision: req.precision,
+            read_only: req.read_only,
+            labels: req.labels,
+            alarms: req.alarms,
+            expression: req.expression,
         }
     }
 }
@@ -81,6 +203,7 @@ impl From<rcada_core::tag::TagValue> for ValueResponse {
         ValueResponse {
             value: tag_value.value,
             timestamp: tag_value.timestamp,
+            quality: tag_value.quality,
             data_type,
         }
     }
@@ -91,6 +214,7 @@ impl From<UpdateValueRequest> for TagValue {
         TagValue {
             value: req.value,
             timestamp: req.timestamp.or(Some(Utc::now())),
+            quality: req.quality,
         }
     }
 }
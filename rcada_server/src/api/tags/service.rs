@@ -0,0 +1,331 @@
+use std::net::SocketAddr;
+
+use ractor::ActorRef;
+use rcada_core::tag::{Tag, TagMeta, TagValue};
+use tokio::sync::{broadcast, mpsc};
+use uuid::Uuid;
+
+use crate::{
+    access::{AccessControl, Permission},
+    actor::{self, Mailbox},
+    api::{
+        access::authorize,
+        error::{ApiError, ErrorCode},
+    },
+    audit::Origin,
+    auth::Identity,
+    repository::tag::{
+        CreateTagResult, DeleteTagError, RenameTagError, TagMetaPatch, UpdateMetaError,
+        UpdateValueError, UpdateValueResult,
+    },
+};
+
+/// Tag operations on behalf of the sender of one request. The REST handlers
+/// and the gRPC service both go through it, so they check the same
+/// permissions and fail with the same errors.
+pub struct TagService<'a> {
+    tag_repo: &'a ActorRef<actor::tag::Message>,
+    access: &'a AccessControl,
+    identity: &'a Identity,
+    peer: Option<SocketAddr>,
+    request_id: Uuid,
+}
+
+impl<'a> TagService<'a> {
+    /// `request_id` goes into the logs, audit records and errors, the REST
+    /// handlers pass the one of the tracing middleware.
+    pub fn new(
+        tag_repo: &'a ActorRef<actor::tag::Message>,
+        access: &'a AccessControl,
+        identity: &'a Identity,
+        peer: Option<SocketAddr>,
+        request_id: Uuid,
+    ) -> Self {
+        Self {
+            tag_repo,
+            access,
+            identity,
+            peer,
+            request_id,
+        }
+    }
+
+    pub fn request_id(&self) -> Uuid {
+        self.request_id
+    }
+
+    /// Sends `command` on behalf of the caller and waits for the reply.
+    async fn ask<T>(
+        &self,
+        command: actor::tag::Message,
+        mut reply: mpsc::Receiver<T>,
+    ) -> Result<T, ApiError> {
+        let origin = Origin::request(self.request_id, self.identity, self.peer);
+        self.tag_repo
+            .enqueue(command.with_origin(origin))
+            .map_err(|e| ApiError::internal(self.request_id, e))?;
+        reply
+            .recv()
+            .await
+            .ok_or_else(|| ApiError::internal(self.request_id, "actor response channel closed"))
+    }
+
+    fn authorize(&self, permission: Permission, tag: &str) -> Result<(), ApiError> {
+        authorize(
+            self.request_id,
+            self.access,
+            self.identity,
+            permission,
+            Some(tag),
+        )
+    }
+
+    fn tag_not_found(&self) -> ApiError {
+        ApiError::new(self.request_id, ErrorCode::TagNotFound, "Tag not found")
+    }
+
+    /// Name of the tag `name` refers to, so that permissions are checked on
+    /// the tag and not on one of its aliases.
+    async fn resolve_name(&self, name: &str) -> Result<String, ApiError> {
+        let (command, reply) = actor::tag::Message::get_tag(name);
+        let result = self.ask(command, reply).await?;
+        Ok(result.map_or_else(|_| name.to_string(), |tag| tag.name.to_string()))
+    }
+
+    pub async fn create_tag(&self, name: &str, meta: TagMeta) -> Result<(), ApiError> {
+        let request_id = self.request_id;
+        self.authorize(Permission::Configure, name)?;
+
+        if meta.range.is_some_and(|range| !range.is_valid()) {
+            tracing::warn!(%request_id, "Invalid engineering range for tag: {}", name);
+            return Err(ApiError::new(
+                request_id,
+                ErrorCode::InvalidRange,
+                "Invalid engineering range",
+            ));
+        }
+        if !meta.alarms.is_valid() {
+            tracing::warn!(%request_id, "Invalid alarm limits for tag: {}", name);
+            return Err(ApiError::new(
+                request_id,
+                ErrorCode::InvalidAlarmLimits,
+                "Invalid alarm limits",
+            ));
+        }
+
+        let (command, reply) = actor::tag::Message::create_tag(name, meta);
+        match self.ask(command, reply).await? {
+            CreateTagResult::SuccessfullyCreated => Ok(()),
+            CreateTagResult::AlreadyExists => {
+                tracing::warn!(%request_id, "Tag already exists: {}", name);
+                Err(
+                    ApiError::new(request_id, ErrorCode::AlreadyExists, "Tag already exists")
+                        .with_details(serde_json::json!({ "name": name })),
+                )
+            },
+            CreateTagResult::Storage(e) => Err(ApiError::internal(
+                request_id,
+                format!("failed to save tags: {e}"),
+            )),
+        }
+    }
+
+    /// Tags the caller may read.
+    pub async fn list_tags(&self) -> Result<Vec<Tag>, ApiError> {
+        let (command, reply) = actor::tag::Message::get_all_tags();
+        let tags = self.ask(command, reply).await?;
+        Ok(tags
+            .into_iter()
+            .filter(|tag| self.may_read(&tag.name))
+            .collect())
+    }
+
+    pub fn may_read(&self, tag: &str) -> bool {
+        self.access
+            .is_allowed(self.identity, Permission::Read, Some(tag))
+    }
+
+    /// Value updates of every tag, filter them with [`TagService::may_read`]
+    /// since permissions can change while subscribed.
+    pub async fn subscribe(&self) -> Result<broadcast::Receiver<Tag>, ApiError> {
+        let (command, reply) = actor::tag::Message::subscribe();
+        self.ask(command, reply).await
+    }
+
+    pub async fn get_tag(&self, name: &str) -> Result<Tag, ApiError> {
+        let (command, reply) = actor::tag::Message::get_tag(name);
+        let result = self.ask(command, reply).await?;
+
+        let resolved = result.as_ref().map_or(name, |tag| tag.name.as_str());
+        self.authorize(Permission::Read, resolved)?;
+
+        result.map_err(|_| {
+            tracing::warn!(request_id = %self.request_id, "Tag not found: {}", name);
+            self.tag_not_found()
+        })
+    }
+
+    pub async fn update_tag_value(
+        &self,
+        name: &str,
+        value: TagValue,
+    ) -> Result<UpdateValueResult, ApiError> {
+        let request_id = self.request_id;
+        let (command, reply) = actor::tag::Message::get_tag(name);
+        let tag = self.ask(command, reply).await?;
+
+        let resolved = tag.as_ref().map_or(name, |tag| tag.name.as_str());
+        self.authorize(Permission::Write, resolved)?;
+
+        let (command, reply) = actor::tag::Message::update_tag_value(name, value);
+        match self.ask(command, reply).await? {
+            Ok(result) => Ok(result),
+            Err(UpdateValueError::TagNameNotFound) => {
+                tracing::warn!(%request_id, "Tag not found for update: {}", name);
+                Err(self.tag_not_found())
+            },
+            Err(UpdateValueError::ReadOnly) => {
+                tracing::warn!(%request_id, "Write to read-only tag rejected: {}", name);
+                Err(ApiError::new(
+                    request_id,
+                    ErrorCode::ReadOnly,
+                    "Tag is read-only",
+                ))
+            },
+            Err(UpdateValueError::NoneTimestampProvided) => {
+                tracing::warn!(%request_id, "Timestamp required for update: {}", name);
+                Err(ApiError::new(
+                    request_id,
+                    ErrorCode::TimestampRequired,
+                    "Timestamp is required after first update",
+                ))
+            },
+            Err(UpdateValueError::TimestamoOutOfOrder {
+                previous,
+            }) => {
+                tracing::warn!(%request_id, "Timestamp out of order for tag: {}", name);
+                Err(ApiError::new(
+                    request_id,
+                    ErrorCode::TimestampOutOfOrder,
+                    "Timestamp out of order",
+                )
+                .with_details(serde_json::json!({ "previous_timestamp": previous })))
+            },
+            Err(UpdateValueError::InvalidDataType {
+                expected,
+                actual,
+            }) => {
+                tracing::warn!(%request_id, "Invalid data type for tag: {}", name);
+                Err(
+                    ApiError::new(request_id, ErrorCode::InvalidDataType, "Invalid data type")
+                        .with_details(serde_json::json!({
+                            "expected": format!("{:?}", expected),
+                            "actual": format!("{:?}", actual)
+                        })),
+                )
+            },
+        }
+    }
+
+    pub async fn update_tag_meta(&self, name: &str, patch: TagMetaPatch) -> Result<Tag, ApiError> {
+        let request_id = self.request_id;
+        let resolved = self.resolve_name(name).await?;
+        self.authorize(Permission::Configure, &resolved)?;
+
+        let (command, reply) = actor::tag::Message::update_tag_meta(name, patch);
+        match self.ask(command, reply).await? {
+            Ok(tag) => Ok(tag),
+            Err(UpdateMetaError::TagNameNotFound) => {
+                tracing::warn!(%request_id, "Tag not found for meta update: {}", name);
+                Err(self.tag_not_found())
+            },
+            Err(UpdateMetaError::InvalidRange) => {
+                tracing::warn!(%request_id, "Invalid engineering range for tag: {}", name);
+                Err(ApiError::new(
+                    request_id,
+                    ErrorCode::InvalidRange,
+                    "Invalid engineering range",
+                ))
+            },
+            Err(UpdateMetaError::InvalidAlarmLimits) => {
+                tracing::warn!(%request_id, "Invalid alarm limits for tag: {}", name);
+                Err(ApiError::new(
+                    request_id,
+                    ErrorCode::InvalidAlarmLimits,
+                    "Invalid alarm limits",
+                ))
+            },
+            Err(UpdateMetaError::IncompatibleDataType {
+                from,
+                to,
+            }) => {
+                tracing::warn!(%request_id, "Incompatible data type change for tag: {}", name);
+                Err(ApiError::new(
+                    request_id,
+                    ErrorCode::IncompatibleDataType,
+                    "Current value can't be converted to the new data type",
+                )
+                .with_details(serde_json::json!({
+                    "from": format!("{:?}", from),
+                    "to": format!("{:?}", to)
+                })))
+            },
+            Err(UpdateMetaError::Storage(e)) => Err(ApiError::internal(
+                request_id,
+                format!("failed to save tags: {e}"),
+            )),
+        }
+    }
+
+    pub async fn delete_tag(&self, name: &str) -> Result<(), ApiError> {
+        let resolved = self.resolve_name(name).await?;
+        self.authorize(Permission::Configure, &resolved)?;
+
+        let (command, reply) = actor::tag::Message::delete_tag(name);
+        match self.ask(command, reply).await? {
+            Ok(()) => Ok(()),
+            Err(DeleteTagError::TagNameNotFound) => {
+                tracing::warn!(request_id = %self.request_id, "Tag not found for deletion: {}", name);
+                Err(self.tag_not_found())
+            },
+            Err(DeleteTagError::Storage(e)) => Err(ApiError::internal(
+                self.request_id,
+                format!("failed to save tags: {e}"),
+            )),
+        }
+    }
+
+    pub async fn rename_tag(
+        &self,
+        name: &str,
+        new_name: &str,
+        keep_alias: bool,
+    ) -> Result<Tag, ApiError> {
+        let request_id = self.request_id;
+        let resolved = self.resolve_name(name).await?;
+        for tag in [resolved.as_str(), new_name] {
+            self.authorize(Permission::Configure, tag)?;
+        }
+
+        let (command, reply) = actor::tag::Message::rename_tag(name, new_name, keep_alias);
+        match self.ask(command, reply).await? {
+            Ok(tag) => Ok(tag),
+            Err(RenameTagError::TagNameNotFound) => {
+                tracing::warn!(%request_id, "Tag not found for rename: {}", name);
+                Err(self.tag_not_found())
+            },
+            Err(RenameTagError::AlreadyExists) => {
+                tracing::warn!(%request_id, "Tag name already in use: {}", new_name);
+                Err(ApiError::new(
+                    request_id,
+                    ErrorCode::AlreadyExists,
+                    "Tag name already in use",
+                ))
+            },
+            Err(RenameTagError::Storage(e)) => Err(ApiError::internal(
+                request_id,
+                format!("failed to save tags: {e}"),
+            )),
+        }
+    }
+}
@@ -0,0 +1,400 @@
+use actix_web::{
+    HttpRequest, HttpResponse, delete, get, post, put,
+    web::{Data, Json, Path, ReqData},
+};
+use ractor::ActorRef;
+use tracing::instrument;
+use uuid::Uuid;
+
+use crate::{
+    access::{AccessControl, Permission},
+    actor::{self, Mailbox},
+    api::{
+        access::authorize,
+        error::{ApiError, ErrorCode, request_id_of},
+    },
+    audit::Origin,
+    auth::Identity,
+    repository::template::{TagTemplate, TemplateError},
+};
+
+use super::model::{
+    InstanceResponse, InstantiateRequest, ListInstancesResponse, ListTemplatesResponse,
+    UpdateTemplateRequest, UpdateTemplateResponse,
+};
+
+fn error_response(request_id: Uuid, error: TemplateError) -> ApiError {
+    tracing::warn!(%request_id, "template request failed: {:?}", error);
+    let api_error = |code: ErrorCode, message: &str| ApiError::new(request_id, code, message);
+    match error {
+        TemplateError::TemplateNotFound => {
+            api_error(ErrorCode::TemplateNotFound, "Template not found")
+        },
+        TemplateError::InstanceNotFound => {
+            api_error(ErrorCode::InstanceNotFound, "Instance not found")
+        },
+        TemplateError::AlreadyExists => {
+            api_error(ErrorCode::AlreadyExists, "Template already exists")
+        },
+        TemplateError::InstanceAlreadyExists => {
+            api_error(ErrorCode::AlreadyExists, "Instance already exists")
+        },
+        TemplateError::HasInstances => api_error(ErrorCode::Conflict, "Template has instances"),
+        TemplateError::TagsAlreadyExist(tags) => {
+            api_error(ErrorCode::AlreadyExists, "Tags already exist")
+                .with_details(serde_json::json!({ "tags": tags }))
+        },
+        TemplateError::InvalidPoint {
+            point,
+            reason,
+        } => api_error(ErrorCode::InvalidRequest, "Invalid template point").with_details(
+            serde_json::json!({
+                "point": point,
+                "reason": reason
+            }),
+        ),
+        TemplateError::TagActor(e) => {
+            ApiError::internal(request_id, format!("tag actor failed: {e}"))
+        },
+        TemplateError::Storage(e) => {
+            ApiError::internal(request_id, format!("failed to save templates: {e}"))
+        },
+    }
+}
+
+#[utoipa::path(
+    tag = "templates",
+    request_body = TagTemplate,
+    responses(
+        (status = 201, description = "Template created", body = TagTemplate),
+        (status = 400, description = "Invalid template point", body = ApiError),
+        (status = 403, description = "Permission denied", body = ApiError),
+        (status = 409, description = "Template already exists", body = ApiError),
+    )
+)]
+#[post("")]
+#[instrument(skip(template_actor, access, identity, req, http_req))]
+pub async fn create_template(
+    template_actor: Data<ActorRef<actor::template::Message>>,
+    access: Data<AccessControl>,
+    identity: ReqData<Identity>,
+    http_req: HttpRequest,
+    req: Json<TagTemplate>,
+) -> Result<HttpResponse, ApiError> {
+    let request_id = request_id_of(&http_req);
+    tracing::info!(%request_id, "request: (create_template) name={}", req.name);
+
+    authorize(request_id, &access, &identity, Permission::Configure, None)?;
+
+    let (command, mut reply) = actor::template::Message::create_template(req.0.clone());
+    template_actor
+        .enqueue(command)
+        .map_err(|e| ApiError::internal(request_id, e))?;
+    let result = reply
+        .recv()
+        .await
+        .ok_or_else(|| ApiError::internal(request_id, "actor response channel closed"))?;
+
+    match result {
+        Ok(()) => Ok(HttpResponse::Created().json(req.0)),
+        Err(e) => Err(error_response(request_id, e)),
+    }
+}
+
+#[utoipa::path(
+    tag = "templates",
+    responses(
+        (status = 200, description = "Templates", body = ListTemplatesResponse),
+        (status = 403, description = "Permission denied", body = ApiError),
+    )
+)]
+#[get("")]
+#[instrument(skip(template_actor, access, identity, http_req))]
+pub async fn list_templates(
+    template_actor: Data<ActorRef<actor::template::Message>>,
+    access: Data<AccessControl>,
+    identity: ReqData<Identity>,
+    http_req: HttpRequest,
+) -> Result<HttpResponse, ApiError> {
+    let request_id = request_id_of(&http_req);
+    tracing::info!(%request_id, "request (list_templates)");
+
+    authorize(request_id, &access, &identity, Permission::Read, None)?;
+
+    let (command, mut reply) = actor::template::Message::get_all_templates();
+    template_actor
+        .enqueue(command)
+        .map_err(|e| ApiError::internal(request_id, e))?;
+    let templates = reply
+        .recv()
+        .await
+        .ok_or_else(|| ApiError::internal(request_id, "actor response channel closed"))?;
+
+    Ok(HttpResponse::Ok().json(ListTemplatesResponse {
+        templates,
+    }))
+}
+
+#[utoipa::path(
+    tag = "templates",
+    params(("name" = String, Path, description = "Template name")),
+    responses(
+        (status = 200, description = "Template", body = TagTemplate),
+        (status = 403, description = "Permission denied", body = ApiError),
+        (status = 404, description = "Template not found", body = ApiError),
+    )
+)]
+#[get("/{name}")]
+#[instrument(skip(template_actor, access, identity, http_req))]
+pub async fn get_template(
+    template_actor: Data<ActorRef<actor::template::Message>>,
+    access: Data<AccessControl>,
+    identity: ReqData<Identity>,
+    http_req: HttpRequest,
+    name: Path<String>,
+) -> Result<HttpResponse, ApiError> {
+    let request_id = request_id_of(&http_req);
+    tracing::info!(%request_id, "request: {} (get_template)", name);
+
+    authorize(request_id, &access, &identity, Permission::Read, None)?;
+
+    let (command, mut reply) = actor::template::Message::get_template(name.as_str());
+    template_actor
+        .enqueue(command)
+        .map_err(|e| ApiError::internal(request_id, e))?;
+    let result = reply
+        .recv()
+        .await
+        .ok_or_else(|| ApiError::internal(request_id, "actor response channel closed"))?;
+
+    match result {
+        Ok(template) => Ok(HttpResponse::Ok().json(template)),
+        Err(e) => Err(error_response(request_id, e)),
+    }
+}
+
+#[utoipa::path(
+    tag = "templates",
+    params(("name" = String, Path, description = "Template name")),
+    request_body = UpdateTemplateRequest,
+    responses(
+        (status = 200, description = "Template and changes of its instances", body = UpdateTemplateResponse),
+        (status = 400, description = "Invalid template point", body = ApiError),
+        (status = 403, description = "Permission denied", body = ApiError),
+        (status = 404, description = "Template not found", body = ApiError),
+    )
+)]
+#[put("/{name}")]
+#[instrument(skip(template_actor, access, identity, req, http_req))]
+pub async fn update_template(
+    template_actor: Data<ActorRef<actor::template::Message>>,
+    access: Data<AccessControl>,
+    identity: ReqData<Identity>,
+    http_req: HttpRequest,
+    name: Path<String>,
+    req: Json<UpdateTemplateRequest>,
+) -> Result<HttpResponse, ApiError> {
+    let request_id = request_id_of(&http_req);
+    tracing::info!(%request_id, "request: {} (update_template)", name);
+
+    authorize(request_id, &access, &identity, Permission::Configure, None)?;
+
+    let template = TagTemplate {
+        name: name.as_str().into(),
+        description: req.0.description,
+        points: req.0.points,
+    };
+    let (command, mut reply) = actor::template::Message::update_template(template.clone());
+    template_actor
+        .enqueue(command.with_origin(Origin::request(request_id, &identity, http_req.peer_addr())))
+        .map_err(|e| ApiError::internal(request_id, e))?;
+    let result = reply
+        .recv()
+        .await
+        .ok_or_else(|| ApiError::internal(request_id, "actor response channel closed"))?;
+
+    match result {
+        Ok(instances) => Ok(HttpResponse::Ok().json(UpdateTemplateResponse {
+            template,
+            instances,
+        })),
+        Err(e) => Err(error_response(request_id, e)),
+    }
+}
+
+#[utoipa::path(
+    tag = "templates",
+    params(("name" = String, Path, description = "Template name")),
+    responses(
+        (status = 204, description = "Template deleted"),
+        (status = 403, description = "Permission denied", body = ApiError),
+        (status = 404, description = "Template not found", body = ApiError),
+        (status = 409, description = "Template has instances", body = ApiError),
+    )
+)]
+#[delete("/{name}")]
+#[instrument(skip(template_actor, access, identity, http_req))]
+pub async fn delete_template(
+    template_actor: Data<ActorRef<actor::template::Message>>,
+    access: Data<AccessControl>,
+    identity: ReqData<Identity>,
+    http_req: HttpRequest,
+    name: Path<String>,
+) -> Result<HttpResponse, ApiError> {
+    let request_id = request_id_of(&http_req);
+    tracing::info!(%request_id, "request: {} (delete_template)", name);
+
+    authorize(request_id, &access, &identity, Permission::Configure, None)?;
+
+    let (command, mut reply) = actor::template::Message::delete_template(name.as_str());
+    template_actor
+        .enqueue(command)
+        .map_err(|e| ApiError::internal(request_id, e))?;
+    let result = reply
+        .recv()
+        .await
+        .ok_or_else(|| ApiError::internal(request_id, "actor response channel closed"))?;
+
+    match result {
+        Ok(()) => Ok(HttpResponse::NoContent().finish()),
+        Err(e) => Err(error_response(request_id, e)),
+    }
+}
+
+#[utoipa::path(
+    tag = "templates",
+    params(("name" = String, Path, description = "Template name")),
+    responses(
+        (status = 200, description = "Instance prefixes", body = ListInstancesResponse),
+        (status = 403, description = "Permission denied", body = ApiError),
+        (status = 404, description = "Template not found", body = ApiError),
+    )
+)]
+#[get("/{name}/instances")]
+#[instrument(skip(template_actor, access, identity, http_req))]
+pub async fn list_instances(
+    template_actor: Data<ActorRef<actor::template::Message>>,
+    access: Data<AccessControl>,
+    identity: ReqData<Identity>,
+    http_req: HttpRequest,
+    name: Path<String>,
+) -> Result<HttpResponse, ApiError> {
+    let request_id = request_id_of(&http_req);
+    tracing::info!(%request_id, "request: {} (list_instances)", name);
+
+    authorize(request_id, &access, &identity, Permission::Read, None)?;
+
+    let (command, mut reply) = actor::template::Message::get_instances(name.as_str());
+    template_actor
+        .enqueue(command)
+        .map_err(|e| ApiError::internal(request_id, e))?;
+    let result = reply
+        .recv()
+        .await
+        .ok_or_else(|| ApiError::internal(request_id, "actor response channel closed"))?;
+
+    match result {
+        Ok(instances) => Ok(HttpResponse::Ok().json(ListInstancesResponse {
+            instances: instances.iter().map(ToString::to_string).collect(),
+        })),
+        Err(e) => Err(error_response(request_id, e)),
+    }
+}
+
+#[utoipa::path(
+    tag = "templates",
+    params(("name" = String, Path, description = "Template name")),
+    request_body = InstantiateRequest,
+    responses(
+        (status = 201, description = "Tags of the new instance", body = InstanceResponse),
+        (status = 400, description = "Empty prefix", body = ApiError),
+        (status = 403, description = "Permission denied", body = ApiError),
+        (status = 404, description = "Template not found", body = ApiError),
+        (status = 409, description = "Instance or tags already exist", body = ApiError),
+    )
+)]
+#[post("/{name}/instances")]
+#[instrument(skip(template_actor, access, identity, req, http_req))]
+pub async fn instantiate(
+    template_actor: Data<ActorRef<actor::template::Message>>,
+    access: Data<AccessControl>,
+    identity: ReqData<Identity>,
+    http_req: HttpRequest,
+    name: Path<String>,
+    req: Json<InstantiateRequest>,
+) -> Result<HttpResponse, ApiError> {
+    let request_id = request_id_of(&http_req);
+    tracing::info!(%request_id, "request: {} (instantiate) prefix={}", name, req.prefix);
+
+    authorize(request_id, &access, &identity, Permission::Configure, None)?;
+
+    if req.prefix.is_empty() {
+        return Err(ApiError::new(
+            request_id,
+            ErrorCode::InvalidRequest,
+            "Instance prefix is empty",
+        ));
+    }
+
+    let (command, mut reply) =
+        actor::template::Message::instantiate(name.as_str(), req.prefix.as_str());
+    template_actor
+        .enqueue(command.with_origin(Origin::request(request_id, &identity, http_req.peer_addr())))
+        .map_err(|e| ApiError::internal(request_id, e))?;
+    let result = reply
+        .recv()
+        .await
+        .ok_or_else(|| ApiError::internal(request_id, "actor response channel closed"))?;
+
+    match result {
+        Ok(tags) => Ok(HttpResponse::Created().json(InstanceResponse {
+            prefix: req.0.prefix,
+            tags: tags.iter().map(ToString::to_string).collect(),
+        })),
+        Err(e) => Err(error_response(request_id, e)),
+    }
+}
+
+#[utoipa::path(
+    tag = "templates",
+    params(("name" = String, Path, description = "Template name"),
+        ("prefix" = String, Path, description = "Instance prefix")),
+    responses(
+        (status = 200, description = "Deleted tags of the instance", body = InstanceResponse),
+        (status = 403, description = "Permission denied", body = ApiError),
+        (status = 404, description = "Template or instance not found", body = ApiError),
+    )
+)]
+#[delete("/{name}/instances/{prefix}")]
+#[instrument(skip(template_actor, access, identity, http_req))]
+pub async fn remove_instance(
+    template_actor: Data<ActorRef<actor::template::Message>>,
+    access: Data<AccessControl>,
+    identity: ReqData<Identity>,
+    http_req: HttpRequest,
+    path: Path<(String, String)>,
+) -> Result<HttpResponse, ApiError> {
+    let request_id = request_id_of(&http_req);
+    let (name, prefix) = path.into_inner();
+    tracing::info!(%request_id, "request: {} (remove_instance) prefix={}", name, prefix);
+
+    authorize(request_id, &access, &identity, Permission::Configure, None)?;
+
+    let (command, mut reply) =
+        actor::template::Message::remove_instance(name.as_str(), prefix.as_str());
+    template_actor
+        .enqueue(command.with_origin(Origin::request(request_id, &identity, http_req.peer_addr())))
+        .map_err(|e| ApiError::internal(request_id, e))?;
+    let result = reply
+        .recv()
+        .await
+        .ok_or_else(|| ApiError::internal(request_id, "actor response channel closed"))?;
+
+    match result {
+        Ok(tags) => Ok(HttpResponse::Ok().json(InstanceResponse {
+            prefix,
+            tags: tags.iter().map(ToString::to_string).collect(),
+        })),
+        Err(e) => Err(error_response(request_id, e)),
+    }
+}
@@ -0,0 +1,17 @@
+pub mod handlers;
+pub mod model;
+
+use actix_web::dev::HttpServiceFactory;
+use utoipa_actix_web::OpenApiFactory;
+
+pub fn scope() -> impl HttpServiceFactory + OpenApiFactory {
+    utoipa_actix_web::scope("/templates")
+        .service(handlers::create_template)
+        .service(handlers::list_templates)
+        .service(handlers::get_template)
+        .service(handlers::update_template)
+        .service(handlers::delete_template)
+        .service(handlers::list_instances)
+        .service(handlers::instantiate)
+        .service(handlers::remove_instance)
+}
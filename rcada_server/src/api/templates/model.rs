@@ -0,0 +1,41 @@
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+
+use crate::{
+    actor::template::InstanceUpdate,
+    repository::template::{TagTemplate, TemplatePoint},
+};
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, ToSchema)]
+pub struct UpdateTemplateRequest {
+    #[serde(default)]
+    pub description: String,
+    pub points: Vec<TemplatePoint>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, ToSchema)]
+pub struct UpdateTemplateResponse {
+    pub template: TagTemplate,
+    pub instances: Vec<InstanceUpdate>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, ToSchema)]
+pub struct ListTemplatesResponse {
+    pub templates: Vec<TagTemplate>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, ToSchema)]
+pub struct InstantiateRequest {
+    pub prefix: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, ToSchema)]
+pub struct InstanceResponse {
+    pub prefix: String,
+    pub tags: Vec<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, ToSchema)]
+pub struct ListInstancesResponse {
+    pub instances: Vec<String>,
+}
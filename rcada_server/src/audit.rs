@@ -0,0 +1,515 @@
+use std::{
+    fs::{self, File, OpenOptions},
+    io::{self, BufRead, BufReader, Write},
+    net::SocketAddr,
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex},
+};
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use utoipa::{IntoParams, ToSchema};
+use uuid::Uuid;
+
+use rcada_core::tag::TagName;
+
+use crate::auth::Identity;
+
+/// `prev_hash` of the first record.
+const GENESIS_HASH: &str = "0000000000000000000000000000000000000000000000000000000000000000";
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct AuditConfig {
+    #[serde(default = "default_enabled")]
+    pub enabled: bool,
+    /// Relative paths are resolved against the data directory.
+    #[serde(default = "default_file")]
+    pub file: PathBuf,
+    /// Also record value writes of drivers and other server components, not
+    /// only those made through the API.
+    #[serde(default)]
+    pub system_value_writes: bool,
+}
+
+fn default_enabled() -> bool {
+    true
+}
+
+fn default_file() -> PathBuf {
+    PathBuf::from("audit.log")
+}
+
+impl Default for AuditConfig {
+    fn default() -> Self {
+        Self {
+            enabled: default_enabled(),
+            file: default_file(),
+            system_value_writes: false,
+        }
+    }
+}
+
+/// Who asked for a change: a user of the API, or a component of the server.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Origin {
+    pub user: String,
+    pub source: Option<String>,
+    pub request_id: Option<Uuid>,
+    pub system: bool,
+}
+
+impl Origin {
+    pub fn system(component: impl Into<String>) -> Self {
+        Self {
+            user: component.into(),
+            source: None,
+            request_id: None,
+            system: true,
+        }
+    }
+
+    /// Command of a client of a protocol without users, e.g. an IEC 104 master.
+    /// It's recorded like a request of the API, not as a system write.
+    pub fn peer(protocol: impl Into<String>, peer: Option<SocketAddr>) -> Self {
+        Self {
+            user: protocol.into(),
+            source: peer.map(|peer| peer.ip().to_string()),
+            request_id: None,
+            system: false,
+        }
+    }
+
+    pub fn request(request_id: Uuid, identity: &Identity, peer: Option<SocketAddr>) -> Self {
+        Self {
+            user: identity.name.clone(),
+            source: peer.map(|peer| peer.ip().to_string()),
+            request_id: Some(request_id),
+            system: false,
+        }
+    }
+}
+
+impl Default for Origin {
+    fn default() -> Self {
+        Self::system("server")
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum AuditAction {
+    CreateTag,
+    UpdateValue,
+    UpdateMeta,
+    DeleteTag,
+    RenameTag,
+    CreateAlias,
+    DeleteAlias,
+}
+
+/// One line of the audit file. `hash` covers every other field and the
+/// previous record's hash, so changing or removing a record breaks the chain.
+///
+/// The hash is a plain SHA-256 without a key and the head of the chain isn't
+/// stored anywhere else. The chain shows accidental damage and careless edits,
+/// but whoever can write the file can also rewrite it with new hashes, or drop
+/// the newest records. Keep the hash of the last record elsewhere, e.g. with the
+/// backups, to be able to tell.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, ToSchema)]
+pub struct AuditRecord {
+    pub seq: u64,
+    pub timestamp: DateTime<Utc>,
+    pub user: String,
+    pub source: Option<String>,
+    pub request_id: Option<Uuid>,
+    pub action: AuditAction,
+    #[schema(value_type = String)]
+    pub tag: TagName,
+    pub old: Option<serde_json::Value>,
+    pub new: Option<serde_json::Value>,
+    /// `ok`, or the error that rejected the change.
+    pub result: String,
+    pub prev_hash: String,
+    pub hash: String,
+}
+
+impl AuditRecord {
+    fn compute_hash(&self) -> String {
+        let unhashed = Self {
+            hash: String::new(),
+            ..self.clone()
+        };
+        let json = serde_json::to_vec(&unhashed).unwrap_or_default();
+        let mut hasher = Sha256::new();
+        hasher.update(self.prev_hash.as_bytes());
+        hasher.update(&json);
+        format!("{:x}", hasher.finalize())
+    }
+}
+
+/// Filter of [`AuditLog::query`], unset fields match every record.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct AuditQuery {
+    pub tag: Option<String>,
+    pub user: Option<String>,
+    pub action: Option<AuditAction>,
+    pub request_id: Option<Uuid>,
+    pub from: Option<DateTime<Utc>>,
+    pub to: Option<DateTime<Utc>>,
+    /// Only the newest matching records are returned.
+    pub limit: Option<usize>,
+}
+
+impl AuditQuery {
+    fn matches(&self, record: &AuditRecord) -> bool {
+        self.tag
+            .as_ref()
+            .is_none_or(|tag| record.tag == tag.as_str())
+            && self.user.as_ref().is_none_or(|user| &record.user == user)
+            && self.action.is_none_or(|action| record.action == action)
+            && self
+                .request_id
+                .is_none_or(|id| record.request_id == Some(id))
+            && self.from.is_none_or(|from| record.timestamp >= from)
+            && self.to.is_none_or(|to| record.timestamp < to)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, ToSchema)]
+pub struct VerifyReport {
+    pub valid: bool,
+    pub records: u64,
+    /// Sequence number (or line for unreadable lines) where the chain breaks.
+    pub broken_at: Option<u64>,
+    pub reason: Option<String>,
+}
+
+struct Writer {
+    file: File,
+    seq: u64,
+    last_hash: String,
+}
+
+/// Append-only, hash-chained log of tag changes. Cloning shares the file.
+#[derive(Clone, Default)]
+pub struct AuditLog {
+    path: Option<PathBuf>,
+    system_value_writes: bool,
+    writer: Option<Arc<Mutex<Writer>>>,
+}
+
+impl AuditLog {
+    pub fn disabled() -> Self {
+        Self::default()
+    }
+
+    /// Opens the file and continues its chain.
+    pub fn open(path: impl AsRef<Path>, system_value_writes: bool) -> io::Result<Self> {
+        let path = path.as_ref().to_path_buf();
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        let file = OpenOptions::new()
+            .create(true)
+            .read(true)
+            .append(true)
+            .open(&path)?;
+
+        let (mut seq, mut last_hash) = (0, GENESIS_HASH.to_string());
+        let mut reader = BufReader::new(&file);
+        let mut line = Vec::new();
+        let mut complete = 0;
+        while reader.read_until(b'\n', &mut line)? > 0 && line.ends_with(b"\n") {
+            complete += line.len() as u64;
+            if let Ok(record) = serde_json::from_slice::<AuditRecord>(&line) {
+                seq = record.seq;
+                last_hash = record.hash;
+            }
+            line.clear();
+        }
+        // A crash may have left half a record that was never acknowledged,
+        // keeping it would break the chain
+        let len = file.metadata()?.len();
+        if complete < len {
+            tracing::warn!(
+                "{}: dropping {} bytes of an incomplete audit record",
+                path.display(),
+                len - complete
+            );
+            file.set_len(complete)?;
+            file.sync_data()?;
+        }
+
+        Ok(Self {
+            path: Some(path),
+            system_value_writes,
+            writer: Some(Arc::new(Mutex::new(Writer {
+                file,
+                seq,
+                last_hash,
+            }))),
+        })
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.writer.is_some()
+    }
+
+    /// Whether a value write from `origin` is recorded.
+    pub fn records_value_write(&self, origin: &Origin) -> bool {
+        self.is_enabled() && (!origin.system || self.system_value_writes)
+    }
+
+    /// Appends a record, errors are logged since the change already happened.
+    pub fn record(
+        &self,
+        origin: &Origin,
+        action: AuditAction,
+        tag: &TagName,
+        old: Option<serde_json::Value>,
+        new: Option<serde_json::Value>,
+        result: String,
+    ) {
+        let Some(writer) = &self.writer else {
+            return;
+        };
+        let mut writer = writer.lock().unwrap_or_else(|e| e.into_inner());
+        let mut record = AuditRecord {
+            seq: writer.seq + 1,
+            timestamp: Utc::now(),
+            user: origin.user.clone(),
+            source: origin.source.clone(),
+            request_id: origin.request_id,
+            action,
+            tag: tag.clone(),
+            old,
+            new,
+            result,
+            prev_hash: writer.last_hash.clone(),
+            hash: String::new(),
+        };
+        record.hash = record.compute_hash();
+
+        let written = serde_json::to_vec(&record)
+            .map_err(io::Error::other)
+            .and_then(|mut line| {
+                line.push(b'\n');
+                writer.file.write_all(&line)?;
+                writer.file.sync_data()
+            });
+        match written {
+            Ok(()) => {
+                writer.seq = record.seq;
+                writer.last_hash = record.hash;
+            },
+            Err(e) => tracing::error!("failed to write audit record {:?}: {}", record, e),
+        }
+    }
+
+    /// Newest records matching `query`, in the order they were written.
+    pub fn query(&self, query: &AuditQuery) -> io::Result<Vec<AuditRecord>> {
+        let mut records: Vec<AuditRecord> = self
+            .read_records()?
+            .filter_map(|(_, record)| record.ok())
+            .filter(|record| query.matches(record))
+            .collect();
+        if let Some(limit) = query.limit {
+            records.drain(..records.len().saturating_sub(limit));
+        }
+        Ok(records)
+    }
+
+    /// Checks that every record is intact and links to the one before it.
+    pub fn verify(&self) -> io::Result<VerifyReport> {
+        let mut report = VerifyReport {
+            valid: true,
+            records: 0,
+            broken_at: None,
+            reason: None,
+        };
+        let mut prev_hash = GENESIS_HASH.to_string();
+        let mut prev_seq = 0;
+        for (line, record) in self.read_records()? {
+            let problem = match &record {
+                Err(e) => Some((line, format!("unreadable record: {e}"))),
+                Ok(record) if record.seq != prev_seq + 1 => {
+                    Some((record.seq, format!("expected record {}", prev_seq + 1)))
+                },
+                Ok(record) if record.prev_hash != prev_hash => {
+                    Some((record.seq, "previous hash doesn't match".to_string()))
+                },
+                Ok(record) if record.compute_hash() != record.hash => {
+                    Some((record.seq, "record was modified".to_string()))
+                },
+                Ok(_) => None,
+            };
+            if let Some((at, reason)) = problem {
+                report.valid = false;
+                report.broken_at = Some(at);
+                report.reason = Some(reason);
+                break;
+            }
+            if let Ok(record) = record {
+                prev_seq = record.seq;
+                prev_hash = record.hash;
+                report.records += 1;
+            }
+        }
+        Ok(report)
+    }
+
+    fn read_records(
+        &self,
+    ) -> io::Result<impl Iterator<Item = (u64, serde_json::Result<AuditRecord>)>> {
+        let file = match &self.path {
+            Some(path) => Some(File::open(path)?),
+            None => None,
+        };
+        Ok(file
+            .into_iter()
+            .flat_map(|file| BufReader::new(file).lines())
+            .map_while(Result::ok)
+            .zip(1..)
+            .filter(|(line, _)| !line.is_empty())
+            .map(|(line, number)| (number, serde_json::from_str(&line))))
+    }
+}
+
+/// `ok`, or the error that rejected a change.
+pub fn outcome<T, E: std::fmt::Debug>(result: &Result<T, E>) -> String {
+    match result {
+        Ok(_) => "ok".to_string(),
+        Err(e) => format!("{e:?}"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn temp_log() -> PathBuf {
+        std::env::temp_dir().join(format!("rcada-audit-{}.log", Uuid::new_v4()))
+    }
+
+    fn write(log: &AuditLog, value: i64) {
+        log.record(
+            &Origin::system("test"),
+            AuditAction::UpdateValue,
+            &TagName::from("counter"),
+            None,
+            Some(serde_json::json!(value)),
+            "ok".to_string(),
+        );
+    }
+
+    fn rewrite_lines(path: &Path, change: impl FnOnce(&mut Vec<String>)) {
+        let mut lines: Vec<String> = fs::read_to_string(path)
+            .unwrap()
+            .lines()
+            .map(str::to_string)
+            .collect();
+        change(&mut lines);
+        fs::write(path, lines.join("\n") + "\n").unwrap();
+    }
+
+    #[test]
+    fn chain_continues_after_reopening() {
+        let path = temp_log();
+        let log = AuditLog::open(&path, true).unwrap();
+        write(&log, 1);
+        write(&log, 2);
+        drop(log);
+
+        let log = AuditLog::open(&path, true).unwrap();
+        write(&log, 3);
+        let report = log.verify().unwrap();
+        assert!(report.valid, "{report:?}");
+        assert_eq!(report.records, 3);
+
+        let records = log.query(&AuditQuery::default()).unwrap();
+        assert_eq!(
+            records.iter().map(|r| r.seq).collect::<Vec<_>>(),
+            vec![1, 2, 3]
+        );
+        assert_eq!(records[0].prev_hash, GENESIS_HASH);
+        assert_eq!(records[2].prev_hash, records[1].hash);
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn modified_record_breaks_the_chain() {
+        let path = temp_log();
+        let log = AuditLog::open(&path, true).unwrap();
+        for value in 1..=3 {
+            write(&log, value);
+        }
+        rewrite_lines(&path, |lines| {
+            lines[1] = lines[1].replace("\"new\":2", "\"new\":20");
+        });
+
+        let report = log.verify().unwrap();
+        assert!(!report.valid);
+        assert_eq!(report.records, 1);
+        assert_eq!(report.broken_at, Some(2));
+        assert_eq!(report.reason.as_deref(), Some("record was modified"));
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn removed_record_breaks_the_chain() {
+        let path = temp_log();
+        let log = AuditLog::open(&path, true).unwrap();
+        for value in 1..=3 {
+            write(&log, value);
+        }
+        rewrite_lines(&path, |lines| {
+            lines.remove(1);
+        });
+
+        let report = log.verify().unwrap();
+        assert!(!report.valid);
+        assert_eq!(report.broken_at, Some(3));
+        assert_eq!(report.reason.as_deref(), Some("expected record 2"));
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn torn_record_is_dropped_on_open() {
+        let path = temp_log();
+        let log = AuditLog::open(&path, true).unwrap();
+        write(&log, 1);
+        write(&log, 2);
+        drop(log);
+
+        // Crash in the middle of writing the third record
+        let contents = fs::read_to_string(&path).unwrap();
+        let torn = contents.lines().last().unwrap()[..40].to_string();
+        fs::write(&path, contents + &torn).unwrap();
+
+        let log = AuditLog::open(&path, true).unwrap();
+        write(&log, 3);
+        let report = log.verify().unwrap();
+        assert!(report.valid, "{report:?}");
+        assert_eq!(report.records, 3);
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn system_value_writes_are_optional() {
+        let path = temp_log();
+        let log = AuditLog::open(&path, false).unwrap();
+        let identity = Identity::anonymous();
+        assert!(!log.records_value_write(&Origin::system("driver")));
+        assert!(log.records_value_write(&Origin::request(Uuid::new_v4(), &identity, None)));
+        assert!(log.records_value_write(&Origin::peer("iec104", None)));
+        assert!(!AuditLog::disabled().records_value_write(&Origin::request(
+            Uuid::new_v4(),
+            &identity,
+            None
+        )));
+        fs::remove_file(path).unwrap();
+    }
+}
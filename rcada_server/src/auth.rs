@@ -0,0 +1,684 @@
+use std::{collections::HashMap, fs, path::PathBuf};
+
+use actix_web::http::header::{AUTHORIZATION, HeaderMap};
+use argon2::{
+    Argon2, PasswordHash, PasswordHasher, PasswordVerifier,
+    password_hash::{SaltString, rand_core::OsRng},
+};
+use chrono::{DateTime, Utc};
+use jsonwebtoken::{Algorithm, DecodingKey, EncodingKey, Header, Validation};
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+
+use crate::tls::ClientCertificate;
+
+/// Header carrying a static API key.
+pub const API_KEY_HEADER: &str = "X-API-Key";
+
+/// Prefix of the names of identities authenticated by a client certificate, so
+/// they can't pass for a user or API key with the same name.
+pub const CERT_PREFIX: &str = "cert:";
+
+/// HS256 secrets shorter than this are rejected.
+const MIN_SECRET_LEN: usize = 32;
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct AuthConfig {
+    /// Without authentication every request is anonymous and allowed.
+    #[serde(default)]
+    pub enabled: bool,
+    /// Leave `/api/v1/health` and `/metrics` open for load balancers and monitoring.
+    #[serde(default = "default_public_health")]
+    pub public_health: bool,
+    #[serde(default)]
+    pub api_keys: Vec<ApiKeyConfig>,
+    #[serde(default)]
+    pub jwt: Option<JwtConfig>,
+    /// Users that can get a token from `POST /api/v1/auth/login`.
+    #[serde(default)]
+    pub users: Vec<UserConfig>,
+    /// Clients that may authenticate by a certificate, see `http.tls`. Valid
+    /// certificates of other common names are rejected.
+    #[serde(default)]
+    pub client_certs: Vec<ClientCertConfig>,
+}
+
+/// Static key for machine clients, sent in the `X-API-Key` header.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ApiKeyConfig {
+    pub name: String,
+    pub key: String,
+    #[serde(default)]
+    pub roles: Vec<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct JwtConfig {
+    #[serde(default)]
+    pub algorithm: JwtAlgorithm,
+    /// HS256 secret or RS256 public key (PEM) used to verify tokens.
+    pub key_file: PathBuf,
+    /// RS256 private key (PEM) used to sign tokens on login.
+    #[serde(default)]
+    pub signing_key_file: Option<PathBuf>,
+    #[serde(default = "default_issuer")]
+    pub issuer: String,
+    #[serde(default = "default_token_ttl_secs")]
+    pub token_ttl_secs: u64,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+pub enum JwtAlgorithm {
+    #[default]
+    HS256,
+    RS256,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct UserConfig {
+    pub name: String,
+    /// Argon2 hash, see `rcada_server --hash-password`.
+    pub password_hash: String,
+    #[serde(default)]
+    pub roles: Vec<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ClientCertConfig {
+    /// Common name of the certificate subject.
+    pub name: String,
+    #[serde(default)]
+    pub roles: Vec<String>,
+}
+
+fn default_public_health() -> bool {
+    true
+}
+
+fn default_issuer() -> String {
+    "rcada".to_string()
+}
+
+fn default_token_ttl_secs() -> u64 {
+    3600
+}
+
+impl Default for AuthConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            public_health: default_public_health(),
+            api_keys: Vec::new(),
+            jwt: None,
+            users: Vec::new(),
+            client_certs: Vec::new(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+pub enum AuthMethod {
+    Anonymous,
+    ApiKey,
+    Jwt,
+    ClientCertificate,
+    /// User name and password sent by a protocol without tokens, e.g. OPC UA.
+    Password,
+}
+
+/// Who sent a request, stored in the request extensions by the middleware.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+pub struct Identity {
+    pub name: String,
+    pub method: AuthMethod,
+    /// Roles given by the configuration or the token, more can be bound
+    /// through the access API.
+    pub roles: Vec<String>,
+}
+
+impl Identity {
+    pub fn anonymous() -> Self {
+        Self {
+            name: "anonymous".to_string(),
+            method: AuthMethod::Anonymous,
+            roles: Vec::new(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Claims {
+    pub sub: String,
+    pub iss: String,
+    pub iat: i64,
+    pub exp: i64,
+    #[serde(default)]
+    pub roles: Vec<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, ToSchema)]
+pub struct Token {
+    pub token: String,
+    pub expires_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Clone, PartialEq, thiserror::Error)]
+pub enum AuthError {
+    #[error("missing credentials")]
+    MissingCredentials,
+    #[error("invalid API key")]
+    InvalidApiKey,
+    #[error("client certificate {0} is not configured")]
+    UnknownClientCertificate(String),
+    #[error("invalid token: {0}")]
+    InvalidToken(String),
+    #[error("invalid user name or password")]
+    InvalidLogin,
+    #[error("login is not configured")]
+    LoginDisabled,
+}
+
+struct JwtKeys {
+    issuer: String,
+    ttl_secs: u64,
+    header: Header,
+    decoding: DecodingKey,
+    validation: Validation,
+    encoding: Option<EncodingKey>,
+}
+
+/// Checks the credentials of requests and issues tokens.
+pub struct Authenticator {
+    enabled: bool,
+    public_health: bool,
+    api_keys: Vec<ApiKeyConfig>,
+    jwt: Option<JwtKeys>,
+    users: HashMap<String, UserConfig>,
+    client_certs: HashMap<String, Vec<String>>,
+}
+
+impl Authenticator {
+    /// Reads the key files, fails with a description of the first problem.
+    pub fn new(config: &AuthConfig) -> Result<Self, String> {
+        let jwt = config.jwt.as_ref().map(JwtKeys::load).transpose()?;
+        for user in &config.users {
+            PasswordHash::new(&user.password_hash)
+                .map_err(|e| format!("user {}: invalid password hash: {e}", user.name))?;
+        }
+        Ok(Self {
+            enabled: config.enabled,
+            public_health: config.public_health,
+            api_keys: config.api_keys.clone(),
+            jwt,
+            users: config
+                .users
+                .iter()
+                .map(|user| (user.name.clone(), user.clone()))
+                .collect(),
+            client_certs: config
+                .client_certs
+                .iter()
+                .map(|client| (client.name.clone(), client.roles.clone()))
+                .collect(),
+        })
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.enabled
+    }
+
+    pub fn is_health_public(&self) -> bool {
+        self.public_health
+    }
+
+    /// Identifies the sender of a request by its API key or bearer token, or
+    /// else by the verified certificate of its connection.
+    pub fn authenticate(
+        &self,
+        headers: &HeaderMap,
+        client_cert: Option<&ClientCertificate>,
+    ) -> Result<Identity, AuthError> {
+        self.authenticate_credentials(
+            headers.get(API_KEY_HEADER).map(|value| value.as_bytes()),
+            headers.get(AUTHORIZATION).map(|value| value.as_bytes()),
+            client_cert,
+        )
+    }
+
+    /// [`Authenticator::authenticate`] with the values of the `X-API-Key`
+    /// and `Authorization` headers, for requests that aren't served by actix.
+    pub fn authenticate_credentials(
+        &self,
+        api_key: Option<&[u8]>,
+        authorization: Option<&[u8]>,
+        client_cert: Option<&ClientCertificate>,
+    ) -> Result<Identity, AuthError> {
+        if !self.enabled {
+            return Ok(Identity::anonymous());
+        }
+        if let Some(cert) = client_cert
+            && api_key.is_none()
+            && authorization.is_none()
+        {
+            let roles = self
+                .client_certs
+                .get(&cert.common_name)
+                .ok_or_else(|| AuthError::UnknownClientCertificate(cert.common_name.clone()))?;
+            return Ok(Identity {
+                name: format!("{CERT_PREFIX}{}", cert.common_name),
+                method: AuthMethod::ClientCertificate,
+                roles: roles.clone(),
+            });
+        }
+        if let Some(key) = api_key {
+            return self
+                .api_keys
+                .iter()
+                .find(|api_key| constant_time_eq(api_key.key.as_bytes(), key))
+                .map(|api_key| Identity {
+                    name: api_key.name.clone(),
+                    method: AuthMethod::ApiKey,
+                    roles: api_key.roles.clone(),
+                })
+                .ok_or(AuthError::InvalidApiKey);
+        }
+
+        let token = authorization
+            .and_then(|value| std::str::from_utf8(value).ok())
+            .and_then(|value| value.strip_prefix("Bearer "))
+            .ok_or(AuthError::MissingCredentials)?;
+        let jwt = self
+            .jwt
+            .as_ref()
+            .ok_or_else(|| AuthError::InvalidToken("tokens are not accepted".to_string()))?;
+        let claims = jsonwebtoken::decode::<Claims>(token.trim(), &jwt.decoding, &jwt.validation)
+            .map_err(|e| AuthError::InvalidToken(e.to_string()))?
+            .claims;
+        // Roles of configured users follow the configuration, not the token
+        let roles = match self.users.get(&claims.sub) {
+            Some(user) => user.roles.clone(),
+            None => claims.roles,
+        };
+        Ok(Identity {
+            name: claims.sub,
+            method: AuthMethod::Jwt,
+            roles,
+        })
+    }
+
+    /// Issues a token for a configured user.
+    pub fn login(&self, name: &str, password: &str) -> Result<Token, AuthError> {
+        let jwt = self.jwt.as_ref().ok_or(AuthError::LoginDisabled)?;
+        let encoding = jwt.encoding.as_ref().ok_or(AuthError::LoginDisabled)?;
+
+        let user = self.verify_user(name, password)?;
+
+        let now = Utc::now();
+        let expires_at = now + chrono::Duration::seconds(jwt.ttl_secs as i64);
+        let claims = Claims {
+            sub: name.to_string(),
+            iss: jwt.issuer.clone(),
+            iat: now.timestamp(),
+            exp: expires_at.timestamp(),
+            roles: user.roles.clone(),
+        };
+        let token = jsonwebtoken::encode(&jwt.header, &claims, encoding)
+            .map_err(|e| AuthError::InvalidToken(e.to_string()))?;
+        Ok(Token {
+            token,
+            expires_at,
+        })
+    }
+
+    /// Checks a user name and password, for protocols that can't carry the
+    /// headers of [`Authenticator::authenticate_credentials`]. Configured
+    /// users log in with their password, API keys with their name and key.
+    pub fn authenticate_password(&self, name: &str, password: &str) -> Result<Identity, AuthError> {
+        if !self.enabled {
+            return Ok(Identity::anonymous());
+        }
+        if self.users.contains_key(name) {
+            let user = self.verify_user(name, password)?;
+            return Ok(Identity {
+                name: name.to_string(),
+                method: AuthMethod::Password,
+                roles: user.roles.clone(),
+            });
+        }
+        self.api_keys
+            .iter()
+            .find(|api_key| {
+                api_key.name == name
+                    && constant_time_eq(api_key.key.as_bytes(), password.as_bytes())
+            })
+            .map(|api_key| Identity {
+                name: api_key.name.clone(),
+                method: AuthMethod::ApiKey,
+                roles: api_key.roles.clone(),
+            })
+            .ok_or(AuthError::InvalidLogin)
+    }
+
+    fn verify_user(&self, name: &str, password: &str) -> Result<&UserConfig, AuthError> {
+        let user = self.users.get(name).ok_or(AuthError::InvalidLogin)?;
+        let hash = PasswordHash::new(&user.password_hash).map_err(|_| AuthError::InvalidLogin)?;
+        Argon2::default()
+            .verify_password(password.as_bytes(), &hash)
+            .map_err(|_| AuthError::InvalidLogin)?;
+        Ok(user)
+    }
+}
+
+impl JwtKeys {
+    fn load(config: &JwtConfig) -> Result<Self, String> {
+        let read = |path: &PathBuf| {
+            fs::read(path).map_err(|e| format!("cannot read {}: {e}", path.display()))
+        };
+        let key = read(&config.key_file)?;
+
+        let (algorithm, decoding, encoding) = match config.algorithm {
+            JwtAlgorithm::HS256 => {
+                let secret = key.trim_ascii();
+                if secret.len() < MIN_SECRET_LEN {
+                    return Err(format!(
+                        "{}: HS256 secret must be at least {MIN_SECRET_LEN} bytes",
+                        config.key_file.display()
+                    ));
+                }
+                (
+                    Algorithm::HS256,
+                    DecodingKey::from_secret(secret),
+                    Some(EncodingKey::from_secret(secret)),
+                )
+            },
+            JwtAlgorithm::RS256 => {
+                let decoding = DecodingKey::from_rsa_pem(&key)
+                    .map_err(|e| format!("{}: {e}", config.key_file.display()))?;
+                let encoding = match &config.signing_key_file {
+                    Some(path) => Some(
+                        EncodingKey::from_rsa_pem(&read(path)?)
+                            .map_err(|e| format!("{}: {e}", path.display()))?,
+                    ),
+                    None => None,
+                };
+                (Algorithm::RS256, decoding, encoding)
+            },
+        };
+
+        let mut validation = Validation::new(algorithm);
+        validation.set_issuer(&[&config.issuer]);
+        validation.set_required_spec_claims(&["exp", "iss", "sub"]);
+        Ok(Self {
+            issuer: config.issuer.clone(),
+            ttl_secs: config.token_ttl_secs,
+            header: Header::new(algorithm),
+            decoding,
+            validation,
+            encoding,
+        })
+    }
+}
+
+pub fn hash_password(password: &str) -> Result<String, String> {
+    let salt = SaltString::generate(&mut OsRng);
+    Argon2::default()
+        .hash_password(password.as_bytes(), &salt)
+        .map(|hash| hash.to_string())
+        .map_err(|e| e.to_string())
+}
+
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0, |acc, (x, y)| acc | (x ^ y)) == 0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SECRET: &str = "0123456789abcdef0123456789abcdef";
+
+    fn authenticator(jwt: bool) -> Authenticator {
+        let jwt = jwt.then(|| {
+            let key_file =
+                std::env::temp_dir().join(format!("rcada-jwt-{}.key", uuid::Uuid::new_v4()));
+            fs::write(&key_file, SECRET).unwrap();
+            JwtConfig {
+                algorithm: JwtAlgorithm::HS256,
+                key_file,
+                signing_key_file: None,
+                issuer: default_issuer(),
+                token_ttl_secs: default_token_ttl_secs(),
+            }
+        });
+        let config = AuthConfig {
+            enabled: true,
+            api_keys: vec![ApiKeyConfig {
+                name: "scada".to_string(),
+                key: "secret-key".to_string(),
+                roles: vec!["operator".to_string()],
+            }],
+            users: vec![UserConfig {
+                name: "alice".to_string(),
+                password_hash: hash_password("password").unwrap(),
+                roles: vec!["admin".to_string()],
+            }],
+            client_certs: vec![ClientCertConfig {
+                name: "historian".to_string(),
+                roles: vec!["viewer".to_string()],
+            }],
+            jwt: jwt.clone(),
+            ..AuthConfig::default()
+        };
+        let authenticator = Authenticator::new(&config).unwrap();
+        if let Some(jwt) = jwt {
+            fs::remove_file(jwt.key_file).unwrap();
+        }
+        authenticator
+    }
+
+    fn claims(exp: i64) -> Claims {
+        let now = Utc::now().timestamp();
+        Claims {
+            sub: "bob".to_string(),
+            iss: default_issuer(),
+            iat: now,
+            exp: now + exp,
+            roles: vec!["viewer".to_string()],
+        }
+    }
+
+    fn sign(algorithm: Algorithm, claims: &Claims) -> String {
+        jsonwebtoken::encode(
+            &Header::new(algorithm),
+            claims,
+            &EncodingKey::from_secret(SECRET.as_bytes()),
+        )
+        .unwrap()
+    }
+
+    fn bearer(authenticator: &Authenticator, token: &str) -> Result<Identity, AuthError> {
+        let authorization = format!("Bearer {token}");
+        authenticator.authenticate_credentials(None, Some(authorization.as_bytes()), None)
+    }
+
+    #[test]
+    fn constant_time_eq_compares_whole_values() {
+        assert!(constant_time_eq(b"secret-key", b"secret-key"));
+        assert!(!constant_time_eq(b"secret-key", b"secret-kez"));
+        assert!(!constant_time_eq(b"secret-key", b"secret"));
+        assert!(!constant_time_eq(b"secret", b"secret-key"));
+        assert!(constant_time_eq(b"", b""));
+    }
+
+    #[test]
+    fn api_key_must_match_exactly() {
+        let authenticator = authenticator(false);
+
+        let identity = authenticator
+            .authenticate_credentials(Some(b"secret-key"), None, None)
+            .unwrap();
+        assert_eq!(identity.name, "scada");
+        assert_eq!(identity.method, AuthMethod::ApiKey);
+        assert_eq!(identity.roles, vec!["operator".to_string()]);
+
+        for key in [&b"secret-ke"[..], b"secret-key ", b"SECRET-KEY", b""] {
+            assert_eq!(
+                authenticator.authenticate_credentials(Some(key), None, None),
+                Err(AuthError::InvalidApiKey)
+            );
+        }
+        assert_eq!(
+            authenticator.authenticate_credentials(None, None, None),
+            Err(AuthError::MissingCredentials)
+        );
+    }
+
+    #[test]
+    fn only_configured_client_certificates_are_accepted() {
+        let authenticator = authenticator(false);
+        let cert = |name: &str| ClientCertificate {
+            common_name: name.to_string(),
+        };
+
+        let identity = authenticator
+            .authenticate_credentials(None, None, Some(&cert("historian")))
+            .unwrap();
+        assert_eq!(identity.name, "cert:historian");
+        assert_eq!(identity.method, AuthMethod::ClientCertificate);
+        assert_eq!(identity.roles, vec!["viewer".to_string()]);
+
+        assert_eq!(
+            authenticator.authenticate_credentials(None, None, Some(&cert("alice"))),
+            Err(AuthError::UnknownClientCertificate("alice".to_string()))
+        );
+        // Credentials sent over the connection win over its certificate
+        let identity = authenticator
+            .authenticate_credentials(Some(b"secret-key"), None, Some(&cert("alice")))
+            .unwrap();
+        assert_eq!(identity.name, "scada");
+    }
+
+    #[test]
+    fn disabled_authentication_is_anonymous() {
+        let authenticator = Authenticator::new(&AuthConfig::default()).unwrap();
+        assert_eq!(
+            authenticator.authenticate_credentials(Some(b"wrong"), None, None),
+            Ok(Identity::anonymous())
+        );
+    }
+
+    #[test]
+    fn login_token_is_accepted() {
+        let authenticator = authenticator(true);
+        assert_eq!(
+            authenticator.login("alice", "wrong").unwrap_err(),
+            AuthError::InvalidLogin
+        );
+
+        let token = authenticator.login("alice", "password").unwrap();
+        let identity = bearer(&authenticator, &token.token).unwrap();
+        assert_eq!(identity.name, "alice");
+        assert_eq!(identity.method, AuthMethod::Jwt);
+        assert_eq!(identity.roles, vec!["admin".to_string()]);
+    }
+
+    #[test]
+    fn expired_token_is_rejected() {
+        let authenticator = authenticator(true);
+        assert!(bearer(&authenticator, &sign(Algorithm::HS256, &claims(60))).is_ok());
+
+        // Past the default leeway of 60 seconds
+        let token = sign(Algorithm::HS256, &claims(-120));
+        assert!(matches!(
+            bearer(&authenticator, &token),
+            Err(AuthError::InvalidToken(_))
+        ));
+    }
+
+    #[test]
+    fn token_with_invalid_signature_or_issuer_is_rejected() {
+        let authenticator = authenticator(true);
+
+        let token = jsonwebtoken::encode(
+            &Header::new(Algorithm::HS256),
+            &claims(60),
+            &EncodingKey::from_secret(b"another secret of at least 32 bytes"),
+        )
+        .unwrap();
+        assert!(matches!(
+            bearer(&authenticator, &token),
+            Err(AuthError::InvalidToken(_))
+        ));
+
+        let mut other_issuer = claims(60);
+        other_issuer.iss = "someone else".to_string();
+        let token = sign(Algorithm::HS256, &other_issuer);
+        assert!(matches!(
+            bearer(&authenticator, &token),
+            Err(AuthError::InvalidToken(_))
+        ));
+
+        assert!(matches!(
+            bearer(&authenticator, "not a token"),
+            Err(AuthError::InvalidToken(_))
+        ));
+    }
+
+    #[test]
+    fn token_with_other_algorithm_is_rejected() {
+        let authenticator = authenticator(true);
+
+        let token = sign(Algorithm::HS512, &claims(60));
+        assert!(matches!(
+            bearer(&authenticator, &token),
+            Err(AuthError::InvalidToken(_))
+        ));
+
+        // Unsigned token, header {"alg":"none","typ":"JWT"}
+        let payload = sign(Algorithm::HS256, &claims(60));
+        let payload = payload.split('.').nth(1).unwrap();
+        let token = format!("eyJhbGciOiJub25lIiwidHlwIjoiSldUIn0.{payload}.");
+        assert!(matches!(
+            bearer(&authenticator, &token),
+            Err(AuthError::InvalidToken(_))
+        ));
+    }
+
+    #[test]
+    fn tokens_are_refused_without_jwt_configuration() {
+        let authenticator = authenticator(false);
+        let token = sign(Algorithm::HS256, &claims(60));
+        assert!(matches!(
+            bearer(&authenticator, &token),
+            Err(AuthError::InvalidToken(_))
+        ));
+        assert_eq!(
+            authenticator.login("alice", "password").unwrap_err(),
+            AuthError::LoginDisabled
+        );
+    }
+
+    #[test]
+    fn short_hs256_secret_is_rejected() {
+        let key_file = std::env::temp_dir().join(format!("rcada-jwt-{}.key", uuid::Uuid::new_v4()));
+        fs::write(&key_file, "too short").unwrap();
+        let config = AuthConfig {
+            enabled: true,
+            jwt: Some(JwtConfig {
+                algorithm: JwtAlgorithm::HS256,
+                key_file: key_file.clone(),
+                signing_key_file: None,
+                issuer: default_issuer(),
+                token_ttl_secs: default_token_ttl_secs(),
+            }),
+            ..AuthConfig::default()
+        };
+        assert!(Authenticator::new(&config).is_err());
+        fs::remove_file(key_file).unwrap();
+    }
+}
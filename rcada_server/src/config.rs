@@ -0,0 +1,405 @@
+use std::{
+    collections::{HashMap, HashSet},
+    fs,
+    net::SocketAddr,
+    path::{Path, PathBuf},
+};
+
+use clap::Parser;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    api::configuration::model::TagConfig,
+    audit::AuditConfig,
+    auth::{AuthConfig, Authenticator, JwtAlgorithm},
+    dnp3::Dnp3Config,
+    driver::DriverConfig,
+    grpc::GrpcConfig,
+    iec104::Iec104Config,
+    opcua::OpcUaConfig,
+    tls::{self, TlsConfig},
+};
+
+pub const DEFAULT_CONFIG_FILE: &str = "rcada.toml";
+
+#[derive(Debug, thiserror::Error)]
+pub enum ConfigError {
+    #[error("cannot read {path}: {source}")]
+    Read {
+        path: PathBuf,
+        source: std::io::Error,
+    },
+    #[error("cannot parse {path}: {source}")]
+    Parse {
+        path: PathBuf,
+        source: Box<toml::de::Error>,
+    },
+    #[error("invalid configuration:\n  - {}", .0.join("\n  - "))]
+    Invalid(Vec<String>),
+}
+
+/// Command-line flags. Every flag can also be set through its environment
+/// variable and takes precedence over the configuration file.
+#[derive(Debug, Clone, Default, Parser)]
+#[command(name = "rcada_server", version, about = "RCADA SCADA server")]
+pub struct Args {
+    /// Configuration file, `rcada.toml` is used if it exists
+    #[arg(short, long, env = "RCADA_CONFIG")]
+    pub config: Option<PathBuf>,
+    /// Address the HTTP server listens on
+    #[arg(long, env = "RCADA_BIND")]
+    pub bind: Option<String>,
+    /// Number of HTTP worker threads
+    #[arg(long, env = "RCADA_WORKERS")]
+    pub workers: Option<usize>,
+    /// Certificate chain (PEM) to serve HTTPS with, needs `--tls-key`
+    #[arg(long, env = "RCADA_TLS_CERT")]
+    pub tls_cert: Option<PathBuf>,
+    /// Private key (PEM) of `--tls-cert`
+    #[arg(long, env = "RCADA_TLS_KEY")]
+    pub tls_key: Option<PathBuf>,
+    /// Log filter, e.g. `info` or `rcada_server=debug,actix_web=warn`
+    #[arg(long, env = "RCADA_LOG")]
+    pub log: Option<String>,
+    /// Tag storage backend
+    #[arg(long, env = "RCADA_STORAGE")]
+    pub storage: Option<StorageBackend>,
+    /// Directory for persisted data
+    #[arg(long, env = "RCADA_DATA_DIR")]
+    pub data_dir: Option<PathBuf>,
+    /// Validate the configuration and exit
+    #[arg(long)]
+    pub check: bool,
+    /// Read a password from stdin, print its hash for `auth.users` and exit
+    #[arg(long)]
+    pub hash_password: bool,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ServerConfig {
+    #[serde(default)]
+    pub http: HttpConfig,
+    #[serde(default)]
+    pub grpc: GrpcConfig,
+    #[serde(default)]
+    pub opcua: OpcUaConfig,
+    #[serde(default)]
+    pub iec104: Iec104Config,
+    #[serde(default)]
+    pub dnp3: Dnp3Config,
+    #[serde(default)]
+    pub log: LogConfig,
+    #[serde(default)]
+    pub storage: StorageConfig,
+    #[serde(default)]
+    pub auth: AuthConfig,
+    #[serde(default)]
+    pub audit: AuditConfig,
+    /// Tags created at startup if they don't exist yet.
+    #[serde(default)]
+    pub tags: Vec<TagConfig>,
+    #[serde(default)]
+    pub drivers: Vec<DriverConfig>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct HttpConfig {
+    #[serde(default = "default_bind")]
+    pub bind: String,
+    /// Defaults to the number of CPU cores.
+    #[serde(default)]
+    pub workers: Option<usize>,
+    /// Serve HTTPS instead of HTTP.
+    #[serde(default)]
+    pub tls: Option<TlsConfig>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct LogConfig {
+    #[serde(default = "default_log_filter")]
+    pub filter: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct StorageConfig {
+    #[serde(default)]
+    pub backend: StorageBackend,
+    #[serde(default = "default_data_dir")]
+    pub data_dir: PathBuf,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize, clap::ValueEnum)]
+#[serde(rename_all = "lowercase")]
+pub enum StorageBackend {
+    /// Tags are lost on restart, only aliases are persisted
+    #[default]
+    Memory,
+    /// Tags and their last values are persisted in the data directory
+    File,
+}
+
+fn default_bind() -> String {
+    "127.0.0.1:8080".to_string()
+}
+
+fn default_log_filter() -> String {
+    "info".to_string()
+}
+
+fn default_data_dir() -> PathBuf {
+    PathBuf::from("data")
+}
+
+impl Default for HttpConfig {
+    fn default() -> Self {
+        Self {
+            bind: default_bind(),
+            workers: None,
+            tls: None,
+        }
+    }
+}
+
+impl Default for LogConfig {
+    fn default() -> Self {
+        Self {
+            filter: default_log_filter(),
+        }
+    }
+}
+
+impl Default for StorageConfig {
+    fn default() -> Self {
+        Self {
+            backend: StorageBackend::default(),
+            data_dir: default_data_dir(),
+        }
+    }
+}
+
+impl ServerConfig {
+    /// Loads the configuration file named by `args` (or the default one if it
+    /// exists), applies the overrides from `args` and validates the result.
+    pub fn load(args: &Args) -> Result<Self, ConfigError> {
+        let mut config = match &args.config {
+            Some(path) => Self::from_file(path)?,
+            None if Path::new(DEFAULT_CONFIG_FILE).exists() => {
+                Self::from_file(DEFAULT_CONFIG_FILE)?
+            },
+            None => Self::default(),
+        };
+        config.apply_args(args);
+        config.validate()?;
+        Ok(config)
+    }
+
+    pub fn from_file(path: impl AsRef<Path>) -> Result<Self, ConfigError> {
+        let path = path.as_ref();
+        let text = fs::read_to_string(path).map_err(|source| ConfigError::Read {
+            path: path.to_path_buf(),
+            source,
+        })?;
+        toml::from_str(&text).map_err(|source| ConfigError::Parse {
+            path: path.to_path_buf(),
+            source: Box::new(source),
+        })
+    }
+
+    pub fn apply_args(&mut self, args: &Args) {
+        if let Some(bind) = &args.bind {
+            self.http.bind = bind.clone();
+        }
+        if let Some(workers) = args.workers {
+            self.http.workers = Some(workers);
+        }
+        if args.tls_cert.is_some() || args.tls_key.is_some() {
+            let tls = self.http.tls.get_or_insert_with(|| TlsConfig {
+                cert_file: PathBuf::new(),
+                key_file: PathBuf::new(),
+                client_ca_file: None,
+                require_client_cert: false,
+            });
+            if let Some(cert_file) = &args.tls_cert {
+                tls.cert_file = cert_file.clone();
+            }
+            if let Some(key_file) = &args.tls_key {
+                tls.key_file = key_file.clone();
+            }
+        }
+        if let Some(filter) = &args.log {
+            self.log.filter = filter.clone();
+        }
+        if let Some(backend) = args.storage {
+            self.storage.backend = backend;
+        }
+        if let Some(data_dir) = &args.data_dir {
+            self.storage.data_dir = data_dir.clone();
+        }
+    }
+
+    /// Collects every problem instead of stopping at the first one.
+    pub fn validate(&self) -> Result<(), ConfigError> {
+        let mut errors = Vec::new();
+
+        // Every enabled listener needs an address of its own
+        let mut binds = vec![("http", self.http.bind.as_str(), "127.0.0.1:8080")];
+        if self.grpc.enabled {
+            binds.push(("grpc", self.grpc.bind.as_str(), "127.0.0.1:50051"));
+        }
+        if self.opcua.enabled {
+            binds.push(("opcua", self.opcua.bind.as_str(), "127.0.0.1:4840"));
+        }
+        if self.iec104.enabled {
+            binds.push(("iec104", self.iec104.bind.as_str(), "127.0.0.1:2404"));
+        }
+        if self.dnp3.enabled {
+            binds.push(("dnp3", self.dnp3.bind.as_str(), "127.0.0.1:20000"));
+        }
+        let mut bound: HashMap<SocketAddr, &str> = HashMap::new();
+        for (name, bind, example) in binds {
+            match bind.parse::<SocketAddr>() {
+                Err(_) => errors.push(format!(
+                    "{name}.bind: `{bind}` is not an address like {example}"
+                )),
+                Ok(address) => {
+                    if let Some(other) = bound.insert(address, name) {
+                        errors.push(format!("{name}.bind: is the same as {other}.bind"));
+                    }
+                },
+            }
+        }
+        if self.http.workers == Some(0) {
+            errors.push("http.workers: must be positive".to_string());
+        }
+        if let Some(tls) = &self.http.tls {
+            if tls.require_client_cert && tls.client_ca_file.is_none() {
+                errors.push("http.tls: require_client_cert without client_ca_file".to_string());
+            }
+            if let Err(e) = tls::server_config(tls) {
+                errors.push(format!("http.tls: {e}"));
+            }
+        }
+        if self.opcua.enabled && self.opcua.security.is_empty() {
+            errors.push("opcua.security: no security mode".to_string());
+        }
+        if self.iec104.enabled {
+            errors.extend(self.iec104.validate());
+        }
+        if self.dnp3.enabled {
+            errors.extend(self.dnp3.validate());
+        }
+        if let Err(e) = tracing_subscriber::EnvFilter::try_new(&self.log.filter) {
+            errors.push(format!("log.filter: {e}"));
+        }
+        if self.storage.data_dir.as_os_str().is_empty() {
+            errors.push("storage.data_dir: is empty".to_string());
+        }
+
+        let client_certs = self
+            .http
+            .tls
+            .as_ref()
+            .is_some_and(|tls| tls.client_ca_file.is_some());
+        if self.auth.enabled
+            && self.auth.api_keys.is_empty()
+            && self.auth.jwt.is_none()
+            && !client_certs
+        {
+            errors.push("auth: enabled without api_keys, jwt or client certificates".to_string());
+        }
+        for api_key in &self.auth.api_keys {
+            if api_key.name.is_empty() || api_key.key.is_empty() {
+                errors.push("auth.api_keys: name and key must not be empty".to_string());
+            }
+        }
+        match &self.auth.jwt {
+            None if !self.auth.users.is_empty() => {
+                errors.push("auth.users: logging in requires auth.jwt".to_string());
+            },
+            Some(jwt)
+                if jwt.algorithm == JwtAlgorithm::RS256
+                    && jwt.signing_key_file.is_none()
+                    && !self.auth.users.is_empty() =>
+            {
+                errors.push("auth.users: RS256 login requires jwt.signing_key_file".to_string());
+            },
+            _ => {},
+        }
+        if self.audit.enabled && self.audit.file.as_os_str().is_empty() {
+            errors.push("audit.file: is empty".to_string());
+        }
+        if let Err(e) = Authenticator::new(&self.auth) {
+            errors.push(format!("auth: {e}"));
+        }
+
+        let mut tag_names = HashSet::new();
+        for tag in &self.tags {
+            if tag.name.is_empty() {
+                errors.push("tags: tag name is empty".to_string());
+            }
+            if !tag_names.insert(tag.name.as_str()) {
+                errors.push(format!("tags: {} is defined more than once", tag.name));
+            }
+            if tag.meta.range.is_some_and(|range| !range.is_valid()) {
+                errors.push(format!("tags: {}: invalid engineering range", tag.name));
+            }
+            if !tag.meta.alarms.is_valid() {
+                errors.push(format!("tags: {}: invalid alarm limits", tag.name));
+            }
+        }
+
+        let mut driver_names = HashSet::new();
+        for driver in &self.drivers {
+            if !driver_names.insert(driver.name()) {
+                errors.push(format!(
+                    "drivers: {} is defined more than once",
+                    driver.name()
+                ));
+            }
+            errors.extend(
+                driver
+                    .validate()
+                    .into_iter()
+                    .map(|e| format!("drivers: {e}")),
+            );
+        }
+
+        if errors.is_empty() {
+            Ok(())
+        } else {
+            Err(ConfigError::Invalid(errors))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn listeners_need_addresses_of_their_own() {
+        let mut config = ServerConfig::default();
+        config.grpc.enabled = true;
+        config.grpc.bind = "localhost".to_string();
+        config.dnp3.enabled = true;
+        config.dnp3.bind = config.http.bind.clone();
+        // Disabled listeners may share an address
+        config.opcua.bind = config.http.bind.clone();
+
+        let Err(ConfigError::Invalid(errors)) = config.validate() else {
+            panic!("valid configuration");
+        };
+        let binds: Vec<_> = errors.iter().filter(|e| e.contains(".bind")).collect();
+        assert_eq!(
+            binds,
+            [
+                "grpc.bind: `localhost` is not an address like 127.0.0.1:50051",
+                "dnp3.bind: is the same as http.bind",
+            ]
+        );
+    }
+}
@@ -0,0 +1,702 @@
+//! DNP3 application fragments: requests and responses with their object
+//! headers, limited to the groups of binary and analog points, controls,
+//! time and internal indications.
+
+use std::io;
+
+use chrono::{DateTime, Utc};
+
+pub const FIR: u8 = 0x80;
+pub const FIN: u8 = 0x40;
+pub const CON: u8 = 0x20;
+pub const UNS: u8 = 0x10;
+
+pub const CONFIRM: u8 = 0;
+pub const READ: u8 = 1;
+pub const WRITE: u8 = 2;
+pub const SELECT: u8 = 3;
+pub const OPERATE: u8 = 4;
+pub const DIRECT_OPERATE: u8 = 5;
+pub const DIRECT_OPERATE_NR: u8 = 6;
+pub const ENABLE_UNSOLICITED: u8 = 20;
+pub const DISABLE_UNSOLICITED: u8 = 21;
+pub const RESPONSE: u8 = 129;
+pub const UNSOLICITED_RESPONSE: u8 = 130;
+
+/// Internal indications, IIN1 in the low byte and IIN2 in the high one.
+pub const IIN_CLASS_1_EVENTS: u16 = 0x0002;
+pub const IIN_CLASS_2_EVENTS: u16 = 0x0004;
+pub const IIN_CLASS_3_EVENTS: u16 = 0x0008;
+pub const IIN_NEED_TIME: u16 = 0x0010;
+pub const IIN_DEVICE_RESTART: u16 = 0x0080;
+pub const IIN_NO_FUNCTION_CODE_SUPPORT: u16 = 0x0100;
+pub const IIN_OBJECT_UNKNOWN: u16 = 0x0200;
+pub const IIN_PARAMETER_ERROR: u16 = 0x0400;
+pub const IIN_EVENT_BUFFER_OVERFLOW: u16 = 0x0800;
+
+/// Index of the device restart bit among the internal indications (g80).
+pub const DEVICE_RESTART_INDEX: u16 = 7;
+
+/// Point flags, the state bit is only in binary flags and over-range only
+/// in analog ones.
+pub const FLAG_ONLINE: u8 = 0x01;
+pub const FLAG_RESTART: u8 = 0x02;
+pub const FLAG_COMM_LOST: u8 = 0x04;
+pub const FLAG_REMOTE_FORCED: u8 = 0x08;
+pub const FLAG_LOCAL_FORCED: u8 = 0x10;
+pub const FLAG_OVER_RANGE: u8 = 0x20;
+const FLAG_STATE: u8 = 0x80;
+
+/// Control status of commands echoed in responses.
+pub const STATUS_SUCCESS: u8 = 0;
+pub const STATUS_NO_SELECT: u8 = 2;
+pub const STATUS_FORMAT_ERROR: u8 = 3;
+pub const STATUS_NOT_SUPPORTED: u8 = 4;
+pub const STATUS_HARDWARE_ERROR: u8 = 6;
+
+/// Control codes of the control relay output block.
+pub const CONTROL_PULSE_ON: u8 = 0x01;
+pub const CONTROL_PULSE_OFF: u8 = 0x02;
+pub const CONTROL_LATCH_ON: u8 = 0x03;
+pub const CONTROL_LATCH_OFF: u8 = 0x04;
+pub const CONTROL_CLOSE: u8 = 0x40;
+pub const CONTROL_TRIP: u8 = 0x80;
+
+/// Object header qualifiers.
+pub const QUALIFIER_START_STOP_8: u8 = 0x00;
+pub const QUALIFIER_START_STOP_16: u8 = 0x01;
+pub const QUALIFIER_ALL: u8 = 0x06;
+pub const QUALIFIER_COUNT_8: u8 = 0x07;
+pub const QUALIFIER_COUNT_16: u8 = 0x08;
+pub const QUALIFIER_INDEX_8: u8 = 0x17;
+pub const QUALIFIER_INDEX_16: u8 = 0x28;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Fragment {
+    pub control: u8,
+    pub function: u8,
+    /// Internal indications, only sent in responses.
+    pub iin: u16,
+    pub headers: Vec<ObjectHeader>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ObjectHeader {
+    pub group: u8,
+    pub variation: u8,
+    pub qualifier: u8,
+    pub range: Range,
+    /// Objects with their indices, none in read requests.
+    pub objects: Vec<(u16, Object)>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Range {
+    All,
+    StartStop(u16, u16),
+    Count(u16),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Object {
+    /// Binary input or output, static or event, with `flags` apart from the
+    /// state.
+    Binary {
+        value: bool,
+        flags: u8,
+        time: Option<DateTime<Utc>>,
+    },
+    /// Analog input or output, static or event.
+    Analog {
+        value: f64,
+        flags: u8,
+        time: Option<DateTime<Utc>>,
+    },
+    /// Control relay output block.
+    Crob {
+        code: u8,
+        count: u8,
+        on_ms: u32,
+        off_ms: u32,
+        status: u8,
+    },
+    AnalogOutput {
+        value: f64,
+        status: u8,
+    },
+    Time(DateTime<Utc>),
+    /// Bit of the internal indications.
+    Indication(bool),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Number {
+    I16,
+    I32,
+    F32,
+    F64,
+}
+
+/// How the objects of a group and variation are laid out.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Layout {
+    /// One bit per object.
+    Packed,
+    Binary {
+        time: bool,
+    },
+    Analog {
+        number: Number,
+        flags: bool,
+        time: bool,
+    },
+    AnalogOutput(Number),
+    Crob,
+    Time,
+    /// Objects that are read past, like common time of occurrence.
+    Skipped(usize),
+}
+
+fn layout(group: u8, variation: u8) -> Option<Layout> {
+    use Number::*;
+    let analog = |number, flags, time| Layout::Analog {
+        number,
+        flags,
+        time,
+    };
+    Some(match (group, variation) {
+        (1 | 10 | 80, 1) => Layout::Packed,
+        (1 | 10, 2) | (2 | 11, 1) => Layout::Binary {
+            time: false,
+        },
+        (2 | 11, 2) => Layout::Binary {
+            time: true,
+        },
+        (30, 1) | (32 | 40 | 42, 1) => analog(I32, true, false),
+        (30, 2) | (32 | 40 | 42, 2) => analog(I16, true, false),
+        (30, 3) => analog(I32, false, false),
+        (30, 4) => analog(I16, false, false),
+        (30 | 32 | 42, 5) | (40, 3) => analog(F32, true, false),
+        (30 | 32 | 42, 6) | (40, 4) => analog(F64, true, false),
+        (32 | 42, 3) => analog(I32, true, true),
+        (32 | 42, 4) => analog(I16, true, true),
+        (32 | 42, 7) => analog(F32, true, true),
+        (32 | 42, 8) => analog(F64, true, true),
+        (41, 1) => Layout::AnalogOutput(I32),
+        (41, 2) => Layout::AnalogOutput(I16),
+        (41, 3) => Layout::AnalogOutput(F32),
+        (41, 4) => Layout::AnalogOutput(F64),
+        (12, 1) => Layout::Crob,
+        (50, 1) => Layout::Time,
+        (51, 1 | 2) => Layout::Skipped(6),
+        (52, 1 | 2) => Layout::Skipped(2),
+        _ => return None,
+    })
+}
+
+impl Fragment {
+    /// Single fragment request.
+    pub fn request(function: u8, sequence: u8, headers: Vec<ObjectHeader>) -> Self {
+        Self {
+            control: FIR | FIN | (sequence & 0x0f),
+            function,
+            iin: 0,
+            headers,
+        }
+    }
+
+    pub fn sequence(&self) -> u8 {
+        self.control & 0x0f
+    }
+
+    pub fn is_response(&self) -> bool {
+        matches!(self.function, RESPONSE | UNSOLICITED_RESPONSE)
+    }
+
+    pub fn encode(&self) -> Vec<u8> {
+        let mut buf = vec![self.control, self.function];
+        if self.is_response() {
+            buf.extend_from_slice(&self.iin.to_le_bytes());
+        }
+        for header in &self.headers {
+            header.encode(&mut buf);
+        }
+        buf
+    }
+
+    pub fn parse(bytes: &[u8]) -> io::Result<Fragment> {
+        let mut reader = Reader(bytes);
+        let control = reader.u8()?;
+        let function = reader.u8()?;
+        let mut fragment = Fragment {
+            control,
+            function,
+            iin: 0,
+            headers: Vec::new(),
+        };
+        if fragment.is_response() {
+            fragment.iin = reader.u16()?;
+        }
+        // Reads and unsolicited controls name objects without their values
+        let data = !matches!(function, READ | ENABLE_UNSOLICITED | DISABLE_UNSOLICITED);
+        while !reader.0.is_empty() {
+            fragment
+                .headers
+                .push(ObjectHeader::parse(&mut reader, data)?);
+        }
+        Ok(fragment)
+    }
+
+    /// Header of the fragment parsed so far, for answering requests that
+    /// can't be parsed.
+    pub fn peek(bytes: &[u8]) -> Option<(u8, u8)> {
+        Some((*bytes.first()?, *bytes.get(1)?))
+    }
+
+    /// Splits `headers` over fragments of at most [`MAX_FRAGMENT`] bytes,
+    /// splitting the objects of a header if it doesn't fit in one.
+    ///
+    /// [`MAX_FRAGMENT`]: super::link::MAX_FRAGMENT
+    pub fn split(headers: Vec<ObjectHeader>) -> Vec<Vec<ObjectHeader>> {
+        // Room for the response header and the internal indications
+        let max_len = super::link::MAX_FRAGMENT - 4;
+        let mut fragments = Vec::new();
+        let mut current = Vec::new();
+        let mut len = 0;
+        for part in headers.iter().flat_map(|header| header.chunks(max_len)) {
+            let part_len = part.encoded_len();
+            if len + part_len > max_len && !current.is_empty() {
+                fragments.push(std::mem::take(&mut current));
+                len = 0;
+            }
+            len += part_len;
+            current.push(part);
+        }
+        fragments.push(current);
+        fragments
+    }
+}
+
+impl ObjectHeader {
+    /// Every object of a group, for reads.
+    pub fn all(group: u8, variation: u8) -> Self {
+        Self {
+            group,
+            variation,
+            qualifier: QUALIFIER_ALL,
+            range: Range::All,
+            objects: Vec::new(),
+        }
+    }
+
+    /// Objects with a range if their indices are consecutive, otherwise with
+    /// their indices.
+    pub fn range(group: u8, variation: u8, objects: Vec<(u16, Object)>) -> Self {
+        let consecutive = objects
+            .windows(2)
+            .all(|pair| pair[0].0.checked_add(1) == Some(pair[1].0));
+        match (consecutive, objects.first(), objects.last()) {
+            (true, Some(&(start, _)), Some(&(stop, _))) => Self {
+                group,
+                variation,
+                qualifier: if stop <= 0xff {
+                    QUALIFIER_START_STOP_8
+                } else {
+                    QUALIFIER_START_STOP_16
+                },
+                range: Range::StartStop(start, stop),
+                objects,
+            },
+            _ => Self::indexed(group, variation, objects),
+        }
+    }
+
+    /// Objects prefixed with their indices, for events and commands.
+    pub fn indexed(group: u8, variation: u8, objects: Vec<(u16, Object)>) -> Self {
+        Self {
+            group,
+            variation,
+            qualifier: QUALIFIER_INDEX_16,
+            range: Range::Count(objects.len() as u16),
+            objects,
+        }
+    }
+
+    /// Objects without indices, like the time.
+    pub fn counted(group: u8, variation: u8, objects: Vec<Object>) -> Self {
+        Self {
+            group,
+            variation,
+            qualifier: QUALIFIER_COUNT_8,
+            range: Range::Count(objects.len() as u16),
+            objects: objects
+                .into_iter()
+                .enumerate()
+                .map(|(index, object)| (index as u16, object))
+                .collect(),
+        }
+    }
+
+    /// The same header with other objects, the range follows them.
+    pub fn with_objects(&self, objects: Vec<(u16, Object)>) -> Self {
+        match self.qualifier {
+            QUALIFIER_START_STOP_8 | QUALIFIER_START_STOP_16 => {
+                Self::range(self.group, self.variation, objects)
+            },
+            qualifier => Self {
+                group: self.group,
+                variation: self.variation,
+                qualifier,
+                range: Range::Count(objects.len() as u16),
+                objects,
+            },
+        }
+    }
+
+    /// The header split in headers of at most `max_len` bytes each.
+    fn chunks(&self, max_len: usize) -> Vec<ObjectHeader> {
+        let len = self.encoded_len();
+        if len <= max_len || self.objects.len() < 2 {
+            return vec![self.clone()];
+        }
+        // The range of a part takes at most as many bytes as this one
+        let empty_len = self.with_objects(Vec::new()).encoded_len().max(7);
+        let object_len = (len - empty_len).div_ceil(self.objects.len());
+        let per_part = ((max_len - empty_len) / object_len.max(1)).max(1);
+        self.objects
+            .chunks(per_part)
+            .map(|objects| self.with_objects(objects.to_vec()))
+            .collect()
+    }
+
+    fn encoded_len(&self) -> usize {
+        let mut buf = Vec::new();
+        self.encode(&mut buf);
+        buf.len()
+    }
+
+    fn encode(&self, buf: &mut Vec<u8>) {
+        buf.extend_from_slice(&[self.group, self.variation, self.qualifier]);
+        match (self.qualifier, self.range) {
+            (QUALIFIER_START_STOP_8, Range::StartStop(start, stop)) => {
+                buf.extend_from_slice(&[start as u8, stop as u8]);
+            },
+            (QUALIFIER_START_STOP_16, Range::StartStop(start, stop)) => {
+                buf.extend_from_slice(&start.to_le_bytes());
+                buf.extend_from_slice(&stop.to_le_bytes());
+            },
+            (QUALIFIER_COUNT_8 | QUALIFIER_INDEX_8, Range::Count(count)) => buf.push(count as u8),
+            (QUALIFIER_COUNT_16 | QUALIFIER_INDEX_16, Range::Count(count)) => {
+                buf.extend_from_slice(&count.to_le_bytes());
+            },
+            _ => {},
+        }
+        let Some(layout) = layout(self.group, self.variation) else {
+            return;
+        };
+        if layout == Layout::Packed {
+            let mut bits = vec![0u8; self.objects.len().div_ceil(8)];
+            for (position, (_, object)) in self.objects.iter().enumerate() {
+                let set = match object {
+                    Object::Binary {
+                        value,
+                        ..
+                    } => *value,
+                    Object::Indication(value) => *value,
+                    _ => false,
+                };
+                if set {
+                    bits[position / 8] |= 1 << (position % 8);
+                }
+            }
+            buf.extend_from_slice(&bits);
+            return;
+        }
+        for (index, object) in &self.objects {
+            match self.qualifier {
+                QUALIFIER_INDEX_8 => buf.push(*index as u8),
+                QUALIFIER_INDEX_16 => buf.extend_from_slice(&index.to_le_bytes()),
+                _ => {},
+            }
+            object.encode(layout, buf);
+        }
+    }
+
+    fn parse(reader: &mut Reader, data: bool) -> io::Result<ObjectHeader> {
+        let group = reader.u8()?;
+        let variation = reader.u8()?;
+        let qualifier = reader.u8()?;
+        let range = match qualifier {
+            QUALIFIER_START_STOP_8 => {
+                let start = reader.u8()?;
+                Range::StartStop(u16::from(start), u16::from(reader.u8()?))
+            },
+            QUALIFIER_START_STOP_16 => {
+                let start = reader.u16()?;
+                Range::StartStop(start, reader.u16()?)
+            },
+            QUALIFIER_ALL => Range::All,
+            QUALIFIER_COUNT_8 | QUALIFIER_INDEX_8 => Range::Count(u16::from(reader.u8()?)),
+            QUALIFIER_COUNT_16 | QUALIFIER_INDEX_16 => Range::Count(reader.u16()?),
+            _ => return Err(invalid(format!("unsupported qualifier {qualifier:#04x}"))),
+        };
+        let mut header = ObjectHeader {
+            group,
+            variation,
+            qualifier,
+            range,
+            objects: Vec::new(),
+        };
+        // Class data and variation 0 name objects without values
+        if !data || group == 60 || variation == 0 || range == Range::All {
+            return Ok(header);
+        }
+        let layout = layout(group, variation)
+            .ok_or_else(|| invalid(format!("unsupported object g{group}v{variation}")))?;
+        let indices: Vec<Option<u16>> = match range {
+            Range::StartStop(start, stop) if start <= stop => (start..=stop).map(Some).collect(),
+            Range::Count(count) => (0..count).map(|_| None).collect(),
+            _ => return Err(invalid("invalid range")),
+        };
+        if layout == Layout::Packed {
+            let bytes = reader.take(indices.len().div_ceil(8))?;
+            for (position, index) in indices.iter().enumerate() {
+                let set = bytes[position / 8] & (1 << (position % 8)) != 0;
+                let object = if group == 80 {
+                    Object::Indication(set)
+                } else {
+                    Object::Binary {
+                        value: set,
+                        flags: FLAG_ONLINE,
+                        time: None,
+                    }
+                };
+                header
+                    .objects
+                    .push((index.unwrap_or(position as u16), object));
+            }
+            return Ok(header);
+        }
+        for (position, index) in indices.into_iter().enumerate() {
+            let index = match (index, qualifier) {
+                (Some(index), _) => index,
+                (None, QUALIFIER_INDEX_8) => u16::from(reader.u8()?),
+                (None, QUALIFIER_INDEX_16) => reader.u16()?,
+                (None, _) => position as u16,
+            };
+            if let Some(object) = Object::parse(layout, reader)? {
+                header.objects.push((index, object));
+            }
+        }
+        Ok(header)
+    }
+}
+
+impl Object {
+    fn encode(&self, layout: Layout, buf: &mut Vec<u8>) {
+        match (self, layout) {
+            (
+                Object::Binary {
+                    value,
+                    flags,
+                    time,
+                },
+                Layout::Binary {
+                    time: timed,
+                },
+            ) => {
+                buf.push((flags & !FLAG_STATE) | if *value { FLAG_STATE } else { 0 });
+                if timed {
+                    put_time(buf, time.unwrap_or_else(Utc::now));
+                }
+            },
+            (
+                Object::Analog {
+                    value,
+                    flags,
+                    time,
+                },
+                Layout::Analog {
+                    number,
+                    flags: with_flags,
+                    time: timed,
+                },
+            ) => {
+                let mut number_bytes = Vec::new();
+                let overflow = put_number(&mut number_bytes, number, *value);
+                if with_flags {
+                    buf.push(if overflow {
+                        flags | FLAG_OVER_RANGE
+                    } else {
+                        *flags
+                    });
+                }
+                buf.extend_from_slice(&number_bytes);
+                if timed {
+                    put_time(buf, time.unwrap_or_else(Utc::now));
+                }
+            },
+            (
+                Object::AnalogOutput {
+                    value,
+                    status,
+                },
+                Layout::AnalogOutput(number),
+            ) => {
+                put_number(buf, number, *value);
+                buf.push(*status);
+            },
+            (
+                Object::Crob {
+                    code,
+                    count,
+                    on_ms,
+                    off_ms,
+                    status,
+                },
+                Layout::Crob,
+            ) => {
+                buf.extend_from_slice(&[*code, *count]);
+                buf.extend_from_slice(&on_ms.to_le_bytes());
+                buf.extend_from_slice(&off_ms.to_le_bytes());
+                buf.push(*status);
+            },
+            (Object::Time(time), Layout::Time) => put_time(buf, *time),
+            _ => {},
+        }
+    }
+
+    fn parse(layout: Layout, reader: &mut Reader) -> io::Result<Option<Object>> {
+        let object = match layout {
+            Layout::Binary {
+                time,
+            } => {
+                let flags = reader.u8()?;
+                Object::Binary {
+                    value: flags & FLAG_STATE != 0,
+                    flags: flags & !FLAG_STATE,
+                    time: time.then(|| reader.time()).transpose()?,
+                }
+            },
+            Layout::Analog {
+                number,
+                flags,
+                time,
+            } => {
+                let flags = if flags { reader.u8()? } else { FLAG_ONLINE };
+                Object::Analog {
+                    value: reader.number(number)?,
+                    flags,
+                    time: time.then(|| reader.time()).transpose()?,
+                }
+            },
+            Layout::AnalogOutput(number) => Object::AnalogOutput {
+                value: reader.number(number)?,
+                status: reader.u8()?,
+            },
+            Layout::Crob => Object::Crob {
+                code: reader.u8()?,
+                count: reader.u8()?,
+                on_ms: reader.u32()?,
+                off_ms: reader.u32()?,
+                status: reader.u8()?,
+            },
+            Layout::Time => Object::Time(reader.time()?),
+            Layout::Skipped(len) => {
+                reader.take(len)?;
+                return Ok(None);
+            },
+            Layout::Packed => return Err(invalid("packed objects must have a range")),
+        };
+        Ok(Some(object))
+    }
+}
+
+/// Writes `value` as `number`, returns whether it had to be clamped.
+fn put_number(buf: &mut Vec<u8>, number: Number, value: f64) -> bool {
+    match number {
+        Number::I16 => {
+            let clamped = value
+                .round()
+                .clamp(f64::from(i16::MIN), f64::from(i16::MAX));
+            buf.extend_from_slice(&(clamped as i16).to_le_bytes());
+            clamped != value.round()
+        },
+        Number::I32 => {
+            let clamped = value
+                .round()
+                .clamp(f64::from(i32::MIN), f64::from(i32::MAX));
+            buf.extend_from_slice(&(clamped as i32).to_le_bytes());
+            clamped != value.round()
+        },
+        Number::F32 => {
+            buf.extend_from_slice(&(value as f32).to_le_bytes());
+            false
+        },
+        Number::F64 => {
+            buf.extend_from_slice(&value.to_le_bytes());
+            false
+        },
+    }
+}
+
+/// Writes `time` as milliseconds since the epoch in 48 bits.
+fn put_time(buf: &mut Vec<u8>, time: DateTime<Utc>) {
+    let milliseconds = time.timestamp_millis().max(0) as u64;
+    buf.extend_from_slice(&milliseconds.to_le_bytes()[..6]);
+}
+
+fn invalid(message: impl Into<String>) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message.into())
+}
+
+struct Reader<'a>(&'a [u8]);
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, n: usize) -> io::Result<&'a [u8]> {
+        if self.0.len() < n {
+            return Err(invalid("fragment too short"));
+        }
+        let (taken, rest) = self.0.split_at(n);
+        self.0 = rest;
+        Ok(taken)
+    }
+
+    fn u8(&mut self) -> io::Result<u8> {
+        Ok(self.take(1)?[0])
+    }
+
+    fn u16(&mut self) -> io::Result<u16> {
+        let bytes = self.take(2)?;
+        Ok(u16::from_le_bytes([bytes[0], bytes[1]]))
+    }
+
+    fn u32(&mut self) -> io::Result<u32> {
+        let bytes = self.take(4)?;
+        Ok(u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+    }
+
+    fn number(&mut self, number: Number) -> io::Result<f64> {
+        Ok(match number {
+            Number::I16 => f64::from(self.u16()? as i16),
+            Number::I32 => f64::from(self.u32()? as i32),
+            Number::F32 => f64::from(f32::from_bits(self.u32()?)),
+            Number::F64 => {
+                let bytes = self.take(8)?;
+                f64::from_le_bytes(
+                    bytes
+                        .try_into()
+                        .map_err(|_| invalid("fragment too short"))?,
+                )
+            },
+        })
+    }
+
+    fn time(&mut self) -> io::Result<DateTime<Utc>> {
+        let bytes = self.take(6)?;
+        let mut milliseconds = [0u8; 8];
+        milliseconds[..6].copy_from_slice(bytes);
+        DateTime::from_timestamp_millis(i64::from_le_bytes(milliseconds))
+            .ok_or_else(|| invalid("invalid time"))
+    }
+}
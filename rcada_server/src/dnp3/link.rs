@@ -0,0 +1,289 @@
+//! DNP3 data link frames and the transport function, which together carry
+//! application fragments over TCP.
+
+use std::io;
+
+use tokio::{
+    io::{AsyncRead, AsyncReadExt, AsyncWriteExt},
+    net::{
+        TcpStream,
+        tcp::{OwnedReadHalf, OwnedWriteHalf},
+    },
+    sync::mpsc,
+    task::JoinHandle,
+};
+
+const START: [u8; 2] = [0x05, 0x64];
+
+/// Frames from the master.
+const DIR: u8 = 0x80;
+/// Frames starting a transaction, the others answer them.
+const PRM: u8 = 0x40;
+
+const RESET_LINK_STATES: u8 = 0;
+const TEST_LINK_STATES: u8 = 2;
+const CONFIRMED_USER_DATA: u8 = 3;
+const UNCONFIRMED_USER_DATA: u8 = 4;
+const REQUEST_LINK_STATUS: u8 = 9;
+
+const ACK: u8 = 0;
+const LINK_STATUS: u8 = 11;
+const NOT_SUPPORTED: u8 = 15;
+
+/// User data bytes of a frame, split in blocks of 16 with their CRC.
+const MAX_USER_DATA: usize = 250;
+const BLOCK_LEN: usize = 16;
+
+/// Application bytes of a transport segment, after its header.
+const MAX_SEGMENT: usize = MAX_USER_DATA - 1;
+
+/// Longest application fragment, from the master or the outstation.
+pub const MAX_FRAGMENT: usize = 2048;
+
+const FIN: u8 = 0x80;
+const FIR: u8 = 0x40;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LinkFrame {
+    pub control: u8,
+    pub destination: u16,
+    pub source: u16,
+    pub data: Vec<u8>,
+}
+
+impl LinkFrame {
+    fn function(&self) -> u8 {
+        self.control & 0x0f
+    }
+
+    pub fn encode(&self, buf: &mut Vec<u8>) {
+        let mut header = Vec::with_capacity(8);
+        header.extend_from_slice(&START);
+        // Segments keep the user data within a frame
+        header.push((5 + self.data.len().min(MAX_USER_DATA)) as u8);
+        header.push(self.control);
+        header.extend_from_slice(&self.destination.to_le_bytes());
+        header.extend_from_slice(&self.source.to_le_bytes());
+        buf.extend_from_slice(&header);
+        buf.extend_from_slice(&crc(&header).to_le_bytes());
+        for block in self.data[..self.data.len().min(MAX_USER_DATA)].chunks(BLOCK_LEN) {
+            buf.extend_from_slice(block);
+            buf.extend_from_slice(&crc(block).to_le_bytes());
+        }
+    }
+
+    /// Reads the next frame from a connection.
+    pub async fn read(reader: &mut (impl AsyncRead + Unpin)) -> io::Result<LinkFrame> {
+        let mut header = [0u8; 10];
+        reader.read_exact(&mut header).await?;
+        if header[..2] != START {
+            return Err(invalid("frame doesn't start with 0x0564"));
+        }
+        if crc(&header[..8]).to_le_bytes() != header[8..] {
+            return Err(invalid("CRC error in frame header"));
+        }
+        let length = usize::from(header[2]);
+        if length < 5 {
+            return Err(invalid(format!("invalid frame length {length}")));
+        }
+        let mut remaining = length - 5;
+        let mut data = Vec::with_capacity(remaining);
+        while remaining > 0 {
+            let len = remaining.min(BLOCK_LEN);
+            let mut block = [0u8; BLOCK_LEN + 2];
+            reader.read_exact(&mut block[..len + 2]).await?;
+            if crc(&block[..len]).to_le_bytes() != block[len..len + 2] {
+                return Err(invalid("CRC error in frame data"));
+            }
+            data.extend_from_slice(&block[..len]);
+            remaining -= len;
+        }
+        Ok(LinkFrame {
+            control: header[3],
+            destination: u16::from_le_bytes([header[4], header[5]]),
+            source: u16::from_le_bytes([header[6], header[7]]),
+            data,
+        })
+    }
+}
+
+/// CRC-16/DNP of the frame header and of every data block.
+fn crc(data: &[u8]) -> u16 {
+    let mut crc = 0u16;
+    for &byte in data {
+        crc ^= u16::from(byte);
+        for _ in 0..8 {
+            crc = if crc & 1 != 0 {
+                (crc >> 1) ^ 0xa6bc
+            } else {
+                crc >> 1
+            };
+        }
+    }
+    !crc
+}
+
+fn invalid(message: impl Into<String>) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message.into())
+}
+
+/// Joins transport segments into fragments.
+#[derive(Default)]
+struct Reassembly {
+    fragment: Vec<u8>,
+    /// Sequence number of the next segment, `None` before a first one.
+    next: Option<u8>,
+}
+
+impl Reassembly {
+    /// Adds a segment, returns the fragment it completes. Segments out of
+    /// sequence drop the fragment.
+    fn push(&mut self, segment: &[u8]) -> Option<Vec<u8>> {
+        let (&header, data) = segment.split_first()?;
+        let sequence = header & 0x3f;
+        if header & FIR != 0 {
+            self.fragment.clear();
+        } else if self.next != Some(sequence) {
+            self.fragment.clear();
+            self.next = None;
+            return None;
+        }
+        if self.fragment.len() + data.len() > MAX_FRAGMENT {
+            self.fragment.clear();
+            self.next = None;
+            return None;
+        }
+        self.fragment.extend_from_slice(data);
+        self.next = Some((sequence + 1) & 0x3f);
+        if header & FIN == 0 {
+            return None;
+        }
+        self.next = None;
+        Some(std::mem::take(&mut self.fragment))
+    }
+}
+
+/// A TCP connection to the other station, sending fragments as unconfirmed
+/// user data and answering the link layer requests of the other side.
+pub struct Link {
+    frames: mpsc::Receiver<io::Result<LinkFrame>>,
+    reading: JoinHandle<()>,
+    writer: OwnedWriteHalf,
+    /// DIR bit of the frames sent, set by masters.
+    direction: u8,
+    local: u16,
+    remote: u16,
+    /// Sequence number of the next transport segment.
+    sequence: u8,
+    reassembly: Reassembly,
+}
+
+impl Link {
+    /// Link between `local` and `remote` addresses, `master` if this is the
+    /// master side.
+    pub fn new(stream: TcpStream, master: bool, local: u16, remote: u16) -> Self {
+        let _ = stream.set_nodelay(true);
+        let (reader, writer) = stream.into_split();
+        // Reading a frame isn't cancel safe, so it's done apart from the timers
+        let (frames, received) = mpsc::channel(16);
+        let reading = tokio::spawn(read_frames(reader, frames));
+        Self {
+            frames: received,
+            reading,
+            writer,
+            direction: if master { DIR } else { 0 },
+            local,
+            remote,
+            sequence: 0,
+            reassembly: Reassembly::default(),
+        }
+    }
+
+    /// Next frame from the other side, `None` once the connection is closed.
+    /// Cancel safe, the frame is handled by [`Link::accept`].
+    pub async fn frame(&mut self) -> Option<io::Result<LinkFrame>> {
+        self.frames.recv().await
+    }
+
+    /// Answers a link layer request or takes a segment, returns the fragment
+    /// completed by it. Frames of other stations are ignored.
+    pub async fn accept(&mut self, frame: LinkFrame) -> io::Result<Option<Vec<u8>>> {
+        if frame.destination != self.local || frame.source != self.remote {
+            tracing::debug!(
+                "DNP3 frame from {} to {} ignored",
+                frame.source,
+                frame.destination
+            );
+            return Ok(None);
+        }
+        if frame.control & PRM == 0 {
+            // Answers to requests this side never sends
+            return Ok(None);
+        }
+        match frame.function() {
+            REQUEST_LINK_STATUS => self.secondary(LINK_STATUS).await?,
+            RESET_LINK_STATES | TEST_LINK_STATES => self.secondary(ACK).await?,
+            CONFIRMED_USER_DATA => {
+                self.secondary(ACK).await?;
+                return Ok(self.reassembly.push(&frame.data));
+            },
+            UNCONFIRMED_USER_DATA => return Ok(self.reassembly.push(&frame.data)),
+            _ => self.secondary(NOT_SUPPORTED).await?,
+        }
+        Ok(None)
+    }
+
+    /// Sends a fragment in as many segments as it takes.
+    pub async fn send(&mut self, fragment: &[u8]) -> io::Result<()> {
+        let mut buf = Vec::new();
+        // Fragments have a header, so there's at least one segment
+        let count = fragment.len().div_ceil(MAX_SEGMENT);
+        for (index, data) in fragment.chunks(MAX_SEGMENT).enumerate() {
+            let mut header = self.sequence;
+            if index == 0 {
+                header |= FIR;
+            }
+            if index + 1 == count {
+                header |= FIN;
+            }
+            self.sequence = (self.sequence + 1) & 0x3f;
+            let mut segment = Vec::with_capacity(data.len() + 1);
+            segment.push(header);
+            segment.extend_from_slice(data);
+            self.frame_to_remote(PRM | UNCONFIRMED_USER_DATA, segment)
+                .encode(&mut buf);
+        }
+        self.writer.write_all(&buf).await
+    }
+
+    async fn secondary(&mut self, function: u8) -> io::Result<()> {
+        let mut buf = Vec::new();
+        self.frame_to_remote(function, Vec::new()).encode(&mut buf);
+        self.writer.write_all(&buf).await
+    }
+
+    fn frame_to_remote(&self, control: u8, data: Vec<u8>) -> LinkFrame {
+        LinkFrame {
+            control: self.direction | control,
+            destination: self.remote,
+            source: self.local,
+            data,
+        }
+    }
+}
+
+impl Drop for Link {
+    fn drop(&mut self) {
+        self.reading.abort();
+    }
+}
+
+async fn read_frames(mut reader: OwnedReadHalf, frames: mpsc::Sender<io::Result<LinkFrame>>) {
+    loop {
+        let frame = LinkFrame::read(&mut reader).await;
+        let failed = frame.is_err();
+        if frames.send(frame).await.is_err() || failed {
+            break;
+        }
+    }
+}
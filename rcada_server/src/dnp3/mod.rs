@@ -0,0 +1,940 @@
+//! DNP3 outstation serving mapped tags to a master over TCP, next to the
+//! IEC 104 outstation. Changes of the inputs are kept as events until the
+//! master confirms them.
+
+pub mod app;
+pub mod link;
+mod session;
+
+use std::{
+    collections::{HashMap, HashSet, VecDeque},
+    future::Future,
+    io,
+    sync::{
+        Arc, Mutex,
+        atomic::{AtomicBool, Ordering},
+    },
+};
+
+use chrono::{DateTime, TimeDelta, Utc};
+use ractor::ActorRef;
+use rcada_core::{
+    tag::{Quality, Tag, TagName, TagValue},
+    value::{DataType, Value},
+};
+use serde::{Deserialize, Serialize};
+use tokio::{
+    net::TcpListener,
+    sync::{broadcast, watch},
+    task::JoinSet,
+};
+
+use crate::{
+    actor::{self, Mailbox},
+    audit::Origin,
+};
+
+use self::app::{
+    FLAG_COMM_LOST, FLAG_LOCAL_FORCED, FLAG_ONLINE, FLAG_OVER_RANGE, FLAG_REMOTE_FORCED,
+    FLAG_RESTART, IIN_CLASS_1_EVENTS, IIN_DEVICE_RESTART, IIN_EVENT_BUFFER_OVERFLOW, Object,
+    ObjectHeader, Range,
+};
+
+/// Link addresses from here on are reserved for broadcasts.
+const MAX_ADDRESS: u16 = 0xffef;
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Dnp3Config {
+    /// Serve the points below over DNP3.
+    #[serde(default)]
+    pub enabled: bool,
+    #[serde(default = "default_bind")]
+    pub bind: String,
+    /// Link address of the outstation.
+    #[serde(default = "default_address")]
+    pub address: u16,
+    /// Link address of the master, frames from other addresses are ignored.
+    #[serde(default = "default_master_address")]
+    pub master_address: u16,
+    /// Events kept until the master confirms them, the oldest are dropped
+    /// past it.
+    #[serde(default = "default_event_buffer")]
+    pub event_buffer: usize,
+    /// Report events in unsolicited responses once the master enables them.
+    #[serde(default = "default_unsolicited")]
+    pub unsolicited: bool,
+    /// Time the master has to confirm a response before it's sent again or
+    /// given up.
+    #[serde(default = "default_confirm_timeout_ms")]
+    pub confirm_timeout_ms: u64,
+    /// Time between a select and the operate that has to follow it.
+    #[serde(default = "default_select_timeout_ms")]
+    pub select_timeout_ms: u64,
+    #[serde(default)]
+    pub points: Vec<Dnp3Point>,
+}
+
+/// A tag served as a point of the outstation.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Dnp3Point {
+    pub tag: String,
+    #[serde(rename = "type")]
+    pub kind: PointKind,
+    pub index: u16,
+    /// Event class of the changes of inputs, 0 for none.
+    #[serde(default = "default_class")]
+    pub class: u8,
+}
+
+/// Type of a point, with the objects it's read and written with.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum PointKind {
+    BinaryInput,
+    /// Written with control relay output blocks.
+    BinaryOutput,
+    AnalogInput,
+    /// Written with analog output blocks.
+    AnalogOutput,
+}
+
+fn default_bind() -> String {
+    "127.0.0.1:20000".to_string()
+}
+
+fn default_address() -> u16 {
+    1024
+}
+
+fn default_master_address() -> u16 {
+    1
+}
+
+fn default_event_buffer() -> usize {
+    1000
+}
+
+fn default_unsolicited() -> bool {
+    true
+}
+
+fn default_confirm_timeout_ms() -> u64 {
+    5000
+}
+
+fn default_select_timeout_ms() -> u64 {
+    5000
+}
+
+fn default_class() -> u8 {
+    1
+}
+
+impl Default for Dnp3Config {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            bind: default_bind(),
+            address: default_address(),
+            master_address: default_master_address(),
+            event_buffer: default_event_buffer(),
+            unsolicited: default_unsolicited(),
+            confirm_timeout_ms: default_confirm_timeout_ms(),
+            select_timeout_ms: default_select_timeout_ms(),
+            points: Vec::new(),
+        }
+    }
+}
+
+impl Dnp3Config {
+    /// Checks the addresses, buffers and points, the bind address is checked
+    /// with the other servers.
+    pub fn validate(&self) -> Vec<String> {
+        let mut errors = Vec::new();
+        for (field, address) in [
+            ("address", self.address),
+            ("master_address", self.master_address),
+        ] {
+            if address > MAX_ADDRESS {
+                errors.push(format!("dnp3.{field}: must be at most {MAX_ADDRESS}"));
+            }
+        }
+        if self.address == self.master_address {
+            errors.push("dnp3.master_address: must differ from address".to_string());
+        }
+        if self.event_buffer == 0 {
+            errors.push("dnp3.event_buffer: must be positive".to_string());
+        }
+        if self.confirm_timeout_ms == 0 || self.select_timeout_ms == 0 {
+            errors.push("dnp3: timeouts must be positive".to_string());
+        }
+        let mut indices = HashSet::new();
+        for point in &self.points {
+            if point.tag.is_empty() {
+                errors.push("dnp3.points: tag name is empty".to_string());
+            }
+            if !indices.insert((point.kind, point.index)) {
+                errors.push(format!(
+                    "dnp3.points: {:?} {} is used more than once",
+                    point.kind, point.index
+                ));
+            }
+            if point.class > 3 {
+                errors.push(format!(
+                    "dnp3.points: {}: class must be between 0 and 3",
+                    point.tag
+                ));
+            }
+        }
+        errors
+    }
+}
+
+impl PointKind {
+    /// Group and variation of the current values: binaries with flags and
+    /// analogs as floats with flags.
+    pub fn static_object(self) -> (u8, u8) {
+        match self {
+            PointKind::BinaryInput => (1, 2),
+            PointKind::BinaryOutput => (10, 2),
+            PointKind::AnalogInput => (30, 5),
+            PointKind::AnalogOutput => (40, 3),
+        }
+    }
+
+    /// Group and variation of the events, with absolute time. Only inputs
+    /// have events.
+    pub fn event_object(self) -> Option<(u8, u8)> {
+        match self {
+            PointKind::BinaryInput => Some((2, 2)),
+            PointKind::AnalogInput => Some((32, 7)),
+            PointKind::BinaryOutput | PointKind::AnalogOutput => None,
+        }
+    }
+
+    /// Type of the points of a group of values or events.
+    pub fn of_group(group: u8) -> Option<Self> {
+        match group {
+            1 | 2 => Some(PointKind::BinaryInput),
+            10 | 11 => Some(PointKind::BinaryOutput),
+            30 | 32 => Some(PointKind::AnalogInput),
+            40 | 42 => Some(PointKind::AnalogOutput),
+            _ => None,
+        }
+    }
+
+    pub fn is_binary(self) -> bool {
+        matches!(self, PointKind::BinaryInput | PointKind::BinaryOutput)
+    }
+
+    /// Object of the point with `value`, offline when the tag has no value
+    /// or one the point can't represent.
+    fn object(self, value: Option<&TagValue>, time: Option<DateTime<Utc>>) -> Object {
+        let mut flags = value.map_or(0, |value| flags(value.quality));
+        let number = match value.map(|value| &value.value) {
+            Some(Value::Integer(i)) => *i as f64,
+            Some(Value::Float(f)) => f64::from(*f),
+            Some(Value::Boolean(b)) => f64::from(u8::from(*b)),
+            Some(Value::String(_)) | None => {
+                flags = 0;
+                0.0
+            },
+        };
+        if self.is_binary() {
+            Object::Binary {
+                value: number != 0.0,
+                flags,
+                time,
+            }
+        } else {
+            Object::Analog {
+                value: number,
+                flags,
+                time,
+            }
+        }
+    }
+}
+
+/// Flags of points with a tag of `quality`.
+pub fn flags(quality: Quality) -> u8 {
+    match quality {
+        Quality::Good => FLAG_ONLINE,
+        Quality::Uncertain => FLAG_ONLINE | FLAG_COMM_LOST,
+        Quality::Bad => 0,
+    }
+}
+
+/// Quality of tags written from a point with `flags`.
+pub fn quality(flags: u8) -> Quality {
+    let doubtful =
+        FLAG_RESTART | FLAG_COMM_LOST | FLAG_REMOTE_FORCED | FLAG_LOCAL_FORCED | FLAG_OVER_RANGE;
+    if flags & FLAG_ONLINE == 0 {
+        Quality::Bad
+    } else if flags & doubtful != 0 {
+        Quality::Uncertain
+    } else {
+        Quality::Good
+    }
+}
+
+/// Change of an input kept until the master confirms it.
+#[derive(Debug, Clone)]
+struct Event {
+    id: u64,
+    class: u8,
+    kind: PointKind,
+    index: u16,
+    object: Object,
+}
+
+#[derive(Debug, Default)]
+struct EventBuffer {
+    events: VecDeque<Event>,
+    next_id: u64,
+    /// Events were dropped since the master last confirmed some.
+    overflow: bool,
+}
+
+/// What the connections share: the points, the events, the clock set by
+/// the master and whether it saw the restart.
+struct Outstation {
+    config: Dnp3Config,
+    tag_repo: ActorRef<actor::tag::Message>,
+    /// Indices of the points of every tag.
+    by_tag: HashMap<TagName, Vec<usize>>,
+    by_point: HashMap<(PointKind, u16), usize>,
+    events: Mutex<EventBuffer>,
+    /// Id of the last recorded event, for waiting on new ones.
+    recorded: watch::Sender<u64>,
+    /// The master didn't clear the restart indication yet.
+    restarted: AtomicBool,
+    /// Difference between the time written by the master and the system
+    /// clock, added to the times of the events.
+    clock_offset: Mutex<TimeDelta>,
+}
+
+impl Outstation {
+    fn new(config: Dnp3Config, tag_repo: ActorRef<actor::tag::Message>) -> Self {
+        let mut by_tag: HashMap<TagName, Vec<usize>> = HashMap::new();
+        let mut by_point = HashMap::new();
+        for (index, point) in config.points.iter().enumerate() {
+            by_tag
+                .entry(TagName::from(point.tag.as_str()))
+                .or_default()
+                .push(index);
+            by_point.insert((point.kind, point.index), index);
+        }
+        Self {
+            config,
+            tag_repo,
+            by_tag,
+            by_point,
+            events: Mutex::new(EventBuffer::default()),
+            recorded: watch::Sender::new(0),
+            restarted: AtomicBool::new(true),
+            clock_offset: Mutex::new(TimeDelta::zero()),
+        }
+    }
+
+    fn point(&self, kind: PointKind, index: u16) -> Option<&Dnp3Point> {
+        self.by_point
+            .get(&(kind, index))
+            .map(|&position| &self.config.points[position])
+    }
+
+    /// `time` on the clock of the master.
+    fn time(&self, time: DateTime<Utc>) -> DateTime<Utc> {
+        time + *self.clock_offset.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    fn set_clock(&self, time: DateTime<Utc>) {
+        *self.clock_offset.lock().unwrap_or_else(|e| e.into_inner()) = time - Utc::now();
+    }
+
+    async fn subscribe(&self) -> io::Result<broadcast::Receiver<Tag>> {
+        let (command, mut reply) = actor::tag::Message::subscribe();
+        self.tag_repo.enqueue(command).map_err(io::Error::other)?;
+        reply
+            .recv()
+            .await
+            .ok_or_else(|| io::Error::other("tag repository didn't answer the subscription"))
+    }
+
+    /// Current values of the points of `kinds` in `range`, one header for
+    /// each kind with points.
+    async fn statics(&self, kinds: &[PointKind], range: Range) -> io::Result<Vec<ObjectHeader>> {
+        let (command, mut reply) = actor::tag::Message::get_all_tags();
+        self.tag_repo.enqueue(command).map_err(io::Error::other)?;
+        let tags: HashMap<TagName, TagValue> = reply
+            .recv()
+            .await
+            .ok_or_else(|| io::Error::other("tag repository didn't answer"))?
+            .into_iter()
+            .map(|tag| (tag.name, tag.value))
+            .collect();
+        let mut headers = Vec::new();
+        for &kind in kinds {
+            let mut points: Vec<&Dnp3Point> = self
+                .config
+                .points
+                .iter()
+                .filter(|point| point.kind == kind)
+                .filter(|point| match range {
+                    Range::StartStop(start, stop) => (start..=stop).contains(&point.index),
+                    Range::All | Range::Count(_) => true,
+                })
+                .collect();
+            if points.is_empty() {
+                continue;
+            }
+            points.sort_by_key(|point| point.index);
+            let objects = points
+                .iter()
+                .map(|point| (point.index, kind.object(tags.get(point.tag.as_str()), None)))
+                .collect();
+            let (group, variation) = kind.static_object();
+            headers.push(ObjectHeader::range(group, variation, objects));
+        }
+        Ok(headers)
+    }
+
+    /// Records the change of `tag` as events of its input points whose
+    /// value or flags differ from `last`.
+    fn record(&self, tag: &Tag, last: &mut HashMap<usize, Object>) {
+        let Some(points) = self.by_tag.get(&tag.name) else {
+            return;
+        };
+        let time = self.time(tag.value.timestamp.unwrap_or_else(Utc::now));
+        let mut buffer = self.events.lock().unwrap_or_else(|e| e.into_inner());
+        for &position in points {
+            let point = &self.config.points[position];
+            if point.class == 0 || point.kind.event_object().is_none() {
+                continue;
+            }
+            let object = point.kind.object(Some(&tag.value), Some(time));
+            let unchanged = last
+                .get(&position)
+                .is_some_and(|previous| same_state(previous, &object));
+            if unchanged {
+                continue;
+            }
+            last.insert(position, object.clone());
+            if buffer.events.len() >= self.config.event_buffer {
+                buffer.events.pop_front();
+                buffer.overflow = true;
+            }
+            buffer.next_id += 1;
+            let id = buffer.next_id;
+            buffer.events.push_back(Event {
+                id,
+                class: point.class,
+                kind: point.kind,
+                index: point.index,
+                object,
+            });
+            self.recorded.send_replace(id);
+        }
+    }
+
+    /// Events of the `classes` (bit 0 for class 1), oldest first, that
+    /// aren't among `excluded`.
+    fn events(&self, classes: u8, excluded: &[u64]) -> Vec<Event> {
+        let buffer = self.events.lock().unwrap_or_else(|e| e.into_inner());
+        buffer
+            .events
+            .iter()
+            .filter(|event| classes & class_bit(event.class) != 0)
+            .filter(|event| !excluded.contains(&event.id))
+            .cloned()
+            .collect()
+    }
+
+    /// Drops the events the master confirmed.
+    fn remove_events(&self, ids: &[u64]) {
+        if ids.is_empty() {
+            return;
+        }
+        let mut buffer = self.events.lock().unwrap_or_else(|e| e.into_inner());
+        buffer.events.retain(|event| !ids.contains(&event.id));
+        buffer.overflow = false;
+    }
+
+    /// Internal indications of the outstation, with the classes of the
+    /// events that aren't among `excluded`.
+    fn iin(&self, excluded: &[u64]) -> u16 {
+        let buffer = self.events.lock().unwrap_or_else(|e| e.into_inner());
+        let mut iin = buffer
+            .events
+            .iter()
+            .filter(|event| !excluded.contains(&event.id))
+            .fold(0, |iin, event| {
+                iin | (IIN_CLASS_1_EVENTS << (event.class - 1))
+            });
+        if buffer.overflow {
+            iin |= IIN_EVENT_BUFFER_OVERFLOW;
+        }
+        if self.restarted.load(Ordering::Relaxed) {
+            iin |= IIN_DEVICE_RESTART;
+        }
+        iin
+    }
+
+    /// Fails unless `point` has a tag that can be written.
+    async fn writable(&self, point: &Dnp3Point) -> Result<DataType, String> {
+        let (command, mut reply) = actor::tag::Message::get_tag(point.tag.as_str());
+        self.tag_repo.enqueue(command).map_err(|e| e.to_string())?;
+        let tag = reply
+            .recv()
+            .await
+            .and_then(Result::ok)
+            .ok_or_else(|| format!("tag {} not found", point.tag))?;
+        if tag.meta.read_only {
+            return Err(format!("tag {} is read-only", point.tag));
+        }
+        Ok(tag.meta.data_type)
+    }
+
+    /// Writes the value of a command of `origin` to the tag of `point`.
+    async fn write(&self, point: &Dnp3Point, value: f64, origin: &Origin) -> Result<(), String> {
+        let value = match self.writable(point).await? {
+            DataType::Integer if value.is_finite() => Value::Integer(value.round() as i64),
+            DataType::Float => Value::Float(value as f32),
+            DataType::Boolean => Value::Boolean(value != 0.0),
+            data_type => {
+                return Err(format!(
+                    "tag {} of type {data_type:?} can't take {value}",
+                    point.tag
+                ));
+            },
+        };
+        let value = TagValue {
+            value,
+            timestamp: Some(Utc::now()),
+            quality: Quality::Good,
+        };
+        let (command, mut reply) = actor::tag::Message::update_tag_value(point.tag.as_str(), value);
+        self.tag_repo
+            .enqueue(command.with_origin(origin.clone()))
+            .map_err(|e| e.to_string())?;
+        match reply.recv().await {
+            Some(Err(e)) => Err(format!("tag {}: {e:?}", point.tag)),
+            _ => Ok(()),
+        }
+    }
+}
+
+/// Bit of an event class in class masks, none for class 0.
+fn class_bit(class: u8) -> u8 {
+    match class {
+        1..=3 => 1 << (class - 1),
+        _ => 0,
+    }
+}
+
+/// Whether two objects of a point have the same value and flags.
+fn same_state(previous: &Object, object: &Object) -> bool {
+    match (previous, object) {
+        (
+            Object::Binary {
+                value,
+                flags,
+                ..
+            },
+            Object::Binary {
+                value: new_value,
+                flags: new_flags,
+                ..
+            },
+        ) => value == new_value && flags == new_flags,
+        (
+            Object::Analog {
+                value,
+                flags,
+                ..
+            },
+            Object::Analog {
+                value: new_value,
+                flags: new_flags,
+                ..
+            },
+        ) => value == new_value && flags == new_flags,
+        _ => false,
+    }
+}
+
+/// Turns the changes of the tags into events until the repository stops.
+async fn record_events(outstation: Arc<Outstation>, mut updates: broadcast::Receiver<Tag>) {
+    let mut last = HashMap::new();
+    loop {
+        match updates.recv().await {
+            Ok(tag) => outstation.record(&tag, &mut last),
+            Err(broadcast::error::RecvError::Lagged(missed)) => {
+                tracing::warn!("DNP3 missed {} tag updates, events are lost", missed);
+            },
+            Err(broadcast::error::RecvError::Closed) => break,
+        }
+    }
+}
+
+/// Serves the configured points to DNP3 masters connecting to `listener`
+/// until `shutdown` completes. The connections share the event buffer.
+pub async fn serve(
+    listener: TcpListener,
+    config: &Dnp3Config,
+    tag_repo: ActorRef<actor::tag::Message>,
+    shutdown: impl Future<Output = ()> + Send + 'static,
+) -> io::Result<()> {
+    let outstation = Arc::new(Outstation::new(config.clone(), tag_repo));
+    let recorder = tokio::spawn(record_events(
+        outstation.clone(),
+        outstation.subscribe().await?,
+    ));
+    tracing::info!("Listening for DNP3 on {}", listener.local_addr()?);
+    tokio::pin!(shutdown);
+    let mut connections = JoinSet::new();
+    loop {
+        tokio::select! {
+            _ = &mut shutdown => break,
+            accepted = listener.accept() => {
+                let (stream, peer) = match accepted {
+                    Ok(accepted) => accepted,
+                    Err(e) => {
+                        tracing::warn!("DNP3 connection failed: {}", e);
+                        continue;
+                    },
+                };
+                let outstation = outstation.clone();
+                connections.spawn(async move {
+                    tracing::info!("DNP3 master {} connected", peer);
+                    match session::run(stream, outstation).await {
+                        Ok(()) => tracing::info!("DNP3 master {} disconnected", peer),
+                        Err(e) => tracing::warn!("DNP3 master {} disconnected: {}", peer, e),
+                    }
+                });
+            },
+            Some(_) = connections.join_next(), if !connections.is_empty() => {},
+        }
+    }
+    connections.shutdown().await;
+    recorder.abort();
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use ractor::Actor;
+    use rcada_core::{tag::TagMeta, unit::Unit};
+    use tokio::{net::TcpStream, time::timeout};
+
+    use super::{app::*, link::Link, *};
+    use crate::{
+        actor::tag::TagRepositoryActor, audit::AuditLog, repository::tag::inmemory::TagStorage,
+    };
+
+    /// Test master making requests one at a time.
+    struct Master {
+        link: Link,
+        sequence: u8,
+    }
+
+    impl Master {
+        async fn request(&mut self, function: u8, headers: Vec<ObjectHeader>) -> Fragment {
+            let sequence = self.sequence;
+            self.sequence = (self.sequence + 1) & 0x0f;
+            let request = Fragment::request(function, sequence, headers);
+            self.link.send(&request.encode()).await.unwrap();
+            let response = self.fragment().await;
+            assert_eq!(
+                (response.function, response.sequence()),
+                (RESPONSE, sequence)
+            );
+            response
+        }
+
+        async fn fragment(&mut self) -> Fragment {
+            loop {
+                let frame = timeout(Duration::from_secs(5), self.link.frame())
+                    .await
+                    .expect("no fragment from the outstation")
+                    .unwrap()
+                    .unwrap();
+                if let Some(bytes) = self.link.accept(frame).await.unwrap() {
+                    return Fragment::parse(&bytes).unwrap();
+                }
+            }
+        }
+
+        async fn confirm(&mut self, response: &Fragment) {
+            let confirm = Fragment {
+                control: FIR | FIN | (response.control & (UNS | 0x0f)),
+                function: CONFIRM,
+                iin: 0,
+                headers: Vec::new(),
+            };
+            self.link.send(&confirm.encode()).await.unwrap();
+        }
+    }
+
+    async fn create(
+        tag_repo: &ActorRef<actor::tag::Message>,
+        name: &str,
+        value: Value,
+        read_only: bool,
+    ) {
+        let meta = TagMeta {
+            read_only,
+            ..TagMeta::new(Unit::None, value.get_data_type())
+        };
+        let (command, mut reply) = actor::tag::Message::create_tag(name, meta);
+        tag_repo.enqueue(command).unwrap();
+        reply.recv().await.unwrap();
+        set(tag_repo, name, value).await;
+    }
+
+    async fn set(tag_repo: &ActorRef<actor::tag::Message>, name: &str, value: Value) {
+        let value = TagValue {
+            value,
+            timestamp: Some(Utc::now()),
+            quality: Quality::Good,
+        };
+        let (command, mut reply) = actor::tag::Message::update_tag_value(name, value);
+        tag_repo.enqueue(command).unwrap();
+        reply.recv().await.unwrap().unwrap();
+    }
+
+    async fn value(tag_repo: &ActorRef<actor::tag::Message>, name: &str) -> Value {
+        let (command, mut reply) = actor::tag::Message::get_tag(name);
+        tag_repo.enqueue(command).unwrap();
+        reply.recv().await.unwrap().unwrap().value.value
+    }
+
+    fn point(tag: &str, kind: PointKind, index: u16, class: u8) -> Dnp3Point {
+        Dnp3Point {
+            tag: tag.to_string(),
+            kind,
+            index,
+            class,
+        }
+    }
+
+    fn analog_output(value: f64) -> Object {
+        Object::AnalogOutput {
+            value,
+            status: STATUS_SUCCESS,
+        }
+    }
+
+    #[tokio::test]
+    async fn serves_points_to_a_master() {
+        let (tag_repo, _) = Actor::spawn(
+            None,
+            TagRepositoryActor::default(),
+            (TagStorage::default(), AuditLog::disabled()),
+        )
+        .await
+        .unwrap();
+        create(&tag_repo, "plant/pump", Value::Boolean(true), true).await;
+        create(&tag_repo, "plant/flow", Value::Float(12.5), true).await;
+        create(&tag_repo, "plant/alarm", Value::Boolean(false), true).await;
+        create(&tag_repo, "plant/speed", Value::Integer(100), false).await;
+
+        let config = Dnp3Config {
+            enabled: true,
+            event_buffer: 2,
+            points: vec![
+                point("plant/pump", PointKind::BinaryInput, 0, 1),
+                point("plant/flow", PointKind::AnalogInput, 3, 2),
+                point("plant/alarm", PointKind::BinaryOutput, 1, 0),
+                point("plant/speed", PointKind::AnalogOutput, 0, 0),
+            ],
+            ..Dnp3Config::default()
+        };
+        assert!(config.validate().is_empty(), "{:?}", config.validate());
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap();
+        let (stop, stopped) = tokio::sync::oneshot::channel::<()>();
+        let server = {
+            let tag_repo = tag_repo.clone();
+            tokio::spawn(async move {
+                let shutdown = async {
+                    let _ = stopped.await;
+                };
+                serve(listener, &config, tag_repo, shutdown).await
+            })
+        };
+
+        let stream = TcpStream::connect(address).await.unwrap();
+        let mut master = Master {
+            link: Link::new(stream, true, 1, 1024),
+            sequence: 0,
+        };
+        // Null unsolicited response reporting the restart
+        let null = master.fragment().await;
+        assert_eq!(null.function, UNSOLICITED_RESPONSE);
+        assert_eq!(null.control & (UNS | CON), UNS | CON);
+        assert!(null.headers.is_empty());
+        assert_ne!(null.iin & IIN_DEVICE_RESTART, 0);
+        master.confirm(&null).await;
+
+        // Class 0, grouped by type
+        let response = master.request(READ, vec![ObjectHeader::all(60, 1)]).await;
+        let values: Vec<_> = response
+            .headers
+            .iter()
+            .map(|header| (header.group, header.variation, header.objects.clone()))
+            .collect();
+        let binary = |value| Object::Binary {
+            value,
+            flags: FLAG_ONLINE,
+            time: None,
+        };
+        let analog = |value| Object::Analog {
+            value,
+            flags: FLAG_ONLINE,
+            time: None,
+        };
+        assert_eq!(
+            values,
+            vec![
+                (1, 2, vec![(0, binary(true))]),
+                (10, 2, vec![(1, binary(false))]),
+                (30, 5, vec![(3, analog(12.5))]),
+                (40, 3, vec![(0, analog(100.0))]),
+            ]
+        );
+        let unknown = master.request(READ, vec![ObjectHeader::all(20, 0)]).await;
+        assert_ne!(unknown.iin & IIN_OBJECT_UNKNOWN, 0);
+        let restart = ObjectHeader::range(
+            80,
+            1,
+            vec![(DEVICE_RESTART_INDEX, Object::Indication(false))],
+        );
+        let response = master.request(WRITE, vec![restart]).await;
+        assert_eq!(response.iin & IIN_DEVICE_RESTART, 0);
+
+        // The buffer keeps the two latest events
+        for flow in [13.0, 14.0, 15.0] {
+            set(&tag_repo, "plant/flow", Value::Float(flow)).await;
+        }
+        let mut iin = 0;
+        for _ in 0..100 {
+            iin = master
+                .request(READ, vec![ObjectHeader::all(30, 0)])
+                .await
+                .iin;
+            if iin & IIN_EVENT_BUFFER_OVERFLOW != 0 {
+                break;
+            }
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+        assert_ne!(iin & IIN_CLASS_2_EVENTS, 0);
+        assert_ne!(iin & IIN_EVENT_BUFFER_OVERFLOW, 0);
+        let events = master.request(READ, vec![ObjectHeader::all(60, 3)]).await;
+        assert_ne!(events.control & CON, 0);
+        assert_eq!(
+            (events.headers[0].group, events.headers[0].variation),
+            (32, 7)
+        );
+        let flows: Vec<(u16, f64)> = events.headers[0]
+            .objects
+            .iter()
+            .map(|(index, object)| match object {
+                Object::Analog {
+                    value,
+                    time,
+                    ..
+                } => {
+                    assert!(time.is_some());
+                    (*index, *value)
+                },
+                object => panic!("{object:?}"),
+            })
+            .collect();
+        assert_eq!(flows, vec![(3, 14.0), (3, 15.0)]);
+        master.confirm(&events).await;
+        let response = master.request(READ, vec![ObjectHeader::all(60, 3)]).await;
+        assert!(response.headers.is_empty());
+        assert_eq!(
+            response.iin & (IIN_CLASS_2_EVENTS | IIN_EVENT_BUFFER_OVERFLOW),
+            0
+        );
+
+        // Controls: direct, refused for read-only tags, and select before
+        // operate
+        let setpoint = ObjectHeader::indexed(41, 3, vec![(0, analog_output(250.25))]);
+        let response = master.request(DIRECT_OPERATE, vec![setpoint]).await;
+        assert_eq!(
+            response.headers[0].objects,
+            vec![(0, analog_output(250.25))]
+        );
+        assert_eq!(value(&tag_repo, "plant/speed").await, Value::Integer(250));
+        let trip = Object::Crob {
+            code: CONTROL_LATCH_ON,
+            count: 1,
+            on_ms: 0,
+            off_ms: 0,
+            status: STATUS_SUCCESS,
+        };
+        let crob = ObjectHeader::indexed(12, 1, vec![(1, trip)]);
+        let response = master.request(DIRECT_OPERATE, vec![crob]).await;
+        assert!(matches!(
+            response.headers[0].objects[0].1,
+            Object::Crob {
+                status: STATUS_NOT_SUPPORTED,
+                ..
+            }
+        ));
+        assert_eq!(value(&tag_repo, "plant/alarm").await, Value::Boolean(false));
+        let setpoint = ObjectHeader::indexed(41, 1, vec![(0, analog_output(300.0))]);
+        let response = master.request(OPERATE, vec![setpoint.clone()]).await;
+        assert_eq!(
+            response.headers[0].objects[0].1,
+            Object::AnalogOutput {
+                value: 300.0,
+                status: STATUS_NO_SELECT,
+            }
+        );
+        master.request(SELECT, vec![setpoint.clone()]).await;
+        assert_eq!(value(&tag_repo, "plant/speed").await, Value::Integer(250));
+        let response = master.request(OPERATE, vec![setpoint]).await;
+        assert_eq!(response.headers[0].objects, vec![(0, analog_output(300.0))]);
+        assert_eq!(value(&tag_repo, "plant/speed").await, Value::Integer(300));
+
+        // Unsolicited events once enabled
+        master
+            .request(ENABLE_UNSOLICITED, vec![ObjectHeader::all(60, 2)])
+            .await;
+        set(&tag_repo, "plant/pump", Value::Boolean(false)).await;
+        let unsolicited = master.fragment().await;
+        assert_eq!(unsolicited.function, UNSOLICITED_RESPONSE);
+        assert_eq!(
+            (
+                unsolicited.headers[0].group,
+                unsolicited.headers[0].variation
+            ),
+            (2, 2)
+        );
+        assert!(matches!(
+            unsolicited.headers[0].objects[..],
+            [(
+                0,
+                Object::Binary {
+                    value: false,
+                    flags: FLAG_ONLINE,
+                    time: Some(_),
+                }
+            )]
+        ));
+        master.confirm(&unsolicited).await;
+        let response = master.request(READ, vec![ObjectHeader::all(60, 2)]).await;
+        assert!(response.headers.is_empty());
+
+        let _ = stop.send(());
+        server.await.unwrap().unwrap();
+    }
+}
@@ -0,0 +1,579 @@
+//! One connection to a master: reads of values and events, confirmations,
+//! unsolicited responses and controls.
+
+use std::{io, sync::Arc, sync::atomic::Ordering, time::Duration};
+
+use tokio::{net::TcpStream, sync::watch, time::Instant};
+
+use crate::audit::Origin;
+
+use super::{
+    Event, Outstation, PointKind,
+    app::{
+        CON, CONFIRM, CONTROL_CLOSE, CONTROL_LATCH_OFF, CONTROL_LATCH_ON, CONTROL_PULSE_OFF,
+        CONTROL_PULSE_ON, CONTROL_TRIP, DEVICE_RESTART_INDEX, DIRECT_OPERATE, DIRECT_OPERATE_NR,
+        DISABLE_UNSOLICITED, ENABLE_UNSOLICITED, FIN, FIR, Fragment, IIN_NO_FUNCTION_CODE_SUPPORT,
+        IIN_OBJECT_UNKNOWN, IIN_PARAMETER_ERROR, OPERATE, Object, ObjectHeader, READ, RESPONSE,
+        Range, SELECT, STATUS_HARDWARE_ERROR, STATUS_NO_SELECT, STATUS_NOT_SUPPORTED,
+        STATUS_SUCCESS, UNS, UNSOLICITED_RESPONSE, WRITE,
+    },
+    class_bit,
+    link::Link,
+};
+
+/// Serves the master on `stream` until it disconnects or the link fails.
+pub(super) async fn run(stream: TcpStream, outstation: Arc<Outstation>) -> io::Result<()> {
+    let origin = Origin::peer("dnp3", stream.peer_addr().ok());
+    let link = Link::new(
+        stream,
+        false,
+        outstation.config.address,
+        outstation.config.master_address,
+    );
+    let mut session = Session {
+        recorded: outstation.recorded.subscribe(),
+        confirm_timeout: Duration::from_millis(outstation.config.confirm_timeout_ms),
+        outstation,
+        link,
+        origin,
+        unsolicited_classes: 0,
+        unsolicited_sequence: 0,
+        unconfirmed: None,
+        selection: None,
+        deferred: None,
+    };
+    session.serve().await
+}
+
+/// What woke the session up.
+enum Input {
+    Fragment(Vec<u8>),
+    Events,
+    Timeout,
+}
+
+/// Unsolicited response waiting for its confirmation.
+struct Unconfirmed {
+    sequence: u8,
+    fragment: Vec<u8>,
+    events: Vec<u64>,
+    sent: Instant,
+}
+
+/// Controls selected for the operate that follows.
+struct Selection {
+    sequence: u8,
+    headers: Vec<ObjectHeader>,
+    selected: Instant,
+}
+
+struct Session {
+    outstation: Arc<Outstation>,
+    link: Link,
+    /// Origin of the controls of the master in the audit log.
+    origin: Origin,
+    recorded: watch::Receiver<u64>,
+    confirm_timeout: Duration,
+    /// Classes enabled for unsolicited responses, bit 0 for class 1.
+    unsolicited_classes: u8,
+    unsolicited_sequence: u8,
+    unconfirmed: Option<Unconfirmed>,
+    selection: Option<Selection>,
+    /// Request that arrived while a confirmation was awaited.
+    deferred: Option<Vec<u8>>,
+}
+
+impl Session {
+    async fn serve(&mut self) -> io::Result<()> {
+        if self.outstation.config.unsolicited {
+            // Tells the master about the restart before anything is enabled
+            self.send_unsolicited(Vec::new(), Vec::new()).await?;
+        }
+        loop {
+            if let Some(request) = self.deferred.take() {
+                self.request(request).await?;
+                continue;
+            }
+            let deadline = self
+                .unconfirmed
+                .as_ref()
+                .map(|unconfirmed| unconfirmed.sent + self.confirm_timeout);
+            match self.next(deadline).await? {
+                Some(Input::Fragment(request)) => self.request(request).await?,
+                Some(Input::Events) => {},
+                Some(Input::Timeout) => {
+                    if let Some(unconfirmed) = &mut self.unconfirmed {
+                        // Sent again with the same sequence until confirmed
+                        unconfirmed.sent = Instant::now();
+                        self.link.send(&unconfirmed.fragment).await?;
+                    }
+                },
+                None => return Ok(()),
+            }
+            self.report_events().await?;
+        }
+    }
+
+    /// Waits for a fragment, new events or `deadline`, `None` once the
+    /// master disconnected.
+    async fn next(&mut self, deadline: Option<Instant>) -> io::Result<Option<Input>> {
+        loop {
+            tokio::select! {
+                frame = self.link.frame() => {
+                    let Some(frame) = frame else {
+                        return Ok(None);
+                    };
+                    if let Some(fragment) = self.link.accept(frame?).await? {
+                        return Ok(Some(Input::Fragment(fragment)));
+                    }
+                },
+                changed = self.recorded.changed() => {
+                    return Ok(changed.ok().map(|()| Input::Events));
+                },
+                _ = tokio::time::sleep_until(deadline.unwrap_or_else(Instant::now)), if deadline.is_some() => {
+                    return Ok(Some(Input::Timeout));
+                },
+            }
+        }
+    }
+
+    async fn request(&mut self, bytes: Vec<u8>) -> io::Result<()> {
+        let request = match Fragment::parse(&bytes) {
+            Ok(request) => request,
+            Err(e) => {
+                tracing::debug!("DNP3 request rejected: {}", e);
+                if let Some((control, function)) = Fragment::peek(&bytes)
+                    && function != CONFIRM
+                {
+                    self.respond(control, IIN_PARAMETER_ERROR, Vec::new())
+                        .await?;
+                }
+                return Ok(());
+            },
+        };
+        if request.is_response() || request.control & FIR == 0 || request.control & FIN == 0 {
+            // Masters send single fragment requests
+            return Ok(());
+        }
+        match request.function {
+            CONFIRM => self.confirmed(&request),
+            READ => self.read(request).await?,
+            WRITE => self.write(request).await?,
+            SELECT | OPERATE | DIRECT_OPERATE | DIRECT_OPERATE_NR => self.operate(request).await?,
+            ENABLE_UNSOLICITED | DISABLE_UNSOLICITED => self.unsolicited(request).await?,
+            _ => {
+                self.respond(request.control, IIN_NO_FUNCTION_CODE_SUPPORT, Vec::new())
+                    .await?
+            },
+        }
+        Ok(())
+    }
+
+    /// Takes the confirmation of the unsolicited response, those of
+    /// solicited ones are awaited where they're sent.
+    fn confirmed(&mut self, confirm: &Fragment) {
+        if confirm.control & UNS == 0 {
+            return;
+        }
+        if let Some(unconfirmed) = self
+            .unconfirmed
+            .take_if(|unconfirmed| unconfirmed.sequence == confirm.sequence())
+        {
+            self.outstation.remove_events(&unconfirmed.events);
+            self.unsolicited_sequence = (self.unsolicited_sequence + 1) & 0x0f;
+        }
+    }
+
+    /// Single fragment response to a request with sequence in `control`.
+    async fn respond(
+        &mut self,
+        control: u8,
+        iin: u16,
+        headers: Vec<ObjectHeader>,
+    ) -> io::Result<()> {
+        let response = Fragment {
+            control: FIR | FIN | (control & 0x0f),
+            function: RESPONSE,
+            iin: iin | self.outstation.iin(&self.in_flight()),
+            headers,
+        };
+        self.link.send(&response.encode()).await
+    }
+
+    /// Events in the unsolicited response that isn't confirmed yet.
+    fn in_flight(&self) -> Vec<u64> {
+        self.unconfirmed
+            .as_ref()
+            .map(|unconfirmed| unconfirmed.events.clone())
+            .unwrap_or_default()
+    }
+
+    async fn read(&mut self, request: Fragment) -> io::Result<()> {
+        let mut iin = 0;
+        let mut headers = Vec::new();
+        let mut classes = 0;
+        let mut kinds = Vec::new();
+        for header in &request.headers {
+            let kind = PointKind::of_group(header.group);
+            match (header.group, header.variation, kind) {
+                (60, 1, _) => {
+                    let all = [
+                        PointKind::BinaryInput,
+                        PointKind::BinaryOutput,
+                        PointKind::AnalogInput,
+                        PointKind::AnalogOutput,
+                    ];
+                    headers.extend(self.outstation.statics(&all, Range::All).await?);
+                },
+                (60, 2..=4, _) => classes |= class_bit(header.variation - 1),
+                (group, variation, Some(kind))
+                    if group == kind.static_object().0
+                        && (variation == 0 || variation == kind.static_object().1) =>
+                {
+                    headers.extend(self.outstation.statics(&[kind], header.range).await?);
+                },
+                (group, variation, Some(kind))
+                    if kind
+                        .event_object()
+                        .is_some_and(|(event_group, event_variation)| {
+                            group == event_group && (variation == 0 || variation == event_variation)
+                        }) =>
+                {
+                    kinds.push(kind);
+                },
+                _ => iin |= IIN_OBJECT_UNKNOWN,
+            }
+        }
+        let in_flight = self.in_flight();
+        let events: Vec<Event> = self
+            .outstation
+            .events(0b111, &in_flight)
+            .into_iter()
+            .filter(|event| classes & class_bit(event.class) != 0 || kinds.contains(&event.kind))
+            .collect();
+        let ids = events.iter().map(|event| event.id).collect();
+        headers.extend(event_headers(events));
+        self.send_solicited(request.sequence(), iin, headers, ids)
+            .await
+    }
+
+    /// Sends a response in as many fragments as it takes, each waiting for
+    /// its confirmation if it has events or isn't the last. Confirmed events
+    /// are dropped, the others stay for the next read.
+    async fn send_solicited(
+        &mut self,
+        mut sequence: u8,
+        iin: u16,
+        headers: Vec<ObjectHeader>,
+        events: Vec<u64>,
+    ) -> io::Result<()> {
+        let fragments = Fragment::split(headers);
+        let count = fragments.len();
+        let mut events = events.into_iter();
+        for (position, headers) in fragments.into_iter().enumerate() {
+            let sent: Vec<u64> = events.by_ref().take(event_count(&headers)).collect();
+            let last = position + 1 == count;
+            let mut control = sequence;
+            if position == 0 {
+                control |= FIR;
+            }
+            if last {
+                control |= FIN;
+            }
+            if !sent.is_empty() || !last {
+                control |= CON;
+            }
+            let mut excluded = self.in_flight();
+            excluded.extend(&sent);
+            let response = Fragment {
+                control,
+                function: RESPONSE,
+                iin: iin | self.outstation.iin(&excluded),
+                headers,
+            };
+            self.link.send(&response.encode()).await?;
+            if control & CON == 0 || !self.wait_for_confirm(sequence).await? {
+                return Ok(());
+            }
+            self.outstation.remove_events(&sent);
+            sequence = (sequence + 1) & 0x0f;
+        }
+        Ok(())
+    }
+
+    /// Waits for the confirmation of the solicited response `sequence`,
+    /// returns false if it didn't come in time or another request came
+    /// instead.
+    async fn wait_for_confirm(&mut self, sequence: u8) -> io::Result<bool> {
+        let deadline = Instant::now() + self.confirm_timeout;
+        loop {
+            let bytes = match self.next(Some(deadline)).await? {
+                Some(Input::Fragment(bytes)) => bytes,
+                Some(Input::Events) => continue,
+                Some(Input::Timeout) => return Ok(false),
+                None => {
+                    return Err(io::Error::new(
+                        io::ErrorKind::UnexpectedEof,
+                        "master disconnected awaiting a confirmation",
+                    ));
+                },
+            };
+            match Fragment::parse(&bytes) {
+                Ok(confirm) if confirm.function == CONFIRM && confirm.control & UNS != 0 => {
+                    self.confirmed(&confirm);
+                },
+                Ok(confirm) if confirm.function == CONFIRM => {
+                    if confirm.sequence() == sequence {
+                        return Ok(true);
+                    }
+                },
+                _ => {
+                    self.deferred = Some(bytes);
+                    return Ok(false);
+                },
+            }
+        }
+    }
+
+    /// Sends the events of the enabled classes in an unsolicited response,
+    /// unless one is waiting for its confirmation.
+    async fn report_events(&mut self) -> io::Result<()> {
+        if !self.outstation.config.unsolicited
+            || self.unsolicited_classes == 0
+            || self.unconfirmed.is_some()
+        {
+            return Ok(());
+        }
+        let events = self.outstation.events(self.unsolicited_classes, &[]);
+        if events.is_empty() {
+            return Ok(());
+        }
+        let ids: Vec<u64> = events.iter().map(|event| event.id).collect();
+        // The rest goes in the next response, once this one is confirmed
+        let headers = Fragment::split(event_headers(events))
+            .into_iter()
+            .next()
+            .unwrap_or_default();
+        let ids = ids[..event_count(&headers)].to_vec();
+        self.send_unsolicited(headers, ids).await
+    }
+
+    async fn send_unsolicited(
+        &mut self,
+        headers: Vec<ObjectHeader>,
+        events: Vec<u64>,
+    ) -> io::Result<()> {
+        let sequence = self.unsolicited_sequence;
+        let response = Fragment {
+            control: FIR | FIN | CON | UNS | sequence,
+            function: UNSOLICITED_RESPONSE,
+            iin: self.outstation.iin(&events),
+            headers,
+        };
+        let fragment = response.encode();
+        self.link.send(&fragment).await?;
+        self.unconfirmed = Some(Unconfirmed {
+            sequence,
+            fragment,
+            events,
+            sent: Instant::now(),
+        });
+        Ok(())
+    }
+
+    async fn unsolicited(&mut self, request: Fragment) -> io::Result<()> {
+        if !self.outstation.config.unsolicited {
+            return self
+                .respond(request.control, IIN_NO_FUNCTION_CODE_SUPPORT, Vec::new())
+                .await;
+        }
+        let mut classes = 0;
+        let mut iin = 0;
+        for header in &request.headers {
+            match (header.group, header.variation) {
+                (60, 2..=4) => classes |= class_bit(header.variation - 1),
+                _ => iin |= IIN_OBJECT_UNKNOWN,
+            }
+        }
+        if request.function == ENABLE_UNSOLICITED {
+            self.unsolicited_classes |= classes;
+        } else {
+            self.unsolicited_classes &= !classes;
+        }
+        self.respond(request.control, iin, Vec::new()).await
+    }
+
+    /// Clears the restart indication or sets the clock.
+    async fn write(&mut self, request: Fragment) -> io::Result<()> {
+        let mut iin = 0;
+        for header in &request.headers {
+            match (header.group, header.variation, header.objects.as_slice()) {
+                (80, 1, [(DEVICE_RESTART_INDEX, Object::Indication(false))]) => {
+                    self.outstation.restarted.store(false, Ordering::Relaxed);
+                },
+                (50, 1, [(_, Object::Time(time))]) => {
+                    self.outstation.set_clock(*time);
+                    tracing::info!("DNP3 clock set to {}", time);
+                },
+                (80, 1, _) | (50, 1, _) => iin |= IIN_PARAMETER_ERROR,
+                _ => iin |= IIN_OBJECT_UNKNOWN,
+            }
+        }
+        self.respond(request.control, iin, Vec::new()).await
+    }
+
+    /// Selects or operates controls, answering with their statuses.
+    async fn operate(&mut self, request: Fragment) -> io::Result<()> {
+        let mut iin = 0;
+        let mut headers = Vec::new();
+        for header in &request.headers {
+            match (header.group, header.variation) {
+                (12, 1) | (41, 1..=4) => headers.push(header.clone()),
+                _ => iin |= IIN_OBJECT_UNKNOWN,
+            }
+        }
+        let selection = self.selection.take();
+        let selected = match request.function {
+            SELECT | DIRECT_OPERATE | DIRECT_OPERATE_NR => true,
+            _ => selection.is_some_and(|selection| {
+                selection.sequence.wrapping_add(1) & 0x0f == request.sequence()
+                    && selection.selected.elapsed()
+                        <= Duration::from_millis(self.outstation.config.select_timeout_ms)
+                    && selection.headers == without_status(&headers)
+            }),
+        };
+        let mut all_valid = true;
+        for header in &mut headers {
+            for (index, object) in &mut header.objects {
+                let status = if !selected {
+                    STATUS_NO_SELECT
+                } else {
+                    self.control(request.function, *index, object).await
+                };
+                all_valid &= status == STATUS_SUCCESS;
+                set_status(object, status);
+            }
+        }
+        if request.function == SELECT && all_valid && iin == 0 {
+            self.selection = Some(Selection {
+                sequence: request.sequence(),
+                headers: without_status(&headers),
+                selected: Instant::now(),
+            });
+        }
+        if request.function == DIRECT_OPERATE_NR {
+            return Ok(());
+        }
+        self.respond(request.control, iin, headers).await
+    }
+
+    /// Checks a control or, unless it's a select, writes it to its tag.
+    /// Returns the status of the control.
+    async fn control(&self, function: u8, index: u16, object: &Object) -> u8 {
+        let (kind, value) = match object {
+            Object::Crob {
+                code,
+                ..
+            } => match crob_state(*code) {
+                Some(on) => (PointKind::BinaryOutput, f64::from(u8::from(on))),
+                None => return STATUS_NOT_SUPPORTED,
+            },
+            Object::AnalogOutput {
+                value,
+                ..
+            } => (PointKind::AnalogOutput, *value),
+            _ => return STATUS_NOT_SUPPORTED,
+        };
+        let Some(point) = self.outstation.point(kind, index) else {
+            return STATUS_NOT_SUPPORTED;
+        };
+        if let Err(e) = self.outstation.writable(point).await {
+            tracing::warn!("DNP3 control of {:?} {} refused: {}", kind, index, e);
+            return STATUS_NOT_SUPPORTED;
+        }
+        if function == SELECT {
+            return STATUS_SUCCESS;
+        }
+        match self.outstation.write(point, value, &self.origin).await {
+            Ok(()) => STATUS_SUCCESS,
+            Err(e) => {
+                tracing::warn!("DNP3 control of {:?} {} failed: {}", kind, index, e);
+                STATUS_HARDWARE_ERROR
+            },
+        }
+    }
+}
+
+/// State a control relay output block sets. Tags have no pulses, so a
+/// pulse sets the state it starts with.
+fn crob_state(code: u8) -> Option<bool> {
+    match (code & 0xc0, code & 0x0f) {
+        (0, CONTROL_LATCH_ON | CONTROL_PULSE_ON) | (CONTROL_CLOSE, CONTROL_PULSE_ON) => Some(true),
+        (0, CONTROL_LATCH_OFF | CONTROL_PULSE_OFF) | (CONTROL_TRIP, CONTROL_PULSE_ON) => {
+            Some(false)
+        },
+        _ => None,
+    }
+}
+
+fn set_status(object: &mut Object, new_status: u8) {
+    match object {
+        Object::Crob {
+            status,
+            ..
+        }
+        | Object::AnalogOutput {
+            status,
+            ..
+        } => *status = new_status,
+        _ => {},
+    }
+}
+
+/// Controls compared between the select and the operate.
+fn without_status(headers: &[ObjectHeader]) -> Vec<ObjectHeader> {
+    let mut headers = headers.to_vec();
+    for header in &mut headers {
+        for (_, object) in &mut header.objects {
+            set_status(object, STATUS_SUCCESS);
+        }
+    }
+    headers
+}
+
+/// Events in headers of their objects, consecutive events of the same
+/// type sharing one.
+fn event_headers(events: Vec<Event>) -> Vec<ObjectHeader> {
+    let mut headers: Vec<ObjectHeader> = Vec::new();
+    for event in events {
+        let Some((group, variation)) = event.kind.event_object() else {
+            continue;
+        };
+        match headers.last_mut() {
+            Some(header) if header.group == group => {
+                let mut objects = std::mem::take(&mut header.objects);
+                objects.push((event.index, event.object));
+                *header = ObjectHeader::indexed(group, variation, objects);
+            },
+            _ => headers.push(ObjectHeader::indexed(
+                group,
+                variation,
+                vec![(event.index, event.object)],
+            )),
+        }
+    }
+    headers
+}
+
+/// Events among `headers`, which hold them in the order they were added.
+fn event_count(headers: &[ObjectHeader]) -> usize {
+    headers
+        .iter()
+        .filter(|header| {
+            PointKind::of_group(header.group)
+                .and_then(PointKind::event_object)
+                .is_some_and(|(group, _)| group == header.group)
+        })
+        .map(|header| header.objects.len())
+        .sum()
+}
@@ -0,0 +1,1117 @@
+use std::{
+    collections::{HashMap, HashSet},
+    io,
+    time::Duration,
+};
+
+use chrono::Utc;
+use ractor::{Actor, ActorProcessingErr, ActorRef};
+use serde::{Deserialize, Serialize};
+use tokio::{
+    net::TcpStream,
+    sync::{broadcast, broadcast::error::RecvError, mpsc},
+    task::JoinHandle,
+    time::{MissedTickBehavior, timeout},
+};
+
+use rcada_core::{
+    tag::{Tag, TagMeta, TagName, TagValue},
+    unit::Unit,
+    value::{DataType, Value},
+};
+
+use crate::{
+    actor::{self, Mailbox},
+    dnp3::{
+        PointKind,
+        app::{
+            CON, CONFIRM, CONTROL_LATCH_OFF, CONTROL_LATCH_ON, DEVICE_RESTART_INDEX,
+            DIRECT_OPERATE, DISABLE_UNSOLICITED, ENABLE_UNSOLICITED, FIN, FIR, Fragment,
+            IIN_CLASS_1_EVENTS, IIN_CLASS_2_EVENTS, IIN_CLASS_3_EVENTS, IIN_DEVICE_RESTART,
+            IIN_NEED_TIME, IIN_NO_FUNCTION_CODE_SUPPORT, IIN_OBJECT_UNKNOWN, IIN_PARAMETER_ERROR,
+            OPERATE, Object, ObjectHeader, READ, SELECT, STATUS_SUCCESS, UNS, UNSOLICITED_RESPONSE,
+            WRITE,
+        },
+        link::Link,
+        quality,
+    },
+    driver::{self, SharedDriverStatus, TagWriter},
+    metrics::metrics,
+};
+
+/// Requests made in a row for the internal indications of a response, like
+/// clearing the restart and then reading the events it reports.
+const MAX_FOLLOW_UPS: usize = 3;
+
+/// Polls a DNP3 outstation over TCP and takes its unsolicited responses,
+/// writing inputs and outputs into tags. Tags of outputs written through
+/// the API are sent to the outstation as controls.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Dnp3MasterConfig {
+    pub name: String,
+    /// Address of the outstation, e.g. `192.168.0.20:20000`.
+    pub address: String,
+    /// Link address of the driver.
+    #[serde(default = "default_master_address")]
+    pub master_address: u16,
+    #[serde(default = "default_outstation_address")]
+    pub outstation_address: u16,
+    /// How often all values and events are read (class 0, 1, 2 and 3).
+    #[serde(default = "default_integrity_poll_secs")]
+    pub integrity_poll_secs: u64,
+    /// How often the events are read (class 1, 2 and 3), 0 to rely on the
+    /// unsolicited responses and the integrity polls.
+    #[serde(default = "default_event_poll_ms")]
+    pub event_poll_ms: u64,
+    /// Enable unsolicited responses after the first integrity poll.
+    #[serde(default = "default_unsolicited")]
+    pub unsolicited: bool,
+    /// Select controls before operating them instead of operating them
+    /// directly.
+    #[serde(default)]
+    pub select_before_operate: bool,
+    /// Timeout of connecting and of every request, the connection is
+    /// reopened after it.
+    #[serde(default = "default_timeout_ms")]
+    pub timeout_ms: u64,
+    #[serde(default = "default_reconnect_interval_ms")]
+    pub reconnect_interval_ms: u64,
+    #[serde(default)]
+    pub points: Vec<Dnp3MasterPoint>,
+}
+
+/// Point of the outstation written to a tag. Missing tags are created,
+/// read-only for inputs.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Dnp3MasterPoint {
+    pub tag: String,
+    #[serde(rename = "type")]
+    pub kind: PointKind,
+    pub index: u16,
+}
+
+fn default_master_address() -> u16 {
+    1
+}
+
+fn default_outstation_address() -> u16 {
+    1024
+}
+
+fn default_integrity_poll_secs() -> u64 {
+    60
+}
+
+fn default_event_poll_ms() -> u64 {
+    5000
+}
+
+fn default_unsolicited() -> bool {
+    true
+}
+
+fn default_timeout_ms() -> u64 {
+    5000
+}
+
+fn default_reconnect_interval_ms() -> u64 {
+    5000
+}
+
+impl Dnp3MasterConfig {
+    pub fn validate(&self) -> Vec<String> {
+        let mut errors = Vec::new();
+        if self.name.is_empty() {
+            errors.push("driver name is empty".to_string());
+        }
+        if self.address.is_empty() {
+            errors.push(format!("driver {}: address is empty", self.name));
+        }
+        if self.master_address == self.outstation_address {
+            errors.push(format!(
+                "driver {}: master_address must differ from outstation_address",
+                self.name
+            ));
+        }
+        for (field, value) in [
+            ("integrity_poll_secs", self.integrity_poll_secs),
+            ("timeout_ms", self.timeout_ms),
+            ("reconnect_interval_ms", self.reconnect_interval_ms),
+        ] {
+            if value == 0 {
+                errors.push(format!("driver {}: {} must be positive", self.name, field));
+            }
+        }
+        let mut points = HashSet::new();
+        let mut tags = HashSet::new();
+        for point in &self.points {
+            if point.tag.is_empty() {
+                errors.push(format!(
+                    "driver {}: {:?} {}: tag is empty",
+                    self.name, point.kind, point.index
+                ));
+            }
+            if !points.insert((point.kind, point.index)) {
+                errors.push(format!(
+                    "driver {}: {:?} {} is mapped more than once",
+                    self.name, point.kind, point.index
+                ));
+            }
+            if !tags.insert(point.tag.as_str()) {
+                errors.push(format!(
+                    "driver {}: tag {} is mapped more than once",
+                    self.name, point.tag
+                ));
+            }
+        }
+        errors
+    }
+}
+
+/// Control of an output for a tag written through the API.
+#[derive(Debug)]
+pub struct Command {
+    tag: TagName,
+    header: ObjectHeader,
+}
+
+/// Polls a DNP3 outstation and keeps the tags of its points up to date.
+pub struct Dnp3Master;
+
+pub struct Dnp3MasterArguments {
+    pub config: Dnp3MasterConfig,
+    pub tag_repo: ActorRef<actor::tag::Message>,
+    pub status: SharedDriverStatus,
+}
+
+pub struct Dnp3MasterState {
+    config: Dnp3MasterConfig,
+    writer: TagWriter,
+    status: SharedDriverStatus,
+    /// Task polling the outstation, while connected or connecting.
+    session: Option<JoinHandle<()>>,
+    /// Controls for the outstation, while connected.
+    commands: Option<mpsc::UnboundedSender<Command>>,
+    updates: JoinHandle<()>,
+    /// Tag of every point with its data type, points whose tag can't be
+    /// created are left out.
+    tags: HashMap<(PointKind, u16), (TagName, DataType)>,
+}
+
+#[derive(Debug)]
+pub enum Message {
+    /// Connects unless a session is running.
+    Tick,
+    Connected(mpsc::UnboundedSender<Command>),
+    Disconnected(String),
+    /// Objects of a response of the outstation.
+    Received(Vec<(PointKind, u16, Object)>),
+    ValueUpdate(Box<Tag>),
+}
+
+#[cfg(feature = "cluster")]
+impl ractor::Message for Message {}
+
+#[cfg_attr(feature = "async-trait", ractor::async_trait)]
+impl Actor for Dnp3Master {
+    type Msg = Message;
+    type State = Dnp3MasterState;
+    type Arguments = Dnp3MasterArguments;
+
+    async fn pre_start(
+        &self,
+        myself: ActorRef<Self::Msg>,
+        args: Self::Arguments,
+    ) -> Result<Self::State, ActorProcessingErr> {
+        let config = args.config;
+        let (command, mut reply) = actor::tag::Message::subscribe();
+        args.tag_repo.enqueue(command)?;
+        let receiver = reply
+            .recv()
+            .await
+            .ok_or("tag repository didn't answer the subscription")?;
+        let updates = tokio::spawn(forward_updates(receiver, myself.clone()));
+
+        tracing::info!(
+            "driver {}: polling {} points of DNP3 outstation {} at {}",
+            config.name,
+            config.points.len(),
+            config.outstation_address,
+            config.address
+        );
+        let mut state = Dnp3MasterState {
+            writer: TagWriter::new(&config.name, args.tag_repo).remembering_writes(),
+            config,
+            status: args.status,
+            session: None,
+            commands: None,
+            updates,
+            tags: HashMap::new(),
+        };
+        for point in state.config.points.clone() {
+            match state.ensure_tag(&point).await {
+                Ok(data_type) => {
+                    state.tags.insert(
+                        (point.kind, point.index),
+                        (TagName::from(point.tag.as_str()), data_type),
+                    );
+                },
+                Err(e) => tracing::warn!("driver {}: {}", state.config.name, e),
+            }
+        }
+        myself.send_interval(
+            Duration::from_millis(state.config.reconnect_interval_ms),
+            || Message::Tick,
+        );
+        myself.send_message(Message::Tick)?;
+        Ok(state)
+    }
+
+    async fn post_stop(
+        &self,
+        _myself: ActorRef<Self::Msg>,
+        state: &mut Self::State,
+    ) -> Result<(), ActorProcessingErr> {
+        state.updates.abort();
+        if let Some(session) = state.session.take() {
+            session.abort();
+        }
+        Ok(())
+    }
+
+    async fn handle(
+        &self,
+        myself: ActorRef<Self::Msg>,
+        message: Self::Msg,
+        state: &mut Self::State,
+    ) -> Result<(), ActorProcessingErr> {
+        match message {
+            Message::Tick => {
+                if state.session.is_none() {
+                    state.session = Some(tokio::spawn(run_session(state.config.clone(), myself)));
+                }
+            },
+            Message::Connected(commands) => {
+                tracing::info!(
+                    "driver {}: connected to {}",
+                    state.config.name,
+                    state.config.address
+                );
+                driver::set_connected(&state.status);
+                state.commands = Some(commands);
+            },
+            Message::Disconnected(error) => {
+                state.session = None;
+                state.commands = None;
+                state.disconnected(error).await;
+            },
+            Message::Received(objects) => {
+                for (kind, index, object) in objects {
+                    let result = match state.apply(kind, index, object).await {
+                        Ok(()) => "accepted",
+                        Err(e) => {
+                            tracing::warn!("driver {}: rejected value: {}", state.config.name, e);
+                            "rejected"
+                        },
+                    };
+                    metrics()
+                        .driver_received
+                        .with_label_values(&[state.config.name.as_str(), result])
+                        .inc();
+                }
+                state
+                    .status
+                    .lock()
+                    .unwrap_or_else(|e| e.into_inner())
+                    .last_poll = Some(Utc::now());
+            },
+            Message::ValueUpdate(tag) => state.command(*tag),
+        }
+        Ok(())
+    }
+}
+
+async fn forward_updates(mut updates: broadcast::Receiver<Tag>, master: ActorRef<Message>) {
+    loop {
+        match updates.recv().await {
+            Ok(tag) => {
+                if master
+                    .send_message(Message::ValueUpdate(Box::new(tag)))
+                    .is_err()
+                {
+                    break;
+                }
+            },
+            // Controls among the dropped updates are lost, like writes while
+            // the outstation is unreachable
+            Err(RecvError::Lagged(count)) => {
+                tracing::warn!("DNP3 master missed {count} value updates");
+            },
+            Err(RecvError::Closed) => break,
+        }
+    }
+}
+
+/// Connects, polls and passes on the values until the connection fails,
+/// then reports the error.
+async fn run_session(config: Dnp3MasterConfig, master: ActorRef<Message>) {
+    let Err(e) = poll(&config, &master).await;
+    let _ = master.send_message(Message::Disconnected(e.to_string()));
+}
+
+async fn poll(
+    config: &Dnp3MasterConfig,
+    master: &ActorRef<Message>,
+) -> io::Result<std::convert::Infallible> {
+    let stream = timeout(
+        Duration::from_millis(config.timeout_ms),
+        TcpStream::connect(&config.address),
+    )
+    .await
+    .map_err(|_| io::Error::new(io::ErrorKind::TimedOut, "connect timed out"))??;
+    let (commands, mut outgoing) = mpsc::unbounded_channel();
+    master
+        .send_message(Message::Connected(commands))
+        .map_err(io::Error::other)?;
+    let mut session = Session {
+        link: Link::new(
+            stream,
+            true,
+            config.master_address,
+            config.outstation_address,
+        ),
+        config,
+        master,
+        sequence: 0,
+        iin: 0,
+    };
+
+    // Events are read by the integrity poll rather than reported while it
+    // runs, the outstation may have enabled them before a reconnect
+    if config.unsolicited {
+        session.request(DISABLE_UNSOLICITED, classes()).await?;
+    }
+    session.integrity_poll().await?;
+    if config.unsolicited {
+        session.request(ENABLE_UNSOLICITED, classes()).await?;
+    }
+    session.follow_up().await?;
+
+    let mut integrity = tokio::time::interval(Duration::from_secs(config.integrity_poll_secs));
+    integrity.set_missed_tick_behavior(MissedTickBehavior::Delay);
+    integrity.tick().await;
+    let mut events = tokio::time::interval(Duration::from_millis(config.event_poll_ms.max(1)));
+    events.set_missed_tick_behavior(MissedTickBehavior::Delay);
+    events.tick().await;
+    loop {
+        tokio::select! {
+            _ = integrity.tick() => session.integrity_poll().await?,
+            _ = events.tick(), if config.event_poll_ms > 0 => session.event_poll().await?,
+            Some(command) = outgoing.recv() => session.operate(command).await?,
+            frame = session.link.frame() => {
+                let frame = frame.ok_or_else(|| {
+                    io::Error::new(io::ErrorKind::UnexpectedEof, "outstation disconnected")
+                })??;
+                if let Some(bytes) = session.link.accept(frame).await? {
+                    match Fragment::parse(&bytes) {
+                        Ok(fragment) if fragment.function == UNSOLICITED_RESPONSE => {
+                            session.received(fragment).await?;
+                        },
+                        Ok(_) => {},
+                        Err(e) => tracing::debug!("driver {}: invalid fragment: {}", config.name, e),
+                    }
+                }
+            },
+        }
+        session.follow_up().await?;
+    }
+}
+
+/// Class 1, 2 and 3 events.
+fn classes() -> Vec<ObjectHeader> {
+    (2..=4)
+        .map(|variation| ObjectHeader::all(60, variation))
+        .collect()
+}
+
+/// Connection to the outstation, making one request at a time.
+struct Session<'a> {
+    config: &'a Dnp3MasterConfig,
+    master: &'a ActorRef<Message>,
+    link: Link,
+    /// Sequence number of the next request.
+    sequence: u8,
+    /// Internal indications of the last response.
+    iin: u16,
+}
+
+impl Session<'_> {
+    async fn integrity_poll(&mut self) -> io::Result<()> {
+        // Events first, the current values are newer
+        let mut headers = classes();
+        headers.push(ObjectHeader::all(60, 1));
+        self.read(headers).await
+    }
+
+    async fn event_poll(&mut self) -> io::Result<()> {
+        self.read(classes()).await
+    }
+
+    /// Reads the objects of `headers`, counting the poll by its result.
+    async fn read(&mut self, headers: Vec<ObjectHeader>) -> io::Result<()> {
+        let result = self.request(READ, headers).await;
+        let label = match &result {
+            Ok(_) => "ok",
+            Err(e) if e.kind() == io::ErrorKind::TimedOut => "timeout",
+            Err(_) => "error",
+        };
+        metrics()
+            .driver_polls
+            .with_label_values(&[self.config.name.as_str(), label])
+            .inc();
+        result.map(|_| ())
+    }
+
+    /// Makes the requests the internal indications of the last response ask
+    /// for: clearing the restart, setting the time and reading events.
+    async fn follow_up(&mut self) -> io::Result<()> {
+        let events = IIN_CLASS_1_EVENTS | IIN_CLASS_2_EVENTS | IIN_CLASS_3_EVENTS;
+        for _ in 0..MAX_FOLLOW_UPS {
+            if self.iin & IIN_DEVICE_RESTART != 0 {
+                let restart = ObjectHeader::range(
+                    80,
+                    1,
+                    vec![(DEVICE_RESTART_INDEX, Object::Indication(false))],
+                );
+                self.request(WRITE, vec![restart]).await?;
+            } else if self.iin & IIN_NEED_TIME != 0 {
+                let time = ObjectHeader::counted(50, 1, vec![Object::Time(Utc::now())]);
+                self.request(WRITE, vec![time]).await?;
+            } else if self.iin & events != 0 {
+                self.event_poll().await?;
+            } else {
+                break;
+            }
+        }
+        Ok(())
+    }
+
+    /// Sends `command` as a direct operate, or a select and an operate.
+    async fn operate(&mut self, command: Command) -> io::Result<()> {
+        let function = if self.config.select_before_operate {
+            let responses = self.request(SELECT, vec![command.header.clone()]).await?;
+            if let Err(e) = control_status(&responses) {
+                tracing::warn!(
+                    "driver {}: select of {} failed: {}",
+                    self.config.name,
+                    command.tag,
+                    e
+                );
+                return Ok(());
+            }
+            OPERATE
+        } else {
+            DIRECT_OPERATE
+        };
+        let responses = self.request(function, vec![command.header]).await?;
+        match control_status(&responses) {
+            Ok(()) => {
+                metrics()
+                    .driver_published
+                    .with_label_values(&[self.config.name.as_str()])
+                    .inc();
+            },
+            Err(e) => {
+                tracing::warn!(
+                    "driver {}: control of {} failed: {}",
+                    self.config.name,
+                    command.tag,
+                    e
+                );
+            },
+        }
+        Ok(())
+    }
+
+    /// Sends a request and returns the fragments of its response. Unsolicited
+    /// responses received meanwhile are taken as well.
+    async fn request(
+        &mut self,
+        function: u8,
+        headers: Vec<ObjectHeader>,
+    ) -> io::Result<Vec<Fragment>> {
+        let sequence = self.sequence;
+        self.sequence = (self.sequence + 1) & 0x0f;
+        let request = Fragment::request(function, sequence, headers);
+        self.link.send(&request.encode()).await?;
+        let deadline = Duration::from_millis(self.config.timeout_ms);
+        let mut expected = sequence;
+        let mut responses = Vec::new();
+        loop {
+            let fragment = timeout(deadline, self.fragment())
+                .await
+                .map_err(|_| io::Error::new(io::ErrorKind::TimedOut, "request timed out"))??;
+            if fragment.function == UNSOLICITED_RESPONSE {
+                self.received(fragment).await?;
+                continue;
+            }
+            if fragment.sequence() != expected {
+                // Late response to an earlier request
+                continue;
+            }
+            let last = fragment.control & FIN != 0;
+            self.received(fragment.clone()).await?;
+            responses.push(fragment);
+            if last {
+                return Ok(responses);
+            }
+            expected = (expected + 1) & 0x0f;
+        }
+    }
+
+    /// Next response of the outstation.
+    async fn fragment(&mut self) -> io::Result<Fragment> {
+        loop {
+            let frame = self.link.frame().await.ok_or_else(|| {
+                io::Error::new(io::ErrorKind::UnexpectedEof, "outstation disconnected")
+            })??;
+            let Some(bytes) = self.link.accept(frame).await? else {
+                continue;
+            };
+            match Fragment::parse(&bytes) {
+                Ok(fragment) if fragment.is_response() => return Ok(fragment),
+                Ok(_) => {},
+                Err(e) => tracing::debug!("driver {}: invalid fragment: {}", self.config.name, e),
+            }
+        }
+    }
+
+    /// Confirms a response if asked to and passes on its points.
+    async fn received(&mut self, fragment: Fragment) -> io::Result<()> {
+        if fragment.control & CON != 0 {
+            let confirm = Fragment {
+                control: FIR | FIN | (fragment.control & (UNS | 0x0f)),
+                function: CONFIRM,
+                iin: 0,
+                headers: Vec::new(),
+            };
+            self.link.send(&confirm.encode()).await?;
+        }
+        self.iin = fragment.iin;
+        let errors = IIN_NO_FUNCTION_CODE_SUPPORT | IIN_OBJECT_UNKNOWN | IIN_PARAMETER_ERROR;
+        if fragment.iin & errors != 0 {
+            tracing::debug!(
+                "driver {}: outstation reported IIN {:#06x}",
+                self.config.name,
+                fragment.iin
+            );
+        }
+        let objects: Vec<(PointKind, u16, Object)> = fragment
+            .headers
+            .into_iter()
+            .filter_map(|header| {
+                let kind = PointKind::of_group(header.group)?;
+                Some(
+                    header
+                        .objects
+                        .into_iter()
+                        .map(move |(index, object)| (kind, index, object)),
+                )
+            })
+            .flatten()
+            .collect();
+        if !objects.is_empty() {
+            self.master
+                .send_message(Message::Received(objects))
+                .map_err(io::Error::other)?;
+        }
+        Ok(())
+    }
+}
+
+/// Fails unless the outstation echoed the control with a success status.
+fn control_status(responses: &[Fragment]) -> Result<(), String> {
+    let status = responses
+        .iter()
+        .flat_map(|response| &response.headers)
+        .flat_map(|header| &header.objects)
+        .find_map(|(_, object)| match object {
+            Object::Crob {
+                status,
+                ..
+            }
+            | Object::AnalogOutput {
+                status,
+                ..
+            } => Some(*status),
+            _ => None,
+        });
+    match status {
+        Some(STATUS_SUCCESS) => Ok(()),
+        Some(status) => Err(format!("status {status}")),
+        None => Err("control not echoed".to_string()),
+    }
+}
+
+impl Dnp3MasterState {
+    /// Data type of the tag of a point, created with the type of the point
+    /// if it's missing.
+    async fn ensure_tag(&self, point: &Dnp3MasterPoint) -> Result<DataType, String> {
+        self.writer
+            .ensure_tag(&point.tag, || {
+                let data_type = if point.kind.is_binary() {
+                    DataType::Boolean
+                } else {
+                    DataType::Float
+                };
+                Ok(TagMeta {
+                    description: format!(
+                        "DNP3 {:?} {} of {}",
+                        point.kind, point.index, self.config.address
+                    ),
+                    read_only: matches!(
+                        point.kind,
+                        PointKind::BinaryInput | PointKind::AnalogInput
+                    ),
+                    ..TagMeta::new(Unit::None, data_type)
+                })
+            })
+            .await
+    }
+
+    /// Writes a value or event of a point into its tag, events keep the
+    /// time the outstation recorded.
+    async fn apply(&mut self, kind: PointKind, index: u16, object: Object) -> Result<(), String> {
+        let Some((tag, data_type)) = self.tags.get(&(kind, index)).cloned() else {
+            return Ok(());
+        };
+        let (value, flags, time) = match object {
+            Object::Binary {
+                value,
+                flags,
+                time,
+            } => (Value::Boolean(value), flags, time),
+            Object::Analog {
+                value,
+                flags,
+                time,
+            } if data_type == DataType::Integer && value.fract() == 0.0 => {
+                (Value::Integer(value as i64), flags, time)
+            },
+            Object::Analog {
+                value,
+                flags,
+                time,
+            } => (Value::Float(value as f32), flags, time),
+            _ => return Ok(()),
+        };
+        let converted = value
+            .convert(data_type)
+            .ok_or_else(|| format!("tag {tag}: {value:?} can't be converted to {data_type:?}"))?;
+        let value = TagValue {
+            value: converted,
+            timestamp: Some(time.unwrap_or_else(Utc::now)),
+            quality: quality(flags),
+        };
+        self.writer.update(&tag, value).await
+    }
+
+    /// Marks the tags `uncertain` once the connection is lost, the next
+    /// integrity poll reads them again.
+    async fn disconnected(&mut self, error: String) {
+        tracing::warn!("driver {}: {}", self.config.name, error);
+        if driver::set_disconnected(&self.status, error) {
+            let tags = self.tags.values().map(|(tag, _)| tag.clone()).collect();
+            self.writer.set_uncertain(tags).await;
+        }
+    }
+
+    /// Sends a control for an output tag written by someone else than the
+    /// driver.
+    fn command(&mut self, tag: Tag) {
+        if self.writer.is_own_write(&tag) {
+            return;
+        }
+        let point = self.config.points.iter().find(|point| {
+            point.tag == tag.name.as_str()
+                && matches!(
+                    point.kind,
+                    PointKind::BinaryOutput | PointKind::AnalogOutput
+                )
+        });
+        let Some(point) = point else {
+            return;
+        };
+        let Some(header) = control(point, &tag.value.value) else {
+            tracing::warn!(
+                "driver {}: can't write {:?} to {}",
+                self.config.name,
+                tag.value.value,
+                tag.name
+            );
+            return;
+        };
+        let Some(commands) = &self.commands else {
+            tracing::warn!(
+                "driver {}: can't write {}, the outstation is unreachable",
+                self.config.name,
+                tag.name
+            );
+            return;
+        };
+        let _ = commands.send(Command {
+            tag: tag.name,
+            header,
+        });
+    }
+}
+
+/// Control setting an output to `value`: latching a binary output on or
+/// off, or an analog output block of a 32-bit integer or a float.
+fn control(point: &Dnp3MasterPoint, value: &Value) -> Option<ObjectHeader> {
+    let object = match (point.kind, value) {
+        (PointKind::BinaryOutput, value) => {
+            let Value::Boolean(on) = value.convert(DataType::Boolean)? else {
+                return None;
+            };
+            let code = if on {
+                CONTROL_LATCH_ON
+            } else {
+                CONTROL_LATCH_OFF
+            };
+            (
+                (12, 1),
+                Object::Crob {
+                    code,
+                    count: 1,
+                    on_ms: 0,
+                    off_ms: 0,
+                    status: STATUS_SUCCESS,
+                },
+            )
+        },
+        (PointKind::AnalogOutput, Value::Integer(i)) => (
+            (41, 1),
+            Object::AnalogOutput {
+                value: f64::from(i32::try_from(*i).ok()?),
+                status: STATUS_SUCCESS,
+            },
+        ),
+        (PointKind::AnalogOutput, Value::Float(f)) => (
+            (41, 3),
+            Object::AnalogOutput {
+                value: f64::from(*f),
+                status: STATUS_SUCCESS,
+            },
+        ),
+        (PointKind::AnalogOutput, Value::Boolean(b)) => (
+            (41, 1),
+            Object::AnalogOutput {
+                value: f64::from(u8::from(*b)),
+                status: STATUS_SUCCESS,
+            },
+        ),
+        _ => return None,
+    };
+    let ((group, variation), object) = object;
+    Some(ObjectHeader::indexed(
+        group,
+        variation,
+        vec![(point.index, object)],
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::DateTime;
+    use rcada_core::tag::Quality;
+    use tokio::{
+        io::{AsyncReadExt, AsyncWriteExt},
+        net::TcpListener,
+        sync::oneshot,
+    };
+
+    use super::*;
+    use crate::{
+        dnp3::{Dnp3Config, Dnp3Point},
+        driver::testing::{create_tag, set_value, set_value_at, tag_repo, until, wait_for},
+    };
+
+    /// Stand-in outstation: the DNP3 outstation of the server itself,
+    /// serving the tags of `tag_repo` until the sender is dropped.
+    fn run_outstation(
+        listener: TcpListener,
+        tag_repo: ActorRef<actor::tag::Message>,
+    ) -> (oneshot::Sender<()>, JoinHandle<()>) {
+        let point = |tag: &str, kind, class| Dnp3Point {
+            tag: tag.to_string(),
+            kind,
+            index: 0,
+            class,
+        };
+        let config = Dnp3Config {
+            enabled: true,
+            confirm_timeout_ms: 1000,
+            points: vec![
+                point("line1/breaker", PointKind::BinaryInput, 1),
+                point("line1/level", PointKind::AnalogInput, 2),
+                point("line1/valve", PointKind::BinaryOutput, 0),
+                point("line1/setpoint", PointKind::AnalogOutput, 0),
+            ],
+            ..Dnp3Config::default()
+        };
+        let (stop, stopped) = oneshot::channel::<()>();
+        let server = tokio::spawn(async move {
+            let shutdown = async {
+                let _ = stopped.await;
+            };
+            crate::dnp3::serve(listener, &config, tag_repo, shutdown)
+                .await
+                .unwrap();
+        });
+        (stop, server)
+    }
+
+    /// Bytes of a frame written as hex, a line for the header and for every
+    /// data block with their CRCs.
+    fn bytes(lines: &[&str]) -> Vec<u8> {
+        lines
+            .iter()
+            .flat_map(|line| line.split_whitespace())
+            .map(|byte| u8::from_str_radix(byte, 16).unwrap())
+            .collect()
+    }
+
+    async fn expect(stream: &mut TcpStream, frame: &[u8]) {
+        let mut received = vec![0; frame.len()];
+        until(stream.read_exact(&mut received))
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(received, frame);
+    }
+
+    /// Frames of master 1 and outstation 1024 written out by hand from IEEE
+    /// 1815, rather than by the outstation of the server.
+    #[tokio::test]
+    async fn exchanges_frames_of_the_standard() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap();
+        let tag_repo = tag_repo().await;
+        let point = |tag: &str, kind| Dnp3MasterPoint {
+            tag: tag.to_string(),
+            kind,
+            index: 0,
+        };
+        let config = Dnp3MasterConfig {
+            name: "captured".to_string(),
+            address: address.to_string(),
+            master_address: 1,
+            outstation_address: 1024,
+            integrity_poll_secs: 60,
+            event_poll_ms: 0,
+            unsolicited: false,
+            select_before_operate: false,
+            timeout_ms: 1000,
+            reconnect_interval_ms: 50,
+            points: vec![
+                point("captured/breaker", PointKind::BinaryInput),
+                point("captured/level", PointKind::AnalogInput),
+                point("captured/valve", PointKind::BinaryOutput),
+            ],
+        };
+        let (driver, _) = Actor::spawn(
+            None,
+            Dnp3Master,
+            Dnp3MasterArguments {
+                config,
+                tag_repo: tag_repo.clone(),
+                status: SharedDriverStatus::default(),
+            },
+        )
+        .await
+        .unwrap();
+        let (mut stream, _) = until(listener.accept()).await.unwrap().unwrap();
+
+        // Read of the events of class 1, 2 and 3 and of class 0
+        expect(
+            &mut stream,
+            &bytes(&[
+                "05 64 14 c4 00 04 01 00 e9 b6",
+                "c0 c0 01 3c 02 06 3c 03 06 3c 04 06 3c 01 06 8a 51",
+            ]),
+        )
+        .await;
+        // g1v2 closed, g30v5 50.0 and g10v2 off, all online
+        let response = bytes(&[
+            "05 64 20 44 01 00 00 04 8e c5",
+            "c0 c0 81 00 00 01 02 00 00 00 81 1e 05 00 00 00 3b 26",
+            "01 00 00 48 42 0a 02 00 00 00 01 e3 42",
+        ]);
+        stream.write_all(&response).await.unwrap();
+        let tag = wait_for(&tag_repo, "captured/breaker", |tag| {
+            tag.value.value == Value::Boolean(true)
+        })
+        .await;
+        assert_eq!(tag.value.quality, Quality::Good);
+        wait_for(&tag_repo, "captured/level", |tag| {
+            tag.value.value == Value::Float(50.0)
+        })
+        .await;
+        wait_for(&tag_repo, "captured/valve", |tag| {
+            tag.value.value == Value::Boolean(false)
+        })
+        .await;
+
+        // Direct operate of a g12v1 latch on, echoed with success
+        set_value(&tag_repo, "captured/valve", Value::Boolean(true)).await;
+        expect(
+            &mut stream,
+            &bytes(&[
+                "05 64 1a c4 00 04 01 00 ec 47",
+                "c1 c1 05 0c 01 28 01 00 00 00 03 01 00 00 00 00 3b 17",
+                "00 00 00 00 00 ff ff",
+            ]),
+        )
+        .await;
+        let response = bytes(&[
+            "05 64 1c 44 01 00 00 04 7f 9b",
+            "c1 c1 81 00 00 0c 01 28 01 00 00 00 03 01 00 00 ce 3a",
+            "00 00 00 00 00 00 00 ff ff",
+        ]);
+        stream.write_all(&response).await.unwrap();
+
+        // Unsolicited g32v7 event of 62.5 at 2023-11-14T22:13:20Z, confirmed
+        let unsolicited = bytes(&[
+            "05 64 1a 44 01 00 00 04 a6 f0",
+            "c2 f0 82 00 00 20 07 17 01 00 01 00 00 7a 42 00 5a cc",
+            "68 e5 cf 8b 01 79 3f",
+        ]);
+        stream.write_all(&unsolicited).await.unwrap();
+        expect(
+            &mut stream,
+            &bytes(&["05 64 08 c4 00 04 01 00 9a 19", "c2 d0 00 6b 7a"]),
+        )
+        .await;
+        let tag = wait_for(&tag_repo, "captured/level", |tag| {
+            tag.value.value == Value::Float(62.5)
+        })
+        .await;
+        assert_eq!(
+            tag.value.timestamp,
+            DateTime::from_timestamp_millis(1_700_000_000_000)
+        );
+        let published = metrics().driver_published.with_label_values(&["captured"]);
+        assert_eq!(published.get(), 1);
+
+        driver.stop(None);
+    }
+
+    #[tokio::test]
+    async fn polls_an_outstation_and_operates_its_outputs() {
+        let plant = tag_repo().await;
+        create_tag(&plant, "line1/breaker", Value::Boolean(true)).await;
+        create_tag(&plant, "line1/level", Value::Float(50.0)).await;
+        create_tag(&plant, "line1/valve", Value::Boolean(false)).await;
+        create_tag(&plant, "line1/setpoint", Value::Float(10.0)).await;
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap();
+        let (stop, server) = run_outstation(listener, plant.clone());
+
+        let tag_repo = tag_repo().await;
+        let point = |tag: &str, kind| Dnp3MasterPoint {
+            tag: tag.to_string(),
+            kind,
+            index: 0,
+        };
+        let config = Dnp3MasterConfig {
+            name: "rtu".to_string(),
+            address: address.to_string(),
+            master_address: 1,
+            outstation_address: 1024,
+            integrity_poll_secs: 60,
+            // Events only come unsolicited
+            event_poll_ms: 0,
+            unsolicited: true,
+            select_before_operate: true,
+            timeout_ms: 1000,
+            reconnect_interval_ms: 50,
+            points: vec![
+                point("rtu/breaker", PointKind::BinaryInput),
+                point("rtu/level", PointKind::AnalogInput),
+                point("rtu/valve", PointKind::BinaryOutput),
+                point("rtu/setpoint", PointKind::AnalogOutput),
+            ],
+        };
+        assert!(config.validate().is_empty(), "{:?}", config.validate());
+        let status = SharedDriverStatus::default();
+        let (driver, _) = Actor::spawn(
+            None,
+            Dnp3Master,
+            Dnp3MasterArguments {
+                config,
+                tag_repo: tag_repo.clone(),
+                status: status.clone(),
+            },
+        )
+        .await
+        .unwrap();
+
+        // Integrity poll
+        let tag = wait_for(&tag_repo, "rtu/breaker", |tag| {
+            tag.value.value == Value::Boolean(true)
+        })
+        .await;
+        assert_eq!(tag.value.quality, Quality::Good);
+        assert!(tag.meta.read_only);
+        wait_for(&tag_repo, "rtu/level", |tag| {
+            tag.value.value == Value::Float(50.0)
+        })
+        .await;
+        let tag = wait_for(&tag_repo, "rtu/setpoint", |tag| {
+            tag.value.value == Value::Float(10.0)
+        })
+        .await;
+        assert!(!tag.meta.read_only);
+        assert!(status.lock().unwrap().connected);
+        let polls = metrics().driver_polls.with_label_values(&["rtu", "ok"]);
+        assert!(polls.get() >= 1);
+
+        // Events keep the time of the change
+        let changed =
+            DateTime::from_timestamp_millis(Utc::now().timestamp_millis() - 60_000).unwrap();
+        set_value_at(&plant, "line1/level", Value::Float(62.5), changed).await;
+        let tag = wait_for(&tag_repo, "rtu/level", |tag| {
+            tag.value.value == Value::Float(62.5)
+        })
+        .await;
+        assert_eq!(tag.value.timestamp, Some(changed));
+        set_value(&plant, "line1/breaker", Value::Boolean(false)).await;
+        wait_for(&tag_repo, "rtu/breaker", |tag| {
+            tag.value.value == Value::Boolean(false)
+        })
+        .await;
+
+        // Outputs written through the API are operated
+        set_value(&tag_repo, "rtu/setpoint", Value::Float(20.0)).await;
+        wait_for(&plant, "line1/setpoint", |tag| {
+            tag.value.value == Value::Float(20.0)
+        })
+        .await;
+        set_value(&tag_repo, "rtu/valve", Value::Boolean(true)).await;
+        wait_for(&plant, "line1/valve", |tag| {
+            tag.value.value == Value::Boolean(true)
+        })
+        .await;
+
+        // Tags turn uncertain while the outstation is down
+        let _ = stop.send(());
+        until(server).await.unwrap().unwrap();
+        wait_for(&tag_repo, "rtu/breaker", |tag| {
+            tag.value.quality == Quality::Uncertain
+        })
+        .await;
+        assert!(!status.lock().unwrap().connected);
+        driver.stop(None);
+    }
+}
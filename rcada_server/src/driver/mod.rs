@@ -0,0 +1,367 @@
+pub mod dnp3;
+pub mod mqtt;
+pub mod opcua;
+pub mod s7;
+#[cfg(test)]
+pub(crate) mod testing;
+
+use std::{
+    collections::{HashMap, VecDeque},
+    path::Path,
+    sync::{Arc, Mutex},
+};
+
+use chrono::{DateTime, Utc};
+use ractor::{Actor, ActorCell, ActorRef};
+use serde::{Deserialize, Serialize};
+
+use rcada_core::{
+    tag::{Quality, Tag, TagMeta, TagName, TagValue},
+    value::DataType,
+};
+
+use crate::{
+    actor::{self, Mailbox},
+    audit::Origin,
+    repository::tag::{CreateTagResult, UpdateValueResult},
+};
+
+/// Shown instead of a password in [`DriverConfig::redacted`].
+pub const REDACTED: &str = "********";
+
+/// Values written by a driver and remembered to tell them from writes
+/// through the API, per tag.
+const MAX_PENDING_WRITES: usize = 64;
+
+/// Configuration of a driver connecting tags to a field bus or another
+/// system, selected by `kind`.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum DriverConfig {
+    MqttPublisher(Box<mqtt::publisher::MqttPublisherConfig>),
+    MqttSubscriber(Box<mqtt::subscriber::MqttSubscriberConfig>),
+    Sparkplug(Box<mqtt::sparkplug::SparkplugConfig>),
+    #[serde(rename = "opcua")]
+    OpcUa(Box<opcua::OpcUaClientConfig>),
+    Dnp3(Box<dnp3::Dnp3MasterConfig>),
+    S7(s7::S7Config),
+}
+
+impl DriverConfig {
+    pub fn name(&self) -> &str {
+        match self {
+            DriverConfig::MqttPublisher(config) => &config.name,
+            DriverConfig::MqttSubscriber(config) => &config.name,
+            DriverConfig::Sparkplug(config) => &config.name,
+            DriverConfig::OpcUa(config) => &config.name,
+            DriverConfig::Dnp3(config) => &config.name,
+            DriverConfig::S7(config) => &config.name,
+        }
+    }
+
+    /// Copy of the configuration with the passwords replaced, to show it to clients.
+    pub fn redacted(&self) -> Self {
+        let redact = |password: &mut Option<String>| {
+            if let Some(password) = password {
+                *password = REDACTED.to_string();
+            }
+        };
+        let mut config = self.clone();
+        match &mut config {
+            DriverConfig::MqttPublisher(config) => redact(&mut config.broker.password),
+            DriverConfig::MqttSubscriber(config) => redact(&mut config.broker.password),
+            DriverConfig::Sparkplug(config) => redact(&mut config.broker.password),
+            DriverConfig::OpcUa(config) => redact(&mut config.password),
+            DriverConfig::Dnp3(_) | DriverConfig::S7(_) => {},
+        }
+        config
+    }
+
+    /// Returns a description of every problem found in the configuration.
+    pub fn validate(&self) -> Vec<String> {
+        match self {
+            DriverConfig::MqttPublisher(config) => config.validate(),
+            DriverConfig::MqttSubscriber(config) => config.validate(),
+            DriverConfig::Sparkplug(config) => config.validate(),
+            DriverConfig::OpcUa(config) => config.validate(),
+            DriverConfig::Dnp3(config) => config.validate(),
+            DriverConfig::S7(config) => config.validate(),
+        }
+    }
+}
+
+/// Connection state of a driver, updated after every poll.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct DriverStatus {
+    pub connected: bool,
+    pub last_poll: Option<DateTime<Utc>>,
+    pub last_error: Option<String>,
+}
+
+/// [`DriverStatus`] shared between a driver and the readiness check.
+pub type SharedDriverStatus = Arc<Mutex<DriverStatus>>;
+
+/// Records that the driver is connected.
+pub fn set_connected(status: &SharedDriverStatus) {
+    let mut status = status.lock().unwrap_or_else(|e| e.into_inner());
+    status.connected = true;
+    status.last_error = None;
+}
+
+/// Records the error the connection was lost with, returns whether the
+/// driver was connected until now.
+pub fn set_disconnected(status: &SharedDriverStatus, error: String) -> bool {
+    let mut status = status.lock().unwrap_or_else(|e| e.into_inner());
+    status.last_error = Some(error);
+    std::mem::replace(&mut status.connected, false)
+}
+
+/// Creates and writes the tags of a driver, with the driver as origin.
+pub struct TagWriter {
+    driver: String,
+    tag_repo: ActorRef<actor::tag::Message>,
+    /// Values written by the driver and not seen among the updates yet,
+    /// `None` unless the driver writes tags to its peer.
+    written: Option<HashMap<TagName, VecDeque<TagValue>>>,
+}
+
+impl TagWriter {
+    pub fn new(driver: &str, tag_repo: ActorRef<actor::tag::Message>) -> Self {
+        Self {
+            driver: driver.to_string(),
+            tag_repo,
+            written: None,
+        }
+    }
+
+    /// Remembers the values written, see [`TagWriter::is_own_write`].
+    pub fn remembering_writes(mut self) -> Self {
+        self.written = Some(HashMap::new());
+        self
+    }
+
+    /// Data type of a tag, created with `meta` if it's missing.
+    pub async fn ensure_tag(
+        &self,
+        tag: &str,
+        meta: impl FnOnce() -> Result<TagMeta, String>,
+    ) -> Result<DataType, String> {
+        let (command, mut reply) = actor::tag::Message::get_tag_data_type(tag);
+        self.tag_repo.enqueue(command).map_err(|e| e.to_string())?;
+        // Values are converted into the type of existing tags
+        if let Some(data_type) = reply.recv().await.flatten() {
+            return Ok(data_type);
+        }
+        let meta = meta()?;
+        let data_type = meta.data_type;
+        let (command, mut reply) = actor::tag::Message::create_tag(tag, meta);
+        self.tag_repo
+            .enqueue(command.with_origin(self.origin()))
+            .map_err(|e| e.to_string())?;
+        match reply.recv().await {
+            Some(CreateTagResult::SuccessfullyCreated) => {
+                tracing::info!("driver {}: created tag {}", self.driver, tag);
+                Ok(data_type)
+            },
+            // Created concurrently
+            Some(CreateTagResult::AlreadyExists) => Ok(data_type),
+            Some(CreateTagResult::Storage(e)) => Err(format!("failed to save tag {tag}: {e}")),
+            None => Err("tag repository didn't answer".to_string()),
+        }
+    }
+
+    pub async fn update(&mut self, tag: &TagName, value: TagValue) -> Result<(), String> {
+        let (command, mut reply) =
+            actor::tag::Message::update_tag_value(tag.clone(), value.clone());
+        self.tag_repo
+            .enqueue(command.with_origin(self.origin()))
+            .map_err(|e| e.to_string())?;
+        match reply.recv().await {
+            Some(Ok(UpdateValueResult::Updated)) => {
+                if let Some(written) = &mut self.written {
+                    let written = written.entry(tag.clone()).or_default();
+                    if written.len() == MAX_PENDING_WRITES {
+                        written.pop_front();
+                    }
+                    written.push_back(value);
+                }
+                Ok(())
+            },
+            Some(Ok(UpdateValueResult::Ignored)) => Ok(()),
+            Some(Err(e)) => Err(format!("tag {tag}: {e:?}")),
+            None => Err("tag repository didn't answer".to_string()),
+        }
+    }
+
+    /// Keeps the value of a tag and marks it with `quality`.
+    pub async fn set_quality(
+        &mut self,
+        tag: &TagName,
+        quality: Quality,
+        timestamp: DateTime<Utc>,
+    ) -> Result<(), String> {
+        let (command, mut reply) = actor::tag::Message::get_tag(tag.clone());
+        self.tag_repo.enqueue(command).map_err(|e| e.to_string())?;
+        let current = reply
+            .recv()
+            .await
+            .and_then(Result::ok)
+            .ok_or_else(|| format!("tag {tag} not found"))?;
+        if current.value.quality == quality {
+            return Ok(());
+        }
+        self.update(
+            tag,
+            TagValue {
+                value: current.value.value,
+                timestamp: Some(timestamp),
+                quality,
+            },
+        )
+        .await
+    }
+
+    /// Marks the tags `uncertain` once the connection to the peer is lost.
+    pub async fn set_uncertain(&mut self, tags: Vec<TagName>) {
+        let now = Utc::now();
+        for tag in tags {
+            if let Err(e) = self.set_quality(&tag, Quality::Uncertain, now).await {
+                tracing::warn!("driver {}: {}", self.driver, e);
+            }
+        }
+    }
+
+    /// Whether an update of a tag is a value the driver wrote, forgetting
+    /// it and the values written before it.
+    pub fn is_own_write(&mut self, tag: &Tag) -> bool {
+        let Some(written) = self
+            .written
+            .as_mut()
+            .and_then(|written| written.get_mut(&tag.name))
+        else {
+            return false;
+        };
+        match written.iter().position(|value| *value == tag.value) {
+            Some(position) => {
+                written.drain(..=position);
+                true
+            },
+            None => false,
+        }
+    }
+
+    fn origin(&self) -> Origin {
+        Origin::system(format!("driver/{}", self.driver))
+    }
+}
+
+pub struct RunningDriver {
+    pub cell: ActorCell,
+    pub status: SharedDriverStatus,
+}
+
+impl RunningDriver {
+    pub fn status(&self) -> DriverStatus {
+        self.status
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .clone()
+    }
+}
+
+/// Starts the driver as an actor connected to the tag repository. Drivers
+/// keep their files in `data_dir`.
+pub async fn spawn(
+    config: DriverConfig,
+    tag_repo: ActorRef<actor::tag::Message>,
+    data_dir: &Path,
+) -> Result<RunningDriver, ractor::SpawnErr> {
+    let name = format!("driver/{}", config.name());
+    let status = SharedDriverStatus::default();
+    let cell = match config {
+        DriverConfig::MqttPublisher(config) => {
+            let (actor, _) = Actor::spawn(
+                Some(name),
+                mqtt::publisher::MqttPublisher,
+                mqtt::publisher::MqttPublisherArguments {
+                    config: *config,
+                    tag_repo,
+                    status: status.clone(),
+                    data_dir: data_dir.to_path_buf(),
+                },
+            )
+            .await?;
+            actor.get_cell()
+        },
+        DriverConfig::MqttSubscriber(config) => {
+            let (actor, _) = Actor::spawn(
+                Some(name),
+                mqtt::subscriber::MqttSubscriber,
+                mqtt::subscriber::MqttSubscriberArguments {
+                    config: *config,
+                    tag_repo,
+                    status: status.clone(),
+                },
+            )
+            .await?;
+            actor.get_cell()
+        },
+        DriverConfig::Sparkplug(config) => {
+            let (actor, _) = Actor::spawn(
+                Some(name),
+                mqtt::sparkplug::SparkplugHost,
+                mqtt::sparkplug::SparkplugArguments {
+                    config: *config,
+                    tag_repo,
+                    status: status.clone(),
+                },
+            )
+            .await?;
+            actor.get_cell()
+        },
+        DriverConfig::OpcUa(config) => {
+            let (actor, _) = Actor::spawn(
+                Some(name),
+                opcua::OpcUaClient,
+                opcua::OpcUaClientArguments {
+                    config: *config,
+                    tag_repo,
+                    status: status.clone(),
+                    data_dir: data_dir.to_path_buf(),
+                },
+            )
+            .await?;
+            actor.get_cell()
+        },
+        DriverConfig::Dnp3(config) => {
+            let (actor, _) = Actor::spawn(
+                Some(name),
+                dnp3::Dnp3Master,
+                dnp3::Dnp3MasterArguments {
+                    config: *config,
+                    tag_repo,
+                    status: status.clone(),
+                },
+            )
+            .await?;
+            actor.get_cell()
+        },
+        DriverConfig::S7(config) => {
+            let (actor, _) = Actor::spawn(
+                Some(name),
+                s7::S7Driver,
+                s7::S7Arguments {
+                    config,
+                    tag_repo,
+                    status: status.clone(),
+                },
+            )
+            .await?;
+            actor.get_cell()
+        },
+    };
+    Ok(RunningDriver {
+        cell,
+        status,
+    })
+}
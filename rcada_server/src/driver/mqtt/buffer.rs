@@ -0,0 +1,106 @@
+use std::{
+    fs::{self, File, OpenOptions},
+    io::{self, Write},
+    path::PathBuf,
+};
+
+use super::packet::{Packet, Publish};
+
+/// Messages waiting for the broker, kept in a file so they survive a
+/// restart. Messages are stored as PUBLISH packets, their packet id is
+/// assigned when they are sent.
+pub struct DiskBuffer {
+    path: PathBuf,
+    file: File,
+    /// Size of the file.
+    size: u64,
+    max_size: u64,
+    messages: usize,
+}
+
+impl DiskBuffer {
+    /// Opens the buffer left by a previous run, if any. A message cut short
+    /// by a crash is dropped.
+    pub fn open(path: PathBuf, max_size: u64) -> io::Result<Self> {
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        let data = match fs::read(&path) {
+            Ok(data) => data,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Vec::new(),
+            Err(e) => return Err(e),
+        };
+        let (messages, complete) = decode(&data);
+        let file = OpenOptions::new().create(true).append(true).open(&path)?;
+        file.set_len(complete as u64)?;
+        Ok(Self {
+            path,
+            file,
+            size: complete as u64,
+            max_size,
+            messages: messages.len(),
+        })
+    }
+
+    pub fn len(&self) -> usize {
+        self.messages
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.messages == 0
+    }
+
+    /// Appends a message, returns `false` if the buffer is full.
+    pub fn push(&mut self, publish: Publish) -> io::Result<bool> {
+        let mut buf = Vec::new();
+        Packet::Publish(publish).encode(&mut buf);
+        if self.size + buf.len() as u64 > self.max_size {
+            return Ok(false);
+        }
+        self.file.write_all(&buf)?;
+        self.size += buf.len() as u64;
+        self.messages += 1;
+        Ok(true)
+    }
+
+    /// All buffered messages, oldest first.
+    pub fn read(&self) -> io::Result<Vec<Publish>> {
+        Ok(decode(&fs::read(&self.path)?).0)
+    }
+
+    /// Removes the `count` oldest messages, after they were sent.
+    pub fn remove(&mut self, count: usize) -> io::Result<()> {
+        if count >= self.messages {
+            self.file.set_len(0)?;
+            self.size = 0;
+            self.messages = 0;
+            return Ok(());
+        }
+        let remaining = self.read()?.split_off(count);
+        let mut buf = Vec::new();
+        for publish in &remaining {
+            Packet::Publish(publish.clone()).encode(&mut buf);
+        }
+        let tmp = self.path.with_extension("tmp");
+        fs::write(&tmp, &buf)?;
+        fs::rename(&tmp, &self.path)?;
+        self.file = OpenOptions::new().append(true).open(&self.path)?;
+        self.size = buf.len() as u64;
+        self.messages = remaining.len();
+        Ok(())
+    }
+}
+
+/// Messages in `data` and the length of the part holding complete messages.
+fn decode(data: &[u8]) -> (Vec<Publish>, usize) {
+    let mut messages = Vec::new();
+    let mut offset = 0;
+    while let Ok(Some((packet, length))) = Packet::decode(&data[offset..]) {
+        let Packet::Publish(publish) = packet else {
+            break;
+        };
+        messages.push(publish);
+        offset += length;
+    }
+    (messages, offset)
+}
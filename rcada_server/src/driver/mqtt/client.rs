@@ -0,0 +1,335 @@
+use std::{
+    collections::{HashSet, VecDeque},
+    io,
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+use rustls::pki_types::ServerName;
+use tokio::{
+    io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt, BufStream},
+    net::TcpStream,
+};
+use tokio_rustls::TlsConnector;
+
+use crate::tls;
+
+use super::{
+    BrokerConfig,
+    packet::{Connect, Packet, Publish, QoS, connack_error},
+};
+
+trait Connection: AsyncRead + AsyncWrite + Unpin + Send {}
+
+impl<T: AsyncRead + AsyncWrite + Unpin + Send> Connection for T {}
+
+/// Client session with an MQTT broker. Exchanges are sequential: every call
+/// waits for the acknowledgement of the broker before it returns, the caller
+/// bounds them with a timeout.
+pub struct MqttClient {
+    stream: BufStream<Box<dyn Connection>>,
+    /// Bytes received and not decoded yet, so that reads can be cancelled.
+    read_buf: Vec<u8>,
+    next_packet_id: u16,
+    keep_alive: Duration,
+    last_sent: Instant,
+    ping_sent: Option<Instant>,
+    /// Messages that arrived while waiting for an acknowledgement.
+    received: VecDeque<Publish>,
+    /// QoS 2 messages delivered and not released by the broker yet.
+    unreleased: HashSet<u16>,
+}
+
+impl MqttClient {
+    /// Connects with a clean session, so nothing is kept by the broker
+    /// between connections. The broker publishes `will` if the connection
+    /// is lost without a disconnect.
+    pub async fn connect(
+        config: &BrokerConfig,
+        client_id: String,
+        will: Option<Publish>,
+    ) -> io::Result<Self> {
+        let tcp = TcpStream::connect(&config.address).await?;
+        tcp.set_nodelay(true)?;
+        let stream: Box<dyn Connection> = match &config.tls {
+            Some(tls_config) => {
+                let client_cert = tls_config
+                    .cert_file
+                    .as_deref()
+                    .zip(tls_config.key_file.as_deref());
+                let tls_config = tls::client_config(&tls_config.ca_file, client_cert)
+                    .map_err(io::Error::other)?;
+                let host = config
+                    .address
+                    .rsplit_once(':')
+                    .map_or(config.address.as_str(), |(host, _)| host)
+                    .trim_start_matches('[')
+                    .trim_end_matches(']');
+                let server_name = ServerName::try_from(host.to_string())
+                    .map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))?;
+                let connector = TlsConnector::from(Arc::new(tls_config));
+                Box::new(connector.connect(server_name, tcp).await?)
+            },
+            None => Box::new(tcp),
+        };
+
+        let mut client = Self {
+            stream: BufStream::new(stream),
+            read_buf: Vec::new(),
+            next_packet_id: 0,
+            keep_alive: Duration::from_secs(config.keep_alive_secs.into()),
+            last_sent: Instant::now(),
+            ping_sent: None,
+            received: VecDeque::new(),
+            unreleased: HashSet::new(),
+        };
+        client
+            .send(&Packet::Connect(Connect {
+                client_id,
+                keep_alive_secs: config.keep_alive_secs,
+                clean_session: true,
+                username: config.username.clone(),
+                password: config.password.clone(),
+                will,
+            }))
+            .await?;
+        match client.receive().await? {
+            Packet::ConnAck {
+                code: 0,
+                ..
+            } => Ok(client),
+            Packet::ConnAck {
+                code,
+                ..
+            } => Err(io::Error::new(
+                io::ErrorKind::ConnectionRefused,
+                format!("broker refused the connection: {}", connack_error(code)),
+            )),
+            packet => Err(unexpected(&packet)),
+        }
+    }
+
+    /// Publishes and waits until the broker has taken over the message, as
+    /// far as the QoS of `publish` asks for.
+    pub async fn publish(&mut self, mut publish: Publish) -> io::Result<()> {
+        if publish.qos == QoS::AtMostOnce {
+            return self.send(&Packet::Publish(publish)).await;
+        }
+        let id = self.packet_id();
+        publish.packet_id = id;
+        let qos = publish.qos;
+        self.send(&Packet::Publish(publish)).await?;
+        if qos == QoS::AtLeastOnce {
+            return self.expect(Packet::PubAck(id)).await;
+        }
+        self.expect(Packet::PubRec(id)).await?;
+        self.send(&Packet::PubRel(id)).await?;
+        self.expect(Packet::PubComp(id)).await
+    }
+
+    /// Pings the broker if nothing was sent for half the keep-alive time.
+    pub async fn keep_alive(&mut self) -> io::Result<()> {
+        if self.keep_alive.is_zero() || self.last_sent.elapsed() < self.keep_alive / 2 {
+            return Ok(());
+        }
+        self.send(&Packet::PingReq).await?;
+        self.expect(Packet::PingResp).await
+    }
+
+    /// Subscribes to topic filters, each with the highest QoS it's delivered
+    /// with. Fails if the broker refuses any of them.
+    pub async fn subscribe(&mut self, filters: Vec<(String, QoS)>) -> io::Result<()> {
+        let packet_id = self.packet_id();
+        let count = filters.len();
+        self.send(&Packet::Subscribe {
+            packet_id,
+            filters: filters.clone(),
+        })
+        .await?;
+        let codes = match self.receive_queued().await? {
+            Packet::SubAck {
+                packet_id: id,
+                codes,
+            } if id == packet_id => codes,
+            packet => return Err(unexpected(&packet)),
+        };
+        if codes.len() != count {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "SUBACK doesn't match the subscription",
+            ));
+        }
+        let refused: Vec<&str> = filters
+            .iter()
+            .zip(codes)
+            .filter(|(_, code)| *code > QoS::ExactlyOnce as u8)
+            .map(|((filter, _), _)| filter.as_str())
+            .collect();
+        if !refused.is_empty() {
+            return Err(io::Error::new(
+                io::ErrorKind::PermissionDenied,
+                format!("broker refused the subscription to {}", refused.join(", ")),
+            ));
+        }
+        Ok(())
+    }
+
+    /// Waits for the next message of the subscriptions and acknowledges it.
+    /// Pings the broker while waiting, and fails if a ping isn't answered
+    /// within `timeout`.
+    pub async fn next_message(&mut self, timeout: Duration) -> io::Result<Publish> {
+        loop {
+            self.ready(timeout).await?;
+            if let Some(publish) = self.poll_message(timeout).await? {
+                return Ok(publish);
+            }
+        }
+    }
+
+    /// Waits until a packet has arrived or a ping is due, so that
+    /// [`MqttClient::poll_message`] doesn't block. Cancelling it loses
+    /// nothing, which lets callers publish while waiting for messages.
+    pub async fn ready(&mut self, timeout: Duration) -> io::Result<()> {
+        if !self.received.is_empty() {
+            return Ok(());
+        }
+        let deadline = match self.ping_sent {
+            Some(sent) => Some(sent + timeout),
+            None if self.keep_alive.is_zero() => None,
+            None => Some(self.last_sent + self.keep_alive / 2),
+        };
+        loop {
+            if Packet::decode(&self.read_buf)?.is_some() {
+                return Ok(());
+            }
+            let read = self.stream.read_buf(&mut self.read_buf);
+            let count = match deadline {
+                Some(deadline) => {
+                    let deadline = tokio::time::Instant::from_std(deadline);
+                    match tokio::time::timeout_at(deadline, read).await {
+                        Ok(count) => count?,
+                        Err(_) => return Ok(()),
+                    }
+                },
+                None => read.await?,
+            };
+            if count == 0 {
+                return Err(io::ErrorKind::UnexpectedEof.into());
+            }
+        }
+    }
+
+    /// Handles the packet that [`MqttClient::ready`] waited for, or pings
+    /// the broker. Returns the message if the packet was a new one.
+    pub async fn poll_message(&mut self, timeout: Duration) -> io::Result<Option<Publish>> {
+        if let Some(publish) = self.received.pop_front() {
+            return self.accept(publish).await;
+        }
+        let Some((packet, length)) = Packet::decode(&self.read_buf)? else {
+            match self.ping_sent {
+                Some(sent) if sent.elapsed() >= timeout => {
+                    return Err(io::Error::new(
+                        io::ErrorKind::TimedOut,
+                        "broker didn't answer a ping",
+                    ));
+                },
+                None if !self.keep_alive.is_zero()
+                    && self.last_sent.elapsed() >= self.keep_alive / 2 =>
+                {
+                    self.send(&Packet::PingReq).await?;
+                    self.ping_sent = Some(Instant::now());
+                },
+                _ => {},
+            }
+            return Ok(None);
+        };
+        self.read_buf.drain(..length);
+        match packet {
+            Packet::Publish(publish) => self.accept(publish).await,
+            Packet::PubRel(id) => {
+                self.unreleased.remove(&id);
+                self.send(&Packet::PubComp(id)).await?;
+                Ok(None)
+            },
+            Packet::PingResp => {
+                self.ping_sent = None;
+                Ok(None)
+            },
+            packet => Err(unexpected(&packet)),
+        }
+    }
+
+    /// Acknowledges a message, `None` for a repeated QoS 2 message that was
+    /// delivered already.
+    async fn accept(&mut self, publish: Publish) -> io::Result<Option<Publish>> {
+        match publish.qos {
+            QoS::AtMostOnce => Ok(Some(publish)),
+            QoS::AtLeastOnce => {
+                self.send(&Packet::PubAck(publish.packet_id)).await?;
+                Ok(Some(publish))
+            },
+            QoS::ExactlyOnce => {
+                self.send(&Packet::PubRec(publish.packet_id)).await?;
+                Ok(self.unreleased.insert(publish.packet_id).then_some(publish))
+            },
+        }
+    }
+
+    pub async fn disconnect(mut self) -> io::Result<()> {
+        self.send(&Packet::Disconnect).await?;
+        self.stream.shutdown().await
+    }
+
+    fn packet_id(&mut self) -> u16 {
+        // 0 isn't a valid packet id
+        self.next_packet_id = self.next_packet_id.checked_add(1).unwrap_or(1);
+        self.next_packet_id
+    }
+
+    async fn send(&mut self, packet: &Packet) -> io::Result<()> {
+        let mut buf = Vec::new();
+        packet.encode(&mut buf);
+        self.stream.write_all(&buf).await?;
+        self.stream.flush().await?;
+        self.last_sent = Instant::now();
+        Ok(())
+    }
+
+    /// Reads the next packet. Cancelling it loses nothing.
+    async fn receive(&mut self) -> io::Result<Packet> {
+        loop {
+            if let Some((packet, length)) = Packet::decode(&self.read_buf)? {
+                self.read_buf.drain(..length);
+                return Ok(packet);
+            }
+            if self.stream.read_buf(&mut self.read_buf).await? == 0 {
+                return Err(io::ErrorKind::UnexpectedEof.into());
+            }
+        }
+    }
+
+    /// Reads the next packet that isn't a message, messages are kept for
+    /// [`MqttClient::next_message`].
+    async fn receive_queued(&mut self) -> io::Result<Packet> {
+        loop {
+            match self.receive().await? {
+                Packet::Publish(publish) => self.received.push_back(publish),
+                packet => return Ok(packet),
+            }
+        }
+    }
+
+    async fn expect(&mut self, expected: Packet) -> io::Result<()> {
+        match self.receive_queued().await? {
+            packet if packet == expected => Ok(()),
+            packet => Err(unexpected(&packet)),
+        }
+    }
+}
+
+fn unexpected(packet: &Packet) -> io::Error {
+    io::Error::new(
+        io::ErrorKind::InvalidData,
+        format!("unexpected packet from broker: {packet:?}"),
+    )
+}
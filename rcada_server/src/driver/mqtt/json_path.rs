@@ -0,0 +1,70 @@
+use std::str::FromStr;
+
+/// Location of a value in a JSON document, in the subset of JSONPath made
+/// of `$` for the document, `.name` or `['name']` for a member and `[index]`
+/// for an array element, e.g. `$.readings[0].value`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct JsonPath(Vec<Segment>);
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Segment {
+    Member(String),
+    Index(usize),
+}
+
+impl JsonPath {
+    pub fn select<'a>(&self, document: &'a serde_json::Value) -> Option<&'a serde_json::Value> {
+        self.0
+            .iter()
+            .try_fold(document, |value, segment| match segment {
+                Segment::Member(name) => value.get(name),
+                Segment::Index(index) => value.get(index),
+            })
+    }
+}
+
+impl FromStr for JsonPath {
+    type Err = String;
+
+    fn from_str(path: &str) -> Result<Self, String> {
+        let mut rest = path
+            .strip_prefix('$')
+            .ok_or_else(|| format!("{path}: must start with $"))?;
+        let mut segments = Vec::new();
+        while !rest.is_empty() {
+            if let Some(member) = rest.strip_prefix('.') {
+                let end = member.find(['.', '[']).unwrap_or(member.len());
+                if end == 0 {
+                    return Err(format!("{path}: empty member name"));
+                }
+                segments.push(Segment::Member(member[..end].to_string()));
+                rest = &member[end..];
+            } else if let Some(index) = rest.strip_prefix('[') {
+                let end = match index.chars().next() {
+                    Some(quote @ ('\'' | '"')) => {
+                        let end = index[1..]
+                            .find(quote)
+                            .ok_or_else(|| format!("{path}: unclosed quote"))?
+                            + 1;
+                        segments.push(Segment::Member(index[1..end].to_string()));
+                        end + 1
+                    },
+                    _ => {
+                        let end = index.find(']').unwrap_or(index.len());
+                        let position = index[..end]
+                            .parse()
+                            .map_err(|_| format!("{path}: invalid index {}", &index[..end]))?;
+                        segments.push(Segment::Index(position));
+                        end
+                    },
+                };
+                rest = index[end..]
+                    .strip_prefix(']')
+                    .ok_or_else(|| format!("{path}: expected ]"))?;
+            } else {
+                return Err(format!("{path}: expected . or ["));
+            }
+        }
+        Ok(Self(segments))
+    }
+}
@@ -0,0 +1,159 @@
+//! Drivers connecting tags to an MQTT broker, over a minimal MQTT 3.1.1
+//! client.
+
+mod buffer;
+mod client;
+mod json_path;
+pub mod packet;
+pub mod publisher;
+pub mod sparkplug;
+pub mod subscriber;
+
+use std::path::PathBuf;
+
+use serde::{Deserialize, Serialize};
+
+pub use client::MqttClient;
+
+/// Longest string MQTT can carry, client ids and topics included.
+const MAX_STRING_LEN: usize = u16::MAX as usize;
+
+/// Connection to the broker, shared by the MQTT drivers.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct BrokerConfig {
+    /// Address of the broker, e.g. `127.0.0.1:1883`.
+    pub address: String,
+    /// Defaults to `rcada-<driver name>`.
+    #[serde(default)]
+    pub client_id: Option<String>,
+    #[serde(default)]
+    pub username: Option<String>,
+    #[serde(default)]
+    pub password: Option<String>,
+    /// The broker drops the connection after one and a half times this
+    /// without a packet, 0 disables the check.
+    #[serde(default = "default_keep_alive_secs")]
+    pub keep_alive_secs: u16,
+    /// Timeout of connecting and of every exchange with the broker, the
+    /// connection is reopened after it.
+    #[serde(default = "default_timeout_ms")]
+    pub timeout_ms: u64,
+    #[serde(default = "default_reconnect_interval_ms")]
+    pub reconnect_interval_ms: u64,
+    #[serde(default)]
+    pub tls: Option<BrokerTlsConfig>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct BrokerTlsConfig {
+    /// CAs (PEM) the certificate of the broker is checked against.
+    pub ca_file: PathBuf,
+    /// Client certificate (PEM) for brokers that authenticate clients by
+    /// certificate, together with `key_file`.
+    #[serde(default)]
+    pub cert_file: Option<PathBuf>,
+    #[serde(default)]
+    pub key_file: Option<PathBuf>,
+}
+
+fn default_keep_alive_secs() -> u16 {
+    30
+}
+
+fn default_timeout_ms() -> u64 {
+    5000
+}
+
+fn default_reconnect_interval_ms() -> u64 {
+    5000
+}
+
+impl BrokerConfig {
+    pub fn client_id(&self, driver: &str) -> String {
+        self.client_id
+            .clone()
+            .unwrap_or_else(|| format!("rcada-{driver}"))
+    }
+
+    pub fn validate(&self, driver: &str) -> Vec<String> {
+        let mut errors = Vec::new();
+        if self.address.is_empty() {
+            errors.push(format!("driver {driver}: address is empty"));
+        }
+        if self.timeout_ms == 0 {
+            errors.push(format!("driver {driver}: timeout_ms must be positive"));
+        }
+        if self.reconnect_interval_ms == 0 {
+            errors.push(format!(
+                "driver {driver}: reconnect_interval_ms must be positive"
+            ));
+        }
+        let client_id = self.client_id(driver);
+        if client_id.is_empty() || client_id.len() > MAX_STRING_LEN {
+            errors.push(format!(
+                "driver {driver}: client_id must have 1 to {MAX_STRING_LEN} bytes"
+            ));
+        }
+        for (field, value) in [("username", &self.username), ("password", &self.password)] {
+            if value
+                .as_ref()
+                .is_some_and(|value| value.len() > MAX_STRING_LEN)
+            {
+                errors.push(format!("driver {driver}: {field} is too long"));
+            }
+        }
+        if let Some(tls) = &self.tls
+            && tls.cert_file.is_some() != tls.key_file.is_some()
+        {
+            errors.push(format!(
+                "driver {driver}: tls.cert_file and tls.key_file must be set together"
+            ));
+        }
+        errors
+    }
+}
+
+/// Checks that `+` and `#` are whole levels of a topic filter and that `#`
+/// is the last one.
+pub fn check_filter(filter: &str) -> Result<(), String> {
+    if filter.is_empty() || filter.len() > MAX_STRING_LEN {
+        return Err(format!("must have 1 to {MAX_STRING_LEN} bytes"));
+    }
+    let levels: Vec<&str> = filter.split('/').collect();
+    for (i, level) in levels.iter().enumerate() {
+        let wildcard = level.contains(['+', '#']);
+        if wildcard && *level != "+" && *level != "#" {
+            return Err("wildcards must be whole levels".to_string());
+        }
+        if *level == "#" && i != levels.len() - 1 {
+            return Err("# must be the last level".to_string());
+        }
+    }
+    Ok(())
+}
+
+/// Whether `topic` matches a topic filter, where `+` matches one level and
+/// `#` the remaining ones. Wildcards don't match topics starting with `$`.
+pub fn filter_matches(filter: &str, topic: &str) -> bool {
+    if topic.starts_with('$') && filter.starts_with(['+', '#']) {
+        return false;
+    }
+    let mut levels = topic.split('/');
+    for part in filter.split('/') {
+        match part {
+            "#" => return true,
+            "+" => {
+                if levels.next().is_none() {
+                    return false;
+                }
+            },
+            _ => {
+                if levels.next() != Some(part) {
+                    return false;
+                }
+            },
+        }
+    }
+    levels.next().is_none()
+}
@@ -0,0 +1,447 @@
+//! MQTT 3.1.1 control packets and their wire format.
+
+use std::io;
+
+use tokio::io::{AsyncRead, AsyncReadExt};
+
+/// Largest packet accepted from the network, far above anything a tag needs.
+const MAX_PACKET_SIZE: usize = 16 * 1024 * 1024;
+
+const PROTOCOL_NAME: &str = "MQTT";
+const PROTOCOL_LEVEL: u8 = 4;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord)]
+pub enum QoS {
+    #[default]
+    AtMostOnce = 0,
+    AtLeastOnce = 1,
+    ExactlyOnce = 2,
+}
+
+impl TryFrom<u8> for QoS {
+    type Error = io::Error;
+
+    fn try_from(qos: u8) -> io::Result<Self> {
+        match qos {
+            0 => Ok(QoS::AtMostOnce),
+            1 => Ok(QoS::AtLeastOnce),
+            2 => Ok(QoS::ExactlyOnce),
+            _ => Err(invalid(format!("invalid QoS {qos}"))),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Connect {
+    pub client_id: String,
+    pub keep_alive_secs: u16,
+    pub clean_session: bool,
+    pub username: Option<String>,
+    pub password: Option<String>,
+    /// Published by the broker when the connection is lost.
+    pub will: Option<Publish>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Publish {
+    pub topic: String,
+    pub payload: Vec<u8>,
+    pub qos: QoS,
+    pub retain: bool,
+    pub dup: bool,
+    /// Only sent with QoS 1 and 2.
+    pub packet_id: u16,
+}
+
+impl Publish {
+    pub fn new(
+        topic: impl Into<String>,
+        payload: impl Into<Vec<u8>>,
+        qos: QoS,
+        retain: bool,
+    ) -> Self {
+        Self {
+            topic: topic.into(),
+            payload: payload.into(),
+            qos,
+            retain,
+            dup: false,
+            packet_id: 0,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Packet {
+    Connect(Connect),
+    ConnAck {
+        session_present: bool,
+        /// 0 when the connection was accepted.
+        code: u8,
+    },
+    Publish(Publish),
+    PubAck(u16),
+    PubRec(u16),
+    PubRel(u16),
+    PubComp(u16),
+    Subscribe {
+        packet_id: u16,
+        filters: Vec<(String, QoS)>,
+    },
+    SubAck {
+        packet_id: u16,
+        /// Granted QoS per filter, `0x80` for a refused filter.
+        codes: Vec<u8>,
+    },
+    Unsubscribe {
+        packet_id: u16,
+        filters: Vec<String>,
+    },
+    UnsubAck(u16),
+    PingReq,
+    PingResp,
+    Disconnect,
+}
+
+/// Why a broker refused a connection, from the return code of CONNACK.
+pub fn connack_error(code: u8) -> &'static str {
+    match code {
+        1 => "unacceptable protocol version",
+        2 => "client identifier rejected",
+        3 => "server unavailable",
+        4 => "bad user name or password",
+        5 => "not authorized",
+        _ => "connection refused",
+    }
+}
+
+impl Packet {
+    /// Appends the packet to `buf`.
+    pub fn encode(&self, buf: &mut Vec<u8>) {
+        let mut body = Vec::new();
+        let header = match self {
+            Packet::Connect(connect) => {
+                put_str(&mut body, PROTOCOL_NAME);
+                body.push(PROTOCOL_LEVEL);
+                let mut flags = 0;
+                if connect.clean_session {
+                    flags |= 0x02;
+                }
+                if let Some(will) = &connect.will {
+                    flags |= 0x04 | (will.qos as u8) << 3;
+                    if will.retain {
+                        flags |= 0x20;
+                    }
+                }
+                if connect.password.is_some() {
+                    flags |= 0x40;
+                }
+                if connect.username.is_some() {
+                    flags |= 0x80;
+                }
+                body.push(flags);
+                body.extend_from_slice(&connect.keep_alive_secs.to_be_bytes());
+                put_str(&mut body, &connect.client_id);
+                if let Some(will) = &connect.will {
+                    put_str(&mut body, &will.topic);
+                    put_bytes(&mut body, &will.payload);
+                }
+                if let Some(username) = &connect.username {
+                    put_str(&mut body, username);
+                }
+                if let Some(password) = &connect.password {
+                    put_str(&mut body, password);
+                }
+                0x10
+            },
+            Packet::ConnAck {
+                session_present,
+                code,
+            } => {
+                body.extend_from_slice(&[u8::from(*session_present), *code]);
+                0x20
+            },
+            Packet::Publish(publish) => {
+                put_str(&mut body, &publish.topic);
+                if publish.qos != QoS::AtMostOnce {
+                    body.extend_from_slice(&publish.packet_id.to_be_bytes());
+                }
+                body.extend_from_slice(&publish.payload);
+                let mut header = 0x30 | (publish.qos as u8) << 1;
+                if publish.dup {
+                    header |= 0x08;
+                }
+                if publish.retain {
+                    header |= 0x01;
+                }
+                header
+            },
+            Packet::PubAck(id) => {
+                body.extend_from_slice(&id.to_be_bytes());
+                0x40
+            },
+            Packet::PubRec(id) => {
+                body.extend_from_slice(&id.to_be_bytes());
+                0x50
+            },
+            Packet::PubRel(id) => {
+                body.extend_from_slice(&id.to_be_bytes());
+                0x62
+            },
+            Packet::PubComp(id) => {
+                body.extend_from_slice(&id.to_be_bytes());
+                0x70
+            },
+            Packet::Subscribe {
+                packet_id,
+                filters,
+            } => {
+                body.extend_from_slice(&packet_id.to_be_bytes());
+                for (filter, qos) in filters {
+                    put_str(&mut body, filter);
+                    body.push(*qos as u8);
+                }
+                0x82
+            },
+            Packet::SubAck {
+                packet_id,
+                codes,
+            } => {
+                body.extend_from_slice(&packet_id.to_be_bytes());
+                body.extend_from_slice(codes);
+                0x90
+            },
+            Packet::Unsubscribe {
+                packet_id,
+                filters,
+            } => {
+                body.extend_from_slice(&packet_id.to_be_bytes());
+                for filter in filters {
+                    put_str(&mut body, filter);
+                }
+                0xa2
+            },
+            Packet::UnsubAck(id) => {
+                body.extend_from_slice(&id.to_be_bytes());
+                0xb0
+            },
+            Packet::PingReq => 0xc0,
+            Packet::PingResp => 0xd0,
+            Packet::Disconnect => 0xe0,
+        };
+        buf.push(header);
+        let mut length = body.len();
+        loop {
+            let mut byte = (length % 128) as u8;
+            length /= 128;
+            if length > 0 {
+                byte |= 0x80;
+            }
+            buf.push(byte);
+            if length == 0 {
+                break;
+            }
+        }
+        buf.extend_from_slice(&body);
+    }
+
+    /// Reads the next packet from a connection.
+    pub async fn read(reader: &mut (impl AsyncRead + Unpin)) -> io::Result<Packet> {
+        let header = reader.read_u8().await?;
+        let mut length = 0usize;
+        for shift in 0..4 {
+            let byte = reader.read_u8().await?;
+            length |= usize::from(byte & 0x7f) << (7 * shift);
+            if byte & 0x80 == 0 {
+                break;
+            }
+            if shift == 3 {
+                return Err(invalid("malformed remaining length"));
+            }
+        }
+        if length > MAX_PACKET_SIZE {
+            return Err(invalid(format!("packet of {length} bytes is too large")));
+        }
+        let mut body = vec![0u8; length];
+        reader.read_exact(&mut body).await?;
+        Packet::parse(header, &body)
+    }
+
+    /// Decodes the packet at the start of `buf` and returns it with its
+    /// length, `None` if `buf` ends before the packet does.
+    pub fn decode(buf: &[u8]) -> io::Result<Option<(Packet, usize)>> {
+        let Some(&header) = buf.first() else {
+            return Ok(None);
+        };
+        let mut length = 0usize;
+        let mut offset = 1;
+        loop {
+            let Some(&byte) = buf.get(offset) else {
+                return Ok(None);
+            };
+            length |= usize::from(byte & 0x7f) << (7 * (offset - 1));
+            offset += 1;
+            if byte & 0x80 == 0 {
+                break;
+            }
+            if offset > 4 {
+                return Err(invalid("malformed remaining length"));
+            }
+        }
+        if length > MAX_PACKET_SIZE {
+            return Err(invalid(format!("packet of {length} bytes is too large")));
+        }
+        let Some(body) = buf.get(offset..offset + length) else {
+            return Ok(None);
+        };
+        Ok(Some((Packet::parse(header, body)?, offset + length)))
+    }
+
+    fn parse(header: u8, body: &[u8]) -> io::Result<Packet> {
+        let mut body = Reader(body);
+        let packet = match header >> 4 {
+            1 => {
+                let protocol = body.string()?;
+                let level = body.u8()?;
+                if protocol != PROTOCOL_NAME || level != PROTOCOL_LEVEL {
+                    return Err(invalid(format!(
+                        "unsupported protocol {protocol} level {level}"
+                    )));
+                }
+                let flags = body.u8()?;
+                let keep_alive_secs = body.u16()?;
+                let client_id = body.string()?;
+                let will = if flags & 0x04 != 0 {
+                    let topic = body.string()?;
+                    let payload = body.bytes()?.to_vec();
+                    Some(Publish::new(
+                        topic,
+                        payload,
+                        QoS::try_from((flags >> 3) & 0x03)?,
+                        flags & 0x20 != 0,
+                    ))
+                } else {
+                    None
+                };
+                let username = (flags & 0x80 != 0).then(|| body.string()).transpose()?;
+                let password = (flags & 0x40 != 0).then(|| body.string()).transpose()?;
+                Packet::Connect(Connect {
+                    client_id,
+                    keep_alive_secs,
+                    clean_session: flags & 0x02 != 0,
+                    username,
+                    password,
+                    will,
+                })
+            },
+            2 => Packet::ConnAck {
+                session_present: body.u8()? & 0x01 != 0,
+                code: body.u8()?,
+            },
+            3 => {
+                let qos = QoS::try_from((header >> 1) & 0x03)?;
+                let topic = body.string()?;
+                let packet_id = if qos == QoS::AtMostOnce {
+                    0
+                } else {
+                    body.u16()?
+                };
+                Packet::Publish(Publish {
+                    topic,
+                    payload: body.rest().to_vec(),
+                    qos,
+                    retain: header & 0x01 != 0,
+                    dup: header & 0x08 != 0,
+                    packet_id,
+                })
+            },
+            4 => Packet::PubAck(body.u16()?),
+            5 => Packet::PubRec(body.u16()?),
+            6 => Packet::PubRel(body.u16()?),
+            7 => Packet::PubComp(body.u16()?),
+            8 => {
+                let packet_id = body.u16()?;
+                let mut filters = Vec::new();
+                while !body.0.is_empty() {
+                    let filter = body.string()?;
+                    filters.push((filter, QoS::try_from(body.u8()?)?));
+                }
+                Packet::Subscribe {
+                    packet_id,
+                    filters,
+                }
+            },
+            9 => Packet::SubAck {
+                packet_id: body.u16()?,
+                codes: body.rest().to_vec(),
+            },
+            10 => {
+                let packet_id = body.u16()?;
+                let mut filters = Vec::new();
+                while !body.0.is_empty() {
+                    filters.push(body.string()?);
+                }
+                Packet::Unsubscribe {
+                    packet_id,
+                    filters,
+                }
+            },
+            11 => Packet::UnsubAck(body.u16()?),
+            12 => Packet::PingReq,
+            13 => Packet::PingResp,
+            14 => Packet::Disconnect,
+            kind => return Err(invalid(format!("unknown packet type {kind}"))),
+        };
+        Ok(packet)
+    }
+}
+
+fn invalid(message: impl Into<String>) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message.into())
+}
+
+fn put_bytes(buf: &mut Vec<u8>, bytes: &[u8]) {
+    // Longer strings are refused by validation before they get here
+    let length = u16::try_from(bytes.len()).unwrap_or(u16::MAX);
+    buf.extend_from_slice(&length.to_be_bytes());
+    buf.extend_from_slice(&bytes[..usize::from(length)]);
+}
+
+fn put_str(buf: &mut Vec<u8>, s: &str) {
+    put_bytes(buf, s.as_bytes());
+}
+
+struct Reader<'a>(&'a [u8]);
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, n: usize) -> io::Result<&'a [u8]> {
+        if self.0.len() < n {
+            return Err(invalid("packet too short"));
+        }
+        let (taken, rest) = self.0.split_at(n);
+        self.0 = rest;
+        Ok(taken)
+    }
+
+    fn u8(&mut self) -> io::Result<u8> {
+        Ok(self.take(1)?[0])
+    }
+
+    fn u16(&mut self) -> io::Result<u16> {
+        let bytes = self.take(2)?;
+        Ok(u16::from_be_bytes([bytes[0], bytes[1]]))
+    }
+
+    fn bytes(&mut self) -> io::Result<&'a [u8]> {
+        let length = self.u16()?;
+        self.take(usize::from(length))
+    }
+
+    fn string(&mut self) -> io::Result<String> {
+        String::from_utf8(self.bytes()?.to_vec()).map_err(|_| invalid("string is not UTF-8"))
+    }
+
+    fn rest(&mut self) -> &'a [u8] {
+        std::mem::take(&mut self.0)
+    }
+}
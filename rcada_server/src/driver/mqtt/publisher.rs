@@ -0,0 +1,678 @@
+use std::{collections::BTreeMap, io, path::PathBuf, time::Duration};
+
+use chrono::Utc;
+use ractor::{Actor, ActorProcessingErr, ActorRef};
+use serde::{Deserialize, Serialize};
+use tokio::{
+    sync::broadcast::{self, error::RecvError},
+    task::JoinHandle,
+    time::timeout,
+};
+
+use rcada_core::{tag::Tag, value::Value};
+
+use crate::{
+    access,
+    actor::{self, Mailbox},
+    driver::{DriverStatus, SharedDriverStatus},
+    metrics::metrics,
+};
+
+use super::{
+    BrokerConfig, MAX_STRING_LEN, MqttClient,
+    buffer::DiskBuffer,
+    packet::{Publish, QoS},
+};
+
+/// Publishes value changes of tags to an MQTT broker. While the broker is
+/// unreachable the messages are buffered on disk and sent once it's back.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct MqttPublisherConfig {
+    pub name: String,
+    #[serde(flatten)]
+    pub broker: BrokerConfig,
+    /// Topic of a tag, with the placeholders `{tag}`, `{label.NAME}` for a
+    /// label of the tag and the keys of `variables`, e.g. `rcada/{site}/{tag}`.
+    #[serde(default = "default_topic")]
+    pub topic: String,
+    #[serde(default)]
+    pub variables: BTreeMap<String, String>,
+    /// Patterns of the published tags, `*` and `?` are wildcards.
+    #[serde(default = "default_tags")]
+    pub tags: Vec<String>,
+    #[serde(default)]
+    pub format: PayloadFormat,
+    /// 0, 1 or 2.
+    #[serde(default = "default_qos")]
+    pub qos: u8,
+    /// Ask the broker to keep the last value of every topic for new
+    /// subscribers.
+    #[serde(default = "default_retain")]
+    pub retain: bool,
+    /// Bytes of messages kept on disk while the broker is unreachable, 0
+    /// disables the buffer. When it's full newer changes are dropped and the
+    /// current values are published after reconnecting.
+    #[serde(default = "default_buffer_size")]
+    pub buffer_size: u64,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum PayloadFormat {
+    /// `{"tag", "value", "data_type", "unit", "quality", "timestamp"}` with an
+    /// RFC 3339 timestamp.
+    #[default]
+    Json,
+    /// `{"v", "q", "t"}` with the timestamp in milliseconds since the epoch.
+    Compact,
+}
+
+fn default_topic() -> String {
+    "rcada/{tag}".to_string()
+}
+
+fn default_tags() -> Vec<String> {
+    vec!["*".to_string()]
+}
+
+fn default_qos() -> u8 {
+    1
+}
+
+fn default_retain() -> bool {
+    true
+}
+
+fn default_buffer_size() -> u64 {
+    16 * 1024 * 1024
+}
+
+impl MqttPublisherConfig {
+    pub fn validate(&self) -> Vec<String> {
+        let mut errors = Vec::new();
+        if self.name.is_empty() {
+            errors.push("driver name is empty".to_string());
+        }
+        errors.extend(self.broker.validate(&self.name));
+        if self.qos > 2 {
+            errors.push(format!("driver {}: qos must be 0, 1 or 2", self.name));
+        }
+        if let Err(e) = self.check_topic() {
+            errors.push(format!("driver {}: topic: {}", self.name, e));
+        }
+        errors
+    }
+
+    fn check_topic(&self) -> Result<(), String> {
+        if self.topic.is_empty() || self.topic.len() > MAX_STRING_LEN {
+            return Err(format!("must have 1 to {MAX_STRING_LEN} bytes"));
+        }
+        if self.topic.contains(['+', '#']) {
+            return Err("wildcards can't be published to".to_string());
+        }
+        let mut rest = self.topic.as_str();
+        while let Some(start) = rest.find('{') {
+            let end = rest[start..].find('}').ok_or("unclosed placeholder")? + start;
+            let name = &rest[start + 1..end];
+            let known = name == "tag"
+                || name
+                    .strip_prefix("label.")
+                    .is_some_and(|label| !label.is_empty())
+                || self.variables.contains_key(name);
+            if !known {
+                return Err(format!("unknown placeholder {{{name}}}"));
+            }
+            rest = &rest[end + 1..];
+        }
+        if rest.contains('}') {
+            return Err("unopened placeholder".to_string());
+        }
+        Ok(())
+    }
+
+    /// Topic of `tag`, `None` if it lacks a label the template refers to.
+    /// Substituted values can't add wildcards, they are replaced by `_`.
+    fn topic(&self, tag: &Tag) -> Option<String> {
+        let mut topic = String::new();
+        let mut rest = self.topic.as_str();
+        while let Some(start) = rest.find('{') {
+            let end = rest[start..].find('}')? + start;
+            let name = &rest[start + 1..end];
+            let value = match name.strip_prefix("label.") {
+                _ if name == "tag" => tag.name.as_str(),
+                Some(label) => tag.meta.labels.get(label)?,
+                None => self.variables.get(name)?,
+            };
+            topic.push_str(&rest[..start]);
+            topic.extend(value.chars().map(|c| match c {
+                '+' | '#' | '\0' => '_',
+                c => c,
+            }));
+            rest = &rest[end + 1..];
+        }
+        topic.push_str(rest);
+        Some(topic)
+    }
+
+    fn payload(&self, tag: &Tag) -> Vec<u8> {
+        let value = json_value(&tag.value.value);
+        let payload = match self.format {
+            PayloadFormat::Json => serde_json::json!({
+                "tag": tag.name,
+                "value": value,
+                "data_type": tag.meta.data_type,
+                "unit": tag.meta.unit,
+                "quality": tag.value.quality,
+                "timestamp": tag.value.timestamp,
+            }),
+            PayloadFormat::Compact => {
+                let mut payload = serde_json::json!({
+                    "v": value,
+                    "q": tag.value.quality,
+                });
+                if let Some(timestamp) = tag.value.timestamp {
+                    payload["t"] = timestamp.timestamp_millis().into();
+                }
+                payload
+            },
+        };
+        payload.to_string().into_bytes()
+    }
+
+    fn publishes(&self, tag: &str) -> bool {
+        self.tags
+            .iter()
+            .any(|pattern| access::matches(pattern, tag))
+    }
+
+    fn timeout(&self) -> Duration {
+        Duration::from_millis(self.broker.timeout_ms)
+    }
+}
+
+/// Value as a plain JSON value. Floats are written with the digits of the
+/// `f32`, so `0.1` isn't sent as `0.10000000149011612`.
+fn json_value(value: &Value) -> serde_json::Value {
+    match value {
+        Value::Integer(v) => (*v).into(),
+        Value::Float(v) => v
+            .to_string()
+            .parse::<f64>()
+            .ok()
+            .and_then(serde_json::Number::from_f64)
+            .map_or(serde_json::Value::Null, serde_json::Value::Number),
+        Value::Boolean(v) => (*v).into(),
+        Value::String(v) => v.as_str().into(),
+    }
+}
+
+/// Publishes the value updates of the tag repository.
+pub struct MqttPublisher;
+
+pub struct MqttPublisherArguments {
+    pub config: MqttPublisherConfig,
+    pub tag_repo: ActorRef<actor::tag::Message>,
+    pub status: SharedDriverStatus,
+    /// The buffer is kept in `<data_dir>/mqtt/<driver name>.buffer`.
+    pub data_dir: PathBuf,
+}
+
+pub struct MqttPublisherState {
+    config: MqttPublisherConfig,
+    tag_repo: ActorRef<actor::tag::Message>,
+    status: SharedDriverStatus,
+    client: Option<MqttClient>,
+    buffer: Option<DiskBuffer>,
+    /// Changes were dropped, the current values are published once connected.
+    resync: bool,
+    updates: JoinHandle<()>,
+}
+
+#[derive(Debug)]
+pub enum Message {
+    ValueUpdate(Box<Tag>),
+    /// Connects if needed and sends what is waiting.
+    Tick,
+    /// Updates were lost before they reached this actor.
+    Resync,
+}
+
+#[cfg(feature = "cluster")]
+impl ractor::Message for Message {}
+
+#[cfg_attr(feature = "async-trait", ractor::async_trait)]
+impl Actor for MqttPublisher {
+    type Msg = Message;
+    type State = MqttPublisherState;
+    type Arguments = MqttPublisherArguments;
+
+    async fn pre_start(
+        &self,
+        myself: ActorRef<Self::Msg>,
+        args: Self::Arguments,
+    ) -> Result<Self::State, ActorProcessingErr> {
+        let config = args.config;
+        let buffer = if config.buffer_size > 0 {
+            let path = args
+                .data_dir
+                .join("mqtt")
+                .join(format!("{}.buffer", config.name));
+            let buffer = DiskBuffer::open(path, config.buffer_size)?;
+            if !buffer.is_empty() {
+                tracing::info!(
+                    "driver {}: {} buffered messages from the previous run",
+                    config.name,
+                    buffer.len()
+                );
+            }
+            Some(buffer)
+        } else {
+            None
+        };
+
+        let (command, mut reply) = actor::tag::Message::subscribe();
+        args.tag_repo.enqueue(command)?;
+        let receiver = reply
+            .recv()
+            .await
+            .ok_or("tag repository didn't answer the subscription")?;
+        let updates = tokio::spawn(forward_updates(receiver, myself.clone()));
+
+        tracing::info!(
+            "driver {}: publishing to {} as {}",
+            config.name,
+            config.broker.address,
+            config.topic
+        );
+        myself.send_interval(
+            Duration::from_millis(config.broker.reconnect_interval_ms),
+            || Message::Tick,
+        );
+        myself.send_message(Message::Tick)?;
+        Ok(MqttPublisherState {
+            config,
+            tag_repo: args.tag_repo,
+            status: args.status,
+            client: None,
+            buffer,
+            // Brings retained values up to date with changes made while stopped
+            resync: true,
+            updates,
+        })
+    }
+
+    async fn post_stop(
+        &self,
+        _myself: ActorRef<Self::Msg>,
+        state: &mut Self::State,
+    ) -> Result<(), ActorProcessingErr> {
+        state.updates.abort();
+        if let Some(client) = state.client.take() {
+            let _ = timeout(state.config.timeout(), client.disconnect()).await;
+        }
+        Ok(())
+    }
+
+    async fn handle(
+        &self,
+        _myself: ActorRef<Self::Msg>,
+        message: Self::Msg,
+        state: &mut Self::State,
+    ) -> Result<(), ActorProcessingErr> {
+        match message {
+            Message::ValueUpdate(tag) => {
+                if state.config.publishes(&tag.name) {
+                    state.send(&tag).await;
+                }
+            },
+            Message::Tick => {
+                let result = state.flush().await;
+                if let Err(e) = &result {
+                    tracing::warn!("driver {}: {}", state.config.name, e);
+                    state.client = None;
+                }
+                *state.status.lock().unwrap_or_else(|e| e.into_inner()) = DriverStatus {
+                    connected: state.client.is_some(),
+                    last_poll: Some(Utc::now()),
+                    last_error: result.err().map(|e| e.to_string()),
+                };
+            },
+            Message::Resync => state.resync = true,
+        }
+        let buffered = state.buffer.as_ref().map_or(0, DiskBuffer::len);
+        metrics()
+            .driver_buffered
+            .with_label_values(&[state.config.name.as_str()])
+            .set(buffered as i64);
+        Ok(())
+    }
+}
+
+/// Passes the updates of the tag repository to the publisher.
+async fn forward_updates(mut updates: broadcast::Receiver<Tag>, publisher: ActorRef<Message>) {
+    loop {
+        let message = match updates.recv().await {
+            Ok(tag) => Message::ValueUpdate(Box::new(tag)),
+            Err(RecvError::Lagged(_)) => Message::Resync,
+            Err(RecvError::Closed) => break,
+        };
+        if publisher.send_message(message).is_err() {
+            break;
+        }
+    }
+}
+
+impl MqttPublisherState {
+    fn message(&self, tag: &Tag) -> Option<Publish> {
+        let Some(topic) = self.config.topic(tag) else {
+            tracing::warn!(
+                "driver {}: tag {} lacks a label of the topic template",
+                self.config.name,
+                tag.name
+            );
+            return None;
+        };
+        let qos = QoS::try_from(self.config.qos).unwrap_or_default();
+        Some(Publish::new(
+            topic,
+            self.config.payload(tag),
+            qos,
+            self.config.retain,
+        ))
+    }
+
+    /// Publishes the value of `tag` now, or buffers it if the broker isn't
+    /// connected or older messages are still waiting.
+    async fn send(&mut self, tag: &Tag) {
+        let Some(publish) = self.message(tag) else {
+            return;
+        };
+        let waiting = self
+            .buffer
+            .as_ref()
+            .is_some_and(|buffer| !buffer.is_empty());
+        if self.client.is_some() && !waiting {
+            match self.publish(publish.clone()).await {
+                Ok(()) => return,
+                Err(e) => {
+                    tracing::warn!("driver {}: publish failed: {}", self.config.name, e);
+                    self.client = None;
+                    let mut status = self.status.lock().unwrap_or_else(|e| e.into_inner());
+                    status.connected = false;
+                    status.last_error = Some(e.to_string());
+                },
+            }
+        }
+
+        let stored = match self.buffer.as_mut().map(|buffer| buffer.push(publish)) {
+            Some(Ok(stored)) => stored,
+            Some(Err(e)) => {
+                tracing::error!(
+                    "driver {}: failed to buffer message: {}",
+                    self.config.name,
+                    e
+                );
+                false
+            },
+            None => false,
+        };
+        if !stored && !self.resync {
+            tracing::warn!(
+                "driver {}: dropping changes until the broker is reachable",
+                self.config.name
+            );
+            self.resync = true;
+        }
+    }
+
+    async fn publish(&mut self, publish: Publish) -> io::Result<()> {
+        let client = self
+            .client
+            .as_mut()
+            .ok_or_else(|| io::Error::from(io::ErrorKind::NotConnected))?;
+        timeout(self.config.timeout(), client.publish(publish))
+            .await
+            .map_err(|_| io::Error::new(io::ErrorKind::TimedOut, "broker didn't answer"))??;
+        metrics()
+            .driver_published
+            .with_label_values(&[self.config.name.as_str()])
+            .inc();
+        Ok(())
+    }
+
+    /// Connects or keeps the connection alive, then sends the buffered
+    /// messages and, if changes were dropped, the current values.
+    async fn flush(&mut self) -> io::Result<()> {
+        let deadline = self.config.timeout();
+        match self.client.as_mut() {
+            Some(client) => timeout(deadline, client.keep_alive())
+                .await
+                .map_err(|_| io::Error::new(io::ErrorKind::TimedOut, "ping timed out"))??,
+            None => {
+                let client_id = self.config.broker.client_id(&self.config.name);
+                let client = timeout(
+                    deadline,
+                    MqttClient::connect(&self.config.broker, client_id, None),
+                )
+                .await
+                .map_err(|_| io::Error::new(io::ErrorKind::TimedOut, "connect timed out"))??;
+                tracing::info!(
+                    "driver {}: connected to {}",
+                    self.config.name,
+                    self.config.broker.address
+                );
+                self.client = Some(client);
+            },
+        }
+
+        if let Some(buffer) = &self.buffer
+            && !buffer.is_empty()
+        {
+            let pending = buffer.read()?;
+            let mut sent = 0;
+            let mut result = Ok(());
+            for publish in pending {
+                result = self.publish(publish).await;
+                if result.is_err() {
+                    break;
+                }
+                sent += 1;
+            }
+            if let Some(buffer) = self.buffer.as_mut() {
+                buffer.remove(sent)?;
+            }
+            result?;
+            tracing::info!(
+                "driver {}: sent {} buffered messages",
+                self.config.name,
+                sent
+            );
+        }
+
+        if self.resync {
+            let (command, mut reply) = actor::tag::Message::get_all_tags();
+            self.tag_repo.enqueue(command).map_err(io::Error::other)?;
+            let tags = reply.recv().await.unwrap_or_default();
+            // Tags that were never written have no value worth publishing
+            let current: Vec<Publish> = tags
+                .iter()
+                .filter(|tag| tag.value.timestamp.is_some() && self.config.publishes(&tag.name))
+                .filter_map(|tag| self.message(tag))
+                .collect();
+            for publish in current {
+                self.publish(publish).await?;
+            }
+            self.resync = false;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rcada_core::{
+        tag::{Quality, TagMeta, TagValue},
+        unit::Unit,
+        value::DataType,
+    };
+    use tokio::{io::AsyncWriteExt, net::TcpListener, sync::mpsc};
+
+    use super::*;
+    use crate::{
+        actor::tag::TagRepositoryActor, audit::AuditLog, driver::mqtt::packet::Packet,
+        repository::tag::inmemory::TagStorage,
+    };
+
+    /// Stand-in broker acknowledging everything and passing on what is
+    /// published to it.
+    async fn run_broker(listener: TcpListener, published: mpsc::UnboundedSender<Publish>) {
+        while let Ok((mut stream, _)) = listener.accept().await {
+            while let Ok(packet) = Packet::read(&mut stream).await {
+                let reply = match packet {
+                    Packet::Connect(_) => Packet::ConnAck {
+                        session_present: false,
+                        code: 0,
+                    },
+                    Packet::Publish(publish) => {
+                        let reply = match publish.qos {
+                            QoS::AtMostOnce => None,
+                            QoS::AtLeastOnce => Some(Packet::PubAck(publish.packet_id)),
+                            QoS::ExactlyOnce => Some(Packet::PubRec(publish.packet_id)),
+                        };
+                        let _ = published.send(publish);
+                        let Some(reply) = reply else {
+                            continue;
+                        };
+                        reply
+                    },
+                    Packet::PubRel(id) => Packet::PubComp(id),
+                    Packet::PingReq => Packet::PingResp,
+                    _ => break,
+                };
+                let mut buf = Vec::new();
+                reply.encode(&mut buf);
+                if stream.write_all(&buf).await.is_err() {
+                    break;
+                }
+            }
+        }
+    }
+
+    async fn write(tag_repo: &ActorRef<actor::tag::Message>, value: f32) {
+        let (command, mut reply) = actor::tag::Message::update_tag_value(
+            "boiler/temperature",
+            TagValue {
+                value: Value::Float(value),
+                timestamp: Some(Utc::now()),
+                quality: Quality::Good,
+            },
+        );
+        tag_repo.enqueue(command).unwrap();
+        reply.recv().await.unwrap().unwrap();
+    }
+
+    async fn next_value(published: &mut mpsc::UnboundedReceiver<Publish>) -> serde_json::Value {
+        let publish = timeout(Duration::from_secs(5), published.recv())
+            .await
+            .expect("message published in time")
+            .unwrap();
+        assert_eq!(publish.topic, "rcada/plant1/boiler/temperature");
+        assert_eq!(publish.qos, QoS::ExactlyOnce);
+        assert!(publish.retain);
+        let payload: serde_json::Value = serde_json::from_slice(&publish.payload).unwrap();
+        assert_eq!(payload["q"], "good");
+        payload["v"].clone()
+    }
+
+    #[tokio::test]
+    async fn buffers_changes_until_the_broker_is_reachable() {
+        let data_dir = std::env::temp_dir().join(format!("rcada-mqtt-{}", uuid::Uuid::new_v4()));
+        let (tag_repo, _) = Actor::spawn(
+            None,
+            TagRepositoryActor::default(),
+            (TagStorage::default(), AuditLog::disabled()),
+        )
+        .await
+        .unwrap();
+        let (command, mut reply) = actor::tag::Message::create_tag(
+            "boiler/temperature",
+            TagMeta::new(Unit::Celsius, DataType::Float),
+        );
+        tag_repo.enqueue(command).unwrap();
+        reply.recv().await.unwrap();
+
+        // Reserve a port for the broker, which isn't running yet
+        let address = TcpListener::bind("127.0.0.1:0")
+            .await
+            .unwrap()
+            .local_addr()
+            .unwrap();
+        let config = MqttPublisherConfig {
+            name: "cloud".to_string(),
+            broker: BrokerConfig {
+                address: address.to_string(),
+                client_id: None,
+                username: None,
+                password: None,
+                keep_alive_secs: 30,
+                timeout_ms: 1000,
+                reconnect_interval_ms: 50,
+                tls: None,
+            },
+            topic: "rcada/{site}/{tag}".to_string(),
+            variables: BTreeMap::from([("site".to_string(), "plant1".to_string())]),
+            tags: default_tags(),
+            format: PayloadFormat::Compact,
+            qos: 2,
+            retain: true,
+            buffer_size: default_buffer_size(),
+        };
+        assert!(config.validate().is_empty());
+        let (publisher, _) = Actor::spawn(
+            None,
+            MqttPublisher,
+            MqttPublisherArguments {
+                config,
+                tag_repo: tag_repo.clone(),
+                status: SharedDriverStatus::default(),
+                data_dir: data_dir.clone(),
+            },
+        )
+        .await
+        .unwrap();
+
+        write(&tag_repo, 1.5).await;
+        write(&tag_repo, 2.5).await;
+        let buffer = data_dir.join("mqtt").join("cloud.buffer");
+        let deadline = tokio::time::Instant::now() + Duration::from_secs(5);
+        while fs_len(&buffer) == 0 {
+            assert!(
+                tokio::time::Instant::now() < deadline,
+                "changes not buffered"
+            );
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+
+        let (sender, mut published) = mpsc::unbounded_channel();
+        tokio::spawn(run_broker(
+            TcpListener::bind(address).await.unwrap(),
+            sender,
+        ));
+        // Buffered changes in order, then the current value
+        assert_eq!(next_value(&mut published).await, 1.5);
+        assert_eq!(next_value(&mut published).await, 2.5);
+        assert_eq!(next_value(&mut published).await, 2.5);
+        assert_eq!(fs_len(&buffer), 0);
+
+        write(&tag_repo, 0.1).await;
+        assert_eq!(next_value(&mut published).await, 0.1);
+
+        publisher.stop(None);
+        tag_repo.stop(None);
+        let _ = std::fs::remove_dir_all(data_dir);
+    }
+
+    fn fs_len(path: &std::path::Path) -> u64 {
+        std::fs::metadata(path).map_or(0, |m| m.len())
+    }
+}
@@ -0,0 +1,1375 @@
+use std::{
+    collections::{HashMap, VecDeque},
+    io,
+    time::Duration,
+};
+
+use chrono::{DateTime, Utc};
+use prost::Message as _;
+use ractor::{Actor, ActorProcessingErr, ActorRef};
+use serde::{Deserialize, Serialize};
+use tokio::{
+    sync::{
+        broadcast::{self, error::RecvError},
+        mpsc,
+    },
+    task::JoinHandle,
+    time::timeout,
+};
+
+use rcada_core::{
+    tag::{Quality, Tag, TagMeta, TagName, TagValue},
+    unit::Unit,
+    value::{DataType, Value},
+};
+
+use crate::{
+    actor::{self, Mailbox},
+    audit::Origin,
+    driver::SharedDriverStatus,
+    metrics::metrics,
+    repository::tag::{CreateTagResult, UpdateValueResult},
+};
+
+use super::{
+    BrokerConfig, MqttClient,
+    packet::{Publish, QoS},
+};
+
+// The value variants keep the field names of the Tahu schema
+#[allow(clippy::enum_variant_names)]
+mod proto {
+    tonic::include_proto!("org.eclipse.tahu.protobuf");
+}
+
+use proto::{
+    Payload,
+    payload::{Metric, metric::Value as MetricValue},
+};
+
+/// First level of every Sparkplug B topic.
+const NAMESPACE: &str = "spBv1.0";
+
+/// Metric of node births and deaths telling the sessions of a node apart.
+const BD_SEQ: &str = "bdSeq";
+
+const REBIRTH: &str = "Node Control/Rebirth";
+
+/// Own writes remembered per tag to tell them from writes through the API.
+const MAX_PENDING_WRITES: usize = 64;
+
+/// Sparkplug B data types that tags can hold.
+mod datatype {
+    pub const INT8: u32 = 1;
+    pub const INT16: u32 = 2;
+    pub const INT32: u32 = 3;
+    pub const INT64: u32 = 4;
+    pub const UINT8: u32 = 5;
+    pub const UINT16: u32 = 6;
+    pub const UINT32: u32 = 7;
+    pub const UINT64: u32 = 8;
+    pub const FLOAT: u32 = 9;
+    pub const DOUBLE: u32 = 10;
+    pub const BOOLEAN: u32 = 11;
+    pub const STRING: u32 = 12;
+    pub const DATE_TIME: u32 = 13;
+    pub const TEXT: u32 = 14;
+    pub const UUID: u32 = 15;
+}
+
+/// Acts as a Sparkplug B host application: creates tags from the birth
+/// certificates of edge nodes and devices, writes their data into the tags
+/// and sends commands for tags written through the API.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SparkplugConfig {
+    pub name: String,
+    #[serde(flatten)]
+    pub broker: BrokerConfig,
+    /// Groups of the edge nodes, `+` for every group.
+    #[serde(default = "default_groups")]
+    pub groups: Vec<String>,
+    /// Id of the host application. If set, the online state of the host is
+    /// published on `spBv1.0/STATE/<host_id>`, which edge nodes may wait for.
+    #[serde(default)]
+    pub host_id: Option<String>,
+    /// Prefix of the tag names, which continue with
+    /// `<group>/<node>[/<device>]/<metric>`.
+    #[serde(default = "default_tag_prefix")]
+    pub tag_prefix: String,
+    /// Send NCMD/DCMD messages for tags written through the API. Without
+    /// commands the tags are created read-only.
+    #[serde(default = "default_commands")]
+    pub commands: bool,
+}
+
+fn default_groups() -> Vec<String> {
+    vec!["+".to_string()]
+}
+
+fn default_tag_prefix() -> String {
+    "sparkplug/".to_string()
+}
+
+fn default_commands() -> bool {
+    true
+}
+
+impl SparkplugConfig {
+    pub fn validate(&self) -> Vec<String> {
+        let mut errors = Vec::new();
+        if self.name.is_empty() {
+            errors.push("driver name is empty".to_string());
+        }
+        errors.extend(self.broker.validate(&self.name));
+        if self.groups.is_empty() {
+            errors.push(format!("driver {}: groups is empty", self.name));
+        }
+        for group in &self.groups {
+            if group != "+" && !valid_id(group) {
+                errors.push(format!("driver {}: invalid group {group}", self.name));
+            }
+        }
+        if let Some(host_id) = &self.host_id
+            && !valid_id(host_id)
+        {
+            errors.push(format!("driver {}: invalid host_id {host_id}", self.name));
+        }
+        errors
+    }
+
+    fn filters(&self) -> Vec<(String, QoS)> {
+        self.groups
+            .iter()
+            .map(|group| (format!("{NAMESPACE}/{group}/#"), QoS::AtLeastOnce))
+            .collect()
+    }
+
+    /// Message of the host state, retained for edge nodes connecting later.
+    fn state(&self, online: bool, timestamp: i64) -> Option<Publish> {
+        self.host_id.as_ref().map(|host_id| {
+            Publish::new(
+                format!("{NAMESPACE}/STATE/{host_id}"),
+                serde_json::json!({ "online": online, "timestamp": timestamp }).to_string(),
+                QoS::AtLeastOnce,
+                true,
+            )
+        })
+    }
+}
+
+/// Group, edge node and device ids are single topic levels without wildcards.
+fn valid_id(id: &str) -> bool {
+    !id.is_empty() && !id.contains(['/', '+', '#'])
+}
+
+/// Data type of the tags of a Sparkplug data type, `None` for types tags
+/// can't hold, like data sets and templates.
+fn data_type(datatype: u32) -> Option<DataType> {
+    use datatype::*;
+
+    match datatype {
+        INT8..=UINT64 | DATE_TIME => Some(DataType::Integer),
+        FLOAT | DOUBLE => Some(DataType::Float),
+        BOOLEAN => Some(DataType::Boolean),
+        STRING | TEXT | UUID => Some(DataType::String),
+        _ => None,
+    }
+}
+
+/// Value of a metric, `None` if it doesn't match `datatype`.
+fn metric_value(value: &MetricValue, datatype: u32) -> Option<Value> {
+    use datatype::*;
+
+    match (datatype, value) {
+        (INT8 | INT16 | INT32, MetricValue::IntValue(n)) => {
+            Some(Value::Integer((*n as i32).into()))
+        },
+        (UINT8 | UINT16 | UINT32, MetricValue::IntValue(n)) => Some(Value::Integer((*n).into())),
+        (INT64, MetricValue::LongValue(n)) => Some(Value::Integer(*n as i64)),
+        (UINT64 | DATE_TIME, MetricValue::LongValue(n)) => {
+            i64::try_from(*n).ok().map(Value::Integer)
+        },
+        (FLOAT, MetricValue::FloatValue(n)) => Some(Value::Float(*n)),
+        (DOUBLE, MetricValue::DoubleValue(n)) => Some(Value::Float(*n as f32)),
+        (BOOLEAN, MetricValue::BooleanValue(b)) => Some(Value::Boolean(*b)),
+        (STRING | TEXT | UUID, MetricValue::StringValue(s)) => Some(Value::String(s.clone())),
+        _ => None,
+    }
+}
+
+/// Metric value of a tag value, `None` if it's out of the range of
+/// `datatype`.
+fn encode_value(value: &Value, datatype: u32) -> Option<MetricValue> {
+    use datatype::*;
+
+    match (datatype, value) {
+        (INT8, Value::Integer(n)) => i8::try_from(*n)
+            .ok()
+            .map(|n| MetricValue::IntValue(i32::from(n) as u32)),
+        (INT16, Value::Integer(n)) => i16::try_from(*n)
+            .ok()
+            .map(|n| MetricValue::IntValue(i32::from(n) as u32)),
+        (INT32, Value::Integer(n)) => i32::try_from(*n)
+            .ok()
+            .map(|n| MetricValue::IntValue(n as u32)),
+        (UINT8, Value::Integer(n)) => u8::try_from(*n)
+            .ok()
+            .map(|n| MetricValue::IntValue(n.into())),
+        (UINT16, Value::Integer(n)) => u16::try_from(*n)
+            .ok()
+            .map(|n| MetricValue::IntValue(n.into())),
+        (UINT32, Value::Integer(n)) => u32::try_from(*n).ok().map(MetricValue::IntValue),
+        (INT64, Value::Integer(n)) => Some(MetricValue::LongValue(*n as u64)),
+        (UINT64 | DATE_TIME, Value::Integer(n)) => {
+            u64::try_from(*n).ok().map(MetricValue::LongValue)
+        },
+        (FLOAT, Value::Float(n)) => Some(MetricValue::FloatValue(*n)),
+        (DOUBLE, Value::Float(n)) => Some(MetricValue::DoubleValue((*n).into())),
+        (BOOLEAN, Value::Boolean(b)) => Some(MetricValue::BooleanValue(*b)),
+        (STRING | TEXT | UUID, Value::String(s)) => Some(MetricValue::StringValue(s.clone())),
+        _ => None,
+    }
+}
+
+/// Time of a metric, falling back to the time of the payload and then to
+/// the time it's received.
+fn timestamp(metric: Option<&Metric>, payload: &Payload) -> DateTime<Utc> {
+    metric
+        .and_then(|metric| metric.timestamp)
+        .or(payload.timestamp)
+        .and_then(|millis| i64::try_from(millis).ok())
+        .and_then(DateTime::from_timestamp_millis)
+        .unwrap_or_else(Utc::now)
+}
+
+fn bd_seq(payload: &Payload) -> Option<u64> {
+    payload
+        .metrics
+        .iter()
+        .find(|metric| metric.name.as_deref() == Some(BD_SEQ))
+        .and_then(|metric| match metric.value {
+            Some(MetricValue::LongValue(n)) => Some(n),
+            Some(MetricValue::IntValue(n)) => Some(n.into()),
+            _ => None,
+        })
+}
+
+/// Levels of a Sparkplug topic, `None` for other topics like the host
+/// states.
+struct Topic<'a> {
+    group: &'a str,
+    kind: &'a str,
+    node: &'a str,
+    device: Option<&'a str>,
+}
+
+impl<'a> Topic<'a> {
+    fn parse(topic: &'a str) -> Option<Self> {
+        let mut levels = topic.split('/');
+        if levels.next()? != NAMESPACE {
+            return None;
+        }
+        let topic = Self {
+            group: levels.next()?,
+            kind: levels.next()?,
+            node: levels.next()?,
+            device: levels.next(),
+        };
+        levels.next().is_none().then_some(topic)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+struct NodeKey {
+    group: String,
+    node: String,
+}
+
+impl std::fmt::Display for NodeKey {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}/{}", self.group, self.node)
+    }
+}
+
+/// Metric announced by a birth certificate.
+struct MetricInfo {
+    tag: TagName,
+    datatype: u32,
+}
+
+/// Metrics of an edge node or device and whether it's online.
+#[derive(Default)]
+struct Edge {
+    online: bool,
+    metrics: HashMap<String, MetricInfo>,
+    aliases: HashMap<u64, String>,
+}
+
+impl Edge {
+    fn metric(&self, metric: &Metric) -> Option<(&str, &MetricInfo)> {
+        let name = match &metric.name {
+            Some(name) => name,
+            None => self.aliases.get(&metric.alias?)?,
+        };
+        self.metrics
+            .get_key_value(name)
+            .map(|(name, info)| (name.as_str(), info))
+    }
+}
+
+#[derive(Default)]
+struct Node {
+    edge: Edge,
+    devices: HashMap<String, Edge>,
+    /// bdSeq of the birth, a death with another one is from an older session.
+    bd_seq: Option<u64>,
+    /// Sequence number of the last message.
+    seq: u64,
+    rebirth_requested: bool,
+}
+
+impl Node {
+    fn edge(&self, device: Option<&str>) -> Option<&Edge> {
+        match device {
+            Some(device) => self.devices.get(device),
+            None => Some(&self.edge),
+        }
+    }
+
+    /// Takes the sequence number of a message, `false` if messages were lost
+    /// in between.
+    fn advance(&mut self, payload: &Payload) -> bool {
+        let Some(seq) = payload.seq else {
+            return true;
+        };
+        let expected = (self.seq + 1) % 256;
+        self.seq = seq;
+        seq == expected
+    }
+}
+
+/// Node, device and metric of a tag, for commands.
+struct Target {
+    node: NodeKey,
+    device: Option<String>,
+    metric: String,
+}
+
+/// Change written into a tag.
+enum Update {
+    Value(TagValue),
+    /// Keeps the value and marks it with a quality.
+    Quality(Quality, DateTime<Utc>),
+}
+
+/// Subscribes to the Sparkplug messages of the configured groups and keeps
+/// tags in step with the edge nodes and devices.
+pub struct SparkplugHost;
+
+pub struct SparkplugArguments {
+    pub config: SparkplugConfig,
+    pub tag_repo: ActorRef<actor::tag::Message>,
+    pub status: SharedDriverStatus,
+}
+
+pub struct SparkplugState {
+    config: SparkplugConfig,
+    tag_repo: ActorRef<actor::tag::Message>,
+    status: SharedDriverStatus,
+    /// Task receiving the messages, while connected or connecting.
+    session: Option<JoinHandle<()>>,
+    /// Messages for the broker, while connected.
+    commands: Option<mpsc::UnboundedSender<Publish>>,
+    updates: JoinHandle<()>,
+    nodes: HashMap<NodeKey, Node>,
+    tags: HashMap<TagName, Target>,
+    /// Values written by the host and not seen among the updates yet, other
+    /// updates of the tags are commands.
+    written: HashMap<TagName, VecDeque<TagValue>>,
+}
+
+#[derive(Debug)]
+pub enum Message {
+    /// Connects unless a session is running.
+    Tick,
+    Connected(mpsc::UnboundedSender<Publish>),
+    Disconnected(String),
+    Received(Publish),
+    ValueUpdate(Box<Tag>),
+}
+
+#[cfg(feature = "cluster")]
+impl ractor::Message for Message {}
+
+#[cfg_attr(feature = "async-trait", ractor::async_trait)]
+impl Actor for SparkplugHost {
+    type Msg = Message;
+    type State = SparkplugState;
+    type Arguments = SparkplugArguments;
+
+    async fn pre_start(
+        &self,
+        myself: ActorRef<Self::Msg>,
+        args: Self::Arguments,
+    ) -> Result<Self::State, ActorProcessingErr> {
+        let config = args.config;
+        let (command, mut reply) = actor::tag::Message::subscribe();
+        args.tag_repo.enqueue(command)?;
+        let receiver = reply
+            .recv()
+            .await
+            .ok_or("tag repository didn't answer the subscription")?;
+        let updates = tokio::spawn(forward_updates(receiver, myself.clone()));
+
+        tracing::info!(
+            "driver {}: Sparkplug host for groups {} on {}",
+            config.name,
+            config.groups.join(", "),
+            config.broker.address
+        );
+        myself.send_interval(
+            Duration::from_millis(config.broker.reconnect_interval_ms),
+            || Message::Tick,
+        );
+        myself.send_message(Message::Tick)?;
+        Ok(SparkplugState {
+            config,
+            tag_repo: args.tag_repo,
+            status: args.status,
+            session: None,
+            commands: None,
+            updates,
+            nodes: HashMap::new(),
+            tags: HashMap::new(),
+            written: HashMap::new(),
+        })
+    }
+
+    async fn post_stop(
+        &self,
+        _myself: ActorRef<Self::Msg>,
+        state: &mut Self::State,
+    ) -> Result<(), ActorProcessingErr> {
+        state.updates.abort();
+        // Dropping the connection makes the broker publish the offline state
+        if let Some(session) = state.session.take() {
+            session.abort();
+        }
+        Ok(())
+    }
+
+    async fn handle(
+        &self,
+        myself: ActorRef<Self::Msg>,
+        message: Self::Msg,
+        state: &mut Self::State,
+    ) -> Result<(), ActorProcessingErr> {
+        match message {
+            Message::Tick => {
+                if state.session.is_none() {
+                    state.session = Some(tokio::spawn(run_session(state.config.clone(), myself)));
+                }
+            },
+            Message::Connected(commands) => {
+                tracing::info!(
+                    "driver {}: connected to {}",
+                    state.config.name,
+                    state.config.broker.address
+                );
+                {
+                    let mut status = state.status.lock().unwrap_or_else(|e| e.into_inner());
+                    status.connected = true;
+                    status.last_error = None;
+                }
+                state.commands = Some(commands);
+                // Values may have changed while disconnected
+                let nodes: Vec<NodeKey> = state.nodes.keys().cloned().collect();
+                for key in nodes {
+                    state.request_rebirth(&key);
+                }
+            },
+            Message::Disconnected(error) => {
+                tracing::warn!("driver {}: {}", state.config.name, error);
+                state.session = None;
+                state.commands = None;
+                {
+                    let mut status = state.status.lock().unwrap_or_else(|e| e.into_inner());
+                    status.connected = false;
+                    status.last_error = Some(error);
+                }
+                let nodes: Vec<NodeKey> = state.nodes.keys().cloned().collect();
+                for key in nodes {
+                    state
+                        .go_offline(&key, None, Quality::Uncertain, Utc::now())
+                        .await;
+                    if let Some(node) = state.nodes.get_mut(&key) {
+                        node.rebirth_requested = false;
+                    }
+                }
+            },
+            Message::Received(publish) => {
+                let result = match state.ingest(&publish).await {
+                    Ok(()) => "accepted",
+                    Err(e) => {
+                        tracing::warn!(
+                            "driver {}: rejected message on {}: {}",
+                            state.config.name,
+                            publish.topic,
+                            e
+                        );
+                        "rejected"
+                    },
+                };
+                state
+                    .status
+                    .lock()
+                    .unwrap_or_else(|e| e.into_inner())
+                    .last_poll = Some(Utc::now());
+                metrics()
+                    .driver_received
+                    .with_label_values(&[state.config.name.as_str(), result])
+                    .inc();
+            },
+            Message::ValueUpdate(tag) => state.command(*tag),
+        }
+        Ok(())
+    }
+}
+
+async fn forward_updates(mut updates: broadcast::Receiver<Tag>, host: ActorRef<Message>) {
+    loop {
+        match updates.recv().await {
+            Ok(tag) => {
+                if host
+                    .send_message(Message::ValueUpdate(Box::new(tag)))
+                    .is_err()
+                {
+                    break;
+                }
+            },
+            // Commands among the dropped updates are lost, like writes to a
+            // node that's offline
+            Err(RecvError::Lagged(count)) => {
+                tracing::warn!("Sparkplug host missed {count} value updates");
+            },
+            Err(RecvError::Closed) => break,
+        }
+    }
+}
+
+/// Connects, subscribes and passes on the messages until the connection
+/// fails, then reports the error.
+async fn run_session(config: SparkplugConfig, host: ActorRef<Message>) {
+    let Err(e) = receive_messages(&config, &host).await;
+    let _ = host.send_message(Message::Disconnected(e.to_string()));
+}
+
+async fn receive_messages(
+    config: &SparkplugConfig,
+    host: &ActorRef<Message>,
+) -> io::Result<std::convert::Infallible> {
+    let deadline = Duration::from_millis(config.broker.timeout_ms);
+    // The will and the online state carry the same timestamp, so edge nodes
+    // can tell which belongs to the current session
+    let started = Utc::now().timestamp_millis();
+    let connect = async {
+        let client_id = config.broker.client_id(&config.name);
+        let will = config.state(false, started);
+        let mut client = MqttClient::connect(&config.broker, client_id, will).await?;
+        client.subscribe(config.filters()).await?;
+        if let Some(online) = config.state(true, started) {
+            client.publish(online).await?;
+        }
+        Ok::<_, io::Error>(client)
+    };
+    let mut client = timeout(deadline, connect)
+        .await
+        .map_err(|_| io::Error::new(io::ErrorKind::TimedOut, "connect timed out"))??;
+    let (commands, mut outgoing) = mpsc::unbounded_channel();
+    host.send_message(Message::Connected(commands))
+        .map_err(io::Error::other)?;
+    loop {
+        tokio::select! {
+            ready = client.ready(deadline) => {
+                ready?;
+                if let Some(publish) = client.poll_message(deadline).await? {
+                    host.send_message(Message::Received(publish))
+                        .map_err(io::Error::other)?;
+                }
+            },
+            Some(command) = outgoing.recv() => client.publish(command).await?,
+        }
+    }
+}
+
+impl SparkplugState {
+    async fn ingest(&mut self, publish: &Publish) -> Result<(), String> {
+        let Some(topic) = Topic::parse(&publish.topic) else {
+            return Ok(());
+        };
+        let payload = Payload::decode(publish.payload.as_slice())
+            .map_err(|e| format!("invalid payload: {e}"))?;
+        let key = NodeKey {
+            group: topic.group.to_string(),
+            node: topic.node.to_string(),
+        };
+        match (topic.kind, topic.device) {
+            ("NBIRTH", None) => self.node_birth(key, &payload).await,
+            ("NDEATH", None) => {
+                self.node_death(&key, &payload).await;
+                Ok(())
+            },
+            ("DBIRTH", Some(device)) => self.device_birth(key, device, &payload).await,
+            ("DDEATH", Some(device)) => {
+                self.check_sequence(&key, &payload);
+                let timestamp = timestamp(None, &payload);
+                self.go_offline(&key, Some(device), Quality::Bad, timestamp)
+                    .await;
+                Ok(())
+            },
+            ("NDATA", None) => self.data(key, None, &payload).await,
+            ("DDATA", Some(device)) => self.data(key, Some(device), &payload).await,
+            // Commands of this and other hosts
+            ("NCMD" | "DCMD", _) => Ok(()),
+            (kind, _) => Err(format!("unknown message type {kind}")),
+        }
+    }
+
+    async fn node_birth(&mut self, key: NodeKey, payload: &Payload) -> Result<(), String> {
+        // Devices of the previous session are offline until their births
+        let devices: Vec<String> = self
+            .nodes
+            .get(&key)
+            .map(|node| node.devices.keys().cloned().collect())
+            .unwrap_or_default();
+        for device in devices {
+            self.go_offline(&key, Some(&device), Quality::Bad, timestamp(None, payload))
+                .await;
+        }
+        let mut edge = Edge::default();
+        let result = self.birth(&key, None, &mut edge, payload).await;
+        self.nodes.insert(
+            key,
+            Node {
+                edge,
+                devices: HashMap::new(),
+                bd_seq: bd_seq(payload),
+                seq: payload.seq.unwrap_or(0),
+                rebirth_requested: false,
+            },
+        );
+        result
+    }
+
+    async fn node_death(&mut self, key: &NodeKey, payload: &Payload) {
+        let Some(node) = self.nodes.get(key) else {
+            return;
+        };
+        if let (Some(death), Some(birth)) = (bd_seq(payload), node.bd_seq)
+            && death != birth
+        {
+            tracing::debug!(
+                "driver {}: ignoring death of an earlier session of {}",
+                self.config.name,
+                key
+            );
+            return;
+        }
+        self.go_offline(key, None, Quality::Bad, timestamp(None, payload))
+            .await;
+    }
+
+    async fn device_birth(
+        &mut self,
+        key: NodeKey,
+        device: &str,
+        payload: &Payload,
+    ) -> Result<(), String> {
+        if !self.nodes.get(&key).is_some_and(|node| node.edge.online) {
+            self.request_rebirth(&key);
+            return Err(format!(
+                "birth of device {device} before the birth of {key}"
+            ));
+        }
+        self.check_sequence(&key, payload);
+        let mut edge = Edge::default();
+        let result = self.birth(&key, Some(device), &mut edge, payload).await;
+        if let Some(node) = self.nodes.get_mut(&key) {
+            node.devices.insert(device.to_string(), edge);
+        }
+        result
+    }
+
+    /// Creates the missing tags of the metrics of a birth certificate and
+    /// writes their values.
+    async fn birth(
+        &mut self,
+        key: &NodeKey,
+        device: Option<&str>,
+        edge: &mut Edge,
+        payload: &Payload,
+    ) -> Result<(), String> {
+        let path = match device {
+            Some(device) => format!("{key}/{device}"),
+            None => key.to_string(),
+        };
+        edge.online = true;
+        let mut errors = Vec::new();
+        for metric in &payload.metrics {
+            let Some(name) = &metric.name else {
+                errors.push("metric without a name".to_string());
+                continue;
+            };
+            if name == BD_SEQ
+                || name.starts_with("Node Control/")
+                || name.starts_with("Device Control/")
+            {
+                continue;
+            }
+            let Some(data_type) = metric.datatype.and_then(data_type) else {
+                tracing::debug!(
+                    "driver {}: skipping metric {} of {} with data type {:?}",
+                    self.config.name,
+                    name,
+                    path,
+                    metric.datatype
+                );
+                continue;
+            };
+            let tag = TagName::from(format!("{}{}/{}", self.config.tag_prefix, path, name));
+            if let Err(e) = self.ensure_tag(&tag, data_type, name, &path).await {
+                errors.push(e);
+                continue;
+            }
+            let info = MetricInfo {
+                tag: tag.clone(),
+                datatype: metric.datatype.unwrap_or_default(),
+            };
+            if let Some(alias) = metric.alias {
+                edge.aliases.insert(alias, name.clone());
+            }
+            edge.metrics.insert(name.clone(), info);
+            self.tags.insert(
+                tag.clone(),
+                Target {
+                    node: key.clone(),
+                    device: device.map(str::to_string),
+                    metric: name.clone(),
+                },
+            );
+            let datatype = metric.datatype.unwrap_or_default();
+            if let Err(e) = self
+                .apply(&tag, self.update(metric, datatype, payload))
+                .await
+            {
+                errors.push(e);
+            }
+        }
+        if errors.is_empty() {
+            Ok(())
+        } else {
+            Err(errors.join(", "))
+        }
+    }
+
+    async fn data(
+        &mut self,
+        key: NodeKey,
+        device: Option<&str>,
+        payload: &Payload,
+    ) -> Result<(), String> {
+        let edge = self
+            .nodes
+            .get(&key)
+            .filter(|node| node.edge.online)
+            .and_then(|node| node.edge(device))
+            .filter(|edge| edge.online);
+        let Some(edge) = edge else {
+            self.request_rebirth(&key);
+            return Err(format!("data before the birth of {key}"));
+        };
+        let mut updates = Vec::new();
+        let mut errors = Vec::new();
+        for metric in &payload.metrics {
+            match edge.metric(metric) {
+                Some((_, info)) => updates.push((
+                    info.tag.clone(),
+                    self.update(metric, info.datatype, payload),
+                )),
+                None => errors.push(format!(
+                    "metric {} isn't in the birth certificate",
+                    metric
+                        .name
+                        .clone()
+                        .or_else(|| metric.alias.map(|alias| format!("with alias {alias}")))
+                        .unwrap_or_default()
+                )),
+            }
+        }
+        // Unknown metrics need a new birth certificate
+        if !self.check_sequence(&key, payload) || !errors.is_empty() {
+            self.request_rebirth(&key);
+        }
+        for (tag, update) in updates {
+            if let Err(e) = self.apply(&tag, update).await {
+                errors.push(e);
+            }
+        }
+        if errors.is_empty() {
+            Ok(())
+        } else {
+            Err(errors.join(", "))
+        }
+    }
+
+    fn update(&self, metric: &Metric, datatype: u32, payload: &Payload) -> Result<Update, String> {
+        let timestamp = timestamp(Some(metric), payload);
+        if metric.is_null == Some(true) {
+            return Ok(Update::Quality(Quality::Bad, timestamp));
+        }
+        let value = metric
+            .value
+            .as_ref()
+            .and_then(|value| metric_value(value, datatype))
+            .ok_or_else(|| {
+                format!(
+                    "value of {} doesn't match data type {datatype}",
+                    metric.name.as_deref().unwrap_or("metric")
+                )
+            })?;
+        Ok(Update::Value(TagValue {
+            value,
+            timestamp: Some(timestamp),
+            quality: Quality::Good,
+        }))
+    }
+
+    /// Marks the tags of a node and its devices, or of one device, with a
+    /// quality and takes them offline.
+    async fn go_offline(
+        &mut self,
+        key: &NodeKey,
+        device: Option<&str>,
+        quality: Quality,
+        timestamp: DateTime<Utc>,
+    ) {
+        let Some(node) = self.nodes.get_mut(key) else {
+            return;
+        };
+        let mut edges: Vec<&mut Edge> = match device {
+            Some(device) => node.devices.get_mut(device).into_iter().collect(),
+            None => std::iter::once(&mut node.edge)
+                .chain(node.devices.values_mut())
+                .collect(),
+        };
+        let mut tags = Vec::new();
+        for edge in edges.iter_mut().filter(|edge| edge.online) {
+            edge.online = false;
+            tags.extend(edge.metrics.values().map(|info| info.tag.clone()));
+        }
+        for tag in tags {
+            if let Err(e) = self
+                .apply(&tag, Ok(Update::Quality(quality, timestamp)))
+                .await
+            {
+                tracing::warn!("driver {}: {}", self.config.name, e);
+            }
+        }
+    }
+
+    /// Checks the sequence number of a message of a node, requesting a
+    /// rebirth if messages were lost.
+    fn check_sequence(&mut self, key: &NodeKey, payload: &Payload) -> bool {
+        let Some(node) = self.nodes.get_mut(key) else {
+            return false;
+        };
+        if node.advance(payload) {
+            return true;
+        }
+        tracing::warn!("driver {}: messages of {} were lost", self.config.name, key);
+        self.request_rebirth(key);
+        false
+    }
+
+    /// Asks an edge node to send its birth certificates again, once until
+    /// they arrive.
+    fn request_rebirth(&mut self, key: &NodeKey) {
+        let node = self.nodes.entry(key.clone()).or_default();
+        if node.rebirth_requested {
+            return;
+        }
+        let Some(commands) = &self.commands else {
+            return;
+        };
+        let metric = Metric {
+            name: Some(REBIRTH.to_string()),
+            datatype: Some(datatype::BOOLEAN),
+            value: Some(MetricValue::BooleanValue(true)),
+            ..Metric::default()
+        };
+        tracing::info!("driver {}: requesting rebirth of {}", self.config.name, key);
+        if commands.send(command(key, None, metric)).is_ok() {
+            node.rebirth_requested = true;
+        }
+    }
+
+    async fn ensure_tag(
+        &self,
+        tag: &TagName,
+        data_type: DataType,
+        metric: &str,
+        path: &str,
+    ) -> Result<(), String> {
+        let (command, mut reply) = actor::tag::Message::get_tag_data_type(tag.clone());
+        self.tag_repo.enqueue(command).map_err(|e| e.to_string())?;
+        match reply.recv().await.flatten() {
+            Some(existing) if existing == data_type => return Ok(()),
+            Some(existing) => {
+                return Err(format!(
+                    "tag {tag} is {existing:?}, the metric {data_type:?}"
+                ));
+            },
+            None => {},
+        }
+        let meta = TagMeta {
+            description: format!("Sparkplug metric {metric} of {path}"),
+            read_only: !self.config.commands,
+            ..TagMeta::new(Unit::None, data_type)
+        };
+        let (command, mut reply) = actor::tag::Message::create_tag(tag.clone(), meta);
+        self.tag_repo
+            .enqueue(command.with_origin(self.origin()))
+            .map_err(|e| e.to_string())?;
+        match reply.recv().await {
+            Some(CreateTagResult::SuccessfullyCreated) => {
+                tracing::info!("driver {}: created tag {}", self.config.name, tag);
+                Ok(())
+            },
+            // Created concurrently, checked with the next birth
+            Some(CreateTagResult::AlreadyExists) => Ok(()),
+            Some(CreateTagResult::Storage(e)) => Err(format!("failed to save tag {tag}: {e}")),
+            None => Err("tag repository didn't answer".to_string()),
+        }
+    }
+
+    async fn apply(&mut self, tag: &TagName, update: Result<Update, String>) -> Result<(), String> {
+        let value = match update.map_err(|e| format!("tag {tag}: {e}"))? {
+            Update::Value(value) => value,
+            Update::Quality(quality, timestamp) => {
+                let (command, mut reply) = actor::tag::Message::get_tag(tag.clone());
+                self.tag_repo.enqueue(command).map_err(|e| e.to_string())?;
+                let current = reply
+                    .recv()
+                    .await
+                    .and_then(Result::ok)
+                    .ok_or_else(|| format!("tag {tag} not found"))?;
+                if current.value.quality == quality {
+                    return Ok(());
+                }
+                TagValue {
+                    value: current.value.value,
+                    timestamp: Some(timestamp),
+                    quality,
+                }
+            },
+        };
+        let (command, mut reply) =
+            actor::tag::Message::update_tag_value(tag.clone(), value.clone());
+        self.tag_repo
+            .enqueue(command.with_origin(self.origin()))
+            .map_err(|e| e.to_string())?;
+        match reply.recv().await {
+            Some(Ok(UpdateValueResult::Updated)) => {
+                let written = self.written.entry(tag.clone()).or_default();
+                if written.len() == MAX_PENDING_WRITES {
+                    written.pop_front();
+                }
+                written.push_back(value);
+                Ok(())
+            },
+            Some(Ok(UpdateValueResult::Ignored)) => Ok(()),
+            Some(Err(e)) => Err(format!("tag {tag}: {e:?}")),
+            None => Err("tag repository didn't answer".to_string()),
+        }
+    }
+
+    /// Sends a command for a tag written by someone else than the host.
+    fn command(&mut self, tag: Tag) {
+        let Some(target) = self.tags.get(&tag.name) else {
+            return;
+        };
+        if let Some(written) = self.written.get_mut(&tag.name)
+            && let Some(position) = written.iter().position(|value| *value == tag.value)
+        {
+            written.drain(..=position);
+            return;
+        }
+        if !self.config.commands {
+            return;
+        }
+        let edge = self
+            .nodes
+            .get(&target.node)
+            .filter(|node| node.edge.online)
+            .and_then(|node| node.edge(target.device.as_deref()))
+            .filter(|edge| edge.online);
+        let (Some(edge), Some(commands)) = (edge, &self.commands) else {
+            tracing::warn!(
+                "driver {}: can't write {}, {} is offline",
+                self.config.name,
+                tag.name,
+                target.node
+            );
+            return;
+        };
+        let Some(info) = edge.metrics.get(&target.metric) else {
+            return;
+        };
+        let Some(value) = encode_value(&tag.value.value, info.datatype) else {
+            tracing::warn!(
+                "driver {}: can't write {:?} to {}, it's out of range",
+                self.config.name,
+                tag.value.value,
+                tag.name
+            );
+            return;
+        };
+        let metric = Metric {
+            name: Some(target.metric.clone()),
+            timestamp: Some(Utc::now().timestamp_millis() as u64),
+            datatype: Some(info.datatype),
+            value: Some(value),
+            ..Metric::default()
+        };
+        if commands
+            .send(command(&target.node, target.device.as_deref(), metric))
+            .is_ok()
+        {
+            metrics()
+                .driver_published
+                .with_label_values(&[self.config.name.as_str()])
+                .inc();
+        }
+    }
+
+    fn origin(&self) -> Origin {
+        Origin::system(format!("driver/{}", self.config.name))
+    }
+}
+
+/// NCMD, or DCMD for a device, with one metric.
+fn command(key: &NodeKey, device: Option<&str>, metric: Metric) -> Publish {
+    let topic = match device {
+        Some(device) => format!("{NAMESPACE}/{}/DCMD/{}/{device}", key.group, key.node),
+        None => format!("{NAMESPACE}/{}/NCMD/{}", key.group, key.node),
+    };
+    let payload = Payload {
+        timestamp: Some(Utc::now().timestamp_millis() as u64),
+        metrics: vec![metric],
+        ..Payload::default()
+    };
+    // Commands are sent with QoS 0 and never retained
+    Publish::new(topic, payload.encode_to_vec(), QoS::AtMostOnce, false)
+}
+
+#[cfg(test)]
+mod tests {
+    use tokio::{io::AsyncWriteExt, net::TcpListener};
+
+    use super::*;
+    use crate::{
+        actor::tag::TagRepositoryActor, audit::AuditLog, driver::mqtt::packet::Packet,
+        repository::tag::inmemory::TagStorage,
+    };
+
+    /// Stand-in broker passing the packets of the host to the test and the
+    /// packets of the test to the host.
+    async fn run_broker(
+        listener: TcpListener,
+        outgoing: mpsc::UnboundedSender<Packet>,
+        mut to_host: mpsc::UnboundedReceiver<Packet>,
+        from_host: mpsc::UnboundedSender<Packet>,
+    ) {
+        let (stream, _) = listener.accept().await.unwrap();
+        let (mut reader, mut writer) = tokio::io::split(stream);
+        tokio::spawn(async move {
+            while let Ok(packet) = Packet::read(&mut reader).await {
+                let reply = match &packet {
+                    Packet::Connect(_) => Some(Packet::ConnAck {
+                        session_present: false,
+                        code: 0,
+                    }),
+                    Packet::Subscribe {
+                        packet_id,
+                        filters,
+                    } => Some(Packet::SubAck {
+                        packet_id: *packet_id,
+                        codes: filters.iter().map(|(_, qos)| *qos as u8).collect(),
+                    }),
+                    Packet::Publish(publish) if publish.qos == QoS::AtLeastOnce => {
+                        Some(Packet::PubAck(publish.packet_id))
+                    },
+                    _ => None,
+                };
+                if let Some(reply) = reply {
+                    let _ = outgoing.send(reply);
+                }
+                let _ = from_host.send(packet);
+            }
+        });
+        while let Some(packet) = to_host.recv().await {
+            let mut buf = Vec::new();
+            packet.encode(&mut buf);
+            writer.write_all(&buf).await.unwrap();
+        }
+    }
+
+    fn metric(name: Option<&str>, alias: Option<u64>, datatype: u32, value: MetricValue) -> Metric {
+        Metric {
+            name: name.map(str::to_string),
+            alias,
+            datatype: Some(datatype),
+            value: Some(value),
+            ..Metric::default()
+        }
+    }
+
+    async fn wait_for(
+        tag_repo: &ActorRef<actor::tag::Message>,
+        name: &str,
+        done: impl Fn(&Tag) -> bool,
+    ) -> Tag {
+        let deadline = tokio::time::Instant::now() + Duration::from_secs(5);
+        loop {
+            let (command, mut reply) = actor::tag::Message::get_tag(name);
+            tag_repo.enqueue(command).unwrap();
+            if let Some(Ok(tag)) = reply.recv().await
+                && done(&tag)
+            {
+                return tag;
+            }
+            assert!(tokio::time::Instant::now() < deadline, "{name}");
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+    }
+
+    /// Next message the host published, skipping other packets.
+    async fn next_publish(from_host: &mut mpsc::UnboundedReceiver<Packet>) -> Publish {
+        loop {
+            if let Packet::Publish(publish) = from_host.recv().await.unwrap() {
+                return publish;
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn mirrors_edge_nodes_into_tags() {
+        let (tag_repo, _) = Actor::spawn(
+            None,
+            TagRepositoryActor::default(),
+            (TagStorage::default(), AuditLog::disabled()),
+        )
+        .await
+        .unwrap();
+
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap();
+        let (to_host, outgoing) = mpsc::unbounded_channel();
+        let (incoming, mut from_host) = mpsc::unbounded_channel();
+        tokio::spawn(run_broker(listener, to_host.clone(), outgoing, incoming));
+
+        let config = SparkplugConfig {
+            name: "edge".to_string(),
+            broker: BrokerConfig {
+                address: address.to_string(),
+                client_id: None,
+                username: None,
+                password: None,
+                keep_alive_secs: 30,
+                timeout_ms: 1000,
+                reconnect_interval_ms: 50,
+                tls: None,
+            },
+            groups: vec!["plant".to_string()],
+            host_id: Some("scada".to_string()),
+            tag_prefix: "sparkplug/".to_string(),
+            commands: true,
+        };
+        assert!(config.validate().is_empty());
+        let (host, _) = Actor::spawn(
+            None,
+            SparkplugHost,
+            SparkplugArguments {
+                config,
+                tag_repo: tag_repo.clone(),
+                status: SharedDriverStatus::default(),
+            },
+        )
+        .await
+        .unwrap();
+
+        let Some(Packet::Connect(connect)) = from_host.recv().await else {
+            panic!("expected CONNECT");
+        };
+        let will = connect.will.unwrap();
+        assert_eq!(will.topic, "spBv1.0/STATE/scada");
+        assert!(
+            String::from_utf8(will.payload)
+                .unwrap()
+                .contains(r#""online":false"#)
+        );
+        let online = next_publish(&mut from_host).await;
+        assert_eq!(online.topic, "spBv1.0/STATE/scada");
+        assert!(online.retain);
+
+        let send = |topic: &str, seq: u64, metrics: Vec<Metric>| {
+            let payload = Payload {
+                timestamp: Some(1767225600000),
+                metrics,
+                seq: Some(seq),
+                ..Payload::default()
+            };
+            to_host
+                .send(Packet::Publish(Publish::new(
+                    topic,
+                    payload.encode_to_vec(),
+                    QoS::AtMostOnce,
+                    false,
+                )))
+                .unwrap();
+        };
+        use datatype::*;
+        send(
+            "spBv1.0/plant/NBIRTH/line1",
+            0,
+            vec![
+                metric(Some(BD_SEQ), None, INT64, MetricValue::LongValue(7)),
+                metric(
+                    Some(REBIRTH),
+                    None,
+                    BOOLEAN,
+                    MetricValue::BooleanValue(false),
+                ),
+                metric(
+                    Some("Temperature"),
+                    Some(1),
+                    DOUBLE,
+                    MetricValue::DoubleValue(21.5),
+                ),
+            ],
+        );
+        send(
+            "spBv1.0/plant/DBIRTH/line1/pump",
+            1,
+            vec![
+                metric(
+                    Some("Running"),
+                    Some(2),
+                    BOOLEAN,
+                    MetricValue::BooleanValue(true),
+                ),
+                metric(
+                    Some("Speed"),
+                    Some(3),
+                    INT16,
+                    MetricValue::IntValue(-5i32 as u32),
+                ),
+                metric(
+                    Some("Setpoint"),
+                    Some(4),
+                    UINT16,
+                    MetricValue::IntValue(100),
+                ),
+            ],
+        );
+        let setpoint = wait_for(&tag_repo, "sparkplug/plant/line1/pump/Setpoint", |tag| {
+            tag.value.value == Value::Integer(100)
+        })
+        .await;
+        assert_eq!(setpoint.meta.data_type, DataType::Integer);
+        assert_eq!(
+            setpoint.value.timestamp,
+            DateTime::from_timestamp_millis(1767225600000)
+        );
+        let speed = wait_for(&tag_repo, "sparkplug/plant/line1/pump/Speed", |_| true).await;
+        assert_eq!(speed.value.value, Value::Integer(-5));
+        let temperature = wait_for(&tag_repo, "sparkplug/plant/line1/Temperature", |_| true).await;
+        assert_eq!(temperature.meta.data_type, DataType::Float);
+        assert_eq!(temperature.value.value, Value::Float(21.5));
+
+        // Data refers to metrics by their aliases
+        send(
+            "spBv1.0/plant/NDATA/line1",
+            2,
+            vec![metric(
+                None,
+                Some(1),
+                DOUBLE,
+                MetricValue::DoubleValue(22.25),
+            )],
+        );
+        wait_for(&tag_repo, "sparkplug/plant/line1/Temperature", |tag| {
+            tag.value.value == Value::Float(22.25)
+        })
+        .await;
+
+        // Writes through the API become commands
+        let (command, mut reply) = actor::tag::Message::update_tag_value(
+            "sparkplug/plant/line1/pump/Setpoint",
+            TagValue {
+                value: Value::Integer(150),
+                timestamp: Some(Utc::now()),
+                quality: Quality::Good,
+            },
+        );
+        tag_repo.enqueue(command).unwrap();
+        reply.recv().await.unwrap().unwrap();
+        let command = next_publish(&mut from_host).await;
+        assert_eq!(command.topic, "spBv1.0/plant/DCMD/line1/pump");
+        assert_eq!(command.qos, QoS::AtMostOnce);
+        let payload = Payload::decode(command.payload.as_slice()).unwrap();
+        assert_eq!(payload.metrics.len(), 1);
+        assert_eq!(payload.metrics[0].name.as_deref(), Some("Setpoint"));
+        assert_eq!(payload.metrics[0].datatype, Some(UINT16));
+        assert_eq!(payload.metrics[0].value, Some(MetricValue::IntValue(150)));
+
+        send("spBv1.0/plant/DDEATH/line1/pump", 3, Vec::new());
+        wait_for(&tag_repo, "sparkplug/plant/line1/pump/Running", |tag| {
+            tag.value.quality == Quality::Bad && tag.value.value == Value::Boolean(true)
+        })
+        .await;
+
+        // The death of an earlier session leaves the node online
+        send(
+            "spBv1.0/plant/NDEATH/line1",
+            0,
+            vec![metric(Some(BD_SEQ), None, INT64, MetricValue::LongValue(6))],
+        );
+        send(
+            "spBv1.0/plant/NDATA/line1",
+            4,
+            vec![metric(
+                None,
+                Some(1),
+                DOUBLE,
+                MetricValue::DoubleValue(23.0),
+            )],
+        );
+        wait_for(&tag_repo, "sparkplug/plant/line1/Temperature", |tag| {
+            tag.value.value == Value::Float(23.0) && tag.value.quality == Quality::Good
+        })
+        .await;
+        send(
+            "spBv1.0/plant/NDEATH/line1",
+            0,
+            vec![metric(Some(BD_SEQ), None, INT64, MetricValue::LongValue(7))],
+        );
+        wait_for(&tag_repo, "sparkplug/plant/line1/Temperature", |tag| {
+            tag.value.quality == Quality::Bad
+        })
+        .await;
+
+        host.stop(None);
+        tag_repo.stop(None);
+    }
+}
@@ -0,0 +1,687 @@
+use std::{io, time::Duration};
+
+use chrono::{DateTime, Utc};
+use ractor::{Actor, ActorProcessingErr, ActorRef};
+use serde::{Deserialize, Serialize};
+use tokio::{task::JoinHandle, time::timeout};
+
+use rcada_core::{
+    tag::{Quality, TagValue},
+    value::{DataType, Value},
+};
+
+use crate::{
+    actor::{self, Mailbox},
+    audit::Origin,
+    driver::SharedDriverStatus,
+    metrics::metrics,
+};
+
+use super::{
+    BrokerConfig, MqttClient, check_filter, filter_matches,
+    json_path::JsonPath,
+    packet::{Publish, QoS},
+};
+
+/// Writes the values of messages from an MQTT broker into tags.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct MqttSubscriberConfig {
+    pub name: String,
+    #[serde(flatten)]
+    pub broker: BrokerConfig,
+    /// Highest QoS the broker delivers messages with, 0, 1 or 2.
+    #[serde(default = "default_qos")]
+    pub qos: u8,
+    #[serde(default)]
+    pub mappings: Vec<MqttMapping>,
+}
+
+/// Messages on the topics matching `topic` written to a tag. A message
+/// matching several mappings is written by each of them.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct MqttMapping {
+    /// Topic filter, `+` matches one level and `#` the remaining ones.
+    pub topic: String,
+    /// Name of the tag, `{N}` is replaced by the N-th level of the topic,
+    /// e.g. `{2}/temperature` for messages on `sensors/boiler/data`.
+    pub tag: String,
+    /// JSONPath of the value, `$` for the whole payload. Payloads that aren't
+    /// JSON are taken as a string.
+    #[serde(default = "default_value_path")]
+    pub value_path: String,
+    /// JSONPath of the timestamp, an RFC 3339 string or a number of
+    /// `timestamp_unit` since the epoch. Without it values are stamped with
+    /// the time they are received.
+    #[serde(default)]
+    pub timestamp_path: Option<String>,
+    #[serde(default)]
+    pub timestamp_unit: TimestampUnit,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+pub enum TimestampUnit {
+    #[serde(rename = "s")]
+    Seconds,
+    #[default]
+    #[serde(rename = "ms")]
+    Milliseconds,
+}
+
+fn default_qos() -> u8 {
+    1
+}
+
+fn default_value_path() -> String {
+    "$".to_string()
+}
+
+impl MqttSubscriberConfig {
+    pub fn validate(&self) -> Vec<String> {
+        let mut errors = Vec::new();
+        if self.name.is_empty() {
+            errors.push("driver name is empty".to_string());
+        }
+        errors.extend(self.broker.validate(&self.name));
+        if self.qos > 2 {
+            errors.push(format!("driver {}: qos must be 0, 1 or 2", self.name));
+        }
+        for mapping in &self.mappings {
+            if let Err(e) = Mapping::new(mapping.clone()) {
+                errors.push(format!(
+                    "driver {}: mapping of {}: {}",
+                    self.name, mapping.topic, e
+                ));
+            }
+        }
+        errors
+    }
+
+    /// Distinct topic filters of the mappings.
+    fn filters(&self) -> Vec<(String, QoS)> {
+        let qos = QoS::try_from(self.qos).unwrap_or_default();
+        let mut filters: Vec<(String, QoS)> = Vec::new();
+        for mapping in &self.mappings {
+            if !filters.iter().any(|(filter, _)| *filter == mapping.topic) {
+                filters.push((mapping.topic.clone(), qos));
+            }
+        }
+        filters
+    }
+}
+
+/// [`MqttMapping`] with its paths parsed.
+struct Mapping {
+    config: MqttMapping,
+    value_path: JsonPath,
+    timestamp_path: Option<JsonPath>,
+}
+
+impl Mapping {
+    fn new(config: MqttMapping) -> Result<Self, String> {
+        check_filter(&config.topic)?;
+        if config.tag.is_empty() {
+            return Err("tag is empty".to_string());
+        }
+        let mut rest = config.tag.as_str();
+        while let Some(start) = rest.find('{') {
+            let end = rest[start..]
+                .find('}')
+                .ok_or("unclosed placeholder in tag")?
+                + start;
+            let level = &rest[start + 1..end];
+            if !level.parse::<usize>().is_ok_and(|level| level > 0) {
+                return Err(format!("tag: {{{level}}} isn't a topic level"));
+            }
+            rest = &rest[end + 1..];
+        }
+        Ok(Self {
+            value_path: config.value_path.parse()?,
+            timestamp_path: config
+                .timestamp_path
+                .as_deref()
+                .map(str::parse)
+                .transpose()?,
+            config,
+        })
+    }
+
+    /// Name of the tag for a message on `topic`, `None` if the topic lacks a
+    /// level the name refers to.
+    fn tag(&self, topic: &str) -> Option<String> {
+        let levels: Vec<&str> = topic.split('/').collect();
+        let mut tag = String::new();
+        let mut rest = self.config.tag.as_str();
+        while let Some(start) = rest.find('{') {
+            let end = rest[start..].find('}')? + start;
+            let level: usize = rest[start + 1..end].parse().ok()?;
+            tag.push_str(&rest[..start]);
+            tag.push_str(levels.get(level.checked_sub(1)?)?);
+            rest = &rest[end + 1..];
+        }
+        tag.push_str(rest);
+        Some(tag)
+    }
+
+    /// Value and timestamp of a message, checked against the data type of
+    /// the tag.
+    fn extract(
+        &self,
+        document: &serde_json::Value,
+        data_type: DataType,
+    ) -> Result<TagValue, String> {
+        let value = self
+            .value_path
+            .select(document)
+            .ok_or_else(|| format!("no value at {}", self.config.value_path))?;
+        let timestamp = match (&self.timestamp_path, &self.config.timestamp_path) {
+            (Some(path), Some(name)) => {
+                let timestamp = path
+                    .select(document)
+                    .ok_or_else(|| format!("no timestamp at {name}"))?;
+                parse_timestamp(timestamp, self.config.timestamp_unit)?
+            },
+            _ => Utc::now(),
+        };
+        Ok(TagValue {
+            value: convert(value, data_type)?,
+            timestamp: Some(timestamp),
+            quality: Quality::Good,
+        })
+    }
+}
+
+/// Converts a JSON value to `data_type`. Numbers, booleans and strings are
+/// converted into each other where the meaning is clear, floats are rounded
+/// for integer tags.
+fn convert(value: &serde_json::Value, data_type: DataType) -> Result<Value, String> {
+    use serde_json::Value as Json;
+
+    let invalid = || format!("{value} can't be converted to {data_type:?}");
+    let number = |value: &Json| -> Option<f64> {
+        match value {
+            Json::Number(n) => n.as_f64(),
+            Json::String(s) => s.trim().parse().ok(),
+            Json::Bool(b) => Some(f64::from(u8::from(*b))),
+            _ => None,
+        }
+        .filter(|n| n.is_finite())
+    };
+    match data_type {
+        DataType::Integer => match value {
+            Json::Number(n) => n.as_i64(),
+            Json::String(s) => s.trim().parse().ok(),
+            _ => None,
+        }
+        .or_else(|| {
+            number(value)
+                .map(f64::round)
+                .filter(|n| *n >= i64::MIN as f64 && *n <= i64::MAX as f64)
+                .map(|n| n as i64)
+        })
+        .map(Value::Integer),
+        DataType::Float => number(value)
+            .map(|n| n as f32)
+            .filter(|n| n.is_finite())
+            .map(Value::Float),
+        DataType::Boolean => match value {
+            Json::Bool(b) => Some(Value::Boolean(*b)),
+            Json::Number(n) => n.as_f64().map(|n| Value::Boolean(n != 0.0)),
+            Json::String(s) => match s.trim().to_ascii_lowercase().as_str() {
+                "true" | "on" | "1" => Some(Value::Boolean(true)),
+                "false" | "off" | "0" => Some(Value::Boolean(false)),
+                _ => None,
+            },
+            _ => None,
+        },
+        DataType::String => match value {
+            Json::String(s) => Some(Value::String(s.clone())),
+            Json::Number(_) | Json::Bool(_) => Some(Value::String(value.to_string())),
+            _ => None,
+        },
+    }
+    .ok_or_else(invalid)
+}
+
+fn parse_timestamp(
+    value: &serde_json::Value,
+    unit: TimestampUnit,
+) -> Result<DateTime<Utc>, String> {
+    let number = match value {
+        serde_json::Value::Number(n) => n.as_f64(),
+        serde_json::Value::String(s) => match s.parse::<f64>() {
+            Ok(n) => Some(n),
+            Err(_) => {
+                return DateTime::parse_from_rfc3339(s)
+                    .map(|timestamp| timestamp.with_timezone(&Utc))
+                    .map_err(|e| format!("invalid timestamp {s}: {e}"));
+            },
+        },
+        _ => None,
+    };
+    let millis = number.map(|n| match unit {
+        TimestampUnit::Seconds => n * 1000.0,
+        TimestampUnit::Milliseconds => n,
+    });
+    millis
+        .filter(|millis| millis.is_finite())
+        .and_then(|millis| DateTime::from_timestamp_millis(millis.round() as i64))
+        .ok_or_else(|| format!("invalid timestamp {value}"))
+}
+
+/// Subscribes to the topics of the mappings and writes the messages into
+/// tags.
+pub struct MqttSubscriber;
+
+pub struct MqttSubscriberArguments {
+    pub config: MqttSubscriberConfig,
+    pub tag_repo: ActorRef<actor::tag::Message>,
+    pub status: SharedDriverStatus,
+}
+
+pub struct MqttSubscriberState {
+    config: MqttSubscriberConfig,
+    mappings: Vec<Mapping>,
+    tag_repo: ActorRef<actor::tag::Message>,
+    status: SharedDriverStatus,
+    /// Task receiving the messages, while connected or connecting.
+    session: Option<JoinHandle<()>>,
+}
+
+#[derive(Debug)]
+pub enum Message {
+    /// Connects unless a session is running.
+    Tick,
+    Connected,
+    Disconnected(String),
+    Received(Publish),
+}
+
+#[cfg(feature = "cluster")]
+impl ractor::Message for Message {}
+
+#[cfg_attr(feature = "async-trait", ractor::async_trait)]
+impl Actor for MqttSubscriber {
+    type Msg = Message;
+    type State = MqttSubscriberState;
+    type Arguments = MqttSubscriberArguments;
+
+    async fn pre_start(
+        &self,
+        myself: ActorRef<Self::Msg>,
+        args: Self::Arguments,
+    ) -> Result<Self::State, ActorProcessingErr> {
+        let config = args.config;
+        let mappings = config
+            .mappings
+            .iter()
+            .cloned()
+            .map(Mapping::new)
+            .collect::<Result<Vec<_>, _>>()?;
+        tracing::info!(
+            "driver {}: subscribing to {} topics on {}",
+            config.name,
+            config.filters().len(),
+            config.broker.address
+        );
+        myself.send_interval(
+            Duration::from_millis(config.broker.reconnect_interval_ms),
+            || Message::Tick,
+        );
+        myself.send_message(Message::Tick)?;
+        Ok(MqttSubscriberState {
+            config,
+            mappings,
+            tag_repo: args.tag_repo,
+            status: args.status,
+            session: None,
+        })
+    }
+
+    async fn post_stop(
+        &self,
+        _myself: ActorRef<Self::Msg>,
+        state: &mut Self::State,
+    ) -> Result<(), ActorProcessingErr> {
+        if let Some(session) = state.session.take() {
+            session.abort();
+        }
+        Ok(())
+    }
+
+    async fn handle(
+        &self,
+        myself: ActorRef<Self::Msg>,
+        message: Self::Msg,
+        state: &mut Self::State,
+    ) -> Result<(), ActorProcessingErr> {
+        match message {
+            Message::Tick => {
+                if state.session.is_none() && !state.config.mappings.is_empty() {
+                    state.session = Some(tokio::spawn(run_session(state.config.clone(), myself)));
+                }
+            },
+            Message::Connected => {
+                tracing::info!(
+                    "driver {}: connected to {}",
+                    state.config.name,
+                    state.config.broker.address
+                );
+                let mut status = state.status.lock().unwrap_or_else(|e| e.into_inner());
+                status.connected = true;
+                status.last_error = None;
+            },
+            Message::Disconnected(error) => {
+                tracing::warn!("driver {}: {}", state.config.name, error);
+                state.session = None;
+                let mut status = state.status.lock().unwrap_or_else(|e| e.into_inner());
+                status.connected = false;
+                status.last_error = Some(error);
+            },
+            Message::Received(publish) => {
+                let result = match state.ingest(&publish).await {
+                    Ok(()) => "accepted",
+                    Err(e) => {
+                        tracing::warn!(
+                            "driver {}: rejected message on {}: {}",
+                            state.config.name,
+                            publish.topic,
+                            e
+                        );
+                        "rejected"
+                    },
+                };
+                state
+                    .status
+                    .lock()
+                    .unwrap_or_else(|e| e.into_inner())
+                    .last_poll = Some(Utc::now());
+                metrics()
+                    .driver_received
+                    .with_label_values(&[state.config.name.as_str(), result])
+                    .inc();
+            },
+        }
+        Ok(())
+    }
+}
+
+/// Connects, subscribes and passes on the messages until the connection
+/// fails, then reports the error.
+async fn run_session(config: MqttSubscriberConfig, subscriber: ActorRef<Message>) {
+    let Err(e) = receive_messages(&config, &subscriber).await;
+    let _ = subscriber.send_message(Message::Disconnected(e.to_string()));
+}
+
+async fn receive_messages(
+    config: &MqttSubscriberConfig,
+    subscriber: &ActorRef<Message>,
+) -> io::Result<std::convert::Infallible> {
+    let deadline = Duration::from_millis(config.broker.timeout_ms);
+    let connect = async {
+        let mut client =
+            MqttClient::connect(&config.broker, config.broker.client_id(&config.name), None)
+                .await?;
+        client.subscribe(config.filters()).await?;
+        Ok::<_, io::Error>(client)
+    };
+    let mut client = timeout(deadline, connect)
+        .await
+        .map_err(|_| io::Error::new(io::ErrorKind::TimedOut, "connect timed out"))??;
+    subscriber
+        .send_message(Message::Connected)
+        .map_err(io::Error::other)?;
+    loop {
+        let publish = client.next_message(deadline).await?;
+        subscriber
+            .send_message(Message::Received(publish))
+            .map_err(io::Error::other)?;
+    }
+}
+
+impl MqttSubscriberState {
+    /// Writes the message into the tags of every mapping of its topic,
+    /// fails if any of them rejects it.
+    async fn ingest(&self, publish: &Publish) -> Result<(), String> {
+        let document = match serde_json::from_slice(&publish.payload) {
+            Ok(document) => document,
+            Err(_) => match std::str::from_utf8(&publish.payload) {
+                Ok(text) => serde_json::Value::String(text.to_string()),
+                Err(_) => return Err("payload is neither JSON nor text".to_string()),
+            },
+        };
+        let mut errors = Vec::new();
+        let mappings = self
+            .mappings
+            .iter()
+            .filter(|mapping| filter_matches(&mapping.config.topic, &publish.topic));
+        for mapping in mappings {
+            if let Err(e) = self.write(mapping, &publish.topic, &document).await {
+                errors.push(e);
+            }
+        }
+        if errors.is_empty() {
+            Ok(())
+        } else {
+            Err(errors.join(", "))
+        }
+    }
+
+    async fn write(
+        &self,
+        mapping: &Mapping,
+        topic: &str,
+        document: &serde_json::Value,
+    ) -> Result<(), String> {
+        let tag = mapping
+            .tag(topic)
+            .ok_or_else(|| format!("topic lacks a level of the tag name {}", mapping.config.tag))?;
+
+        let (command, mut reply) = actor::tag::Message::get_tag_data_type(tag.as_str());
+        self.tag_repo.enqueue(command).map_err(|e| e.to_string())?;
+        let data_type = reply
+            .recv()
+            .await
+            .flatten()
+            .ok_or_else(|| format!("tag {tag} not found"))?;
+        let value = mapping
+            .extract(document, data_type)
+            .map_err(|e| format!("tag {tag}: {e}"))?;
+
+        let (command, mut reply) = actor::tag::Message::update_tag_value(tag.as_str(), value);
+        self.tag_repo
+            .enqueue(command.with_origin(Origin::system(format!("driver/{}", self.config.name))))
+            .map_err(|e| e.to_string())?;
+        match reply.recv().await {
+            Some(Err(e)) => Err(format!("tag {tag}: {e:?}")),
+            _ => Ok(()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rcada_core::{
+        tag::{Tag, TagMeta},
+        unit::Unit,
+    };
+    use tokio::{io::AsyncWriteExt, net::TcpListener, sync::mpsc};
+
+    use super::*;
+    use crate::{
+        actor::tag::TagRepositoryActor, audit::AuditLog, driver::mqtt::packet::Packet,
+        repository::tag::inmemory::TagStorage,
+    };
+
+    /// Stand-in broker granting every subscription and sending the messages
+    /// it's given to the subscriber.
+    async fn run_broker(listener: TcpListener, mut messages: mpsc::UnboundedReceiver<Publish>) {
+        let (stream, _) = listener.accept().await.unwrap();
+        let (mut reader, mut writer) = tokio::io::split(stream);
+        let (subscribed, mut ready) = mpsc::unbounded_channel();
+        tokio::spawn(async move {
+            while let Ok(packet) = Packet::read(&mut reader).await {
+                let reply = match packet {
+                    Packet::Connect(_) => Packet::ConnAck {
+                        session_present: false,
+                        code: 0,
+                    },
+                    Packet::Subscribe {
+                        packet_id,
+                        filters,
+                    } => Packet::SubAck {
+                        packet_id,
+                        codes: filters.iter().map(|(_, qos)| *qos as u8).collect(),
+                    },
+                    _ => continue,
+                };
+                let _ = subscribed.send(reply);
+            }
+        });
+        for _ in 0..2 {
+            let mut buf = Vec::new();
+            ready.recv().await.unwrap().encode(&mut buf);
+            writer.write_all(&buf).await.unwrap();
+        }
+        let mut packet_id = 0;
+        while let Some(mut publish) = messages.recv().await {
+            packet_id += 1;
+            publish.packet_id = packet_id;
+            let mut buf = Vec::new();
+            Packet::Publish(publish).encode(&mut buf);
+            writer.write_all(&buf).await.unwrap();
+        }
+    }
+
+    async fn wait_for(
+        tag_repo: &ActorRef<actor::tag::Message>,
+        name: &str,
+        done: impl Fn(&Tag) -> bool,
+    ) -> Tag {
+        let deadline = tokio::time::Instant::now() + Duration::from_secs(5);
+        loop {
+            let (command, mut reply) = actor::tag::Message::get_tag(name);
+            tag_repo.enqueue(command).unwrap();
+            let tag = reply.recv().await.unwrap().unwrap();
+            if done(&tag) {
+                return tag;
+            }
+            assert!(tokio::time::Instant::now() < deadline, "{name}: {tag:?}");
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+    }
+
+    #[tokio::test]
+    async fn writes_valid_messages_into_tags() {
+        let (tag_repo, _) = Actor::spawn(
+            None,
+            TagRepositoryActor::default(),
+            (TagStorage::default(), AuditLog::disabled()),
+        )
+        .await
+        .unwrap();
+        for (name, data_type) in [
+            ("boiler/temperature", DataType::Float),
+            ("boiler/running", DataType::Boolean),
+        ] {
+            let (command, mut reply) =
+                actor::tag::Message::create_tag(name, TagMeta::new(Unit::None, data_type));
+            tag_repo.enqueue(command).unwrap();
+            reply.recv().await.unwrap();
+        }
+
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap();
+        let (messages, received) = mpsc::unbounded_channel();
+        tokio::spawn(run_broker(listener, received));
+
+        let mapping = |tag: &str, value_path: &str, timestamp_path: Option<&str>| MqttMapping {
+            topic: "sensors/+/data".to_string(),
+            tag: tag.to_string(),
+            value_path: value_path.to_string(),
+            timestamp_path: timestamp_path.map(str::to_string),
+            timestamp_unit: TimestampUnit::Milliseconds,
+        };
+        let config = MqttSubscriberConfig {
+            name: "sensors".to_string(),
+            broker: BrokerConfig {
+                address: address.to_string(),
+                client_id: None,
+                username: None,
+                password: None,
+                keep_alive_secs: 30,
+                timeout_ms: 1000,
+                reconnect_interval_ms: 50,
+                tls: None,
+            },
+            qos: 1,
+            mappings: vec![
+                mapping("{2}/temperature", "$.temp", Some("$['ts']")),
+                mapping("{2}/running", "$.state", None),
+            ],
+        };
+        assert!(config.validate().is_empty());
+        let (subscriber, _) = Actor::spawn(
+            None,
+            MqttSubscriber,
+            MqttSubscriberArguments {
+                config,
+                tag_repo: tag_repo.clone(),
+                status: SharedDriverStatus::default(),
+            },
+        )
+        .await
+        .unwrap();
+
+        let send = |topic: &str, payload: &str| {
+            messages
+                .send(Publish::new(topic, payload, QoS::AtLeastOnce, false))
+                .unwrap();
+        };
+        send(
+            "sensors/boiler/data",
+            r#"{"temp": "21.5", "ts": 1767225600000, "state": "on"}"#,
+        );
+        let tag = wait_for(&tag_repo, "boiler/temperature", |tag| {
+            tag.value.timestamp.is_some()
+        })
+        .await;
+        assert_eq!(tag.value.value, Value::Float(21.5));
+        assert_eq!(
+            tag.value.timestamp,
+            DateTime::from_timestamp_millis(1767225600000)
+        );
+        wait_for(&tag_repo, "boiler/running", |tag| {
+            tag.value.value == Value::Boolean(true)
+        })
+        .await;
+
+        // The temperature can't be converted, the state is still written
+        send(
+            "sensors/boiler/data",
+            r#"{"temp": "hot", "ts": 1767225601000, "state": 0}"#,
+        );
+        // No tag for the kettle
+        send("sensors/kettle/data", r#"{"temp": 90, "state": true}"#);
+        wait_for(&tag_repo, "boiler/running", |tag| {
+            tag.value.value == Value::Boolean(false)
+        })
+        .await;
+
+        send(
+            "sensors/boiler/data",
+            r#"{"temp": 22, "ts": "2026-01-01T00:00:02Z", "state": 1}"#,
+        );
+        let tag = wait_for(&tag_repo, "boiler/temperature", |tag| {
+            tag.value.value != Value::Float(21.5)
+        })
+        .await;
+        assert_eq!(tag.value.value, Value::Float(22.0));
+        assert_eq!(
+            tag.value.timestamp,
+            DateTime::from_timestamp_millis(1767225602000)
+        );
+
+        subscriber.stop(None);
+        tag_repo.stop(None);
+    }
+}
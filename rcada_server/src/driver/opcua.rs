@@ -0,0 +1,1062 @@
+use std::{
+    collections::HashSet,
+    path::{Path, PathBuf},
+    sync::Arc,
+    time::Duration,
+};
+
+use chrono::Utc;
+use opcua::{
+    client::{
+        Client, ClientBuilder, DataChangeCallback, IdentityToken, Session, SessionPollResult,
+    },
+    types::{
+        AttributeId, BrowseDescription, BrowseDirection, BrowseResultMask, DataTypeId, DataValue,
+        Identifier, MonitoredItemCreateRequest, MonitoringMode, MonitoringParameters, NamespaceMap,
+        NodeClass, NodeClassMask, NodeId, ReadValueId, ReferenceTypeId, TimestampsToReturn,
+        Variant,
+    },
+};
+use ractor::{Actor, ActorProcessingErr, ActorRef};
+use serde::{Deserialize, Serialize};
+use tokio::{task::JoinHandle, time::timeout};
+use tokio_stream::StreamExt;
+
+use rcada_core::{
+    tag::{Quality, TagMeta, TagName, TagValue},
+    unit::Unit,
+    value::DataType,
+};
+
+use crate::{
+    actor,
+    driver::{self, SharedDriverStatus, TagWriter},
+    metrics::metrics,
+    opcua::{SecurityMode, tag_value},
+};
+
+/// Directory below the data directory with the certificate of the OPC UA
+/// drivers and the trusted and rejected server certificates.
+pub const PKI_DIR: &str = "opcua/client-pki";
+
+/// Levels below a browsed node searched for variables.
+const MAX_BROWSE_DEPTH: usize = 8;
+
+/// Nodes per browse and read request, servers limit the operations of a
+/// request.
+const REQUEST_CHUNK: usize = 100;
+
+/// Subscribes to nodes of an OPC UA server and writes their values into
+/// tags.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct OpcUaClientConfig {
+    pub name: String,
+    /// Endpoint URL of the server, e.g. `opc.tcp://192.168.0.10:4840/`.
+    pub endpoint: String,
+    /// Security mode of the endpoint, the signing ones use
+    /// Aes128_Sha256_RsaOaep.
+    #[serde(default = "default_security")]
+    pub security: SecurityMode,
+    /// Without a user name sessions are anonymous.
+    #[serde(default)]
+    pub username: Option<String>,
+    #[serde(default)]
+    pub password: Option<String>,
+    /// Accept server certificates that aren't in `opcua/client-pki/trusted`
+    /// yet, otherwise they are stored in `opcua/client-pki/rejected` to be
+    /// moved there.
+    #[serde(default)]
+    pub trust_server_certs: bool,
+    /// How often the server sends the changes of the nodes.
+    #[serde(default = "default_publishing_interval_ms")]
+    pub publishing_interval_ms: u64,
+    /// Timeout of connecting and of every request, the connection is
+    /// reopened after it.
+    #[serde(default = "default_timeout_ms")]
+    pub timeout_ms: u64,
+    #[serde(default = "default_reconnect_interval_ms")]
+    pub reconnect_interval_ms: u64,
+    #[serde(default)]
+    pub nodes: Vec<OpcUaNode>,
+}
+
+/// Node whose value is written to a tag, or with `browse` the variables
+/// below it.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct OpcUaNode {
+    /// Node id, with the namespace by index (`ns=2;s=Line1.Speed`) or by URI
+    /// (`nsu=urn:plc;s=Line1.Speed`).
+    pub node_id: String,
+    /// Name of the tag. With `browse`, the prefix of the tags of the
+    /// variables, followed by the browse names of the objects and folders
+    /// down to them, e.g. `plc/Line1/Speed`.
+    pub tag: String,
+    /// Map the variables found in the objects and folders below the node
+    /// instead of the node.
+    #[serde(default)]
+    pub browse: bool,
+}
+
+fn default_security() -> SecurityMode {
+    SecurityMode::None
+}
+
+fn default_publishing_interval_ms() -> u64 {
+    500
+}
+
+fn default_timeout_ms() -> u64 {
+    5000
+}
+
+fn default_reconnect_interval_ms() -> u64 {
+    5000
+}
+
+impl OpcUaClientConfig {
+    pub fn validate(&self) -> Vec<String> {
+        let mut errors = Vec::new();
+        if self.name.is_empty() {
+            errors.push("driver name is empty".to_string());
+        }
+        if !self.endpoint.starts_with("opc.tcp://") {
+            errors.push(format!(
+                "driver {}: endpoint must be an opc.tcp:// URL",
+                self.name
+            ));
+        }
+        if self.username.is_some() != self.password.is_some() {
+            errors.push(format!(
+                "driver {}: username and password go together",
+                self.name
+            ));
+        }
+        for (field, value) in [
+            ("publishing_interval_ms", self.publishing_interval_ms),
+            ("timeout_ms", self.timeout_ms),
+            ("reconnect_interval_ms", self.reconnect_interval_ms),
+        ] {
+            if value == 0 {
+                errors.push(format!("driver {}: {} must be positive", self.name, field));
+            }
+        }
+        for node in &self.nodes {
+            if let Err(e) = ConfiguredNodeId::parse(&node.node_id) {
+                errors.push(format!(
+                    "driver {}: node {}: {}",
+                    self.name, node.node_id, e
+                ));
+            }
+            if node.tag.is_empty() {
+                errors.push(format!(
+                    "driver {}: node {}: tag is empty",
+                    self.name, node.node_id
+                ));
+            }
+        }
+        errors
+    }
+}
+
+/// Node id of the configuration, resolved once the namespaces of the
+/// server are known.
+struct ConfiguredNodeId {
+    namespace_uri: Option<String>,
+    node_id: NodeId,
+}
+
+impl ConfiguredNodeId {
+    fn parse(id: &str) -> Result<Self, String> {
+        let invalid = || format!("invalid node id {id}");
+        match id.strip_prefix("nsu=") {
+            Some(rest) => {
+                let (uri, identifier) = rest.split_once(';').ok_or_else(invalid)?;
+                let identifier: Identifier = identifier.parse().map_err(|_| invalid())?;
+                Ok(Self {
+                    namespace_uri: Some(uri.to_string()),
+                    node_id: NodeId::new(0, identifier),
+                })
+            },
+            None => Ok(Self {
+                namespace_uri: None,
+                node_id: id.parse().map_err(|_| invalid())?,
+            }),
+        }
+    }
+
+    fn resolve(self, namespaces: &NamespaceMap) -> Result<NodeId, String> {
+        let Some(uri) = self.namespace_uri else {
+            return Ok(self.node_id);
+        };
+        let namespace = namespaces
+            .get_index(&uri)
+            .ok_or_else(|| format!("server has no namespace {uri}"))?;
+        Ok(NodeId::new(namespace, self.node_id.identifier))
+    }
+}
+
+/// Data type of the tags of a node data type, `None` for types tags can't
+/// hold.
+fn data_type(id: &NodeId) -> Option<DataType> {
+    match id.as_data_type_id().ok()? {
+        DataTypeId::Boolean => Some(DataType::Boolean),
+        DataTypeId::SByte
+        | DataTypeId::Byte
+        | DataTypeId::Int16
+        | DataTypeId::UInt16
+        | DataTypeId::Int32
+        | DataTypeId::UInt32
+        | DataTypeId::Int64
+        | DataTypeId::UInt64 => Some(DataType::Integer),
+        DataTypeId::Float | DataTypeId::Double => Some(DataType::Float),
+        DataTypeId::String => Some(DataType::String),
+        _ => None,
+    }
+}
+
+fn quality(value: &DataValue) -> Quality {
+    let status = value.status();
+    if status.is_good() {
+        Quality::Good
+    } else if status.is_uncertain() {
+        Quality::Uncertain
+    } else {
+        Quality::Bad
+    }
+}
+
+/// Variable of the server mapped onto a tag.
+#[derive(Debug)]
+pub struct Point {
+    tag: TagName,
+    node_id: NodeId,
+    /// Data type of the variable, `None` if tags can't hold it.
+    data_type: Option<DataType>,
+}
+
+/// Connects to an OPC UA server, browses the configured nodes and writes
+/// the changes of the variables into tags.
+pub struct OpcUaClient;
+
+pub struct OpcUaClientArguments {
+    pub config: OpcUaClientConfig,
+    pub tag_repo: ActorRef<actor::tag::Message>,
+    pub status: SharedDriverStatus,
+    pub data_dir: PathBuf,
+}
+
+pub struct OpcUaClientState {
+    config: OpcUaClientConfig,
+    writer: TagWriter,
+    status: SharedDriverStatus,
+    data_dir: PathBuf,
+    /// Task running the session, while connected or connecting.
+    session: Option<JoinHandle<()>>,
+    /// Tag and its data type by client handle of the monitored item minus
+    /// one, `None` for variables without a tag.
+    points: Vec<Option<(TagName, DataType)>>,
+}
+
+#[derive(Debug)]
+pub enum Message {
+    /// Connects unless a session is running.
+    Tick,
+    /// Variables found after connecting, in the order of the client handles.
+    Browsed(Vec<Point>),
+    Connected,
+    /// The connection is lost, the session reconnects by itself.
+    ConnectionLost(String),
+    /// The session failed and ended.
+    Disconnected(String),
+    Changed(u32, Box<DataValue>),
+}
+
+#[cfg(feature = "cluster")]
+impl ractor::Message for Message {}
+
+#[cfg_attr(feature = "async-trait", ractor::async_trait)]
+impl Actor for OpcUaClient {
+    type Msg = Message;
+    type State = OpcUaClientState;
+    type Arguments = OpcUaClientArguments;
+
+    async fn pre_start(
+        &self,
+        myself: ActorRef<Self::Msg>,
+        args: Self::Arguments,
+    ) -> Result<Self::State, ActorProcessingErr> {
+        let config = args.config;
+        tracing::info!(
+            "driver {}: subscribing to {} nodes on {}",
+            config.name,
+            config.nodes.len(),
+            config.endpoint
+        );
+        myself.send_interval(Duration::from_millis(config.reconnect_interval_ms), || {
+            Message::Tick
+        });
+        myself.send_message(Message::Tick)?;
+        Ok(OpcUaClientState {
+            writer: TagWriter::new(&config.name, args.tag_repo),
+            config,
+            status: args.status,
+            data_dir: args.data_dir,
+            session: None,
+            points: Vec::new(),
+        })
+    }
+
+    async fn post_stop(
+        &self,
+        _myself: ActorRef<Self::Msg>,
+        state: &mut Self::State,
+    ) -> Result<(), ActorProcessingErr> {
+        if let Some(session) = state.session.take() {
+            session.abort();
+        }
+        Ok(())
+    }
+
+    async fn handle(
+        &self,
+        myself: ActorRef<Self::Msg>,
+        message: Self::Msg,
+        state: &mut Self::State,
+    ) -> Result<(), ActorProcessingErr> {
+        match message {
+            Message::Tick => {
+                if state.session.is_none() && !state.config.nodes.is_empty() {
+                    state.session = Some(tokio::spawn(run_session(
+                        state.config.clone(),
+                        state.data_dir.clone(),
+                        myself,
+                    )));
+                }
+            },
+            Message::Browsed(points) => {
+                state.points.clear();
+                for point in points {
+                    match state.ensure_tag(&point).await {
+                        Ok(data_type) => state.points.push(Some((point.tag, data_type))),
+                        Err(e) => {
+                            tracing::warn!("driver {}: {}", state.config.name, e);
+                            state.points.push(None);
+                        },
+                    }
+                }
+            },
+            Message::Connected => {
+                tracing::info!(
+                    "driver {}: connected to {}",
+                    state.config.name,
+                    state.config.endpoint
+                );
+                driver::set_connected(&state.status);
+            },
+            Message::ConnectionLost(error) => state.disconnected(error).await,
+            Message::Disconnected(error) => {
+                state.session = None;
+                state.disconnected(error).await;
+            },
+            Message::Changed(handle, value) => {
+                let result = match state.apply(handle, *value).await {
+                    Ok(()) => "accepted",
+                    Err(e) => {
+                        tracing::warn!("driver {}: rejected change: {}", state.config.name, e);
+                        "rejected"
+                    },
+                };
+                state
+                    .status
+                    .lock()
+                    .unwrap_or_else(|e| e.into_inner())
+                    .last_poll = Some(Utc::now());
+                metrics()
+                    .driver_received
+                    .with_label_values(&[state.config.name.as_str(), result])
+                    .inc();
+            },
+        }
+        Ok(())
+    }
+}
+
+/// Connects, subscribes and passes on the changes until the session fails,
+/// then reports the error.
+async fn run_session(config: OpcUaClientConfig, data_dir: PathBuf, driver: ActorRef<Message>) {
+    let Err(e) = receive_changes(&config, &data_dir, &driver).await;
+    let _ = driver.send_message(Message::Disconnected(e));
+}
+
+async fn receive_changes(
+    config: &OpcUaClientConfig,
+    data_dir: &Path,
+    driver: &ActorRef<Message>,
+) -> Result<std::convert::Infallible, String> {
+    let mut client = client(config, data_dir)?;
+    let (policy, mode) = config.security.policy();
+    let identity = match (&config.username, &config.password) {
+        (Some(username), Some(password)) => IdentityToken::new_user_name(username, password),
+        _ => IdentityToken::Anonymous,
+    };
+    let connect = client
+        .connect_to_matching_endpoint((config.endpoint.as_str(), policy.to_uri(), mode), identity);
+    let (session, event_loop) = timeout(Duration::from_millis(config.timeout_ms), connect)
+        .await
+        .map_err(|_| "connect timed out".to_string())?
+        .map_err(|e| e.to_string())?;
+
+    // The event loop connects and, after a lost connection, reconnects the
+    // session and transfers or recreates the subscription
+    let events = event_loop.enter();
+    let subscribe = subscribe(&session, config, driver);
+    tokio::pin!(events, subscribe);
+    let mut subscribed = false;
+    loop {
+        tokio::select! {
+            result = &mut subscribe, if !subscribed => {
+                result?;
+                subscribed = true;
+                driver.send_message(Message::Connected).map_err(|e| e.to_string())?;
+            },
+            event = events.next() => {
+                let message = match event {
+                    Some(Ok(SessionPollResult::ConnectionLost(status))) => {
+                        Message::ConnectionLost(format!("connection lost: {status}"))
+                    },
+                    Some(Ok(SessionPollResult::ReconnectFailed(status))) => {
+                        Message::ConnectionLost(format!("connecting failed: {status}"))
+                    },
+                    Some(Ok(SessionPollResult::Reconnected(_))) if subscribed => {
+                        // The publishing of a new connection only starts with
+                        // a trigger, which the recovery of the subscription
+                        // sent before the connection was set up
+                        session.trigger_publish_now();
+                        Message::Connected
+                    },
+                    Some(Ok(_)) => continue,
+                    Some(Err(status)) => return Err(format!("session failed: {status}")),
+                    None => return Err("session closed".to_string()),
+                };
+                driver.send_message(message).map_err(|e| e.to_string())?;
+            },
+        }
+    }
+}
+
+fn client(config: &OpcUaClientConfig, data_dir: &Path) -> Result<Client, String> {
+    let reconnect_interval = Duration::from_millis(config.reconnect_interval_ms);
+    ClientBuilder::new()
+        .application_name("rcada")
+        .application_uri("urn:rcada:client")
+        .product_uri("urn:rcada")
+        .session_name(format!("rcada-{}", config.name))
+        .pki_dir(data_dir.join(PKI_DIR))
+        // Without signing the certificate isn't used
+        .create_sample_keypair(config.security != SecurityMode::None)
+        .trust_server_certs(config.trust_server_certs)
+        .request_timeout(Duration::from_millis(config.timeout_ms))
+        .session_retry_limit(-1)
+        .session_retry_initial(reconnect_interval)
+        .session_retry_max(reconnect_interval)
+        .client()
+        .map_err(|errors| errors.join(", "))
+}
+
+/// Finds the variables of the configured nodes once connected and creates
+/// a subscription with a monitored item for each of them.
+async fn subscribe(
+    session: &Arc<Session>,
+    config: &OpcUaClientConfig,
+    driver: &ActorRef<Message>,
+) -> Result<(), String> {
+    if !session.wait_for_connection().await {
+        return Err("session closed".to_string());
+    }
+    let namespaces = session
+        .read_namespace_array()
+        .await
+        .map_err(|e| e.to_string())?;
+    let mut variables = Vec::new();
+    for node in &config.nodes {
+        let node_id = ConfiguredNodeId::parse(&node.node_id)
+            .and_then(|id| id.resolve(&namespaces))
+            .map_err(|e| format!("node {}: {}", node.node_id, e))?;
+        if node.browse {
+            let found = browse(session, &node_id)
+                .await
+                .map_err(|e| format!("browsing {}: {}", node.node_id, e))?;
+            if found.is_empty() {
+                tracing::warn!(
+                    "driver {}: no variables below {}",
+                    config.name,
+                    node.node_id
+                );
+            }
+            variables.extend(
+                found
+                    .into_iter()
+                    .map(|(path, id)| (TagName::from(format!("{}/{}", node.tag, path)), id)),
+            );
+        } else {
+            variables.push((TagName::from(node.tag.as_str()), node_id));
+        }
+    }
+
+    let mut points = Vec::with_capacity(variables.len());
+    for chunk in variables.chunks(REQUEST_CHUNK) {
+        let reads: Vec<ReadValueId> = chunk
+            .iter()
+            .map(|(_, node_id)| ReadValueId {
+                node_id: node_id.clone(),
+                attribute_id: AttributeId::DataType as u32,
+                ..Default::default()
+            })
+            .collect();
+        let values = session
+            .read(&reads, TimestampsToReturn::Neither, 0.0)
+            .await
+            .map_err(|e| e.to_string())?;
+        for ((tag, node_id), value) in chunk.iter().zip(values) {
+            let data_type = match value.value {
+                Some(Variant::NodeId(id)) => data_type(&id),
+                _ => None,
+            };
+            points.push(Point {
+                tag: tag.clone(),
+                node_id: node_id.clone(),
+                data_type,
+            });
+        }
+    }
+    let items: Vec<MonitoredItemCreateRequest> = points
+        .iter()
+        .enumerate()
+        .map(|(index, point)| MonitoredItemCreateRequest {
+            item_to_monitor: point.node_id.clone().into(),
+            monitoring_mode: MonitoringMode::Reporting,
+            requested_parameters: MonitoringParameters {
+                client_handle: index as u32 + 1,
+                sampling_interval: config.publishing_interval_ms as f64,
+                queue_size: 1,
+                discard_oldest: true,
+                ..Default::default()
+            },
+        })
+        .collect();
+    // Before the first change, which is handled after the tags are created
+    driver
+        .send_message(Message::Browsed(points))
+        .map_err(|e| e.to_string())?;
+
+    let changes = driver.clone();
+    let subscription = session
+        .create_subscription(
+            Duration::from_millis(config.publishing_interval_ms),
+            // Kept for 30 publishing intervals without the client
+            30,
+            10,
+            0,
+            0,
+            true,
+            DataChangeCallback::new(move |value, item| {
+                let _ =
+                    changes.send_message(Message::Changed(item.client_handle(), Box::new(value)));
+            }),
+        )
+        .await
+        .map_err(|e| e.to_string())?;
+    for chunk in items.chunks(REQUEST_CHUNK) {
+        let created = session
+            .create_monitored_items(subscription, TimestampsToReturn::Both, chunk.to_vec())
+            .await
+            .map_err(|e| e.to_string())?;
+        for item in created {
+            if item.result.status_code.is_bad() {
+                tracing::warn!(
+                    "driver {}: can't monitor {}: {}",
+                    config.name,
+                    item.item_to_monitor.node_id,
+                    item.result.status_code
+                );
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Variables in the objects and folders below `root` with the browse names
+/// of the nodes down to them, joined by `/`.
+async fn browse(session: &Session, root: &NodeId) -> Result<Vec<(String, NodeId)>, String> {
+    let mut variables = Vec::new();
+    let mut visited = HashSet::from([root.clone()]);
+    let mut level = vec![(String::new(), root.clone())];
+    for _ in 0..MAX_BROWSE_DEPTH {
+        let mut next = Vec::new();
+        for chunk in level.chunks(REQUEST_CHUNK) {
+            let descriptions: Vec<BrowseDescription> = chunk
+                .iter()
+                .map(|(_, node_id)| BrowseDescription {
+                    node_id: node_id.clone(),
+                    browse_direction: BrowseDirection::Forward,
+                    reference_type_id: ReferenceTypeId::HierarchicalReferences.into(),
+                    include_subtypes: true,
+                    node_class_mask: (NodeClassMask::OBJECT | NodeClassMask::VARIABLE).bits(),
+                    result_mask: BrowseResultMask::All as u32,
+                })
+                .collect();
+            let results = session
+                .browse(&descriptions, 0, None)
+                .await
+                .map_err(|e| e.to_string())?;
+            for ((path, _), result) in chunk.iter().zip(results) {
+                let mut references = result.references.unwrap_or_default();
+                let mut continuation_point = result.continuation_point;
+                while !continuation_point.is_null() {
+                    let more = session
+                        .browse_next(false, &[continuation_point])
+                        .await
+                        .map_err(|e| e.to_string())?;
+                    let Some(more) = more.into_iter().next() else {
+                        break;
+                    };
+                    references.extend(more.references.unwrap_or_default());
+                    continuation_point = more.continuation_point;
+                }
+                for reference in references {
+                    let node_id = reference.node_id.node_id;
+                    // Properties describe their parent, they aren't values
+                    if reference.node_id.server_index != 0
+                        || reference.reference_type_id == ReferenceTypeId::HasProperty
+                        || !visited.insert(node_id.clone())
+                    {
+                        continue;
+                    }
+                    let name = reference.browse_name.name.as_ref();
+                    let path = if path.is_empty() {
+                        name.to_string()
+                    } else {
+                        format!("{path}/{name}")
+                    };
+                    match reference.node_class {
+                        NodeClass::Variable => variables.push((path, node_id)),
+                        NodeClass::Object => next.push((path, node_id)),
+                        _ => {},
+                    }
+                }
+            }
+        }
+        if next.is_empty() {
+            break;
+        }
+        level = next;
+    }
+    Ok(variables)
+}
+
+impl OpcUaClientState {
+    /// Data type of the tag of a variable, created read-only with the type
+    /// of the variable if it's missing.
+    async fn ensure_tag(&self, point: &Point) -> Result<DataType, String> {
+        self.writer
+            .ensure_tag(&point.tag, || {
+                let data_type = point.data_type.ok_or_else(|| {
+                    format!(
+                        "tag {} not found and node {} has no supported data type",
+                        point.tag, point.node_id
+                    )
+                })?;
+                Ok(TagMeta {
+                    description: format!(
+                        "OPC UA node {} of {}",
+                        point.node_id, self.config.endpoint
+                    ),
+                    read_only: true,
+                    ..TagMeta::new(Unit::None, data_type)
+                })
+            })
+            .await
+    }
+
+    /// Writes a change of a monitored item into its tag. Changes without a
+    /// value only update the quality.
+    async fn apply(&mut self, handle: u32, value: DataValue) -> Result<(), String> {
+        let point = handle
+            .checked_sub(1)
+            .and_then(|index| self.points.get(index as usize));
+        let Some(Some((tag, data_type))) = point else {
+            return Err(format!("no tag for monitored item {handle}"));
+        };
+        let quality = quality(&value);
+        let timestamp = value
+            .source_timestamp
+            .or(value.server_timestamp)
+            .map(|timestamp| timestamp.as_chrono())
+            .unwrap_or_else(Utc::now);
+        match value.value.filter(|variant| !variant.is_empty()) {
+            Some(variant) => {
+                let converted = tag_value(&variant)
+                    .and_then(|value| value.convert(*data_type))
+                    .ok_or_else(|| {
+                        format!("tag {tag}: {variant:?} can't be converted to {data_type:?}")
+                    })?;
+                self.writer
+                    .update(
+                        tag,
+                        TagValue {
+                            value: converted,
+                            timestamp: Some(timestamp),
+                            quality,
+                        },
+                    )
+                    .await
+            },
+            None if quality != Quality::Good => {
+                self.writer.set_quality(tag, quality, timestamp).await
+            },
+            None => Err(format!("tag {tag}: change without a value")),
+        }
+    }
+
+    /// Marks the tags `uncertain` once the connection is lost, the server
+    /// sends the values again after reconnecting.
+    async fn disconnected(&mut self, error: String) {
+        tracing::warn!("driver {}: {}", self.config.name, error);
+        if driver::set_disconnected(&self.status, error) {
+            let tags = self
+                .points
+                .iter()
+                .flatten()
+                .map(|(tag, _)| tag.clone())
+                .collect();
+            self.writer.set_uncertain(tags).await;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use opcua::{
+        server::{
+            ANONYMOUS_USER_TOKEN_ID, ServerBuilder, ServerEndpoint, ServerHandle,
+            address_space::Variable,
+            diagnostics::NamespaceMetadata,
+            node_manager::memory::{SimpleNodeManager, simple_node_manager},
+        },
+        types::{ObjectId, UAString},
+    };
+    use rcada_core::value::Value;
+    use tokio::{net::TcpListener, sync::oneshot};
+
+    use super::*;
+    use crate::{
+        access::{AccessControl, AccessModel},
+        auth::{AuthConfig, Authenticator},
+        driver::testing::{create_tag, set_value, tag_repo, until, wait_for},
+        opcua::{OpcUaConfig, OpcUaState},
+    };
+
+    /// Stand-in server: the OPC UA endpoint of the server itself, serving
+    /// the tags of `tag_repo` until the sender is dropped.
+    async fn run_server(
+        listener: TcpListener,
+        tag_repo: ActorRef<actor::tag::Message>,
+        data_dir: PathBuf,
+    ) -> (oneshot::Sender<()>, JoinHandle<()>) {
+        let state = OpcUaState {
+            tag_repo,
+            access: Arc::new(AccessControl::new(AccessModel::default())),
+            authenticator: Arc::new(Authenticator::new(&AuthConfig::default()).unwrap()),
+        };
+        let (stop, stopped) = oneshot::channel::<()>();
+        let server = tokio::spawn(async move {
+            let shutdown = async {
+                let _ = stopped.await;
+            };
+            crate::opcua::serve(
+                listener,
+                &OpcUaConfig::default(),
+                &data_dir,
+                state,
+                shutdown,
+            )
+            .await
+            .unwrap();
+        });
+        (stop, server)
+    }
+
+    /// Independent peer: a server of async-opcua with its simple node
+    /// manager, holding a `Plant` folder with a `Line1` folder below it.
+    async fn run_plain_server(
+        listener: TcpListener,
+        pki_dir: PathBuf,
+    ) -> (Arc<SimpleNodeManager>, ServerHandle, JoinHandle<()>) {
+        let address = listener.local_addr().unwrap();
+        let (server, handle) = ServerBuilder::new()
+            .application_name("plc")
+            .application_uri("urn:plc:server")
+            .host(address.ip().to_string())
+            .port(address.port())
+            .pki_dir(pki_dir)
+            .create_sample_keypair(false)
+            .discovery_urls(vec!["/".to_string()])
+            .add_endpoint(
+                "none",
+                ServerEndpoint::new_none("/", &[ANONYMOUS_USER_TOKEN_ID.to_string()]),
+            )
+            .with_node_manager(simple_node_manager(
+                NamespaceMetadata {
+                    namespace_uri: "urn:plc".to_string(),
+                    ..Default::default()
+                },
+                "plc",
+            ))
+            .build()
+            .unwrap();
+        let manager = handle
+            .node_managers()
+            .get_of_type::<SimpleNodeManager>()
+            .unwrap();
+        let ns = handle.get_namespace_index("urn:plc").unwrap();
+        {
+            let mut space = manager.address_space().write();
+            let plant = NodeId::new(ns, "Plant");
+            let line = NodeId::new(ns, "Plant.Line1");
+            space.add_folder(&plant, "Plant", "Plant", &ObjectId::ObjectsFolder.into());
+            space.add_folder(&line, "Line1", "Line1", &plant);
+            space.add_variables(
+                vec![Variable::new(
+                    &NodeId::new(ns, "Plant.Name"),
+                    "Name",
+                    "Name",
+                    UAString::from("north"),
+                )],
+                &plant,
+            );
+            space.add_variables(
+                vec![
+                    Variable::new(
+                        &NodeId::new(ns, "Plant.Line1.Speed"),
+                        "Speed",
+                        "Speed",
+                        12.0,
+                    ),
+                    Variable::new(
+                        &NodeId::new(ns, "Plant.Line1.Count"),
+                        "Count",
+                        "Count",
+                        7i32,
+                    ),
+                ],
+                &line,
+            );
+        }
+        let server = tokio::spawn(async move {
+            server.run_with(listener).await.unwrap();
+        });
+        (manager, handle, server)
+    }
+
+    #[tokio::test]
+    async fn mirrors_variables_of_a_plain_server() {
+        let data_dir =
+            std::env::temp_dir().join(format!("rcada-opcua-client-{}", uuid::Uuid::new_v4()));
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap();
+        let (manager, handle, server) = run_plain_server(listener, data_dir.join("server")).await;
+
+        let tag_repo = tag_repo().await;
+        create_tag(&tag_repo, "plain/speed", Value::Integer(0)).await;
+        let config = OpcUaClientConfig {
+            name: "plain".to_string(),
+            endpoint: format!("opc.tcp://{address}/"),
+            security: SecurityMode::None,
+            username: None,
+            password: None,
+            trust_server_certs: false,
+            publishing_interval_ms: 50,
+            timeout_ms: 1000,
+            reconnect_interval_ms: 50,
+            nodes: vec![
+                OpcUaNode {
+                    node_id: "nsu=urn:plc;s=Plant.Line1.Speed".to_string(),
+                    tag: "plain/speed".to_string(),
+                    browse: false,
+                },
+                OpcUaNode {
+                    node_id: "nsu=urn:plc;s=Plant".to_string(),
+                    tag: "plain/plant".to_string(),
+                    browse: true,
+                },
+            ],
+        };
+        assert!(config.validate().is_empty());
+        let (driver, _) = Actor::spawn(
+            None,
+            OpcUaClient,
+            OpcUaClientArguments {
+                config,
+                tag_repo: tag_repo.clone(),
+                status: SharedDriverStatus::default(),
+                data_dir: data_dir.join("client"),
+            },
+        )
+        .await
+        .unwrap();
+
+        wait_for(&tag_repo, "plain/speed", |tag| {
+            tag.value.value == Value::Integer(12)
+        })
+        .await;
+        let tag = wait_for(&tag_repo, "plain/plant/Name", |tag| {
+            tag.value.quality == Quality::Good
+        })
+        .await;
+        assert_eq!(tag.value.value, Value::String("north".to_string()));
+        let tag = wait_for(&tag_repo, "plain/plant/Line1/Count", |tag| {
+            tag.value.quality == Quality::Good
+        })
+        .await;
+        assert_eq!(tag.value.value, Value::Integer(7));
+        assert!(tag.meta.read_only);
+        let tag = wait_for(&tag_repo, "plain/plant/Line1/Speed", |tag| {
+            tag.value.quality == Quality::Good
+        })
+        .await;
+        assert_eq!(tag.meta.data_type, DataType::Float);
+
+        let ns = handle.get_namespace_index("urn:plc").unwrap();
+        manager
+            .set_value(
+                handle.subscriptions(),
+                &NodeId::new(ns, "Plant.Line1.Speed"),
+                None,
+                DataValue::new_now(13.0),
+            )
+            .unwrap();
+        wait_for(&tag_repo, "plain/plant/Line1/Speed", |tag| {
+            tag.value.value == Value::Float(13.0)
+        })
+        .await;
+        wait_for(&tag_repo, "plain/speed", |tag| {
+            tag.value.value == Value::Integer(13)
+        })
+        .await;
+
+        driver.stop(None);
+        handle.cancel();
+        until(server).await.unwrap().unwrap();
+        let _ = std::fs::remove_dir_all(&data_dir);
+    }
+
+    #[tokio::test]
+    async fn mirrors_variables_into_tags() {
+        let data_dir =
+            std::env::temp_dir().join(format!("rcada-opcua-client-{}", uuid::Uuid::new_v4()));
+        let plc = tag_repo().await;
+        create_tag(&plc, "line1/speed", Value::Float(42.0)).await;
+        create_tag(&plc, "line1/running", Value::Boolean(true)).await;
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap();
+        let (stop, server) = run_server(listener, plc.clone(), data_dir.join("server")).await;
+
+        let tag_repo = tag_repo().await;
+        // Values are converted into the data type of existing tags
+        create_tag(&tag_repo, "plc/speed", Value::Integer(0)).await;
+        let config = OpcUaClientConfig {
+            name: "plc".to_string(),
+            endpoint: format!("opc.tcp://{address}/"),
+            security: SecurityMode::None,
+            username: None,
+            password: None,
+            trust_server_certs: false,
+            publishing_interval_ms: 50,
+            timeout_ms: 1000,
+            reconnect_interval_ms: 50,
+            nodes: vec![
+                OpcUaNode {
+                    node_id: "nsu=urn:rcada:tags;s=line1/speed".to_string(),
+                    tag: "plc/speed".to_string(),
+                    browse: false,
+                },
+                OpcUaNode {
+                    node_id: "nsu=urn:rcada:tags;i=1".to_string(),
+                    tag: "plc/all".to_string(),
+                    browse: true,
+                },
+            ],
+        };
+        assert!(config.validate().is_empty());
+        let status = SharedDriverStatus::default();
+        let (driver, _) = Actor::spawn(
+            None,
+            OpcUaClient,
+            OpcUaClientArguments {
+                config,
+                tag_repo: tag_repo.clone(),
+                status: status.clone(),
+                data_dir: data_dir.join("client"),
+            },
+        )
+        .await
+        .unwrap();
+
+        wait_for(&tag_repo, "plc/speed", |tag| {
+            tag.value.value == Value::Integer(42)
+        })
+        .await;
+        let tag = wait_for(&tag_repo, "plc/all/line1/running", |tag| {
+            tag.value.quality == Quality::Good
+        })
+        .await;
+        assert_eq!(tag.value.value, Value::Boolean(true));
+        assert!(tag.meta.read_only);
+        let tag = wait_for(&tag_repo, "plc/all/line1/speed", |tag| {
+            tag.value.quality == Quality::Good
+        })
+        .await;
+        assert_eq!(tag.meta.data_type, DataType::Float);
+
+        set_value(&plc, "line1/speed", Value::Float(43.5)).await;
+        wait_for(&tag_repo, "plc/all/line1/speed", |tag| {
+            tag.value.value == Value::Float(43.5)
+        })
+        .await;
+        // 43.5 isn't an integer, the tag keeps its value
+        set_value(&plc, "line1/speed", Value::Float(44.0)).await;
+        wait_for(&tag_repo, "plc/speed", |tag| {
+            tag.value.value == Value::Integer(44)
+        })
+        .await;
+        assert!(status.lock().unwrap().connected);
+
+        // Tags turn uncertain while the server is down, the session is
+        // recovered once it's back
+        let _ = stop.send(());
+        until(server).await.unwrap().unwrap();
+        wait_for(&tag_repo, "plc/all/line1/running", |tag| {
+            tag.value.quality == Quality::Uncertain
+        })
+        .await;
+        assert!(!status.lock().unwrap().connected);
+        set_value(&plc, "line1/running", Value::Boolean(false)).await;
+        let listener = TcpListener::bind(address).await.unwrap();
+        let (stop, server) = run_server(listener, plc.clone(), data_dir.join("server")).await;
+        let tag = wait_for(&tag_repo, "plc/all/line1/running", |tag| {
+            tag.value.quality == Quality::Good
+        })
+        .await;
+        assert_eq!(tag.value.value, Value::Boolean(false));
+        set_value(&plc, "line1/speed", Value::Float(45.0)).await;
+        wait_for(&tag_repo, "plc/speed", |tag| {
+            tag.value.value == Value::Integer(45)
+        })
+        .await;
+
+        driver.stop(None);
+        let _ = stop.send(());
+        until(server).await.unwrap().unwrap();
+        let _ = std::fs::remove_dir_all(&data_dir);
+    }
+}
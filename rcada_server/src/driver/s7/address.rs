@@ -0,0 +1,267 @@
+use std::{fmt, str::FromStr};
+
+use rcada_core::value::{DataType, Value};
+
+/// Memory area of a PLC.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Area {
+    /// Process image of the inputs, `I` or `E`.
+    Input,
+    /// Process image of the outputs, `Q` or `A`.
+    Output,
+    /// Bit memory, `M`.
+    Marker,
+    DataBlock(u16),
+}
+
+impl Area {
+    /// Area code of the S7 protocol.
+    pub fn code(self) -> u8 {
+        match self {
+            Area::Input => 0x81,
+            Area::Output => 0x82,
+            Area::Marker => 0x83,
+            Area::DataBlock(_) => 0x84,
+        }
+    }
+
+    /// Number of the data block, 0 for the other areas.
+    pub fn db(self) -> u16 {
+        match self {
+            Area::DataBlock(db) => db,
+            _ => 0,
+        }
+    }
+}
+
+/// Type of a variable, which has to fit the width of its address.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum S7Type {
+    Bool,
+    Byte,
+    Sint,
+    Usint,
+    Word,
+    Int,
+    Uint,
+    Dword,
+    Dint,
+    Udint,
+    Real,
+}
+
+impl S7Type {
+    /// Bytes taken by a variable, 1 for a bit.
+    pub fn size(self) -> u32 {
+        match self {
+            S7Type::Bool | S7Type::Byte | S7Type::Sint | S7Type::Usint => 1,
+            S7Type::Word | S7Type::Int | S7Type::Uint => 2,
+            S7Type::Dword | S7Type::Dint | S7Type::Udint | S7Type::Real => 4,
+        }
+    }
+
+    pub fn data_type(self) -> DataType {
+        match self {
+            S7Type::Bool => DataType::Boolean,
+            S7Type::Real => DataType::Float,
+            _ => DataType::Integer,
+        }
+    }
+
+    /// Range of the integer types.
+    fn bounds(self) -> (i64, i64) {
+        match self {
+            S7Type::Byte | S7Type::Usint => (0, u8::MAX.into()),
+            S7Type::Sint => (i8::MIN.into(), i8::MAX.into()),
+            S7Type::Word | S7Type::Uint => (0, u16::MAX.into()),
+            S7Type::Int => (i16::MIN.into(), i16::MAX.into()),
+            S7Type::Dword | S7Type::Udint => (0, u32::MAX.into()),
+            S7Type::Dint => (i32::MIN.into(), i32::MAX.into()),
+            S7Type::Bool | S7Type::Real => (0, 0),
+        }
+    }
+
+    fn name(self) -> &'static str {
+        match self {
+            S7Type::Bool => "BOOL",
+            S7Type::Byte => "BYTE",
+            S7Type::Sint => "SINT",
+            S7Type::Usint => "USINT",
+            S7Type::Word => "WORD",
+            S7Type::Int => "INT",
+            S7Type::Uint => "UINT",
+            S7Type::Dword => "DWORD",
+            S7Type::Dint => "DINT",
+            S7Type::Udint => "UDINT",
+            S7Type::Real => "REAL",
+        }
+    }
+}
+
+/// Variable of a PLC in the syntax of STEP 7, e.g. `DB10.DBD4:REAL`,
+/// `DB1.DBX0.3`, `MW20:INT`, `I0.1` or `QB4`. The type after the colon
+/// defaults to BOOL, BYTE, WORD or DWORD by the width of the address.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct S7Address {
+    pub area: Area,
+    pub byte: u32,
+    /// Bit within the byte of a BOOL.
+    pub bit: u8,
+    pub kind: S7Type,
+}
+
+/// Addresses are 3 bytes of bits in the protocol.
+const MAX_BYTE: u32 = 0xffff;
+
+impl S7Address {
+    /// Value of the variable in the bytes read from `self.byte` on.
+    pub fn decode(&self, data: &[u8]) -> Option<Value> {
+        let bytes = data.get(..self.kind.size() as usize)?;
+        let value = match self.kind {
+            S7Type::Bool => Value::Boolean(bytes[0] & (1 << self.bit) != 0),
+            S7Type::Byte | S7Type::Usint => Value::Integer(bytes[0].into()),
+            S7Type::Sint => Value::Integer((bytes[0] as i8).into()),
+            S7Type::Word | S7Type::Uint => {
+                Value::Integer(u16::from_be_bytes([bytes[0], bytes[1]]).into())
+            },
+            S7Type::Int => Value::Integer(i16::from_be_bytes([bytes[0], bytes[1]]).into()),
+            S7Type::Dword | S7Type::Udint => {
+                Value::Integer(u32::from_be_bytes(bytes.try_into().ok()?).into())
+            },
+            S7Type::Dint => Value::Integer(i32::from_be_bytes(bytes.try_into().ok()?).into()),
+            S7Type::Real => Value::Float(f32::from_be_bytes(bytes.try_into().ok()?)),
+        };
+        Some(value)
+    }
+
+    /// Bytes written for `value`, `None` if it's out of the range of the
+    /// type. A BOOL is a single byte of 0 or 1 written as a bit.
+    pub fn encode(&self, value: &Value) -> Option<Vec<u8>> {
+        match (self.kind, value.convert(self.kind.data_type())?) {
+            (S7Type::Bool, Value::Boolean(on)) => Some(vec![on.into()]),
+            (S7Type::Real, Value::Float(value)) => Some(value.to_be_bytes().to_vec()),
+            (kind, Value::Integer(value)) => {
+                let (min, max) = kind.bounds();
+                if !(min..=max).contains(&value) {
+                    return None;
+                }
+                let size = kind.size() as usize;
+                Some(value.to_be_bytes()[8 - size..].to_vec())
+            },
+            _ => None,
+        }
+    }
+}
+
+impl FromStr for S7Address {
+    type Err = String;
+
+    fn from_str(address: &str) -> Result<Self, String> {
+        let invalid = |reason: &str| format!("{address}: {reason}");
+        let upper = address.trim().to_ascii_uppercase();
+        let (location, kind) = match upper.split_once(':') {
+            Some((location, kind)) => (location, Some(kind)),
+            None => (upper.as_str(), None),
+        };
+
+        let (area, rest) = if let Some(rest) = location.strip_prefix("DB") {
+            let (db, rest) = rest
+                .split_once('.')
+                .ok_or_else(|| invalid("expected DB<number>.DB<X|B|W|D><offset>"))?;
+            let db = db
+                .parse()
+                .map_err(|_| invalid("invalid data block number"))?;
+            let rest = rest
+                .strip_prefix("DB")
+                .ok_or_else(|| invalid("expected DB<X|B|W|D> after the data block"))?;
+            (Area::DataBlock(db), rest)
+        } else {
+            let mut chars = location.chars();
+            let area = match chars.next() {
+                Some('I' | 'E') => Area::Input,
+                Some('Q' | 'A') => Area::Output,
+                Some('M') => Area::Marker,
+                _ => return Err(invalid("expected an area of DB, I, E, Q, A or M")),
+            };
+            (area, chars.as_str())
+        };
+
+        let (width, offset) = match rest.chars().next() {
+            Some(width @ ('X' | 'B' | 'W' | 'D')) => (width, &rest[1..]),
+            _ if matches!(area, Area::DataBlock(_)) => {
+                return Err(invalid("expected DBX, DBB, DBW or DBD"));
+            },
+            _ => ('X', rest),
+        };
+        let (byte, bit) = match (width, offset.split_once('.')) {
+            ('X', Some((byte, bit))) => {
+                let bit = bit.parse().map_err(|_| invalid("invalid bit"))?;
+                if bit > 7 {
+                    return Err(invalid("bit must be 0 to 7"));
+                }
+                (byte, bit)
+            },
+            ('X', None) => return Err(invalid("expected <byte>.<bit> for a bit")),
+            (_, Some(_)) => return Err(invalid("only bits have a bit number")),
+            (_, None) => (offset, 0),
+        };
+        let byte: u32 = byte.parse().map_err(|_| invalid("invalid offset"))?;
+        if byte > MAX_BYTE {
+            return Err(invalid("offset is too large"));
+        }
+
+        let kind = match (width, kind) {
+            ('X', None | Some("BOOL")) => S7Type::Bool,
+            ('B', None | Some("BYTE")) => S7Type::Byte,
+            ('B', Some("SINT")) => S7Type::Sint,
+            ('B', Some("USINT")) => S7Type::Usint,
+            ('W', None | Some("WORD")) => S7Type::Word,
+            ('W', Some("INT")) => S7Type::Int,
+            ('W', Some("UINT")) => S7Type::Uint,
+            ('D', None | Some("DWORD")) => S7Type::Dword,
+            ('D', Some("DINT")) => S7Type::Dint,
+            ('D', Some("UDINT")) => S7Type::Udint,
+            ('D', Some("REAL")) => S7Type::Real,
+            (_, Some(kind)) => {
+                return Err(invalid(&format!("type {kind} doesn't fit the address")));
+            },
+            _ => unreachable!(),
+        };
+        Ok(Self {
+            area,
+            byte,
+            bit,
+            kind,
+        })
+    }
+}
+
+impl fmt::Display for S7Address {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let width = match (self.kind, self.kind.size()) {
+            (S7Type::Bool, _) => 'X',
+            (_, 1) => 'B',
+            (_, 2) => 'W',
+            _ => 'D',
+        };
+        match self.area {
+            Area::DataBlock(db) => write!(f, "DB{db}.DB{width}{}", self.byte)?,
+            area => {
+                let letter = match area {
+                    Area::Input => 'I',
+                    Area::Output => 'Q',
+                    _ => 'M',
+                };
+                if width == 'X' {
+                    write!(f, "{letter}{}", self.byte)?;
+                } else {
+                    write!(f, "{letter}{width}{}", self.byte)?;
+                }
+            },
+        }
+        if self.kind == S7Type::Bool {
+            write!(f, ".{}", self.bit)?;
+        }
+        write!(f, ":{}", self.kind.name())
+    }
+}
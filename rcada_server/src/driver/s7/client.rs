@@ -0,0 +1,334 @@
+use std::io;
+
+use tokio::{
+    io::{AsyncRead, AsyncReadExt, AsyncWriteExt},
+    net::TcpStream,
+};
+
+use super::address::{Area, S7Address, S7Type};
+
+const TPKT_VERSION: u8 = 3;
+const TPKT_HEADER_LEN: usize = 4;
+pub(super) const COTP_CONNECTION_REQUEST: u8 = 0xe0;
+pub(super) const COTP_CONNECTION_CONFIRM: u8 = 0xd0;
+pub(super) const COTP_DATA: u8 = 0xf0;
+/// Last data unit of a message.
+pub(super) const COTP_EOT: u8 = 0x80;
+/// Largest data unit the client takes, 1024 bytes.
+const COTP_TPDU_SIZE: u8 = 0x0a;
+
+pub(super) const PROTOCOL_ID: u8 = 0x32;
+pub(super) const JOB: u8 = 0x01;
+pub(super) const ACK_DATA: u8 = 0x03;
+pub(super) const SETUP_COMMUNICATION: u8 = 0xf0;
+pub(super) const READ_VAR: u8 = 0x04;
+pub(super) const WRITE_VAR: u8 = 0x05;
+pub(super) const RETURN_SUCCESS: u8 = 0xff;
+pub(super) const TRANSPORT_BIT: u8 = 0x01;
+pub(super) const TRANSPORT_BYTE: u8 = 0x02;
+pub(super) const DATA_BIT: u8 = 0x03;
+/// Data of bytes, words or double words, with its length in bits.
+pub(super) const DATA_BYTE: u8 = 0x04;
+
+/// PDU length asked for, the PLC answers with the one it supports.
+const REQUESTED_PDU_LEN: u16 = 960;
+/// Smallest PDU length of the S7 CPUs.
+const MIN_PDU_LEN: u16 = 240;
+/// Variables of a request at most, the limit of the S7-300.
+pub const MAX_ITEMS: usize = 20;
+/// Header of a job, the function and the count of variables.
+pub const REQUEST_OVERHEAD: usize = 12;
+/// Header of an acknowledgement, the function and the count of variables.
+pub const RESPONSE_OVERHEAD: usize = 14;
+/// Specification of a variable in a request.
+pub const ITEM_LEN: usize = 12;
+/// Return code, transport size and length before the data of a variable.
+pub const DATA_ITEM_OVERHEAD: usize = 4;
+
+/// Bytes of an area read in one variable of a request.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Item {
+    pub area: Area,
+    pub start: u32,
+    pub len: u16,
+}
+
+/// Minimal S7 client over ISO-on-TCP (RFC 1006) with a PG connection to the
+/// CPU in `rack` and `slot`. Requests are sequential, the caller bounds them
+/// with a timeout.
+pub struct S7Client {
+    stream: TcpStream,
+    pdu_len: usize,
+    pdu_ref: u16,
+}
+
+impl S7Client {
+    pub async fn connect(address: &str, rack: u8, slot: u8) -> io::Result<Self> {
+        let mut stream = TcpStream::connect(address).await?;
+        stream.set_nodelay(true)?;
+
+        let mut request = vec![
+            COTP_CONNECTION_REQUEST,
+            0x00,
+            0x00,
+            0x00,
+            0x01,
+            0x00,
+            0xc0,
+            0x01,
+            COTP_TPDU_SIZE,
+        ];
+        // Local TSAP, remote TSAP of a PG connection to the rack and slot
+        request.extend_from_slice(&[0xc1, 0x02, 0x01, 0x00]);
+        request.extend_from_slice(&[0xc2, 0x02, 0x01, (rack << 5) | slot]);
+        stream.write_all(&tpkt(&request, &[])).await?;
+        let confirm = read_tpkt(&mut stream).await?;
+        if confirm.get(1) != Some(&COTP_CONNECTION_CONFIRM) {
+            return Err(io::Error::new(
+                io::ErrorKind::ConnectionRefused,
+                format!("PLC refused the connection to rack {rack}, slot {slot}"),
+            ));
+        }
+
+        let mut client = Self {
+            stream,
+            pdu_len: REQUESTED_PDU_LEN.into(),
+            pdu_ref: 0,
+        };
+        let mut params = vec![SETUP_COMMUNICATION, 0x00, 0x00, 0x01, 0x00, 0x01];
+        params.extend_from_slice(&REQUESTED_PDU_LEN.to_be_bytes());
+        let (params, _) = client.exchange(&params, &[]).await?;
+        let pdu_len = match params.as_slice() {
+            [SETUP_COMMUNICATION, _, _, _, _, _, hi, lo, ..] => u16::from_be_bytes([*hi, *lo]),
+            _ => return Err(malformed()),
+        };
+        if pdu_len < MIN_PDU_LEN {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("PDU length {pdu_len} is too small"),
+            ));
+        }
+        client.pdu_len = pdu_len.into();
+        Ok(client)
+    }
+
+    /// PDU length negotiated with the PLC, which bounds requests and
+    /// responses.
+    pub fn pdu_len(&self) -> usize {
+        self.pdu_len
+    }
+
+    /// Reads the items in one request, giving the bytes of each or the
+    /// reason the PLC couldn't read it.
+    pub async fn read(&mut self, items: &[Item]) -> io::Result<Vec<Result<Vec<u8>, String>>> {
+        let mut params = vec![READ_VAR, items.len() as u8];
+        for item in items {
+            params.extend_from_slice(&item_spec(
+                TRANSPORT_BYTE,
+                item.len,
+                item.area,
+                item.start * 8,
+            ));
+        }
+        let (_, data) = self.exchange(&params, &[]).await?;
+
+        let mut results = Vec::with_capacity(items.len());
+        let mut rest = data.as_slice();
+        for (position, item) in items.iter().enumerate() {
+            let [code, transport, hi, lo, tail @ ..] = rest else {
+                return Err(malformed());
+            };
+            let len = data_len(*transport, u16::from_be_bytes([*hi, *lo]));
+            if tail.len() < len {
+                return Err(malformed());
+            }
+            results.push(if *code != RETURN_SUCCESS {
+                Err(return_code(*code))
+            } else if len != usize::from(item.len) {
+                Err(format!("{len} bytes instead of {}", item.len))
+            } else {
+                Ok(tail[..len].to_vec())
+            });
+            // Variables after the last one start at an even offset
+            let fill = usize::from(len % 2 == 1 && position + 1 < items.len());
+            rest = tail.get(len + fill..).unwrap_or_default();
+        }
+        Ok(results)
+    }
+
+    /// Writes the bytes of a variable, or its bit for a BOOL, giving the
+    /// reason if the PLC refused it.
+    pub async fn write(
+        &mut self,
+        address: &S7Address,
+        value: &[u8],
+    ) -> io::Result<Result<(), String>> {
+        let (transport, len, start, data_transport, bits) = if address.kind == S7Type::Bool {
+            let start = address.byte * 8 + u32::from(address.bit);
+            (TRANSPORT_BIT, 1, start, DATA_BIT, 1)
+        } else {
+            let len = value.len() as u16;
+            (TRANSPORT_BYTE, len, address.byte * 8, DATA_BYTE, len * 8)
+        };
+        let mut params = vec![WRITE_VAR, 1];
+        params.extend_from_slice(&item_spec(transport, len, address.area, start));
+        let mut data = vec![0x00, data_transport];
+        data.extend_from_slice(&bits.to_be_bytes());
+        data.extend_from_slice(value);
+        let (_, data) = self.exchange(&params, &data).await?;
+        match data.as_slice() {
+            [RETURN_SUCCESS, ..] => Ok(Ok(())),
+            [code, ..] => Ok(Err(return_code(*code))),
+            [] => Err(malformed()),
+        }
+    }
+
+    /// Sends a job and returns the parameters and data of its
+    /// acknowledgement.
+    async fn exchange(&mut self, params: &[u8], data: &[u8]) -> io::Result<(Vec<u8>, Vec<u8>)> {
+        self.pdu_ref = self.pdu_ref.wrapping_add(1);
+        let mut pdu = vec![PROTOCOL_ID, JOB, 0x00, 0x00];
+        pdu.extend_from_slice(&self.pdu_ref.to_be_bytes());
+        pdu.extend_from_slice(&(params.len() as u16).to_be_bytes());
+        pdu.extend_from_slice(&(data.len() as u16).to_be_bytes());
+        pdu.extend_from_slice(params);
+        pdu.extend_from_slice(data);
+        self.stream
+            .write_all(&tpkt(&[COTP_DATA, COTP_EOT], &pdu))
+            .await?;
+
+        let response = receive(&mut self.stream).await?;
+        let [
+            PROTOCOL_ID,
+            ACK_DATA,
+            _,
+            _,
+            r1,
+            r2,
+            p1,
+            p2,
+            d1,
+            d2,
+            class,
+            code,
+            body @ ..,
+        ] = response.as_slice()
+        else {
+            return Err(malformed());
+        };
+        if u16::from_be_bytes([*r1, *r2]) != self.pdu_ref {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "response to another request",
+            ));
+        }
+        if (*class, *code) != (0, 0) {
+            return Err(io::Error::other(format!(
+                "PLC refused the request: error class {class:#04x}, code {code:#04x}"
+            )));
+        }
+        let params_len = usize::from(u16::from_be_bytes([*p1, *p2]));
+        let data_len = usize::from(u16::from_be_bytes([*d1, *d2]));
+        if body.len() != params_len + data_len {
+            return Err(malformed());
+        }
+        let (params, data) = body.split_at(params_len);
+        Ok((params.to_vec(), data.to_vec()))
+    }
+}
+
+/// Specification of `len` elements of `transport` from the bit `start`.
+pub(super) fn item_spec(transport: u8, len: u16, area: Area, start: u32) -> [u8; ITEM_LEN] {
+    let [len_hi, len_lo] = len.to_be_bytes();
+    let [db_hi, db_lo] = area.db().to_be_bytes();
+    let [_, a1, a2, a3] = start.to_be_bytes();
+    [
+        0x12,
+        0x0a,
+        0x10,
+        transport,
+        len_hi,
+        len_lo,
+        db_hi,
+        db_lo,
+        area.code(),
+        a1,
+        a2,
+        a3,
+    ]
+}
+
+/// Bytes of data with a length given in bits or in bytes, depending on the
+/// transport size.
+pub(super) fn data_len(transport: u8, len: u16) -> usize {
+    match transport {
+        DATA_BIT | DATA_BYTE | 0x05 => usize::from(len).div_ceil(8),
+        _ => len.into(),
+    }
+}
+
+fn return_code(code: u8) -> String {
+    match code {
+        0x01 => "hardware fault".to_string(),
+        0x03 => "access denied".to_string(),
+        0x05 => "address out of range".to_string(),
+        0x06 => "data type not supported".to_string(),
+        0x07 => "data type inconsistent".to_string(),
+        0x0a => "object doesn't exist".to_string(),
+        code => format!("return code {code:#04x}"),
+    }
+}
+
+fn malformed() -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, "malformed response")
+}
+
+/// Packet of a COTP data unit, `cotp` without its length.
+pub(super) fn tpkt(cotp: &[u8], payload: &[u8]) -> Vec<u8> {
+    let len = TPKT_HEADER_LEN + 1 + cotp.len() + payload.len();
+    let mut packet = vec![TPKT_VERSION, 0x00];
+    packet.extend_from_slice(&(len as u16).to_be_bytes());
+    packet.push(cotp.len() as u8);
+    packet.extend_from_slice(cotp);
+    packet.extend_from_slice(payload);
+    packet
+}
+
+/// Data unit of a packet, starting with the length of its COTP header.
+pub(super) async fn read_tpkt(stream: &mut (impl AsyncRead + Unpin)) -> io::Result<Vec<u8>> {
+    let mut header = [0u8; TPKT_HEADER_LEN];
+    stream.read_exact(&mut header).await?;
+    let len = usize::from(u16::from_be_bytes([header[2], header[3]]));
+    if header[0] != TPKT_VERSION || len < TPKT_HEADER_LEN + 2 {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            "not an ISO-on-TCP packet",
+        ));
+    }
+    let mut unit = vec![0u8; len - TPKT_HEADER_LEN];
+    stream.read_exact(&mut unit).await?;
+    Ok(unit)
+}
+
+/// Payload of the data units up to the last one of a message.
+pub(super) async fn receive(stream: &mut (impl AsyncRead + Unpin)) -> io::Result<Vec<u8>> {
+    let mut payload = Vec::new();
+    loop {
+        let unit = read_tpkt(stream).await?;
+        let header_len = usize::from(unit[0]) + 1;
+        match unit.get(..header_len) {
+            Some([_, COTP_DATA, last, ..]) => {
+                payload.extend_from_slice(&unit[header_len..]);
+                if last & COTP_EOT != 0 {
+                    return Ok(payload);
+                }
+            },
+            _ => {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    "expected a COTP data unit",
+                ));
+            },
+        }
+    }
+}
@@ -0,0 +1,787 @@
+//! Driver for Siemens S7-300/400/1200/1500 PLCs over ISO-on-TCP, reading
+//! and writing variables of data blocks and of the I, Q and M areas.
+
+mod address;
+mod client;
+
+use std::{collections::HashSet, io, time::Duration};
+
+use chrono::{DateTime, Utc};
+use ractor::{Actor, ActorProcessingErr, ActorRef};
+use serde::{Deserialize, Serialize};
+use tokio::{
+    sync::{broadcast, broadcast::error::RecvError},
+    task::JoinHandle,
+    time::timeout,
+};
+
+use rcada_core::{
+    tag::{Quality, Tag, TagMeta, TagName, TagValue},
+    unit::Unit,
+    value::{DataType, Value},
+};
+
+pub use address::{Area, S7Address, S7Type};
+use client::{
+    DATA_ITEM_OVERHEAD, ITEM_LEN, Item, MAX_ITEMS, REQUEST_OVERHEAD, RESPONSE_OVERHEAD, S7Client,
+};
+
+use crate::{
+    actor::{self, Mailbox},
+    driver::{DriverStatus, SharedDriverStatus, TagWriter},
+    metrics::metrics,
+};
+
+/// Unused bytes between two variables read as one range, reading them is
+/// cheaper than another variable in the request.
+const MAX_GAP: u32 = 16;
+
+/// Polls the variables of an S7 PLC and writes them into tags. Tags of
+/// writable variables written through the API are written to the PLC.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct S7Config {
+    pub name: String,
+    /// Address of the PLC, e.g. `192.168.0.10:102`.
+    pub address: String,
+    #[serde(default)]
+    pub rack: u8,
+    /// Slot of the CPU, 2 for an S7-300 and 1 (or 0) for an S7-1200 or
+    /// S7-1500.
+    #[serde(default = "default_slot")]
+    pub slot: u8,
+    #[serde(default = "default_poll_interval_ms")]
+    pub poll_interval_ms: u64,
+    /// Timeout of connecting and reading all variables of a poll, or of a
+    /// write, the connection is reopened after it.
+    #[serde(default = "default_timeout_ms")]
+    pub timeout_ms: u64,
+    #[serde(default)]
+    pub points: Vec<S7Point>,
+}
+
+/// Variable of the PLC written to a tag. Missing tags are created with the
+/// type of the variable, read-only unless `writable`.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct S7Point {
+    pub tag: String,
+    /// Variable in the syntax of STEP 7, see [`S7Address`].
+    pub address: String,
+    #[serde(default)]
+    pub writable: bool,
+}
+
+fn default_slot() -> u8 {
+    1
+}
+
+fn default_poll_interval_ms() -> u64 {
+    1000
+}
+
+fn default_timeout_ms() -> u64 {
+    1000
+}
+
+impl S7Config {
+    pub fn validate(&self) -> Vec<String> {
+        let mut errors = Vec::new();
+        if self.name.is_empty() {
+            errors.push("driver name is empty".to_string());
+        }
+        if self.address.is_empty() {
+            errors.push(format!("driver {}: address is empty", self.name));
+        }
+        if self.rack > 7 {
+            errors.push(format!("driver {}: rack must be 0 to 7", self.name));
+        }
+        if self.slot > 31 {
+            errors.push(format!("driver {}: slot must be 0 to 31", self.name));
+        }
+        if self.poll_interval_ms == 0 {
+            errors.push(format!(
+                "driver {}: poll_interval_ms must be positive",
+                self.name
+            ));
+        }
+        if self.timeout_ms == 0 {
+            errors.push(format!("driver {}: timeout_ms must be positive", self.name));
+        }
+        let mut tags = HashSet::new();
+        for point in &self.points {
+            if point.tag.is_empty() {
+                errors.push(format!(
+                    "driver {}: {}: tag is empty",
+                    self.name, point.address
+                ));
+            }
+            if let Err(e) = point.address.parse::<S7Address>() {
+                errors.push(format!("driver {}: {}", self.name, e));
+            }
+            if !tags.insert(point.tag.as_str()) {
+                errors.push(format!(
+                    "driver {}: tag {} is mapped more than once",
+                    self.name, point.tag
+                ));
+            }
+        }
+        errors
+    }
+}
+
+/// Point with its parsed address and the data type of its tag.
+#[derive(Debug, Clone)]
+struct Variable {
+    tag: TagName,
+    address: S7Address,
+    data_type: DataType,
+    writable: bool,
+}
+
+/// Value of a variable read with the time of the read, or why it couldn't
+/// be read.
+type Reading = (TagName, Result<Value, String>, DateTime<Utc>);
+
+/// Range of bytes read in one item of a request, with the variables in it.
+#[derive(Debug, Clone, PartialEq)]
+struct Block {
+    item: Item,
+    variables: Vec<usize>,
+}
+
+impl Block {
+    fn end(&self) -> u32 {
+        self.item.start + u32::from(self.item.len)
+    }
+}
+
+/// Groups the variables into the fewest requests: variables close to each
+/// other in an area are read as one range, and as many ranges as fit the
+/// PDU length in one request.
+fn plan(variables: &[Variable], pdu_len: usize) -> Vec<Vec<Block>> {
+    let max_len = (pdu_len - RESPONSE_OVERHEAD - DATA_ITEM_OVERHEAD) as u32;
+    let mut order: Vec<usize> = (0..variables.len()).collect();
+    order.sort_by_key(|&i| (variables[i].address.area, variables[i].address.byte));
+
+    let mut blocks: Vec<Block> = Vec::new();
+    for i in order {
+        let address = variables[i].address;
+        let end = address.byte + address.kind.size();
+        match blocks.last_mut() {
+            Some(block)
+                if block.item.area == address.area
+                    && address.byte <= block.end() + MAX_GAP
+                    && end.max(block.end()) - block.item.start <= max_len =>
+            {
+                block.item.len = (end.max(block.end()) - block.item.start) as u16;
+                block.variables.push(i);
+            },
+            _ => blocks.push(Block {
+                item: Item {
+                    area: address.area,
+                    start: address.byte,
+                    len: address.kind.size() as u16,
+                },
+                variables: vec![i],
+            }),
+        }
+    }
+
+    let mut requests: Vec<Vec<Block>> = Vec::new();
+    let (mut request_len, mut response_len) = (0, 0);
+    for block in blocks {
+        // Odd lengths are filled up to the next variable
+        let len = usize::from(block.item.len);
+        let data_len = DATA_ITEM_OVERHEAD + len + len % 2;
+        match requests.last_mut() {
+            Some(request)
+                if request.len() < MAX_ITEMS
+                    && request_len + ITEM_LEN <= pdu_len
+                    && response_len + data_len <= pdu_len =>
+            {
+                request_len += ITEM_LEN;
+                response_len += data_len;
+                request.push(block);
+            },
+            _ => {
+                request_len = REQUEST_OVERHEAD + ITEM_LEN;
+                response_len = RESPONSE_OVERHEAD + data_len;
+                requests.push(vec![block]);
+            },
+        }
+    }
+    requests
+}
+
+/// Polls an S7 PLC and keeps the tags of its variables up to date.
+pub struct S7Driver;
+
+pub struct S7Arguments {
+    pub config: S7Config,
+    pub tag_repo: ActorRef<actor::tag::Message>,
+    pub status: SharedDriverStatus,
+}
+
+pub struct S7State {
+    config: S7Config,
+    writer: TagWriter,
+    status: SharedDriverStatus,
+    client: Option<S7Client>,
+    updates: JoinHandle<()>,
+    /// Variables whose tag exists, points whose tag can't be created are
+    /// left out.
+    variables: Vec<Variable>,
+    /// Requests of a poll for the PDU length of the connection.
+    requests: Vec<Vec<Block>>,
+}
+
+#[derive(Debug)]
+pub enum Message {
+    Poll,
+    ValueUpdate(Box<Tag>),
+}
+
+#[cfg(feature = "cluster")]
+impl ractor::Message for Message {}
+
+#[cfg_attr(feature = "async-trait", ractor::async_trait)]
+impl Actor for S7Driver {
+    type Msg = Message;
+    type State = S7State;
+    type Arguments = S7Arguments;
+
+    async fn pre_start(
+        &self,
+        myself: ActorRef<Self::Msg>,
+        args: Self::Arguments,
+    ) -> Result<Self::State, ActorProcessingErr> {
+        let (command, mut reply) = actor::tag::Message::subscribe();
+        args.tag_repo.enqueue(command)?;
+        let receiver = reply
+            .recv()
+            .await
+            .ok_or("tag repository didn't answer the subscription")?;
+        let updates = tokio::spawn(forward_updates(receiver, myself.clone()));
+
+        tracing::info!(
+            "driver {}: polling {} variables of {} every {} ms",
+            args.config.name,
+            args.config.points.len(),
+            args.config.address,
+            args.config.poll_interval_ms
+        );
+        let mut state = S7State {
+            writer: TagWriter::new(&args.config.name, args.tag_repo).remembering_writes(),
+            config: args.config,
+            status: args.status,
+            client: None,
+            updates,
+            variables: Vec::new(),
+            requests: Vec::new(),
+        };
+        for point in state.config.points.clone() {
+            let variable = match point.address.parse::<S7Address>() {
+                Ok(address) => state.ensure_tag(&point, address).await,
+                Err(e) => Err(e),
+            };
+            match variable {
+                Ok(variable) => state.variables.push(variable),
+                Err(e) => tracing::warn!("driver {}: {}", state.config.name, e),
+            }
+        }
+        myself.send_interval(Duration::from_millis(state.config.poll_interval_ms), || {
+            Message::Poll
+        });
+        Ok(state)
+    }
+
+    async fn post_stop(
+        &self,
+        _myself: ActorRef<Self::Msg>,
+        state: &mut Self::State,
+    ) -> Result<(), ActorProcessingErr> {
+        state.updates.abort();
+        Ok(())
+    }
+
+    async fn handle(
+        &self,
+        _myself: ActorRef<Self::Msg>,
+        message: Self::Msg,
+        state: &mut Self::State,
+    ) -> Result<(), ActorProcessingErr> {
+        match message {
+            Message::Poll => {
+                let deadline = Duration::from_millis(state.config.timeout_ms);
+                // Only the exchanges with the PLC are bounded, replies of the
+                // tag repository are always awaited
+                let (result, error) = match timeout(deadline, state.read()).await {
+                    Ok(Ok(readings)) => {
+                        state.apply(readings).await;
+                        ("ok", None)
+                    },
+                    Ok(Err(e)) => {
+                        tracing::warn!("driver {}: poll failed: {}", state.config.name, e);
+                        state.disconnected().await;
+                        ("error", Some(e.to_string()))
+                    },
+                    Err(_) => {
+                        tracing::warn!("driver {}: poll timed out", state.config.name);
+                        state.disconnected().await;
+                        ("timeout", Some("poll timed out".to_string()))
+                    },
+                };
+                *state.status.lock().unwrap_or_else(|e| e.into_inner()) = DriverStatus {
+                    connected: state.client.is_some(),
+                    last_poll: Some(Utc::now()),
+                    last_error: error,
+                };
+                metrics()
+                    .driver_polls
+                    .with_label_values(&[state.config.name.as_str(), result])
+                    .inc();
+            },
+            Message::ValueUpdate(tag) => state.write(*tag).await,
+        }
+        Ok(())
+    }
+}
+
+async fn forward_updates(mut updates: broadcast::Receiver<Tag>, driver: ActorRef<Message>) {
+    loop {
+        match updates.recv().await {
+            Ok(tag) => {
+                if driver
+                    .send_message(Message::ValueUpdate(Box::new(tag)))
+                    .is_err()
+                {
+                    break;
+                }
+            },
+            // Writes among the dropped updates are lost, like writes while
+            // the PLC is unreachable
+            Err(RecvError::Lagged(count)) => {
+                tracing::warn!("S7 driver missed {count} value updates");
+            },
+            Err(RecvError::Closed) => break,
+        }
+    }
+}
+
+impl S7State {
+    /// Reads every variable, connecting first if needed.
+    async fn read(&mut self) -> io::Result<Vec<Reading>> {
+        if self.client.is_none() {
+            let client =
+                S7Client::connect(&self.config.address, self.config.rack, self.config.slot).await?;
+            tracing::info!(
+                "driver {}: connected to {} with a PDU length of {}",
+                self.config.name,
+                self.config.address,
+                client.pdu_len()
+            );
+            self.requests = plan(&self.variables, client.pdu_len());
+            self.client = Some(client);
+        }
+        let Some(client) = self.client.as_mut() else {
+            return Ok(Vec::new());
+        };
+
+        let mut readings = Vec::new();
+        for request in &self.requests {
+            let items: Vec<Item> = request.iter().map(|block| block.item).collect();
+            let results = client.read(&items).await?;
+            let now = Utc::now();
+            for (block, result) in request.iter().zip(results) {
+                for &i in &block.variables {
+                    let variable = &self.variables[i];
+                    let value = match &result {
+                        Ok(data) => {
+                            let offset = (variable.address.byte - block.item.start) as usize;
+                            variable
+                                .address
+                                .decode(&data[offset..])
+                                .and_then(|value| value.convert(variable.data_type))
+                                .ok_or_else(|| {
+                                    format!(
+                                        "{} can't be converted to {:?}",
+                                        variable.address, variable.data_type
+                                    )
+                                })
+                        },
+                        Err(e) => Err(format!("reading {} failed: {}", variable.address, e)),
+                    };
+                    readings.push((variable.tag.clone(), value, now));
+                }
+            }
+        }
+        Ok(readings)
+    }
+
+    /// Writes the values read into their tags, variables that couldn't be
+    /// read turn their tags `bad`.
+    async fn apply(&mut self, readings: Vec<Reading>) {
+        for (tag, value, now) in readings {
+            let result = match value {
+                Ok(value) => {
+                    let value = TagValue {
+                        value,
+                        timestamp: Some(now),
+                        quality: Quality::Good,
+                    };
+                    self.writer.update(&tag, value).await
+                },
+                Err(e) => {
+                    tracing::warn!("driver {}: tag {}: {}", self.config.name, tag, e);
+                    self.writer.set_quality(&tag, Quality::Bad, now).await
+                },
+            };
+            if let Err(e) = result {
+                tracing::warn!("driver {}: {}", self.config.name, e);
+            }
+        }
+    }
+
+    /// Writes a tag written by someone else than the driver to its
+    /// variable.
+    async fn write(&mut self, tag: Tag) {
+        if self.writer.is_own_write(&tag) {
+            return;
+        }
+        let Some(variable) = self
+            .variables
+            .iter()
+            .find(|variable| variable.writable && variable.tag == tag.name)
+        else {
+            return;
+        };
+        let address = variable.address;
+        let Some(data) = address.encode(&tag.value.value) else {
+            tracing::warn!(
+                "driver {}: can't write {:?} to {}",
+                self.config.name,
+                tag.value.value,
+                address
+            );
+            return;
+        };
+        let Some(client) = self.client.as_mut() else {
+            tracing::warn!(
+                "driver {}: can't write {}, the PLC is unreachable",
+                self.config.name,
+                tag.name
+            );
+            return;
+        };
+        let deadline = Duration::from_millis(self.config.timeout_ms);
+        match timeout(deadline, client.write(&address, &data)).await {
+            Ok(Ok(Ok(()))) => {
+                tracing::debug!("driver {}: wrote {}", self.config.name, address);
+            },
+            Ok(Ok(Err(e))) => {
+                tracing::warn!(
+                    "driver {}: writing {} failed: {}",
+                    self.config.name,
+                    address,
+                    e
+                );
+            },
+            Ok(Err(e)) => {
+                tracing::warn!(
+                    "driver {}: writing {} failed: {}",
+                    self.config.name,
+                    address,
+                    e
+                );
+                self.disconnected().await;
+            },
+            Err(_) => {
+                tracing::warn!("driver {}: writing {} timed out", self.config.name, address);
+                self.disconnected().await;
+            },
+        }
+    }
+
+    /// Variable of a point, with the data type of its tag. The tag is
+    /// created with the type of the variable if it's missing.
+    async fn ensure_tag(&self, point: &S7Point, address: S7Address) -> Result<Variable, String> {
+        let data_type = self
+            .writer
+            .ensure_tag(&point.tag, || {
+                Ok(TagMeta {
+                    description: format!("S7 {} of {}", address, self.config.address),
+                    read_only: !point.writable,
+                    ..TagMeta::new(Unit::None, address.kind.data_type())
+                })
+            })
+            .await?;
+        Ok(Variable {
+            tag: TagName::from(point.tag.as_str()),
+            address,
+            data_type,
+            writable: point.writable,
+        })
+    }
+
+    /// Drops the connection and marks the tags `uncertain` if it was open,
+    /// the next poll connects again.
+    async fn disconnected(&mut self) {
+        if self.client.take().is_some() {
+            let tags = self
+                .variables
+                .iter()
+                .map(|variable| variable.tag.clone())
+                .collect();
+            self.writer.set_uncertain(tags).await;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        collections::HashMap,
+        sync::{Arc, Mutex},
+    };
+
+    use tokio::{io::AsyncWriteExt, net::TcpListener, task::JoinSet};
+
+    use super::{client::*, *};
+    use crate::driver::testing::{set_value, tag_repo, until, wait_for};
+
+    /// Memory of the stand-in PLC by area code and data block.
+    type Memory = Arc<Mutex<HashMap<(u8, u16), Vec<u8>>>>;
+
+    fn store(memory: &Memory, area: Area, offset: usize, bytes: &[u8]) {
+        let mut memory = memory.lock().unwrap();
+        let area = memory
+            .entry((area.code(), area.db()))
+            .or_insert_with(|| vec![0; 1024]);
+        area[offset..offset + bytes.len()].copy_from_slice(bytes);
+    }
+
+    fn load(memory: &Memory, area: Area, offset: usize, len: usize) -> Vec<u8> {
+        let memory = memory.lock().unwrap();
+        memory
+            .get(&(area.code(), area.db()))
+            .map_or(vec![0; len], |area| area[offset..offset + len].to_vec())
+    }
+
+    /// Stand-in PLC with a PDU length of 240, serving `memory` and counting
+    /// the variables of every read request, until the task is aborted.
+    fn run_plc(
+        listener: TcpListener,
+        memory: Memory,
+        reads: Arc<Mutex<Vec<usize>>>,
+    ) -> JoinHandle<()> {
+        tokio::spawn(async move {
+            let mut connections = JoinSet::new();
+            while let Ok((mut stream, _)) = listener.accept().await {
+                let memory = memory.clone();
+                let reads = reads.clone();
+                connections.spawn(async move {
+                    let request = read_tpkt(&mut stream).await.unwrap();
+                    assert_eq!(request[1], COTP_CONNECTION_REQUEST);
+                    let confirm = [COTP_CONNECTION_CONFIRM, 0x00, 0x01, 0x00, 0x01, 0x00];
+                    stream.write_all(&tpkt(&confirm, &[])).await.unwrap();
+                    while let Ok(pdu) = receive(&mut stream).await {
+                        let (params, data) = serve(&pdu, &memory, &reads);
+                        let mut response = vec![PROTOCOL_ID, ACK_DATA, 0x00, 0x00, pdu[4], pdu[5]];
+                        response.extend_from_slice(&(params.len() as u16).to_be_bytes());
+                        response.extend_from_slice(&(data.len() as u16).to_be_bytes());
+                        response.extend_from_slice(&[0x00, 0x00]);
+                        response.extend_from_slice(&params);
+                        response.extend_from_slice(&data);
+                        let packet = tpkt(&[COTP_DATA, COTP_EOT], &response);
+                        if stream.write_all(&packet).await.is_err() {
+                            break;
+                        }
+                    }
+                });
+            }
+        })
+    }
+
+    /// Parameters and data of the acknowledgement of a job.
+    fn serve(pdu: &[u8], memory: &Memory, reads: &Mutex<Vec<usize>>) -> (Vec<u8>, Vec<u8>) {
+        assert_eq!(pdu[..2], [PROTOCOL_ID, JOB]);
+        let params_len = usize::from(u16::from_be_bytes([pdu[6], pdu[7]]));
+        let (params, data) = pdu[10..].split_at(params_len);
+        let area = |spec: &[u8]| {
+            let db = u16::from_be_bytes([spec[6], spec[7]]);
+            match spec[8] {
+                0x81 => Area::Input,
+                0x82 => Area::Output,
+                0x83 => Area::Marker,
+                _ => Area::DataBlock(db),
+            }
+        };
+        let start = |spec: &[u8]| u32::from_be_bytes([0, spec[9], spec[10], spec[11]]) as usize;
+        match params[0] {
+            SETUP_COMMUNICATION => {
+                let mut params = params.to_vec();
+                params[6..8].copy_from_slice(&240u16.to_be_bytes());
+                (params, Vec::new())
+            },
+            READ_VAR => {
+                let count = usize::from(params[1]);
+                reads.lock().unwrap().push(count);
+                let mut data = Vec::new();
+                for (position, spec) in params[2..].chunks(ITEM_LEN).enumerate() {
+                    assert_eq!(spec[3], TRANSPORT_BYTE);
+                    let len = u16::from_be_bytes([spec[4], spec[5]]);
+                    data.extend_from_slice(&[RETURN_SUCCESS, DATA_BYTE]);
+                    data.extend_from_slice(&(len * 8).to_be_bytes());
+                    data.extend_from_slice(&load(memory, area(spec), start(spec) / 8, len.into()));
+                    if len % 2 == 1 && position + 1 < count {
+                        data.push(0);
+                    }
+                }
+                (params[..2].to_vec(), data)
+            },
+            WRITE_VAR => {
+                let spec = &params[2..];
+                let (area, start) = (area(spec), start(spec));
+                if spec[3] == TRANSPORT_BIT {
+                    assert_eq!(data[1], DATA_BIT);
+                    let mut byte = load(memory, area, start / 8, 1)[0];
+                    byte &= !(1 << (start % 8));
+                    byte |= (data[4] & 1) << (start % 8);
+                    store(memory, area, start / 8, &[byte]);
+                } else {
+                    assert_eq!(data[1], DATA_BYTE);
+                    store(memory, area, start / 8, &data[4..]);
+                }
+                (params[..2].to_vec(), vec![RETURN_SUCCESS])
+            },
+            function => panic!("unexpected function {function:#04x}"),
+        }
+    }
+
+    async fn wait_for_memory(memory: &Memory, area: Area, offset: usize, expected: &[u8]) {
+        let deadline = tokio::time::Instant::now() + Duration::from_secs(10);
+        while load(memory, area, offset, expected.len()) != expected {
+            assert!(tokio::time::Instant::now() < deadline, "{area:?} {offset}");
+            tokio::time::sleep(Duration::from_millis(20)).await;
+        }
+    }
+
+    #[tokio::test]
+    async fn reads_and_writes_variables_of_a_plc() {
+        let memory = Memory::default();
+        store(&memory, Area::DataBlock(10), 0, &21.5f32.to_be_bytes());
+        store(&memory, Area::DataBlock(10), 4, &(-12i16).to_be_bytes());
+        store(&memory, Area::DataBlock(10), 6, &[0b10]);
+        store(&memory, Area::DataBlock(10), 100, &100_000i32.to_be_bytes());
+        store(&memory, Area::Input, 0, &[0b1000]);
+        store(&memory, Area::Marker, 20, &7u16.to_be_bytes());
+        for i in 0..21 {
+            store(
+                &memory,
+                Area::DataBlock(40),
+                i * 40,
+                &(i as u16).to_be_bytes(),
+            );
+        }
+        let reads = Arc::new(Mutex::new(Vec::new()));
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap();
+        let plc = run_plc(listener, memory.clone(), reads.clone());
+
+        let point = |tag: &str, address: &str, writable| S7Point {
+            tag: tag.to_string(),
+            address: address.to_string(),
+            writable,
+        };
+        let mut points = vec![
+            point("line1/temperature", "DB10.DBD4:REAL", false),
+            point("line1/offset", "DB10.DBW4:INT", false),
+            point("line1/running", "db10.dbx6.1", false),
+            point("line1/count", "DB10.DBD100:DINT", false),
+            point("line1/door", "E0.3", false),
+            point("line1/mode", "MW20:INT", false),
+            point("line1/pump", "Q0.1", true),
+            point("line1/setpoint", "DB20.DBD0:REAL", true),
+        ];
+        // Overlapping variables are read once
+        points[0].address = "DB10.DBD0:REAL".to_string();
+        for i in 0..21 {
+            points.push(point(
+                &format!("line1/counter{i}"),
+                &format!("DB40.DBW{}", i * 40),
+                false,
+            ));
+        }
+        let config = S7Config {
+            name: "plc".to_string(),
+            address: address.to_string(),
+            rack: 0,
+            slot: 2,
+            poll_interval_ms: 50,
+            timeout_ms: 1000,
+            points,
+        };
+        assert!(config.validate().is_empty(), "{:?}", config.validate());
+        let tag_repo = tag_repo().await;
+        let status = SharedDriverStatus::default();
+        let (driver, _) = Actor::spawn(
+            None,
+            S7Driver,
+            S7Arguments {
+                config,
+                tag_repo: tag_repo.clone(),
+                status: status.clone(),
+            },
+        )
+        .await
+        .unwrap();
+
+        let tag = wait_for(&tag_repo, "line1/temperature", |tag| {
+            tag.value.value == Value::Float(21.5)
+        })
+        .await;
+        assert_eq!(tag.value.quality, Quality::Good);
+        assert!(tag.meta.read_only);
+        for (name, value) in [
+            ("line1/offset", Value::Integer(-12)),
+            ("line1/running", Value::Boolean(true)),
+            ("line1/count", Value::Integer(100_000)),
+            ("line1/door", Value::Boolean(true)),
+            ("line1/mode", Value::Integer(7)),
+            ("line1/pump", Value::Boolean(false)),
+            ("line1/setpoint", Value::Float(0.0)),
+            ("line1/counter20", Value::Integer(20)),
+        ] {
+            wait_for(&tag_repo, name, |tag| tag.value.value == value).await;
+        }
+        assert!(status.lock().unwrap().connected);
+        // I, Q, M, two ranges of DB10, DB20 and 21 of DB40, with 19
+        // variables in a request of 240 bytes
+        assert_eq!(reads.lock().unwrap()[..2], [19, 8]);
+
+        store(&memory, Area::DataBlock(10), 0, &22.25f32.to_be_bytes());
+        wait_for(&tag_repo, "line1/temperature", |tag| {
+            tag.value.value == Value::Float(22.25)
+        })
+        .await;
+
+        // Writable tags written through the API are written to the PLC
+        set_value(&tag_repo, "line1/setpoint", Value::Float(42.5)).await;
+        wait_for_memory(&memory, Area::DataBlock(20), 0, &42.5f32.to_be_bytes()).await;
+        set_value(&tag_repo, "line1/pump", Value::Boolean(true)).await;
+        wait_for_memory(&memory, Area::Output, 0, &[0b10]).await;
+        wait_for(&tag_repo, "line1/pump", |tag| {
+            tag.value.value == Value::Boolean(true)
+        })
+        .await;
+
+        // Tags turn uncertain while the PLC is unreachable
+        plc.abort();
+        let _ = until(plc).await.unwrap();
+        wait_for(&tag_repo, "line1/temperature", |tag| {
+            tag.value.quality == Quality::Uncertain
+        })
+        .await;
+        assert!(!status.lock().unwrap().connected);
+        driver.stop(None);
+    }
+}
@@ -0,0 +1,84 @@
+//! Fixtures of the driver tests: a tag repository and its tags.
+
+use std::{future::Future, time::Duration};
+
+use chrono::{DateTime, Utc};
+use ractor::{Actor, ActorRef};
+
+use rcada_core::{
+    tag::{Quality, Tag, TagMeta, TagValue},
+    unit::Unit,
+    value::Value,
+};
+
+use crate::{
+    actor::{self, Mailbox, tag::TagRepositoryActor},
+    audit::AuditLog,
+    repository::tag::inmemory::TagStorage,
+};
+
+pub async fn tag_repo() -> ActorRef<actor::tag::Message> {
+    let (tag_repo, _) = Actor::spawn(
+        None,
+        TagRepositoryActor::default(),
+        (TagStorage::default(), AuditLog::disabled()),
+    )
+    .await
+    .unwrap();
+    tag_repo
+}
+
+pub async fn create_tag(tag_repo: &ActorRef<actor::tag::Message>, name: &str, value: Value) {
+    let (command, mut reply) =
+        actor::tag::Message::create_tag(name, TagMeta::new(Unit::None, value.get_data_type()));
+    tag_repo.enqueue(command).unwrap();
+    reply.recv().await.unwrap();
+    set_value(tag_repo, name, value).await;
+}
+
+pub async fn set_value(tag_repo: &ActorRef<actor::tag::Message>, name: &str, value: Value) {
+    set_value_at(tag_repo, name, value, Utc::now()).await;
+}
+
+pub async fn set_value_at(
+    tag_repo: &ActorRef<actor::tag::Message>,
+    name: &str,
+    value: Value,
+    timestamp: DateTime<Utc>,
+) {
+    let (command, mut reply) = actor::tag::Message::update_tag_value(
+        name,
+        TagValue {
+            value,
+            timestamp: Some(timestamp),
+            quality: Quality::Good,
+        },
+    );
+    tag_repo.enqueue(command).unwrap();
+    reply.recv().await.unwrap().unwrap();
+}
+
+/// Polls a tag until `done`, failing after 10 seconds.
+pub async fn wait_for(
+    tag_repo: &ActorRef<actor::tag::Message>,
+    name: &str,
+    done: impl Fn(&Tag) -> bool,
+) -> Tag {
+    let deadline = tokio::time::Instant::now() + Duration::from_secs(10);
+    loop {
+        let (command, mut reply) = actor::tag::Message::get_tag(name);
+        tag_repo.enqueue(command).unwrap();
+        let tag = reply.recv().await.unwrap();
+        if let Ok(tag) = &tag
+            && done(tag)
+        {
+            return tag.clone();
+        }
+        assert!(tokio::time::Instant::now() < deadline, "{name}: {tag:?}");
+        tokio::time::sleep(Duration::from_millis(20)).await;
+    }
+}
+
+pub fn until<F: Future>(future: F) -> tokio::time::Timeout<F> {
+    tokio::time::timeout(Duration::from_secs(10), future)
+}
@@ -0,0 +1,273 @@
+//! Conversions between the protobuf messages and the types of the server.
+//! Messages from clients are checked here, the errors are the reasons of an
+//! [`ErrorCode::InvalidRequest`] error.
+
+use chrono::{DateTime, Utc};
+use rcada_core::{
+    tag::{AlarmLimits, EngineeringRange, Quality, Tag, TagMeta, TagValue},
+    unit::Unit,
+    value::{DataType, Value},
+};
+use uuid::Uuid;
+
+use crate::{
+    api::error::{ApiError, ErrorCode},
+    repository::tag::{TagMetaPatch, UpdateValueResult},
+};
+
+use super::proto;
+
+pub fn invalid_request(request_id: Uuid, reason: String) -> ApiError {
+    tracing::warn!(%request_id, "invalid gRPC request: {}", reason);
+    ApiError::new(request_id, ErrorCode::InvalidRequest, "Invalid request")
+        .with_details(serde_json::json!({ "reason": reason }))
+}
+
+fn unit(unit: i32) -> Result<Unit, String> {
+    let unit = proto::Unit::try_from(unit).map_err(|_| format!("unknown unit {unit}"))?;
+    Ok(match unit {
+        proto::Unit::None => Unit::None,
+        proto::Unit::Percent => Unit::Percent,
+        proto::Unit::Volt => Unit::Volt,
+        proto::Unit::Ampere => Unit::Ampere,
+        proto::Unit::Degree => Unit::Degree,
+        proto::Unit::Radian => Unit::Radian,
+        proto::Unit::Celsius => Unit::Celsius,
+        proto::Unit::Kelvin => Unit::Kelvin,
+        proto::Unit::Metre => Unit::Metre,
+        proto::Unit::Kilogram => Unit::Kilogram,
+        proto::Unit::Second => Unit::Second,
+    })
+}
+
+fn proto_unit(unit: Unit) -> proto::Unit {
+    match unit {
+        Unit::None => proto::Unit::None,
+        Unit::Percent => proto::Unit::Percent,
+        Unit::Volt => proto::Unit::Volt,
+        Unit::Ampere => proto::Unit::Ampere,
+        Unit::Degree => proto::Unit::Degree,
+        Unit::Radian => proto::Unit::Radian,
+        Unit::Celsius => proto::Unit::Celsius,
+        Unit::Kelvin => proto::Unit::Kelvin,
+        Unit::Metre => proto::Unit::Metre,
+        Unit::Kilogram => proto::Unit::Kilogram,
+        Unit::Second => proto::Unit::Second,
+    }
+}
+
+fn data_type(data_type: i32) -> Result<DataType, String> {
+    match proto::DataType::try_from(data_type) {
+        Ok(proto::DataType::Integer) => Ok(DataType::Integer),
+        Ok(proto::DataType::Float) => Ok(DataType::Float),
+        Ok(proto::DataType::Boolean) => Ok(DataType::Boolean),
+        Ok(proto::DataType::String) => Ok(DataType::String),
+        Ok(proto::DataType::Unspecified) => Err("data_type is required".to_string()),
+        Err(_) => Err(format!("unknown data_type {data_type}")),
+    }
+}
+
+fn proto_data_type(data_type: DataType) -> proto::DataType {
+    match data_type {
+        DataType::Integer => proto::DataType::Integer,
+        DataType::Float => proto::DataType::Float,
+        DataType::Boolean => proto::DataType::Boolean,
+        DataType::String => proto::DataType::String,
+    }
+}
+
+fn value(value: Option<proto::Value>) -> Result<Value, String> {
+    match value.and_then(|value| value.kind) {
+        Some(proto::value::Kind::Integer(v)) => Ok(Value::Integer(v)),
+        Some(proto::value::Kind::Float(v)) => Ok(Value::Float(v)),
+        Some(proto::value::Kind::Boolean(v)) => Ok(Value::Boolean(v)),
+        Some(proto::value::Kind::String(v)) => Ok(Value::String(v)),
+        None => Err("value is required".to_string()),
+    }
+}
+
+fn proto_value(value: Value) -> proto::Value {
+    let kind = match value {
+        Value::Integer(v) => proto::value::Kind::Integer(v),
+        Value::Float(v) => proto::value::Kind::Float(v),
+        Value::Boolean(v) => proto::value::Kind::Boolean(v),
+        Value::String(v) => proto::value::Kind::String(v),
+    };
+    proto::Value {
+        kind: Some(kind),
+    }
+}
+
+fn quality(quality: i32) -> Result<Quality, String> {
+    match proto::Quality::try_from(quality) {
+        Ok(proto::Quality::Good) => Ok(Quality::Good),
+        Ok(proto::Quality::Uncertain) => Ok(Quality::Uncertain),
+        Ok(proto::Quality::Bad) => Ok(Quality::Bad),
+        Err(_) => Err(format!("unknown quality {quality}")),
+    }
+}
+
+fn proto_quality(quality: Quality) -> proto::Quality {
+    match quality {
+        Quality::Good => proto::Quality::Good,
+        Quality::Uncertain => proto::Quality::Uncertain,
+        Quality::Bad => proto::Quality::Bad,
+    }
+}
+
+fn timestamp(timestamp: prost_types::Timestamp) -> Result<DateTime<Utc>, String> {
+    u32::try_from(timestamp.nanos)
+        .ok()
+        .and_then(|nanos| DateTime::from_timestamp(timestamp.seconds, nanos))
+        .ok_or_else(|| format!("invalid timestamp {timestamp}"))
+}
+
+fn proto_timestamp(timestamp: DateTime<Utc>) -> prost_types::Timestamp {
+    prost_types::Timestamp {
+        seconds: timestamp.timestamp(),
+        nanos: timestamp.timestamp_subsec_nanos() as i32,
+    }
+}
+
+fn precision(precision: u32) -> Result<u8, String> {
+    u8::try_from(precision).map_err(|_| format!("precision {precision} is too large"))
+}
+
+fn range(range: proto::EngineeringRange) -> EngineeringRange {
+    EngineeringRange {
+        low: range.low,
+        high: range.high,
+    }
+}
+
+fn alarms(alarms: proto::AlarmLimits) -> AlarmLimits {
+    AlarmLimits {
+        low_low: alarms.low_low,
+        low: alarms.low,
+        high: alarms.high,
+        high_high: alarms.high_high,
+    }
+}
+
+pub fn tag_meta(meta: Option<proto::TagMeta>) -> Result<TagMeta, String> {
+    let meta = meta.ok_or("meta is required")?;
+    Ok(TagMeta {
+        unit: unit(meta.unit)?,
+        data_type: data_type(meta.data_type)?,
+        description: meta.description,
+        range: meta.range.map(range),
+        precision: meta.precision.map(precision).transpose()?,
+        read_only: meta.read_only,
+        labels: meta.labels.into_iter().collect(),
+        alarms: meta.alarms.map(alarms).unwrap_or_default(),
+        expression: meta.expression,
+    })
+}
+
+pub fn tag_meta_patch(req: proto::UpdateTagMetaRequest) -> Result<TagMetaPatch, String> {
+    let labels: Vec<(String, Option<String>)> = req
+        .set_labels
+        .into_iter()
+        .map(|(key, value)| (key, Some(value)))
+        .chain(req.remove_labels.into_iter().map(|key| (key, None)))
+        .collect();
+    Ok(TagMetaPatch {
+        unit: req.unit.map(unit).transpose()?,
+        data_type: req.data_type.map(data_type).transpose()?,
+        description: req.description,
+        range: if req.clear_range {
+            Some(None)
+        } else {
+            req.range.map(|r| Some(range(r)))
+        },
+        precision: if req.clear_precision {
+            Some(None)
+        } else {
+            req.precision.map(precision).transpose()?.map(Some)
+        },
+        read_only: req.read_only,
+        labels: (!labels.is_empty()).then(|| labels.into_iter().collect()),
+        alarms: req.alarms.map(alarms),
+        expression: if req.clear_expression {
+            Some(None)
+        } else {
+            req.expression.map(Some)
+        },
+    })
+}
+
+/// Name and value of a write, without a timestamp the value is stamped with
+/// the current time like writes through REST.
+pub fn value_write(req: proto::UpdateTagValueRequest) -> Result<(String, TagValue), String> {
+    let tag_value = TagValue {
+        value: value(req.value)?,
+        timestamp: Some(match req.timestamp {
+            Some(t) => timestamp(t)?,
+            None => Utc::now(),
+        }),
+        quality: quality(req.quality)?,
+    };
+    Ok((req.name, tag_value))
+}
+
+pub fn proto_tag_value(value: TagValue) -> proto::TagValue {
+    proto::TagValue {
+        value: Some(proto_value(value.value)),
+        timestamp: value.timestamp.map(proto_timestamp),
+        quality: proto_quality(value.quality).into(),
+    }
+}
+
+pub fn proto_tag(tag: Tag) -> proto::Tag {
+    let meta = tag.meta;
+    proto::Tag {
+        name: tag.name.to_string(),
+        value: Some(proto_tag_value(tag.value)),
+        meta: Some(proto::TagMeta {
+            unit: proto_unit(meta.unit).into(),
+            data_type: proto_data_type(meta.data_type).into(),
+            description: meta.description,
+            range: meta.range.map(|range| proto::EngineeringRange {
+                low: range.low,
+                high: range.high,
+            }),
+            precision: meta.precision.map(u32::from),
+            read_only: meta.read_only,
+            labels: meta.labels.into_iter().collect(),
+            alarms: Some(proto::AlarmLimits {
+                low_low: meta.alarms.low_low,
+                low: meta.alarms.low,
+                high: meta.alarms.high,
+                high_high: meta.alarms.high_high,
+            }),
+            expression: meta.expression,
+        }),
+    }
+}
+
+pub fn proto_update_value_result(result: UpdateValueResult) -> proto::UpdateValueResult {
+    match result {
+        UpdateValueResult::Updated => proto::UpdateValueResult::Updated,
+        UpdateValueResult::Ignored => proto::UpdateValueResult::Ignored,
+    }
+}
+
+/// Name of an error code as in the REST error body, e.g. `tag_not_found`.
+pub fn error_code_name(code: ErrorCode) -> String {
+    match serde_json::to_value(code) {
+        Ok(serde_json::Value::String(name)) => name,
+        _ => format!("{code:?}"),
+    }
+}
+
+pub fn proto_error(error: ApiError) -> proto::Error {
+    proto::Error {
+        code: error_code_name(error.code),
+        message: error.message,
+        request_id: error.request_id.to_string(),
+        details: error
+            .details
+            .map(|details| details.to_string())
+            .unwrap_or_default(),
+    }
+}
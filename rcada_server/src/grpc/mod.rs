@@ -0,0 +1,210 @@
+//! gRPC API next to the REST API, defined in `proto/tags.proto`.
+
+mod convert;
+mod tags;
+
+use std::{future::Future, io, sync::Arc, time::Duration};
+
+use ractor::ActorRef;
+use serde::{Deserialize, Serialize};
+use tokio::net::TcpListener;
+use tokio_rustls::TlsAcceptor;
+use tokio_stream::wrappers::{ReceiverStream, TcpListenerStream};
+use tonic::{Code, Request, Status, metadata::MetadataValue, transport::Server};
+
+use crate::{
+    access::AccessControl,
+    actor,
+    api::error::{ApiError, ErrorCode},
+    auth::{API_KEY_HEADER, Authenticator},
+    tls::ClientCertificate,
+};
+
+pub mod proto {
+    tonic::include_proto!("rcada.v1");
+}
+
+/// TLS handshakes taking longer than this are dropped.
+const HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(10);
+
+const ACCEPT_ERROR_DELAY: Duration = Duration::from_millis(100);
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct GrpcConfig {
+    /// Serve the gRPC API besides the REST API.
+    #[serde(default)]
+    pub enabled: bool,
+    #[serde(default = "default_bind")]
+    pub bind: String,
+}
+
+fn default_bind() -> String {
+    "127.0.0.1:50051".to_string()
+}
+
+impl Default for GrpcConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            bind: default_bind(),
+        }
+    }
+}
+
+/// What the gRPC services share with the HTTP server.
+#[derive(Clone)]
+pub struct GrpcState {
+    pub tag_repo: ActorRef<actor::tag::Message>,
+    pub access: Arc<AccessControl>,
+    pub authenticator: Arc<Authenticator>,
+}
+
+/// Serves the gRPC API on `listener` until `shutdown` completes. With `tls`
+/// the connections use the TLS configuration of the HTTP server, so they get
+/// the same certificate, reloads included, and client certificates.
+pub async fn serve(
+    listener: TcpListener,
+    tls: Option<rustls::ServerConfig>,
+    state: GrpcState,
+    shutdown: impl Future<Output = ()> + Send + 'static,
+) -> io::Result<()> {
+    let address = listener.local_addr()?;
+    let authenticator = state.authenticator.clone();
+    let router = Server::builder().add_service(proto::tags_server::TagsServer::with_interceptor(
+        tags::TagsService::new(state),
+        move |request| authenticate(&authenticator, request),
+    ));
+
+    let served = match tls {
+        Some(mut tls) => {
+            tracing::info!("Listening for gRPC on https://{}", address);
+            tls.alpn_protocols = vec![b"h2".to_vec()];
+            let incoming =
+                ReceiverStream::new(accept_tls(listener, TlsAcceptor::from(Arc::new(tls))));
+            router
+                .serve_with_incoming_shutdown(incoming, shutdown)
+                .await
+        },
+        None => {
+            tracing::info!("Listening for gRPC on http://{}", address);
+            let incoming = TcpListenerStream::new(listener);
+            router
+                .serve_with_incoming_shutdown(incoming, shutdown)
+                .await
+        },
+    };
+    served.map_err(io::Error::other)
+}
+
+/// Accepts connections and passes on those that complete the handshake.
+fn accept_tls(
+    listener: TcpListener,
+    acceptor: TlsAcceptor,
+) -> tokio::sync::mpsc::Receiver<io::Result<tokio_rustls::server::TlsStream<tokio::net::TcpStream>>>
+{
+    let (sender, receiver) = tokio::sync::mpsc::channel(16);
+    tokio::spawn(async move {
+        loop {
+            let (stream, peer) = match listener.accept().await {
+                Ok(accepted) => accepted,
+                Err(e) => {
+                    // Usually out of file descriptors, give others time to close
+                    tracing::warn!("Failed to accept gRPC connection: {}", e);
+                    tokio::time::sleep(ACCEPT_ERROR_DELAY).await;
+                    continue;
+                },
+            };
+            let acceptor = acceptor.clone();
+            let connections = sender.clone();
+            tokio::spawn(async move {
+                match tokio::time::timeout(HANDSHAKE_TIMEOUT, acceptor.accept(stream)).await {
+                    Ok(Ok(stream)) => {
+                        let _ = connections.send(Ok(stream)).await;
+                    },
+                    Ok(Err(e)) => tracing::debug!(%peer, "gRPC TLS handshake failed: {}", e),
+                    Err(_) => tracing::debug!(%peer, "gRPC TLS handshake timed out"),
+                }
+            });
+            if sender.is_closed() {
+                break;
+            }
+        }
+    });
+    receiver
+}
+
+/// Stores the [`Identity`](crate::auth::Identity) of the caller in the
+/// request extensions, checking the credentials like the HTTP middleware.
+fn authenticate(
+    authenticator: &Authenticator,
+    mut request: Request<()>,
+) -> Result<Request<()>, Status> {
+    let client_cert = request.peer_certs().and_then(|certs| {
+        certs
+            .first()
+            .and_then(|cert| ClientCertificate::from_der(cert))
+    });
+    let metadata = request.metadata();
+    let identity = authenticator
+        .authenticate_credentials(
+            metadata.get(API_KEY_HEADER).map(|value| value.as_bytes()),
+            metadata.get("authorization").map(|value| value.as_bytes()),
+            client_cert.as_ref(),
+        )
+        .map_err(|e| {
+            tracing::warn!("unauthorized gRPC request: {}", e);
+            Status::from(ApiError::new(
+                uuid::Uuid::new_v4(),
+                ErrorCode::Unauthorized,
+                e.to_string(),
+            ))
+        })?;
+    request.extensions_mut().insert(identity);
+    Ok(request)
+}
+
+/// Status with the gRPC code closest to the HTTP status of the REST API and
+/// the error body in the metadata.
+impl From<ApiError> for Status {
+    fn from(error: ApiError) -> Self {
+        let code = match error.code {
+            ErrorCode::AlreadyExists => Code::AlreadyExists,
+            ErrorCode::InvalidRequest
+            | ErrorCode::InvalidRange
+            | ErrorCode::InvalidAlarmLimits
+            | ErrorCode::InvalidDataType
+            | ErrorCode::InvalidConfiguration => Code::InvalidArgument,
+            ErrorCode::TimestampRequired
+            | ErrorCode::TimestampOutOfOrder
+            | ErrorCode::Conflict
+            | ErrorCode::IncompatibleDataType => Code::FailedPrecondition,
+            ErrorCode::Unauthorized | ErrorCode::InvalidCredentials => Code::Unauthenticated,
+            ErrorCode::PermissionDenied | ErrorCode::ReadOnly => Code::PermissionDenied,
+            ErrorCode::NotFound
+            | ErrorCode::TagNotFound
+            | ErrorCode::AliasNotFound
+            | ErrorCode::TemplateNotFound
+            | ErrorCode::InstanceNotFound
+            | ErrorCode::RoleNotFound
+            | ErrorCode::BindingNotFound
+            | ErrorCode::LoginDisabled => Code::NotFound,
+            ErrorCode::Internal => Code::Internal,
+        };
+        let mut status = Status::new(code, error.message.clone());
+        let metadata = status.metadata_mut();
+        if let Ok(value) = convert::error_code_name(error.code).parse() {
+            metadata.insert("rcada-error-code", value);
+        }
+        if let Ok(value) = error.request_id.to_string().parse() {
+            metadata.insert("rcada-request-id", value);
+        }
+        if let Some(details) = &error.details {
+            metadata.insert_bin(
+                "rcada-error-details-bin",
+                MetadataValue::from_bytes(details.to_string().as_bytes()),
+            );
+        }
+        status
+    }
+}
@@ -0,0 +1,614 @@
+use std::{net::SocketAddr, pin::Pin};
+
+use rcada_core::tag::Tag;
+use tokio::sync::{
+    broadcast::{self, error::RecvError},
+    mpsc,
+};
+use tokio_stream::{Stream, wrappers::ReceiverStream};
+use tonic::{Request, Response, Status, Streaming};
+use uuid::Uuid;
+
+use crate::{
+    access,
+    api::{error::ApiError, tags::service::TagService},
+    auth::Identity,
+    repository::tag::UpdateValueResult,
+};
+
+use super::{
+    GrpcState,
+    convert::{self, invalid_request},
+    proto::{self, tags_server::Tags},
+};
+
+/// Responses buffered per stream before the sender waits for the client.
+const STREAM_BUFFER: usize = 64;
+
+type ResponseStream<T> = Pin<Box<dyn Stream<Item = Result<T, Status>> + Send>>;
+
+pub struct TagsService {
+    state: GrpcState,
+}
+
+impl TagsService {
+    pub fn new(state: GrpcState) -> Self {
+        Self {
+            state,
+        }
+    }
+
+    fn service<'a>(&'a self, identity: &'a Identity, peer: Option<SocketAddr>) -> TagService<'a> {
+        TagService::new(
+            &self.state.tag_repo,
+            &self.state.access,
+            identity,
+            peer,
+            Uuid::new_v4(),
+        )
+    }
+}
+
+/// Identity stored by the interceptor and address of the caller.
+fn caller<T>(request: &Request<T>) -> Result<(Identity, Option<SocketAddr>), Status> {
+    let identity = request
+        .extensions()
+        .get::<Identity>()
+        .cloned()
+        .ok_or_else(|| Status::unauthenticated("request was not authenticated"))?;
+    Ok((identity, request.remote_addr()))
+}
+
+#[tonic::async_trait]
+impl Tags for TagsService {
+    async fn create_tag(
+        &self,
+        request: Request<proto::CreateTagRequest>,
+    ) -> Result<Response<proto::CreateTagResponse>, Status> {
+        let (identity, peer) = caller(&request)?;
+        let service = self.service(&identity, peer);
+        let request_id = service.request_id();
+        let req = request.into_inner();
+        tracing::info!(%request_id, "gRPC request: (CreateTag) name={}", req.name);
+
+        let meta = convert::tag_meta(req.meta).map_err(|e| invalid_request(request_id, e))?;
+        service.create_tag(&req.name, meta).await?;
+        Ok(Response::new(proto::CreateTagResponse {
+            name: req.name,
+        }))
+    }
+
+    async fn list_tags(
+        &self,
+        request: Request<proto::ListTagsRequest>,
+    ) -> Result<Response<proto::ListTagsResponse>, Status> {
+        let (identity, peer) = caller(&request)?;
+        let service = self.service(&identity, peer);
+        tracing::info!(request_id = %service.request_id(), "gRPC request (ListTags)");
+
+        let tags = service.list_tags().await?;
+        Ok(Response::new(proto::ListTagsResponse {
+            tags: tags.into_iter().map(convert::proto_tag).collect(),
+        }))
+    }
+
+    async fn get_tag(
+        &self,
+        request: Request<proto::GetTagRequest>,
+    ) -> Result<Response<proto::Tag>, Status> {
+        let (identity, peer) = caller(&request)?;
+        let service = self.service(&identity, peer);
+        let req = request.into_inner();
+        tracing::info!(request_id = %service.request_id(), "gRPC request: {} (GetTag)", req.name);
+
+        let tag = service.get_tag(&req.name).await?;
+        Ok(Response::new(convert::proto_tag(tag)))
+    }
+
+    async fn update_tag_value(
+        &self,
+        request: Request<proto::UpdateTagValueRequest>,
+    ) -> Result<Response<proto::UpdateTagValueResponse>, Status> {
+        let (identity, peer) = caller(&request)?;
+        let service = self.service(&identity, peer);
+        let request_id = service.request_id();
+        let req = request.into_inner();
+        tracing::info!(%request_id, "gRPC request: {} (UpdateTagValue)", req.name);
+
+        let (name, value) =
+            convert::value_write(req).map_err(|e| invalid_request(request_id, e))?;
+        let result = service.update_tag_value(&name, value).await?;
+        Ok(Response::new(proto::UpdateTagValueResponse {
+            result: convert::proto_update_value_result(result).into(),
+        }))
+    }
+
+    async fn update_tag_meta(
+        &self,
+        request: Request<proto::UpdateTagMetaRequest>,
+    ) -> Result<Response<proto::Tag>, Status> {
+        let (identity, peer) = caller(&request)?;
+        let service = self.service(&identity, peer);
+        let request_id = service.request_id();
+        let req = request.into_inner();
+        tracing::info!(%request_id, "gRPC request: {} (UpdateTagMeta)", req.name);
+
+        let name = req.name.clone();
+        let patch = convert::tag_meta_patch(req).map_err(|e| invalid_request(request_id, e))?;
+        let tag = service.update_tag_meta(&name, patch).await?;
+        Ok(Response::new(convert::proto_tag(tag)))
+    }
+
+    async fn delete_tag(
+        &self,
+        request: Request<proto::DeleteTagRequest>,
+    ) -> Result<Response<proto::DeleteTagResponse>, Status> {
+        let (identity, peer) = caller(&request)?;
+        let service = self.service(&identity, peer);
+        let req = request.into_inner();
+        tracing::info!(request_id = %service.request_id(), "gRPC request: {} (DeleteTag)", req.name);
+
+        service.delete_tag(&req.name).await?;
+        Ok(Response::new(proto::DeleteTagResponse {}))
+    }
+
+    async fn rename_tag(
+        &self,
+        request: Request<proto::RenameTagRequest>,
+    ) -> Result<Response<proto::Tag>, Status> {
+        let (identity, peer) = caller(&request)?;
+        let service = self.service(&identity, peer);
+        let req = request.into_inner();
+        tracing::info!(
+            request_id = %service.request_id(),
+            "gRPC request: {} (RenameTag) new_name={}",
+            req.name,
+            req.new_name
+        );
+
+        let tag = service
+            .rename_tag(&req.name, &req.new_name, req.keep_alias.unwrap_or(true))
+            .await?;
+        Ok(Response::new(convert::proto_tag(tag)))
+    }
+
+    type SubscribeStream = ResponseStream<proto::TagUpdate>;
+
+    async fn subscribe(
+        &self,
+        request: Request<Streaming<proto::SubscribeRequest>>,
+    ) -> Result<Response<Self::SubscribeStream>, Status> {
+        let (identity, peer) = caller(&request)?;
+        let service = self.service(&identity, peer);
+        tracing::info!(request_id = %service.request_id(), "gRPC request (Subscribe)");
+        let updates = service.subscribe().await?;
+
+        let (sender, receiver) = mpsc::channel(STREAM_BUFFER);
+        let state = self.state.clone();
+        let requests = request.into_inner();
+        tokio::spawn(async move {
+            let service = TagService::new(
+                &state.tag_repo,
+                &state.access,
+                &identity,
+                peer,
+                Uuid::new_v4(),
+            );
+            if let Err(status) = run_subscription(&service, requests, updates, &sender).await {
+                let _ = sender.send(Err(status)).await;
+            }
+        });
+        Ok(Response::new(Box::pin(ReceiverStream::new(receiver))))
+    }
+
+    type WriteValuesStream = ResponseStream<proto::WriteValueResponse>;
+
+    async fn write_values(
+        &self,
+        request: Request<Streaming<proto::WriteValueRequest>>,
+    ) -> Result<Response<Self::WriteValuesStream>, Status> {
+        let (identity, peer) = caller(&request)?;
+        tracing::info!(identity = identity.name, "gRPC request (WriteValues)");
+
+        let (sender, receiver) = mpsc::channel(STREAM_BUFFER);
+        let state = self.state.clone();
+        let mut requests = request.into_inner();
+        tokio::spawn(async move {
+            loop {
+                let req = match requests.message().await {
+                    Ok(Some(req)) => req,
+                    Ok(None) => break,
+                    Err(status) => {
+                        tracing::debug!("WriteValues stream failed: {}", status);
+                        break;
+                    },
+                };
+                // Every write is a request of its own, with its own id in the audit log
+                let service = TagService::new(
+                    &state.tag_repo,
+                    &state.access,
+                    &identity,
+                    peer,
+                    Uuid::new_v4(),
+                );
+                let result = write_value(&service, req.write).await;
+                let response = proto::WriteValueResponse {
+                    id: req.id,
+                    result: Some(match result {
+                        Ok(result) => proto::write_value_response::Result::Ok(
+                            convert::proto_update_value_result(result).into(),
+                        ),
+                        Err(e) => {
+                            proto::write_value_response::Result::Error(convert::proto_error(e))
+                        },
+                    }),
+                };
+                if sender.send(Ok(response)).await.is_err() {
+                    break;
+                }
+            }
+        });
+        Ok(Response::new(Box::pin(ReceiverStream::new(receiver))))
+    }
+}
+
+async fn write_value(
+    service: &TagService<'_>,
+    write: Option<proto::UpdateTagValueRequest>,
+) -> Result<UpdateValueResult, ApiError> {
+    let request_id = service.request_id();
+    let write =
+        write.ok_or_else(|| invalid_request(request_id, "write is required".to_string()))?;
+    tracing::info!(%request_id, "gRPC request: {} (WriteValues)", write.name);
+    let (name, value) = convert::value_write(write).map_err(|e| invalid_request(request_id, e))?;
+    service.update_tag_value(&name, value).await
+}
+
+/// Forwards the updates of subscribed tags until the client goes away. The
+/// client may close its side of the stream and keep receiving updates.
+async fn run_subscription(
+    service: &TagService<'_>,
+    mut requests: Streaming<proto::SubscribeRequest>,
+    mut updates: broadcast::Receiver<Tag>,
+    sender: &mpsc::Sender<Result<proto::TagUpdate, Status>>,
+) -> Result<(), Status> {
+    let mut patterns: Vec<String> = Vec::new();
+    let mut requests_open = true;
+    loop {
+        tokio::select! {
+            req = requests.message(), if requests_open => match req? {
+                Some(req) => {
+                    let mut unsubscribe = Vec::with_capacity(req.unsubscribe.len());
+                    for pattern in req.unsubscribe {
+                        unsubscribe.push(resolve_pattern(service, pattern).await);
+                    }
+                    patterns.retain(|pattern| !unsubscribe.contains(pattern));
+                    for tag in add_patterns(service, &mut patterns, req.subscribe).await? {
+                        send_update(sender, tag).await?;
+                    }
+                },
+                None => requests_open = false,
+            },
+            update = updates.recv() => match update {
+                Ok(tag) => {
+                    if patterns.iter().any(|pattern| access::matches(pattern, &tag.name))
+                        && service.may_read(&tag.name)
+                    {
+                        send_update(sender, tag).await?;
+                    }
+                },
+                Err(RecvError::Lagged(missed)) => {
+                    return Err(Status::data_loss(format!(
+                        "{missed} updates were dropped because the client fell behind, \
+                         subscribe again"
+                    )));
+                },
+                Err(RecvError::Closed) => return Err(Status::unavailable("server is stopping")),
+            },
+            _ = sender.closed() => return Ok(()),
+        }
+    }
+}
+
+/// Replaces an alias by the name of its tag, since updates are sent under the
+/// tag name. Patterns with wildcards and unknown names are kept as they are.
+async fn resolve_pattern(service: &TagService<'_>, pattern: String) -> String {
+    if pattern.contains(['*', '?']) {
+        return pattern;
+    }
+    match service.get_tag(&pattern).await {
+        Ok(tag) => tag.name.to_string(),
+        Err(_) => pattern,
+    }
+}
+
+/// Adds `new` to `patterns` and returns the newly subscribed tags the caller
+/// may read.
+async fn add_patterns(
+    service: &TagService<'_>,
+    patterns: &mut Vec<String>,
+    new: Vec<String>,
+) -> Result<Vec<Tag>, ApiError> {
+    let mut added = Vec::new();
+    for pattern in new {
+        let pattern = resolve_pattern(service, pattern).await;
+        if !patterns.contains(&pattern) {
+            patterns.push(pattern.clone());
+            added.push(pattern);
+        }
+    }
+    if added.is_empty() {
+        return Ok(Vec::new());
+    }
+    Ok(service
+        .list_tags()
+        .await?
+        .into_iter()
+        .filter(|tag| {
+            added
+                .iter()
+                .any(|pattern| access::matches(pattern, &tag.name))
+        })
+        .collect())
+}
+
+async fn send_update(
+    sender: &mpsc::Sender<Result<proto::TagUpdate, Status>>,
+    tag: Tag,
+) -> Result<(), Status> {
+    let update = proto::TagUpdate {
+        name: tag.name.to_string(),
+        value: Some(convert::proto_tag_value(tag.value)),
+    };
+    sender
+        .send(Ok(update))
+        .await
+        .map_err(|_| Status::cancelled("client went away"))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use rcada_core::value::Value;
+    use tokio::{net::TcpListener, sync::oneshot};
+    use tonic::{Code, service::interceptor::InterceptedService, transport::Channel};
+
+    use super::*;
+    use crate::{
+        access::{AccessControl, AccessModel, Grant, Permission, Role, Subject, SubjectKind},
+        auth::{ApiKeyConfig, AuthConfig, Authenticator},
+        driver::testing::{create_tag, set_value, tag_repo},
+        grpc::proto::tags_client::TagsClient,
+    };
+
+    type Client = TagsClient<
+        InterceptedService<
+            Channel,
+            Box<dyn FnMut(Request<()>) -> Result<Request<()>, Status> + Send>,
+        >,
+    >;
+
+    struct Server {
+        address: SocketAddr,
+        tag_repo: ractor::ActorRef<crate::actor::tag::Message>,
+        _stop: oneshot::Sender<()>,
+    }
+
+    impl Server {
+        /// Serves `area1.pump` and `area2.pump` to the API keys `operator`,
+        /// which may read and write area 1, and `viewer`, which may read all.
+        async fn start() -> Self {
+            let tag_repo = tag_repo().await;
+            create_tag(&tag_repo, "area1.pump", Value::Float(1.0)).await;
+            create_tag(&tag_repo, "area2.pump", Value::Float(2.0)).await;
+
+            let mut model = AccessModel::default();
+            model.roles.push(Role {
+                name: "area1-operator".to_string(),
+                description: String::new(),
+                grants: vec![Grant {
+                    permissions: vec![Permission::Read, Permission::Write],
+                    tags: "area1.*".to_string(),
+                }],
+            });
+            for (key, role) in [("operator", "area1-operator"), ("viewer", "viewer")] {
+                model.bindings.insert(
+                    Subject::new(SubjectKind::ApiKey, key),
+                    [role.to_string()].into(),
+                );
+            }
+            let config = AuthConfig {
+                enabled: true,
+                api_keys: ["operator", "viewer"]
+                    .into_iter()
+                    .map(|name| ApiKeyConfig {
+                        name: name.to_string(),
+                        key: format!("{name}-key"),
+                        roles: Vec::new(),
+                    })
+                    .collect(),
+                ..AuthConfig::default()
+            };
+            let state = GrpcState {
+                tag_repo: tag_repo.clone(),
+                access: Arc::new(AccessControl::new(model)),
+                authenticator: Arc::new(Authenticator::new(&config).unwrap()),
+            };
+
+            let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+            let address = listener.local_addr().unwrap();
+            let (stop, stopped) = oneshot::channel::<()>();
+            tokio::spawn(crate::grpc::serve(listener, None, state, async {
+                let _ = stopped.await;
+            }));
+            Self {
+                address,
+                tag_repo,
+                _stop: stop,
+            }
+        }
+
+        async fn client(&self, api_key: Option<&str>) -> Client {
+            let channel = Channel::from_shared(format!("http://{}", self.address))
+                .unwrap()
+                .connect()
+                .await
+                .unwrap();
+            let api_key: Option<tonic::metadata::AsciiMetadataValue> =
+                api_key.map(|name| format!("{name}-key").parse().unwrap());
+            TagsClient::with_interceptor(
+                channel,
+                Box::new(move |mut request: Request<()>| {
+                    if let Some(api_key) = &api_key {
+                        request.metadata_mut().insert("x-api-key", api_key.clone());
+                    }
+                    Ok(request)
+                }),
+            )
+        }
+    }
+
+    fn get(name: &str) -> proto::GetTagRequest {
+        proto::GetTagRequest {
+            name: name.to_string(),
+        }
+    }
+
+    fn write(name: &str, value: f32) -> proto::UpdateTagValueRequest {
+        proto::UpdateTagValueRequest {
+            name: name.to_string(),
+            value: Some(proto::Value {
+                kind: Some(proto::value::Kind::Float(value)),
+            }),
+            timestamp: None,
+            quality: proto::Quality::Good.into(),
+        }
+    }
+
+    fn value(tag: &proto::TagUpdate) -> Option<proto::value::Kind> {
+        tag.value.as_ref()?.value.as_ref()?.kind.clone()
+    }
+
+    #[tokio::test]
+    async fn reads_and_writes_need_permissions() {
+        let server = Server::start().await;
+
+        let mut anonymous = server.client(None).await;
+        let status = anonymous.get_tag(get("area1.pump")).await.unwrap_err();
+        assert_eq!(status.code(), Code::Unauthenticated);
+
+        let mut operator = server.client(Some("operator")).await;
+        let tag = operator
+            .get_tag(get("area1.pump"))
+            .await
+            .unwrap()
+            .into_inner();
+        assert_eq!(tag.name, "area1.pump");
+        let status = operator.get_tag(get("area2.pump")).await.unwrap_err();
+        assert_eq!(status.code(), Code::PermissionDenied);
+        assert_eq!(
+            status.metadata().get("rcada-error-code").unwrap(),
+            "permission_denied"
+        );
+
+        let response = operator
+            .update_tag_value(write("area1.pump", 5.0))
+            .await
+            .unwrap()
+            .into_inner();
+        assert_eq!(response.result(), proto::UpdateValueResult::Updated);
+        let mut viewer = server.client(Some("viewer")).await;
+        let status = viewer
+            .update_tag_value(write("area1.pump", 6.0))
+            .await
+            .unwrap_err();
+        assert_eq!(status.code(), Code::PermissionDenied);
+        let tag = viewer
+            .get_tag(get("area1.pump"))
+            .await
+            .unwrap()
+            .into_inner();
+        let value = tag
+            .value
+            .and_then(|value| value.value)
+            .and_then(|value| value.kind);
+        assert_eq!(value, Some(proto::value::Kind::Float(5.0)));
+
+        // Every write of a stream is checked and answered on its own
+        let writes = [
+            (1, "area1.pump", 7.0),
+            (2, "area2.pump", 8.0),
+            (3, "area1.pump", 9.0),
+        ]
+        .map(|(id, name, value)| proto::WriteValueRequest {
+            id,
+            write: Some(write(name, value)),
+        });
+        let mut responses = operator
+            .write_values(tokio_stream::iter(writes))
+            .await
+            .unwrap()
+            .into_inner();
+        let mut results = Vec::new();
+        while let Some(response) = responses.message().await.unwrap() {
+            let result = match response.result.unwrap() {
+                proto::write_value_response::Result::Ok(_) => "ok".to_string(),
+                proto::write_value_response::Result::Error(e) => e.code,
+            };
+            results.push((response.id, result));
+        }
+        assert_eq!(
+            results,
+            [
+                (1, "ok".to_string()),
+                (2, "permission_denied".to_string()),
+                (3, "ok".to_string())
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn subscriptions_leave_out_tags_the_caller_may_not_read() {
+        let server = Server::start().await;
+        let mut operator = server.client(Some("operator")).await;
+
+        let (requests, receiver) = mpsc::channel(4);
+        let mut updates = operator
+            .subscribe(ReceiverStream::new(receiver))
+            .await
+            .unwrap()
+            .into_inner();
+        requests
+            .send(proto::SubscribeRequest {
+                subscribe: vec!["*".to_string()],
+                unsubscribe: Vec::new(),
+            })
+            .await
+            .unwrap();
+
+        // The current value first, then the updates
+        let update = updates.message().await.unwrap().unwrap();
+        assert_eq!(update.name, "area1.pump");
+        assert_eq!(value(&update), Some(proto::value::Kind::Float(1.0)));
+        set_value(&server.tag_repo, "area2.pump", Value::Float(3.0)).await;
+        set_value(&server.tag_repo, "area1.pump", Value::Float(4.0)).await;
+        let update = updates.message().await.unwrap().unwrap();
+        assert_eq!(update.name, "area1.pump");
+        assert_eq!(value(&update), Some(proto::value::Kind::Float(4.0)));
+
+        // No updates after unsubscribing
+        requests
+            .send(proto::SubscribeRequest {
+                subscribe: Vec::new(),
+                unsubscribe: vec!["*".to_string()],
+            })
+            .await
+            .unwrap();
+        tokio::time::sleep(std::time::Duration::from_millis(50)).await;
+        set_value(&server.tag_repo, "area1.pump", Value::Float(5.0)).await;
+        let next =
+            tokio::time::timeout(std::time::Duration::from_millis(200), updates.message()).await;
+        assert!(next.is_err(), "{next:?}");
+    }
+}
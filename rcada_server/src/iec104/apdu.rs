@@ -0,0 +1,621 @@
+//! IEC 60870-5-104 APDUs: the APCI control frames and the ASDUs carried by
+//! the I-frames, limited to the types the outstation sends and understands.
+
+use std::io;
+
+use chrono::{DateTime, Datelike, NaiveDate, Timelike, Utc};
+use tokio::io::{AsyncRead, AsyncReadExt};
+
+const START: u8 = 0x68;
+
+/// Longest APDU after the start and length bytes.
+const MAX_APDU_LEN: usize = 253;
+
+/// Longest ASDU, what's left of an APDU after the control field.
+const MAX_ASDU_LEN: usize = MAX_APDU_LEN - 4;
+
+/// Sequence numbers are 15 bits and wrap around.
+pub const SEQUENCE_MODULO: u16 = 1 << 15;
+
+pub const M_SP_NA_1: u8 = 1;
+pub const M_DP_NA_1: u8 = 3;
+pub const M_ME_NA_1: u8 = 9;
+pub const M_ME_NB_1: u8 = 11;
+pub const M_ME_NC_1: u8 = 13;
+pub const M_SP_TB_1: u8 = 30;
+pub const M_DP_TB_1: u8 = 31;
+pub const M_ME_TD_1: u8 = 34;
+pub const M_ME_TE_1: u8 = 35;
+pub const M_ME_TF_1: u8 = 36;
+pub const C_SE_NA_1: u8 = 48;
+pub const C_SE_NB_1: u8 = 49;
+pub const C_SE_NC_1: u8 = 50;
+pub const C_SE_TA_1: u8 = 61;
+pub const C_SE_TB_1: u8 = 62;
+pub const C_SE_TC_1: u8 = 63;
+pub const M_EI_NA_1: u8 = 70;
+pub const C_IC_NA_1: u8 = 100;
+pub const C_CS_NA_1: u8 = 103;
+
+pub const COT_SPONTANEOUS: u8 = 3;
+pub const COT_INITIALISED: u8 = 4;
+pub const COT_ACTIVATION: u8 = 6;
+pub const COT_ACTIVATION_CON: u8 = 7;
+pub const COT_DEACTIVATION: u8 = 8;
+pub const COT_DEACTIVATION_CON: u8 = 9;
+pub const COT_ACTIVATION_TERM: u8 = 10;
+pub const COT_INTERROGATED: u8 = 20;
+pub const COT_UNKNOWN_TYPE: u8 = 44;
+pub const COT_UNKNOWN_CAUSE: u8 = 45;
+pub const COT_UNKNOWN_COMMON_ADDRESS: u8 = 46;
+pub const COT_UNKNOWN_ADDRESS: u8 = 47;
+
+/// Qualifier of interrogation for the whole station.
+pub const QOI_STATION: u8 = 20;
+
+/// Quality descriptor bits, the same in SIQ, DIQ and QDS apart from
+/// overflow which only measured values have.
+pub const QUALITY_OVERFLOW: u8 = 0x01;
+pub const QUALITY_NOT_TOPICAL: u8 = 0x40;
+pub const QUALITY_INVALID: u8 = 0x80;
+
+/// Select bit of the qualifier of a setpoint command, cleared to execute.
+pub const QUALIFIER_SELECT: u8 = 0x80;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum UFunction {
+    StartDtAct,
+    StartDtCon,
+    StopDtAct,
+    StopDtCon,
+    TestFrAct,
+    TestFrCon,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Apdu {
+    /// Numbered information transfer, `send` and `receive` are N(S) and N(R).
+    I {
+        send: u16,
+        receive: u16,
+        asdu: Asdu,
+    },
+    /// Acknowledges the I-frames before `receive`.
+    S {
+        receive: u16,
+    },
+    U(UFunction),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Asdu {
+    pub type_id: u8,
+    pub cause: u8,
+    pub negative: bool,
+    pub test: bool,
+    pub originator: u8,
+    pub common_address: u16,
+    pub objects: Vec<InformationObject>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct InformationObject {
+    pub address: u32,
+    pub element: Element,
+    /// Time tag, which selects the CP56Time2a variant of the type.
+    pub time: Option<DateTime<Utc>>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Element {
+    Single {
+        value: bool,
+        quality: u8,
+    },
+    /// State 1 is off and 2 on, 0 and 3 are intermediate and faulty.
+    Double {
+        state: u8,
+        quality: u8,
+    },
+    /// Fraction of full scale in 1/32768.
+    Normalized {
+        value: i16,
+        quality: u8,
+    },
+    Scaled {
+        value: i16,
+        quality: u8,
+    },
+    Float {
+        value: f32,
+        quality: u8,
+    },
+    NormalizedSetpoint {
+        value: i16,
+        qualifier: u8,
+    },
+    ScaledSetpoint {
+        value: i16,
+        qualifier: u8,
+    },
+    FloatSetpoint {
+        value: f32,
+        qualifier: u8,
+    },
+    EndOfInitialisation {
+        cause: u8,
+    },
+    Interrogation {
+        qualifier: u8,
+    },
+    ClockSync {
+        time: DateTime<Utc>,
+    },
+    /// Information of a type that isn't supported, kept to be mirrored back.
+    Unsupported(Vec<u8>),
+}
+
+impl Element {
+    /// Type identification of the element, with or without a time tag.
+    /// `None` for unsupported elements, whose type the ASDU keeps.
+    pub fn type_id(&self, timed: bool) -> Option<u8> {
+        let (plain, with_time) = match self {
+            Element::Single {
+                ..
+            } => (M_SP_NA_1, M_SP_TB_1),
+            Element::Double {
+                ..
+            } => (M_DP_NA_1, M_DP_TB_1),
+            Element::Normalized {
+                ..
+            } => (M_ME_NA_1, M_ME_TD_1),
+            Element::Scaled {
+                ..
+            } => (M_ME_NB_1, M_ME_TE_1),
+            Element::Float {
+                ..
+            } => (M_ME_NC_1, M_ME_TF_1),
+            Element::NormalizedSetpoint {
+                ..
+            } => (C_SE_NA_1, C_SE_TA_1),
+            Element::ScaledSetpoint {
+                ..
+            } => (C_SE_NB_1, C_SE_TB_1),
+            Element::FloatSetpoint {
+                ..
+            } => (C_SE_NC_1, C_SE_TC_1),
+            Element::EndOfInitialisation {
+                ..
+            } => (M_EI_NA_1, M_EI_NA_1),
+            Element::Interrogation {
+                ..
+            } => (C_IC_NA_1, C_IC_NA_1),
+            Element::ClockSync {
+                ..
+            } => (C_CS_NA_1, C_CS_NA_1),
+            Element::Unsupported(_) => return None,
+        };
+        Some(if timed { with_time } else { plain })
+    }
+
+    fn encode(&self, buf: &mut Vec<u8>) {
+        match self {
+            Element::Single {
+                value,
+                quality,
+            } => buf.push(u8::from(*value) | quality),
+            Element::Double {
+                state,
+                quality,
+            } => buf.push((state & 0x03) | quality),
+            Element::Normalized {
+                value,
+                quality,
+            }
+            | Element::Scaled {
+                value,
+                quality,
+            } => {
+                buf.extend_from_slice(&value.to_le_bytes());
+                buf.push(*quality);
+            },
+            Element::Float {
+                value,
+                quality,
+            } => {
+                buf.extend_from_slice(&value.to_le_bytes());
+                buf.push(*quality);
+            },
+            Element::NormalizedSetpoint {
+                value,
+                qualifier,
+            }
+            | Element::ScaledSetpoint {
+                value,
+                qualifier,
+            } => {
+                buf.extend_from_slice(&value.to_le_bytes());
+                buf.push(*qualifier);
+            },
+            Element::FloatSetpoint {
+                value,
+                qualifier,
+            } => {
+                buf.extend_from_slice(&value.to_le_bytes());
+                buf.push(*qualifier);
+            },
+            Element::EndOfInitialisation {
+                cause,
+            } => buf.push(*cause),
+            Element::Interrogation {
+                qualifier,
+            } => buf.push(*qualifier),
+            Element::ClockSync {
+                time,
+            } => put_time(buf, time),
+            Element::Unsupported(bytes) => buf.extend_from_slice(bytes),
+        }
+    }
+
+    /// Reads the element of an object of type `type_id`, and whether the
+    /// type has a time tag after it. `None` for unsupported types.
+    fn parse(type_id: u8, reader: &mut Reader) -> io::Result<Option<(Element, bool)>> {
+        let timed = matches!(
+            type_id,
+            M_SP_TB_1
+                | M_DP_TB_1
+                | M_ME_TD_1
+                | M_ME_TE_1
+                | M_ME_TF_1
+                | C_SE_TA_1
+                | C_SE_TB_1
+                | C_SE_TC_1
+        );
+        let element = match type_id {
+            M_SP_NA_1 | M_SP_TB_1 => {
+                let siq = reader.u8()?;
+                Element::Single {
+                    value: siq & 0x01 != 0,
+                    quality: siq & 0xf0,
+                }
+            },
+            M_DP_NA_1 | M_DP_TB_1 => {
+                let diq = reader.u8()?;
+                Element::Double {
+                    state: diq & 0x03,
+                    quality: diq & 0xf0,
+                }
+            },
+            M_ME_NA_1 | M_ME_TD_1 => Element::Normalized {
+                value: reader.i16()?,
+                quality: reader.u8()?,
+            },
+            M_ME_NB_1 | M_ME_TE_1 => Element::Scaled {
+                value: reader.i16()?,
+                quality: reader.u8()?,
+            },
+            M_ME_NC_1 | M_ME_TF_1 => Element::Float {
+                value: reader.f32()?,
+                quality: reader.u8()?,
+            },
+            C_SE_NA_1 | C_SE_TA_1 => Element::NormalizedSetpoint {
+                value: reader.i16()?,
+                qualifier: reader.u8()?,
+            },
+            C_SE_NB_1 | C_SE_TB_1 => Element::ScaledSetpoint {
+                value: reader.i16()?,
+                qualifier: reader.u8()?,
+            },
+            C_SE_NC_1 | C_SE_TC_1 => Element::FloatSetpoint {
+                value: reader.f32()?,
+                qualifier: reader.u8()?,
+            },
+            M_EI_NA_1 => Element::EndOfInitialisation {
+                cause: reader.u8()?,
+            },
+            C_IC_NA_1 => Element::Interrogation {
+                qualifier: reader.u8()?,
+            },
+            C_CS_NA_1 => Element::ClockSync {
+                time: reader.time()?,
+            },
+            _ => return Ok(None),
+        };
+        Ok(Some((element, timed)))
+    }
+}
+
+impl InformationObject {
+    pub fn new(address: u32, element: Element) -> Self {
+        Self {
+            address,
+            element,
+            time: None,
+        }
+    }
+
+    fn encoded_len(&self) -> usize {
+        let mut element = Vec::new();
+        self.element.encode(&mut element);
+        3 + element.len() + if self.time.is_some() { 7 } else { 0 }
+    }
+}
+
+impl Asdu {
+    /// ASDU with `objects`, which all have the type of the first one.
+    pub fn new(cause: u8, common_address: u16, objects: Vec<InformationObject>) -> Self {
+        let type_id = objects
+            .first()
+            .and_then(|object| object.element.type_id(object.time.is_some()))
+            .unwrap_or_default();
+        Self {
+            type_id,
+            cause,
+            negative: false,
+            test: false,
+            originator: 0,
+            common_address,
+            objects,
+        }
+    }
+
+    /// Splits `objects` into as few ASDUs as the types and the APDU size
+    /// allow, keeping their order.
+    pub fn batches(cause: u8, common_address: u16, objects: Vec<InformationObject>) -> Vec<Asdu> {
+        let mut batches: Vec<Asdu> = Vec::new();
+        let mut len = 0;
+        for object in objects {
+            let object_len = object.encoded_len();
+            let type_id = object.element.type_id(object.time.is_some());
+            match batches.last_mut() {
+                Some(asdu)
+                    if Some(asdu.type_id) == type_id
+                        && asdu.objects.len() < 127
+                        && len + object_len <= MAX_ASDU_LEN =>
+                {
+                    asdu.objects.push(object);
+                    len += object_len;
+                },
+                _ => {
+                    batches.push(Asdu::new(cause, common_address, vec![object]));
+                    len = 6 + object_len;
+                },
+            }
+        }
+        batches
+    }
+
+    /// The ASDU sent back with another cause, for confirmations and for
+    /// refusing commands.
+    pub fn mirror(&self, cause: u8, negative: bool) -> Asdu {
+        Asdu {
+            cause,
+            negative,
+            ..self.clone()
+        }
+    }
+
+    fn encode(&self, buf: &mut Vec<u8>) {
+        buf.push(self.type_id);
+        // Objects are never sent as a sequence, every one has its address
+        buf.push(self.objects.len().min(127) as u8);
+        let mut cause = self.cause & 0x3f;
+        if self.negative {
+            cause |= 0x40;
+        }
+        if self.test {
+            cause |= 0x80;
+        }
+        buf.push(cause);
+        buf.push(self.originator);
+        buf.extend_from_slice(&self.common_address.to_le_bytes());
+        for object in self.objects.iter().take(127) {
+            buf.extend_from_slice(&object.address.to_le_bytes()[..3]);
+            object.element.encode(buf);
+            if let Some(time) = &object.time {
+                put_time(buf, time);
+            }
+        }
+    }
+
+    fn parse(body: &[u8]) -> io::Result<Asdu> {
+        let mut reader = Reader(body);
+        let type_id = reader.u8()?;
+        let vsq = reader.u8()?;
+        let cause = reader.u8()?;
+        let originator = reader.u8()?;
+        let common_address = reader.u16()?;
+        let sequence = vsq & 0x80 != 0;
+        let count = vsq & 0x7f;
+        let mut objects = Vec::with_capacity(usize::from(count));
+        let mut address = 0;
+        for index in 0..count {
+            if index == 0 || !sequence {
+                address = reader.address()?;
+            } else {
+                address += 1;
+            }
+            let Some((element, timed)) = Element::parse(type_id, &mut reader)? else {
+                objects.push(InformationObject::new(
+                    address,
+                    Element::Unsupported(reader.rest().to_vec()),
+                ));
+                break;
+            };
+            let time = timed.then(|| reader.time()).transpose()?;
+            objects.push(InformationObject {
+                address,
+                element,
+                time,
+            });
+        }
+        Ok(Asdu {
+            type_id,
+            cause: cause & 0x3f,
+            negative: cause & 0x40 != 0,
+            test: cause & 0x80 != 0,
+            originator,
+            common_address,
+            objects,
+        })
+    }
+}
+
+impl Apdu {
+    pub fn encode(&self, buf: &mut Vec<u8>) {
+        let mut body = Vec::new();
+        match self {
+            Apdu::I {
+                send,
+                receive,
+                asdu,
+            } => {
+                body.extend_from_slice(&(send << 1).to_le_bytes());
+                body.extend_from_slice(&(receive << 1).to_le_bytes());
+                asdu.encode(&mut body);
+            },
+            Apdu::S {
+                receive,
+            } => {
+                body.extend_from_slice(&[0x01, 0x00]);
+                body.extend_from_slice(&(receive << 1).to_le_bytes());
+            },
+            Apdu::U(function) => {
+                let control = match function {
+                    UFunction::StartDtAct => 0x07,
+                    UFunction::StartDtCon => 0x0b,
+                    UFunction::StopDtAct => 0x13,
+                    UFunction::StopDtCon => 0x23,
+                    UFunction::TestFrAct => 0x43,
+                    UFunction::TestFrCon => 0x83,
+                };
+                body.extend_from_slice(&[control, 0x00, 0x00, 0x00]);
+            },
+        }
+        buf.push(START);
+        // Batches keep ASDUs short enough
+        buf.push(body.len().min(MAX_APDU_LEN) as u8);
+        buf.extend_from_slice(&body);
+    }
+
+    /// Reads the next APDU from a connection.
+    pub async fn read(reader: &mut (impl AsyncRead + Unpin)) -> io::Result<Apdu> {
+        let start = reader.read_u8().await?;
+        if start != START {
+            return Err(invalid(format!("unexpected start byte {start:#04x}")));
+        }
+        let length = usize::from(reader.read_u8().await?);
+        if !(4..=MAX_APDU_LEN).contains(&length) {
+            return Err(invalid(format!("invalid APDU length {length}")));
+        }
+        let mut body = vec![0u8; length];
+        reader.read_exact(&mut body).await?;
+        Apdu::parse(&body)
+    }
+
+    fn parse(body: &[u8]) -> io::Result<Apdu> {
+        let control = &body[..4];
+        let rest = &body[4..];
+        if control[0] & 0x01 == 0 {
+            return Ok(Apdu::I {
+                send: u16::from_le_bytes([control[0], control[1]]) >> 1,
+                receive: u16::from_le_bytes([control[2], control[3]]) >> 1,
+                asdu: Asdu::parse(rest)?,
+            });
+        }
+        if !rest.is_empty() {
+            return Err(invalid("S- or U-frame with an ASDU"));
+        }
+        if control[0] & 0x03 == 0x01 {
+            return Ok(Apdu::S {
+                receive: u16::from_le_bytes([control[2], control[3]]) >> 1,
+            });
+        }
+        let function = match control[0] {
+            0x07 => UFunction::StartDtAct,
+            0x0b => UFunction::StartDtCon,
+            0x13 => UFunction::StopDtAct,
+            0x23 => UFunction::StopDtCon,
+            0x43 => UFunction::TestFrAct,
+            0x83 => UFunction::TestFrCon,
+            control => return Err(invalid(format!("unknown U-frame {control:#04x}"))),
+        };
+        Ok(Apdu::U(function))
+    }
+}
+
+fn invalid(message: impl Into<String>) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message.into())
+}
+
+/// Writes `time` as CP56Time2a, years are counted from 2000.
+fn put_time(buf: &mut Vec<u8>, time: &DateTime<Utc>) {
+    let milliseconds = time.second() * 1000 + time.timestamp_subsec_millis().min(999);
+    buf.extend_from_slice(&(milliseconds as u16).to_le_bytes());
+    buf.push(time.minute() as u8);
+    buf.push(time.hour() as u8);
+    buf.push(time.day() as u8 | (time.weekday().number_from_monday() as u8) << 5);
+    buf.push(time.month() as u8);
+    buf.push(time.year().rem_euclid(100) as u8);
+}
+
+struct Reader<'a>(&'a [u8]);
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, n: usize) -> io::Result<&'a [u8]> {
+        if self.0.len() < n {
+            return Err(invalid("ASDU too short"));
+        }
+        let (taken, rest) = self.0.split_at(n);
+        self.0 = rest;
+        Ok(taken)
+    }
+
+    fn u8(&mut self) -> io::Result<u8> {
+        Ok(self.take(1)?[0])
+    }
+
+    fn u16(&mut self) -> io::Result<u16> {
+        let bytes = self.take(2)?;
+        Ok(u16::from_le_bytes([bytes[0], bytes[1]]))
+    }
+
+    fn i16(&mut self) -> io::Result<i16> {
+        let bytes = self.take(2)?;
+        Ok(i16::from_le_bytes([bytes[0], bytes[1]]))
+    }
+
+    fn f32(&mut self) -> io::Result<f32> {
+        let bytes = self.take(4)?;
+        Ok(f32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+    }
+
+    fn address(&mut self) -> io::Result<u32> {
+        let bytes = self.take(3)?;
+        Ok(u32::from_le_bytes([bytes[0], bytes[1], bytes[2], 0]))
+    }
+
+    fn time(&mut self) -> io::Result<DateTime<Utc>> {
+        let bytes = self.take(7)?;
+        let milliseconds = u32::from(u16::from_le_bytes([bytes[0], bytes[1]]));
+        NaiveDate::from_ymd_opt(
+            2000 + i32::from(bytes[6] & 0x7f),
+            u32::from(bytes[5] & 0x0f),
+            u32::from(bytes[4] & 0x1f),
+        )
+        .and_then(|date| {
+            date.and_hms_milli_opt(
+                u32::from(bytes[3] & 0x1f),
+                u32::from(bytes[2] & 0x3f),
+                milliseconds / 1000,
+                milliseconds % 1000,
+            )
+        })
+        .map(|time| time.and_utc())
+        .ok_or_else(|| invalid("invalid CP56Time2a"))
+    }
+
+    fn rest(&mut self) -> &'a [u8] {
+        std::mem::take(&mut self.0)
+    }
+}
@@ -0,0 +1,426 @@
+//! One connection to a master: APCI sequence numbers, the k/w window and
+//! the t1/t2/t3 timers, and the ASDUs exchanged once data transfer starts.
+
+use std::{collections::VecDeque, io, sync::Arc, time::Duration};
+
+use rcada_core::tag::Tag;
+use tokio::{
+    io::AsyncWriteExt,
+    net::{TcpStream, tcp::OwnedWriteHalf},
+    sync::{broadcast, mpsc},
+    time::Instant,
+};
+
+use crate::audit::Origin;
+
+use super::{
+    Outstation,
+    apdu::{
+        Apdu, Asdu, C_CS_NA_1, C_IC_NA_1, C_SE_NA_1, C_SE_NB_1, C_SE_NC_1, C_SE_TA_1, C_SE_TB_1,
+        C_SE_TC_1, COT_ACTIVATION, COT_ACTIVATION_CON, COT_ACTIVATION_TERM, COT_DEACTIVATION,
+        COT_DEACTIVATION_CON, COT_INITIALISED, COT_INTERROGATED, COT_SPONTANEOUS,
+        COT_UNKNOWN_ADDRESS, COT_UNKNOWN_CAUSE, COT_UNKNOWN_COMMON_ADDRESS, COT_UNKNOWN_TYPE,
+        Element, InformationObject, QOI_STATION, QUALIFIER_SELECT, SEQUENCE_MODULO, UFunction,
+    },
+};
+
+/// Common address of ASDUs meant for every station.
+const BROADCAST_ADDRESS: u16 = u16::MAX;
+
+/// Serves the master on `stream` until it disconnects or breaks the protocol.
+pub(super) async fn run(stream: TcpStream, outstation: Arc<Outstation>) -> io::Result<()> {
+    let _ = stream.set_nodelay(true);
+    let origin = Origin::peer("iec104", stream.peer_addr().ok());
+    // Before anything is sent, so that no change is missed
+    let updates = outstation.subscribe().await?;
+    let (mut reader, writer) = stream.into_split();
+    // Reading a frame isn't cancel safe, so it's done apart from the timers
+    let (frames, received) = mpsc::channel(16);
+    let reading = tokio::spawn(async move {
+        loop {
+            let frame = Apdu::read(&mut reader).await;
+            let failed = frame.is_err();
+            if frames.send(frame).await.is_err() || failed {
+                break;
+            }
+        }
+    });
+    let mut connection = Connection::new(outstation, writer, origin);
+    let result = connection.serve(received, updates).await;
+    reading.abort();
+    result
+}
+
+struct Connection {
+    outstation: Arc<Outstation>,
+    writer: OwnedWriteHalf,
+    /// Origin of the setpoint commands of the master in the audit log.
+    origin: Origin,
+    t1: Duration,
+    t2: Duration,
+    t3: Duration,
+    /// Data transfer was started with STARTDT.
+    started: bool,
+    /// End of initialisation was sent.
+    initialised: bool,
+    /// Sequence numbers V(S) of the next I-frame sent and V(R) of the next
+    /// one received.
+    send_sequence: u16,
+    receive_sequence: u16,
+    /// Sequence numbers of the I-frames the master didn't acknowledge yet,
+    /// with when they were sent.
+    unacknowledged: VecDeque<(u16, Instant)>,
+    /// ASDUs waiting for the k window to open.
+    queue: VecDeque<Asdu>,
+    /// I-frames received since the last acknowledgement, and when the first
+    /// of them was.
+    received: u16,
+    received_since: Option<Instant>,
+    last_received: Instant,
+    /// When a TESTFR sent because of t3 is waiting for its confirmation.
+    test_sent: Option<Instant>,
+}
+
+impl Connection {
+    fn new(outstation: Arc<Outstation>, writer: OwnedWriteHalf, origin: Origin) -> Self {
+        let config = &outstation.config;
+        Self {
+            t1: Duration::from_secs(config.t1_secs),
+            t2: Duration::from_secs(config.t2_secs),
+            t3: Duration::from_secs(config.t3_secs),
+            outstation,
+            writer,
+            origin,
+            started: false,
+            initialised: false,
+            send_sequence: 0,
+            receive_sequence: 0,
+            unacknowledged: VecDeque::new(),
+            queue: VecDeque::new(),
+            received: 0,
+            received_since: None,
+            last_received: Instant::now(),
+            test_sent: None,
+        }
+    }
+
+    async fn serve(
+        &mut self,
+        mut frames: mpsc::Receiver<io::Result<Apdu>>,
+        mut updates: broadcast::Receiver<Tag>,
+    ) -> io::Result<()> {
+        loop {
+            self.flush().await?;
+            let deadline = self.deadline();
+            tokio::select! {
+                frame = frames.recv() => match frame {
+                    Some(frame) => self.receive(frame?).await?,
+                    None => return Ok(()),
+                },
+                update = updates.recv() => match update {
+                    Ok(tag) => self.changed(&tag),
+                    Err(broadcast::error::RecvError::Lagged(missed)) => {
+                        tracing::warn!("IEC 104 missed {} tag updates", missed);
+                    },
+                    Err(broadcast::error::RecvError::Closed) => return Ok(()),
+                },
+                _ = tokio::time::sleep_until(deadline) => self.timeout().await?,
+            }
+        }
+    }
+
+    /// Next time one of the timers runs out.
+    fn deadline(&self) -> Instant {
+        let t1 = self
+            .unacknowledged
+            .front()
+            .map(|(_, sent)| *sent)
+            .into_iter()
+            .chain(self.test_sent)
+            .min()
+            .map(|sent| sent + self.t1);
+        let t2 = self.received_since.map(|since| since + self.t2);
+        let t3 = self.last_received + self.t3;
+        [t1, t2].into_iter().flatten().fold(t3, Instant::min)
+    }
+
+    async fn timeout(&mut self) -> io::Result<()> {
+        let now = Instant::now();
+        let t1_expired = |sent: Instant| now.duration_since(sent) >= self.t1;
+        if self
+            .unacknowledged
+            .front()
+            .is_some_and(|(_, sent)| t1_expired(*sent))
+        {
+            return Err(io::Error::new(
+                io::ErrorKind::TimedOut,
+                "I-frames not acknowledged within t1",
+            ));
+        }
+        if self.test_sent.is_some_and(t1_expired) {
+            return Err(io::Error::new(
+                io::ErrorKind::TimedOut,
+                "TESTFR not confirmed within t1",
+            ));
+        }
+        if self
+            .received_since
+            .is_some_and(|since| now.duration_since(since) >= self.t2)
+        {
+            self.acknowledge_received().await?;
+        }
+        if self.test_sent.is_none() && now.duration_since(self.last_received) >= self.t3 {
+            self.send(&Apdu::U(UFunction::TestFrAct)).await?;
+            self.test_sent = Some(now);
+        }
+        Ok(())
+    }
+
+    async fn receive(&mut self, frame: Apdu) -> io::Result<()> {
+        self.last_received = Instant::now();
+        match frame {
+            Apdu::I {
+                send,
+                receive,
+                asdu,
+            } => {
+                if !self.started {
+                    return Err(protocol_error("I-frame before STARTDT"));
+                }
+                if send != self.receive_sequence {
+                    return Err(protocol_error(format!(
+                        "I-frame {send} received, {} expected",
+                        self.receive_sequence
+                    )));
+                }
+                self.receive_sequence = (self.receive_sequence + 1) % SEQUENCE_MODULO;
+                self.received += 1;
+                self.received_since.get_or_insert(self.last_received);
+                self.acknowledged(receive)?;
+                self.command(asdu).await?;
+                if self.received >= self.outstation.config.w {
+                    self.acknowledge_received().await?;
+                }
+            },
+            Apdu::S {
+                receive,
+            } => self.acknowledged(receive)?,
+            Apdu::U(UFunction::StartDtAct) => {
+                self.started = true;
+                self.send(&Apdu::U(UFunction::StartDtCon)).await?;
+                if !self.initialised {
+                    self.initialised = true;
+                    self.queue.push_back(Asdu::new(
+                        COT_INITIALISED,
+                        self.outstation.config.common_address,
+                        vec![InformationObject::new(
+                            0,
+                            Element::EndOfInitialisation {
+                                cause: 0,
+                            },
+                        )],
+                    ));
+                }
+            },
+            Apdu::U(UFunction::StopDtAct) => {
+                // The master interrogates again after starting
+                self.started = false;
+                self.queue.clear();
+                self.send(&Apdu::U(UFunction::StopDtCon)).await?;
+            },
+            Apdu::U(UFunction::TestFrAct) => self.send(&Apdu::U(UFunction::TestFrCon)).await?,
+            Apdu::U(UFunction::TestFrCon) => self.test_sent = None,
+            Apdu::U(UFunction::StartDtCon | UFunction::StopDtCon) => {},
+        }
+        Ok(())
+    }
+
+    /// Drops the I-frames the master acknowledged with N(R) `receive`.
+    fn acknowledged(&mut self, receive: u16) -> io::Result<()> {
+        let outstanding =
+            usize::from((self.send_sequence + SEQUENCE_MODULO - receive) % SEQUENCE_MODULO);
+        if outstanding > self.unacknowledged.len() {
+            return Err(protocol_error(format!(
+                "acknowledgement of I-frame {receive} which wasn't sent"
+            )));
+        }
+        while self.unacknowledged.len() > outstanding {
+            self.unacknowledged.pop_front();
+        }
+        Ok(())
+    }
+
+    async fn acknowledge_received(&mut self) -> io::Result<()> {
+        self.send(&Apdu::S {
+            receive: self.receive_sequence,
+        })
+        .await
+    }
+
+    /// Sends queued ASDUs while fewer than k I-frames are unacknowledged.
+    async fn flush(&mut self) -> io::Result<()> {
+        while self.started && self.unacknowledged.len() < usize::from(self.outstation.config.k) {
+            let Some(asdu) = self.queue.pop_front() else {
+                break;
+            };
+            let frame = Apdu::I {
+                send: self.send_sequence,
+                receive: self.receive_sequence,
+                asdu,
+            };
+            self.send(&frame).await?;
+            self.unacknowledged
+                .push_back((self.send_sequence, Instant::now()));
+            self.send_sequence = (self.send_sequence + 1) % SEQUENCE_MODULO;
+        }
+        Ok(())
+    }
+
+    async fn send(&mut self, frame: &Apdu) -> io::Result<()> {
+        if matches!(frame, Apdu::I { .. } | Apdu::S { .. }) {
+            // Both acknowledge what was received
+            self.received = 0;
+            self.received_since = None;
+        }
+        let mut buf = Vec::new();
+        frame.encode(&mut buf);
+        self.writer.write_all(&buf).await
+    }
+
+    fn changed(&mut self, tag: &Tag) {
+        if !self.started {
+            return;
+        }
+        let objects = self.outstation.changed(tag);
+        self.queue.extend(Asdu::batches(
+            COT_SPONTANEOUS,
+            self.outstation.config.common_address,
+            objects,
+        ));
+    }
+
+    /// Answers an ASDU from the master, replies are queued like data.
+    async fn command(&mut self, asdu: Asdu) -> io::Result<()> {
+        let common_address = self.outstation.config.common_address;
+        if asdu.common_address != common_address && asdu.common_address != BROADCAST_ADDRESS {
+            self.queue
+                .push_back(asdu.mirror(COT_UNKNOWN_COMMON_ADDRESS, true));
+            return Ok(());
+        }
+        match asdu.type_id {
+            C_IC_NA_1 => self.interrogation(asdu).await?,
+            C_CS_NA_1 => self.clock_sync(asdu),
+            C_SE_NA_1 | C_SE_NB_1 | C_SE_NC_1 | C_SE_TA_1 | C_SE_TB_1 | C_SE_TC_1 => {
+                self.setpoint(asdu).await;
+            },
+            type_id => {
+                tracing::debug!("IEC 104 ASDU of unsupported type {}", type_id);
+                self.queue.push_back(asdu.mirror(COT_UNKNOWN_TYPE, true));
+            },
+        }
+        Ok(())
+    }
+
+    async fn interrogation(&mut self, asdu: Asdu) -> io::Result<()> {
+        let qualifier = match asdu.objects.first().map(|object| &object.element) {
+            Some(Element::Interrogation {
+                qualifier,
+            }) => *qualifier,
+            _ => return Err(protocol_error("interrogation without qualifier")),
+        };
+        match asdu.cause {
+            // Groups aren't configured, only the station is interrogated
+            COT_ACTIVATION if qualifier == QOI_STATION => {
+                self.queue.push_back(asdu.mirror(COT_ACTIVATION_CON, false));
+                let objects = self.outstation.snapshot().await?;
+                self.queue.extend(Asdu::batches(
+                    COT_INTERROGATED,
+                    self.outstation.config.common_address,
+                    objects,
+                ));
+                self.queue
+                    .push_back(asdu.mirror(COT_ACTIVATION_TERM, false));
+            },
+            COT_ACTIVATION => self.queue.push_back(asdu.mirror(COT_ACTIVATION_CON, true)),
+            // Interrogations are answered at once, there's nothing to stop
+            COT_DEACTIVATION => self
+                .queue
+                .push_back(asdu.mirror(COT_DEACTIVATION_CON, true)),
+            _ => self.queue.push_back(asdu.mirror(COT_UNKNOWN_CAUSE, true)),
+        }
+        Ok(())
+    }
+
+    fn clock_sync(&mut self, asdu: Asdu) {
+        let time = match asdu.objects.first().map(|object| &object.element) {
+            Some(Element::ClockSync {
+                time,
+            }) if asdu.cause == COT_ACTIVATION => *time,
+            _ => {
+                self.queue.push_back(asdu.mirror(COT_UNKNOWN_CAUSE, true));
+                return;
+            },
+        };
+        self.outstation.set_clock(time);
+        tracing::info!("IEC 104 clock synchronised to {}", time);
+        self.queue.push_back(asdu.mirror(COT_ACTIVATION_CON, false));
+    }
+
+    /// Writes the value of an executed setpoint to its tag. A select is
+    /// confirmed without being remembered, executing works without one.
+    /// Commands carry a single information object, others are refused.
+    async fn setpoint(&mut self, asdu: Asdu) {
+        let object = match asdu.objects.as_slice() {
+            [object] => object,
+            [] => {
+                self.queue.push_back(asdu.mirror(COT_UNKNOWN_ADDRESS, true));
+                return;
+            },
+            _ => {
+                self.queue.push_back(asdu.mirror(COT_ACTIVATION_CON, true));
+                return;
+            },
+        };
+        let Some(&index) = self.outstation.by_setpoint.get(&object.address) else {
+            self.queue.push_back(asdu.mirror(COT_UNKNOWN_ADDRESS, true));
+            return;
+        };
+        let point = &self.outstation.config.points[index];
+        let Some(value) = point.setpoint(&object.element) else {
+            self.queue.push_back(asdu.mirror(COT_UNKNOWN_TYPE, true));
+            return;
+        };
+        let select = match object.element {
+            Element::NormalizedSetpoint {
+                qualifier,
+                ..
+            }
+            | Element::ScaledSetpoint {
+                qualifier,
+                ..
+            }
+            | Element::FloatSetpoint {
+                qualifier,
+                ..
+            } => qualifier & QUALIFIER_SELECT != 0,
+            _ => false,
+        };
+        let reply = match asdu.cause {
+            COT_ACTIVATION if select => asdu.mirror(COT_ACTIVATION_CON, false),
+            COT_ACTIVATION => match self.outstation.write(point, value, &self.origin).await {
+                Ok(()) => asdu.mirror(COT_ACTIVATION_CON, false),
+                Err(e) => {
+                    tracing::warn!("IEC 104 setpoint {} refused: {}", object.address, e);
+                    asdu.mirror(COT_ACTIVATION_CON, true)
+                },
+            },
+            COT_DEACTIVATION => asdu.mirror(COT_DEACTIVATION_CON, false),
+            _ => asdu.mirror(COT_UNKNOWN_CAUSE, true),
+        };
+        self.queue.push_back(reply);
+    }
+}
+
+fn protocol_error(message: impl Into<String>) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message.into())
+}
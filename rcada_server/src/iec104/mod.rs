@@ -0,0 +1,901 @@
+//! IEC 60870-5-104 outstation serving mapped tags to a SCADA master or
+//! control centre, next to the REST, gRPC and OPC UA interfaces.
+
+pub mod apdu;
+mod connection;
+
+use std::{
+    collections::{HashMap, HashSet},
+    future::Future,
+    io,
+    sync::{Arc, Mutex},
+};
+
+use chrono::{DateTime, TimeDelta, Utc};
+use ractor::ActorRef;
+use rcada_core::{
+    tag::{Quality, Tag, TagName, TagValue},
+    value::{DataType, Value},
+};
+use serde::{Deserialize, Serialize};
+use tokio::{net::TcpListener, sync::broadcast, task::JoinSet};
+
+use crate::{
+    actor::{self, Mailbox},
+    audit::Origin,
+    repository::tag::UpdateValueError,
+};
+
+use self::apdu::{
+    Element, InformationObject, QUALITY_INVALID, QUALITY_NOT_TOPICAL, QUALITY_OVERFLOW,
+};
+
+/// Largest information object address, they're three bytes.
+const MAX_IOA: u32 = 0xff_ffff;
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Iec104Config {
+    /// Serve the points below over IEC 60870-5-104.
+    #[serde(default)]
+    pub enabled: bool,
+    #[serde(default = "default_bind")]
+    pub bind: String,
+    /// Common address of the ASDUs, the station address the master uses.
+    #[serde(default = "default_common_address")]
+    pub common_address: u16,
+    /// I-frames sent without being acknowledged before the outstation waits.
+    #[serde(default = "default_k")]
+    pub k: u16,
+    /// I-frames received before the outstation acknowledges them.
+    #[serde(default = "default_w")]
+    pub w: u16,
+    /// Time after which a frame that isn't acknowledged closes the connection.
+    #[serde(default = "default_t1_secs")]
+    pub t1_secs: u64,
+    /// Longest time received I-frames stay unacknowledged, below `t1_secs`.
+    #[serde(default = "default_t2_secs")]
+    pub t2_secs: u64,
+    /// Idle time after which the connection is tested with TESTFR.
+    #[serde(default = "default_t3_secs")]
+    pub t3_secs: u64,
+    /// Send spontaneous values with a CP56Time2a time tag.
+    #[serde(default = "default_time_tags")]
+    pub time_tags: bool,
+    #[serde(default)]
+    pub points: Vec<Iec104Point>,
+}
+
+/// A tag served under an information object address.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Iec104Point {
+    pub tag: String,
+    pub ioa: u32,
+    #[serde(rename = "type")]
+    pub kind: PointType,
+    /// Tag value of one transmitted unit, full scale for normalised values.
+    #[serde(default = "default_scale")]
+    pub scale: f64,
+    /// Address of the setpoint command writing the tag, for measured values.
+    #[serde(default)]
+    pub setpoint_ioa: Option<u32>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum PointType {
+    Single,
+    /// Booleans are sent as on and off, integers as the raw state 0 to 3.
+    Double,
+    Normalized,
+    Scaled,
+    Float,
+}
+
+fn default_bind() -> String {
+    "127.0.0.1:2404".to_string()
+}
+
+fn default_common_address() -> u16 {
+    1
+}
+
+fn default_k() -> u16 {
+    12
+}
+
+fn default_w() -> u16 {
+    8
+}
+
+fn default_t1_secs() -> u64 {
+    15
+}
+
+fn default_t2_secs() -> u64 {
+    10
+}
+
+fn default_t3_secs() -> u64 {
+    20
+}
+
+fn default_time_tags() -> bool {
+    true
+}
+
+fn default_scale() -> f64 {
+    1.0
+}
+
+impl Default for Iec104Config {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            bind: default_bind(),
+            common_address: default_common_address(),
+            k: default_k(),
+            w: default_w(),
+            t1_secs: default_t1_secs(),
+            t2_secs: default_t2_secs(),
+            t3_secs: default_t3_secs(),
+            time_tags: default_time_tags(),
+            points: Vec::new(),
+        }
+    }
+}
+
+impl Iec104Config {
+    /// Checks the protocol parameters and the points, the bind address is
+    /// checked with the other servers.
+    pub fn validate(&self) -> Vec<String> {
+        let mut errors = Vec::new();
+        if self.common_address == 0 || self.common_address == u16::MAX {
+            errors.push("iec104.common_address: must be between 1 and 65534".to_string());
+        }
+        if self.k == 0 || self.k >= apdu::SEQUENCE_MODULO {
+            errors.push("iec104.k: must be between 1 and 32767".to_string());
+        }
+        if self.w == 0 || self.w > self.k {
+            errors.push("iec104.w: must be positive and at most k".to_string());
+        }
+        if self.t1_secs == 0 || self.t2_secs == 0 || self.t3_secs == 0 {
+            errors.push("iec104: timeouts must be positive".to_string());
+        }
+        if self.t2_secs >= self.t1_secs {
+            errors.push("iec104.t2_secs: must be below t1_secs".to_string());
+        }
+        let mut addresses = HashSet::new();
+        for point in &self.points {
+            if point.tag.is_empty() {
+                errors.push("iec104.points: tag name is empty".to_string());
+            }
+            for ioa in std::iter::once(point.ioa).chain(point.setpoint_ioa) {
+                if ioa == 0 || ioa > MAX_IOA {
+                    errors.push(format!(
+                        "iec104.points: {}: address {ioa} is not between 1 and {MAX_IOA}",
+                        point.tag
+                    ));
+                } else if !addresses.insert(ioa) {
+                    errors.push(format!(
+                        "iec104.points: address {ioa} is used more than once"
+                    ));
+                }
+            }
+            if !point.scale.is_finite() || point.scale == 0.0 {
+                errors.push(format!(
+                    "iec104.points: {}: scale must be finite and not zero",
+                    point.tag
+                ));
+            }
+            if point.setpoint_ioa.is_some()
+                && matches!(point.kind, PointType::Single | PointType::Double)
+            {
+                errors.push(format!(
+                    "iec104.points: {}: setpoints are only for measured values",
+                    point.tag
+                ));
+            }
+        }
+        errors
+    }
+}
+
+impl Iec104Point {
+    /// Information object with `value`, invalid when the tag has no value
+    /// or one the point can't represent.
+    fn object(&self, value: Option<&TagValue>, time: Option<DateTime<Utc>>) -> InformationObject {
+        let mut quality = match value.map(|value| value.quality) {
+            Some(Quality::Good) => 0,
+            Some(Quality::Uncertain) => QUALITY_NOT_TOPICAL,
+            Some(Quality::Bad) | None => QUALITY_INVALID,
+        };
+        let number = match value.map(|value| &value.value) {
+            Some(Value::Integer(i)) => *i as f64,
+            Some(Value::Float(f)) => f64::from(*f),
+            Some(Value::Boolean(b)) => f64::from(u8::from(*b)),
+            Some(Value::String(_)) | None => {
+                quality |= QUALITY_INVALID;
+                0.0
+            },
+        };
+        let element = match self.kind {
+            PointType::Single => Element::Single {
+                value: number != 0.0,
+                quality,
+            },
+            PointType::Double => {
+                let state = match value.map(|value| &value.value) {
+                    Some(Value::Boolean(on)) => 1 + u8::from(*on),
+                    _ if (0.0..=3.0).contains(&number) && number.fract() == 0.0 => number as u8,
+                    _ => {
+                        quality |= QUALITY_INVALID;
+                        0
+                    },
+                };
+                Element::Double {
+                    state,
+                    quality,
+                }
+            },
+            PointType::Normalized => {
+                let (value, overflow) = to_i16(number / self.scale * 32768.0);
+                Element::Normalized {
+                    value,
+                    quality: quality | overflow,
+                }
+            },
+            PointType::Scaled => {
+                let (value, overflow) = to_i16(number / self.scale);
+                Element::Scaled {
+                    value,
+                    quality: quality | overflow,
+                }
+            },
+            PointType::Float => Element::Float {
+                value: (number / self.scale) as f32,
+                quality,
+            },
+        };
+        InformationObject {
+            address: self.ioa,
+            element,
+            time,
+        }
+    }
+
+    /// Tag value set by a setpoint command, `None` if the command doesn't
+    /// match the type of the point.
+    fn setpoint(&self, element: &Element) -> Option<f64> {
+        match (self.kind, element) {
+            (
+                PointType::Normalized,
+                Element::NormalizedSetpoint {
+                    value,
+                    ..
+                },
+            ) => Some(f64::from(*value) / 32768.0 * self.scale),
+            (
+                PointType::Scaled,
+                Element::ScaledSetpoint {
+                    value,
+                    ..
+                },
+            ) => Some(f64::from(*value) * self.scale),
+            (
+                PointType::Float,
+                Element::FloatSetpoint {
+                    value,
+                    ..
+                },
+            ) => Some(f64::from(*value) * self.scale),
+            _ => None,
+        }
+    }
+}
+
+/// `value` rounded into an `i16` and the overflow bit if it didn't fit.
+fn to_i16(value: f64) -> (i16, u8) {
+    let rounded = value.round();
+    if rounded < f64::from(i16::MIN) || rounded > f64::from(i16::MAX) {
+        (
+            rounded.clamp(f64::from(i16::MIN), f64::from(i16::MAX)) as i16,
+            QUALITY_OVERFLOW,
+        )
+    } else {
+        (rounded as i16, 0)
+    }
+}
+
+/// What the connections share: the points and the clock set by the master.
+struct Outstation {
+    config: Iec104Config,
+    tag_repo: ActorRef<actor::tag::Message>,
+    /// Indices of the points of every tag.
+    by_tag: HashMap<TagName, Vec<usize>>,
+    /// Indices of the points by setpoint address.
+    by_setpoint: HashMap<u32, usize>,
+    /// Difference between the time set by clock synchronisation and the
+    /// system clock, added to the time tags.
+    clock_offset: Mutex<TimeDelta>,
+}
+
+impl Outstation {
+    fn new(config: Iec104Config, tag_repo: ActorRef<actor::tag::Message>) -> Self {
+        let mut by_tag: HashMap<TagName, Vec<usize>> = HashMap::new();
+        let mut by_setpoint = HashMap::new();
+        for (index, point) in config.points.iter().enumerate() {
+            by_tag
+                .entry(TagName::from(point.tag.as_str()))
+                .or_default()
+                .push(index);
+            if let Some(ioa) = point.setpoint_ioa {
+                by_setpoint.insert(ioa, index);
+            }
+        }
+        Self {
+            config,
+            tag_repo,
+            by_tag,
+            by_setpoint,
+            clock_offset: Mutex::new(TimeDelta::zero()),
+        }
+    }
+
+    /// `time` on the clock of the master.
+    fn time(&self, time: DateTime<Utc>) -> DateTime<Utc> {
+        time + *self.clock_offset.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    fn set_clock(&self, time: DateTime<Utc>) {
+        *self.clock_offset.lock().unwrap_or_else(|e| e.into_inner()) = time - Utc::now();
+    }
+
+    async fn subscribe(&self) -> io::Result<broadcast::Receiver<Tag>> {
+        let (command, mut reply) = actor::tag::Message::subscribe();
+        self.tag_repo.enqueue(command).map_err(io::Error::other)?;
+        reply
+            .recv()
+            .await
+            .ok_or_else(|| io::Error::other("tag repository didn't answer the subscription"))
+    }
+
+    /// Every point with the current value of its tag, for interrogations.
+    async fn snapshot(&self) -> io::Result<Vec<InformationObject>> {
+        let (command, mut reply) = actor::tag::Message::get_all_tags();
+        self.tag_repo.enqueue(command).map_err(io::Error::other)?;
+        let tags: HashMap<TagName, TagValue> = reply
+            .recv()
+            .await
+            .ok_or_else(|| io::Error::other("tag repository didn't answer"))?
+            .into_iter()
+            .map(|tag| (tag.name, tag.value))
+            .collect();
+        Ok(self
+            .config
+            .points
+            .iter()
+            .map(|point| point.object(tags.get(point.tag.as_str()), None))
+            .collect())
+    }
+
+    /// Points of `tag` with its new value, for spontaneous transmission.
+    fn changed(&self, tag: &Tag) -> Vec<InformationObject> {
+        let Some(points) = self.by_tag.get(&tag.name) else {
+            return Vec::new();
+        };
+        let time = self
+            .config
+            .time_tags
+            .then(|| self.time(tag.value.timestamp.unwrap_or_else(Utc::now)));
+        points
+            .iter()
+            .map(|&index| self.config.points[index].object(Some(&tag.value), time))
+            .collect()
+    }
+
+    /// Writes the value of a setpoint command of `origin` to the tag of `point`.
+    async fn write(&self, point: &Iec104Point, value: f64, origin: &Origin) -> Result<(), String> {
+        let (command, mut reply) = actor::tag::Message::get_tag(point.tag.as_str());
+        self.tag_repo.enqueue(command).map_err(|e| e.to_string())?;
+        let tag = reply
+            .recv()
+            .await
+            .and_then(Result::ok)
+            .ok_or_else(|| format!("tag {} not found", point.tag))?;
+        let value = match tag.meta.data_type {
+            DataType::Integer if value.is_finite() => Value::Integer(value.round() as i64),
+            DataType::Float => Value::Float(value as f32),
+            DataType::Boolean => Value::Boolean(value != 0.0),
+            data_type => {
+                return Err(format!(
+                    "tag {} of type {data_type:?} can't take {value}",
+                    point.tag
+                ));
+            },
+        };
+        let value = TagValue {
+            value,
+            timestamp: Some(Utc::now()),
+            quality: Quality::Good,
+        };
+        let (command, mut reply) = actor::tag::Message::update_tag_value(point.tag.as_str(), value);
+        self.tag_repo
+            .enqueue(command.with_origin(origin.clone()))
+            .map_err(|e| e.to_string())?;
+        match reply.recv().await {
+            Some(Ok(_)) => Ok(()),
+            Some(Err(UpdateValueError::ReadOnly)) => Err(format!("tag {} is read-only", point.tag)),
+            Some(Err(e)) => Err(format!("tag {}: {e:?}", point.tag)),
+            None => Err("tag repository didn't answer".to_string()),
+        }
+    }
+}
+
+/// Serves the configured points to IEC 104 masters connecting to `listener`
+/// until `shutdown` completes.
+pub async fn serve(
+    listener: TcpListener,
+    config: &Iec104Config,
+    tag_repo: ActorRef<actor::tag::Message>,
+    shutdown: impl Future<Output = ()> + Send + 'static,
+) -> io::Result<()> {
+    let outstation = Arc::new(Outstation::new(config.clone(), tag_repo));
+    tracing::info!("Listening for IEC 104 on {}", listener.local_addr()?);
+    tokio::pin!(shutdown);
+    let mut connections = JoinSet::new();
+    loop {
+        tokio::select! {
+            _ = &mut shutdown => break,
+            accepted = listener.accept() => {
+                let (stream, peer) = match accepted {
+                    Ok(accepted) => accepted,
+                    Err(e) => {
+                        tracing::warn!("IEC 104 connection failed: {}", e);
+                        continue;
+                    },
+                };
+                let outstation = outstation.clone();
+                connections.spawn(async move {
+                    tracing::info!("IEC 104 master {} connected", peer);
+                    match connection::run(stream, outstation).await {
+                        Ok(()) => tracing::info!("IEC 104 master {} disconnected", peer),
+                        Err(e) => tracing::warn!("IEC 104 master {} disconnected: {}", peer, e),
+                    }
+                });
+            },
+            Some(_) = connections.join_next(), if !connections.is_empty() => {},
+        }
+    }
+    connections.shutdown().await;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use ractor::Actor;
+    use rcada_core::{tag::TagMeta, unit::Unit};
+    use tokio::{
+        io::AsyncWriteExt,
+        net::TcpStream,
+        time::{Instant, timeout},
+    };
+
+    use super::{apdu::*, *};
+    use crate::{
+        actor::tag::TagRepositoryActor,
+        audit::AuditLog,
+        repository::tag::{TagMetaPatch, inmemory::TagStorage},
+    };
+
+    /// Test master acknowledging every I-frame unless it's holding them.
+    struct Master {
+        stream: TcpStream,
+        send: u16,
+        receive: u16,
+        hold: bool,
+    }
+
+    impl Master {
+        async fn send(&mut self, frame: Apdu) {
+            let mut buf = Vec::new();
+            frame.encode(&mut buf);
+            self.stream.write_all(&buf).await.unwrap();
+        }
+
+        async fn command(&mut self, cause: u8, object: InformationObject) {
+            self.commands(cause, vec![object]).await;
+        }
+
+        async fn commands(&mut self, cause: u8, objects: Vec<InformationObject>) {
+            let frame = Apdu::I {
+                send: self.send,
+                receive: self.receive,
+                asdu: Asdu::new(cause, 1, objects),
+            };
+            self.send += 1;
+            self.send(frame).await;
+        }
+
+        async fn ack(&mut self) {
+            self.send(Apdu::S {
+                receive: self.receive,
+            })
+            .await;
+        }
+
+        async fn frame(&mut self) -> Apdu {
+            let frame = timeout(Duration::from_secs(5), Apdu::read(&mut self.stream))
+                .await
+                .expect("no frame from the outstation")
+                .unwrap();
+            if let Apdu::I {
+                send,
+                ..
+            } = &frame
+            {
+                assert_eq!(*send, self.receive);
+                self.receive += 1;
+                if !self.hold {
+                    self.ack().await;
+                }
+            }
+            frame
+        }
+
+        /// Next ASDU, skipping spontaneous ones unless `spontaneous`.
+        async fn asdu(&mut self, spontaneous: bool) -> Asdu {
+            loop {
+                if let Apdu::I {
+                    asdu,
+                    ..
+                } = self.frame().await
+                    && (spontaneous || asdu.cause != COT_SPONTANEOUS)
+                {
+                    return asdu;
+                }
+            }
+        }
+    }
+
+    async fn create(
+        tag_repo: &ActorRef<actor::tag::Message>,
+        name: &str,
+        data_type: DataType,
+        value: Value,
+    ) {
+        let (command, mut reply) =
+            actor::tag::Message::create_tag(name, TagMeta::new(Unit::None, data_type));
+        tag_repo.enqueue(command).unwrap();
+        reply.recv().await.unwrap();
+        set(tag_repo, name, value).await;
+    }
+
+    async fn set(tag_repo: &ActorRef<actor::tag::Message>, name: &str, value: Value) {
+        let value = TagValue {
+            value,
+            timestamp: Some(Utc::now()),
+            quality: Quality::Good,
+        };
+        let (command, mut reply) = actor::tag::Message::update_tag_value(name, value);
+        tag_repo.enqueue(command).unwrap();
+        reply.recv().await.unwrap().unwrap();
+    }
+
+    async fn value(tag_repo: &ActorRef<actor::tag::Message>, name: &str) -> Value {
+        let (command, mut reply) = actor::tag::Message::get_tag(name);
+        tag_repo.enqueue(command).unwrap();
+        reply.recv().await.unwrap().unwrap().value.value
+    }
+
+    fn point(
+        tag: &str,
+        ioa: u32,
+        kind: PointType,
+        scale: f64,
+        setpoint_ioa: Option<u32>,
+    ) -> Iec104Point {
+        Iec104Point {
+            tag: tag.to_string(),
+            ioa,
+            kind,
+            scale,
+            setpoint_ioa,
+        }
+    }
+
+    #[tokio::test]
+    async fn serves_points_to_a_master() {
+        let (tag_repo, _) = Actor::spawn(
+            None,
+            TagRepositoryActor::default(),
+            (TagStorage::default(), AuditLog::disabled()),
+        )
+        .await
+        .unwrap();
+        create(
+            &tag_repo,
+            "plant/breaker",
+            DataType::Boolean,
+            Value::Boolean(true),
+        )
+        .await;
+        create(
+            &tag_repo,
+            "plant/level",
+            DataType::Float,
+            Value::Float(50.0),
+        )
+        .await;
+        create(&tag_repo, "plant/flow", DataType::Float, Value::Float(12.5)).await;
+        create(
+            &tag_repo,
+            "plant/target",
+            DataType::Integer,
+            Value::Integer(100),
+        )
+        .await;
+        create(
+            &tag_repo,
+            "plant/limit",
+            DataType::Float,
+            Value::Float(80.0),
+        )
+        .await;
+        let (command, mut reply) = actor::tag::Message::update_tag_meta(
+            "plant/limit",
+            TagMetaPatch {
+                read_only: Some(true),
+                ..Default::default()
+            },
+        );
+        tag_repo.enqueue(command).unwrap();
+        reply.recv().await.unwrap().unwrap();
+
+        let config = Iec104Config {
+            enabled: true,
+            k: 3,
+            w: 2,
+            points: vec![
+                point("plant/breaker", 100, PointType::Single, 1.0, None),
+                point("plant/level", 200, PointType::Normalized, 100.0, None),
+                point("plant/flow", 300, PointType::Float, 1.0, Some(301)),
+                point("plant/target", 400, PointType::Scaled, 0.1, Some(401)),
+                point("plant/limit", 500, PointType::Float, 1.0, Some(501)),
+            ],
+            ..Iec104Config::default()
+        };
+        assert!(config.validate().is_empty(), "{:?}", config.validate());
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap();
+        let (stop, stopped) = tokio::sync::oneshot::channel::<()>();
+        let server = {
+            let tag_repo = tag_repo.clone();
+            tokio::spawn(async move {
+                let shutdown = async {
+                    let _ = stopped.await;
+                };
+                serve(listener, &config, tag_repo, shutdown).await
+            })
+        };
+
+        let mut master = Master {
+            stream: TcpStream::connect(address).await.unwrap(),
+            send: 0,
+            receive: 0,
+            hold: false,
+        };
+        master.send(Apdu::U(UFunction::StartDtAct)).await;
+        assert_eq!(master.frame().await, Apdu::U(UFunction::StartDtCon));
+        let init = master.asdu(false).await;
+        assert_eq!((init.type_id, init.cause), (M_EI_NA_1, COT_INITIALISED));
+
+        // General interrogation, without time tags
+        let interrogation = InformationObject::new(
+            0,
+            Element::Interrogation {
+                qualifier: QOI_STATION,
+            },
+        );
+        master.command(COT_ACTIVATION, interrogation).await;
+        let confirmation = master.asdu(false).await;
+        assert_eq!(
+            (confirmation.type_id, confirmation.cause),
+            (C_IC_NA_1, COT_ACTIVATION_CON)
+        );
+        assert!(!confirmation.negative);
+        let mut objects = Vec::new();
+        loop {
+            let asdu = master.asdu(false).await;
+            if asdu.cause == COT_ACTIVATION_TERM {
+                break;
+            }
+            assert_eq!(asdu.cause, COT_INTERROGATED);
+            objects.extend(asdu.objects);
+        }
+        let elements: Vec<_> = objects
+            .iter()
+            .map(|object| (object.address, object.element.clone(), object.time))
+            .collect();
+        assert_eq!(
+            elements,
+            vec![
+                (
+                    100,
+                    Element::Single {
+                        value: true,
+                        quality: 0,
+                    },
+                    None,
+                ),
+                (
+                    200,
+                    Element::Normalized {
+                        value: 16384,
+                        quality: 0,
+                    },
+                    None,
+                ),
+                (
+                    300,
+                    Element::Float {
+                        value: 12.5,
+                        quality: 0,
+                    },
+                    None,
+                ),
+                (
+                    400,
+                    Element::Scaled {
+                        value: 1000,
+                        quality: 0,
+                    },
+                    None,
+                ),
+                (
+                    500,
+                    Element::Float {
+                        value: 80.0,
+                        quality: 0,
+                    },
+                    None,
+                ),
+            ]
+        );
+
+        // Spontaneous transmission with a time tag
+        set(&tag_repo, "plant/flow", Value::Float(20.0)).await;
+        let spontaneous = master.asdu(true).await;
+        assert_eq!(
+            (spontaneous.type_id, spontaneous.cause),
+            (M_ME_TF_1, COT_SPONTANEOUS)
+        );
+        assert_eq!(spontaneous.objects[0].address, 300);
+        assert_eq!(
+            spontaneous.objects[0].element,
+            Element::Float {
+                value: 20.0,
+                quality: 0,
+            }
+        );
+        assert!(spontaneous.objects[0].time.is_some());
+
+        // Setpoints, scaled ones are rounded into integer tags
+        let setpoint = InformationObject::new(
+            301,
+            Element::FloatSetpoint {
+                value: 42.0,
+                qualifier: 0,
+            },
+        );
+        master.command(COT_ACTIVATION, setpoint).await;
+        let confirmation = master.asdu(false).await;
+        assert_eq!(
+            (confirmation.type_id, confirmation.cause),
+            (C_SE_NC_1, COT_ACTIVATION_CON)
+        );
+        assert!(!confirmation.negative);
+        assert_eq!(value(&tag_repo, "plant/flow").await, Value::Float(42.0));
+        let setpoint = InformationObject::new(
+            401,
+            Element::ScaledSetpoint {
+                value: 123,
+                qualifier: 0,
+            },
+        );
+        master.command(COT_ACTIVATION, setpoint).await;
+        assert!(!master.asdu(false).await.negative);
+        assert_eq!(value(&tag_repo, "plant/target").await, Value::Integer(12));
+        let setpoint = InformationObject::new(
+            999,
+            Element::FloatSetpoint {
+                value: 1.0,
+                qualifier: 0,
+            },
+        );
+        master.command(COT_ACTIVATION, setpoint).await;
+        let refusal = master.asdu(false).await;
+        assert_eq!(refusal.cause, COT_UNKNOWN_ADDRESS);
+        assert!(refusal.negative);
+        // Read-only tags refuse setpoints, and so do commands with several objects
+        let setpoint = InformationObject::new(
+            501,
+            Element::FloatSetpoint {
+                value: 90.0,
+                qualifier: 0,
+            },
+        );
+        master.command(COT_ACTIVATION, setpoint.clone()).await;
+        let refusal = master.asdu(false).await;
+        assert_eq!(refusal.cause, COT_ACTIVATION_CON);
+        assert!(refusal.negative);
+        assert_eq!(value(&tag_repo, "plant/limit").await, Value::Float(80.0));
+        let second = InformationObject::new(
+            301,
+            Element::FloatSetpoint {
+                value: 7.0,
+                qualifier: 0,
+            },
+        );
+        master
+            .commands(COT_ACTIVATION, vec![second, setpoint])
+            .await;
+        let refusal = master.asdu(false).await;
+        assert_eq!(refusal.cause, COT_ACTIVATION_CON);
+        assert!(refusal.negative);
+        assert_eq!(refusal.objects.len(), 2);
+        assert_eq!(value(&tag_repo, "plant/flow").await, Value::Float(42.0));
+
+        // Clock synchronisation moves the time tags
+        let master_time = Utc::now() + TimeDelta::hours(1);
+        let sync = InformationObject::new(
+            0,
+            Element::ClockSync {
+                time: master_time,
+            },
+        );
+        master.command(COT_ACTIVATION, sync).await;
+        let confirmation = master.asdu(false).await;
+        assert_eq!(
+            (confirmation.type_id, confirmation.cause),
+            (C_CS_NA_1, COT_ACTIVATION_CON)
+        );
+        set(&tag_repo, "plant/breaker", Value::Boolean(false)).await;
+        let spontaneous = master.asdu(true).await;
+        assert_eq!(spontaneous.type_id, M_SP_TB_1);
+        let time = spontaneous.objects[0].time.unwrap();
+        assert!(time > Utc::now() + TimeDelta::minutes(59), "{time}");
+
+        // No more than k unacknowledged I-frames
+        master.hold = true;
+        for level in 1..=4 {
+            set(&tag_repo, "plant/level", Value::Float(level as f32)).await;
+        }
+        for _ in 0..3 {
+            master.asdu(true).await;
+        }
+        let start = Instant::now();
+        assert!(
+            timeout(Duration::from_millis(300), Apdu::read(&mut master.stream))
+                .await
+                .is_err(),
+            "more than k frames after {:?}",
+            start.elapsed()
+        );
+        master.ack().await;
+        let last = master.asdu(true).await;
+        assert_eq!(
+            last.objects[0].element,
+            Element::Normalized {
+                value: 1311,
+                quality: 0,
+            }
+        );
+
+        stop.send(()).unwrap();
+        server.await.unwrap().unwrap();
+    }
+}
@@ -0,0 +1,14 @@
+pub mod access;
+pub mod actor;
+pub mod api;
+pub mod audit;
+pub mod auth;
+pub mod config;
+pub mod dnp3;
+pub mod driver;
+pub mod grpc;
+pub mod iec104;
+pub mod metrics;
+pub mod opcua;
+pub mod repository;
+pub mod tls;
@@ -1,52 +1,317 @@
-mod actor;
-mod api;
-mod repository;
+use std::{future::Future, pin::Pin, time::Duration};
 
 use actix_web::{App, HttpServer, web};
+use clap::Parser;
 use tracing_actix_web::TracingLogger;
+use tracing_subscriber::{EnvFilter, prelude::*, reload};
 
-use rcada_core::unit::Unit;
+use rcada_server::{
+    access::{ACCESS_FILE, AccessControl},
+    actor,
+    actor::{
+        Mailbox,
+        config::{ConfigActor, ConfigArguments, LogFilterReloader},
+        tag::TagRepositoryActor,
+        template::TemplateRepositoryActor,
+    },
+    api,
+    audit::AuditLog,
+    auth::{self, Authenticator},
+    config::{Args, ServerConfig, StorageBackend},
+    dnp3, grpc, iec104, opcua,
+    repository::{
+        tag::{
+            TagRepository,
+            file::{ALIASES_FILE, FileTagStorage},
+            inmemory::TagStorage,
+        },
+        template::{TEMPLATES_FILE, inmemory::TemplateStorage},
+    },
+    tls,
+};
 
-use crate::{actor::tag::TagRepositoryActor, repository::tag::inmemory::TagStorage};
+/// How long open connections may keep a protocol server from stopping.
+const SHUTDOWN_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Completes when a protocol server is asked to stop.
+type Shutdown = Pin<Box<dyn Future<Output = ()> + Send>>;
+
+/// gRPC, OPC UA, IEC 104 or DNP3 server running next to the HTTP API.
+struct ProtocolServer {
+    name: &'static str,
+    stop: tokio::sync::oneshot::Sender<()>,
+    task: tokio::task::JoinHandle<()>,
+}
+
+impl ProtocolServer {
+    fn spawn<F>(name: &'static str, serve: impl FnOnce(Shutdown) -> F) -> Self
+    where
+        F: Future<Output = std::io::Result<()>> + Send + 'static,
+    {
+        let (stop, stopped) = tokio::sync::oneshot::channel::<()>();
+        let serving = serve(Box::pin(async {
+            let _ = stopped.await;
+        }));
+        let task = tokio::spawn(async move {
+            if let Err(e) = serving.await {
+                tracing::error!("{} server failed: {}", name, e);
+            }
+        });
+        Self {
+            name,
+            stop,
+            task,
+        }
+    }
+
+    async fn stop(self) {
+        tracing::info!("Stopping {} server", self.name);
+        let _ = self.stop.send(());
+        if tokio::time::timeout(SHUTDOWN_TIMEOUT, self.task)
+            .await
+            .is_err()
+        {
+            tracing::warn!("{} connections still open, closing them", self.name);
+        }
+    }
+}
 
 #[actix_web::main]
 async fn main() -> std::io::Result<()> {
-    tracing_subscriber::fmt()
-        .with_max_level(tracing::Level::INFO)
+    let args = Args::parse();
+    if args.hash_password {
+        let mut password = String::new();
+        std::io::stdin().read_line(&mut password)?;
+        match auth::hash_password(password.trim_end_matches(['\r', '\n'])) {
+            Ok(hash) => println!("{hash}"),
+            Err(e) => {
+                eprintln!("error: {e}");
+                std::process::exit(2);
+            },
+        }
+        return Ok(());
+    }
+    let config = match ServerConfig::load(&args) {
+        Ok(config) => config,
+        Err(e) => {
+            eprintln!("error: {e}");
+            std::process::exit(2);
+        },
+    };
+    if args.check {
+        println!("configuration is valid");
+        return Ok(());
+    }
+
+    let (filter, filter_handle) = reload::Layer::new(EnvFilter::new(&config.log.filter));
+    tracing_subscriber::registry()
+        .with(filter)
+        .with(tracing_subscriber::fmt::layer())
         .init();
+    let set_log_filter: LogFilterReloader = Box::new(move |filter| {
+        let filter = EnvFilter::try_new(filter).map_err(|e| e.to_string())?;
+        filter_handle.reload(filter).map_err(|e| e.to_string())
+    });
 
     tracing::info!("Starting RCADA server");
 
-    let tag_storage = TagStorage::new();
+    std::fs::create_dir_all(&config.storage.data_dir)?;
+    match config.storage.backend {
+        StorageBackend::Memory => {
+            let storage = TagStorage::with_alias_file(config.storage.data_dir.join(ALIASES_FILE))?;
+            run(args, config, storage, set_log_filter).await
+        },
+        StorageBackend::File => {
+            let storage = FileTagStorage::open(&config.storage.data_dir)?;
+            run(args, config, storage, set_log_filter).await
+        },
+    }
+}
+
+async fn run<R>(
+    args: Args,
+    config: ServerConfig,
+    tag_storage: R,
+    set_log_filter: LogFilterReloader,
+) -> std::io::Result<()>
+where
+    R: TagRepository + Default + 'static,
+{
+    let audit = if config.audit.enabled {
+        AuditLog::open(
+            config.storage.data_dir.join(&config.audit.file),
+            config.audit.system_value_writes,
+        )?
+    } else {
+        AuditLog::disabled()
+    };
+
     let (tag_repo_ref, tag_repo_handle) = ractor::Actor::spawn(
         Some("tag_repository".into()),
         TagRepositoryActor::default(),
-        tag_storage,
+        (tag_storage, audit.clone()),
     )
     .await
     .expect("Failed to start tag-repository actor");
 
-    let (create_tag, mut result_rx) = crate::actor::tag::Message::create_tag(
-        "temp",
-        Unit::Celsius,
-        rcada_core::value::DataType::Float,
-    );
-    tag_repo_ref
-        .send_message(create_tag)
-        .expect("Cannot send message to actor");
-    if result_rx.recv().await.is_none() {
-        tracing::error!("Cannot create tag");
+    let templates = TemplateStorage::with_file(config.storage.data_dir.join(TEMPLATES_FILE))?;
+    let (template_repo_ref, template_repo_handle) = ractor::Actor::spawn(
+        Some("template_repository".into()),
+        TemplateRepositoryActor::default(),
+        (templates, tag_repo_ref.clone()),
+    )
+    .await
+    .expect("Failed to start template-repository actor");
+
+    let http = config.http.clone();
+    let grpc_config = config.grpc.clone();
+    let opcua_config = config.opcua.clone();
+    let iec104_config = config.iec104.clone();
+    let dnp3_config = config.dnp3.clone();
+    let data_dir = web::Data::new(api::health::DataDir(config.storage.data_dir.clone()));
+    let tls = http
+        .tls
+        .as_ref()
+        .map(tls::server_config)
+        .transpose()
+        .map_err(std::io::Error::other)?;
+    let authenticator =
+        web::Data::new(Authenticator::new(&config.auth).map_err(std::io::Error::other)?);
+    let access = web::Data::new(AccessControl::open(
+        config.storage.data_dir.join(ACCESS_FILE),
+    )?);
+    if !authenticator.is_enabled() {
+        tracing::warn!("Authentication is disabled, every request is allowed");
+    }
+    let grpc_server = if grpc_config.enabled {
+        let listener = tokio::net::TcpListener::bind(&grpc_config.bind).await?;
+        let tls = tls.as_ref().map(|(tls_config, _)| tls_config.clone());
+        let state = grpc::GrpcState {
+            tag_repo: tag_repo_ref.clone(),
+            access: access.clone().into_inner(),
+            authenticator: authenticator.clone().into_inner(),
+        };
+        Some(ProtocolServer::spawn("gRPC", |shutdown| {
+            grpc::serve(listener, tls, state, shutdown)
+        }))
+    } else {
+        None
+    };
+    let opcua_server = if opcua_config.enabled {
+        let listener = tokio::net::TcpListener::bind(&opcua_config.bind).await?;
+        let data_dir = config.storage.data_dir.clone();
+        let state = opcua::OpcUaState {
+            tag_repo: tag_repo_ref.clone(),
+            access: access.clone().into_inner(),
+            authenticator: authenticator.clone().into_inner(),
+        };
+        Some(ProtocolServer::spawn("OPC UA", |shutdown| async move {
+            opcua::serve(listener, &opcua_config, &data_dir, state, shutdown).await
+        }))
+    } else {
+        None
+    };
+    let iec104_server = if iec104_config.enabled {
+        let listener = tokio::net::TcpListener::bind(&iec104_config.bind).await?;
+        let tag_repo = tag_repo_ref.clone();
+        Some(ProtocolServer::spawn("IEC 104", |shutdown| async move {
+            iec104::serve(listener, &iec104_config, tag_repo, shutdown).await
+        }))
+    } else {
+        None
+    };
+    let dnp3_server = if dnp3_config.enabled {
+        let listener = tokio::net::TcpListener::bind(&dnp3_config.bind).await?;
+        let tag_repo = tag_repo_ref.clone();
+        Some(ProtocolServer::spawn("DNP3", |shutdown| async move {
+            dnp3::serve(listener, &dnp3_config, tag_repo, shutdown).await
+        }))
+    } else {
+        None
+    };
+    let (config_ref, config_handle) = ractor::Actor::spawn(
+        Some("config".into()),
+        ConfigActor,
+        ConfigArguments {
+            args,
+            config,
+            tag_repo: tag_repo_ref.clone(),
+            set_log_filter,
+            certificate: tls.as_ref().map(|(_, resolver)| resolver.clone()),
+        },
+    )
+    .await
+    .expect("Failed to start config actor");
+
+    #[cfg(unix)]
+    {
+        let config_ref = config_ref.clone();
+        tokio::spawn(async move {
+            use tokio::signal::unix::{SignalKind, signal};
+
+            let Ok(mut hangup) = signal(SignalKind::hangup()) else {
+                tracing::error!("Cannot listen for SIGHUP");
+                return;
+            };
+            while hangup.recv().await.is_some() {
+                tracing::info!("SIGHUP received, reloading configuration");
+                let (command, mut reply) = actor::config::Message::reload();
+                if config_ref.enqueue(command).is_err() {
+                    break;
+                }
+                match reply.recv().await {
+                    Some(Ok(report)) => tracing::info!("Configuration reloaded: {:?}", report),
+                    Some(Err(e)) => tracing::error!("Configuration reload failed: {:?}", e),
+                    None => tracing::error!("Configuration reload failed: config actor stopped"),
+                }
+            }
+        });
     }
 
     {
         let tag_repo = tag_repo_ref.clone();
-        let server = HttpServer::new(move || {
+        let template_repo = template_repo_ref.clone();
+        let config_actor = config_ref.clone();
+        let openapi = api::openapi::openapi();
+        let mut server = HttpServer::new(move || {
             App::new()
+                .wrap(actix_web::middleware::from_fn(
+                    api::metrics::middleware::record_duration,
+                ))
                 .wrap(TracingLogger::default())
+                .app_data(authenticator.clone())
+                .app_data(access.clone())
+                .app_data(web::Data::new(audit.clone()))
                 .app_data(web::Data::new(tag_repo.clone()))
+                .app_data(web::Data::new(template_repo.clone()))
+                .app_data(web::Data::new(config_actor.clone()))
+                .app_data(data_dir.clone())
+                .app_data(api::error::json_config())
+                .app_data(api::error::query_config())
+                .app_data(api::error::path_config())
+                .service(api::metrics::scope())
+                .service(api::openapi::services(openapi.clone()))
                 .service(api::scope())
+                .default_service(web::to(api::error::not_found))
         })
-        .bind("127.0.0.1:8080")?
+        .on_connect(|connection, data| {
+            if let Some(cert) = tls::client_certificate(connection) {
+                data.insert(cert);
+            }
+        });
+        if let Some(workers) = http.workers {
+            server = server.workers(workers);
+        }
+        let server = match tls {
+            Some((tls_config, _)) => {
+                tracing::info!("Listening on https://{}", http.bind);
+                server.bind_rustls_0_23(&http.bind, tls_config)?
+            },
+            None => {
+                tracing::info!("Listening on http://{}", http.bind);
+                server.bind(&http.bind)?
+            },
+        }
         .run();
 
         if let Err(e) = server.await {
@@ -54,6 +319,27 @@ async fn main() -> std::io::Result<()> {
         }
     }
 
+    for server in [grpc_server, opcua_server, iec104_server, dnp3_server]
+        .into_iter()
+        .flatten()
+    {
+        server.stop().await;
+    }
+
+    tracing::info!("Stopping config actor");
+    config_ref.stop(None);
+
+    if let Err(e) = config_handle.await {
+        tracing::error!("Config actor stopped with error {:?}", e);
+    }
+
+    tracing::info!("Stopping template repository actor");
+    template_repo_ref.stop(None);
+
+    if let Err(e) = template_repo_handle.await {
+        tracing::error!("Template repository actor stopped with error {:?}", e);
+    }
+
     tracing::info!("Stopping tag repository actor");
     tag_repo_ref.stop(None);
 
@@ -0,0 +1,141 @@
+use std::sync::LazyLock;
+
+use prometheus::{
+    Encoder, HistogramOpts, HistogramVec, IntCounter, IntCounterVec, IntGauge, IntGaugeVec, Opts,
+    Registry, TextEncoder,
+};
+
+static METRICS: LazyLock<Metrics> = LazyLock::new(Metrics::new);
+
+/// Process-wide metrics, served in the Prometheus text format on `/metrics`.
+pub fn metrics() -> &'static Metrics {
+    &METRICS
+}
+
+pub struct Metrics {
+    registry: Registry,
+    pub tags: IntGauge,
+    pub tag_updates: IntCounter,
+    /// Receivers of value updates, as of the last message of the tag actor.
+    pub value_subscribers: IntGauge,
+    /// By error kind, see [`crate::repository::tag::UpdateValueError::kind`].
+    pub rejected_updates: IntCounterVec,
+    /// Messages sent to an actor and not handled yet, by actor.
+    pub mailbox_depth: IntGaugeVec,
+    /// By actor and message.
+    pub message_duration: HistogramVec,
+    /// By method, route pattern and status.
+    pub http_duration: HistogramVec,
+    /// By driver and result (`ok`, `error` or `timeout`).
+    pub driver_polls: IntCounterVec,
+    /// Messages sent by publishing drivers, by driver.
+    pub driver_published: IntCounterVec,
+    /// Messages a publishing driver keeps until it reaches its broker, by driver.
+    pub driver_buffered: IntGaugeVec,
+    /// Messages received by subscribing drivers, by driver and result
+    /// (`accepted` or `rejected`).
+    pub driver_received: IntCounterVec,
+}
+
+impl Metrics {
+    fn new() -> Self {
+        let registry =
+            Registry::new_custom(Some("rcada".to_string()), None).expect("valid metrics prefix");
+        let metrics = Self {
+            tags: IntGauge::new("tags", "Number of tags").expect("valid metric"),
+            tag_updates: IntCounter::new("tag_updates_total", "Accepted tag value updates")
+                .expect("valid metric"),
+            value_subscribers: IntGauge::new(
+                "tag_value_subscribers",
+                "Subscribers to tag value updates",
+            )
+            .expect("valid metric"),
+            rejected_updates: IntCounterVec::new(
+                Opts::new("tag_updates_rejected_total", "Rejected tag value updates"),
+                &["kind"],
+            )
+            .expect("valid metric"),
+            mailbox_depth: IntGaugeVec::new(
+                Opts::new("actor_mailbox_depth", "Messages waiting for an actor"),
+                &["actor"],
+            )
+            .expect("valid metric"),
+            message_duration: HistogramVec::new(
+                HistogramOpts::new(
+                    "actor_message_duration_seconds",
+                    "Time an actor took to handle a message",
+                ),
+                &["actor", "message"],
+            )
+            .expect("valid metric"),
+            http_duration: HistogramVec::new(
+                HistogramOpts::new(
+                    "http_request_duration_seconds",
+                    "Time taken to answer HTTP requests",
+                ),
+                &["method", "route", "status"],
+            )
+            .expect("valid metric"),
+            driver_polls: IntCounterVec::new(
+                Opts::new("driver_polls_total", "Poll cycles of drivers"),
+                &["driver", "result"],
+            )
+            .expect("valid metric"),
+            driver_published: IntCounterVec::new(
+                Opts::new(
+                    "driver_published_messages_total",
+                    "Messages published by drivers",
+                ),
+                &["driver"],
+            )
+            .expect("valid metric"),
+            driver_buffered: IntGaugeVec::new(
+                Opts::new(
+                    "driver_buffered_messages",
+                    "Messages buffered by drivers while their broker is unreachable",
+                ),
+                &["driver"],
+            )
+            .expect("valid metric"),
+            driver_received: IntCounterVec::new(
+                Opts::new(
+                    "driver_received_messages_total",
+                    "Messages received by drivers",
+                ),
+                &["driver", "result"],
+            )
+            .expect("valid metric"),
+            registry,
+        };
+        metrics.register();
+        metrics
+    }
+
+    fn register(&self) {
+        let collectors: [Box<dyn prometheus::core::Collector>; 11] = [
+            Box::new(self.tags.clone()),
+            Box::new(self.tag_updates.clone()),
+            Box::new(self.value_subscribers.clone()),
+            Box::new(self.rejected_updates.clone()),
+            Box::new(self.mailbox_depth.clone()),
+            Box::new(self.message_duration.clone()),
+            Box::new(self.http_duration.clone()),
+            Box::new(self.driver_polls.clone()),
+            Box::new(self.driver_published.clone()),
+            Box::new(self.driver_buffered.clone()),
+            Box::new(self.driver_received.clone()),
+        ];
+        for collector in collectors {
+            self.registry
+                .register(collector)
+                .expect("metrics are registered once");
+        }
+    }
+
+    /// All metrics in the Prometheus text format.
+    pub fn encode(&self) -> Result<String, prometheus::Error> {
+        let mut buffer = Vec::new();
+        TextEncoder::new().encode(&self.registry.gather(), &mut buffer)?;
+        String::from_utf8(buffer).map_err(|e| prometheus::Error::Msg(e.to_string()))
+    }
+}
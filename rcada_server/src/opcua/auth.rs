@@ -0,0 +1,150 @@
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex, OnceLock},
+};
+
+use async_trait::async_trait;
+use opcua::{
+    nodes::AccessLevel,
+    server::{
+        ServerEndpoint,
+        authenticator::{
+            AuthManager, Password, UserToken, user_pass_security_policy_id,
+            user_pass_security_policy_uri,
+        },
+    },
+    types::{Error, Identifier, NodeId, StatusCode, UAString, UserTokenPolicy, UserTokenType},
+};
+
+use crate::{
+    access::{AccessControl, Permission},
+    auth::{Authenticator, Identity},
+};
+
+use super::nodes;
+
+/// Checks the user name and password of sessions like the HTTP middleware
+/// checks credentials, and hides what the identity may not read or write.
+pub struct OpcUaAuthenticator {
+    authenticator: Arc<Authenticator>,
+    access: Arc<AccessControl>,
+    /// Identity of the sessions by user token, the token is the kind of
+    /// credentials and the name, so there's one per configured user or key.
+    identities: Mutex<HashMap<String, Identity>>,
+    namespace: OnceLock<u16>,
+}
+
+impl OpcUaAuthenticator {
+    pub fn new(authenticator: Arc<Authenticator>, access: Arc<AccessControl>) -> Self {
+        Self {
+            authenticator,
+            access,
+            identities: Mutex::new(HashMap::new()),
+            namespace: OnceLock::new(),
+        }
+    }
+
+    /// Sets the index of the tag namespace, known once the server is built.
+    pub fn set_namespace(&self, namespace: u16) {
+        let _ = self.namespace.set(namespace);
+    }
+
+    /// Identity of the session with `token`.
+    pub fn identity(&self, token: &UserToken) -> Identity {
+        if token.is_anonymous() {
+            return Identity::anonymous();
+        }
+        self.identities
+            .lock()
+            .unwrap()
+            .get(&token.0)
+            .cloned()
+            // Only tokens handed out by the authenticator reach here
+            .unwrap_or_else(Identity::anonymous)
+    }
+
+    /// Name of the tag whose value or property `node_id` is, `None` for
+    /// folders and nodes of other namespaces.
+    fn tag_of<'a>(&self, node_id: &'a NodeId) -> Option<&'a str> {
+        if Some(&node_id.namespace) != self.namespace.get() {
+            return None;
+        }
+        match &node_id.identifier {
+            Identifier::String(id) => nodes::tag_name(id.as_ref()),
+            _ => None,
+        }
+    }
+}
+
+#[async_trait]
+impl AuthManager for OpcUaAuthenticator {
+    async fn authenticate_anonymous_token(&self, _endpoint: &ServerEndpoint) -> Result<(), Error> {
+        if self.authenticator.is_enabled() {
+            return Err(Error::new(
+                StatusCode::BadIdentityTokenRejected,
+                "anonymous sessions aren't allowed",
+            ));
+        }
+        Ok(())
+    }
+
+    async fn authenticate_username_identity_token(
+        &self,
+        _endpoint: &ServerEndpoint,
+        username: &str,
+        password: &Password,
+    ) -> Result<UserToken, Error> {
+        let identity = self
+            .authenticator
+            .authenticate_password(username, password.get())
+            .map_err(|e| {
+                tracing::warn!("unauthorized OPC UA session for {}: {}", username, e);
+                Error::new(StatusCode::BadUserAccessDenied, e.to_string())
+            })?;
+        let token = format!("{:?}:{}", identity.method, identity.name);
+        self.identities
+            .lock()
+            .unwrap()
+            .insert(token.clone(), identity);
+        Ok(UserToken(token))
+    }
+
+    fn effective_user_access_level(
+        &self,
+        token: &UserToken,
+        user_access_level: AccessLevel,
+        node_id: &NodeId,
+    ) -> AccessLevel {
+        let Some(tag) = self.tag_of(node_id) else {
+            return user_access_level;
+        };
+        let identity = self.identity(token);
+        let mut access_level = user_access_level;
+        if !self
+            .access
+            .is_allowed(&identity, Permission::Read, Some(tag))
+        {
+            access_level.remove(AccessLevel::CURRENT_READ);
+        }
+        if !self
+            .access
+            .is_allowed(&identity, Permission::Write, Some(tag))
+        {
+            access_level.remove(AccessLevel::CURRENT_WRITE);
+        }
+        access_level
+    }
+
+    fn user_token_policies(&self, endpoint: &ServerEndpoint) -> Vec<UserTokenPolicy> {
+        if !self.authenticator.is_enabled() {
+            return vec![UserTokenPolicy::anonymous()];
+        }
+        vec![UserTokenPolicy {
+            policy_id: user_pass_security_policy_id(endpoint),
+            token_type: UserTokenType::UserName,
+            issued_token_type: UAString::null(),
+            issuer_endpoint_url: UAString::null(),
+            security_policy_uri: user_pass_security_policy_uri(endpoint),
+        }]
+    }
+}
@@ -0,0 +1,413 @@
+//! OPC UA server exposing the tags to HMIs and MES systems, next to the REST
+//! and gRPC APIs.
+
+mod auth;
+mod nodes;
+
+use std::{future::Future, io, net::SocketAddr, path::Path, sync::Arc, time::Duration};
+
+use opcua::{
+    crypto::SecurityPolicy,
+    server::{ANONYMOUS_USER_TOKEN_ID, Server, ServerBuilder, ServerEndpoint, ServerHandle},
+    types::MessageSecurityMode,
+};
+use ractor::ActorRef;
+use serde::{Deserialize, Serialize};
+use tokio::net::TcpListener;
+
+use crate::{
+    access::AccessControl,
+    actor::{self, Mailbox},
+    auth::Authenticator,
+};
+
+pub use self::nodes::{NAMESPACE_URI, tag_value};
+use self::{
+    auth::OpcUaAuthenticator,
+    nodes::{TagNodeManager, TagNodes},
+};
+
+/// Directory below the data directory with the certificate of the server
+/// and the trusted and rejected client certificates.
+pub const PKI_DIR: &str = "opcua/pki";
+
+/// How often the address space is compared with the tag repository, for
+/// metadata changes, new and deleted tags, which aren't broadcast.
+const RESYNC_INTERVAL: Duration = Duration::from_secs(10);
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct OpcUaConfig {
+    /// Serve the tags over OPC UA besides the REST API.
+    #[serde(default)]
+    pub enabled: bool,
+    #[serde(default = "default_bind")]
+    pub bind: String,
+    /// Security modes offered by the endpoint, the signing ones use
+    /// Aes128_Sha256_RsaOaep and a self-signed certificate in `opcua/pki`.
+    #[serde(default = "default_security")]
+    pub security: Vec<SecurityMode>,
+    /// Accept client certificates that aren't in `opcua/pki/trusted` yet,
+    /// otherwise they are stored in `opcua/pki/rejected` to be moved there.
+    #[serde(default)]
+    pub trust_client_certs: bool,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SecurityMode {
+    None,
+    Sign,
+    SignAndEncrypt,
+}
+
+impl SecurityMode {
+    /// Security policy and message security mode of the endpoints with this
+    /// mode.
+    pub fn policy(self) -> (SecurityPolicy, MessageSecurityMode) {
+        match self {
+            SecurityMode::None => (SecurityPolicy::None, MessageSecurityMode::None),
+            SecurityMode::Sign => (
+                SecurityPolicy::Aes128Sha256RsaOaep,
+                MessageSecurityMode::Sign,
+            ),
+            SecurityMode::SignAndEncrypt => (
+                SecurityPolicy::Aes128Sha256RsaOaep,
+                MessageSecurityMode::SignAndEncrypt,
+            ),
+        }
+    }
+}
+
+fn default_bind() -> String {
+    "127.0.0.1:4840".to_string()
+}
+
+fn default_security() -> Vec<SecurityMode> {
+    vec![SecurityMode::None]
+}
+
+impl Default for OpcUaConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            bind: default_bind(),
+            security: default_security(),
+            trust_client_certs: false,
+        }
+    }
+}
+
+/// What the OPC UA server shares with the HTTP server.
+#[derive(Clone)]
+pub struct OpcUaState {
+    pub tag_repo: ActorRef<actor::tag::Message>,
+    pub access: Arc<AccessControl>,
+    pub authenticator: Arc<Authenticator>,
+}
+
+/// Serves the tags over OPC UA on `listener` until `shutdown` completes.
+/// Every tag is a variable in a folder hierarchy built from its name, kept
+/// up to date with the tag repository.
+pub async fn serve(
+    listener: TcpListener,
+    config: &OpcUaConfig,
+    data_dir: &Path,
+    state: OpcUaState,
+    shutdown: impl Future<Output = ()> + Send + 'static,
+) -> io::Result<()> {
+    let address = listener.local_addr()?;
+    // Before the nodes are built, so that no update is missed
+    let (command, mut reply) = actor::tag::Message::subscribe();
+    state.tag_repo.enqueue(command).map_err(io::Error::other)?;
+    let updates = reply
+        .recv()
+        .await
+        .ok_or_else(|| io::Error::other("tag repository didn't answer the subscription"))?;
+    let authenticator = Arc::new(OpcUaAuthenticator::new(
+        state.authenticator.clone(),
+        state.access.clone(),
+    ));
+
+    let (server, handle) = build_server(address, config, data_dir, &state, authenticator.clone())
+        .map_err(io::Error::other)?;
+
+    let namespace = handle
+        .get_namespace_index(NAMESPACE_URI)
+        .ok_or_else(|| io::Error::other("tag namespace wasn't registered"))?;
+    authenticator.set_namespace(namespace);
+    let manager = handle
+        .node_managers()
+        .get_of_type::<TagNodeManager>()
+        .ok_or_else(|| io::Error::other("tag node manager wasn't registered"))?;
+    let sync = tokio::spawn(
+        TagNodes::new(manager, handle.subscriptions().clone(), namespace).run(
+            state.tag_repo,
+            updates,
+            RESYNC_INTERVAL,
+        ),
+    );
+
+    tokio::spawn(async move {
+        shutdown.await;
+        handle.cancel();
+    });
+    tracing::info!("Listening for OPC UA on opc.tcp://{}", address);
+    let served = server.run_with(listener).await;
+    sync.abort();
+    served.map_err(io::Error::other)
+}
+
+fn build_server(
+    address: SocketAddr,
+    config: &OpcUaConfig,
+    data_dir: &Path,
+    state: &OpcUaState,
+    authenticator: Arc<OpcUaAuthenticator>,
+) -> Result<(Server, ServerHandle), String> {
+    let mut builder = ServerBuilder::new()
+        .application_name("rcada")
+        .application_uri("urn:rcada:server")
+        .product_uri("urn:rcada")
+        .host(address.ip().to_string())
+        .port(address.port())
+        .pki_dir(data_dir.join(PKI_DIR))
+        // Without signing endpoints the certificate isn't used
+        .create_sample_keypair(
+            config
+                .security
+                .iter()
+                .any(|mode| *mode != SecurityMode::None),
+        )
+        .trust_client_certs(config.trust_client_certs)
+        .discovery_urls(vec!["/".to_string()])
+        .with_authenticator(authenticator.clone())
+        .with_node_manager(nodes::node_manager(
+            state.tag_repo.clone(),
+            state.access.clone(),
+            authenticator,
+        ));
+    // The authenticator decides which user tokens an endpoint accepts
+    let user_tokens = [ANONYMOUS_USER_TOKEN_ID.to_string()];
+    for mode in &config.security {
+        let (id, endpoint) = match mode {
+            SecurityMode::None => ("none", ServerEndpoint::new_none("/", &user_tokens)),
+            SecurityMode::Sign => (
+                "sign",
+                ServerEndpoint::new_aes128_sha256_rsaoaep_sign("/", &user_tokens),
+            ),
+            SecurityMode::SignAndEncrypt => (
+                "sign_and_encrypt",
+                ServerEndpoint::new_aes128_sha256_rsaoaep_sign_encrypt("/", &user_tokens),
+            ),
+        };
+        builder = builder.add_endpoint(id, endpoint);
+    }
+    builder.build()
+}
+
+#[cfg(test)]
+mod tests {
+    use opcua::{
+        client::{ClientBuilder, DataChangeCallback, IdentityToken},
+        types::{
+            AttributeId, DataValue, EUInformation, MessageSecurityMode, NodeId, ReadValueId,
+            StatusCode, TimestampsToReturn, UserTokenPolicy, Variant, WriteValue,
+        },
+    };
+    use ractor::Actor;
+    use rcada_core::{
+        tag::{EngineeringRange, Quality, TagMeta, TagValue},
+        unit::Unit,
+        value::{DataType, Value},
+    };
+    use tokio::sync::mpsc;
+
+    use super::*;
+    use crate::{
+        access::AccessModel,
+        actor::tag::TagRepositoryActor,
+        audit::AuditLog,
+        auth::{ApiKeyConfig, AuthConfig},
+        repository::tag::inmemory::TagStorage,
+    };
+
+    #[tokio::test]
+    async fn serves_tags_as_variables() {
+        let data_dir = std::env::temp_dir().join(format!("rcada-opcua-{}", uuid::Uuid::new_v4()));
+        let (tag_repo, _) = Actor::spawn(
+            None,
+            TagRepositoryActor::default(),
+            (TagStorage::default(), AuditLog::disabled()),
+        )
+        .await
+        .unwrap();
+        let mut meta = TagMeta::new(Unit::Percent, DataType::Float);
+        meta.range = Some(EngineeringRange {
+            low: 0.0,
+            high: 100.0,
+        });
+        let (command, mut reply) = actor::tag::Message::create_tag("plant1/pump07.speed", meta);
+        tag_repo.enqueue(command).unwrap();
+        reply.recv().await.unwrap();
+
+        let authenticator = Authenticator::new(&AuthConfig {
+            enabled: true,
+            api_keys: vec![ApiKeyConfig {
+                name: "hmi".to_string(),
+                key: "hmi-secret".to_string(),
+                roles: vec!["operator".to_string()],
+            }],
+            ..AuthConfig::default()
+        })
+        .unwrap();
+        let state = OpcUaState {
+            tag_repo: tag_repo.clone(),
+            access: Arc::new(AccessControl::new(AccessModel::default())),
+            authenticator: Arc::new(authenticator),
+        };
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap();
+        let (stop, stopped) = tokio::sync::oneshot::channel::<()>();
+        let server_dir = data_dir.clone();
+        let server = tokio::spawn(async move {
+            let shutdown = async {
+                let _ = stopped.await;
+            };
+            serve(
+                listener,
+                &OpcUaConfig::default(),
+                &server_dir,
+                state,
+                shutdown,
+            )
+            .await
+        });
+
+        let mut client = ClientBuilder::new()
+            .application_name("rcada test")
+            .application_uri("urn:rcada:test")
+            .pki_dir(data_dir.join("client"))
+            .create_sample_keypair(false)
+            .trust_server_certs(true)
+            .session_retry_limit(3)
+            .client()
+            .unwrap();
+        let url = format!("opc.tcp://{address}/");
+        let endpoint = (
+            url.as_str(),
+            "None",
+            MessageSecurityMode::None,
+            UserTokenPolicy::anonymous(),
+        );
+        let (session, event_loop) = client
+            .connect_to_matching_endpoint(
+                endpoint,
+                IdentityToken::new_user_name("hmi", "hmi-secret"),
+            )
+            .await
+            .unwrap();
+        let event_loop = event_loop.spawn();
+        assert!(session.wait_for_connection().await);
+        let namespace = session
+            .read_namespace_array()
+            .await
+            .unwrap()
+            .get_index(nodes::NAMESPACE_URI)
+            .unwrap();
+        let node = |id: &str| NodeId::new(namespace, id);
+        let read = |id: &str| ReadValueId {
+            node_id: node(id),
+            attribute_id: AttributeId::Value as u32,
+            ..Default::default()
+        };
+
+        // Created and not written yet
+        let values = session
+            .read(
+                &[
+                    read("plant1/pump07.speed"),
+                    read("plant1/pump07.speed#EngineeringUnits"),
+                    read("plant1/pump07.speed#EURange"),
+                ],
+                TimestampsToReturn::Both,
+                0.0,
+            )
+            .await
+            .unwrap();
+        assert_eq!(values[0].status, Some(StatusCode::BadWaitingForInitialData));
+        let Some(Variant::ExtensionObject(unit)) = &values[1].value else {
+            panic!("no engineering units: {:?}", values[1]);
+        };
+        let unit = unit.inner_as::<EUInformation>().unwrap();
+        assert_eq!(unit.display_name.text.as_ref(), "%");
+        assert!(matches!(values[2].value, Some(Variant::ExtensionObject(_))));
+
+        // Changes of the tag reach subscriptions
+        let (changes, mut changed) = mpsc::unbounded_channel();
+        let subscription = session
+            .create_subscription(
+                Duration::from_millis(100),
+                100,
+                10,
+                0,
+                0,
+                true,
+                DataChangeCallback::new(move |value: DataValue, _| {
+                    let _ = changes.send(value);
+                }),
+            )
+            .await
+            .unwrap();
+        session
+            .create_monitored_items(
+                subscription,
+                TimestampsToReturn::Both,
+                vec![node("plant1/pump07.speed").into()],
+            )
+            .await
+            .unwrap();
+        let (command, mut reply) = actor::tag::Message::update_tag_value(
+            "plant1/pump07.speed",
+            TagValue {
+                value: Value::Float(42.5),
+                timestamp: Some(chrono::Utc::now()),
+                quality: Quality::Good,
+            },
+        );
+        tag_repo.enqueue(command).unwrap();
+        reply.recv().await.unwrap().unwrap();
+        let value = loop {
+            let value = tokio::time::timeout(Duration::from_secs(5), changed.recv())
+                .await
+                .unwrap()
+                .unwrap();
+            if value.status().is_good() {
+                break value;
+            }
+        };
+        assert_eq!(value.value, Some(Variant::Float(42.5)));
+
+        // Writes go through the tag repository, doubles are converted
+        let results = session
+            .write(&[WriteValue {
+                node_id: node("plant1/pump07.speed"),
+                attribute_id: AttributeId::Value as u32,
+                value: DataValue::value_only(Variant::Double(55.0)),
+                ..Default::default()
+            }])
+            .await
+            .unwrap();
+        assert_eq!(results, vec![StatusCode::Good]);
+        let (command, mut reply) = actor::tag::Message::get_tag("plant1/pump07.speed");
+        tag_repo.enqueue(command).unwrap();
+        let tag = reply.recv().await.unwrap().unwrap();
+        assert_eq!(tag.value.value, Value::Float(55.0));
+
+        let _ = session.disconnect().await;
+        event_loop.abort();
+        let _ = stop.send(());
+        server.await.unwrap().unwrap();
+        let _ = std::fs::remove_dir_all(&data_dir);
+    }
+}
@@ -0,0 +1,550 @@
+use std::{
+    collections::{HashMap, HashSet},
+    sync::Arc,
+    time::Duration,
+};
+
+use async_trait::async_trait;
+use opcua::{
+    nodes::{AccessLevel, DefaultTypeTree, NodeType, VariableBuilder},
+    server::{
+        SubscriptionCache,
+        address_space::AddressSpace,
+        diagnostics::NamespaceMetadata,
+        node_manager::{
+            NodeManagerBuilder, ParsedWriteValue, RequestContext, ServerContext, WriteNode,
+            memory::{
+                InMemoryNodeManager, InMemoryNodeManagerBuilder, InMemoryNodeManagerImpl,
+                InMemoryNodeManagerImplBuilder,
+            },
+        },
+    },
+    sync::RwLock,
+    types::{
+        AttributeId, DataTypeId, DataValue, DateTime, EUInformation, Identifier, LocalizedText,
+        NodeId, NumericRange, ObjectId, QualifiedName, Range, StatusCode, VariableTypeId, Variant,
+    },
+};
+use ractor::ActorRef;
+use rcada_core::{
+    tag::{Quality, Tag, TagMeta, TagName, TagValue},
+    unit::Unit,
+    value::{DataType, Value},
+};
+use tokio::sync::broadcast::{self, error::RecvError};
+use uuid::Uuid;
+
+use crate::{
+    access::AccessControl,
+    actor::{self, Mailbox},
+    api::{error::ErrorCode, tags::service::TagService},
+};
+
+use super::auth::OpcUaAuthenticator;
+
+pub const NAMESPACE_URI: &str = "urn:rcada:tags";
+
+/// Numeric id of the folder with all tags, the other nodes have string ids.
+const ROOT_ID: u32 = 1;
+
+/// Characters splitting tag names into folders.
+const SEPARATORS: [char; 2] = ['/', '.'];
+
+const ENGINEERING_UNITS: &str = "EngineeringUnits";
+const EU_RANGE: &str = "EURange";
+
+/// Namespace of the UNECE unit codes in [`EUInformation`].
+const UNECE_URI: &str = "http://www.opcfoundation.org/UA/units/un/cefact";
+
+pub type TagNodeManager = InMemoryNodeManager<TagNodeManagerImpl>;
+
+/// Builds the node manager of the tag namespace.
+pub fn node_manager(
+    tag_repo: ActorRef<actor::tag::Message>,
+    access: Arc<AccessControl>,
+    authenticator: Arc<OpcUaAuthenticator>,
+) -> impl NodeManagerBuilder {
+    InMemoryNodeManagerBuilder::new(TagNodeManagerBuilder {
+        tag_repo,
+        access,
+        authenticator,
+    })
+}
+
+struct TagNodeManagerBuilder {
+    tag_repo: ActorRef<actor::tag::Message>,
+    access: Arc<AccessControl>,
+    authenticator: Arc<OpcUaAuthenticator>,
+}
+
+impl InMemoryNodeManagerImplBuilder for TagNodeManagerBuilder {
+    type Impl = TagNodeManagerImpl;
+
+    fn build(self, context: ServerContext, address_space: &mut AddressSpace) -> Self::Impl {
+        let namespace_index = context
+            .type_tree
+            .write()
+            .namespaces_mut()
+            .add_namespace(NAMESPACE_URI);
+        address_space.add_namespace(NAMESPACE_URI, namespace_index);
+        // Here rather than in `init`, which runs once the server is started
+        // and tags may have been added already
+        address_space.add_folder(
+            &NodeId::new(namespace_index, ROOT_ID),
+            QualifiedName::new(namespace_index, "Tags"),
+            "Tags",
+            &ObjectId::ObjectsFolder.into(),
+        );
+        TagNodeManagerImpl {
+            namespace: NamespaceMetadata {
+                namespace_uri: NAMESPACE_URI.to_string(),
+                namespace_index,
+                ..Default::default()
+            },
+            tag_repo: self.tag_repo,
+            access: self.access,
+            authenticator: self.authenticator,
+        }
+    }
+}
+
+/// Serves the tag nodes, which [`TagNodes`] keeps up to date, and routes
+/// writes through the tag repository.
+pub struct TagNodeManagerImpl {
+    namespace: NamespaceMetadata,
+    tag_repo: ActorRef<actor::tag::Message>,
+    access: Arc<AccessControl>,
+    authenticator: Arc<OpcUaAuthenticator>,
+}
+
+#[async_trait]
+impl InMemoryNodeManagerImpl for TagNodeManagerImpl {
+    async fn init(&self, _address_space: &mut AddressSpace, _context: ServerContext) {}
+
+    fn name(&self) -> &str {
+        "rcada-tags"
+    }
+
+    fn namespaces(&self) -> Vec<NamespaceMetadata> {
+        vec![self.namespace.clone()]
+    }
+
+    /// Writes values like the REST API, the variable is updated once the
+    /// tag repository broadcasts the new value.
+    async fn write(
+        &self,
+        context: &RequestContext,
+        address_space: &RwLock<AddressSpace>,
+        nodes_to_write: &mut [&mut WriteNode],
+    ) -> Result<(), StatusCode> {
+        let mut writes = Vec::new();
+        {
+            let mut address_space = address_space.write();
+            let type_tree = context.type_tree.read();
+            for (index, write) in nodes_to_write.iter_mut().enumerate() {
+                match parse_write(context, &mut address_space, &type_tree, write.value()) {
+                    Ok((name, value)) => writes.push((index, name, value)),
+                    Err(status) => write.set_status(status),
+                }
+            }
+        }
+        if writes.is_empty() {
+            return Ok(());
+        }
+
+        let identity = self.authenticator.identity(&context.token);
+        let service = TagService::new(
+            &self.tag_repo,
+            &self.access,
+            &identity,
+            None,
+            Uuid::new_v4(),
+        );
+        let request_id = service.request_id();
+        for (index, name, value) in writes {
+            tracing::info!(%request_id, "OPC UA write: {}", name);
+            let status = match service.update_tag_value(&name, value).await {
+                Ok(_) => StatusCode::Good,
+                Err(e) => write_status(e.code),
+            };
+            nodes_to_write[index].set_status(status);
+        }
+        Ok(())
+    }
+}
+
+/// Tag and value of a write to the value of a tag variable.
+fn parse_write(
+    context: &RequestContext,
+    address_space: &mut AddressSpace,
+    type_tree: &DefaultTypeTree,
+    write: &ParsedWriteValue,
+) -> Result<(TagName, TagValue), StatusCode> {
+    let node = address_space.validate_node_write(context, write, type_tree)?;
+    let NodeType::Variable(variable) = node else {
+        return Err(StatusCode::BadNotWritable);
+    };
+    if write.attribute_id != AttributeId::Value {
+        return Err(StatusCode::BadNotWritable);
+    }
+    if write.index_range != NumericRange::None {
+        return Err(StatusCode::BadWriteNotSupported);
+    }
+    let name = match &write.node_id.identifier {
+        Identifier::String(id) if tag_name(id.as_ref()) == Some(id.as_ref()) => id.as_ref(),
+        _ => return Err(StatusCode::BadNotWritable),
+    };
+    let data_type = match variable.data_type() {
+        id if id == DataTypeId::Int64 => DataType::Integer,
+        id if id == DataTypeId::Float => DataType::Float,
+        id if id == DataTypeId::Boolean => DataType::Boolean,
+        id if id == DataTypeId::String => DataType::String,
+        _ => return Err(StatusCode::BadTypeMismatch),
+    };
+    let value = write
+        .value
+        .value
+        .as_ref()
+        .and_then(tag_value)
+        .and_then(|value| value.convert(data_type))
+        .ok_or(StatusCode::BadTypeMismatch)?;
+    let quality = match write.value.status {
+        Some(status) if status.is_bad() => Quality::Bad,
+        Some(status) if status.is_uncertain() => Quality::Uncertain,
+        _ => Quality::Good,
+    };
+    let timestamp = write
+        .value
+        .source_timestamp
+        .unwrap_or_else(DateTime::now)
+        .as_chrono();
+    Ok((
+        name.into(),
+        TagValue {
+            value,
+            timestamp: Some(timestamp),
+            quality,
+        },
+    ))
+}
+
+/// Status of a write the tag repository or the access control refused.
+fn write_status(code: ErrorCode) -> StatusCode {
+    match code {
+        ErrorCode::Unauthorized | ErrorCode::PermissionDenied => StatusCode::BadUserAccessDenied,
+        ErrorCode::ReadOnly => StatusCode::BadNotWritable,
+        ErrorCode::NotFound | ErrorCode::TagNotFound => StatusCode::BadNodeIdUnknown,
+        ErrorCode::TimestampRequired | ErrorCode::TimestampOutOfOrder => {
+            StatusCode::BadInvalidTimestamp
+        },
+        ErrorCode::InvalidDataType | ErrorCode::IncompatibleDataType => StatusCode::BadTypeMismatch,
+        _ => StatusCode::BadInternalError,
+    }
+}
+
+/// Name of the tag whose variable or property has the string id `id`,
+/// `None` for folders.
+pub fn tag_name(id: &str) -> Option<&str> {
+    if id.ends_with(SEPARATORS) {
+        return None;
+    }
+    let name = [ENGINEERING_UNITS, EU_RANGE]
+        .iter()
+        .find_map(|property| {
+            id.strip_suffix(property)
+                .and_then(|rest| rest.strip_suffix('#'))
+        })
+        .unwrap_or(id);
+    Some(name)
+}
+
+fn variant(value: &Value) -> Variant {
+    match value {
+        Value::Integer(v) => Variant::Int64(*v),
+        Value::Float(v) => Variant::Float(*v),
+        Value::Boolean(v) => Variant::Boolean(*v),
+        Value::String(v) => Variant::String(v.as_str().into()),
+    }
+}
+
+/// Value of a written or read variant, converted to the data type of the
+/// tag by the caller, so that any numeric type fits a numeric tag.
+pub fn tag_value(variant: &Variant) -> Option<Value> {
+    Some(match variant {
+        Variant::Boolean(v) => Value::Boolean(*v),
+        Variant::SByte(v) => Value::Integer((*v).into()),
+        Variant::Byte(v) => Value::Integer((*v).into()),
+        Variant::Int16(v) => Value::Integer((*v).into()),
+        Variant::UInt16(v) => Value::Integer((*v).into()),
+        Variant::Int32(v) => Value::Integer((*v).into()),
+        Variant::UInt32(v) => Value::Integer((*v).into()),
+        Variant::Int64(v) => Value::Integer(*v),
+        Variant::UInt64(v) => Value::Integer(i64::try_from(*v).ok()?),
+        Variant::Float(v) => Value::Float(*v),
+        Variant::Double(v) => Value::Float(*v as f32),
+        Variant::String(v) => Value::String(v.as_ref().to_string()),
+        _ => return None,
+    })
+}
+
+fn data_type_id(data_type: DataType) -> DataTypeId {
+    match data_type {
+        DataType::Integer => DataTypeId::Int64,
+        DataType::Float => DataTypeId::Float,
+        DataType::Boolean => DataTypeId::Boolean,
+        DataType::String => DataTypeId::String,
+    }
+}
+
+fn data_value(value: &TagValue) -> DataValue {
+    let status = match (value.timestamp, value.quality) {
+        (None, _) => StatusCode::BadWaitingForInitialData,
+        (Some(_), Quality::Good) => StatusCode::Good,
+        (Some(_), Quality::Uncertain) => StatusCode::Uncertain,
+        (Some(_), Quality::Bad) => StatusCode::Bad,
+    };
+    DataValue {
+        value: Some(variant(&value.value)),
+        status: Some(status),
+        source_timestamp: value.timestamp.map(DateTime::from),
+        server_timestamp: Some(DateTime::now()),
+        ..Default::default()
+    }
+}
+
+/// Engineering unit with its UNECE common code, symbol and name.
+fn eu_information(unit: Unit) -> Option<EUInformation> {
+    let (code, symbol, name) = match unit {
+        Unit::None => return None,
+        Unit::Percent => ("P1", "%", "percent"),
+        Unit::Volt => ("VLT", "V", "volt"),
+        Unit::Ampere => ("AMP", "A", "ampere"),
+        Unit::Degree => ("DD", "°", "degree"),
+        Unit::Radian => ("C81", "rad", "radian"),
+        Unit::Celsius => ("CEL", "°C", "degree Celsius"),
+        Unit::Kelvin => ("KEL", "K", "kelvin"),
+        Unit::Metre => ("MTR", "m", "metre"),
+        Unit::Kilogram => ("KGM", "kg", "kilogram"),
+        Unit::Second => ("SEC", "s", "second"),
+    };
+    Some(EUInformation {
+        namespace_uri: UNECE_URI.into(),
+        unit_id: code.bytes().fold(0, |id, c| id << 8 | i32::from(c)),
+        display_name: LocalizedText::new("", symbol),
+        description: LocalizedText::new("", name),
+    })
+}
+
+/// Keeps the address space in line with the tag repository: applies the
+/// broadcast values and compares everything regularly.
+pub struct TagNodes {
+    manager: Arc<TagNodeManager>,
+    subscriptions: Arc<SubscriptionCache>,
+    namespace: u16,
+    /// Tags as the nodes show them.
+    tags: HashMap<TagName, Tag>,
+    /// Ids of the folders containing tags.
+    folders: HashSet<String>,
+}
+
+impl TagNodes {
+    pub fn new(
+        manager: Arc<TagNodeManager>,
+        subscriptions: Arc<SubscriptionCache>,
+        namespace: u16,
+    ) -> Self {
+        Self {
+            manager,
+            subscriptions,
+            namespace,
+            tags: HashMap::new(),
+            folders: HashSet::new(),
+        }
+    }
+
+    /// Applies `updates` and resyncs every `interval` and whenever updates
+    /// were missed, until the tag repository stops.
+    pub async fn run(
+        mut self,
+        tag_repo: ActorRef<actor::tag::Message>,
+        mut updates: broadcast::Receiver<Tag>,
+        interval: Duration,
+    ) {
+        let mut resync = tokio::time::interval(interval);
+        loop {
+            tokio::select! {
+                _ = resync.tick() => {
+                    let (command, mut reply) = actor::tag::Message::get_all_tags();
+                    if tag_repo.enqueue(command).is_err() {
+                        break;
+                    }
+                    let Some(tags) = reply.recv().await else {
+                        break;
+                    };
+                    self.resync(tags);
+                },
+                update = updates.recv() => match update {
+                    Ok(tag) => self.update(tag),
+                    Err(RecvError::Lagged(missed)) => {
+                        tracing::debug!("OPC UA server missed {} tag updates, resyncing", missed);
+                        resync.reset_immediately();
+                    },
+                    Err(RecvError::Closed) => break,
+                },
+            }
+        }
+    }
+
+    fn update(&mut self, tag: Tag) {
+        match self.tags.get(&tag.name) {
+            Some(current) if current.meta == tag.meta => self.set_value(&tag),
+            _ => self.put(&tag),
+        }
+        self.tags.insert(tag.name.clone(), tag);
+    }
+
+    fn resync(&mut self, tags: Vec<Tag>) {
+        let names: HashSet<TagName> = tags.iter().map(|tag| tag.name.clone()).collect();
+        let removed: Vec<TagName> = self
+            .tags
+            .keys()
+            .filter(|name| !names.contains(*name))
+            .cloned()
+            .collect();
+        for name in removed {
+            self.remove(&name);
+        }
+        for tag in tags {
+            if self.tags.get(&tag.name) != Some(&tag) {
+                self.update(tag);
+            }
+        }
+
+        let used: HashSet<String> = self
+            .tags
+            .keys()
+            .flat_map(|name| folders(name).map(|(id, _)| id.to_string()))
+            .collect();
+        let mut address_space = self.manager.address_space().write();
+        self.folders.retain(|id| {
+            if !used.contains(id) {
+                address_space.delete(&NodeId::new(self.namespace, id.as_str()), true);
+            }
+            used.contains(id)
+        });
+    }
+
+    /// Creates or recreates the nodes of `tag`.
+    fn put(&mut self, tag: &Tag) {
+        let namespace = self.namespace;
+        let id = NodeId::new(namespace, tag.name.as_str());
+        {
+            let mut address_space = self.manager.address_space().write();
+            delete_tag_nodes(&mut address_space, namespace, &tag.name);
+
+            let mut parent = NodeId::new(namespace, ROOT_ID);
+            for (folder, browse_name) in folders(&tag.name) {
+                let folder_id = NodeId::new(namespace, folder);
+                if self.folders.insert(folder.to_string()) {
+                    address_space.add_folder(
+                        &folder_id,
+                        QualifiedName::new(namespace, browse_name),
+                        browse_name,
+                        &parent,
+                    );
+                }
+                parent = folder_id;
+            }
+
+            let browse_name = tag
+                .name
+                .rsplit(SEPARATORS)
+                .next()
+                .filter(|name| !name.is_empty())
+                .unwrap_or(&tag.name);
+            let access_level = if tag.meta.read_only {
+                AccessLevel::CURRENT_READ
+            } else {
+                AccessLevel::CURRENT_READ | AccessLevel::CURRENT_WRITE
+            };
+            VariableBuilder::new(&id, QualifiedName::new(namespace, browse_name), browse_name)
+                .description(tag.meta.description.as_str())
+                .data_type(data_type_id(tag.meta.data_type))
+                .access_level(access_level)
+                .user_access_level(access_level)
+                .has_type_definition(VariableTypeId::BaseDataVariableType)
+                .organized_by(parent)
+                .insert(&mut *address_space);
+            for (property, value, data_type) in properties(&tag.meta) {
+                VariableBuilder::new(
+                    &NodeId::new(namespace, format!("{}#{}", tag.name, property)),
+                    QualifiedName::new(0, property),
+                    property,
+                )
+                .value(value)
+                .data_type(data_type)
+                .access_level(AccessLevel::CURRENT_READ)
+                .user_access_level(AccessLevel::CURRENT_READ)
+                .has_type_definition(VariableTypeId::PropertyType)
+                .property_of(id.clone())
+                .insert(&mut *address_space);
+            }
+        }
+        self.set_value(tag);
+    }
+
+    fn set_value(&self, tag: &Tag) {
+        let id = NodeId::new(self.namespace, tag.name.as_str());
+        if let Err(e) =
+            self.manager
+                .set_value(&self.subscriptions, &id, None, data_value(&tag.value))
+        {
+            tracing::warn!("Failed to set OPC UA value of {}: {}", tag.name, e);
+        }
+    }
+
+    fn remove(&mut self, name: &TagName) {
+        let mut address_space = self.manager.address_space().write();
+        delete_tag_nodes(&mut address_space, self.namespace, name);
+        self.tags.remove(name);
+    }
+}
+
+fn delete_tag_nodes(address_space: &mut AddressSpace, namespace: u16, name: &str) {
+    address_space.delete(&NodeId::new(namespace, name), true);
+    for property in [ENGINEERING_UNITS, EU_RANGE] {
+        address_space.delete(&NodeId::new(namespace, format!("{name}#{property}")), true);
+    }
+}
+
+/// Properties of a tag variable with their value and data type.
+fn properties(meta: &TagMeta) -> Vec<(&'static str, Variant, DataTypeId)> {
+    let mut properties = Vec::new();
+    if let Some(unit) = eu_information(meta.unit) {
+        properties.push((
+            ENGINEERING_UNITS,
+            Variant::from(unit),
+            DataTypeId::EUInformation,
+        ));
+    }
+    if let Some(range) = meta.range {
+        let range = Range {
+            low: range.low,
+            high: range.high,
+        };
+        properties.push((EU_RANGE, Variant::from(range), DataTypeId::Range));
+    }
+    properties
+}
+
+/// Folders of a tag name from the outermost, with their id, which is the
+/// name up to and including the separator, and their browse name.
+fn folders(name: &str) -> impl Iterator<Item = (&str, &str)> {
+    let mut start = 0;
+    name.match_indices(SEPARATORS).map(move |(index, _)| {
+        let id = &name[..=index];
+        let segment = &name[start..index];
+        start = index + 1;
+        (id, if segment.is_empty() { id } else { segment })
+    })
+}
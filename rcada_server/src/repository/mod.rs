@@ -1 +1,2 @@
 pub mod tag;
+pub mod template;
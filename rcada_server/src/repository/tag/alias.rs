@@ -0,0 +1,155 @@
+use std::{
+    fs, io,
+    path::{Path, PathBuf},
+};
+
+use dashmap::DashMap;
+use rcada_core::tag::TagName;
+
+use crate::repository::tag::TagAlias;
+
+/// Table of alternative tag names, optionally backed by a JSON file.
+#[derive(Default, Clone)]
+pub struct AliasTable {
+    aliases: DashMap<TagName, TagName>,
+    path: Option<PathBuf>,
+}
+
+impl AliasTable {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Loads the table from `path` and saves every change back to it.
+    ///
+    /// A missing file is treated as an empty table.
+    pub fn with_file(path: impl AsRef<Path>) -> io::Result<Self> {
+        let path = path.as_ref().to_path_buf();
+        let aliases = DashMap::new();
+
+        match fs::read(&path) {
+            Ok(bytes) => {
+                let entries: Vec<TagAlias> = serde_json::from_slice(&bytes)
+                    .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+                for entry in entries {
+                    aliases.insert(entry.alias, entry.target);
+                }
+            },
+            Err(e) if e.kind() == io::ErrorKind::NotFound => {},
+            Err(e) => return Err(e),
+        }
+
+        Ok(Self {
+            aliases,
+            path: Some(path),
+        })
+    }
+
+    pub fn get(&self, alias: &TagName) -> Option<TagName> {
+        self.aliases.get(alias).map(|target| target.clone())
+    }
+
+    pub fn contains(&self, alias: &TagName) -> bool {
+        self.aliases.contains_key(alias)
+    }
+
+    pub fn insert(&self, alias: TagName, target: TagName) -> io::Result<()> {
+        let previous = self.aliases.insert(alias.clone(), target);
+        self.save().inspect_err(|_| {
+            match previous {
+                Some(previous) => self.aliases.insert(alias, previous),
+                None => self.aliases.remove(&alias).map(|(_, target)| target),
+            };
+        })
+    }
+
+    pub fn remove(&self, alias: &TagName) -> io::Result<Option<TagName>> {
+        let Some((_, target)) = self.aliases.remove(alias) else {
+            return Ok(None);
+        };
+        self.save().inspect_err(|_| {
+            self.aliases.insert(alias.clone(), target.clone());
+        })?;
+        Ok(Some(target))
+    }
+
+    /// Moves the aliases of a renamed tag from `from` to `to`, dropping an
+    /// alias named `to` and adding `from` as an alias if `keep_alias` is set.
+    ///
+    /// The new table is saved in one go before it replaces the current one,
+    /// so a failed save leaves the table as it was.
+    pub fn rename(&self, from: &TagName, to: &TagName, keep_alias: bool) -> io::Result<()> {
+        let mut aliases: Vec<TagAlias> = self
+            .list()
+            .into_iter()
+            .filter(|entry| entry.alias != *to)
+            .map(|entry| TagAlias {
+                target: if entry.target == *from {
+                    to.clone()
+                } else {
+                    entry.target
+                },
+                alias: entry.alias,
+            })
+            .collect();
+        if keep_alias {
+            aliases.push(TagAlias {
+                alias: from.clone(),
+                target: to.clone(),
+            });
+            aliases.sort_by(|a, b| a.alias.cmp(&b.alias));
+        }
+        self.replace(aliases)
+    }
+
+    /// Replaces every alias, saving the new table before it replaces the
+    /// current one.
+    pub fn replace(&self, aliases: Vec<TagAlias>) -> io::Result<()> {
+        self.write(&aliases)?;
+
+        self.aliases.clear();
+        for entry in aliases {
+            self.aliases.insert(entry.alias, entry.target);
+        }
+        Ok(())
+    }
+
+    /// Removes every alias pointing to `target`, leaving the table as it was
+    /// if that can't be saved.
+    pub fn remove_target(&self, target: &TagName) -> io::Result<()> {
+        let aliases = self
+            .list()
+            .into_iter()
+            .filter(|entry| entry.target != *target)
+            .collect();
+        self.replace(aliases)
+    }
+
+    pub fn list(&self) -> Vec<TagAlias> {
+        let mut aliases: Vec<TagAlias> = self
+            .aliases
+            .iter()
+            .map(|entry| TagAlias {
+                alias: entry.key().clone(),
+                target: entry.value().clone(),
+            })
+            .collect();
+        aliases.sort_by(|a, b| a.alias.cmp(&b.alias));
+        aliases
+    }
+
+    fn save(&self) -> io::Result<()> {
+        self.write(&self.list())
+    }
+
+    fn write(&self, aliases: &[TagAlias]) -> io::Result<()> {
+        let Some(path) = &self.path else {
+            return Ok(());
+        };
+
+        let json = serde_json::to_vec_pretty(aliases)?;
+        let tmp = path.with_extension("tmp");
+        fs::write(&tmp, json)?;
+        fs::rename(tmp, path)
+    }
+}
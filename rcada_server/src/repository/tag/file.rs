@@ -0,0 +1,402 @@
+use std::{
+    fs, io,
+    path::{Path, PathBuf},
+    sync::{
+        Mutex,
+        atomic::{AtomicBool, Ordering},
+    },
+    time::{Duration, Instant},
+};
+
+use rcada_core::{
+    tag::{Tag, TagMeta, TagName, TagValue},
+    value::DataType,
+};
+
+use crate::repository::tag::{
+    AliasError, ApplyChangesError, CreateTagResult, DeleteTagError, ReadTagError, RenameTagError,
+    TagAlias, TagChange, TagMetaPatch, TagRepository, UpdateMetaError, UpdateValueError,
+    UpdateValueResult, inmemory::TagStorage,
+};
+
+pub const TAGS_FILE: &str = "tags.json";
+pub const ALIASES_FILE: &str = "aliases.json";
+
+/// Value changes are written at most this often, configuration changes immediately.
+const VALUE_SAVE_INTERVAL: Duration = Duration::from_secs(1);
+
+/// In-memory storage that keeps a snapshot of all tags in a data directory.
+///
+/// Value changes held back by the throttling are written by [`TagRepository::flush`]
+/// and when the storage is dropped, so only the changes since the last flush
+/// are lost if the process crashes.
+#[derive(Default)]
+pub struct FileTagStorage {
+    inner: TagStorage,
+    path: Option<PathBuf>,
+    last_save: Mutex<Option<Instant>>,
+    /// Value changes were made since the last save.
+    pending: AtomicBool,
+}
+
+impl FileTagStorage {
+    pub fn open(data_dir: impl AsRef<Path>) -> io::Result<Self> {
+        let data_dir = data_dir.as_ref();
+        fs::create_dir_all(data_dir)?;
+
+        let inner = TagStorage::with_alias_file(data_dir.join(ALIASES_FILE))?;
+        let path = data_dir.join(TAGS_FILE);
+        match fs::read(&path) {
+            Ok(bytes) => {
+                let tags: Vec<Tag> = serde_json::from_slice(&bytes)
+                    .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+                // Restored as saved, even if an alias has the same name
+                for tag in tags {
+                    inner.restore(&tag.name.clone(), Some(tag));
+                }
+            },
+            Err(e) if e.kind() == io::ErrorKind::NotFound => {},
+            Err(e) => return Err(e),
+        }
+        // The tags and aliases are separate files, a crash between writing
+        // them leaves aliases of tags that were renamed or deleted
+        for entry in inner.remove_dangling_aliases()? {
+            tracing::warn!(
+                "dropped alias {} of {}: the target is no tag or the alias is one",
+                entry.alias,
+                entry.target
+            );
+        }
+
+        Ok(Self {
+            inner,
+            path: Some(path),
+            last_save: Mutex::new(None),
+            pending: AtomicBool::new(false),
+        })
+    }
+
+    fn save(&self) -> io::Result<()> {
+        let Some(path) = &self.path else {
+            return Ok(());
+        };
+        if let Ok(mut last_save) = self.last_save.lock() {
+            *last_save = Some(Instant::now());
+        }
+        self.pending.store(false, Ordering::Relaxed);
+
+        let mut tags = self.inner.get_all_tags();
+        tags.sort_by(|a, b| a.name.cmp(&b.name));
+        let result = serde_json::to_vec_pretty(&tags)
+            .map_err(io::Error::other)
+            .and_then(|json| {
+                let tmp = path.with_extension("tmp");
+                fs::write(&tmp, json)?;
+                fs::rename(tmp, path)
+            });
+        if let Err(e) = &result {
+            // Saved again with the next change
+            self.pending.store(true, Ordering::Relaxed);
+            tracing::error!("failed to save tags to {}: {}", path.display(), e);
+        }
+        result
+    }
+
+    /// Value changes are kept in memory if they can't be saved, the next
+    /// save writes them.
+    fn save_throttled(&self) {
+        let due = self
+            .last_save
+            .lock()
+            .map(|last_save| last_save.is_none_or(|at| at.elapsed() >= VALUE_SAVE_INTERVAL))
+            .unwrap_or(true);
+        if due {
+            let _ = self.save();
+        } else {
+            self.pending.store(true, Ordering::Relaxed);
+        }
+    }
+
+    /// Tags named `names` and the aliases, to undo a change that can't be
+    /// saved.
+    fn snapshot<'a>(&self, names: impl IntoIterator<Item = &'a TagName>) -> Snapshot {
+        let mut tags = Vec::new();
+        for name in names {
+            match self.inner.get_tag(name) {
+                Ok(tag) if tag.name == *name => tags.push((tag.name.clone(), Some(tag))),
+                // An alias, the name of no tag
+                Ok(tag) => {
+                    tags.push((tag.name.clone(), Some(tag)));
+                    tags.push((name.clone(), None));
+                },
+                Err(_) => tags.push((name.clone(), None)),
+            }
+        }
+        Snapshot {
+            tags,
+            aliases: self.inner.get_all_aliases(),
+        }
+    }
+
+    /// Saves the tags after a configuration change, putting back the tags
+    /// and aliases of `before` if that fails.
+    fn save_or_undo(&self, before: Snapshot) -> io::Result<()> {
+        self.save().inspect_err(|_| self.undo(before))
+    }
+
+    fn undo(&self, before: Snapshot) {
+        // Restored in reverse, so a tag listed twice ends up as it was first
+        for (name, tag) in before.tags.into_iter().rev() {
+            self.inner.restore(&name, tag);
+        }
+        if self.inner.get_all_aliases() != before.aliases
+            && let Err(e) = self.inner.restore_aliases(before.aliases)
+        {
+            tracing::error!("failed to restore the aliases: {}", e);
+        }
+    }
+}
+
+/// Tags, `None` for names that weren't tags, and aliases before a change.
+struct Snapshot {
+    tags: Vec<(TagName, Option<Tag>)>,
+    aliases: Vec<TagAlias>,
+}
+
+impl Drop for FileTagStorage {
+    fn drop(&mut self) {
+        let _ = self.save();
+    }
+}
+
+impl TagRepository for FileTagStorage {
+    fn is_tag_exists(&self, name: &TagName) -> bool {
+        self.inner.is_tag_exists(name)
+    }
+
+    fn create_tag(&self, name: TagName, meta: TagMeta) -> CreateTagResult {
+        let before = self.snapshot([&name]);
+        let result = self.inner.create_tag(name, meta);
+        if result == CreateTagResult::SuccessfullyCreated
+            && let Err(e) = self.save_or_undo(before)
+        {
+            return CreateTagResult::Storage(e.to_string());
+        }
+        result
+    }
+
+    fn get_tag(&self, name: &TagName) -> Result<Tag, ReadTagError> {
+        self.inner.get_tag(name)
+    }
+
+    fn get_all_tags(&self) -> Vec<Tag> {
+        self.inner.get_all_tags()
+    }
+
+    fn tag_count(&self) -> usize {
+        self.inner.tag_count()
+    }
+
+    fn update_tag_value(
+        &self,
+        name: TagName,
+        value: TagValue,
+    ) -> Result<UpdateValueResult, UpdateValueError> {
+        let result = self.inner.update_tag_value(name, value);
+        if result == Ok(UpdateValueResult::Updated) {
+            self.save_throttled();
+        }
+        result
+    }
+
+    fn update_tag_meta(&self, name: &TagName, patch: TagMetaPatch) -> Result<Tag, UpdateMetaError> {
+        let before = self.snapshot([name]);
+        let tag = self.inner.update_tag_meta(name, patch)?;
+        self.save_or_undo(before)
+            .map_err(|e| UpdateMetaError::Storage(e.to_string()))?;
+        Ok(tag)
+    }
+
+    fn delete_tag(&self, name: &TagName) -> Result<(), DeleteTagError> {
+        let before = self.snapshot([name]);
+        self.inner.delete_tag(name)?;
+        self.save_or_undo(before)
+            .map_err(|e| DeleteTagError::Storage(e.to_string()))
+    }
+
+    fn rename_tag(
+        &self,
+        name: &TagName,
+        new_name: TagName,
+        keep_alias: bool,
+    ) -> Result<Tag, RenameTagError> {
+        let before = self.snapshot([name, &new_name]);
+        let tag = self.inner.rename_tag(name, new_name, keep_alias)?;
+        self.save_or_undo(before)
+            .map_err(|e| RenameTagError::Storage(e.to_string()))?;
+        Ok(tag)
+    }
+
+    fn create_alias(&self, alias: TagName, target: &TagName) -> Result<TagAlias, AliasError> {
+        self.inner.create_alias(alias, target)
+    }
+
+    fn delete_alias(&self, alias: &TagName) -> Result<(), AliasError> {
+        self.inner.delete_alias(alias)
+    }
+
+    fn get_all_aliases(&self) -> Vec<TagAlias> {
+        self.inner.get_all_aliases()
+    }
+
+    fn apply_changes(&self, changes: Vec<TagChange>) -> Result<(), ApplyChangesError> {
+        let before = self.snapshot(changes.iter().map(TagChange::name));
+        match self.inner.apply_changes(changes) {
+            Err(e @ ApplyChangesError::Storage(_)) => {
+                self.undo(before);
+                return Err(e);
+            },
+            result => result?,
+        }
+        self.save_or_undo(before)
+            .map_err(|e| ApplyChangesError::Storage(e.to_string()))
+    }
+
+    fn get_tag_data_type(&self, name: &TagName) -> Option<DataType> {
+        self.inner.get_tag_data_type(name)
+    }
+
+    fn get_tag_value(&self, name: &TagName) -> Option<TagValue> {
+        self.inner.get_tag_value(name)
+    }
+
+    fn flush(&self) {
+        if self.pending.load(Ordering::Relaxed) {
+            let _ = self.save();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rcada_core::{tag::Quality, unit::Unit, value::Value};
+
+    use super::*;
+
+    fn value(v: i64) -> TagValue {
+        TagValue {
+            value: Value::Integer(v),
+            timestamp: Some(chrono::Utc::now()),
+            quality: Quality::Good,
+        }
+    }
+
+    #[test]
+    fn flush_writes_value_changes_held_back() {
+        let data_dir = std::env::temp_dir().join(format!("rcada-tags-{}", uuid::Uuid::new_v4()));
+        let name = TagName::from("counter");
+
+        let storage = FileTagStorage::open(&data_dir).unwrap();
+        storage.create_tag(name.clone(), TagMeta::new(Unit::None, DataType::Integer));
+        storage.update_tag_value(name.clone(), value(1)).unwrap();
+        storage.update_tag_value(name.clone(), value(2)).unwrap();
+        storage.flush();
+        // Skip the save on drop to see what the flush wrote
+        std::mem::forget(storage);
+
+        let storage = FileTagStorage::open(&data_dir).unwrap();
+        assert_eq!(
+            storage.get_tag_value(&name).map(|value| value.value),
+            Some(Value::Integer(2))
+        );
+        drop(storage);
+        fs::remove_dir_all(data_dir).unwrap();
+    }
+
+    #[test]
+    fn aliases_left_by_an_interrupted_save_are_dropped() {
+        let data_dir = std::env::temp_dir().join(format!("rcada-tags-{}", uuid::Uuid::new_v4()));
+        let storage = FileTagStorage::open(&data_dir).unwrap();
+        for name in ["a", "b"] {
+            storage.create_tag(
+                TagName::from(name),
+                TagMeta::new(Unit::None, DataType::Integer),
+            );
+        }
+        drop(storage);
+
+        let alias = |alias: &str, target: &str| TagAlias {
+            alias: TagName::from(alias),
+            target: TagName::from(target),
+        };
+        let aliases = [alias("a", "b"), alias("x", "a"), alias("y", "renamed")];
+        fs::write(
+            data_dir.join(ALIASES_FILE),
+            serde_json::to_vec(&aliases).unwrap(),
+        )
+        .unwrap();
+
+        let storage = FileTagStorage::open(&data_dir).unwrap();
+        assert_eq!(storage.get_all_aliases(), [alias("x", "a")]);
+        drop(storage);
+        let storage = FileTagStorage::open(&data_dir).unwrap();
+        assert_eq!(storage.get_all_aliases(), [alias("x", "a")]);
+        drop(storage);
+        fs::remove_dir_all(data_dir).unwrap();
+    }
+
+    #[test]
+    fn changes_that_cant_be_saved_are_undone() {
+        let data_dir = std::env::temp_dir().join(format!("rcada-tags-{}", uuid::Uuid::new_v4()));
+        let name = TagName::from("counter");
+        let meta = TagMeta::new(Unit::None, DataType::Integer);
+
+        let storage = FileTagStorage::open(&data_dir).unwrap();
+        storage.create_tag(name.clone(), meta.clone());
+        // The temporary file can't be written over a directory
+        let blocker = data_dir.join(TAGS_FILE).with_extension("tmp");
+        fs::create_dir(&blocker).unwrap();
+
+        let other = TagName::from("other");
+        assert!(matches!(
+            storage.create_tag(other.clone(), meta.clone()),
+            CreateTagResult::Storage(_)
+        ));
+        assert!(!storage.is_tag_exists(&other));
+
+        let patch = TagMetaPatch {
+            description: Some("changed".to_string()),
+            ..Default::default()
+        };
+        assert!(matches!(
+            storage.update_tag_meta(&name, patch),
+            Err(UpdateMetaError::Storage(_))
+        ));
+        assert_eq!(storage.get_tag(&name).unwrap().meta, meta);
+
+        assert!(matches!(
+            storage.delete_tag(&name),
+            Err(DeleteTagError::Storage(_))
+        ));
+        assert!(matches!(
+            storage.rename_tag(&name, other.clone(), true),
+            Err(RenameTagError::Storage(_))
+        ));
+        assert!(matches!(
+            storage.apply_changes(vec![TagChange::Delete {
+                name: name.clone()
+            }]),
+            Err(ApplyChangesError::Storage(_))
+        ));
+        assert!(storage.is_tag_exists(&name));
+        assert!(!storage.is_tag_exists(&other));
+        assert!(storage.get_all_aliases().is_empty());
+
+        fs::remove_dir(&blocker).unwrap();
+        drop(storage);
+        let storage = FileTagStorage::open(&data_dir).unwrap();
+        assert_eq!(storage.get_tag(&name).unwrap().meta, meta);
+        drop(storage);
+        fs::remove_dir_all(data_dir).unwrap();
+    }
+}
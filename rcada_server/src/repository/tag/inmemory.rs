@@ -1,19 +1,22 @@
+use std::{collections::HashSet, io, path::Path};
+
 use dashmap::DashMap;
 use rcada_core::{
-    tag::{Tag, TagMeta, TagName, TagValue},
-    unit::Unit,
+    tag::{Quality, Tag, TagMeta, TagName, TagValue},
     value::{DataType, Value},
 };
 
 use crate::repository::tag::{
-    CreateTagResult, DeleteTagError, ReadTagError, TagRepository, UpdateValueError,
-    UpdateValueResult,
+    AliasError, ApplyChangesError, CreateTagResult, DeleteTagError, ReadTagError, RenameTagError,
+    TagAlias, TagChange, TagMetaPatch, TagRepository, UpdateMetaError, UpdateValueError,
+    UpdateValueResult, alias::AliasTable,
 };
 
 #[derive(Default, Clone)]
 pub struct TagStorage {
     values: DashMap<TagName, TagValue>,
     meta: DashMap<TagName, TagMeta>,
+    aliases: AliasTable,
 }
 
 impl TagStorage {
@@ -21,40 +24,87 @@ impl TagStorage {
         Self {
             values: DashMap::new(),
             meta: DashMap::new(),
+            aliases: AliasTable::new(),
+        }
+    }
+
+    /// Creates a storage whose alias table is persisted in `path`.
+    pub fn with_alias_file(path: impl AsRef<Path>) -> io::Result<Self> {
+        Ok(Self {
+            values: DashMap::new(),
+            meta: DashMap::new(),
+            aliases: AliasTable::with_file(path)?,
+        })
+    }
+
+    /// Puts back a tag as it was, or removes `name` if it wasn't a tag.
+    /// Aliases are left alone.
+    pub fn restore(&self, name: &TagName, tag: Option<Tag>) {
+        match tag {
+            Some(tag) => {
+                self.values.insert(name.clone(), tag.value);
+                self.meta.insert(name.clone(), tag.meta);
+            },
+            None => {
+                self.values.remove(name);
+                self.meta.remove(name);
+            },
+        }
+    }
+
+    /// Puts back the aliases as they were.
+    pub fn restore_aliases(&self, aliases: Vec<TagAlias>) -> io::Result<()> {
+        self.aliases.replace(aliases)
+    }
+
+    /// Removes the aliases whose target isn't a tag or whose name is one,
+    /// returning them.
+    pub fn remove_dangling_aliases(&self) -> io::Result<Vec<TagAlias>> {
+        let (aliases, dangling): (Vec<_>, Vec<_>) =
+            self.aliases.list().into_iter().partition(|entry| {
+                self.values.contains_key(&entry.target) && !self.values.contains_key(&entry.alias)
+            });
+        if !dangling.is_empty() {
+            self.aliases.replace(aliases)?;
+        }
+        Ok(dangling)
+    }
+
+    /// Returns the canonical name for `name`, following an alias if there is one.
+    fn resolve(&self, name: &TagName) -> TagName {
+        if self.values.contains_key(name) {
+            return name.clone();
         }
+        self.aliases.get(name).unwrap_or_else(|| name.clone())
     }
 }
 
 impl TagRepository for TagStorage {
     fn is_tag_exists(&self, name: &TagName) -> bool {
-        self.values.contains_key(name)
+        self.values.contains_key(&self.resolve(name))
     }
 
-    fn create_tag(&self, name: TagName, unit: Unit, data_type: DataType) -> CreateTagResult {
-        if self.values.contains_key(&name) {
+    fn create_tag(&self, name: TagName, meta: TagMeta) -> CreateTagResult {
+        if self.values.contains_key(&name) || self.aliases.contains(&name) {
             return CreateTagResult::AlreadyExists;
         }
 
         self.values.insert(
             name.clone(),
             TagValue {
-                value: Value::default_with_data_type(data_type),
+                value: Value::default_with_data_type(meta.data_type),
                 timestamp: None,
+                quality: Quality::default(),
             },
         );
 
-        self.meta.insert(
-            name,
-            TagMeta {
-                unit,
-                data_type,
-            },
-        );
+        self.meta.insert(name, meta);
 
         CreateTagResult::SuccessfullyCreated
     }
 
     fn get_tag(&self, name: &TagName) -> Result<Tag, ReadTagError> {
+        let name = &self.resolve(name);
         let value = self
             .values
             .get(name)
@@ -89,11 +139,27 @@ impl TagRepository for TagStorage {
         tags
     }
 
+    fn tag_count(&self) -> usize {
+        self.values.len()
+    }
+
     fn update_tag_value(
         &self,
         name: TagName,
         value: TagValue,
     ) -> Result<UpdateValueResult, UpdateValueError> {
+        let name = self.resolve(&name);
+        let expected = self
+            .get_tag_data_type(&name)
+            .ok_or(UpdateValueError::TagNameNotFound)?;
+        let actual = value.value.get_data_type();
+        if expected != actual {
+            return Err(UpdateValueError::InvalidDataType {
+                expected,
+                actual,
+            });
+        }
+
         let previous_value =
             if let Some(old_value) = self.values.insert(name.clone(), value.clone()) {
                 old_value
@@ -108,22 +174,349 @@ impl TagRepository for TagStorage {
         }
     }
 
+    fn update_tag_meta(&self, name: &TagName, patch: TagMetaPatch) -> Result<Tag, UpdateMetaError> {
+        let name = &self.resolve(name);
+        let mut meta = self
+            .meta
+            .get(name)
+            .ok_or(UpdateMetaError::TagNameNotFound)?
+            .clone();
+        let mut value = self
+            .values
+            .get(name)
+            .ok_or(UpdateMetaError::TagNameNotFound)?
+            .clone();
+
+        let previous_data_type = meta.data_type;
+        patch.apply(&mut meta);
+
+        if meta.range.is_some_and(|range| !range.is_valid()) {
+            return Err(UpdateMetaError::InvalidRange);
+        }
+        if !meta.alarms.is_valid() {
+            return Err(UpdateMetaError::InvalidAlarmLimits);
+        }
+
+        if meta.data_type != previous_data_type {
+            value.value = value.value.convert(meta.data_type).ok_or(
+                UpdateMetaError::IncompatibleDataType {
+                    from: previous_data_type,
+                    to: meta.data_type,
+                },
+            )?;
+            self.values.insert(name.clone(), value.clone());
+        }
+
+        self.meta.insert(name.clone(), meta.clone());
+
+        Ok(Tag {
+            name: name.clone(),
+            value,
+            meta,
+        })
+    }
+
     fn delete_tag(&self, name: &TagName) -> Result<(), DeleteTagError> {
-        let value_removed = self.values.remove(name).is_some();
-        let _meta_removed = self.meta.remove(name).is_some();
+        let name = &self.resolve(name);
+        if !self.values.contains_key(name) {
+            return Err(DeleteTagError::TagNameNotFound);
+        }
+        self.aliases
+            .remove_target(name)
+            .map_err(|e| DeleteTagError::Storage(e.to_string()))?;
+        self.values.remove(name);
+        self.meta.remove(name);
+        Ok(())
+    }
 
-        if value_removed {
-            Ok(())
-        } else {
-            Err(DeleteTagError::TagNameNotFound)
+    fn rename_tag(
+        &self,
+        name: &TagName,
+        new_name: TagName,
+        keep_alias: bool,
+    ) -> Result<Tag, RenameTagError> {
+        let name = self.resolve(name);
+        if !self.values.contains_key(&name) {
+            return Err(RenameTagError::TagNameNotFound);
+        }
+        if name == new_name {
+            return self
+                .get_tag(&name)
+                .map_err(|_| RenameTagError::TagNameNotFound);
+        }
+        if self.values.contains_key(&new_name) {
+            return Err(RenameTagError::AlreadyExists);
         }
+
+        // Renaming a tag to one of its own aliases drops the alias
+        if self
+            .aliases
+            .get(&new_name)
+            .is_some_and(|target| target != name)
+        {
+            return Err(RenameTagError::AlreadyExists);
+        }
+        self.aliases
+            .rename(&name, &new_name, keep_alias)
+            .map_err(|e| RenameTagError::Storage(e.to_string()))?;
+
+        let (_, value) = self
+            .values
+            .remove(&name)
+            .ok_or(RenameTagError::TagNameNotFound)?;
+        let (_, meta) = self
+            .meta
+            .remove(&name)
+            .ok_or(RenameTagError::TagNameNotFound)?;
+        self.values.insert(new_name.clone(), value.clone());
+        self.meta.insert(new_name.clone(), meta.clone());
+
+        Ok(Tag {
+            name: new_name,
+            value,
+            meta,
+        })
+    }
+
+    fn create_alias(&self, alias: TagName, target: &TagName) -> Result<TagAlias, AliasError> {
+        let target = self.resolve(target);
+        if !self.values.contains_key(&target) {
+            return Err(AliasError::TagNameNotFound);
+        }
+        if self.values.contains_key(&alias) || self.aliases.contains(&alias) {
+            return Err(AliasError::AlreadyExists);
+        }
+
+        self.aliases
+            .insert(alias.clone(), target.clone())
+            .map_err(|e| AliasError::Storage(e.to_string()))?;
+
+        Ok(TagAlias {
+            alias,
+            target,
+        })
+    }
+
+    fn delete_alias(&self, alias: &TagName) -> Result<(), AliasError> {
+        match self.aliases.remove(alias) {
+            Ok(Some(_)) => Ok(()),
+            Ok(None) => Err(AliasError::AliasNotFound),
+            Err(e) => Err(AliasError::Storage(e.to_string())),
+        }
+    }
+
+    fn get_all_aliases(&self) -> Vec<TagAlias> {
+        self.aliases.list()
+    }
+
+    fn apply_changes(&self, changes: Vec<TagChange>) -> Result<(), ApplyChangesError> {
+        let is_valid = |meta: &TagMeta| {
+            meta.range.is_none_or(|range| range.is_valid()) && meta.alarms.is_valid()
+        };
+
+        // Validate everything first so that a failing change leaves the storage untouched
+        let mut converted = Vec::with_capacity(changes.len());
+        let mut seen = HashSet::new();
+        for change in &changes {
+            if !seen.insert(change.name()) {
+                return Err(ApplyChangesError::AlreadyExists(change.name().clone()));
+            }
+            match change {
+                TagChange::Create {
+                    name,
+                    meta,
+                } => {
+                    if self.is_tag_exists(name) || self.aliases.contains(name) {
+                        return Err(ApplyChangesError::AlreadyExists(name.clone()));
+                    }
+                    if !is_valid(meta) {
+                        return Err(ApplyChangesError::InvalidMeta(name.clone()));
+                    }
+                    converted.push(None);
+                },
+                TagChange::Update {
+                    name,
+                    meta,
+                } => {
+                    let value = self
+                        .get_tag_value(name)
+                        .ok_or_else(|| ApplyChangesError::TagNameNotFound(name.clone()))?;
+                    if !is_valid(meta) {
+                        return Err(ApplyChangesError::InvalidMeta(name.clone()));
+                    }
+                    let from = value.value.get_data_type();
+                    let value = value.value.convert(meta.data_type).ok_or_else(|| {
+                        ApplyChangesError::IncompatibleDataType {
+                            name: name.clone(),
+                            from,
+                            to: meta.data_type,
+                        }
+                    })?;
+                    converted.push(Some(value));
+                },
+                TagChange::Delete {
+                    name,
+                } => {
+                    if !self.is_tag_exists(name) {
+                        return Err(ApplyChangesError::TagNameNotFound(name.clone()));
+                    }
+                    converted.push(None);
+                },
+            }
+        }
+
+        // Only saving the aliases of deleted tags can fail
+        let mut result = Ok(());
+        for (change, value) in changes.into_iter().zip(converted) {
+            match change {
+                TagChange::Create {
+                    name,
+                    meta,
+                } => {
+                    self.create_tag(name, meta);
+                },
+                TagChange::Update {
+                    name,
+                    meta,
+                } => {
+                    let name = self.resolve(&name);
+                    if let (Some(value), Some(mut entry)) = (value, self.values.get_mut(&name)) {
+                        entry.value = value;
+                    }
+                    self.meta.insert(name, meta);
+                },
+                TagChange::Delete {
+                    name,
+                } => {
+                    if let Err(DeleteTagError::Storage(e)) = self.delete_tag(&name) {
+                        result = Err(ApplyChangesError::Storage(e));
+                    }
+                },
+            }
+        }
+
+        result
     }
 
     fn get_tag_data_type(&self, name: &TagName) -> Option<DataType> {
-        self.meta.get(name).map(|meta| meta.data_type)
+        self.meta
+            .get(&self.resolve(name))
+            .map(|meta| meta.data_type)
     }
 
     fn get_tag_value(&self, name: &TagName) -> Option<TagValue> {
-        self.values.get(name).map(|value| value.clone())
+        self.values
+            .get(&self.resolve(name))
+            .map(|value| value.clone())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rcada_core::unit::Unit;
+
+    use super::*;
+
+    fn storage(names: &[&str]) -> TagStorage {
+        let storage = TagStorage::new();
+        for name in names {
+            storage.create_tag(
+                TagName::from(*name),
+                TagMeta::new(Unit::None, DataType::Integer),
+            );
+        }
+        storage
+    }
+
+    fn alias(alias: &str, target: &str) -> TagAlias {
+        TagAlias {
+            alias: TagName::from(alias),
+            target: TagName::from(target),
+        }
+    }
+
+    #[test]
+    fn aliases_resolve_to_their_target() {
+        let storage = storage(&["boiler.temp"]);
+        let temp = TagName::from("temp");
+        storage
+            .create_alias(temp.clone(), &TagName::from("boiler.temp"))
+            .unwrap();
+        // An alias of an alias points to the tag
+        assert_eq!(
+            storage.create_alias(TagName::from("t"), &temp),
+            Ok(alias("t", "boiler.temp"))
+        );
+
+        let value = TagValue {
+            value: Value::Integer(42),
+            timestamp: None,
+            quality: Quality::Good,
+        };
+        storage.update_tag_value(temp.clone(), value).unwrap();
+        let tag = storage.get_tag(&TagName::from("t")).unwrap();
+        assert_eq!(tag.name, TagName::from("boiler.temp"));
+        assert_eq!(tag.value.value, Value::Integer(42));
+
+        assert_eq!(
+            storage.create_tag(temp.clone(), TagMeta::new(Unit::None, DataType::Integer)),
+            CreateTagResult::AlreadyExists
+        );
+        assert_eq!(
+            storage.create_alias(TagName::from("boiler.temp"), &temp),
+            Err(AliasError::AlreadyExists)
+        );
+
+        // Deleting through an alias deletes the tag and all its aliases
+        storage.delete_tag(&temp).unwrap();
+        assert!(!storage.is_tag_exists(&TagName::from("boiler.temp")));
+        assert!(storage.get_all_aliases().is_empty());
+    }
+
+    #[test]
+    fn renamed_tags_keep_their_aliases() {
+        let storage = storage(&["a", "other"]);
+        storage
+            .create_alias(TagName::from("x"), &TagName::from("a"))
+            .unwrap();
+        storage
+            .create_alias(TagName::from("y"), &TagName::from("other"))
+            .unwrap();
+
+        let tag = storage
+            .rename_tag(&TagName::from("a"), TagName::from("b"), true)
+            .unwrap();
+        assert_eq!(tag.name, TagName::from("b"));
+        assert_eq!(
+            storage.get_all_aliases(),
+            [alias("a", "b"), alias("x", "b"), alias("y", "other")]
+        );
+        assert_eq!(
+            storage.get_tag(&TagName::from("a")).unwrap().name,
+            TagName::from("b")
+        );
+
+        // Without keep_alias the old name is free again
+        storage
+            .rename_tag(&TagName::from("x"), TagName::from("c"), false)
+            .unwrap();
+        assert_eq!(
+            storage.get_all_aliases(),
+            [alias("a", "c"), alias("x", "c"), alias("y", "other")]
+        );
+        assert!(!storage.is_tag_exists(&TagName::from("b")));
+
+        // A tag takes the name of its own alias, not that of another tag's
+        assert_eq!(
+            storage.rename_tag(&TagName::from("c"), TagName::from("y"), false),
+            Err(RenameTagError::AlreadyExists)
+        );
+        storage
+            .rename_tag(&TagName::from("c"), TagName::from("x"), false)
+            .unwrap();
+        assert_eq!(
+            storage.get_all_aliases(),
+            [alias("a", "x"), alias("y", "other")]
+        );
     }
 }
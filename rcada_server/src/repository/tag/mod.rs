@@ -1,10 +1,15 @@
+pub mod alias;
+pub mod file;
 pub mod inmemory;
 
 use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+
+use std::collections::BTreeMap;
 
 use rcada_core::{
-    tag::{Tag, TagName, TagValue},
+    tag::{AlarmLimits, EngineeringRange, Tag, TagMeta, TagName, TagValue},
     unit::Unit,
     value::DataType,
 };
@@ -12,32 +17,61 @@ use rcada_core::{
 pub trait TagRepository: Send + Sync + Sized {
     fn is_tag_exists(&self, name: &TagName) -> bool;
 
-    fn create_tag(&self, name: TagName, unit: Unit, data_type: DataType) -> CreateTagResult;
+    fn create_tag(&self, name: TagName, meta: TagMeta) -> CreateTagResult;
 
     fn get_tag(&self, name: &TagName) -> Result<Tag, ReadTagError>;
 
     fn get_all_tags(&self) -> Vec<Tag>;
 
+    fn tag_count(&self) -> usize;
+
     fn update_tag_value(
         &self,
         name: TagName,
         value: TagValue,
     ) -> Result<UpdateValueResult, UpdateValueError>;
 
+    fn update_tag_meta(&self, name: &TagName, patch: TagMetaPatch) -> Result<Tag, UpdateMetaError>;
+
     fn delete_tag(&self, name: &TagName) -> Result<(), DeleteTagError>;
 
+    /// Renames a tag, optionally keeping the old name as an alias.
+    ///
+    /// Existing aliases of the tag are moved to the new name.
+    fn rename_tag(
+        &self,
+        name: &TagName,
+        new_name: TagName,
+        keep_alias: bool,
+    ) -> Result<Tag, RenameTagError>;
+
+    fn create_alias(&self, alias: TagName, target: &TagName) -> Result<TagAlias, AliasError>;
+
+    fn delete_alias(&self, alias: &TagName) -> Result<(), AliasError>;
+
+    fn get_all_aliases(&self) -> Vec<TagAlias>;
+
+    /// Applies all changes or none of them.
+    fn apply_changes(&self, changes: Vec<TagChange>) -> Result<(), ApplyChangesError>;
+
     fn get_tag_data_type(&self, name: &TagName) -> Option<DataType>;
 
     fn get_tag_value(&self, name: &TagName) -> Option<TagValue>;
+
+    /// Persists changes the repository held back, called shortly after
+    /// value changes and on shutdown.
+    fn flush(&self) {}
 }
 
-#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, ToSchema)]
 pub enum CreateTagResult {
     SuccessfullyCreated,
     AlreadyExists,
+    /// The tag couldn't be saved and wasn't created.
+    Storage(String),
 }
 
-#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, ToSchema)]
 pub enum UpdateValueResult {
     Updated,
     Ignored,
@@ -54,6 +88,122 @@ pub enum UpdateValueError {
     },
     NoneTimestampProvided,
     TagNameNotFound,
+    /// Only the server itself and its drivers write read-only tags.
+    ReadOnly,
+}
+
+impl UpdateValueError {
+    /// Name of the variant, for metrics.
+    pub fn kind(&self) -> &'static str {
+        match self {
+            Self::TimestamoOutOfOrder {
+                ..
+            } => "TimestamoOutOfOrder",
+            Self::InvalidDataType {
+                ..
+            } => "InvalidDataType",
+            Self::NoneTimestampProvided => "NoneTimestampProvided",
+            Self::TagNameNotFound => "TagNameNotFound",
+            Self::ReadOnly => "ReadOnly",
+        }
+    }
+}
+
+/// Partial change of a tag's metadata. `None` fields are left untouched.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct TagMetaPatch {
+    pub unit: Option<Unit>,
+    pub data_type: Option<DataType>,
+    pub description: Option<String>,
+    pub range: Option<Option<EngineeringRange>>,
+    pub precision: Option<Option<u8>>,
+    pub read_only: Option<bool>,
+    /// Labels to merge into the existing ones, `None` removes the label.
+    pub labels: Option<BTreeMap<String, Option<String>>>,
+    pub alarms: Option<AlarmLimits>,
+    pub expression: Option<Option<String>>,
+}
+
+impl TagMetaPatch {
+    pub fn apply(self, meta: &mut TagMeta) {
+        if let Some(unit) = self.unit {
+            meta.unit = unit;
+        }
+        if let Some(data_type) = self.data_type {
+            meta.data_type = data_type;
+        }
+        if let Some(description) = self.description {
+            meta.description = description;
+        }
+        if let Some(range) = self.range {
+            meta.range = range;
+        }
+        if let Some(precision) = self.precision {
+            meta.precision = precision;
+        }
+        if let Some(read_only) = self.read_only {
+            meta.read_only = read_only;
+        }
+        if let Some(alarms) = self.alarms {
+            meta.alarms = alarms;
+        }
+        if let Some(expression) = self.expression {
+            meta.expression = expression;
+        }
+        for (key, value) in self.labels.into_iter().flatten() {
+            match value {
+                Some(value) => meta.labels.insert(key, value),
+                None => meta.labels.remove(&key),
+            };
+        }
+    }
+
+    /// Replaces every field of `previous` with `meta`, also removing the
+    /// labels of `previous` that `meta` doesn't have. Labels set elsewhere
+    /// are kept.
+    pub fn replacing(previous: &TagMeta, meta: TagMeta) -> Self {
+        let mut patch = Self::from(meta);
+        let labels = patch.labels.get_or_insert_default();
+        for key in previous.labels.keys() {
+            labels.entry(key.clone()).or_insert(None);
+        }
+        patch
+    }
+}
+
+/// Replaces every field of the metadata, labels are merged.
+impl From<TagMeta> for TagMetaPatch {
+    fn from(meta: TagMeta) -> Self {
+        TagMetaPatch {
+            unit: Some(meta.unit),
+            data_type: Some(meta.data_type),
+            description: Some(meta.description),
+            range: Some(meta.range),
+            precision: Some(meta.precision),
+            read_only: Some(meta.read_only),
+            labels: Some(
+                meta.labels
+                    .into_iter()
+                    .map(|(key, value)| (key, Some(value)))
+                    .collect(),
+            ),
+            alarms: Some(meta.alarms),
+            expression: Some(meta.expression),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum UpdateMetaError {
+    TagNameNotFound,
+    InvalidRange,
+    InvalidAlarmLimits,
+    /// Current value can't be converted into the new data type.
+    IncompatibleDataType {
+        from: DataType,
+        to: DataType,
+    },
+    Storage(String),
 }
 
 #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
@@ -64,9 +214,148 @@ pub enum DeleteTagResult {
 #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub enum DeleteTagError {
     TagNameNotFound,
+    Storage(String),
+}
+
+/// Change of the tag configuration applied as part of a batch.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum TagChange {
+    Create {
+        name: TagName,
+        meta: TagMeta,
+    },
+    /// Replaces the metadata, converting the value if the data type changes.
+    Update {
+        name: TagName,
+        meta: TagMeta,
+    },
+    Delete {
+        name: TagName,
+    },
+}
+
+impl TagChange {
+    pub fn name(&self) -> &TagName {
+        match self {
+            TagChange::Create {
+                name,
+                ..
+            }
+            | TagChange::Update {
+                name,
+                ..
+            }
+            | TagChange::Delete {
+                name,
+            } => name,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum ApplyChangesError {
+    AlreadyExists(TagName),
+    TagNameNotFound(TagName),
+    InvalidMeta(TagName),
+    IncompatibleDataType {
+        name: TagName,
+        from: DataType,
+        to: DataType,
+    },
+    /// The changes couldn't be saved, none of them was applied.
+    Storage(String),
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct TagAlias {
+    pub alias: TagName,
+    pub target: TagName,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum RenameTagError {
+    TagNameNotFound,
+    AlreadyExists,
+    Storage(String),
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum AliasError {
+    TagNameNotFound,
+    AliasNotFound,
+    /// The alias is already used as a tag name or another alias.
+    AlreadyExists,
+    Storage(String),
 }
 
 #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub enum ReadTagError {
     TagNameNotFound,
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn meta() -> TagMeta {
+        let mut meta = TagMeta::new(Unit::None, DataType::Float);
+        meta.description = "Boiler temperature".to_string();
+        meta.range = Some(EngineeringRange {
+            low: 0.0,
+            high: 100.0,
+        });
+        meta.precision = Some(1);
+        meta.labels = BTreeMap::from([
+            ("area".to_string(), "boiler".to_string()),
+            ("site".to_string(), "north".to_string()),
+        ]);
+        meta
+    }
+
+    #[test]
+    fn patches_change_only_the_fields_they_set() {
+        let mut patched = meta();
+        TagMetaPatch::default().apply(&mut patched);
+        assert_eq!(patched, meta());
+
+        let patch = TagMetaPatch {
+            description: Some("Boiler outlet temperature".to_string()),
+            range: Some(None),
+            read_only: Some(true),
+            labels: Some(BTreeMap::from([
+                ("area".to_string(), None),
+                ("line".to_string(), Some("2".to_string())),
+            ])),
+            ..Default::default()
+        };
+        patch.apply(&mut patched);
+
+        let mut expected = meta();
+        expected.description = "Boiler outlet temperature".to_string();
+        expected.range = None;
+        expected.read_only = true;
+        expected.labels = BTreeMap::from([
+            ("line".to_string(), "2".to_string()),
+            ("site".to_string(), "north".to_string()),
+        ]);
+        assert_eq!(patched, expected);
+    }
+
+    #[test]
+    fn replacing_patches_drop_the_labels_of_the_previous_meta() {
+        let previous = meta();
+        let mut current = meta();
+        current
+            .labels
+            .insert("owner".to_string(), "operator".to_string());
+
+        let mut next = TagMeta::new(Unit::None, DataType::Integer);
+        next.labels = BTreeMap::from([("site".to_string(), "south".to_string())]);
+        TagMetaPatch::replacing(&previous, next.clone()).apply(&mut current);
+
+        // Labels set next to the previous meta are kept
+        next.labels
+            .insert("owner".to_string(), "operator".to_string());
+        assert_eq!(current, next);
+    }
+}
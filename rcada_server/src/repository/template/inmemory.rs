@@ -0,0 +1,249 @@
+use std::{
+    fs, io,
+    path::{Path, PathBuf},
+};
+
+use dashmap::{DashMap, mapref::entry::Entry};
+use rcada_core::tag::TagName;
+use serde::{Deserialize, Serialize};
+
+use crate::repository::template::{TagTemplate, TemplateError, TemplateName, TemplateRepository};
+
+/// Templates and their instances, optionally backed by a JSON file.
+#[derive(Default, Clone)]
+pub struct TemplateStorage {
+    templates: DashMap<TemplateName, TagTemplate>,
+    instances: DashMap<TemplateName, Vec<TagName>>,
+    path: Option<PathBuf>,
+}
+
+/// Entry of the templates file.
+#[derive(Serialize, Deserialize)]
+struct StoredTemplate {
+    #[serde(flatten)]
+    template: TagTemplate,
+    #[serde(default)]
+    instances: Vec<TagName>,
+}
+
+impl TemplateStorage {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Loads the templates from `path` and saves every change back to it.
+    ///
+    /// A missing file is treated as having no templates.
+    pub fn with_file(path: impl AsRef<Path>) -> io::Result<Self> {
+        let path = path.as_ref().to_path_buf();
+        let storage = Self {
+            path: Some(path.clone()),
+            ..Self::default()
+        };
+
+        match fs::read(&path) {
+            Ok(bytes) => {
+                let entries: Vec<StoredTemplate> = serde_json::from_slice(&bytes)
+                    .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+                for entry in entries {
+                    storage
+                        .instances
+                        .insert(entry.template.name.clone(), entry.instances);
+                    storage
+                        .templates
+                        .insert(entry.template.name.clone(), entry.template);
+                }
+            },
+            Err(e) if e.kind() == io::ErrorKind::NotFound => {},
+            Err(e) => return Err(e),
+        }
+
+        Ok(storage)
+    }
+
+    fn save(&self) -> Result<(), TemplateError> {
+        let Some(path) = &self.path else {
+            return Ok(());
+        };
+
+        let mut entries: Vec<StoredTemplate> = self
+            .templates
+            .iter()
+            .map(|template| StoredTemplate {
+                template: template.value().clone(),
+                instances: self
+                    .instances
+                    .get(template.key())
+                    .map(|instances| instances.clone())
+                    .unwrap_or_default(),
+            })
+            .collect();
+        entries.sort_by(|a, b| a.template.name.cmp(&b.template.name));
+
+        let write = || -> io::Result<()> {
+            let json = serde_json::to_vec_pretty(&entries)?;
+            let tmp = path.with_extension("tmp");
+            fs::write(&tmp, json)?;
+            fs::rename(tmp, path)
+        };
+        write().map_err(|e| TemplateError::Storage(e.to_string()))
+    }
+}
+
+impl TemplateRepository for TemplateStorage {
+    fn create_template(&self, template: TagTemplate) -> Result<(), TemplateError> {
+        template.validate()?;
+        let name = template.name.clone();
+        match self.templates.entry(name.clone()) {
+            Entry::Occupied(_) => return Err(TemplateError::AlreadyExists),
+            Entry::Vacant(entry) => {
+                self.instances.insert(name.clone(), Vec::new());
+                entry.insert(template);
+            },
+        }
+        self.save().inspect_err(|_| {
+            self.templates.remove(&name);
+            self.instances.remove(&name);
+        })
+    }
+
+    fn update_template(&self, template: TagTemplate) -> Result<TagTemplate, TemplateError> {
+        template.validate()?;
+        let name = template.name.clone();
+        let previous = {
+            let mut entry = self
+                .templates
+                .get_mut(&name)
+                .ok_or(TemplateError::TemplateNotFound)?;
+            std::mem::replace(entry.value_mut(), template)
+        };
+        self.save().inspect_err(|_| {
+            self.templates.insert(name, previous.clone());
+        })?;
+        Ok(previous)
+    }
+
+    fn get_template(&self, name: &TemplateName) -> Result<TagTemplate, TemplateError> {
+        self.templates
+            .get(name)
+            .map(|template| template.clone())
+            .ok_or(TemplateError::TemplateNotFound)
+    }
+
+    fn get_all_templates(&self) -> Vec<TagTemplate> {
+        self.templates
+            .iter()
+            .map(|entry| entry.value().clone())
+            .collect()
+    }
+
+    fn delete_template(&self, name: &TemplateName) -> Result<(), TemplateError> {
+        if !self.templates.contains_key(name) {
+            return Err(TemplateError::TemplateNotFound);
+        }
+        if self.instances.get(name).is_some_and(|i| !i.is_empty()) {
+            return Err(TemplateError::HasInstances);
+        }
+        let Some((_, template)) = self.templates.remove(name) else {
+            return Err(TemplateError::TemplateNotFound);
+        };
+        let instances = self.instances.remove(name);
+        self.save().inspect_err(|_| {
+            self.templates.insert(name.clone(), template);
+            if let Some((_, instances)) = instances {
+                self.instances.insert(name.clone(), instances);
+            }
+        })
+    }
+
+    fn add_instance(&self, template: &TemplateName, prefix: TagName) -> Result<(), TemplateError> {
+        {
+            let mut instances = self
+                .instances
+                .get_mut(template)
+                .ok_or(TemplateError::TemplateNotFound)?;
+            if instances.contains(&prefix) {
+                return Err(TemplateError::InstanceAlreadyExists);
+            }
+            instances.push(prefix.clone());
+        }
+        self.save().inspect_err(|_| {
+            if let Some(mut instances) = self.instances.get_mut(template) {
+                instances.retain(|instance| *instance != prefix);
+            }
+        })
+    }
+
+    fn remove_instance(
+        &self,
+        template: &TemplateName,
+        prefix: &TagName,
+    ) -> Result<(), TemplateError> {
+        let index = {
+            let mut instances = self
+                .instances
+                .get_mut(template)
+                .ok_or(TemplateError::TemplateNotFound)?;
+            let index = instances
+                .iter()
+                .position(|instance| instance == prefix)
+                .ok_or(TemplateError::InstanceNotFound)?;
+            instances.remove(index);
+            index
+        };
+        self.save().inspect_err(|_| {
+            if let Some(mut instances) = self.instances.get_mut(template) {
+                instances.insert(index, prefix.clone());
+            }
+        })
+    }
+
+    fn get_instances(&self, template: &TemplateName) -> Result<Vec<TagName>, TemplateError> {
+        self.instances
+            .get(template)
+            .map(|instances| instances.clone())
+            .ok_or(TemplateError::TemplateNotFound)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rcada_core::{tag::TagMeta, unit::Unit, value::DataType};
+
+    use super::*;
+    use crate::repository::template::TemplatePoint;
+
+    fn pump() -> TagTemplate {
+        TagTemplate {
+            name: "pump".into(),
+            description: "Centrifugal pump".into(),
+            points: vec![TemplatePoint {
+                name: "speed".into(),
+                meta: TagMeta::new(Unit::Percent, DataType::Float),
+            }],
+        }
+    }
+
+    #[test]
+    fn templates_and_instances_survive_a_restart() {
+        let path =
+            std::env::temp_dir().join(format!("rcada-templates-{}.json", uuid::Uuid::new_v4()));
+
+        let storage = TemplateStorage::with_file(&path).unwrap();
+        storage.create_template(pump()).unwrap();
+        storage.add_instance(&"pump".into(), "p1".into()).unwrap();
+        storage.add_instance(&"pump".into(), "p2".into()).unwrap();
+        storage
+            .remove_instance(&"pump".into(), &"p1".into())
+            .unwrap();
+        drop(storage);
+
+        let storage = TemplateStorage::with_file(&path).unwrap();
+        assert_eq!(storage.get_template(&"pump".into()).unwrap(), pump());
+        assert_eq!(
+            storage.get_instances(&"pump".into()).unwrap(),
+            vec![TagName::from("p2")]
+        );
+        std::fs::remove_file(path).unwrap();
+    }
+}
@@ -0,0 +1,123 @@
+pub mod inmemory;
+
+/// File the templates and their instances are stored in, in the data directory.
+pub const TEMPLATES_FILE: &str = "templates.json";
+
+use serde::{Deserialize, Serialize};
+use smol_str::SmolStr;
+use utoipa::ToSchema;
+
+use rcada_core::tag::{TagMeta, TagName};
+
+pub type TemplateName = SmolStr;
+
+/// Separator between an instance prefix and a template point name.
+pub const INSTANCE_SEPARATOR: char = '.';
+
+/// Placeholder replaced with the instance prefix in point expressions.
+pub const INSTANCE_PLACEHOLDER: &str = "{instance}";
+
+pub trait TemplateRepository: Send + Sync + Sized {
+    fn create_template(&self, template: TagTemplate) -> Result<(), TemplateError>;
+
+    /// Replaces a template, returning the previous version.
+    fn update_template(&self, template: TagTemplate) -> Result<TagTemplate, TemplateError>;
+
+    fn get_template(&self, name: &TemplateName) -> Result<TagTemplate, TemplateError>;
+
+    fn get_all_templates(&self) -> Vec<TagTemplate>;
+
+    fn delete_template(&self, name: &TemplateName) -> Result<(), TemplateError>;
+
+    fn add_instance(&self, template: &TemplateName, prefix: TagName) -> Result<(), TemplateError>;
+
+    fn remove_instance(
+        &self,
+        template: &TemplateName,
+        prefix: &TagName,
+    ) -> Result<(), TemplateError>;
+
+    fn get_instances(&self, template: &TemplateName) -> Result<Vec<TagName>, TemplateError>;
+}
+
+/// Set of tags shared by every instance of an equipment type.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, ToSchema)]
+pub struct TagTemplate {
+    #[schema(value_type = String)]
+    pub name: TemplateName,
+    #[serde(default)]
+    pub description: String,
+    pub points: Vec<TemplatePoint>,
+}
+
+/// Tag of a template, named relative to the instance prefix.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, ToSchema)]
+pub struct TemplatePoint {
+    pub name: String,
+    pub meta: TagMeta,
+}
+
+impl TagTemplate {
+    pub fn validate(&self) -> Result<(), TemplateError> {
+        for (i, point) in self.points.iter().enumerate() {
+            if point.name.is_empty() {
+                return Err(TemplateError::InvalidPoint {
+                    point: point.name.clone(),
+                    reason: "name is empty".into(),
+                });
+            }
+            if self.points[..i].iter().any(|p| p.name == point.name) {
+                return Err(TemplateError::InvalidPoint {
+                    point: point.name.clone(),
+                    reason: "name is duplicated".into(),
+                });
+            }
+            if point.meta.range.is_some_and(|range| !range.is_valid()) {
+                return Err(TemplateError::InvalidPoint {
+                    point: point.name.clone(),
+                    reason: "invalid engineering range".into(),
+                });
+            }
+            if !point.meta.alarms.is_valid() {
+                return Err(TemplateError::InvalidPoint {
+                    point: point.name.clone(),
+                    reason: "invalid alarm limits".into(),
+                });
+            }
+        }
+        Ok(())
+    }
+}
+
+impl TemplatePoint {
+    pub fn tag_name(&self, prefix: &str) -> TagName {
+        format!("{prefix}{INSTANCE_SEPARATOR}{}", self.name).into()
+    }
+
+    /// Metadata of the point's tag in the instance with the given prefix.
+    pub fn instance_meta(&self, prefix: &str) -> TagMeta {
+        let mut meta = self.meta.clone();
+        meta.expression = meta
+            .expression
+            .map(|expression| expression.replace(INSTANCE_PLACEHOLDER, prefix));
+        meta
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum TemplateError {
+    TemplateNotFound,
+    AlreadyExists,
+    InvalidPoint {
+        point: String,
+        reason: String,
+    },
+    InstanceNotFound,
+    InstanceAlreadyExists,
+    /// Template still has instances and can't be deleted.
+    HasInstances,
+    /// Tags of a new instance collide with existing tags.
+    TagsAlreadyExist(Vec<TagName>),
+    TagActor(String),
+    Storage(String),
+}
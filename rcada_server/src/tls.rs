@@ -0,0 +1,215 @@
+use std::{
+    any::Any,
+    fs,
+    io::BufReader,
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex, RwLock},
+    time::SystemTime,
+};
+
+use actix_tls::accept::rustls_0_23::TlsStream;
+use actix_web::rt::net::TcpStream;
+use rustls::{
+    RootCertStore,
+    crypto::{CryptoProvider, ring},
+    pki_types::PrivateKeyDer,
+    server::{ClientHello, ResolvesServerCert, WebPkiClientVerifier},
+    sign::CertifiedKey,
+};
+use serde::{Deserialize, Serialize};
+use x509_parser::prelude::{FromDer, X509Certificate};
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct TlsConfig {
+    /// Certificate chain (PEM), the server certificate first.
+    pub cert_file: PathBuf,
+    /// Private key (PEM) of the server certificate.
+    pub key_file: PathBuf,
+    /// CAs (PEM) whose client certificates are accepted. Clients with a
+    /// certificate are authenticated by its common name.
+    #[serde(default)]
+    pub client_ca_file: Option<PathBuf>,
+    /// Refuse connections without a client certificate.
+    #[serde(default)]
+    pub require_client_cert: bool,
+}
+
+/// Verified certificate of the peer, stored in the connection data.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ClientCertificate {
+    pub common_name: String,
+}
+
+impl ClientCertificate {
+    /// Reads the common name of a DER certificate.
+    pub fn from_der(der: &[u8]) -> Option<Self> {
+        let (_, cert) = X509Certificate::from_der(der).ok()?;
+        let common_name = cert.subject().iter_common_name().next()?.as_str().ok()?;
+        Some(Self {
+            common_name: common_name.to_string(),
+        })
+    }
+}
+
+/// Serves the certificate from `cert_file` and `key_file`, and replaces it
+/// when [`CertificateResolver::reload`] finds the files changed.
+#[derive(Debug)]
+pub struct CertificateResolver {
+    cert_file: PathBuf,
+    key_file: PathBuf,
+    provider: Arc<CryptoProvider>,
+    key: RwLock<Arc<CertifiedKey>>,
+    /// Modification times of the files when they were last loaded, whether
+    /// that worked or not.
+    modified: Mutex<(Option<SystemTime>, Option<SystemTime>)>,
+}
+
+impl CertificateResolver {
+    fn new(config: &TlsConfig, provider: Arc<CryptoProvider>) -> Result<Self, String> {
+        let modified = modified(&config.cert_file, &config.key_file);
+        let key = load_certified_key(&config.cert_file, &config.key_file, &provider)?;
+        Ok(Self {
+            cert_file: config.cert_file.clone(),
+            key_file: config.key_file.clone(),
+            provider,
+            key: RwLock::new(Arc::new(key)),
+            modified: Mutex::new(modified),
+        })
+    }
+
+    /// Loads the files again if they were modified, returns whether the
+    /// certificate was replaced. A broken certificate keeps the current one
+    /// and is only loaded again once the files change again.
+    pub fn reload(&self) -> Result<bool, String> {
+        let mut last = self.modified.lock().unwrap_or_else(|e| e.into_inner());
+        let modified = modified(&self.cert_file, &self.key_file);
+        if modified == *last {
+            return Ok(false);
+        }
+        *last = modified;
+        let key = load_certified_key(&self.cert_file, &self.key_file, &self.provider)?;
+        *self.key.write().unwrap_or_else(|e| e.into_inner()) = Arc::new(key);
+        Ok(true)
+    }
+}
+
+impl ResolvesServerCert for CertificateResolver {
+    fn resolve(&self, _client_hello: ClientHello<'_>) -> Option<Arc<CertifiedKey>> {
+        Some(self.key.read().unwrap_or_else(|e| e.into_inner()).clone())
+    }
+}
+
+/// Builds the configuration for `HttpServer::bind_rustls_0_23`. The returned
+/// resolver reloads the certificate of running servers.
+pub fn server_config(
+    config: &TlsConfig,
+) -> Result<(rustls::ServerConfig, Arc<CertificateResolver>), String> {
+    let provider = Arc::new(ring::default_provider());
+    let resolver = Arc::new(CertificateResolver::new(config, provider.clone())?);
+
+    let builder = rustls::ServerConfig::builder_with_provider(provider.clone())
+        .with_safe_default_protocol_versions()
+        .map_err(|e| e.to_string())?;
+    let builder = match &config.client_ca_file {
+        Some(ca_file) => {
+            let mut roots = RootCertStore::empty();
+            for cert in read_certs(ca_file)? {
+                roots
+                    .add(cert)
+                    .map_err(|e| format!("{}: {e}", ca_file.display()))?;
+            }
+            let verifier =
+                WebPkiClientVerifier::builder_with_provider(Arc::new(roots), provider.clone());
+            let verifier = if config.require_client_cert {
+                verifier
+            } else {
+                verifier.allow_unauthenticated()
+            };
+            builder.with_client_cert_verifier(
+                verifier
+                    .build()
+                    .map_err(|e| format!("{}: {e}", ca_file.display()))?,
+            )
+        },
+        None => builder.with_no_client_auth(),
+    };
+    Ok((builder.with_cert_resolver(resolver.clone()), resolver))
+}
+
+/// Configuration for connecting to servers whose certificate is issued by a
+/// CA in `ca_file`, e.g. an MQTT broker. With `client_cert`, a certificate and
+/// key file, the client authenticates with that certificate.
+pub fn client_config(
+    ca_file: &Path,
+    client_cert: Option<(&Path, &Path)>,
+) -> Result<rustls::ClientConfig, String> {
+    let mut roots = RootCertStore::empty();
+    for cert in read_certs(ca_file)? {
+        roots
+            .add(cert)
+            .map_err(|e| format!("{}: {e}", ca_file.display()))?;
+    }
+    let builder = rustls::ClientConfig::builder_with_provider(Arc::new(ring::default_provider()))
+        .with_safe_default_protocol_versions()
+        .map_err(|e| e.to_string())?
+        .with_root_certificates(roots);
+    match client_cert {
+        Some((cert_file, key_file)) => builder
+            .with_client_auth_cert(read_certs(cert_file)?, read_private_key(key_file)?)
+            .map_err(|e| format!("{}: {e}", cert_file.display())),
+        None => Ok(builder.with_no_client_auth()),
+    }
+}
+
+/// Certificate of a TLS connection, for `HttpServer::on_connect`.
+pub fn client_certificate(connection: &dyn Any) -> Option<ClientCertificate> {
+    let stream = connection.downcast_ref::<TlsStream<TcpStream>>()?;
+    let (_, session) = stream.get_ref();
+    let cert = session.peer_certificates()?.first()?;
+    ClientCertificate::from_der(cert.as_ref())
+}
+
+fn modified(cert_file: &Path, key_file: &Path) -> (Option<SystemTime>, Option<SystemTime>) {
+    let modified = |path: &Path| fs::metadata(path).and_then(|m| m.modified()).ok();
+    (modified(cert_file), modified(key_file))
+}
+
+fn read_certs(path: &Path) -> Result<Vec<rustls::pki_types::CertificateDer<'static>>, String> {
+    let file = fs::File::open(path).map_err(|e| format!("cannot read {}: {e}", path.display()))?;
+    let certs = rustls_pemfile::certs(&mut BufReader::new(file))
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|e| format!("{}: {e}", path.display()))?;
+    if certs.is_empty() {
+        return Err(format!("{}: no certificates found", path.display()));
+    }
+    Ok(certs)
+}
+
+fn read_private_key(key_file: &Path) -> Result<PrivateKeyDer<'static>, String> {
+    let file =
+        fs::File::open(key_file).map_err(|e| format!("cannot read {}: {e}", key_file.display()))?;
+    rustls_pemfile::private_key(&mut BufReader::new(file))
+        .map_err(|e| format!("{}: {e}", key_file.display()))?
+        .ok_or_else(|| format!("{}: no private key found", key_file.display()))
+}
+
+fn load_certified_key(
+    cert_file: &Path,
+    key_file: &Path,
+    provider: &CryptoProvider,
+) -> Result<CertifiedKey, String> {
+    let certs = read_certs(cert_file)?;
+    let key = provider
+        .key_provider
+        .load_private_key(read_private_key(key_file)?)
+        .map_err(|e| format!("{}: {e}", key_file.display()))?;
+    let certified = CertifiedKey::new(certs, key);
+    certified.keys_match().map_err(|e| {
+        format!(
+            "{}: key doesn't match the certificate: {e}",
+            key_file.display()
+        )
+    })?;
+    Ok(certified)
+}